@@ -0,0 +1,77 @@
+//! A dashboard redrawn in place: per sensor, the latest reading, the last
+//! hour's stats and the firing alerts, with where the service listens.
+//!
+//! It only needs ANSI escapes, which every terminal the course uses
+//! understands. The monitors log each reading too; the next redraw clears
+//! their lines.
+
+use std::time::Duration;
+
+use temp::prelude::*;
+use temp::runtime::listener::Endpoint;
+
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+const HOUR_SECONDS: u64 = 3600;
+
+/// What the dashboard reads, taken from a running service.
+pub struct Sources {
+    sensors: Vec<(String, TemperatureStore, MonitorHandle)>,
+    endpoints: Vec<Endpoint>,
+}
+
+impl Sources {
+    pub fn of(service: &TempService) -> Self {
+        let sensors = service
+            .sensor_ids()
+            .into_iter()
+            .filter_map(|id| {
+                let store = service.store(id)?.clone_handle();
+                Some((id.to_string(), store, service.monitor(id)?.clone()))
+            })
+            .collect();
+        let endpoints = service.listeners().endpoints().unwrap_or_default();
+        Self { sensors, endpoints }
+    }
+}
+
+/// Redraws every `every` until the task is dropped with the runtime.
+pub async fn run(sources: Sources, every: Duration) {
+    let mut ticker = tokio::time::interval(every);
+    loop {
+        ticker.tick().await;
+        print!("{}{}", CLEAR_SCREEN, render(&sources).await);
+    }
+}
+
+async fn render(sources: &Sources) -> String {
+    let now = SystemClock.now();
+    let mut screen = String::new();
+    screen.push_str(&format!("{:<12} {:>9} {:>6} {:>9} {:>9} {:>9} {:>7}  alerts\n", "sensor", "latest", "age", "min 1h", "avg 1h", "max 1h", "stored"));
+    for (sensor_id, store, monitor) in &sources.sensors {
+        let (latest, age) = match store.get_latest() {
+            Some(reading) => (format!("{:.1}°C", reading.temperature.celsius), format!("{}s", now.saturating_sub(reading.timestamp))),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let (min, avg, max) = match store.window_stats(now.saturating_sub(HOUR_SECONDS), now + 1) {
+            Some(stats) => (
+                format!("{:.1}°C", stats.min.celsius),
+                format!("{:.1}°C", stats.average.celsius),
+                format!("{:.1}°C", stats.max.celsius),
+            ),
+            None => ("-".to_string(), "-".to_string(), "-".to_string()),
+        };
+        let alerts = match monitor.get_alerts().await {
+            Ok(alerts) if alerts.is_empty() => "none".to_string(),
+            Ok(alerts) => alerts.iter().map(|alert| alert.message.as_str()).collect::<Vec<_>>().join("; "),
+            Err(_) => "monitor stopped".to_string(),
+        };
+        screen.push_str(&format!("{:<12} {:>9} {:>6} {:>9} {:>9} {:>9} {:>7}  {}\n", sensor_id, latest, age, min, avg, max, store.len(), alerts));
+    }
+
+    screen.push('\n');
+    for endpoint in &sources.endpoints {
+        screen.push_str(&format!("Listening for {} on {}\n", endpoint.transport.as_str(), endpoint.addr));
+    }
+    screen.push_str("Ctrl-C to stop\n");
+    screen
+}
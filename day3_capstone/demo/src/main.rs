@@ -0,0 +1,183 @@
+//! Runs the whole capstone at once: simulated sensors, their monitors, the
+//! protocol server and a live dashboard in the terminal.
+//!
+//! Usage:
+//!   demo [--tcp <host:port>] [--http <host:port>] [--seed <n>] [--seconds <n>] [--no-dashboard]
+//!
+//! Three sensors start with a day of seeded history, so stats and rollups
+//! have something to show from the first second:
+//!
+//! - `living_room`, a simulated room (see `temp::runtime::simulation`) whose
+//!   heater runs at half power against a cold, swinging ambient; every
+//!   reading advances it by a simulated minute.
+//! - `freezer`, a noisy mock around -18°C.
+//! - `greenhouse`, a mock with a daily cycle, drift and spikes that fails
+//!   every 25th read, so the fault handling has something to do.
+//!
+//! The protocol is served on `127.0.0.1:7979` and HTTP ingestion on
+//! `127.0.0.1:8080` unless told otherwise; try `temp_backup 127.0.0.1:7979
+//! backup freezer -` or posting readings as described in `temp::runtime::http`.
+//! The same seed gives the same history and noise. The demo runs until
+//! Ctrl-C, or for `--seconds`, which suits scripted runs.
+
+mod dashboard;
+
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use temp::core::control::Actuator;
+use temp::core::mock::{FailureSchedule, Noise, NoiseProfile, DAY_SECONDS};
+use temp::prelude::*;
+use temp::runtime::simulation::{AmbientProfile, ThermalModel, ThermalParams};
+
+const USAGE: &str =
+    "usage: demo [--tcp <host:port>] [--http <host:port>] [--seed <n>] [--seconds <n>] [--no-dashboard]";
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+/// Room for the seeded day plus a few hours of live readings.
+const STORE_CAPACITY: usize = 4096;
+/// Spacing of the seeded history.
+const HISTORY_STEP_SECONDS: u64 = 300;
+
+struct Options {
+    tcp: SocketAddr,
+    http: SocketAddr,
+    seed: u64,
+    seconds: Option<u64>,
+    dashboard: bool,
+}
+
+/// A demo sensor's seeded history: around `base` with a daily swing.
+struct Seed {
+    sensor_id: &'static str,
+    base: f32,
+    profile: NoiseProfile,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let service = match start(&options) {
+        Ok(service) => service,
+        Err(e) => {
+            eprintln!("Failed to start the demo: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let dashboard = options
+        .dashboard
+        .then(|| tokio::spawn(dashboard::run(dashboard::Sources::of(&service), Duration::from_secs(1))));
+
+    match options.seconds {
+        Some(seconds) => {
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(seconds)) => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+            if let Some(task) = &dashboard {
+                task.abort();
+            }
+            service.shutdown().await;
+        }
+        None => {
+            if let Err(e) = service.shutdown_on_signal().await {
+                eprintln!("Failed to wait for a signal: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn start(options: &Options) -> std::io::Result<TempService> {
+    let ambient = AmbientProfile::Sinusoidal {
+        mean: Temperature::new(5.0),
+        amplitude: 5.0,
+        period: Duration::from_secs(DAY_SECONDS),
+    };
+    let room = ThermalModel::new(Temperature::new(18.0), ThermalParams::room(), ambient);
+    let (living_room, mut heater) = room.split("living_room".to_string(), Duration::from_secs(60));
+    let Ok(()) = heater.set_output(0.5);
+
+    let freezer = AsyncMockSensor::new("freezer".to_string(), -18.0)
+        .with_profile(NoiseProfile { gaussian: 0.3, ..Default::default() }, options.seed);
+    let greenhouse_profile = NoiseProfile {
+        gaussian: 0.2,
+        drift: 0.02,
+        daily_amplitude: 6.0,
+        spike_probability: 0.01,
+        spike_magnitude: 8.0,
+        ..Default::default()
+    };
+    let greenhouse = AsyncMockSensor::new("greenhouse".to_string(), 22.0)
+        .with_profile(greenhouse_profile, options.seed)
+        .with_failure_schedule(FailureSchedule::new().fail_every(25));
+
+    let seeds = [
+        Seed { sensor_id: "living_room", base: 20.0, profile: NoiseProfile { daily_amplitude: 2.5, ..Default::default() } },
+        Seed { sensor_id: "freezer", base: -18.0, profile: NoiseProfile { gaussian: 0.3, ..Default::default() } },
+        Seed { sensor_id: "greenhouse", base: 22.0, profile: greenhouse_profile },
+    ];
+    let mut builder = TempServiceBuilder::new()
+        .store_capacity(STORE_CAPACITY)
+        .watchdog(3.0)
+        .listen(ListenerConfig::tcp(options.tcp))
+        .listen(ListenerConfig::http(options.http))
+        .sensor(living_room.with_noise(0.1, options.seed), SAMPLE_INTERVAL)
+        .sensor(freezer, SAMPLE_INTERVAL)
+        .sensor(greenhouse, SAMPLE_INTERVAL);
+    let now = SystemClock.now();
+    for (offset, seed) in (0..).zip(seeds) {
+        builder = builder.history(seed.sensor_id, history(&seed, now, options.seed + offset));
+    }
+    builder.build()
+}
+
+/// A day of readings ending before `now`.
+fn history(seed: &Seed, now: u64, noise_seed: u64) -> Vec<TemperatureReading> {
+    let mut noise = Noise::with_profile(seed.profile, noise_seed);
+    let start = now - DAY_SECONDS;
+    (start..now)
+        .step_by(HISTORY_STEP_SECONDS as usize)
+        .map(|timestamp| {
+            let celsius = seed.base + noise.daily_cycle(timestamp) + noise.sample();
+            TemperatureReading::with_timestamp(Temperature::new(celsius), timestamp)
+        })
+        .collect()
+}
+
+fn parse(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        tcp: SocketAddr::from(([127, 0, 0, 1], 7979)),
+        http: SocketAddr::from(([127, 0, 0, 1], 8080)),
+        seed: 42,
+        seconds: None,
+        dashboard: true,
+    };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        if flag == "--no-dashboard" {
+            options.dashboard = false;
+            continue;
+        }
+        let value = args.next().ok_or_else(|| format!("Missing value for {}", flag))?;
+        let invalid = || format!("Invalid value for {}: {}", flag, value);
+        match flag.as_str() {
+            "--tcp" => options.tcp = value.parse().map_err(|_| invalid())?,
+            "--http" => options.http = value.parse().map_err(|_| invalid())?,
+            "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+            "--seconds" => options.seconds = Some(value.parse().map_err(|_| invalid())?),
+            _ => return Err(format!("Unknown option {}", flag)),
+        }
+    }
+    Ok(options)
+}
@@ -0,0 +1,68 @@
+//! The temperature stack behind one dependency.
+//!
+//! Each crate of the stack is re-exported under a short name, built with
+//! the features it needs to work with the others, so depending on `temp`
+//! alone gets matching versions of all of them:
+//!
+//! - [`core`]: temperatures, the sensor traits, drivers and wrappers
+//!   (`temp_core`, with `std`)
+//! - [`store`]: the reading store, its log and aggregates (`temp_store`)
+//! - [`alert`]: the alert engine, its history and webhooks (`temp_alert`)
+//! - [`protocol`]: commands, responses and their handler (`temp_protocol`)
+//! - [`runtime`]: monitors, listeners and the service builder on tokio
+//!   (`temp_async`, with the default `async` feature)
+//!
+//! The crates' own features are forwarded under the same names, e.g.
+//! `temp = { features = ["i2c", "encryption"] }`. Most programs only need
+//! `use temp::prelude::*;`.
+
+#![forbid(unsafe_code)]
+
+pub use temp_alert as alert;
+pub use temp_core as core;
+pub use temp_protocol as protocol;
+pub use temp_store as store;
+
+#[cfg(feature = "async")]
+pub use temp_async as runtime;
+
+/// The types nearly every program using the stack names.
+pub mod prelude {
+    pub use temp_alert::{Alert, AlertEngine, AlertEvent, Severity};
+    pub use temp_core::clock::{Clock, SystemClock};
+    pub use temp_core::{
+        CalibratedSensor, Calibration, RetryPolicy, RetrySensor, SensorHealth, SensorId, SensorInfo, Temperature,
+        TemperatureSensor, ThresholdConfig,
+    };
+    pub use temp_protocol::{Command, ProtocolError, Response, TemperatureProtocolHandler};
+    pub use temp_store::{TemperatureReading, TemperatureStats, TemperatureStore};
+
+    #[cfg(feature = "async")]
+    pub use temp_async::listener::ListenerConfig;
+    #[cfg(feature = "async")]
+    pub use temp_async::service::{TempService, TempServiceBuilder};
+    #[cfg(feature = "async")]
+    pub use temp_async::transport::ProtocolClient;
+    #[cfg(feature = "async")]
+    pub use temp_async::{AsyncMockSensor, AsyncTemperatureSensor, MonitorHandle};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::prelude::*;
+    use super::protocol::MessagePayload;
+
+    #[test]
+    fn prelude_covers_a_round_trip() {
+        let mut handler = TemperatureProtocolHandler::new();
+        let sensor_id: SensorId = "temp_01".parse().unwrap();
+        let message = handler.create_command(Command::GetReading { sensor_id });
+        let MessagePayload::Response(Response::Reading { temperature, .. }) = handler.process_command(message).payload else {
+            panic!("Expected a reading");
+        };
+
+        let store = TemperatureStore::new(10);
+        store.add_reading(TemperatureReading::new(Temperature::new(temperature)));
+        assert_eq!(store.get_latest().map(|reading| reading.temperature), Some(Temperature::new(23.5)));
+    }
+}
@@ -0,0 +1,161 @@
+//! Escalation of unacknowledged alerts.
+//!
+//! A policy is a chain of steps, each naming a channel and how long after the
+//! alert fired it is used: e.g. a webhook right away, email after 15 minutes,
+//! an on-call webhook after 30. Acknowledging the alert stops the chain.
+//! Delivery is left to a [`Notifier`], so the engine itself does no I/O.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Severity;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Channel {
+    Webhook { url: String },
+    Email { to: String },
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Webhook { url } => write!(f, "webhook {}", url),
+            Channel::Email { to } => write!(f, "email to {}", to),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EscalationStep {
+    /// Seconds after the alert fired.
+    pub after_seconds: u64,
+    pub channel: Channel,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EscalationPolicy {
+    /// Alerts below this severity are never escalated.
+    pub min_severity: Severity,
+    pub steps: Vec<EscalationStep>,
+}
+
+impl EscalationPolicy {
+    pub fn new(min_severity: Severity) -> Self {
+        Self {
+            min_severity,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Appends a step; steps should be added in increasing `after_seconds` order.
+    pub fn then(mut self, after_seconds: u64, channel: Channel) -> Self {
+        self.steps.push(EscalationStep { after_seconds, channel });
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub alert_id: u64,
+    /// Index of the policy step that produced this notification.
+    pub step: usize,
+    pub channel: Channel,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Delivers notifications, e.g. by posting to a webhook or sending mail.
+pub trait Notifier {
+    type Error: fmt::Debug;
+
+    fn send(&mut self, notification: &Notification) -> Result<(), Self::Error>;
+}
+
+/// Keeps every notification in memory; handy for tests and dry runs.
+#[derive(Debug, Default)]
+pub struct RecordingNotifier {
+    pub sent: Vec<Notification>,
+}
+
+impl Notifier for RecordingNotifier {
+    type Error = core::convert::Infallible;
+
+    fn send(&mut self, notification: &Notification) -> Result<(), Self::Error> {
+        self.sent.push(notification.clone());
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{AlertEngine, ThresholdConfig};
+
+    fn engine() -> AlertEngine {
+        let policy = EscalationPolicy::new(Severity::Warning)
+            .then(0, Channel::Webhook { url: "https://hooks.example/ops".to_string() })
+            .then(600, Channel::Email { to: "ops@example.com".to_string() })
+            .then(1200, Channel::Webhook { url: "https://hooks.example/oncall".to_string() });
+
+        let mut engine = AlertEngine::new();
+        engine.set_thresholds("freezer", ThresholdConfig::range(-25.0, -15.0).unwrap());
+        engine.set_escalation_policy(Some(policy));
+        engine
+    }
+
+    #[test]
+    fn escalates_until_acknowledged() {
+        let mut engine = engine();
+        let id = engine.evaluate("freezer", -10.0, 1000)[0].alert().id;
+
+        let due = engine.due_notifications(1000);
+        assert_eq!(due.len(), 1);
+        assert!(matches!(due[0].channel, Channel::Webhook { .. }));
+        assert!(engine.due_notifications(1100).is_empty());
+
+        let due = engine.due_notifications(1600);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].channel, Channel::Email { to: "ops@example.com".to_string() });
+        assert_eq!(engine.alert(id).unwrap().escalation_step, 2);
+
+        engine.acknowledge(id, "alice", 1700).unwrap();
+        assert!(engine.due_notifications(5000).is_empty());
+    }
+
+    #[test]
+    fn catches_up_on_missed_steps() {
+        let mut engine = engine();
+        engine.evaluate("freezer", -10.0, 1000);
+
+        let due = engine.due_notifications(3000);
+        assert_eq!(due.iter().map(|n| n.step).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert!(engine.due_notifications(9000).is_empty());
+    }
+
+    #[test]
+    fn skips_resolved_silenced_and_minor_alerts() {
+        let mut engine = engine();
+        engine.set_escalation_policy(Some(
+            EscalationPolicy::new(Severity::Critical).then(0, Channel::Email { to: "boss@example.com".to_string() }),
+        ));
+
+        // Warning only
+        engine.evaluate("freezer", -14.0, 1000);
+        assert!(engine.due_notifications(1000).is_empty());
+
+        // Critical, but silenced
+        engine.silence(None, 1000, 2000, "defrost".to_string()).unwrap();
+        engine.evaluate("freezer", 0.0, 1100);
+        assert!(engine.due_notifications(1100).is_empty());
+
+        let mut notifier = RecordingNotifier::default();
+        assert_eq!(engine.dispatch(2000, &mut notifier), 1);
+        assert_eq!(notifier.sent[0].severity, Severity::Critical);
+
+        engine.evaluate("freezer", -20.0, 2100);
+        assert!(engine.active_alerts().is_empty());
+    }
+}
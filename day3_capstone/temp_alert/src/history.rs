@@ -0,0 +1,273 @@
+//! Alert history.
+//!
+//! The engine only keeps firing alerts and a bounded list of resolved ones.
+//! [`AlertHistory`] records every transition instead, optionally appending it
+//! to a [`FileBackend`] so the history survives restarts, and answers queries
+//! by sensor, severity and time range.
+
+use alloc::string::String;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use temp_store::error_hook::{self, SwallowedKind};
+#[cfg(feature = "std")]
+use temp_store::{FileBackend, FlushPolicy};
+
+use crate::{Alert, AlertEvent, AlertKind, Severity};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Transition {
+    Fired,
+    SeverityChanged { previous: Severity },
+    Resolved,
+    Acknowledged { by: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AlertRecord {
+    pub alert_id: u64,
+    pub sensor_id: String,
+    pub kind: AlertKind,
+    /// Severity after the transition.
+    pub severity: Severity,
+    pub transition: Transition,
+    pub value: f32,
+    pub timestamp: u64,
+}
+
+impl AlertRecord {
+    pub fn new(alert: &Alert, transition: Transition, timestamp: u64) -> Self {
+        Self {
+            alert_id: alert.id,
+            sensor_id: alert.key.sensor_id.clone(),
+            kind: alert.key.kind,
+            severity: alert.severity,
+            transition,
+            value: alert.value,
+            timestamp,
+        }
+    }
+
+    pub fn from_event(event: &AlertEvent) -> Self {
+        let alert = event.alert();
+        let (transition, timestamp) = match event {
+            AlertEvent::Fired(_) => (Transition::Fired, alert.fired_at),
+            AlertEvent::SeverityChanged { previous, .. } => {
+                (Transition::SeverityChanged { previous: *previous }, alert.last_seen)
+            }
+            AlertEvent::Resolved(_) => (Transition::Resolved, alert.resolved_at.unwrap_or(alert.last_seen)),
+        };
+        Self::new(alert, transition, timestamp)
+    }
+}
+
+/// Filter for [`AlertHistory::query`]; unset fields match everything.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AlertQuery {
+    pub sensor_id: Option<String>,
+    pub min_severity: Option<Severity>,
+    /// Inclusive start of the time range.
+    pub start: Option<u64>,
+    /// Exclusive end of the time range.
+    pub end: Option<u64>,
+}
+
+impl AlertQuery {
+    pub fn matches(&self, record: &AlertRecord) -> bool {
+        self.sensor_id.as_deref().is_none_or(|id| id == record.sensor_id)
+            && self.min_severity.is_none_or(|min| record.severity >= min)
+            && self.start.is_none_or(|start| record.timestamp >= start)
+            && self.end.is_none_or(|end| record.timestamp < end)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AlertCounts {
+    pub fired: usize,
+    pub resolved: usize,
+    pub acknowledged: usize,
+    /// Alerts that fired as or were raised to critical.
+    pub critical: usize,
+}
+
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct AlertHistory {
+    records: Vec<AlertRecord>,
+    backend: Option<FileBackend<AlertRecord>>,
+}
+
+#[cfg(feature = "std")]
+impl AlertHistory {
+    /// History kept in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the history stored at `path` and appends new records to it.
+    pub fn open(path: impl AsRef<Path>, policy: FlushPolicy) -> io::Result<Self> {
+        let records = FileBackend::load_records(&path)?;
+        let backend = FileBackend::open(&path, policy)?;
+        Ok(Self {
+            records,
+            backend: Some(backend),
+        })
+    }
+
+    pub fn record(&mut self, record: AlertRecord) {
+        if let Some(backend) = &mut self.backend {
+            if let Err(e) = backend.append(&record) {
+                let context = format!("Failed to persist alert history to {}", backend.path().display());
+                error_hook::report(SwallowedKind::Io, &context, &e);
+            }
+        }
+        self.records.push(record);
+    }
+
+    pub fn record_event(&mut self, event: &AlertEvent) {
+        self.record(AlertRecord::from_event(event));
+    }
+
+    /// Flushes and syncs pending writes of the persistent backend, if any.
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.backend {
+            Some(backend) => backend.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Matching records, oldest first.
+    pub fn query(&self, query: &AlertQuery) -> Vec<&AlertRecord> {
+        self.records.iter().filter(|r| query.matches(r)).collect()
+    }
+
+    /// Transition counts for the records matching `query`.
+    pub fn counts(&self, query: &AlertQuery) -> AlertCounts {
+        let mut counts = AlertCounts::default();
+        for record in self.records.iter().filter(|r| query.matches(r)) {
+            match &record.transition {
+                Transition::Fired => counts.fired += 1,
+                Transition::Resolved => counts.resolved += 1,
+                Transition::Acknowledged { .. } => counts.acknowledged += 1,
+                Transition::SeverityChanged { .. } => {}
+            }
+            let raised = matches!(record.transition, Transition::Fired | Transition::SeverityChanged { .. });
+            if raised && record.severity == Severity::Critical {
+                counts.critical += 1;
+            }
+        }
+        counts
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{AlertEngine, ThresholdConfig};
+
+    fn history_of(engine: &mut AlertEngine, readings: &[(&str, f32, u64)]) -> AlertHistory {
+        let mut history = AlertHistory::new();
+        for (sensor_id, celsius, timestamp) in readings {
+            for event in engine.evaluate(sensor_id, *celsius, *timestamp) {
+                history.record_event(&event);
+            }
+        }
+        history
+    }
+
+    fn engine() -> AlertEngine {
+        let mut engine = AlertEngine::new();
+        engine.set_thresholds("freezer", ThresholdConfig::range(-25.0, -15.0).unwrap());
+        engine.set_thresholds("fridge", ThresholdConfig::range(2.0, 8.0).unwrap());
+        engine
+    }
+
+    #[test]
+    fn queries_by_sensor_severity_and_time() {
+        let mut engine = engine();
+        let history = history_of(
+            &mut engine,
+            &[
+                ("freezer", -14.0, 100), // warning fired
+                ("freezer", -5.0, 200),  // raised to critical
+                ("freezer", -20.0, 300), // resolved
+                ("fridge", 9.0, 400),    // warning fired
+            ],
+        );
+        assert_eq!(history.len(), 4);
+
+        let freezer = history.query(&AlertQuery {
+            sensor_id: Some("freezer".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(freezer.len(), 3);
+        assert_eq!(freezer[1].transition, Transition::SeverityChanged { previous: Severity::Warning });
+        assert_eq!(freezer[2].timestamp, 300);
+
+        let critical = history.query(&AlertQuery {
+            min_severity: Some(Severity::Critical),
+            ..Default::default()
+        });
+        // The raise to critical and the resolution of the critical alert
+        assert_eq!(critical.len(), 2);
+
+        let late = history.query(&AlertQuery {
+            start: Some(300),
+            end: Some(400),
+            ..Default::default()
+        });
+        assert_eq!(late.len(), 1);
+        assert_eq!(late[0].transition, Transition::Resolved);
+    }
+
+    #[test]
+    fn counts_transitions() {
+        let mut engine = engine();
+        let mut history = history_of(&mut engine, &[("freezer", -5.0, 100), ("fridge", 9.0, 150), ("freezer", -20.0, 200)]);
+        let alert = &engine.active_alerts()[0];
+        history.record(AlertRecord::new(alert, Transition::Acknowledged { by: "ops".to_string() }, 250));
+
+        let counts = history.counts(&AlertQuery::default());
+        assert_eq!(counts, AlertCounts { fired: 2, resolved: 1, acknowledged: 1, critical: 1 });
+
+        let counts = history.counts(&AlertQuery {
+            sensor_id: Some("fridge".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(counts.fired, 1);
+        assert_eq!(counts.critical, 0);
+    }
+
+    #[test]
+    fn persists_and_reloads() {
+        let path = std::env::temp_dir().join(format!("temp_alert_{}_history.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        {
+            let mut engine = engine();
+            let mut history = AlertHistory::open(&path, FlushPolicy::EveryWrite).unwrap();
+            for event in engine.evaluate("fridge", 10.0, 100) {
+                history.record_event(&event);
+            }
+        }
+
+        let history = AlertHistory::open(&path, FlushPolicy::EveryWrite).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history.query(&AlertQuery::default())[0].sensor_id, "fridge");
+
+        drop(history);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,613 @@
+//! Alert engine for threshold violations.
+//!
+//! Readings are evaluated against per-sensor thresholds. Alerts are
+//! deduplicated by [`AlertKey`]: a sensor that stays too hot keeps one firing
+//! alert whose severity follows the reading, and the alert resolves on its own
+//! once the reading is back in range. Silence windows keep alerts from being
+//! notified (e.g. during maintenance) without hiding them from listings.
+//! Firing alerts nobody acknowledges are escalated along an
+//! [`EscalationPolicy`]. Every transition can be kept in an [`AlertHistory`],
+//! and posted to a [`Webhook`].
+//!
+//! Without the default `std` feature only the alert, silence, policy and
+//! history record types are built, on `alloc`, so firmware can decode them
+//! from protocol messages.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::collections::{HashMap, VecDeque};
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use temp_store::error_hook::{self, SwallowedKind};
+pub use temp_core::gradient::{GradientThreshold, GradientViolation};
+pub use temp_core::threshold::{Alarm, AlarmState, ThresholdConfig, ThresholdError};
+
+pub mod escalation;
+pub mod history;
+#[cfg(feature = "std")]
+pub mod webhook;
+
+pub use escalation::{Channel, EscalationPolicy, Notification, Notifier};
+#[cfg(feature = "std")]
+pub use history::AlertHistory;
+pub use history::{AlertCounts, AlertQuery, AlertRecord, Transition};
+#[cfg(feature = "std")]
+pub use webhook::{Webhook, WebhookPayload};
+
+/// How far past a threshold, in °C, a reading turns a warning into a critical alert.
+pub const DEFAULT_CRITICAL_MARGIN: f32 = 5.0;
+/// Resolved alerts kept for listing.
+pub const RESOLVED_HISTORY: usize = 256;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    HighTemperature,
+    LowTemperature,
+    /// The sensor stopped producing readings.
+    NoData,
+    /// Changing faster than its [`GradientThreshold`] allows.
+    RisingFast,
+    FallingFast,
+}
+
+impl fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertKind::HighTemperature => write!(f, "high temperature"),
+            AlertKind::LowTemperature => write!(f, "low temperature"),
+            AlertKind::NoData => write!(f, "no data"),
+            AlertKind::RisingFast => write!(f, "rising fast"),
+            AlertKind::FallingFast => write!(f, "falling fast"),
+        }
+    }
+}
+
+/// Deduplication key: at most one alert per key is firing at a time.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AlertKey {
+    pub sensor_id: String,
+    pub kind: AlertKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub id: u64,
+    pub key: AlertKey,
+    pub severity: Severity,
+    pub state: AlertState,
+    pub message: String,
+    /// Reading that last matched the alert; seconds without data for
+    /// [`AlertKind::NoData`], °C per minute for the rate of change kinds.
+    pub value: f32,
+    pub fired_at: u64,
+    pub last_seen: u64,
+    pub resolved_at: Option<u64>,
+    /// Readings folded into this alert by deduplication.
+    pub occurrences: u32,
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_at: Option<u64>,
+    /// Raised while a silence window matched; not notified.
+    pub silenced: bool,
+    /// Escalation steps already notified.
+    pub escalation_step: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertEvent {
+    Fired(Alert),
+    SeverityChanged { alert: Alert, previous: Severity },
+    Resolved(Alert),
+}
+
+impl AlertEvent {
+    pub fn alert(&self) -> &Alert {
+        match self {
+            AlertEvent::Fired(alert) | AlertEvent::Resolved(alert) => alert,
+            AlertEvent::SeverityChanged { alert, .. } => alert,
+        }
+    }
+
+    /// Silenced alerts are tracked but not sent to anyone.
+    pub fn should_notify(&self) -> bool {
+        !self.alert().silenced
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Silence {
+    pub id: u64,
+    /// Sensor to silence; `None` silences every sensor.
+    pub sensor_id: Option<String>,
+    pub start: u64,
+    pub end: u64,
+    pub reason: String,
+}
+
+impl Silence {
+    pub fn matches(&self, sensor_id: &str, timestamp: u64) -> bool {
+        (self.start..self.end).contains(&timestamp)
+            && self.sensor_id.as_deref().is_none_or(|id| id == sensor_id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertError {
+    UnknownAlert(u64),
+    UnknownSilence(u64),
+    InvalidSilence { start: u64, end: u64 },
+}
+
+impl fmt::Display for AlertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertError::UnknownAlert(id) => write!(f, "Alert {} not found", id),
+            AlertError::UnknownSilence(id) => write!(f, "Silence {} not found", id),
+            AlertError::InvalidSilence { start, end } => {
+                write!(f, "Silence must end after it starts ({} >= {})", start, end)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AlertError {}
+
+#[cfg(feature = "std")]
+pub struct AlertEngine {
+    next_id: u64,
+    critical_margin: f32,
+    alarms: HashMap<String, Alarm>,
+    firing: HashMap<AlertKey, Alert>,
+    resolved: VecDeque<Alert>,
+    silences: Vec<Silence>,
+    escalation: Option<EscalationPolicy>,
+}
+
+#[cfg(feature = "std")]
+impl AlertEngine {
+    pub fn new() -> Self {
+        Self {
+            next_id: 1,
+            critical_margin: DEFAULT_CRITICAL_MARGIN,
+            alarms: HashMap::new(),
+            firing: HashMap::new(),
+            resolved: VecDeque::new(),
+            silences: Vec::new(),
+            escalation: None,
+        }
+    }
+
+    pub fn with_critical_margin(mut self, margin: f32) -> Self {
+        self.critical_margin = margin;
+        self
+    }
+
+    /// Sets or replaces a sensor's thresholds; a firing alert is then held
+    /// or resolved by the new ones.
+    pub fn set_thresholds(&mut self, sensor_id: &str, thresholds: ThresholdConfig) {
+        match self.alarms.get_mut(sensor_id) {
+            Some(alarm) => alarm.set_thresholds(thresholds),
+            None => {
+                self.alarms.insert(sensor_id.to_string(), Alarm::new(thresholds));
+            }
+        }
+    }
+
+    pub fn thresholds(&self, sensor_id: &str) -> Option<ThresholdConfig> {
+        self.alarms.get(sensor_id).map(Alarm::thresholds)
+    }
+
+    pub fn remove_thresholds(&mut self, sensor_id: &str) {
+        self.alarms.remove(sensor_id);
+    }
+
+    pub fn set_escalation_policy(&mut self, policy: Option<EscalationPolicy>) {
+        self.escalation = policy;
+    }
+
+    pub fn escalation_policy(&self) -> Option<&EscalationPolicy> {
+        self.escalation.as_ref()
+    }
+
+    /// Checks one reading and returns what changed.
+    pub fn evaluate(&mut self, sensor_id: &str, celsius: f32, timestamp: u64) -> Vec<AlertEvent> {
+        let Some(alarm) = self.alarms.get_mut(sensor_id) else {
+            return Vec::new();
+        };
+        let state = alarm.update(celsius);
+        let thresholds = alarm.thresholds();
+
+        let mut events = Vec::new();
+        for (kind, alarm_state, excess, limit) in [
+            (AlertKind::HighTemperature, AlarmState::High, celsius - thresholds.max(), thresholds.max()),
+            (AlertKind::LowTemperature, AlarmState::Low, thresholds.min() - celsius, thresholds.min()),
+        ] {
+            let key = AlertKey { sensor_id: sensor_id.to_string(), kind };
+            if state != alarm_state {
+                events.extend(self.resolve(&key, timestamp));
+                continue;
+            }
+            let severity = if excess >= self.critical_margin { Severity::Critical } else { Severity::Warning };
+            let message = format!("{} is {:.1}°C, {} limit {:.1}°C", sensor_id, celsius, kind, limit);
+            events.extend(self.raise(key, severity, message, celsius, timestamp));
+        }
+        events
+    }
+
+    /// Forgets a sensor's thresholds and resolves all of its firing alerts,
+    /// e.g. when it is decommissioned.
+    pub fn clear_sensor(&mut self, sensor_id: &str, timestamp: u64) -> Vec<AlertEvent> {
+        self.remove_thresholds(sensor_id);
+        let mut keys: Vec<AlertKey> = self.firing.keys().filter(|k| k.sensor_id == sensor_id).cloned().collect();
+        keys.sort_by_key(|k| self.firing[k].id);
+        keys.iter().filter_map(|key| self.resolve(key, timestamp)).collect()
+    }
+
+    /// Dead-man check, independent of thresholds: raises a no-data alert while
+    /// the sensor's newest reading is older than `max_age` and resolves it
+    /// once data arrives again.
+    pub fn check_data_age(&mut self, sensor_id: &str, age: Duration, max_age: Duration, timestamp: u64) -> Option<AlertEvent> {
+        let key = AlertKey { sensor_id: sensor_id.to_string(), kind: AlertKind::NoData };
+        if age > max_age {
+            let message = format!("No data from {} for {:.1?} (expected within {:.1?})", sensor_id, age, max_age);
+            self.raise(key, Severity::Warning, message, age.as_secs_f32(), timestamp)
+        } else {
+            self.resolve(&key, timestamp)
+        }
+    }
+
+    /// Rate of change check, independent of thresholds: raises a rising or
+    /// falling fast alert while `rate`, in °C per minute from a
+    /// [`TemperatureGradient`](temp_core::TemperatureGradient), exceeds
+    /// `threshold`, and resolves it once the rate is back within it.
+    pub fn check_gradient(&mut self, sensor_id: &str, rate: f32, threshold: &GradientThreshold, timestamp: u64) -> Vec<AlertEvent> {
+        let violation = threshold.check(rate);
+        let mut events = Vec::new();
+        for (kind, direction, limit) in [
+            (AlertKind::RisingFast, GradientViolation::Rising, threshold.max_rise()),
+            (AlertKind::FallingFast, GradientViolation::Falling, threshold.max_fall()),
+        ] {
+            let key = AlertKey { sensor_id: sensor_id.to_string(), kind };
+            match limit {
+                Some(limit) if violation == Some(direction) => {
+                    let message = format!("{} is {} at {:.1}°C/min, limit {:.1}°C/min", sensor_id, direction, rate.abs(), limit);
+                    events.extend(self.raise(key, Severity::Warning, message, rate, timestamp));
+                }
+                _ => events.extend(self.resolve(&key, timestamp)),
+            }
+        }
+        events
+    }
+
+    fn raise(&mut self, key: AlertKey, severity: Severity, message: String, value: f32, timestamp: u64) -> Option<AlertEvent> {
+        let silenced = self.is_silenced(&key.sensor_id, timestamp);
+
+        if let Some(alert) = self.firing.get_mut(&key) {
+            alert.occurrences += 1;
+            alert.last_seen = timestamp;
+            alert.value = value;
+            alert.message = message;
+            if alert.severity == severity {
+                return None;
+            }
+            let previous = alert.severity;
+            alert.severity = severity;
+            alert.silenced = silenced;
+            return Some(AlertEvent::SeverityChanged { alert: alert.clone(), previous });
+        }
+
+        let alert = Alert {
+            id: self.next_id,
+            key: key.clone(),
+            severity,
+            state: AlertState::Firing,
+            message,
+            value,
+            fired_at: timestamp,
+            last_seen: timestamp,
+            resolved_at: None,
+            occurrences: 1,
+            acknowledged_by: None,
+            acknowledged_at: None,
+            silenced,
+            escalation_step: 0,
+        };
+        self.next_id += 1;
+        self.firing.insert(key, alert.clone());
+        Some(AlertEvent::Fired(alert))
+    }
+
+    fn resolve(&mut self, key: &AlertKey, timestamp: u64) -> Option<AlertEvent> {
+        let mut alert = self.firing.remove(key)?;
+        alert.state = AlertState::Resolved;
+        alert.resolved_at = Some(timestamp);
+        alert.silenced = self.is_silenced(&key.sensor_id, timestamp);
+
+        if self.resolved.len() >= RESOLVED_HISTORY {
+            self.resolved.pop_front();
+        }
+        self.resolved.push_back(alert.clone());
+        Some(AlertEvent::Resolved(alert))
+    }
+
+    pub fn acknowledge(&mut self, alert_id: u64, by: &str, timestamp: u64) -> Result<&Alert, AlertError> {
+        let alert = self
+            .firing
+            .values_mut()
+            .find(|alert| alert.id == alert_id)
+            .ok_or(AlertError::UnknownAlert(alert_id))?;
+        alert.acknowledged_by = Some(by.to_string());
+        alert.acknowledged_at = Some(timestamp);
+        Ok(alert)
+    }
+
+    /// Adds a silence window covering `start..end`.
+    pub fn silence(&mut self, sensor_id: Option<String>, start: u64, end: u64, reason: String) -> Result<&Silence, AlertError> {
+        if start >= end {
+            return Err(AlertError::InvalidSilence { start, end });
+        }
+
+        self.silences.push(Silence {
+            id: self.next_id,
+            sensor_id,
+            start,
+            end,
+            reason,
+        });
+        self.next_id += 1;
+        Ok(self.silences.last().unwrap())
+    }
+
+    pub fn remove_silence(&mut self, silence_id: u64) -> Result<Silence, AlertError> {
+        let index = self
+            .silences
+            .iter()
+            .position(|s| s.id == silence_id)
+            .ok_or(AlertError::UnknownSilence(silence_id))?;
+        Ok(self.silences.remove(index))
+    }
+
+    pub fn silence_by_id(&self, silence_id: u64) -> Option<&Silence> {
+        self.silences.iter().find(|s| s.id == silence_id)
+    }
+
+    /// Silences that have not ended by `now`; expired ones are dropped.
+    pub fn silences(&mut self, now: u64) -> &[Silence] {
+        self.silences.retain(|s| s.end > now);
+        &self.silences
+    }
+
+    pub fn is_silenced(&self, sensor_id: &str, timestamp: u64) -> bool {
+        self.silences.iter().any(|s| s.matches(sensor_id, timestamp))
+    }
+
+    /// Escalation steps that have come due by `now` for firing alerts that are
+    /// neither acknowledged nor silenced. Each step is returned only once.
+    pub fn due_notifications(&mut self, now: u64) -> Vec<Notification> {
+        let Some(policy) = &self.escalation else {
+            return Vec::new();
+        };
+
+        let mut due = Vec::new();
+        for alert in self.firing.values_mut() {
+            let silenced = self.silences.iter().any(|s| s.matches(&alert.key.sensor_id, now));
+            if alert.acknowledged_at.is_some() || silenced || alert.severity < policy.min_severity {
+                continue;
+            }
+
+            while let Some(step) = policy.steps.get(alert.escalation_step) {
+                if alert.fired_at.saturating_add(step.after_seconds) > now {
+                    break;
+                }
+                due.push(Notification {
+                    alert_id: alert.id,
+                    step: alert.escalation_step,
+                    channel: step.channel.clone(),
+                    severity: alert.severity,
+                    message: alert.message.clone(),
+                });
+                alert.escalation_step += 1;
+            }
+        }
+
+        due.sort_by_key(|n| (n.alert_id, n.step));
+        due
+    }
+
+    /// Sends everything due by `now`; returns how many notifications were delivered.
+    pub fn dispatch<N: Notifier>(&mut self, now: u64, notifier: &mut N) -> usize {
+        self.due_notifications(now)
+            .iter()
+            .filter(|notification| match notifier.send(notification) {
+                Ok(()) => true,
+                Err(e) => {
+                    let context = format!("Failed to notify {} about alert {}", notification.channel, notification.alert_id);
+                    error_hook::report(SwallowedKind::Notification, &context, &format_args!("{:?}", e));
+                    false
+                }
+            })
+            .count()
+    }
+
+    /// Firing alerts, most severe first, then oldest first.
+    pub fn active_alerts(&self) -> Vec<Alert> {
+        let mut alerts: Vec<Alert> = self.firing.values().cloned().collect();
+        alerts.sort_by(|a, b| b.severity.cmp(&a.severity).then(a.fired_at.cmp(&b.fired_at)).then(a.id.cmp(&b.id)));
+        alerts
+    }
+
+    /// Recently resolved alerts, oldest first.
+    pub fn resolved_alerts(&self) -> impl Iterator<Item = &Alert> {
+        self.resolved.iter()
+    }
+
+    pub fn alert(&self, alert_id: u64) -> Option<&Alert> {
+        self.firing
+            .values()
+            .chain(self.resolved.iter())
+            .find(|alert| alert.id == alert_id)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for AlertEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn engine() -> AlertEngine {
+        let mut engine = AlertEngine::new();
+        engine.set_thresholds("temp_01", ThresholdConfig::range(10.0, 30.0).unwrap());
+        engine
+    }
+
+    #[test]
+    fn deduplicates_and_follows_severity() {
+        let mut engine = engine();
+
+        let events = engine.evaluate("temp_01", 31.0, 100);
+        assert!(matches!(&events[..], [AlertEvent::Fired(alert)] if alert.severity == Severity::Warning));
+
+        // Same condition again: no new alert
+        assert!(engine.evaluate("temp_01", 32.0, 110).is_empty());
+
+        let events = engine.evaluate("temp_01", 36.0, 120);
+        assert!(matches!(
+            &events[..],
+            [AlertEvent::SeverityChanged { alert, previous: Severity::Warning }] if alert.severity == Severity::Critical
+        ));
+
+        let active = engine.active_alerts();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].occurrences, 3);
+        assert_eq!(active[0].fired_at, 100);
+        assert_eq!(active[0].last_seen, 120);
+    }
+
+    #[test]
+    fn resolves_on_recovery() {
+        let mut engine = engine();
+        engine.evaluate("temp_01", 5.0, 100);
+
+        let events = engine.evaluate("temp_01", 20.0, 200);
+        assert!(matches!(&events[..], [AlertEvent::Resolved(alert)] if alert.resolved_at == Some(200)));
+        assert!(engine.active_alerts().is_empty());
+        assert_eq!(engine.resolved_alerts().count(), 1);
+
+        // Swinging from too cold to too hot resolves one and fires the other
+        engine.evaluate("temp_01", 5.0, 300);
+        let events = engine.evaluate("temp_01", 40.0, 310);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], AlertEvent::Fired(_)));
+        assert!(matches!(events[1], AlertEvent::Resolved(_)));
+    }
+
+    #[test]
+    fn consecutive_readings_and_hysteresis() {
+        let mut engine = AlertEngine::new();
+        engine.set_thresholds("temp_01", ThresholdConfig::new(10.0, 30.0, 1.0, 2).unwrap());
+
+        // A single spike is not enough
+        assert!(engine.evaluate("temp_01", 31.0, 100).is_empty());
+        assert!(engine.evaluate("temp_01", 29.0, 110).is_empty());
+        assert!(engine.evaluate("temp_01", 31.0, 120).is_empty());
+        assert!(matches!(&engine.evaluate("temp_01", 31.0, 130)[..], [AlertEvent::Fired(_)]));
+
+        // Back under the limit but inside the band: still firing
+        assert!(engine.evaluate("temp_01", 29.5, 140).is_empty());
+        assert_eq!(engine.active_alerts().len(), 1);
+        assert!(matches!(&engine.evaluate("temp_01", 28.9, 150)[..], [AlertEvent::Resolved(_)]));
+    }
+
+    #[test]
+    fn silence_windows_suppress_notification() {
+        let mut engine = engine();
+        engine.set_thresholds("temp_02", ThresholdConfig::range(10.0, 30.0).unwrap());
+        let silence_id = engine.silence(Some("temp_01".to_string()), 100, 200, "maintenance".to_string()).unwrap().id;
+
+        let events = engine.evaluate("temp_01", 35.0, 150);
+        assert!(!events[0].should_notify());
+        assert!(engine.evaluate("temp_02", 35.0, 150)[0].should_notify());
+
+        // Still listed while silenced
+        assert_eq!(engine.active_alerts().len(), 2);
+
+        engine.remove_silence(silence_id).unwrap();
+        assert!(!engine.is_silenced("temp_01", 150));
+        assert_eq!(engine.remove_silence(silence_id), Err(AlertError::UnknownSilence(silence_id)));
+        assert!(engine.silence(None, 10, 10, String::new()).is_err());
+    }
+
+    #[test]
+    fn acknowledge_firing_alert() {
+        let mut engine = engine();
+        let id = engine.evaluate("temp_01", 35.0, 100)[0].alert().id;
+
+        let alert = engine.acknowledge(id, "operator", 120).unwrap();
+        assert_eq!(alert.acknowledged_by.as_deref(), Some("operator"));
+        assert_eq!(engine.acknowledge(999, "operator", 120).unwrap_err(), AlertError::UnknownAlert(999));
+
+        // Acknowledgement survives deduplicated readings
+        engine.evaluate("temp_01", 35.5, 130);
+        assert_eq!(engine.alert(id).unwrap().acknowledged_at, Some(120));
+    }
+
+    #[test]
+    fn no_data_alert_ignores_thresholds() {
+        let mut engine = AlertEngine::new();
+
+        // No thresholds configured for this sensor
+        let max_age = Duration::from_secs(30);
+        assert!(engine.check_data_age("attic", Duration::from_secs(20), max_age, 1000).is_none());
+        let event = engine.check_data_age("attic", Duration::from_secs(45), max_age, 1015).unwrap();
+        assert!(matches!(&event, AlertEvent::Fired(alert) if alert.key.kind == AlertKind::NoData));
+        assert!(engine.check_data_age("attic", Duration::from_secs(60), max_age, 1030).is_none());
+        assert_eq!(engine.active_alerts()[0].value, 60.0);
+
+        assert!(engine.evaluate("attic", 20.0, 1040).is_empty());
+        assert!(matches!(engine.check_data_age("attic", Duration::ZERO, max_age, 1040), Some(AlertEvent::Resolved(_))));
+    }
+
+    #[test]
+    fn gradient_alerts_follow_the_direction() {
+        let mut engine = AlertEngine::new();
+        let threshold = GradientThreshold::new(Some(2.0), Some(1.0)).unwrap();
+
+        assert!(engine.check_gradient("freezer", 1.5, &threshold, 100).is_empty());
+        let events = engine.check_gradient("freezer", 2.5, &threshold, 110);
+        assert!(matches!(&events[..], [AlertEvent::Fired(alert)] if alert.key.kind == AlertKind::RisingFast));
+        assert_eq!(events[0].alert().message, "freezer is rising at 2.5°C/min, limit 2.0°C/min");
+
+        // Swinging the other way resolves one and fires the other
+        let events = engine.check_gradient("freezer", -1.5, &threshold, 120);
+        assert!(matches!(&events[..], [AlertEvent::Resolved(_), AlertEvent::Fired(alert)] if alert.key.kind == AlertKind::FallingFast));
+        assert_eq!(engine.active_alerts()[0].value, -1.5);
+        assert!(matches!(&engine.check_gradient("freezer", 0.0, &threshold, 130)[..], [AlertEvent::Resolved(_)]));
+    }
+}
@@ -0,0 +1,393 @@
+//! Outbound webhooks.
+//!
+//! A [`Webhook`] posts alert transitions, and optionally periodic summaries,
+//! to a URL such as a Slack or Teams incoming webhook or a Zapier catch hook.
+//! Each service expects its own JSON, so the body is a template with
+//! `{{field}}` placeholders filled from the [`WebhookPayload`]: e.g.
+//! `{"text": "{{sensor_id}}: {{message}}"}`. A placeholder is replaced by
+//! the field's JSON with the quotes of strings left to the template, so it
+//! works both inside a string and as a bare number. Nested fields are named
+//! with dots. Without a template the payload's own JSON is posted.
+//!
+//! With a secret, every request carries an HMAC-SHA256 of its timestamp and
+//! body, so the receiver can check it came from us and is not a replay:
+//! [`SIGNATURE_HEADER`] is `sha256=` and the hex digest of
+//! `{timestamp}.{body}`, with the timestamp in [`TIMESTAMP_HEADER`].
+//!
+//! Like [`escalation`](crate::escalation), this module does no I/O: it
+//! builds the [`WebhookRequest`]s and says when to retry them, and delivery
+//! is left to the caller (see `temp_async::webhook`).
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use temp_store::TemperatureStats;
+
+use crate::{AlertEvent, Severity};
+
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+pub const DEFAULT_RETRY_SECONDS: u64 = 30;
+/// Longest wait between two attempts, however many failed.
+pub const MAX_RETRY_SECONDS: u64 = 3600;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Webhook {
+    /// `http://` only; reach HTTPS endpoints through a TLS-terminating proxy.
+    pub url: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Alert transitions below this severity are not posted.
+    #[serde(default = "default_min_severity")]
+    pub min_severity: Severity,
+    /// Whether periodic summaries are posted too.
+    #[serde(default)]
+    pub summaries: bool,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Wait before the first retry; it doubles with every further one.
+    #[serde(default = "default_retry_seconds")]
+    pub retry_seconds: u64,
+}
+
+fn default_min_severity() -> Severity {
+    Severity::Info
+}
+
+fn default_max_attempts() -> u32 {
+    DEFAULT_MAX_ATTEMPTS
+}
+
+fn default_retry_seconds() -> u64 {
+    DEFAULT_RETRY_SECONDS
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebhookError {
+    InvalidUrl(String),
+    /// A header name or value that would break the request, e.g. a newline.
+    InvalidHeader(String),
+    UnknownField(String),
+    UnterminatedPlaceholder,
+    /// The rendered template is not JSON.
+    InvalidJson(String),
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookError::InvalidUrl(url) => write!(f, "Webhook URL must start with http://, got '{}'", url),
+            WebhookError::InvalidHeader(name) => write!(f, "Header '{}' must be a single line", name),
+            WebhookError::UnknownField(field) => write!(f, "Template names unknown field '{}'", field),
+            WebhookError::UnterminatedPlaceholder => write!(f, "Template has a '{{{{' without '}}}}'"),
+            WebhookError::InvalidJson(e) => write!(f, "Template does not render to JSON: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+/// What a webhook is posted about; the fields its template can name.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WebhookPayload {
+    Alert(AlertNotice),
+    Summary(Summary),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AlertNotice {
+    /// `fired`, `severity_changed` or `resolved`.
+    pub event: String,
+    pub alert_id: u64,
+    pub sensor_id: String,
+    /// E.g. `high temperature`.
+    pub kind: String,
+    pub severity: Severity,
+    pub message: String,
+    pub value: f32,
+    pub timestamp: u64,
+}
+
+impl AlertNotice {
+    pub fn from_event(event: &AlertEvent) -> Self {
+        let alert = event.alert();
+        let (name, timestamp) = match event {
+            AlertEvent::Fired(_) => ("fired", alert.fired_at),
+            AlertEvent::SeverityChanged { .. } => ("severity_changed", alert.last_seen),
+            AlertEvent::Resolved(_) => ("resolved", alert.resolved_at.unwrap_or(alert.last_seen)),
+        };
+        Self {
+            event: name.to_string(),
+            alert_id: alert.id,
+            sensor_id: alert.key.sensor_id.clone(),
+            kind: alert.key.kind.to_string(),
+            severity: alert.severity,
+            message: alert.message.clone(),
+            value: alert.value,
+            timestamp,
+        }
+    }
+}
+
+/// A sensor's readings over `from..to`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub sensor_id: String,
+    pub from: u64,
+    pub to: u64,
+    pub count: usize,
+    pub min: f32,
+    pub max: f32,
+    pub average: f32,
+    pub firing_alerts: usize,
+}
+
+impl Summary {
+    pub fn new(sensor_id: &str, from: u64, to: u64, stats: &TemperatureStats, firing_alerts: usize) -> Self {
+        Self {
+            sensor_id: sensor_id.to_string(),
+            from,
+            to,
+            count: stats.count,
+            min: stats.min.celsius,
+            max: stats.max.celsius,
+            average: stats.average.celsius,
+            firing_alerts,
+        }
+    }
+}
+
+/// A rendered request, ready to post.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            headers: BTreeMap::new(),
+            template: None,
+            secret: None,
+            min_severity: default_min_severity(),
+            summaries: false,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retry_seconds: DEFAULT_RETRY_SECONDS,
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn with_template(mut self, template: &str) -> Self {
+        self.template = Some(template.to_string());
+        self
+    }
+
+    pub fn with_secret(mut self, secret: &str) -> Self {
+        self.secret = Some(secret.to_string());
+        self
+    }
+
+    pub fn with_min_severity(mut self, min_severity: Severity) -> Self {
+        self.min_severity = min_severity;
+        self
+    }
+
+    pub fn with_summaries(mut self) -> Self {
+        self.summaries = true;
+        self
+    }
+
+    pub fn with_retries(mut self, max_attempts: u32, retry_seconds: u64) -> Self {
+        self.max_attempts = max_attempts;
+        self.retry_seconds = retry_seconds;
+        self
+    }
+
+    /// Checks the URL, and the template by rendering it for an alert and a
+    /// summary, so a typo is found when the webhook is configured rather
+    /// than when the first alert fires.
+    pub fn validate(&self) -> Result<(), WebhookError> {
+        if !self.url.starts_with("http://") {
+            return Err(WebhookError::InvalidUrl(self.url.clone()));
+        }
+        let broken = |text: &str| text.contains(['\r', '\n']);
+        let invalid = self.headers.iter().find(|(name, value)| broken(name) || name.contains(':') || broken(value));
+        if let Some((name, _)) = invalid {
+            return Err(WebhookError::InvalidHeader(name.clone()));
+        }
+        let alert = WebhookPayload::Alert(AlertNotice {
+            event: "fired".to_string(),
+            alert_id: 1,
+            sensor_id: "sensor".to_string(),
+            kind: "high temperature".to_string(),
+            severity: Severity::Warning,
+            message: "sensor is 31.0°C, high temperature limit 30.0°C".to_string(),
+            value: 31.0,
+            timestamp: 0,
+        });
+        self.render(&alert)?;
+        if self.summaries {
+            let summary = WebhookPayload::Summary(Summary {
+                sensor_id: "sensor".to_string(),
+                from: 0,
+                to: 3600,
+                count: 60,
+                min: 20.0,
+                max: 22.0,
+                average: 21.0,
+                firing_alerts: 0,
+            });
+            self.render(&summary)?;
+        }
+        Ok(())
+    }
+
+    /// Whether `payload` is posted to this webhook.
+    pub fn wants(&self, payload: &WebhookPayload) -> bool {
+        match payload {
+            WebhookPayload::Alert(notice) => notice.severity >= self.min_severity,
+            WebhookPayload::Summary(_) => self.summaries,
+        }
+    }
+
+    pub fn render(&self, payload: &WebhookPayload) -> Result<String, WebhookError> {
+        let json = serde_json::to_string(payload).map_err(|e| WebhookError::InvalidJson(e.to_string()))?;
+        let Some(template) = &self.template else {
+            return Ok(json);
+        };
+        // Parsed back from text rather than converted, which would print
+        // 21.3f32 widened to f64 as 21.299999237060547
+        let fields: Value = serde_json::from_str(&json).map_err(|e| WebhookError::InvalidJson(e.to_string()))?;
+
+        let mut body = String::with_capacity(template.len());
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find("{{") {
+            body.push_str(&rest[..start]);
+            let (name, after) = rest[start + 2..].split_once("}}").ok_or(WebhookError::UnterminatedPlaceholder)?;
+            let name = name.trim();
+            let pointer = format!("/{}", name.replace('.', "/"));
+            let value = fields.pointer(&pointer).ok_or_else(|| WebhookError::UnknownField(name.to_string()))?;
+            match value {
+                // Escaped, without the quotes
+                Value::String(text) => {
+                    let quoted = Value::String(text.clone()).to_string();
+                    body.push_str(&quoted[1..quoted.len() - 1]);
+                }
+                other => body.push_str(&other.to_string()),
+            }
+            rest = after;
+        }
+        body.push_str(rest);
+
+        serde_json::from_str::<Value>(&body).map_err(|e| WebhookError::InvalidJson(e.to_string()))?;
+        Ok(body)
+    }
+
+    /// The request posting `payload` at `timestamp`, signed if there is a
+    /// secret.
+    pub fn request(&self, payload: &WebhookPayload, timestamp: u64) -> Result<WebhookRequest, WebhookError> {
+        let body = self.render(payload)?;
+        let mut headers: Vec<(String, String)> = self.headers.iter().map(|(name, value)| (name.clone(), value.clone())).collect();
+        headers.push(("Content-Type".to_string(), "application/json".to_string()));
+        if let Some(secret) = &self.secret {
+            headers.push((TIMESTAMP_HEADER.to_string(), timestamp.to_string()));
+            headers.push((SIGNATURE_HEADER.to_string(), format!("sha256={}", sign(secret, timestamp, &body))));
+        }
+        Ok(WebhookRequest { url: self.url.clone(), headers, body })
+    }
+
+    /// Seconds to wait after failed attempt number `attempt` (from 1), or
+    /// `None` once all attempts are used up.
+    pub fn retry_delay(&self, attempt: u32) -> Option<u64> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let factor = 1u64.checked_shl(attempt.saturating_sub(1)).unwrap_or(u64::MAX);
+        Some(self.retry_seconds.saturating_mul(factor).min(MAX_RETRY_SECONDS))
+    }
+}
+
+/// Hex HMAC-SHA256 of `{timestamp}.{body}` under `secret`.
+pub fn sign(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AlertEngine, ThresholdConfig};
+
+    #[test]
+    fn renders_templates_and_signs() {
+        let mut engine = AlertEngine::new();
+        engine.set_thresholds("freezer", ThresholdConfig::range(-25.0, -15.0).unwrap());
+        let event = engine.evaluate("freezer", -10.0, 1_700_000_000).remove(0);
+        let payload = WebhookPayload::Alert(AlertNotice::from_event(&event));
+
+        let slack = Webhook::new("http://hooks.example/T0/B0")
+            .with_template(r#"{"text": "[{{severity}}] {{message}}", "value": {{value}}, "id": {{ alert_id }}}"#)
+            .with_header("X-Team", "ops")
+            .with_secret("s3cret");
+        slack.validate().unwrap();
+        let request = slack.request(&payload, 1_700_000_005).unwrap();
+        assert_eq!(request.body, r#"{"text": "[Critical] freezer is -10.0°C, high temperature limit -15.0°C", "value": -10.0, "id": 1}"#);
+        assert!(request.headers.contains(&("X-Team".to_string(), "ops".to_string())));
+        let signature = format!("sha256={}", sign("s3cret", 1_700_000_005, &request.body));
+        assert!(request.headers.contains(&(SIGNATURE_HEADER.to_string(), signature)));
+
+        // Without a template, the payload itself
+        let raw: Value = serde_json::from_str(&Webhook::new("http://hooks.example").render(&payload).unwrap()).unwrap();
+        assert_eq!((raw["type"].as_str(), raw["event"].as_str()), (Some("alert"), Some("fired")));
+
+        let quoted = Webhook::new("http://x").with_template(r#"{"text": "{{message}} \"{{kind}}\""}"#);
+        assert!(quoted.render(&payload).is_ok());
+        let typo = Webhook::new("http://x").with_template(r#"{"text": "{{mesage}}"}"#);
+        assert_eq!(typo.validate(), Err(WebhookError::UnknownField("mesage".to_string())));
+        let broken = Webhook::new("http://x").with_template(r#"{"value": "{{value}}"#);
+        assert!(matches!(broken.validate(), Err(WebhookError::InvalidJson(_))));
+        assert!(matches!(Webhook::new("https://x").validate(), Err(WebhookError::InvalidUrl(_))));
+        let injected = Webhook::new("http://x").with_header("X-Team", "ops\r\nX-Admin: yes");
+        assert_eq!(injected.validate(), Err(WebhookError::InvalidHeader("X-Team".to_string())));
+        // Summaries have fields of their own
+        let summary = Webhook::new("http://x").with_template(r#"{"text": "{{message}}"}"#).with_summaries();
+        assert_eq!(summary.validate(), Err(WebhookError::UnknownField("message".to_string())));
+        let stats = TemperatureStats::new(
+            temp_core::Temperature::new(20.5),
+            temp_core::Temperature::new(22.1),
+            temp_core::PreciseTemperature::new(21.3),
+            60,
+        );
+        let payload = WebhookPayload::Summary(Summary::new("hall", 0, 3600, &stats, 0));
+        let summary = summary.with_template(r#"{"text": "{{sensor_id}} averaged {{average}}°C"}"#);
+        assert_eq!(summary.render(&payload).unwrap(), r#"{"text": "hall averaged 21.3°C"}"#);
+    }
+
+    #[test]
+    fn retries_back_off() {
+        let webhook = Webhook::new("http://x").with_retries(4, 30);
+        let delays: Vec<Option<u64>> = (1..=4).map(|attempt| webhook.retry_delay(attempt)).collect();
+        assert_eq!(delays, [Some(30), Some(60), Some(120), None]);
+        assert_eq!(Webhook::new("http://x").with_retries(100, 30).retry_delay(90), Some(MAX_RETRY_SECONDS));
+    }
+}
@@ -0,0 +1,120 @@
+//! Emulates a sensor node on the host, so tools that talk to nodes can be
+//! developed without a board.
+//!
+//! Usage:
+//!   embedded-emulator [--listen <host:port>] [--temperature <temperature>] [--noise <celsius>]
+//!                     [--seed <n>] [--script <file>] [--fail-every <n>]
+//!                     [--garbage-every <n>:<celsius>] [--offline-between <from>..<until>]
+//!
+//! The node answers on `127.0.0.1:7878` unless told otherwise, speaking the
+//! framed serial protocol of `temp_embedded::framing`. The base
+//! temperature carries its unit, as in `22.5C` or `72F`. A script has lines of
+//! `<seconds> <celsius>` setting the temperature from then on; failure
+//! windows are in seconds since start. See `temp_async::emulator` for
+//! bridging the node to a pseudo-terminal.
+
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+
+use temp_async::emulator::{self, EmulatedNode, TemperatureScript};
+use temp_core::clock::{Clock, SystemClock};
+use temp_core::mock::{FailureSchedule, MockTemperatureSensor};
+use temp_core::Temperature;
+use tokio::net::TcpListener;
+
+const USAGE: &str = "usage: embedded-emulator [--listen <host:port>] [--temperature <temperature>] [--noise <celsius>] \
+                     [--seed <n>] [--script <file>] [--fail-every <n>] [--garbage-every <n>:<celsius>] \
+                     [--offline-between <from>..<until>]";
+
+struct Options {
+    listen: String,
+    temperature: f32,
+    noise: f32,
+    seed: u64,
+    script: TemperatureScript,
+    schedule: FailureSchedule,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let sensor = MockTemperatureSensor::new("emulated".to_string(), options.temperature)
+        .with_noise(options.noise, options.seed)
+        .with_failure_schedule(options.schedule);
+    let node = Arc::new(Mutex::new(EmulatedNode::new(sensor, SystemClock).with_script(options.script)));
+
+    let listener = match TcpListener::bind(&options.listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to listen on {}: {}", options.listen, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("Emulated node listening on {}", options.listen);
+    tokio::spawn(emulator::run_sampler(node.clone()));
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let node = node.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = emulator::serve_connection(stream, node).await {
+                        eprintln!("Connection from {} failed: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Failed to accept a connection: {}", e),
+        }
+    }
+}
+
+fn parse(args: &[String]) -> Result<Options, String> {
+    let started = SystemClock.now();
+    let mut options = Options {
+        listen: "127.0.0.1:7878".to_string(),
+        temperature: 22.0,
+        noise: 0.0,
+        seed: 0,
+        script: TemperatureScript::default(),
+        schedule: FailureSchedule::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("Missing value for {}", flag))?;
+        let invalid = || format!("Invalid value for {}: {}", flag, value);
+        match flag.as_str() {
+            "--listen" => options.listen = value.clone(),
+            "--temperature" => {
+                let temperature: Temperature = value.parse().map_err(|e| format!("{}: {}", invalid(), e))?;
+                options.temperature = temperature.celsius;
+            }
+            "--noise" => options.noise = value.parse().map_err(|_| invalid())?,
+            "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+            "--script" => {
+                options.script = TemperatureScript::load(value).map_err(|e| format!("Failed to read {}: {}", value, e))?
+            }
+            "--fail-every" => options.schedule = options.schedule.fail_every(value.parse().map_err(|_| invalid())?),
+            "--garbage-every" => {
+                let (n, celsius) = value.split_once(':').ok_or_else(invalid)?;
+                let (n, celsius) = (n.parse().map_err(|_| invalid())?, celsius.parse().map_err(|_| invalid())?);
+                options.schedule = options.schedule.garbage_every(n, celsius);
+            }
+            "--offline-between" => {
+                let (from, until) = value.split_once("..").ok_or_else(invalid)?;
+                let (from, until): (u64, u64) = (from.parse().map_err(|_| invalid())?, until.parse().map_err(|_| invalid())?);
+                options.schedule = options.schedule.offline_between(started + from..started + until);
+            }
+            _ => return Err(format!("Unknown option {}", flag)),
+        }
+    }
+    Ok(options)
+}
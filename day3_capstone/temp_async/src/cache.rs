@@ -0,0 +1,113 @@
+//! Small LRU cache with per-entry expiry.
+//!
+//! Eviction scans for the least recently used entry, which is fine for the
+//! few dozen entries a client keeps and avoids a linked list.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+struct Entry<V> {
+    value: V,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+pub struct LruCache<K, V> {
+    capacity: usize,
+    entries: HashMap<K, Entry<V>>,
+    /// Bumped on every access to order entries by recency.
+    tick: u64,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            tick: 0,
+        }
+    }
+
+    /// The cached value, unless it expired by `now`.
+    pub fn get(&mut self, key: &K, now: Instant) -> Option<V> {
+        let expired = self.entries.get(key)?.expires_at <= now;
+        if expired {
+            self.entries.remove(key);
+            return None;
+        }
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.tick;
+        Some(entry.value.clone())
+    }
+
+    /// Caches `value` for `ttl`, evicting the least recently used entry when full.
+    pub fn insert(&mut self, key: K, value: V, ttl: Duration, now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let oldest = self.entries.iter().min_by_key(|(_, e)| e.last_used).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                expires_at: now + ttl,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.entries.remove(key).map(|e| e.value)
+    }
+
+    /// Keeps only the entries whose key matches `keep`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&K) -> bool) {
+        self.entries.retain(|k, _| keep(k));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_and_expired_entries() {
+        let now = Instant::now();
+        let ttl = Duration::from_secs(10);
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1, ttl, now);
+        cache.insert("b", 2, ttl, now);
+        assert_eq!(cache.get(&"a", now), Some(1));
+
+        // "b" is the least recently used
+        cache.insert("c", 3, ttl, now);
+        assert_eq!(cache.get(&"b", now), None);
+        assert_eq!(cache.len(), 2);
+
+        assert_eq!(cache.get(&"c", now + Duration::from_secs(9)), Some(3));
+        assert_eq!(cache.get(&"c", now + ttl), None);
+        assert_eq!(cache.len(), 1);
+    }
+}
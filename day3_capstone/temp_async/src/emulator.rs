@@ -0,0 +1,255 @@
+//! A sensor node emulated on the host, so tools that talk to nodes can be
+//! developed without a board; run it with the `embedded-emulator` binary.
+//!
+//! [`EmulatedNode`] runs the firmware's [`EmbeddedProtocolHandler`] and
+//! samples a [`MockTemperatureSensor`], whose noise and failure schedule
+//! stand in for the real sensor's, optionally following a
+//! [`TemperatureScript`]. Its clock doubles as the node's RTC, so readings
+//! are stamped with Unix time. [`serve_connection`] speaks the node's serial
+//! protocol, postcard in the frames of [`temp_embedded::framing`], over any
+//! byte stream. The binary serves it over TCP; for tools that open a serial
+//! device, `socat` bridges it to a pseudo-terminal:
+//!
+//! ```text
+//! socat pty,link=/tmp/ttyTEMP,raw,echo=0 tcp:127.0.0.1:7878
+//! ```
+
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use temp_core::clock::Clock;
+use temp_core::mock::MockTemperatureSensor;
+use temp_embedded::framing::{self, FrameDecoder};
+use temp_embedded::rtc::RtcProvider;
+use temp_embedded::{EmbeddedError, EmbeddedProtocolHandler, EmbeddedResponse, DEFAULT_MTU, READING_BUFFER_SIZE};
+use temp_store::error_hook::{self, SwallowedKind};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::sleep;
+
+/// Longest frame on the link, for a response of a full MTU.
+const FRAME_LEN: usize = framing::max_frame_len(DEFAULT_MTU);
+
+/// Base temperatures over time, in seconds since the node started.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TemperatureScript {
+    /// Sorted by time.
+    steps: Vec<(u64, f32)>,
+}
+
+impl TemperatureScript {
+    /// Reads a script of `<seconds> <celsius>` lines, see [`parse`](Self::parse).
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses `<seconds> <celsius>` lines, skipping blank lines and `#`
+    /// comments. Each line sets the base temperature from then on.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut steps = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let step = line
+                .split_once(char::is_whitespace)
+                .and_then(|(seconds, celsius)| Some((seconds.parse().ok()?, celsius.trim().parse().ok()?)));
+            let Some(step) = step else {
+                let message = format!("line {}: expected <seconds> <celsius>", number + 1);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            };
+            steps.push(step);
+        }
+        steps.sort_by_key(|&(seconds, _)| seconds);
+        Ok(Self { steps })
+    }
+
+    /// The base temperature `elapsed` seconds in, `None` before the first step.
+    pub fn at(&self, elapsed: u64) -> Option<f32> {
+        self.steps.iter().take_while(|&&(seconds, _)| seconds <= elapsed).last().map(|&(_, celsius)| celsius)
+    }
+}
+
+pub struct EmulatedNode {
+    handler: EmbeddedProtocolHandler<READING_BUFFER_SIZE>,
+    sensor: MockTemperatureSensor,
+    script: TemperatureScript,
+    clock: Box<dyn Clock>,
+    started: u64,
+}
+
+impl EmulatedNode {
+    /// A node booting now by `clock`, which also stamps its readings.
+    pub fn new(sensor: MockTemperatureSensor, clock: impl Clock + 'static) -> Self {
+        let started = clock.now();
+        let mut handler = EmbeddedProtocolHandler::new();
+        handler.init(started as u32);
+        Self {
+            handler,
+            sensor,
+            script: TemperatureScript::default(),
+            clock: Box::new(clock),
+            started,
+        }
+    }
+
+    pub fn with_script(mut self, script: TemperatureScript) -> Self {
+        self.script = script;
+        self
+    }
+
+    pub fn handler(&self) -> &EmbeddedProtocolHandler<READING_BUFFER_SIZE> {
+        &self.handler
+    }
+
+    pub fn sensor_mut(&mut self) -> &mut MockTemperatureSensor {
+        &mut self.sensor
+    }
+
+    /// Takes a batch of readings, like the firmware's sampling loop.
+    pub fn sample(&mut self) -> Result<usize, EmbeddedError> {
+        let now = self.clock.now();
+        if let Some(celsius) = self.script.at(now.saturating_sub(self.started)) {
+            self.sensor.set_base_temperature(celsius);
+        }
+        let uptime = now.saturating_sub(self.started) as u32;
+        self.handler.sample_with_rtc(&mut self.sensor, &mut ClockRtc(&*self.clock), uptime)
+    }
+
+    /// Time between samples at the sample rate the host set.
+    pub fn sample_interval(&self) -> Duration {
+        Duration::from_secs(1) / self.handler.get_sample_rate().max(1)
+    }
+
+    /// Answers one command message with a framed response. A message that
+    /// does not parse is answered with a `DeserializationError`, as the
+    /// firmware does.
+    pub fn answer(&mut self, message: &[u8]) -> Vec<u8> {
+        let response = match self.handler.deserialize_command(message) {
+            Ok(command) => self.handler.process_command(command, self.clock.now() as u32),
+            Err(e) => EmbeddedResponse::Error(e.error_code()),
+        };
+        self.frame(&response)
+    }
+
+    fn frame(&self, response: &EmbeddedResponse) -> Vec<u8> {
+        let framed = self
+            .handler
+            .serialize_response(response)
+            .and_then(|message| framing::encode::<FRAME_LEN>(&message));
+        match framed {
+            Ok(frame) => frame.to_vec(),
+            Err(e) => self.frame(&EmbeddedResponse::Error(e.error_code())),
+        }
+    }
+}
+
+/// The emulator's clock as seen by the firmware.
+struct ClockRtc<'a>(&'a dyn Clock);
+
+impl RtcProvider for ClockRtc<'_> {
+    fn unix_time(&mut self) -> Option<u32> {
+        u32::try_from(self.0.now()).ok()
+    }
+}
+
+/// Samples `node` at its sample rate until the task is dropped.
+pub async fn run_sampler(node: Arc<Mutex<EmulatedNode>>) {
+    loop {
+        let interval = {
+            let mut node = error_hook::lock(&node, "Recovered the emulated node lock");
+            if let Err(e) = node.sample() {
+                error_hook::report(SwallowedKind::Sensor, "Emulated sensor read failed", &e);
+            }
+            node.sample_interval()
+        };
+        sleep(interval).await;
+    }
+}
+
+/// Answers framed commands read from `connection` until the peer closes it.
+pub async fn serve_connection<T>(connection: T, node: Arc<Mutex<EmulatedNode>>) -> io::Result<()>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    let (mut read_half, mut write_half) = tokio::io::split(connection);
+    let mut decoder = FrameDecoder::<FRAME_LEN>::new();
+    let mut buffer = [0; 256];
+    loop {
+        let count = read_half.read(&mut buffer).await?;
+        if count == 0 {
+            return Ok(());
+        }
+        let mut out = Vec::new();
+        for &byte in &buffer[..count] {
+            let Some(message) = decoder.push(byte) else {
+                continue;
+            };
+            let mut node = error_hook::lock(&node, "Recovered the emulated node lock");
+            match message {
+                Ok(message) => out.extend(node.answer(&message)),
+                Err(e) => out.extend(node.frame(&EmbeddedResponse::Error(e.error_code()))),
+            }
+        }
+        if !out.is_empty() {
+            write_half.write_all(&out).await?;
+            write_half.flush().await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temp_core::clock::ManualClock;
+    use temp_core::mock::FailureSchedule;
+    use temp_embedded::rtc::TimeSource;
+    use temp_embedded::EmbeddedCommand;
+    use tokio::io::duplex;
+
+    async fn request(client: &mut (impl AsyncRead + AsyncWrite + Unpin), command: &EmbeddedCommand) -> EmbeddedResponse {
+        let message = postcard::to_allocvec(command).unwrap();
+        client.write_all(&framing::encode::<FRAME_LEN>(&message).unwrap()).await.unwrap();
+        let mut decoder = FrameDecoder::<FRAME_LEN>::new();
+        loop {
+            if let Some(frame) = decoder.push(client.read_u8().await.unwrap()) {
+                return postcard::from_bytes(&frame.unwrap()).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn emulated_node_follows_its_script() {
+        let clock = ManualClock::new(1_700_000_000);
+        let sensor = MockTemperatureSensor::new("emulated".to_string(), 20.0)
+            .with_failure_schedule(FailureSchedule::new().fail_reads(3..4))
+            .with_clock(clock.clone());
+        let script = TemperatureScript::parse("# warm up after a minute\n0 20.0\n60 25.5\n").unwrap();
+        let node = Arc::new(Mutex::new(EmulatedNode::new(sensor, clock.clone()).with_script(script)));
+        let (mut client, server) = duplex(1024);
+        tokio::spawn(serve_connection(server, node.clone()));
+
+        for seconds in [0, 60, 1] {
+            clock.advance(seconds);
+            let _ = node.lock().unwrap().sample();
+        }
+        let latest = request(&mut client, &EmbeddedCommand::GetLatestReading).await;
+        assert!(matches!(latest, EmbeddedResponse::Reading(reading) if reading.temperature.celsius == 25.5 && reading.timestamp == 1_700_000_060 && reading.source == TimeSource::Rtc));
+        assert_eq!(request(&mut client, &EmbeddedCommand::GetReadingCount).await, EmbeddedResponse::ReadingCount(2));
+
+        assert_eq!(request(&mut client, &EmbeddedCommand::SetSampleRate(4)).await, EmbeddedResponse::SampleRateSet(4));
+        assert_eq!(node.lock().unwrap().sample_interval(), Duration::from_millis(250));
+
+        // Noise on the line costs one frame
+        client.write_all(&[7, 7, framing::DELIMITER]).await.unwrap();
+        let mut byte = [0; 64];
+        let count = client.read(&mut byte).await.unwrap();
+        let garbled: EmbeddedResponse = postcard::from_bytes_cobs(&mut byte[..count]).unwrap();
+        assert!(matches!(garbled, EmbeddedResponse::Error(code) if code == EmbeddedError::DeserializationError.error_code()));
+        assert_eq!(request(&mut client, &EmbeddedCommand::GetReadingCount).await, EmbeddedResponse::ReadingCount(2));
+
+        assert!(TemperatureScript::parse("60").is_err());
+    }
+}
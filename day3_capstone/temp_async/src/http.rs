@@ -0,0 +1,616 @@
+//! HTTP ingestion endpoint for third-party sensors.
+//!
+//! A [`ListenerConfig::Http`](crate::listener::ListenerConfig::Http)
+//! listener takes `POST /readings` from systems that do not speak the line
+//! protocol, such as other brands' hubs and shell scripts:
+//!
+//! ```text
+//! curl -H 'X-Source: greenhouse-hub' -H 'Content-Type: text/csv' \
+//!      --data-binary $'sensor_id,temperature,timestamp\nhall,21.5,1700000000' \
+//!      http://monitor:8080/readings
+//! ```
+//!
+//! The body is a JSON [`ExternalReading`] or an array of them, or CSV as
+//! parsed by [`ingest::parse_csv`]. Each reading is submitted as a
+//! `SubmitReading` command, so validation, deduplication, rate limits and
+//! access control are the protocol's. The source is the `X-Source` header,
+//! or the peer's address without one. A bearer token in `Authorization`
+//! authenticates the request on handlers that require it.
+//!
+//! The answer is an [`IngestReport`]. It is a 200 when any reading was
+//! stored or already there, and carries the first rejection's status when
+//! every reading was rejected, e.g. 429 when the source is over its limit.
+//!
+//! The listener also serves backups of a sensor's store, streamed through
+//! the store instead of held in memory like `ExportBackup` answers are:
+//!
+//! ```text
+//! curl -o hall.json.gz 'http://monitor:8080/backup?sensor=hall&format=gzip'
+//! curl --data-binary @hall.json.gz 'http://monitor:8080/restore?sensor=hall'
+//! ```
+//!
+//! `GET /backup` sends the backup in chunks as it is written, in the
+//! `format` given as `json` (the default), `compact` or `gzip`; a backup
+//! that fails halfway ends without the last chunk. `POST /restore` takes a
+//! backup of any format up to [`MAX_RESTORE`] bytes and answers with a
+//! [`RestoreReport`]. The body is read whole before anything is restored,
+//! so an upload cut short leaves the store as it was. They need the same
+//! access as `ExportBackup` and `RestoreBackup`.
+//!
+//! One request is answered per connection.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use temp_core::SensorId;
+use temp_protocol::acl::Permission;
+use temp_protocol::ingest::{self, ExternalReading, SubmitOutcome};
+use temp_protocol::{Command, Language, MessagePayload, ProtocolError, Response, SessionState, TemperatureProtocolHandler};
+use temp_store::{error_hook, BackupFormat, TemperatureStore};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio_util::io::SyncIoBridge;
+
+/// Longest request header accepted.
+const MAX_HEADER: usize = 8 * 1024;
+/// Largest request body accepted, about 20 000 CSV readings.
+pub const MAX_BODY: usize = 1024 * 1024;
+/// Largest backup `POST /restore` accepts, held in memory while restored.
+pub const MAX_RESTORE: usize = 64 * 1024 * 1024;
+/// Bytes of a backup passed on at a time.
+const BACKUP_CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IngestReport {
+    pub accepted: usize,
+    pub duplicates: usize,
+    pub rejected: Vec<Rejection>,
+}
+
+/// A reading that was not stored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rejection {
+    /// Position in the submitted batch.
+    pub index: usize,
+    /// The protocol error code, also an HTTP status.
+    pub code: u16,
+    pub message: String,
+}
+
+/// The answer to `POST /restore`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub sensor_id: SensorId,
+    pub readings: usize,
+}
+
+struct Request {
+    method: String,
+    /// With the query string.
+    path: String,
+    /// Names in lower case.
+    headers: Vec<(String, String)>,
+    /// The `Content-Length`; the body is only read into `body` where it is small.
+    length: usize,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    /// The path without the query string.
+    fn route(&self) -> &str {
+        self.path.split_once('?').map_or(&self.path, |(route, _)| route)
+    }
+
+    fn query(&self, name: &str) -> Option<&str> {
+        let (_, query) = self.path.split_once('?')?;
+        query.split('&').filter_map(|pair| pair.split_once('=')).find(|(key, _)| *key == name).map(|(_, value)| value)
+    }
+}
+
+#[derive(Debug)]
+struct HttpResponse {
+    status: u16,
+    body: String,
+    /// The methods a 405 names.
+    allow: &'static str,
+}
+
+impl HttpResponse {
+    fn json(status: u16, body: &impl Serialize) -> Self {
+        Self { status, body: serde_json::to_string(body).unwrap_or_default(), allow: "POST" }
+    }
+
+    fn method_not_allowed(allow: &'static str, message: impl Into<String>) -> Self {
+        Self { allow, ..Self::error(405, message) }
+    }
+
+    fn protocol_error(error: &ProtocolError, language: Language) -> Self {
+        match error.to_localized_response(language) {
+            Response::Error { code, message } => Self::error(code, message),
+            _ => Self::error(error.code(), format!("{:?}", error)),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        #[derive(Serialize)]
+        struct Error {
+            error: String,
+        }
+        Self::json(status, &Error { error: message.into() })
+    }
+}
+
+/// Answers one request on `stream` and closes it. Returns false if the
+/// request was not valid HTTP.
+pub(crate) async fn serve_connection<S>(
+    stream: S,
+    peer: SocketAddr,
+    handler: &Mutex<TemperatureProtocolHandler>,
+) -> io::Result<bool>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = BufReader::new(stream);
+    let (response, well_formed) = match read_request(&mut stream).await? {
+        Ok(request) if matches!(request.route(), "/backup" | "/restore") => {
+            match serve_backup(&mut stream, &request, handler).await? {
+                Some(response) => (response, true),
+                None => return Ok(true),
+            }
+        }
+        Ok(mut request) => match read_body(&mut stream, &mut request).await? {
+            Ok(()) => (answer(handler, &request, peer), true),
+            Err(response) => (response, false),
+        },
+        Err(response) => (response, false),
+    };
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status),
+        response.body.len()
+    );
+    if response.status == 405 {
+        head.push_str(&format!("Allow: {}\r\n", response.allow));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(well_formed)
+}
+
+/// The request on `reader` up to its body, or the error to answer it with.
+async fn read_request<S>(reader: &mut BufReader<S>) -> io::Result<Result<Request, HttpResponse>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut header = Vec::new();
+    while !header.ends_with(b"\r\n\r\n") {
+        if header.len() >= MAX_HEADER {
+            return Ok(Err(HttpResponse::error(431, "Request header too long")));
+        }
+        header.push(reader.read_u8().await?);
+    }
+    let header = String::from_utf8_lossy(&header);
+    let mut lines = header.lines();
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(path), Some(version)) = (request_line.next(), request_line.next(), request_line.next()) else {
+        return Ok(Err(HttpResponse::error(400, "Malformed request line")));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Ok(Err(HttpResponse::error(505, "Only HTTP/1.x is supported")));
+    }
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let mut request = Request { method: method.to_string(), path: path.to_string(), headers, length: 0, body: Vec::new() };
+
+    request.length = match request.header("content-length").map(str::parse::<usize>) {
+        Some(Ok(length)) => length,
+        Some(Err(_)) => return Ok(Err(HttpResponse::error(400, "Invalid Content-Length"))),
+        None if request.header("transfer-encoding").is_some() => {
+            return Ok(Err(HttpResponse::error(411, "Content-Length is required")));
+        }
+        None => 0,
+    };
+    Ok(Ok(request))
+}
+
+/// Reads the body of `request` into it, or returns the error to answer it with.
+async fn read_body<S>(reader: &mut BufReader<S>, request: &mut Request) -> io::Result<Result<(), HttpResponse>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    if request.length > MAX_BODY {
+        return Ok(Err(HttpResponse::error(413, format!("Body must not exceed {} bytes", MAX_BODY))));
+    }
+    continue_if_expected(reader, request).await?;
+    request.body = vec![0; request.length];
+    reader.read_exact(&mut request.body).await?;
+    Ok(Ok(()))
+}
+
+async fn continue_if_expected<S>(stream: &mut S, request: &Request) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    // Sent by curl before bodies over a kilobyte
+    if request.header("expect").is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue")) {
+        stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+    }
+    Ok(())
+}
+
+/// The session `request` runs in, authenticated by its bearer token if it has one.
+fn open_session(handler: &mut TemperatureProtocolHandler, request: &Request) -> Result<SessionState, HttpResponse> {
+    let mut session = SessionState {
+        language: request.header("accept-language").map_or(Language::English, Language::negotiate),
+        identity: None,
+    };
+    if let Some(token) = request.header("authorization").and_then(|value| value.strip_prefix("Bearer ")) {
+        let message = handler.create_command(Command::Authenticate { token: token.trim().to_string() });
+        if let MessagePayload::Response(Response::Error { code, message }) = handler.process_command_for(message, &mut session).payload {
+            return Err(HttpResponse::error(code, message));
+        }
+    }
+    Ok(session)
+}
+
+/// Streams `GET /backup` or `POST /restore`. Returns the response still to
+/// send, or `None` once a backup was sent.
+async fn serve_backup<S>(
+    stream: &mut BufReader<S>,
+    request: &Request,
+    handler: &Mutex<TemperatureProtocolHandler>,
+) -> io::Result<Option<HttpResponse>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let restore = request.route() == "/restore";
+    let (method, permission) = if restore { ("POST", Permission::Write) } else { ("GET", Permission::Read) };
+    if request.method != method {
+        return Ok(Some(HttpResponse::method_not_allowed(method, format!("{} takes {}", request.route(), method))));
+    }
+    let format = match request.query("format") {
+        None | Some("json") => BackupFormat::Json,
+        Some("compact") => BackupFormat::Compact,
+        Some("gzip") => BackupFormat::Gzip,
+        Some(other) => {
+            return Ok(Some(HttpResponse::error(400, format!("Unknown backup format {}, use json, compact or gzip", other))));
+        }
+    };
+    let sensor_id: SensorId = match request.query("sensor").map(str::parse) {
+        Some(Ok(sensor_id)) => sensor_id,
+        Some(Err(e)) => return Ok(Some(HttpResponse::error(400, format!("Invalid sensor: {}", e)))),
+        None => return Ok(Some(HttpResponse::error(400, "The sensor is given as ?sensor="))),
+    };
+
+    let store = {
+        let mut handler = error_hook::lock(handler, "Recovered the protocol handler lock");
+        open_session(&mut handler, request).and_then(|session| {
+            handler
+                .backup_store_for(&sensor_id, permission, &session)
+                .map_err(|error| HttpResponse::protocol_error(&error, session.language))
+        })
+    };
+    let store = match store {
+        Ok(store) => store,
+        Err(response) => return Ok(Some(response)),
+    };
+    if restore {
+        restore_backup(stream, request, store, sensor_id).await.map(Some)
+    } else {
+        send_backup(stream, store, format).await.map(|()| None)
+    }
+}
+
+/// Sends the backup as it is written, in chunks.
+async fn send_backup<S>(stream: &mut S, store: TemperatureStore, format: BackupFormat) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let (writer, mut backup) = tokio::io::duplex(BACKUP_CHUNK);
+    let writer = SyncIoBridge::new(writer);
+    let written = tokio::task::spawn_blocking(move || store.write_backup(writer, format));
+
+    let content_type = match format {
+        BackupFormat::Json => "application/x-ndjson",
+        BackupFormat::Compact => "application/octet-stream",
+        BackupFormat::Gzip => "application/gzip",
+    };
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n",
+        content_type
+    );
+    stream.write_all(head.as_bytes()).await?;
+    let mut chunk = vec![0; BACKUP_CHUNK];
+    loop {
+        let read = backup.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        stream.write_all(format!("{:x}\r\n", read).as_bytes()).await?;
+        stream.write_all(&chunk[..read]).await?;
+        stream.write_all(b"\r\n").await?;
+    }
+    // Without the last chunk the client can tell the backup is incomplete
+    written.await.map_err(io::Error::other)??;
+    stream.write_all(b"0\r\n\r\n").await?;
+    stream.shutdown().await
+}
+
+/// Reads the backup in the body and restores it into `store`.
+async fn restore_backup<S>(
+    stream: &mut BufReader<S>,
+    request: &Request,
+    store: TemperatureStore,
+    sensor_id: SensorId,
+) -> io::Result<HttpResponse>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    if request.length > MAX_RESTORE {
+        return Ok(HttpResponse::error(413, format!("Backups must not exceed {} bytes", MAX_RESTORE)));
+    }
+    continue_if_expected(stream, request).await?;
+    let mut backup = Vec::with_capacity(request.length);
+    let copied = (&mut *stream).take(request.length as u64).read_to_end(&mut backup).await?;
+    if copied != request.length {
+        return Ok(HttpResponse::error(
+            400,
+            format!("The backup ended after {} of {} bytes", copied, request.length),
+        ));
+    }
+
+    let restored = tokio::task::spawn_blocking(move || store.restore_backup(backup.as_slice()));
+    match restored.await.map_err(io::Error::other)? {
+        Ok(readings) => Ok(HttpResponse::json(200, &RestoreReport { sensor_id, readings })),
+        Err(e) => Ok(HttpResponse::error(400, format!("Restoring '{}' failed: {}", sensor_id, e))),
+    }
+}
+
+fn answer(handler: &Mutex<TemperatureProtocolHandler>, request: &Request, peer: SocketAddr) -> HttpResponse {
+    if request.route() != "/readings" {
+        return HttpResponse::error(404, format!("No endpoint {}", request.route()));
+    }
+    if request.method != "POST" {
+        return HttpResponse::method_not_allowed("POST", "Readings are submitted with POST");
+    }
+    let readings = match parse_body(request) {
+        Ok(readings) => readings,
+        Err(response) => return response,
+    };
+    let source = request.header("x-source").map_or_else(|| peer.ip().to_string(), str::to_string);
+
+    let mut handler = error_hook::lock(handler, "Recovered the protocol handler lock");
+    let mut session = match open_session(&mut handler, request) {
+        Ok(session) => session,
+        Err(response) => return response,
+    };
+
+    let mut report = IngestReport::default();
+    for (index, reading) in readings.into_iter().enumerate() {
+        let message = handler.create_command(Command::SubmitReading { source: source.clone(), reading });
+        match handler.process_command_for(message, &mut session).payload {
+            MessagePayload::Response(Response::ReadingSubmitted { outcome: SubmitOutcome::Accepted, .. }) => report.accepted += 1,
+            MessagePayload::Response(Response::ReadingSubmitted { outcome: SubmitOutcome::Duplicate, .. }) => report.duplicates += 1,
+            MessagePayload::Response(Response::Error { code, message }) => report.rejected.push(Rejection { index, code, message }),
+            other => report.rejected.push(Rejection { index, code: 500, message: format!("Unexpected answer {:?}", other) }),
+        }
+    }
+    let status = match report.rejected.first() {
+        Some(rejection) if report.accepted + report.duplicates == 0 => rejection.code,
+        _ => 200,
+    };
+    HttpResponse::json(status, &report)
+}
+
+fn parse_body(request: &Request) -> Result<Vec<ExternalReading>, HttpResponse> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(ExternalReading),
+        Many(Vec<ExternalReading>),
+    }
+
+    let body = std::str::from_utf8(&request.body).map_err(|_| HttpResponse::error(400, "Body is not UTF-8"))?;
+    let content_type = request.header("content-type").unwrap_or("application/json");
+    match content_type.split(';').next().unwrap_or_default().trim() {
+        "text/csv" => ingest::parse_csv(body).map_err(|reason| HttpResponse::error(400, reason)),
+        "application/json" => match serde_json::from_str(body) {
+            Ok(OneOrMany::One(reading)) => Ok(vec![reading]),
+            Ok(OneOrMany::Many(readings)) => Ok(readings),
+            Err(_) => Err(HttpResponse::error(400, "Expected a reading or an array of readings")),
+        },
+        other => Err(HttpResponse::error(415, format!("Unsupported content type {}, use application/json or text/csv", other))),
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        415 => "Unsupported Media Type",
+        422 => "Unprocessable Content",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temp_core::clock::ManualClock;
+    use temp_core::Temperature;
+    use temp_protocol::ingest::RateLimit;
+    use temp_store::TemperatureReading;
+    use tokio::io::duplex;
+
+    async fn post(handler: &Mutex<TemperatureProtocolHandler>, request: &str) -> (u16, String) {
+        let (mut client, server) = duplex(64 * 1024);
+        client.write_all(request.as_bytes()).await.unwrap();
+        let peer = "192.0.2.7:50000".parse().unwrap();
+        serve_connection(server, peer, handler).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    /// Sends `request` while the response is read, for bodies larger than the pipe.
+    async fn exchange(handler: &Mutex<TemperatureProtocolHandler>, request: &[u8]) -> (u16, String, Vec<u8>) {
+        let (mut client, server) = duplex(16 * 1024);
+        let peer = "192.0.2.7:50000".parse().unwrap();
+        let mut response = Vec::new();
+        let (served, _) = tokio::join!(serve_connection(server, peer, handler), async {
+            client.write_all(request).await.unwrap();
+            client.shutdown().await.unwrap();
+            client.read_to_end(&mut response).await.unwrap();
+        });
+        served.unwrap();
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(response[..split].to_vec()).unwrap();
+        (head[9..12].parse().unwrap(), head, response[split + 4..].to_vec())
+    }
+
+    fn dechunk(mut body: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        loop {
+            let line = body.windows(2).position(|w| w == b"\r\n").unwrap();
+            let size = usize::from_str_radix(std::str::from_utf8(&body[..line]).unwrap(), 16).unwrap();
+            if size == 0 {
+                return data;
+            }
+            data.extend_from_slice(&body[line + 2..line + 2 + size]);
+            body = &body[line + 4 + size..];
+        }
+    }
+
+    fn request(path: &str, content_type: &str, body: &str) -> String {
+        format!(
+            "POST {} HTTP/1.1\r\nHost: monitor\r\nX-Source: hub\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+            path,
+            content_type,
+            body.len(),
+            body
+        )
+    }
+
+    #[tokio::test]
+    async fn ingests_json_and_csv() {
+        let now = 1_700_000_000;
+        let handler = TemperatureProtocolHandler::new()
+            .with_clock(ManualClock::new(now))
+            .with_ingest_rate_limit(RateLimit { per_minute: 1, burst: 3 });
+        let handler = Mutex::new(handler);
+
+        let json = format!(r#"[{{"sensor_id": "temp_01", "temperature": 21.5, "timestamp": {now}}}, {{"sensor_id": "nope", "temperature": 20}}]"#);
+        let (status, body) = post(&handler, &request("/readings", "application/json", &json)).await;
+        let report: IngestReport = serde_json::from_str(&body).unwrap();
+        assert_eq!((status, report.accepted, report.rejected[0].index, report.rejected[0].code), (200, 1, 1, 404));
+
+        // The first reading again, and one in Fahrenheit
+        let csv = format!("sensor_id,temperature,timestamp\ntemp_01,21.5,{now}\ntemp_02,70F,{now}\n");
+        let (status, body) = post(&handler, &request("/readings", "text/csv", &csv)).await;
+        assert_eq!(status, 200);
+        assert_eq!(serde_json::from_str::<IngestReport>(&body).unwrap(), IngestReport { accepted: 1, duplicates: 1, rejected: Vec::new() });
+        let stats = {
+            let mut handler = handler.lock().unwrap();
+            let message = handler.create_command(Command::GetStats { sensor_id: "temp_01".parse().unwrap() });
+            handler.process_command(message).payload
+        };
+        assert!(matches!(stats, MessagePayload::Response(Response::Stats { stats, .. }) if stats.count == 1));
+
+        // The hub has used up its burst of three
+        let single = format!(r#"{{"sensor_id": "temp_03", "temperature": 19.0, "timestamp": {now}}}"#);
+        let (status, _) = post(&handler, &request("/readings", "application/json", &single)).await;
+        assert_eq!(status, 429);
+
+        assert_eq!(post(&handler, &request("/other", "application/json", "[]")).await.0, 404);
+        assert_eq!(post(&handler, &request("/readings", "text/plain", "21")).await.0, 415);
+        assert_eq!(post(&handler, &request("/readings", "application/json", "{")).await.0, 400);
+        assert_eq!(post(&handler, "GET /readings HTTP/1.1\r\n\r\n").await.0, 405);
+    }
+
+    #[tokio::test]
+    async fn streams_backups_in_and_out() {
+        let readings: Vec<_> = (0..20_000)
+            .map(|i| TemperatureReading::with_timestamp(Temperature::new(20.0 + (i % 40) as f32 * 0.25), 1_700_000_000 + i * 60))
+            .collect();
+        let (hall, attic, cellar) =
+            (TemperatureStore::new(readings.len()), TemperatureStore::new(readings.len()), TemperatureStore::new(readings.len()));
+        for reading in &readings {
+            hall.add_reading(*reading);
+        }
+        let mut handler = TemperatureProtocolHandler::new();
+        handler.attach_store("hall".parse().unwrap(), hall.clone_handle());
+        handler.attach_store("attic".parse().unwrap(), attic.clone_handle());
+        handler.attach_store("cellar".parse().unwrap(), cellar.clone_handle());
+        let handler = Mutex::new(handler);
+
+        // Larger than one chunk as JSON, so it is streamed in several
+        let (status, head, body) = exchange(&handler, b"GET /backup?sensor=hall HTTP/1.1\r\n\r\n").await;
+        assert_eq!(status, 200);
+        assert!(head.contains("Transfer-Encoding: chunked"));
+        assert!(body.len() > 4 * BACKUP_CHUNK);
+        assert_eq!(temp_store::backup::read_backup(dechunk(&body).as_slice()).unwrap(), readings);
+
+        let (status, head, body) = exchange(&handler, b"GET /backup?sensor=hall&format=gzip HTTP/1.1\r\n\r\n").await;
+        assert_eq!(status, 200);
+        assert!(head.contains("Content-Type: application/gzip"));
+        let gzip = dechunk(&body);
+
+        let mut restore = format!("POST /restore?sensor=attic HTTP/1.1\r\nContent-Length: {}\r\n\r\n", gzip.len()).into_bytes();
+        restore.extend_from_slice(&gzip);
+        let (status, _, body) = exchange(&handler, &restore).await;
+        assert_eq!(status, 200);
+        let report: RestoreReport = serde_json::from_slice(&body).unwrap();
+        assert_eq!((report.sensor_id.as_str(), report.readings), ("attic", readings.len()));
+        assert_eq!(attic.get_stats(), hall.get_stats());
+
+        // A cut-off backup restores nothing
+        let cut = gzip.len() / 2;
+        let mut restore = format!("POST /restore?sensor=attic HTTP/1.1\r\nContent-Length: {}\r\n\r\n", cut).into_bytes();
+        restore.extend_from_slice(&gzip[..cut]);
+        assert_eq!(exchange(&handler, &restore).await.0, 400);
+        assert_eq!(attic.get_stats().count, readings.len());
+
+        // So does one that ends before its Content-Length, even between records
+        let json = dechunk(&exchange(&handler, b"GET /backup?sensor=hall HTTP/1.1\r\n\r\n").await.2);
+        let cut = json[..json.len() / 2].iter().rposition(|&b| b == b'\n').unwrap() + 1;
+        let mut restore = format!("POST /restore?sensor=cellar HTTP/1.1\r\nContent-Length: {}\r\n\r\n", json.len()).into_bytes();
+        restore.extend_from_slice(&json[..cut]);
+        let (status, _, body) = exchange(&handler, &restore).await;
+        assert_eq!(status, 400);
+        assert!(String::from_utf8(body).unwrap().contains(&format!("after {} of {} bytes", cut, json.len())));
+        assert_eq!(cellar.get_stats().count, 0);
+
+        let oversized = format!("POST /restore?sensor=cellar HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_RESTORE + 1);
+        assert_eq!(exchange(&handler, oversized.as_bytes()).await.0, 413);
+
+        let (status, head, _) = exchange(&handler, b"POST /backup?sensor=hall HTTP/1.1\r\n\r\n").await;
+        assert_eq!(status, 405);
+        assert!(head.contains("Allow: GET"));
+        assert_eq!(exchange(&handler, b"GET /backup?sensor=basement HTTP/1.1\r\n\r\n").await.0, 404);
+        assert_eq!(exchange(&handler, b"GET /backup?sensor=hall&format=xml HTTP/1.1\r\n\r\n").await.0, 400);
+        assert_eq!(exchange(&handler, b"GET /backup HTTP/1.1\r\n\r\n").await.0, 400);
+    }
+}
@@ -1,14 +1,60 @@
-use std::time::Duration;
-use tokio::time::{sleep, interval};
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{sleep, interval, interval_at, Instant};
 use tokio::sync::{mpsc, oneshot};
-use temp_core::Temperature;
+use temp_core::{SensorHealth, Temperature};
+use temp_core::clock::{Clock, SystemClock};
+use temp_core::control::{Actuator, ControlLoop, Controller};
+use temp_core::gradient::{GradientThreshold, TemperatureGradient};
+use temp_core::threshold::ThresholdConfig;
+use temp_core::health::SharedHealth;
+use temp_core::mock::{FailureSchedule, Fault, Noise, NoiseProfile};
+use temp_core::transform::SharedPipeline;
+use temp_store::error_hook::{self, SwallowedKind};
 use temp_store::{TemperatureReading, TemperatureStore};
+use temp_alert::{Alert, AlertEngine, AlertEvent};
+use temp_alert::webhook::{AlertNotice, Summary, WebhookPayload};
+use crate::webhook::WebhookSender;
+
+pub mod cache;
+pub mod emulator;
+pub mod http;
+pub mod listener;
+pub mod proxy;
+pub mod scenario;
+pub mod service;
+pub mod simulation;
+#[cfg(feature = "systemd")]
+pub mod systemd;
+pub mod tls;
+pub mod transport;
+pub mod webhook;
 
 pub trait AsyncTemperatureSensor: Send {
     type Error: std::fmt::Debug + Send;
 
-    async fn read_temperature(&mut self) -> Result<Temperature, Self::Error>;
+    fn read_temperature(&mut self) -> impl Future<Output = Result<Temperature, Self::Error>> + Send;
     fn sensor_id(&self) -> &str;
+
+    /// Async counterpart of `TemperatureSensor::read_temperatures`: up to
+    /// `out.len()` samples, oldest first, one by default.
+    fn read_temperatures(&mut self, out: &mut [Temperature]) -> impl Future<Output = Result<usize, Self::Error>> + Send {
+        async move {
+            match out.first_mut() {
+                Some(slot) => {
+                    *slot = self.read_temperature().await?;
+                    Ok(1)
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    /// Async counterpart of `TemperatureSensor::self_test`: by default one
+    /// reading, which has to be a possible temperature.
+    fn self_test(&mut self) -> impl Future<Output = Result<SensorHealth, Self::Error>> + Send {
+        async move { self.read_temperature().await.map(SensorHealth::of_reading) }
+    }
 }
 
 pub struct AsyncMockSensor {
@@ -16,6 +62,13 @@ pub struct AsyncMockSensor {
     temperature: f32,
     read_delay: Duration,
     fail_next: bool,
+    offline: bool,
+    fifo_depth: usize,
+    health: SensorHealth,
+    noise: Noise,
+    clock: Box<dyn Clock>,
+    schedule: FailureSchedule,
+    reads: usize,
 }
 
 impl AsyncMockSensor {
@@ -25,14 +78,47 @@ impl AsyncMockSensor {
             temperature,
             read_delay: Duration::from_millis(100),
             fail_next: false,
+            offline: false,
+            fifo_depth: 1,
+            health: SensorHealth::Ok,
+            noise: Noise::new(0.0, 0),
+            clock: Box::new(SystemClock),
+            schedule: FailureSchedule::new(),
+            reads: 0,
         }
     }
 
+    /// Adds noise following `profile` to every reading, see
+    /// [`MockTemperatureSensor::with_profile`](temp_core::mock::MockTemperatureSensor::with_profile).
+    pub fn with_profile(mut self, profile: NoiseProfile, seed: u64) -> Self {
+        self.noise = Noise::with_profile(profile, seed);
+        self
+    }
+
+    /// Time source of the daily cycle.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Injects the faults of `schedule`, see [`FailureSchedule`]. Offline
+    /// reads fail with `ReadFailed` like [`set_offline`](Self::set_offline).
+    pub fn with_failure_schedule(mut self, schedule: FailureSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
     pub fn with_delay(mut self, delay: Duration) -> Self {
         self.read_delay = delay;
         self
     }
 
+    /// Returns up to `depth` samples per batch read, for one read delay.
+    pub fn with_fifo_depth(mut self, depth: usize) -> Self {
+        self.fifo_depth = depth.max(1);
+        self
+    }
+
     pub fn set_temperature(&mut self, temp: f32) {
         self.temperature = temp;
     }
@@ -40,6 +126,22 @@ impl AsyncMockSensor {
     pub fn fail_next_read(&mut self) {
         self.fail_next = true;
     }
+
+    /// Fails every read until set back online.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// What self-tests report while the sensor is online.
+    pub fn with_health(mut self, health: SensorHealth) -> Self {
+        self.health = health;
+        self
+    }
+
+    fn sample(&mut self) -> Temperature {
+        let now = self.clock.now();
+        Temperature::new(self.temperature + self.noise.daily_cycle(now) + self.noise.sample())
+    }
 }
 
 #[derive(Debug)]
@@ -53,13 +155,41 @@ impl AsyncTemperatureSensor for AsyncMockSensor {
 
     async fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
         sleep(self.read_delay).await;
+        self.reads += 1;
+        let fault = self.schedule.fault(self.reads, self.clock.now());
 
-        if self.fail_next {
+        if self.fail_next || self.offline || matches!(fault, Some(Fault::Fail | Fault::Offline)) {
             self.fail_next = false;
             return Err(AsyncSensorError::ReadFailed);
         }
 
-        Ok(Temperature::new(self.temperature))
+        match fault {
+            Some(Fault::Garbage(celsius)) => Ok(Temperature::new(celsius)),
+            _ => Ok(self.sample()),
+        }
+    }
+
+    async fn read_temperatures(&mut self, out: &mut [Temperature]) -> Result<usize, Self::Error> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        out[0] = self.read_temperature().await?;
+        let garbage = match self.schedule.fault(self.reads, self.clock.now()) {
+            Some(Fault::Garbage(_)) => Some(out[0]),
+            _ => None,
+        };
+        let count = out.len().min(self.fifo_depth);
+        for slot in &mut out[1..count] {
+            *slot = garbage.unwrap_or_else(|| self.sample());
+        }
+        Ok(count)
+    }
+
+    async fn self_test(&mut self) -> Result<SensorHealth, Self::Error> {
+        if self.offline || self.schedule.is_offline(self.clock.now()) {
+            return Err(AsyncSensorError::ReadFailed);
+        }
+        Ok(self.health)
     }
 
     fn sensor_id(&self) -> &str {
@@ -72,22 +202,150 @@ pub enum MonitorCommand {
     SetInterval(Duration),
     GetStats(oneshot::Sender<Option<temp_store::TemperatureStats>>),
     GetLatest(oneshot::Sender<Option<TemperatureReading>>),
+    SetSetpoint(Temperature),
+    GetAlerts(oneshot::Sender<Vec<Alert>>),
     Stop,
 }
 
+/// Object-safe view of a `ControlLoop` so the monitor can own any controller/actuator pair.
+trait ControlStep: Send {
+    fn step(&mut self, measured: Temperature, dt_seconds: f32) -> Result<f32, String>;
+    fn set_setpoint(&mut self, setpoint: Temperature);
+}
+
+impl<C, A> ControlStep for ControlLoop<C, A>
+where
+    C: Controller + Send,
+    A: Actuator + Send,
+{
+    fn step(&mut self, measured: Temperature, dt_seconds: f32) -> Result<f32, String> {
+        ControlLoop::step(self, measured, dt_seconds).map_err(|e| format!("{:?}", e))
+    }
+
+    fn set_setpoint(&mut self, setpoint: Temperature) {
+        ControlLoop::set_setpoint(self, setpoint);
+    }
+}
+
+/// Most samples the monitor takes from a sensor per tick.
+pub const SAMPLE_BATCH: usize = 16;
+
 pub struct AsyncTemperatureMonitor {
     store: TemperatureStore,
     command_rx: mpsc::Receiver<MonitorCommand>,
     command_tx: mpsc::Sender<MonitorCommand>,
+    control: Option<Box<dyn ControlStep>>,
+    alerts: AlertEngine,
+    watchdog_multiplier: Option<f32>,
+    transforms: Option<SharedPipeline>,
+    /// Where self-test results are published.
+    health: Option<SharedHealth>,
+    last_health: Option<SensorHealth>,
+    /// How often the loop pings the systemd watchdog.
+    keepalive_interval: Option<Duration>,
+    gradient: Option<(TemperatureGradient, GradientThreshold)>,
+    thresholds: Option<ThresholdConfig>,
+    /// Where alerts go, and how often a summary follows them.
+    webhooks: Option<(WebhookSender, Duration)>,
 }
 
 impl AsyncTemperatureMonitor {
     pub fn new(capacity: usize) -> Self {
+        Self::with_store(TemperatureStore::new(capacity))
+    }
+
+    /// A monitor writing into an existing (possibly persistent or shared) store.
+    pub fn with_store(store: TemperatureStore) -> Self {
         let (command_tx, command_rx) = mpsc::channel(32);
         Self {
-            store: TemperatureStore::new(capacity),
+            store,
             command_rx,
             command_tx,
+            control: None,
+            alerts: AlertEngine::new(),
+            watchdog_multiplier: None,
+            transforms: None,
+            health: None,
+            last_health: None,
+            keepalive_interval: None,
+            gradient: None,
+            thresholds: None,
+            webhooks: None,
+        }
+    }
+
+    /// Drives `control` with every successful reading, turning the monitor into a thermostat.
+    pub fn with_control_loop<C, A>(mut self, control: ControlLoop<C, A>) -> Self
+    where
+        C: Controller + Send + 'static,
+        A: Actuator + Send + 'static,
+    {
+        self.control = Some(Box::new(control));
+        self
+    }
+
+    /// Raises a "no data" alert when the sensor produced no reading for
+    /// `multiplier` sampling intervals, whatever the thresholds say, unless
+    /// a self-test then finds the sensor working: it is only quiet, e.g.
+    /// behind a deadband transform.
+    pub fn with_watchdog(mut self, multiplier: f32) -> Self {
+        self.watchdog_multiplier = Some(multiplier);
+        self
+    }
+
+    /// Raises high and low temperature alerts for the sensor's readings, see
+    /// [`AlertEngine::evaluate`].
+    pub fn with_thresholds(mut self, thresholds: ThresholdConfig) -> Self {
+        self.thresholds = Some(thresholds);
+        self
+    }
+
+    /// Raises a rising or falling fast alert while the rate `gradient`
+    /// measures over the readings exceeds `threshold`.
+    pub fn with_gradient_alert(mut self, gradient: TemperatureGradient, threshold: GradientThreshold) -> Self {
+        self.gradient = Some((gradient, threshold));
+        self
+    }
+
+    /// Posts every alert that is not silenced to `webhooks`, and a summary of
+    /// the readings every `summary_every`.
+    pub fn with_webhooks(mut self, webhooks: WebhookSender, summary_every: Duration) -> Self {
+        self.webhooks = Some((webhooks, summary_every));
+        self
+    }
+
+    /// Passes every reading through `transforms` before it is stored or
+    /// reaches the control loop. Readings the pipeline drops count as
+    /// missing for the watchdog.
+    pub fn with_transforms(mut self, transforms: SharedPipeline) -> Self {
+        self.transforms = Some(transforms);
+        self
+    }
+
+    /// Publishes the sensor's self-test results to `health`. The sensor is
+    /// tested when the monitor starts, when the watchdog finds it overdue,
+    /// and again once readings come back after a failed test.
+    pub fn with_health(mut self, health: SharedHealth) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Pings the systemd watchdog from the monitor loop, if the unit has one,
+    /// so a stalled loop gets the service restarted.
+    #[cfg(feature = "systemd")]
+    pub fn with_systemd_watchdog(mut self) -> Self {
+        self.keepalive_interval = systemd::watchdog_interval();
+        self
+    }
+
+    pub fn store(&self) -> &TemperatureStore {
+        &self.store
+    }
+
+    fn transform(&self, temperature: Temperature) -> Option<Temperature> {
+        match &self.transforms {
+            Some(pipeline) => error_hook::lock(pipeline, "Recovered the transform pipeline lock").apply(temperature),
+            None => Some(temperature),
         }
     }
 
@@ -99,35 +357,115 @@ impl AsyncTemperatureMonitor {
 
     pub async fn run<S: AsyncTemperatureSensor>(&mut self, mut sensor: S, initial_interval: Duration) {
         let mut sample_interval = interval(initial_interval);
+        let mut current_interval = initial_interval;
+        let mut last_sample: Option<Instant> = None;
+        let mut watchdog_interval = interval(initial_interval);
+        let mut last_reading = Instant::now();
+        let mut keepalive = interval(self.keepalive_interval.unwrap_or(initial_interval));
+        let summary_every = self.webhooks.as_ref().map_or(initial_interval, |(_, every)| *every);
+        let mut summary = interval_at(Instant::now() + summary_every, summary_every);
+        let mut summary_from = unix_now();
+        let mut batch = [Temperature::new(0.0); SAMPLE_BATCH];
+        let mut readings = Vec::with_capacity(SAMPLE_BATCH);
+        if self.health.is_some() {
+            self.self_test(&mut sensor).await;
+        }
+        if let Some(thresholds) = self.thresholds {
+            self.alerts.set_thresholds(sensor.sensor_id(), thresholds);
+        }
 
         loop {
             tokio::select! {
                 _ = sample_interval.tick() => {
-                    match sensor.read_temperature().await {
-                        Ok(temp) => {
-                            let reading = TemperatureReading::new(temp);
-                            self.store.add_reading(reading);
-                            println!("Temperature reading: {} from sensor {}", temp, sensor.sensor_id());
+                    match sensor.read_temperatures(&mut batch).await {
+                        Ok(count) => {
+                            readings.clear();
+                            for &raw in &batch[..count] {
+                                match self.transform(raw) {
+                                    Some(temp) => {
+                                        readings.push(TemperatureReading::new(temp));
+                                        println!("Temperature reading: {} from sensor {}", temp, sensor.sensor_id());
+                                    }
+                                    None => println!("Dropped reading {} from sensor {}", raw, sensor.sensor_id()),
+                                }
+                            }
+                            self.store.add_readings(&readings);
+                            self.check_thresholds(sensor.sensor_id(), &readings);
+
+                            // The control loop and watchdog act once per tick, on the newest sample
+                            if let Some(latest) = readings.last() {
+                                last_reading = Instant::now();
+                                self.check_watchdog(sensor.sensor_id(), Duration::ZERO, current_interval);
+                                if self.last_health == Some(SensorHealth::Failed) {
+                                    self.self_test(&mut sensor).await;
+                                }
+
+                                let now = Instant::now();
+                                let dt = last_sample.map_or(0.0, |last| (now - last).as_secs_f32());
+                                last_sample = Some(now);
+                                self.check_gradient(sensor.sensor_id(), latest.temperature, dt);
+                                if let Some(control) = self.control.as_mut() {
+                                    if let Err(e) = control.step(latest.temperature, dt) {
+                                        error_hook::report(SwallowedKind::Actuator, "Failed to drive actuator", &e);
+                                    }
+                                }
+                            }
                         }
                         Err(e) => {
-                            eprintln!("Failed to read temperature from {}: {:?}", sensor.sensor_id(), e);
+                            let context = format!("Failed to read temperature from {}", sensor.sensor_id());
+                            error_hook::report(SwallowedKind::Sensor, &context, &format_args!("{:?}", e));
                         }
                     }
                 }
 
+                _ = watchdog_interval.tick(), if self.watchdog_multiplier.is_some() => {
+                    let mut age = last_reading.elapsed();
+                    if self.max_data_age(current_interval).is_some_and(|max_age| age > max_age)
+                        && self.self_test(&mut sensor).await.is_working()
+                    {
+                        age = Duration::ZERO;
+                    }
+                    self.check_watchdog(sensor.sensor_id(), age, current_interval);
+                }
+
+                _ = summary.tick(), if self.webhooks.is_some() => {
+                    let to = unix_now() + 1;
+                    self.publish_summary(sensor.sensor_id(), summary_from, to);
+                    summary_from = to;
+                }
+
+                _ = keepalive.tick(), if self.keepalive_interval.is_some() => {
+                    #[cfg(feature = "systemd")]
+                    systemd::notify_watchdog();
+                }
+
                 command = self.command_rx.recv() => {
                     match command {
                         Some(MonitorCommand::SetInterval(new_interval)) => {
                             sample_interval = interval(new_interval);
+                            watchdog_interval = interval(new_interval);
+                            current_interval = new_interval;
                             println!("Changed sampling interval to {:?}", new_interval);
                         }
                         Some(MonitorCommand::GetStats(reply)) => {
                             let stats = self.store.calculate_stats();
-                            let _ = reply.send(stats);
+                            send_reply(reply, stats, "stats");
                         }
                         Some(MonitorCommand::GetLatest(reply)) => {
                             let latest = self.store.get_latest();
-                            let _ = reply.send(latest);
+                            send_reply(reply, latest, "latest reading");
+                        }
+                        Some(MonitorCommand::SetSetpoint(setpoint)) => {
+                            match self.control.as_mut() {
+                                Some(control) => {
+                                    control.set_setpoint(setpoint);
+                                    println!("Changed setpoint to {}", setpoint);
+                                }
+                                None => eprintln!("Ignoring setpoint {}: no control loop configured", setpoint),
+                            }
+                        }
+                        Some(MonitorCommand::GetAlerts(reply)) => {
+                            send_reply(reply, self.alerts.active_alerts(), "alerts");
                         }
                         Some(MonitorCommand::Stop) => {
                             println!("Stopping temperature monitor");
@@ -144,6 +482,164 @@ impl AsyncTemperatureMonitor {
     }
 }
 
+impl AsyncTemperatureMonitor {
+    fn max_data_age(&self, expected_interval: Duration) -> Option<Duration> {
+        self.watchdog_multiplier.map(|multiplier| expected_interval.mul_f32(multiplier))
+    }
+
+    fn check_watchdog(&mut self, sensor_id: &str, age: Duration, expected_interval: Duration) {
+        let Some(max_age) = self.max_data_age(expected_interval) else {
+            return;
+        };
+        let Some(event) = self.alerts.check_data_age(sensor_id, age, max_age, unix_now()) else {
+            return;
+        };
+        match &event {
+            AlertEvent::Fired(alert) => eprintln!("Alert: {}", alert.message),
+            AlertEvent::Resolved(_) => println!("Sensor {} is reporting again", sensor_id),
+            _ => {}
+        }
+        self.publish(&event);
+    }
+
+    fn check_thresholds(&mut self, sensor_id: &str, readings: &[TemperatureReading]) {
+        for reading in readings {
+            for event in self.alerts.evaluate(sensor_id, reading.temperature.celsius, reading.timestamp) {
+                match &event {
+                    AlertEvent::Fired(alert) | AlertEvent::SeverityChanged { alert, .. } => eprintln!("Alert: {}", alert.message),
+                    AlertEvent::Resolved(alert) => println!("Resolved: {}", alert.message),
+                }
+                self.publish(&event);
+            }
+        }
+    }
+
+    fn check_gradient(&mut self, sensor_id: &str, temperature: Temperature, dt: f32) {
+        let Some((gradient, threshold)) = self.gradient.as_mut() else {
+            return;
+        };
+        let Some(rate) = gradient.update(temperature, dt) else {
+            return;
+        };
+        for event in self.alerts.check_gradient(sensor_id, rate, threshold, unix_now()) {
+            match &event {
+                AlertEvent::Fired(alert) => eprintln!("Alert: {}", alert.message),
+                AlertEvent::Resolved(_) => println!("Sensor {} is changing at {:.1}°C/min again", sensor_id, rate),
+                _ => {}
+            }
+            self.publish(&event);
+        }
+    }
+
+    fn publish(&self, event: &AlertEvent) {
+        if let Some((webhooks, _)) = &self.webhooks {
+            if event.should_notify() {
+                webhooks.send(WebhookPayload::Alert(AlertNotice::from_event(event)));
+            }
+        }
+    }
+
+    /// Posts the stats of the readings in `from..to`; a window without any is skipped.
+    fn publish_summary(&self, sensor_id: &str, from: u64, to: u64) {
+        let (Some((webhooks, _)), Some(stats)) = (&self.webhooks, self.store.window_stats(from, to)) else {
+            return;
+        };
+        let firing = self.alerts.active_alerts().len();
+        webhooks.send(WebhookPayload::Summary(Summary::new(sensor_id, from, to, &stats, firing)));
+    }
+}
+
+impl AsyncTemperatureMonitor {
+    /// Runs the sensor's self-test and publishes the result; a test that
+    /// cannot reach the sensor counts as failed.
+    async fn self_test<S: AsyncTemperatureSensor>(&mut self, sensor: &mut S) -> SensorHealth {
+        let health = match sensor.self_test().await {
+            Ok(health) => health,
+            Err(e) => {
+                if self.last_health != Some(SensorHealth::Failed) {
+                    let context = format!("Self-test of {} failed", sensor.sensor_id());
+                    error_hook::report(SwallowedKind::Sensor, &context, &format_args!("{:?}", e));
+                }
+                SensorHealth::Failed
+            }
+        };
+        if self.last_health != Some(health) {
+            println!("Sensor {} self-test: {}", sensor.sensor_id(), health);
+        }
+        self.last_health = Some(health);
+        if let Some(shared) = &self.health {
+            *error_hook::lock(shared, "Recovered the sensor health lock") = Some(health);
+        }
+        health
+    }
+}
+
+/// Replies to a command; the requester may have given up waiting.
+fn send_reply<T>(reply: oneshot::Sender<T>, value: T, what: &str) {
+    if reply.send(value).is_err() {
+        error_hook::report(SwallowedKind::ReplyDropped, &format!("Requester of {} went away", what), &"reply dropped");
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Periodically flushes a persistent store according to its flush policy.
+pub struct BackgroundFlusher {
+    stop_tx: Option<oneshot::Sender<()>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl BackgroundFlusher {
+    /// Checks the store's flush policy every `check_interval`. Interval
+    /// policies are timed on tokio's clock, so they follow `tokio::time::pause`.
+    pub fn spawn(store: TemperatureStore, check_interval: Duration) -> Self {
+        let (stop_tx, mut stop_rx) = oneshot::channel();
+        store.set_flush_clock(|| Instant::now().into_std());
+
+        let task = tokio::spawn(async move {
+            let mut ticker = interval(check_interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        flush_blocking(&store, |store| store.flush_if_due().map(|_| ()), "Background flush failed").await;
+                    }
+                    _ = &mut stop_rx => break,
+                }
+            }
+
+            flush_blocking(&store, TemperatureStore::flush, "Final flush failed").await;
+        });
+
+        Self {
+            stop_tx: Some(stop_tx),
+            task,
+        }
+    }
+
+    /// Stops the flusher after a last flush of everything pending.
+    pub async fn shutdown(mut self) {
+        if let Some(stop_tx) = self.stop_tx.take() {
+            let _ = stop_tx.send(());
+        }
+        if let Err(e) = (&mut self.task).await {
+            error_hook::report(SwallowedKind::TaskFailed, "Background flusher failed", &e);
+        }
+    }
+}
+
+/// Runs `flush` on the blocking pool: writing and syncing the log would
+/// otherwise hold up every task on the worker.
+async fn flush_blocking(store: &TemperatureStore, flush: fn(&TemperatureStore) -> std::io::Result<()>, context: &'static str) {
+    let store = store.clone_handle();
+    match tokio::task::spawn_blocking(move || flush(&store)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => error_hook::report(SwallowedKind::Io, context, &e),
+        Err(e) => error_hook::report(SwallowedKind::TaskFailed, context, &e),
+    }
+}
+
 #[derive(Clone)]
 pub struct MonitorHandle {
     command_tx: mpsc::Sender<MonitorCommand>,
@@ -166,6 +662,17 @@ impl MonitorHandle {
         Ok(rx.await?)
     }
 
+    pub async fn set_setpoint(&self, setpoint: Temperature) -> Result<(), mpsc::error::SendError<MonitorCommand>> {
+        self.command_tx.send(MonitorCommand::SetSetpoint(setpoint)).await
+    }
+
+    /// Alerts currently firing, such as the watchdog's "no data" alert.
+    pub async fn get_alerts(&self) -> Result<Vec<Alert>, Box<dyn std::error::Error + Send + Sync>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(MonitorCommand::GetAlerts(tx)).await?;
+        Ok(rx.await?)
+    }
+
     pub async fn stop(&self) -> Result<(), mpsc::error::SendError<MonitorCommand>> {
         self.command_tx.send(MonitorCommand::Stop).await
     }
@@ -185,12 +692,12 @@ mod tests {
         assert_eq!(sensor.sensor_id(), "test");
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn async_sensor_respects_delay() {
         let mut sensor = AsyncMockSensor::new("test".to_string(), 25.0)
             .with_delay(Duration::from_millis(200));
 
-        let start = std::time::Instant::now();
+        let start = Instant::now();
         let _reading = sensor.read_temperature().await.unwrap();
         let elapsed = start.elapsed();
 
@@ -210,7 +717,54 @@ mod tests {
         assert_eq!(reading.celsius, 25.0);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
+    async fn async_sensor_follows_its_failure_schedule() {
+        let clock = temp_core::clock::ManualClock::new(0);
+        let mut sensor = AsyncMockSensor::new("test".to_string(), 25.0)
+            .with_fifo_depth(2)
+            .with_failure_schedule(FailureSchedule::new().fail_every(2).garbage_reads(3..4, -127.0).offline_between(100..200))
+            .with_clock(clock.clone());
+
+        assert_eq!(sensor.read_temperature().await.unwrap().celsius, 25.0);
+        assert!(matches!(sensor.read_temperature().await, Err(AsyncSensorError::ReadFailed)));
+        let mut batch = [Temperature::new(0.0); 2];
+        assert_eq!(sensor.read_temperatures(&mut batch).await.unwrap(), 2);
+        assert_eq!(batch, [Temperature::new(-127.0); 2]);
+
+        clock.set(150);
+        assert!(matches!(sensor.read_temperature().await, Err(AsyncSensorError::ReadFailed)));
+        assert!(sensor.self_test().await.is_err());
+        clock.set(200);
+        assert_eq!(sensor.read_temperature().await.unwrap().celsius, 25.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn async_sensor_follows_its_noise_profile() {
+        let clock = temp_core::clock::ManualClock::new(temp_core::mock::DAY_SECONDS / 2);
+        let profile = NoiseProfile { daily_amplitude: 2.0, gaussian: 0.1, ..Default::default() };
+        let seed = temp_core::mock::test_seed();
+        let sensor = || {
+            AsyncMockSensor::new("noisy".to_string(), 20.0)
+                .with_delay(Duration::from_millis(1))
+                .with_fifo_depth(4)
+                .with_profile(profile, seed)
+                .with_clock(clock.clone())
+        };
+
+        let (mut first, mut second) = (sensor(), sensor());
+        let mut batch = [Temperature::new(0.0); 4];
+        first.read_temperatures(&mut batch).await.unwrap();
+        // Warmest at noon, with a different noise sample each
+        let replay = format!("{}={}", temp_core::mock::TEST_SEED_VAR, seed);
+        assert!(batch.iter().all(|t| (t.celsius - 22.0).abs() < 1.0), "{}", replay);
+        assert!(batch.windows(2).any(|pair| pair[0] != pair[1]), "{}", replay);
+
+        let mut again = [Temperature::new(0.0); 4];
+        second.read_temperatures(&mut again).await.unwrap();
+        assert_eq!(batch, again);
+    }
+
+    #[tokio::test(start_paused = true)]
     async fn monitor_handles_commands() {
         let mut monitor = AsyncTemperatureMonitor::new(10);
         let handle = monitor.get_handle();
@@ -247,7 +801,30 @@ mod tests {
         timeout(Duration::from_millis(500), monitor_task).await.unwrap().unwrap();
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
+    async fn monitor_stores_every_sample_of_a_batch() {
+        let mut sensor = AsyncMockSensor::new("fifo".to_string(), 20.0)
+            .with_delay(Duration::from_millis(10))
+            .with_fifo_depth(4);
+        let mut batch = [Temperature::new(0.0); 8];
+        assert_eq!(sensor.read_temperatures(&mut batch).await.unwrap(), 4);
+
+        let mut monitor = AsyncTemperatureMonitor::new(100);
+        let handle = monitor.get_handle();
+        let monitor_task = tokio::spawn(async move {
+            monitor.run(sensor, Duration::from_millis(100)).await;
+        });
+
+        // Three ticks, one read each
+        sleep(Duration::from_millis(250)).await;
+        let stats = handle.get_stats().await.unwrap().unwrap();
+        assert_eq!(stats.count, 12);
+
+        handle.stop().await.unwrap();
+        timeout(Duration::from_millis(500), monitor_task).await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
     async fn multiple_sensors_simulation() {
         // Simulate multiple sensors running concurrently
         let sensor1 = AsyncMockSensor::new("sensor1".to_string(), 20.0)
@@ -277,4 +854,285 @@ mod tests {
         r1.unwrap();
         r2.unwrap();
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn background_flusher_applies_interval_policy() {
+        let path = std::env::temp_dir().join(format!("temp_async_{}_flusher.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = TemperatureStore::open(10, &path, temp_store::FlushPolicy::Interval(Duration::from_secs(60))).unwrap();
+        let flusher = BackgroundFlusher::spawn(store.clone_handle(), Duration::from_secs(1));
+
+        store.add_reading(TemperatureReading::new(Temperature::new(21.0)));
+        assert_eq!(store.pending_writes(), 1);
+
+        sleep(Duration::from_secs(30)).await;
+        assert_eq!(store.pending_writes(), 1);
+
+        sleep(Duration::from_secs(32)).await;
+        assert_eq!(store.pending_writes(), 0);
+
+        store.add_reading(TemperatureReading::new(Temperature::new(22.0)));
+        flusher.shutdown().await;
+        assert_eq!(store.pending_writes(), 0);
+        assert_eq!(temp_store::FileBackend::load(&path).unwrap().len(), 2);
+
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn watchdog_raises_and_clears_no_data_alert() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        struct FlakySensor(Arc<AtomicBool>);
+
+        impl AsyncTemperatureSensor for FlakySensor {
+            type Error = AsyncSensorError;
+
+            async fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+                if self.0.load(Ordering::SeqCst) {
+                    Err(AsyncSensorError::Timeout)
+                } else {
+                    Ok(Temperature::new(21.0))
+                }
+            }
+
+            fn sensor_id(&self) -> &str {
+                "flaky"
+            }
+        }
+
+        let dead = Arc::new(AtomicBool::new(true));
+        let mut monitor = AsyncTemperatureMonitor::new(10).with_watchdog(3.0);
+        let handle = monitor.get_handle();
+        let sensor = FlakySensor(dead.clone());
+        let task = tokio::spawn(async move {
+            monitor.run(sensor, Duration::from_secs(10)).await;
+        });
+
+        // Paused time: a minute and a quarter pass instantly
+        sleep(Duration::from_secs(75)).await;
+        let alerts = handle.get_alerts().await.unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].key.kind, temp_alert::AlertKind::NoData);
+
+        dead.store(false, Ordering::SeqCst);
+        sleep(Duration::from_secs(30)).await;
+        assert!(handle.get_alerts().await.unwrap().is_empty());
+
+        handle.stop().await.unwrap();
+        timeout(Duration::from_secs(1), task).await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn watchdog_self_tests_sensors_that_go_quiet() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        /// Never has a new sample, like a sensor behind a deadband.
+        struct QuietSensor(Arc<AtomicBool>);
+
+        impl AsyncTemperatureSensor for QuietSensor {
+            type Error = AsyncSensorError;
+
+            async fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+                if self.0.load(Ordering::SeqCst) {
+                    Err(AsyncSensorError::Timeout)
+                } else {
+                    Ok(Temperature::new(21.0))
+                }
+            }
+
+            async fn read_temperatures(&mut self, _out: &mut [Temperature]) -> Result<usize, Self::Error> {
+                Ok(0)
+            }
+
+            fn sensor_id(&self) -> &str {
+                "quiet"
+            }
+        }
+
+        let dead = Arc::new(AtomicBool::new(false));
+        let health: SharedHealth = Arc::new(Mutex::new(None));
+        let mut monitor = AsyncTemperatureMonitor::new(10).with_watchdog(3.0).with_health(Arc::clone(&health));
+        let handle = monitor.get_handle();
+        let sensor = QuietSensor(dead.clone());
+        let task = tokio::spawn(async move {
+            monitor.run(sensor, Duration::from_secs(10)).await;
+        });
+
+        sleep(Duration::from_secs(75)).await;
+        assert!(handle.get_alerts().await.unwrap().is_empty());
+        assert_eq!(*health.lock().unwrap(), Some(SensorHealth::Ok));
+
+        dead.store(true, Ordering::SeqCst);
+        sleep(Duration::from_secs(20)).await;
+        let alerts = handle.get_alerts().await.unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].key.kind, temp_alert::AlertKind::NoData);
+        assert_eq!(*health.lock().unwrap(), Some(SensorHealth::Failed));
+
+        handle.stop().await.unwrap();
+        timeout(Duration::from_secs(1), task).await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn monitor_alerts_past_its_thresholds() {
+        let thresholds = ThresholdConfig::new(10.0, 30.0, 1.0, 2).unwrap();
+        let mut monitor = AsyncTemperatureMonitor::new(10).with_thresholds(thresholds);
+        let handle = monitor.get_handle();
+        let sensor = AsyncMockSensor::new("oven".to_string(), 36.0).with_delay(Duration::from_millis(10));
+        let task = tokio::spawn(async move {
+            monitor.run(sensor, Duration::from_secs(10)).await;
+        });
+
+        // Not yet two readings in a row
+        sleep(Duration::from_secs(5)).await;
+        assert!(handle.get_alerts().await.unwrap().is_empty());
+        sleep(Duration::from_secs(10)).await;
+        let alerts = handle.get_alerts().await.unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].key.kind, temp_alert::AlertKind::HighTemperature);
+        assert_eq!(alerts[0].severity, temp_alert::Severity::Critical);
+
+        handle.stop().await.unwrap();
+        timeout(Duration::from_secs(1), task).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn monitor_posts_alerts_and_summaries_to_webhooks() {
+        use temp_alert::Webhook;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (body_tx, mut bodies) = mpsc::channel(8);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                while !request.ends_with(b"}") {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await.unwrap();
+                let request = String::from_utf8(request).unwrap();
+                let _ = body_tx.send(request.split_once("\r\n\r\n").unwrap().1.to_string()).await;
+            }
+        });
+
+        let thresholds = ThresholdConfig::new(10.0, 30.0, 1.0, 1).unwrap();
+        let template = r#"{"type": "{{type}}", "sensor": "{{sensor_id}}"}"#;
+        let webhooks = WebhookSender::new(vec![Webhook::new(&url).with_template(template).with_summaries()]).unwrap();
+        let mut monitor = AsyncTemperatureMonitor::new(10)
+            .with_thresholds(thresholds)
+            .with_webhooks(webhooks, Duration::from_millis(200));
+        let handle = monitor.get_handle();
+        let sensor = AsyncMockSensor::new("oven".to_string(), 36.0);
+        let task = tokio::spawn(async move {
+            monitor.run(sensor, Duration::from_millis(20)).await;
+        });
+
+        let alert = timeout(Duration::from_secs(2), bodies.recv()).await.unwrap().unwrap();
+        assert_eq!(alert, r#"{"type": "alert", "sensor": "oven"}"#);
+        let summary = timeout(Duration::from_secs(2), bodies.recv()).await.unwrap().unwrap();
+        assert_eq!(summary, r#"{"type": "summary", "sensor": "oven"}"#);
+
+        handle.stop().await.unwrap();
+        timeout(Duration::from_secs(1), task).await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn monitor_alerts_on_fast_changes() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        /// A degree warmer every read while heating.
+        struct HeatingSensor(f32, Arc<AtomicBool>);
+
+        impl AsyncTemperatureSensor for HeatingSensor {
+            type Error = AsyncSensorError;
+
+            async fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+                if self.1.load(Ordering::SeqCst) {
+                    self.0 += 1.0;
+                }
+                Ok(Temperature::new(self.0))
+            }
+
+            fn sensor_id(&self) -> &str {
+                "kiln"
+            }
+        }
+
+        let heating = Arc::new(AtomicBool::new(true));
+        let threshold = GradientThreshold::rising(2.0).unwrap();
+        let mut monitor = AsyncTemperatureMonitor::new(10).with_gradient_alert(TemperatureGradient::new(), threshold);
+        let handle = monitor.get_handle();
+        let sensor = HeatingSensor(20.0, heating.clone());
+        let task = tokio::spawn(async move {
+            monitor.run(sensor, Duration::from_secs(10)).await;
+        });
+
+        // 6 °C/min
+        sleep(Duration::from_secs(35)).await;
+        let alerts = handle.get_alerts().await.unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].key.kind, temp_alert::AlertKind::RisingFast);
+        assert!((alerts[0].value - 6.0).abs() < 0.1, "{}", alerts[0].value);
+
+        heating.store(false, Ordering::SeqCst);
+        sleep(Duration::from_secs(20)).await;
+        assert!(handle.get_alerts().await.unwrap().is_empty());
+
+        handle.stop().await.unwrap();
+        timeout(Duration::from_secs(1), task).await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn monitor_drives_control_loop() {
+        use std::sync::{Arc, Mutex};
+        use temp_core::control::{BangBangController, ControlMode};
+
+        struct SharedActuator(Arc<Mutex<Vec<f32>>>);
+
+        impl Actuator for SharedActuator {
+            type Error = ();
+
+            fn set_output(&mut self, level: f32) -> Result<(), ()> {
+                self.0.lock().unwrap().push(level);
+                Ok(())
+            }
+
+            fn output(&self) -> f32 {
+                self.0.lock().unwrap().last().copied().unwrap_or(0.0)
+            }
+        }
+
+        let outputs = Arc::new(Mutex::new(Vec::new()));
+        let controller = BangBangController::new(Temperature::new(22.0), 0.5, ControlMode::Heating);
+        let mut monitor = AsyncTemperatureMonitor::new(10)
+            .with_control_loop(ControlLoop::new(controller, SharedActuator(Arc::clone(&outputs))));
+        let handle = monitor.get_handle();
+        let sensor = AsyncMockSensor::new("test".to_string(), 20.0)
+            .with_delay(Duration::from_millis(1));
+
+        let monitor_task = tokio::spawn(async move {
+            monitor.run(sensor, Duration::from_millis(20)).await;
+        });
+
+        sleep(Duration::from_millis(50)).await;
+        handle.set_setpoint(Temperature::new(18.0)).await.unwrap();
+        sleep(Duration::from_millis(50)).await;
+        handle.stop().await.unwrap();
+        timeout(Duration::from_millis(500), monitor_task).await.unwrap().unwrap();
+
+        let outputs = outputs.lock().unwrap();
+        // Heating while below 22 °C, off once the setpoint dropped to 18 °C
+        assert_eq!(outputs.first(), Some(&1.0));
+        assert_eq!(outputs.last(), Some(&0.0));
+    }
 }
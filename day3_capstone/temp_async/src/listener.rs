@@ -0,0 +1,1141 @@
+//! Protocol listeners serving one handler over several transports at once.
+//!
+//! Each [`ListenerConfig`] binds a socket per address, e.g. one for IPv4 and
+//! one for IPv6, optionally only on one network interface. TCP speaks the line protocol of
+//! [`serve_connection`](crate::transport::serve_connection) with a session
+//! per connection, over TLS if the listener has a [`TlsServerConfig`], where
+//! a client certificate authenticates the session. WebSocket carries the same
+//! JSON messages, one per text frame, for browsers and other clients that
+//! can only open one; it takes TLS the same way. UDP takes one JSON
+//! message per datagram and answers the sender, with a session per peer
+//! address that is forgotten once idle.
+//! HTTP takes readings from third-party systems, see [`http`](crate::http).
+//! Every listener shares the handler and the [`Sessions`] registry, and
+//! keeps its own [`TransportStats`]. [`Listeners::endpoints`] lists where
+//! clients can reach them, for discovery.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Deserializer, Serialize};
+use temp_store::error_hook::{self, SwallowedKind};
+use temp_protocol::homeassistant::HomeAssistantDiscovery;
+use temp_protocol::auth::{AuthError, Credentials};
+use temp_protocol::{Identity, Language, MessagePayload, ProtocolError, SessionState, TemperatureProtocolHandler};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::tls::{TlsAcceptor, TlsServerConfig};
+use crate::{http, transport};
+
+/// Largest UDP payload; larger responses are answered with a 413.
+const MAX_DATAGRAM: usize = 65_507;
+
+/// Pending connections the kernel queues per TCP socket, as `std` does.
+const TCP_BACKLOG: i32 = 128;
+
+/// A TLS client that hasn't finished its handshake by then is dropped.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportKind {
+    Tcp,
+    Ws,
+    Udp,
+    Http,
+}
+
+impl TransportKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransportKind::Tcp => "tcp",
+            TransportKind::Ws => "ws",
+            TransportKind::Udp => "udp",
+            TransportKind::Http => "http",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "transport", rename_all = "snake_case")]
+pub enum ListenerConfig {
+    Tcp {
+        /// One address or several, e.g. `["0.0.0.0:7878", "[::]:7878"]`.
+        #[serde(alias = "addr", deserialize_with = "one_or_many")]
+        addrs: Vec<SocketAddr>,
+        /// Binds the unspecified addresses on this network interface only.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interface: Option<String>,
+        /// Connections beyond this are closed right away; unlimited if `None`.
+        #[serde(default)]
+        max_connections: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tls: Option<TlsServerConfig>,
+    },
+    /// The messages of `Tcp`, one per WebSocket text frame, on any path.
+    Ws {
+        #[serde(alias = "addr", deserialize_with = "one_or_many")]
+        addrs: Vec<SocketAddr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interface: Option<String>,
+        #[serde(default)]
+        max_connections: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tls: Option<TlsServerConfig>,
+    },
+    Udp {
+        #[serde(alias = "addr", deserialize_with = "one_or_many")]
+        addrs: Vec<SocketAddr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interface: Option<String>,
+        /// A peer's session, and the language and identity it negotiated,
+        /// is forgotten after this long without a datagram.
+        #[serde(default = "default_session_idle_seconds")]
+        session_idle_seconds: u64,
+    },
+    /// Ingestion of readings with `POST /readings`.
+    Http {
+        #[serde(alias = "addr", deserialize_with = "one_or_many")]
+        addrs: Vec<SocketAddr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interface: Option<String>,
+        #[serde(default)]
+        max_connections: Option<usize>,
+    },
+}
+
+fn default_session_idle_seconds() -> u64 {
+    300
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<SocketAddr>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(SocketAddr),
+        Many(Vec<SocketAddr>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(addr) => vec![addr],
+        OneOrMany::Many(addrs) => addrs,
+    })
+}
+
+impl ListenerConfig {
+    pub fn tcp(addr: SocketAddr) -> Self {
+        ListenerConfig::Tcp {
+            addrs: vec![addr],
+            interface: None,
+            max_connections: None,
+            tls: None,
+        }
+    }
+
+    pub fn ws(addr: SocketAddr) -> Self {
+        ListenerConfig::Ws {
+            addrs: vec![addr],
+            interface: None,
+            max_connections: None,
+            tls: None,
+        }
+    }
+
+    pub fn udp(addr: SocketAddr) -> Self {
+        ListenerConfig::Udp {
+            addrs: vec![addr],
+            interface: None,
+            session_idle_seconds: default_session_idle_seconds(),
+        }
+    }
+
+    pub fn http(addr: SocketAddr) -> Self {
+        ListenerConfig::Http {
+            addrs: vec![addr],
+            interface: None,
+            max_connections: None,
+        }
+    }
+
+    /// Also binds `addr`, e.g. `[::]:7878` next to `0.0.0.0:7878`.
+    pub fn also_on(mut self, addr: SocketAddr) -> Self {
+        match &mut self {
+            ListenerConfig::Tcp { addrs, .. }
+            | ListenerConfig::Ws { addrs, .. }
+            | ListenerConfig::Udp { addrs, .. }
+            | ListenerConfig::Http { addrs, .. } => addrs.push(addr),
+        }
+        self
+    }
+
+    /// Binds the unspecified addresses on the addresses of `interface`, e.g.
+    /// `eth0`, instead of on every interface.
+    pub fn on_interface(mut self, name: impl Into<String>) -> Self {
+        match &mut self {
+            ListenerConfig::Tcp { interface, .. }
+            | ListenerConfig::Ws { interface, .. }
+            | ListenerConfig::Udp { interface, .. }
+            | ListenerConfig::Http { interface, .. } => {
+                *interface = Some(name.into());
+            }
+        }
+        self
+    }
+
+    /// Serves TCP or WebSocket over TLS; other transports ignore it.
+    pub fn with_tls(mut self, config: TlsServerConfig) -> Self {
+        if let ListenerConfig::Tcp { tls, .. } | ListenerConfig::Ws { tls, .. } = &mut self {
+            *tls = Some(config);
+        }
+        self
+    }
+
+    pub fn addrs(&self) -> &[SocketAddr] {
+        match self {
+            ListenerConfig::Tcp { addrs, .. }
+            | ListenerConfig::Ws { addrs, .. }
+            | ListenerConfig::Udp { addrs, .. }
+            | ListenerConfig::Http { addrs, .. } => addrs,
+        }
+    }
+
+    pub fn interface(&self) -> Option<&str> {
+        match self {
+            ListenerConfig::Tcp { interface, .. }
+            | ListenerConfig::Ws { interface, .. }
+            | ListenerConfig::Udp { interface, .. }
+            | ListenerConfig::Http { interface, .. } => interface.as_deref(),
+        }
+    }
+
+    pub fn max_connections(&self) -> Option<usize> {
+        match self {
+            ListenerConfig::Tcp { max_connections, .. }
+            | ListenerConfig::Ws { max_connections, .. }
+            | ListenerConfig::Http { max_connections, .. } => *max_connections,
+            ListenerConfig::Udp { .. } => None,
+        }
+    }
+
+    pub fn tls(&self) -> Option<&TlsServerConfig> {
+        match self {
+            ListenerConfig::Tcp { tls, .. } | ListenerConfig::Ws { tls, .. } => tls.as_ref(),
+            ListenerConfig::Udp { .. } | ListenerConfig::Http { .. } => None,
+        }
+    }
+
+    pub fn transport(&self) -> TransportKind {
+        match self {
+            ListenerConfig::Tcp { .. } => TransportKind::Tcp,
+            ListenerConfig::Ws { .. } => TransportKind::Ws,
+            ListenerConfig::Udp { .. } => TransportKind::Udp,
+            ListenerConfig::Http { .. } => TransportKind::Http,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionKey {
+    pub transport: TransportKind,
+    /// Address of the listener the session came in on.
+    pub local_addr: SocketAddr,
+    pub peer: SocketAddr,
+}
+
+struct Session {
+    state: SessionState,
+    last_seen: Instant,
+}
+
+/// The sessions open on every listener, with the language and identity
+/// each negotiated.
+#[derive(Default)]
+pub struct Sessions {
+    sessions: Mutex<HashMap<SessionKey, Session>>,
+}
+
+impl Sessions {
+    fn lock(&self) -> MutexGuard<'_, HashMap<SessionKey, Session>> {
+        error_hook::lock(&self.sessions, "Recovered the sessions lock")
+    }
+
+    /// Opens the session if it is new and returns its state and whether it was new.
+    fn touch(&self, key: SessionKey) -> (SessionState, bool) {
+        let mut sessions = self.lock();
+        let now = Instant::now();
+        match sessions.get_mut(&key) {
+            Some(session) => {
+                session.last_seen = now;
+                (session.state.clone(), false)
+            }
+            None => {
+                sessions.insert(key, Session { state: SessionState::default(), last_seen: now });
+                (SessionState::default(), true)
+            }
+        }
+    }
+
+    fn update(&self, key: &SessionKey, state: SessionState) {
+        if let Some(session) = self.lock().get_mut(key) {
+            session.state = state;
+        }
+    }
+
+    fn close(&self, key: &SessionKey) {
+        self.lock().remove(key);
+    }
+
+    /// Closes the sessions of the listener at `local_addr` idle for longer than `idle`.
+    fn expire(&self, local_addr: SocketAddr, idle: Duration) {
+        let now = Instant::now();
+        self.lock()
+            .retain(|key, session| key.local_addr != local_addr || now.duration_since(session.last_seen) <= idle);
+    }
+
+    fn count(&self, local_addr: SocketAddr) -> usize {
+        self.lock().keys().filter(|key| key.local_addr == local_addr).count()
+    }
+
+    pub fn language(&self, key: &SessionKey) -> Option<Language> {
+        self.lock().get(key).map(|session| session.state.language)
+    }
+
+    /// Who the session authenticated as, if it did.
+    pub fn identity(&self, key: &SessionKey) -> Option<Identity> {
+        self.lock().get(key).and_then(|session| session.state.identity.clone())
+    }
+
+    pub fn keys(&self) -> Vec<SessionKey> {
+        self.lock().keys().copied().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    sessions_opened: AtomicU64,
+    messages: AtomicU64,
+    malformed: AtomicU64,
+    rejected: AtomicU64,
+}
+
+fn bump(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransportStats {
+    pub transport: TransportKind,
+    pub local_addr: SocketAddr,
+    pub sessions_opened: u64,
+    pub active_sessions: usize,
+    pub messages: u64,
+    /// Messages that were not a valid protocol message.
+    pub malformed: u64,
+    /// Connections over the limit and responses too large to send.
+    pub rejected: u64,
+}
+
+/// Where a client can reach a listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub transport: TransportKind,
+    pub addr: SocketAddr,
+}
+
+enum Socket {
+    Tcp(std::net::TcpListener),
+    Udp(std::net::UdpSocket),
+}
+
+impl Socket {
+    fn bind(transport: TransportKind, addr: SocketAddr) -> io::Result<Self> {
+        let kind = match transport {
+            TransportKind::Tcp | TransportKind::Ws | TransportKind::Http => socket2::Type::STREAM,
+            TransportKind::Udp => socket2::Type::DGRAM,
+        };
+        let socket = socket2::Socket::new(socket2::Domain::for_address(addr), kind, None)?;
+        // Otherwise `[::]` takes the IPv4 port too and `0.0.0.0` on the same port fails
+        if addr.is_ipv6() {
+            socket.set_only_v6(true)?;
+        }
+        #[cfg(unix)]
+        if kind == socket2::Type::STREAM {
+            socket.set_reuse_address(true)?;
+        }
+        socket.bind(&addr.into())?;
+        socket.set_nonblocking(true)?;
+        Ok(match transport {
+            TransportKind::Tcp | TransportKind::Ws | TransportKind::Http => {
+                socket.listen(TCP_BACKLOG)?;
+                Socket::Tcp(socket.into())
+            }
+            TransportKind::Udp => Socket::Udp(socket.into()),
+        })
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Socket::Tcp(socket) => socket.local_addr(),
+            Socket::Udp(socket) => socket.local_addr(),
+        }
+    }
+}
+
+/// The addresses `config` binds: its own, with the unspecified ones replaced
+/// by those of its interface in the same family, if it names one.
+fn bind_addrs(config: &ListenerConfig) -> io::Result<Vec<SocketAddr>> {
+    let Some(name) = config.interface() else {
+        return Ok(config.addrs().to_vec());
+    };
+    let interfaces: Vec<_> = if_addrs::get_if_addrs()?.into_iter().filter(|i| i.name == name).collect();
+    if interfaces.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("No network interface {}", name)));
+    }
+    let mut addrs = Vec::new();
+    for &addr in config.addrs() {
+        if !addr.ip().is_unspecified() {
+            addrs.push(addr);
+            continue;
+        }
+        addrs.extend(
+            interfaces
+                .iter()
+                .filter(|i| i.ip().is_ipv4() == addr.is_ipv4())
+                .map(|i| interface_addr(i, addr.port())),
+        );
+    }
+    if addrs.is_empty() {
+        let message = format!("Network interface {} has no address of the listener's families", name);
+        return Err(io::Error::new(io::ErrorKind::AddrNotAvailable, message));
+    }
+    Ok(addrs)
+}
+
+fn interface_addr(interface: &if_addrs::Interface, port: u16) -> SocketAddr {
+    match interface.ip() {
+        IpAddr::V4(ip) => SocketAddr::new(ip.into(), port),
+        // A link-local address is ambiguous without its interface
+        IpAddr::V6(ip) => {
+            let scope_id = if interface.is_link_local() { interface.index.unwrap_or(0) } else { 0 };
+            SocketAddrV6::new(ip, port, 0, scope_id).into()
+        }
+    }
+}
+
+struct Listener {
+    config: ListenerConfig,
+    local_addr: SocketAddr,
+    socket: Option<Socket>,
+    tls: Option<TlsAcceptor>,
+    counters: Arc<Counters>,
+    task: Option<JoinHandle<()>>,
+}
+
+/// Bound listeners; they answer once [`serve`](Self::serve) is called and
+/// stop when shut down or dropped.
+pub struct Listeners {
+    listeners: Vec<Listener>,
+    sessions: Arc<Sessions>,
+}
+
+impl Listeners {
+    /// Binds every address of every listener, so a port already in use or
+    /// an unreadable TLS certificate fails before anything is served. Port 0 picks a free port, shared by
+    /// all addresses of the same listener; see [`local_addrs`](Self::local_addrs).
+    pub fn bind(configs: &[ListenerConfig]) -> io::Result<Self> {
+        let mut listeners = Vec::with_capacity(configs.len());
+        for config in configs {
+            let tls = config.tls().map(TlsServerConfig::acceptor).transpose()?;
+            let mut picked_port = None;
+            for mut addr in bind_addrs(config)? {
+                let ephemeral = addr.port() == 0;
+                if let (true, Some(port)) = (ephemeral, picked_port) {
+                    addr.set_port(port);
+                }
+                let socket = Socket::bind(config.transport(), addr)?;
+                let local_addr = socket.local_addr()?;
+                if ephemeral {
+                    picked_port = Some(local_addr.port());
+                }
+                listeners.push(Listener {
+                    config: config.clone(),
+                    local_addr,
+                    socket: Some(socket),
+                    tls: tls.clone(),
+                    counters: Arc::new(Counters::default()),
+                    task: None,
+                });
+            }
+        }
+        Ok(Self {
+            listeners,
+            sessions: Arc::new(Sessions::default()),
+        })
+    }
+
+    /// Starts answering on every listener. Must be called inside a tokio runtime.
+    pub fn serve(&mut self, handler: Arc<Mutex<TemperatureProtocolHandler>>) -> io::Result<()> {
+        for listener in &mut self.listeners {
+            let Some(socket) = listener.socket.take() else {
+                continue;
+            };
+            let local_addr = listener.local_addr;
+            let handler = Arc::clone(&handler);
+            let sessions = Arc::clone(&self.sessions);
+            let counters = Arc::clone(&listener.counters);
+            let task = match (socket, &listener.config) {
+                (Socket::Tcp(socket), ListenerConfig::Tcp { .. } | ListenerConfig::Ws { .. } | ListenerConfig::Http { .. }) => {
+                    let socket = TcpListener::from_std(socket)?;
+                    let config = listener.config.clone();
+                    let tls = listener.tls.clone();
+                    tokio::spawn(accept_tcp(socket, config, local_addr, tls, handler, sessions, counters))
+                }
+                (Socket::Udp(socket), &ListenerConfig::Udp { session_idle_seconds, .. }) => {
+                    let socket = UdpSocket::from_std(socket)?;
+                    let idle = Duration::from_secs(session_idle_seconds);
+                    tokio::spawn(serve_udp(socket, local_addr, idle, handler, sessions, counters))
+                }
+                _ => unreachable!("sockets are bound from their own config"),
+            };
+            listener.task = Some(task);
+        }
+        Ok(())
+    }
+
+    /// Address of the first listener for `transport`.
+    pub fn local_addr(&self, transport: TransportKind) -> Option<SocketAddr> {
+        self.local_addrs(transport).into_iter().next()
+    }
+
+    /// Every address bound for `transport`, in the order they were configured.
+    pub fn local_addrs(&self, transport: TransportKind) -> Vec<SocketAddr> {
+        self.listeners
+            .iter()
+            .filter(|l| l.config.transport() == transport)
+            .map(|l| l.local_addr)
+            .collect()
+    }
+
+    /// Where clients can reach the listeners. One bound to an unspecified
+    /// address is listed at each of the host's addresses in that family,
+    /// leaving out loopback.
+    pub fn endpoints(&self) -> io::Result<Vec<Endpoint>> {
+        let mut host_addrs = None;
+        let mut endpoints = Vec::new();
+        for listener in &self.listeners {
+            let transport = listener.config.transport();
+            let local_addr = listener.local_addr;
+            if !local_addr.ip().is_unspecified() {
+                endpoints.push(Endpoint { transport, addr: local_addr });
+                continue;
+            }
+            let interfaces = match &mut host_addrs {
+                Some(interfaces) => interfaces,
+                None => host_addrs.insert(if_addrs::get_if_addrs()?),
+            };
+            endpoints.extend(
+                interfaces
+                    .iter()
+                    .filter(|i| !i.is_loopback() && i.ip().is_ipv4() == local_addr.is_ipv4())
+                    .map(|i| Endpoint { transport, addr: interface_addr(i, local_addr.port()) }),
+            );
+        }
+        Ok(endpoints)
+    }
+
+    /// Adds every endpoint to the device of `discovery`'s config messages.
+    pub fn advertise(&self, discovery: HomeAssistantDiscovery) -> io::Result<HomeAssistantDiscovery> {
+        let endpoints = self.endpoints()?;
+        Ok(endpoints
+            .into_iter()
+            .fold(discovery, |discovery, endpoint| discovery.with_endpoint(endpoint.transport.as_str(), endpoint.addr)))
+    }
+
+    pub fn sessions(&self) -> &Sessions {
+        &self.sessions
+    }
+
+    /// Counters of every listener, in the order they were configured.
+    pub fn stats(&self) -> Vec<TransportStats> {
+        self.listeners
+            .iter()
+            .map(|l| TransportStats {
+                transport: l.config.transport(),
+                local_addr: l.local_addr,
+                sessions_opened: l.counters.sessions_opened.load(Ordering::Relaxed),
+                active_sessions: self.sessions.count(l.local_addr),
+                messages: l.counters.messages.load(Ordering::Relaxed),
+                malformed: l.counters.malformed.load(Ordering::Relaxed),
+                rejected: l.counters.rejected.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Stops listening and closes open TCP connections.
+    pub fn shutdown(&mut self) {
+        for listener in &mut self.listeners {
+            if let Some(task) = listener.task.take() {
+                task.abort();
+            }
+            self.sessions.expire(listener.local_addr, Duration::ZERO);
+        }
+    }
+}
+
+impl Drop for Listeners {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+async fn accept_tcp(
+    listener: TcpListener,
+    config: ListenerConfig,
+    local_addr: SocketAddr,
+    tls: Option<TlsAcceptor>,
+    handler: Arc<Mutex<TemperatureProtocolHandler>>,
+    sessions: Arc<Sessions>,
+    counters: Arc<Counters>,
+) {
+    let (transport, max_connections) = (config.transport(), config.max_connections());
+    // Dropped with this task, which closes every connection
+    let mut connections = JoinSet::new();
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+        };
+        let (stream, peer) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                let context = format!("Failed to accept a connection on {}", local_addr);
+                error_hook::report(SwallowedKind::Io, &context, &e);
+                // Typically out of file descriptors; give connections time to close
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        if max_connections.is_some_and(|max| sessions.count(local_addr) >= max) {
+            bump(&counters.rejected);
+            continue;
+        }
+
+        let key = SessionKey { transport, local_addr, peer };
+        sessions.touch(key);
+        bump(&counters.sessions_opened);
+        let handler = Arc::clone(&handler);
+        let sessions = Arc::clone(&sessions);
+        let counters = Arc::clone(&counters);
+        let tls = tls.clone();
+        connections.spawn(async move {
+            let served = match (transport, tls) {
+                (TransportKind::Http, _) => serve_http(stream, peer, &handler, &counters).await,
+                (_, Some(tls)) => match accept_tls(stream, &tls, key, &handler, &sessions).await {
+                    Ok(stream) => serve_stream(stream, key, &handler, &sessions, &counters).await,
+                    Err(e) => Err(e),
+                },
+                (_, None) => serve_stream(stream, key, &handler, &sessions, &counters).await,
+            };
+            if let Err(e) = served {
+                let context = format!("Connection from {} failed", peer);
+                error_hook::report(SwallowedKind::Protocol, &context, &e);
+            }
+            sessions.close(&key);
+        });
+    }
+}
+
+/// Completes the TLS handshake, authenticating the session by the client's
+/// certificate if it presented one the handler's auth provider knows.
+async fn accept_tls(
+    stream: TcpStream,
+    tls: &TlsAcceptor,
+    key: SessionKey,
+    handler: &Mutex<TemperatureProtocolHandler>,
+    sessions: &Sessions,
+) -> io::Result<tokio_rustls::server::TlsStream<TcpStream>> {
+    let stream = tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, tls.accept(stream))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))??;
+    if let Some(certificate) = stream.get_ref().1.peer_certificates().and_then(|certs| certs.first()) {
+        let credentials = Credentials::ClientCertificate(certificate.to_vec());
+        let authenticated = error_hook::lock(handler, "Recovered the protocol handler lock").authenticate(&credentials);
+        match authenticated {
+            Ok(identity) => {
+                let (mut state, _) = sessions.touch(key);
+                state.identity = Some(identity);
+                sessions.update(&key, state);
+            }
+            // Left to authenticate with a token
+            Err(AuthError::Unsupported) => {}
+            Err(e) => {
+                let message = format!("Client certificate rejected: {}", e);
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, message));
+            }
+        }
+    }
+    Ok(stream)
+}
+
+/// Serves a TCP or WebSocket connection, plain or TLS, by its session's transport.
+async fn serve_stream<S>(
+    stream: S,
+    key: SessionKey,
+    handler: &Mutex<TemperatureProtocolHandler>,
+    sessions: &Sessions,
+    counters: &Counters,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match key.transport {
+        TransportKind::Ws => serve_ws(stream, key, handler, sessions, counters).await,
+        _ => serve_tcp(stream, key, handler, sessions, counters).await,
+    }
+}
+
+async fn serve_tcp<S>(
+    stream: S,
+    key: SessionKey,
+    handler: &Mutex<TemperatureProtocolHandler>,
+    sessions: &Sessions,
+    counters: &Counters,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    let (read_half, mut write_half) = tokio::io::split(stream);
+    let mut lines = BufReader::new(read_half).lines();
+    let buffers = error_hook::lock(handler, "Recovered the protocol handler lock").buffers();
+    while let Some(line) = lines.next_line().await? {
+        bump(&counters.messages);
+        let (mut state, _) = sessions.touch(key);
+        let Some(response) = transport::answer(handler, &line, &mut state) else {
+            bump(&counters.malformed);
+            continue;
+        };
+        sessions.update(&key, state);
+        transport::write_line(&mut write_half, &buffers, response).await?;
+    }
+    Ok(())
+}
+
+async fn serve_ws<S>(
+    stream: S,
+    key: SessionKey,
+    handler: &Mutex<TemperatureProtocolHandler>,
+    sessions: &Sessions,
+    counters: &Counters,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut socket = tokio_tungstenite::accept_async(stream).await.map_err(io::Error::other)?;
+    let buffers = error_hook::lock(handler, "Recovered the protocol handler lock").buffers();
+    // Pings are answered while the socket is read
+    while let Some(frame) = socket.next().await {
+        let text = match frame.map_err(io::Error::other)? {
+            Message::Text(text) => text,
+            Message::Binary(_) => {
+                bump(&counters.messages);
+                bump(&counters.malformed);
+                continue;
+            }
+            Message::Close(_) => break,
+            Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => continue,
+        };
+        bump(&counters.messages);
+        let (mut state, _) = sessions.touch(key);
+        let Some(response) = transport::answer(handler, &text, &mut state) else {
+            bump(&counters.malformed);
+            continue;
+        };
+        sessions.update(&key, state);
+        let encoded = buffers.encode_json(&response).map_err(io::Error::other)?;
+        let text = std::str::from_utf8(&encoded).map_err(io::Error::other)?.to_owned();
+        socket.send(Message::Text(text)).await.map_err(io::Error::other)?;
+        buffers.recycle(response);
+    }
+    Ok(())
+}
+
+async fn serve_http(
+    stream: TcpStream,
+    peer: SocketAddr,
+    handler: &Mutex<TemperatureProtocolHandler>,
+    counters: &Counters,
+) -> io::Result<()> {
+    bump(&counters.messages);
+    if !http::serve_connection(stream, peer, handler).await? {
+        bump(&counters.malformed);
+    }
+    Ok(())
+}
+
+async fn serve_udp(
+    socket: UdpSocket,
+    local_addr: SocketAddr,
+    idle: Duration,
+    handler: Arc<Mutex<TemperatureProtocolHandler>>,
+    sessions: Arc<Sessions>,
+    counters: Arc<Counters>,
+) {
+    let mut buffer = vec![0; MAX_DATAGRAM];
+    let buffers = error_hook::lock(&handler, "Recovered the protocol handler lock").buffers();
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(e) => {
+                let context = format!("Failed to receive on {}", local_addr);
+                error_hook::report(SwallowedKind::Io, &context, &e);
+                continue;
+            }
+        };
+        sessions.expire(local_addr, idle);
+        let key = SessionKey { transport: TransportKind::Udp, local_addr, peer };
+        let (mut state, opened) = sessions.touch(key);
+        if opened {
+            bump(&counters.sessions_opened);
+        }
+        bump(&counters.messages);
+
+        let response = std::str::from_utf8(&buffer[..len])
+            .ok()
+            .and_then(|datagram| transport::answer(&handler, datagram.trim_end(), &mut state));
+        let Some(mut response) = response else {
+            bump(&counters.malformed);
+            continue;
+        };
+        sessions.update(&key, state);
+
+        let mut datagram = match buffers.encode_json(&response) {
+            Ok(datagram) => datagram,
+            Err(e) => {
+                let context = format!("Failed to serialize a response to {}", peer);
+                error_hook::report(SwallowedKind::Protocol, &context, &e);
+                continue;
+            }
+        };
+        if datagram.len() > MAX_DATAGRAM {
+            bump(&counters.rejected);
+            let error = ProtocolError::SystemError {
+                code: 413,
+                details: "Response too large for a datagram, use TCP".to_string(),
+            };
+            response.payload = MessagePayload::Response(error.to_response());
+            datagram.clear();
+            serde_json::to_writer(&mut *datagram, &response).unwrap_or_default();
+        }
+        if let Err(e) = socket.send_to(&datagram, peer).await {
+            let context = format!("Failed to answer {}", peer);
+            error_hook::report(SwallowedKind::Io, &context, &e);
+        }
+        buffers.recycle(response);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::ProtocolClient;
+    use temp_protocol::{Command, ProtocolMessage, Response};
+
+    fn any_port() -> SocketAddr {
+        "127.0.0.1:0".parse().unwrap()
+    }
+
+    async fn udp_request(socket: &UdpSocket, payload: &[u8]) -> ProtocolMessage {
+        socket.send(payload).await.unwrap();
+        let mut buffer = vec![0; MAX_DATAGRAM];
+        let len = socket.recv(&mut buffer).await.unwrap();
+        serde_json::from_slice(&buffer[..len]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_tcp_and_udp_from_one_handler() {
+        let handler = Arc::new(Mutex::new(TemperatureProtocolHandler::new()));
+        let mut listeners = Listeners::bind(&[
+            ListenerConfig::Tcp {
+                addrs: vec![any_port()],
+                interface: None,
+                max_connections: Some(1),
+                tls: None,
+            },
+            ListenerConfig::udp(any_port()),
+        ])
+        .unwrap();
+        listeners.serve(Arc::clone(&handler)).unwrap();
+
+        let tcp_addr = listeners.local_addr(TransportKind::Tcp).unwrap();
+        let client = ProtocolClient::new(TcpStream::connect(tcp_addr).await.unwrap());
+        let reading = client.request(Command::GetReading { sensor_id: "temp_01".parse().unwrap() }).await.unwrap();
+        assert!(matches!(reading, Response::Reading { .. }));
+
+        // The reading taken over TCP is visible over UDP, in the peer's language
+        let udp = UdpSocket::bind(any_port()).await.unwrap();
+        udp.connect(listeners.local_addr(TransportKind::Udp).unwrap()).await.unwrap();
+        let (set_language, get_stats, unknown) = {
+            let mut handler = handler.lock().unwrap();
+            (
+                handler.create_command(Command::SetLanguage { preferences: "fr".to_string() }),
+                handler.create_command(Command::GetStats { sensor_id: "temp_01".parse().unwrap() }),
+                handler.create_command(Command::GetStats { sensor_id: "nope".parse().unwrap() }),
+            )
+        };
+        udp_request(&udp, &serde_json::to_vec(&set_language).unwrap()).await;
+        let stats = udp_request(&udp, &serde_json::to_vec(&get_stats).unwrap()).await;
+        assert!(matches!(stats.payload, MessagePayload::Response(Response::Stats { stats, .. }) if stats.count == 1));
+        let error = udp_request(&udp, &serde_json::to_vec(&unknown).unwrap()).await;
+        assert!(
+            matches!(error.payload, MessagePayload::Response(Response::Error { message, .. }) if message.contains("introuvable"))
+        );
+
+        // A second TCP connection is over the limit and closed right away
+        let mut second = TcpStream::connect(tcp_addr).await.unwrap();
+        let mut buffer = [0; 1];
+        assert_eq!(tokio::io::AsyncReadExt::read(&mut second, &mut buffer).await.unwrap(), 0);
+
+        let stats = listeners.stats();
+        assert_eq!((stats[0].transport, stats[0].active_sessions, stats[0].rejected), (TransportKind::Tcp, 1, 1));
+        assert_eq!((stats[1].sessions_opened, stats[1].messages), (1, 3));
+        assert_eq!(listeners.sessions().len(), 2);
+
+        listeners.shutdown();
+        assert!(listeners.sessions().is_empty());
+        assert!(client.request(Command::GetStatus).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn serves_websocket_from_the_same_handler() {
+        let handler = Arc::new(Mutex::new(TemperatureProtocolHandler::new()));
+        let mut listeners = Listeners::bind(&[ListenerConfig::tcp(any_port()), ListenerConfig::ws(any_port())]).unwrap();
+        listeners.serve(Arc::clone(&handler)).unwrap();
+
+        let tcp_addr = listeners.local_addr(TransportKind::Tcp).unwrap();
+        let client = ProtocolClient::new(TcpStream::connect(tcp_addr).await.unwrap());
+        client.request(Command::GetReading { sensor_id: "temp_01".parse().unwrap() }).await.unwrap();
+
+        let ws_addr = listeners.local_addr(TransportKind::Ws).unwrap();
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/", ws_addr)).await.unwrap();
+        let (set_language, get_stats) = {
+            let mut handler = handler.lock().unwrap();
+            (
+                handler.create_command(Command::SetLanguage { preferences: "fr".to_string() }),
+                handler.create_command(Command::GetStats { sensor_id: "nope".parse().unwrap() }),
+            )
+        };
+        let request = |message: &ProtocolMessage| Message::Text(serde_json::to_string(message).unwrap());
+        socket.send(request(&set_language)).await.unwrap();
+        socket.next().await.unwrap().unwrap();
+        socket.send(Message::Text("not json".to_string())).await.unwrap();
+        socket.send(request(&get_stats)).await.unwrap();
+        let Message::Text(text) = socket.next().await.unwrap().unwrap() else {
+            panic!("Expected a text frame");
+        };
+        // The unknown sensor is reported in the language this session chose
+        let error: ProtocolMessage = serde_json::from_str(&text).unwrap();
+        assert!(
+            matches!(error.payload, MessagePayload::Response(Response::Error { message, .. }) if message.contains("introuvable"))
+        );
+
+        let ws_stats = listeners.stats()[1];
+        assert_eq!(ws_stats.transport, TransportKind::Ws);
+        assert_eq!((ws_stats.sessions_opened, ws_stats.active_sessions), (1, 1));
+        assert_eq!((ws_stats.messages, ws_stats.malformed), (3, 1));
+        let ws_session = listeners.sessions().keys().into_iter().find(|key| key.transport == TransportKind::Ws).unwrap();
+        assert_eq!(listeners.sessions().language(&ws_session), Some(Language::French));
+
+        socket.close(None).await.unwrap();
+        listeners.shutdown();
+    }
+
+    #[test]
+    fn parses_listener_configs() {
+        let toml = r#"
+            [[listeners]]
+            transport = "tcp"
+            addr = "0.0.0.0:7878"
+            max_connections = 64
+
+            [[listeners]]
+            transport = "udp"
+            addr = "0.0.0.0:7879"
+
+            [[listeners]]
+            transport = "udp"
+            addrs = ["0.0.0.0:7880", "[::]:7880"]
+            interface = "eth0"
+
+            [[listeners]]
+            transport = "tcp"
+            addr = "0.0.0.0:7443"
+            tls = { cert = "/etc/temp/server.pem", key = "/etc/temp/server.key" }
+
+            [[listeners]]
+            transport = "ws"
+            addr = "0.0.0.0:8080"
+        "#;
+        #[derive(Deserialize)]
+        struct File {
+            listeners: Vec<ListenerConfig>,
+        }
+        let file: File = toml::from_str(toml).unwrap();
+        assert_eq!(file.listeners[0].transport(), TransportKind::Tcp);
+        assert_eq!(file.listeners[1], ListenerConfig::udp("0.0.0.0:7879".parse().unwrap()));
+        let dual_stack = ListenerConfig::udp("0.0.0.0:7880".parse().unwrap())
+            .also_on("[::]:7880".parse().unwrap())
+            .on_interface("eth0");
+        assert_eq!(file.listeners[2], dual_stack);
+        let tls = crate::tls::TlsServerConfig::new("/etc/temp/server.pem", "/etc/temp/server.key");
+        assert_eq!(file.listeners[3], ListenerConfig::tcp("0.0.0.0:7443".parse().unwrap()).with_tls(tls));
+        assert_eq!(file.listeners[4], ListenerConfig::ws("0.0.0.0:8080".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn binds_ipv4_and_ipv6_on_one_ephemeral_port() {
+        let handler = Arc::new(Mutex::new(TemperatureProtocolHandler::new()));
+        let config = ListenerConfig::tcp(any_port()).also_on("[::1]:0".parse().unwrap());
+        let mut listeners = Listeners::bind(&[config]).unwrap();
+        listeners.serve(handler).unwrap();
+
+        let addrs = listeners.local_addrs(TransportKind::Tcp);
+        assert_eq!(addrs.len(), 2);
+        assert!(addrs[0].is_ipv4() && addrs[1].is_ipv6());
+        assert_eq!(addrs[0].port(), addrs[1].port());
+        for addr in &addrs {
+            let client = ProtocolClient::new(TcpStream::connect(addr).await.unwrap());
+            assert!(matches!(client.request(Command::GetStatus).await.unwrap(), Response::Status { .. }));
+        }
+        assert_eq!(listeners.stats().iter().map(|s| s.local_addr).collect::<Vec<_>>(), addrs);
+
+        let endpoints = listeners.endpoints().unwrap();
+        assert_eq!(endpoints, addrs.iter().map(|&addr| Endpoint { transport: TransportKind::Tcp, addr }).collect::<Vec<_>>());
+        let discovery = listeners.advertise(HomeAssistantDiscovery::new("lab".to_string())).unwrap();
+        let connections = discovery.config_for("temp_01").device.connections;
+        assert_eq!(connections[1], ("tcp".to_string(), addrs[1].to_string()));
+    }
+
+    /// Writes a CA, a server certificate for localhost and a client
+    /// certificate into a fresh directory, and returns it with the SHA-256
+    /// fingerprint of the client certificate.
+    fn write_certificates(name: &str) -> (std::path::PathBuf, String) {
+        use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+        use sha2::{Digest, Sha256};
+
+        let dir = std::env::temp_dir().join(format!("temp_async_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+        let mut client_der = Vec::new();
+        for (name, sans) in [("server", vec!["localhost".to_string(), "127.0.0.1".to_string()]), ("client", Vec::new())] {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(sans).unwrap().signed_by(&key, &ca, &ca_key).unwrap();
+            std::fs::write(dir.join(format!("{}.pem", name)), cert.pem()).unwrap();
+            std::fs::write(dir.join(format!("{}.key", name)), key.serialize_pem()).unwrap();
+            client_der = cert.der().to_vec();
+        }
+        let fingerprint = Sha256::digest(&client_der).iter().map(|b| format!("{:02x}", b)).collect();
+        (dir, fingerprint)
+    }
+
+    #[tokio::test]
+    async fn serves_tls_and_authenticates_client_certificates() {
+        use crate::tls::{TlsClientConfig, TlsServerConfig};
+        use temp_protocol::auth::ClientCertificates;
+
+        let (dir, fingerprint) = write_certificates("tls");
+        let certificates = ClientCertificates::new().with_fingerprint(&fingerprint, Identity::new("gateway")).unwrap();
+        let handler = Arc::new(Mutex::new(TemperatureProtocolHandler::new().with_auth(certificates)));
+        let server = TlsServerConfig::new(dir.join("server.pem"), dir.join("server.key")).with_client_ca(dir.join("ca.pem"));
+        let mut listeners = Listeners::bind(&[ListenerConfig::tcp(any_port()).with_tls(server)]).unwrap();
+        listeners.serve(handler).unwrap();
+        let target = listeners.local_addr(TransportKind::Tcp).unwrap().to_string();
+
+        // The certificate authenticates the session, so no token is needed
+        let tls = TlsClientConfig::new(dir.join("ca.pem")).with_client_cert(dir.join("client.pem"), dir.join("client.key"));
+        let client = ProtocolClient::connect(&target, None, Some(&tls.connector().unwrap())).await.unwrap();
+        assert!(matches!(client.request(Command::GetStatus).await.unwrap(), Response::Status { .. }));
+        let key = listeners.sessions().keys()[0];
+        assert_eq!(listeners.sessions().identity(&key).map(|identity| identity.subject), Some("gateway".to_string()));
+
+        // Without a certificate the handshake fails
+        let anonymous = TlsClientConfig::new(dir.join("ca.pem")).connector().unwrap();
+        let refused = match ProtocolClient::connect(&target, None, Some(&anonymous)).await {
+            Ok(client) => client.request(Command::GetStatus).await.is_err(),
+            Err(_) => true,
+        };
+        assert!(refused);
+
+        let missing = ListenerConfig::tcp(any_port()).with_tls(TlsServerConfig::new(dir.join("missing.pem"), dir.join("server.key")));
+        assert_eq!(Listeners::bind(&[missing]).err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn serves_websocket_over_tls() {
+        use crate::tls::{self, TlsClientConfig, TlsServerConfig};
+        use temp_protocol::auth::ClientCertificates;
+
+        let (dir, fingerprint) = write_certificates("wss");
+        let certificates = ClientCertificates::new().with_fingerprint(&fingerprint, Identity::new("gateway")).unwrap();
+        let handler = Arc::new(Mutex::new(TemperatureProtocolHandler::new().with_auth(certificates)));
+        let server = TlsServerConfig::new(dir.join("server.pem"), dir.join("server.key")).with_client_ca(dir.join("ca.pem"));
+        let mut listeners = Listeners::bind(&[ListenerConfig::ws(any_port()).with_tls(server)]).unwrap();
+        listeners.serve(Arc::clone(&handler)).unwrap();
+        let target = listeners.local_addr(TransportKind::Ws).unwrap().to_string();
+
+        let connector = TlsClientConfig::new(dir.join("ca.pem"))
+            .with_client_cert(dir.join("client.pem"), dir.join("client.key"))
+            .connector()
+            .unwrap();
+        let stream = tls::connect(TcpStream::connect(&target).await.unwrap(), &target, &connector).await.unwrap();
+        let (mut socket, _) = tokio_tungstenite::client_async(format!("wss://{}/", target), stream).await.unwrap();
+        let get_status = handler.lock().unwrap().create_command(Command::GetStatus);
+        socket.send(Message::Text(serde_json::to_string(&get_status).unwrap())).await.unwrap();
+        let Message::Text(text) = socket.next().await.unwrap().unwrap() else {
+            panic!("Expected a text frame");
+        };
+        let status: ProtocolMessage = serde_json::from_str(&text).unwrap();
+        assert!(matches!(status.payload, MessagePayload::Response(Response::Status { .. })));
+        let key = listeners.sessions().keys()[0];
+        assert_eq!(key.transport, TransportKind::Ws);
+        assert_eq!(listeners.sessions().identity(&key).map(|identity| identity.subject), Some("gateway".to_string()));
+
+        // Plain WebSocket is not served on a TLS listener
+        assert!(tokio_tungstenite::connect_async(format!("ws://{}/", target)).await.is_err());
+
+        socket.close(None).await.unwrap();
+        listeners.shutdown();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn binds_the_addresses_of_an_interface() {
+        let config = ListenerConfig::udp("0.0.0.0:0".parse().unwrap()).on_interface("lo");
+        let listeners = Listeners::bind(&[config]).unwrap();
+        assert!(listeners.local_addrs(TransportKind::Udp).iter().all(|addr| addr.ip().is_loopback()));
+
+        let missing = ListenerConfig::udp(any_port()).on_interface("no-such-if0");
+        assert_eq!(Listeners::bind(&[missing]).err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+    }
+}
@@ -0,0 +1,274 @@
+//! Scripted temperature scenarios for integration tests.
+//!
+//! A scenario is a TOML list of steps played back over simulated time, e.g.
+//! "ramp to 40 °C over 10 min, then sensor offline for 2 min":
+//!
+//! ```toml
+//! start = 20.0
+//!
+//! [[steps]]
+//! action = "ramp"
+//! to = 40.0
+//! over = "10m"
+//!
+//! [[steps]]
+//! action = "offline"
+//! duration = "2m"
+//! ```
+//!
+//! Temperatures are °C, or text with a unit such as `"104F"`. Durations
+//! are written as `250ms`, `30s`, `10m` or `1h`. After the last
+//! step the final state holds. A [`ScenarioSensor`] reads the scenario at the
+//! elapsed tokio time, so tests with paused time replay it exactly;
+//! [`Scenario::apply`] drives any [`SensorSimulator`] such as the mocks.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer};
+use temp_core::Temperature;
+use temp_core::mock::MockTemperatureSensor;
+use temp_core::units::deserialize_celsius;
+use tokio::time::Instant;
+
+use crate::{AsyncMockSensor, AsyncSensorError, AsyncTemperatureSensor};
+
+/// A sensor whose temperature and availability can be set from outside.
+pub trait SensorSimulator {
+    fn set_temperature(&mut self, celsius: f32);
+    fn set_offline(&mut self, offline: bool);
+}
+
+impl SensorSimulator for MockTemperatureSensor {
+    fn set_temperature(&mut self, celsius: f32) {
+        MockTemperatureSensor::set_temperature(self, celsius);
+    }
+
+    fn set_offline(&mut self, offline: bool) {
+        MockTemperatureSensor::set_offline(self, offline);
+    }
+}
+
+impl SensorSimulator for AsyncMockSensor {
+    fn set_temperature(&mut self, celsius: f32) {
+        AsyncMockSensor::set_temperature(self, celsius);
+    }
+
+    fn set_offline(&mut self, offline: bool) {
+        AsyncMockSensor::set_offline(self, offline);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    /// Linear change to `to` °C.
+    Ramp {
+        #[serde(deserialize_with = "deserialize_celsius")]
+        to: f32,
+        #[serde(deserialize_with = "deserialize_duration")]
+        over: Duration,
+    },
+    /// Jump straight to `to` °C.
+    Set {
+        #[serde(deserialize_with = "deserialize_celsius")]
+        to: f32,
+    },
+    Hold {
+        #[serde(deserialize_with = "deserialize_duration")]
+        duration: Duration,
+    },
+    /// Every read fails for `duration`; the temperature holds meanwhile.
+    Offline {
+        #[serde(deserialize_with = "deserialize_duration")]
+        duration: Duration,
+    },
+}
+
+impl Step {
+    fn duration(&self) -> Duration {
+        match self {
+            Step::Ramp { over, .. } => *over,
+            Step::Set { .. } => Duration::ZERO,
+            Step::Hold { duration } | Step::Offline { duration } => *duration,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Temperature at time zero, in °C.
+    #[serde(deserialize_with = "deserialize_celsius")]
+    pub start: f32,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+/// What the sensor reports at a point of the scenario.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Condition {
+    pub temperature: Temperature,
+    pub online: bool,
+}
+
+impl Scenario {
+    pub fn from_toml(source: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(source)
+    }
+
+    /// Length of the scripted part.
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(Step::duration).sum()
+    }
+
+    pub fn condition_at(&self, elapsed: Duration) -> Condition {
+        let mut celsius = self.start;
+        let mut step_start = Duration::ZERO;
+        for step in &self.steps {
+            let step_end = step_start + step.duration();
+            let in_step = elapsed < step_end;
+            match step {
+                Step::Ramp { to, over } if in_step => {
+                    let progress = (elapsed - step_start).as_secs_f32() / over.as_secs_f32();
+                    celsius += (to - celsius) * progress;
+                }
+                Step::Ramp { to, .. } | Step::Set { to } => celsius = *to,
+                Step::Offline { .. } if in_step => {
+                    return Condition { temperature: Temperature::new(celsius), online: false };
+                }
+                Step::Hold { .. } | Step::Offline { .. } => {}
+            }
+            if in_step {
+                break;
+            }
+            step_start = step_end;
+        }
+        Condition { temperature: Temperature::new(celsius), online: true }
+    }
+
+    /// Puts `sensor` into the state the scenario prescribes at `elapsed`.
+    pub fn apply(&self, elapsed: Duration, sensor: &mut impl SensorSimulator) {
+        let condition = self.condition_at(elapsed);
+        sensor.set_temperature(condition.temperature.celsius);
+        sensor.set_offline(!condition.online);
+    }
+}
+
+/// Plays a scenario back in tokio time, starting when the sensor is created.
+pub struct ScenarioSensor {
+    id: String,
+    scenario: Scenario,
+    started: Instant,
+}
+
+impl ScenarioSensor {
+    pub fn new(id: String, scenario: Scenario) -> Self {
+        Self {
+            id,
+            scenario,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl AsyncTemperatureSensor for ScenarioSensor {
+    type Error = AsyncSensorError;
+
+    async fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+        let condition = self.scenario.condition_at(self.started.elapsed());
+        if condition.online {
+            Ok(condition.temperature)
+        } else {
+            Err(AsyncSensorError::ReadFailed)
+        }
+    }
+
+    fn sensor_id(&self) -> &str {
+        &self.id
+    }
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_duration(&text).ok_or_else(|| serde::de::Error::custom(format!("invalid duration '{}'", text)))
+}
+
+/// Parses `250ms`, `30s`, `10m` or `1h`.
+fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let (number, unit) = text.split_at(split);
+    let value: f64 = number.parse().ok()?;
+    let seconds = match unit {
+        "ms" => value / 1000.0,
+        "s" => value,
+        "m" | "min" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temp_core::TemperatureSensor;
+
+    const DOOR_OPEN: &str = r#"
+        name = "door left open"
+        start = 20.0
+
+        [[steps]]
+        action = "ramp"
+        to = "104F"
+        over = "10m"
+
+        [[steps]]
+        action = "offline"
+        duration = "2m"
+
+        [[steps]]
+        action = "set"
+        to = 18.0
+    "#;
+
+    #[test]
+    fn parses_and_plays_back_steps() {
+        let scenario = Scenario::from_toml(DOOR_OPEN).unwrap();
+        assert_eq!(scenario.duration(), Duration::from_secs(12 * 60));
+
+        let at = |minutes: f32| scenario.condition_at(Duration::from_secs_f32(minutes * 60.0));
+        assert_eq!(at(0.0).temperature.celsius, 20.0);
+        assert_eq!(at(5.0).temperature.celsius, 30.0);
+        assert_eq!(at(11.0), Condition { temperature: Temperature::new(40.0), online: false });
+        assert_eq!(at(12.0), Condition { temperature: Temperature::new(18.0), online: true });
+        assert_eq!(at(60.0).temperature.celsius, 18.0);
+
+        let mut mock = MockTemperatureSensor::new("mock".to_string(), 0.0);
+        scenario.apply(Duration::from_secs(11 * 60), &mut mock);
+        assert!(mock.read_temperature().is_err());
+        scenario.apply(Duration::from_secs(2 * 60), &mut mock);
+        assert_eq!(mock.read_temperature().unwrap().celsius, 24.0);
+    }
+
+    #[test]
+    fn rejects_bad_durations_and_actions() {
+        let bad_duration = "start = 20.0\n[[steps]]\naction = \"hold\"\nduration = \"10 parsecs\"";
+        assert!(Scenario::from_toml(bad_duration).unwrap_err().to_string().contains("invalid duration"));
+        let bad_action = "start = 20.0\n[[steps]]\naction = \"explode\"";
+        assert!(Scenario::from_toml(bad_action).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn scenario_sensor_follows_tokio_time() {
+        let mut sensor = ScenarioSensor::new("lab".to_string(), Scenario::from_toml(DOOR_OPEN).unwrap());
+        tokio::time::advance(Duration::from_secs(5 * 60)).await;
+        assert_eq!(sensor.read_temperature().await.unwrap().celsius, 30.0);
+        tokio::time::advance(Duration::from_secs(6 * 60)).await;
+        assert!(sensor.read_temperature().await.is_err());
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert_eq!(sensor.read_temperature().await.unwrap().celsius, 18.0);
+    }
+}
@@ -0,0 +1,560 @@
+//! Wiring of the whole service graph.
+//!
+//! [`TempServiceBuilder`] opens one store per sensor (optionally persistent,
+//! with a background flusher each), spawns a monitor task per sensor and
+//! registers the stores with a protocol handler, so embedding the capstone
+//! takes a builder chain and a [`TempService::shutdown`] at the end. The
+//! handler can also be served on [`listener`](crate::listener)s. The
+//! builder must be run inside a tokio runtime since it spawns tasks.
+//!
+//! A daemon ends with [`TempService::shutdown_on_signal`], which shuts down
+//! cleanly on SIGTERM or Ctrl-C. With the `systemd` feature the service also
+//! reports readiness and feeds the watchdog, see [`systemd`](crate::systemd).
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use temp_alert::AlertHistory;
+use temp_core::health::SharedHealth;
+use temp_core::transform::{TransformConfig, TransformPipeline};
+use temp_core::{SensorId, SensorInfo};
+use temp_protocol::acl::AccessControl;
+use temp_protocol::auth::AuthConfig;
+use temp_protocol::tags::{self, Tags};
+use temp_protocol::{SensorSpec, TemperatureProtocolHandler, STORE_CAPACITY_PER_SENSOR};
+use temp_store::error_hook::{self, SwallowedKind};
+use temp_store::{FlushPolicy, OutlierRejection, TemperatureReading, TemperatureStore};
+use tokio::task::JoinHandle;
+
+use crate::listener::{ListenerConfig, Listeners};
+use crate::{AsyncTemperatureMonitor, AsyncTemperatureSensor, BackgroundFlusher, MonitorHandle};
+
+/// Where and how the per-sensor reading logs are written.
+#[derive(Debug, Clone)]
+pub struct PersistenceOptions {
+    /// Directory holding one `<sensor_id>.log` per sensor.
+    pub dir: PathBuf,
+    pub policy: FlushPolicy,
+    /// How often the background flusher checks the flush policy.
+    pub flush_check_interval: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServiceOptions {
+    /// Raw readings kept in memory per sensor.
+    pub store_capacity: usize,
+    /// Keep stores in memory only when `None`.
+    pub persistence: Option<PersistenceOptions>,
+    /// See [`AsyncTemperatureMonitor::with_watchdog`].
+    pub watchdog_multiplier: Option<f32>,
+    /// Transforms applied to each sensor's readings before they are stored,
+    /// by sensor id. They can be changed later with `SetTransforms`.
+    pub transforms: HashMap<String, Vec<TransformConfig>>,
+    /// Datasheet figures answered to `GetSensorInfo`, by sensor id.
+    pub sensor_info: HashMap<String, SensorSpec>,
+    /// Tags of each sensor, by sensor id. They can be changed later with `SetTags`.
+    pub tags: HashMap<String, Tags>,
+    /// Transports serving the protocol handler, all at once.
+    pub listeners: Vec<ListenerConfig>,
+    /// Commands refused until enabled at runtime, e.g. `RestoreBackup` in production.
+    pub disabled_commands: Vec<String>,
+    /// Outliers left out of every sensor's stats.
+    pub outlier_rejection: Option<OutlierRejection>,
+    /// How sessions authenticate; every session is trusted when `None`.
+    pub auth: Option<AuthConfig>,
+    /// Which identities may access which sensors; all of them when `None`.
+    pub access_control: Option<AccessControl>,
+}
+
+impl Default for ServiceOptions {
+    fn default() -> Self {
+        Self {
+            store_capacity: STORE_CAPACITY_PER_SENSOR,
+            persistence: None,
+            watchdog_multiplier: None,
+            transforms: HashMap::new(),
+            sensor_info: HashMap::new(),
+            tags: HashMap::new(),
+            listeners: Vec::new(),
+            disabled_commands: Vec::new(),
+            outlier_rejection: None,
+            auth: None,
+            access_control: None,
+        }
+    }
+}
+
+type SpawnMonitor = Box<dyn FnOnce(AsyncTemperatureMonitor) -> JoinHandle<()> + Send>;
+
+struct PendingSensor {
+    sensor_id: String,
+    spawn: SpawnMonitor,
+}
+
+#[derive(Default)]
+pub struct TempServiceBuilder {
+    options: ServiceOptions,
+    sensors: Vec<PendingSensor>,
+    alert_history: Option<AlertHistory>,
+    history: HashMap<String, Vec<TemperatureReading>>,
+}
+
+impl TempServiceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: ServiceOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    pub fn store_capacity(mut self, capacity: usize) -> Self {
+        self.options.store_capacity = capacity;
+        self
+    }
+
+    /// Persists every sensor's readings to `dir`, checking `policy` once a second.
+    pub fn persist_to(mut self, dir: impl Into<PathBuf>, policy: FlushPolicy) -> Self {
+        self.options.persistence = Some(PersistenceOptions {
+            dir: dir.into(),
+            policy,
+            flush_check_interval: Duration::from_secs(1),
+        });
+        self
+    }
+
+    pub fn watchdog(mut self, multiplier: f32) -> Self {
+        self.options.watchdog_multiplier = Some(multiplier);
+        self
+    }
+
+    pub fn transforms(mut self, sensor_id: impl Into<String>, transforms: Vec<TransformConfig>) -> Self {
+        self.options.transforms.insert(sensor_id.into(), transforms);
+        self
+    }
+
+    /// Datasheet figures of a sensor, e.g. from its driver.
+    pub fn sensor_info(mut self, sensor_id: impl Into<String>, info: &impl SensorInfo) -> Self {
+        self.options.sensor_info.insert(sensor_id.into(), SensorSpec::of(info));
+        self
+    }
+
+    /// Tags a sensor, e.g. with `tags::parse("location=lab1,floor=2")`.
+    pub fn tags(mut self, sensor_id: impl Into<String>, tags: Tags) -> Self {
+        self.options.tags.insert(sensor_id.into(), tags);
+        self
+    }
+
+    pub fn outlier_rejection(mut self, rejection: OutlierRejection) -> Self {
+        self.options.outlier_rejection = Some(rejection);
+        self
+    }
+
+    pub fn auth(mut self, config: AuthConfig) -> Self {
+        self.options.auth = Some(config);
+        self
+    }
+
+    /// Limits sessions to the sensors granted to them, e.g. from
+    /// [`AccessControl::load`].
+    pub fn access_control(mut self, acl: AccessControl) -> Self {
+        self.options.access_control = Some(acl);
+        self
+    }
+
+    pub fn listen(mut self, listener: ListenerConfig) -> Self {
+        self.options.listeners.push(listener);
+        self
+    }
+
+    /// History the protocol handler records alert transitions to.
+    pub fn alert_history(mut self, history: AlertHistory) -> Self {
+        self.alert_history = Some(history);
+        self
+    }
+
+    /// Readings a sensor's store starts with, oldest first, e.g. seeded data
+    /// for a demo. They are added before its monitor starts.
+    pub fn history(mut self, sensor_id: impl Into<String>, readings: Vec<TemperatureReading>) -> Self {
+        self.history.insert(sensor_id.into(), readings);
+        self
+    }
+
+    /// Adds a sensor sampled every `interval` by its own monitor task.
+    pub fn sensor<S>(mut self, sensor: S, interval: Duration) -> Self
+    where
+        S: AsyncTemperatureSensor + 'static,
+    {
+        let sensor_id = sensor.sensor_id().to_string();
+        let spawn: SpawnMonitor = Box::new(move |mut monitor| {
+            tokio::spawn(async move { monitor.run(sensor, interval).await })
+        });
+        self.sensors.push(PendingSensor { sensor_id, spawn });
+        self
+    }
+
+    /// Opens the stores and starts the monitors and flushers.
+    pub fn build(mut self) -> io::Result<TempService> {
+        let mut seen = HashSet::new();
+        if let Some(pending) = self.sensors.iter().find(|p| !seen.insert(p.sensor_id.as_str())) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Duplicate sensor id {}", pending.sensor_id),
+            ));
+        }
+        let ids = self
+            .sensors
+            .iter()
+            .map(|pending| {
+                SensorId::new(&pending.sensor_id).map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid sensor id {}: {}", pending.sensor_id, e))
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        if let Some(sensor_id) = self.options.transforms.keys().find(|id| !seen.contains(id.as_str())) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Transforms configured for unknown sensor {}", sensor_id),
+            ));
+        }
+        if let Some(sensor_id) = self.options.sensor_info.keys().find(|id| !seen.contains(id.as_str())) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Sensor info given for unknown sensor {}", sensor_id),
+            ));
+        }
+        if let Some(sensor_id) = self.options.tags.keys().find(|id| !seen.contains(id.as_str())) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Tags given for unknown sensor {}", sensor_id),
+            ));
+        }
+        if let Some(sensor_id) = self.history.keys().find(|id| !seen.contains(id.as_str())) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("History given for unknown sensor {}", sensor_id),
+            ));
+        }
+        if let Some((sensor_id, Err(e))) = self.options.tags.iter().map(|(id, t)| (id, tags::validate(t))).find(|(_, r)| r.is_err()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid tags for {}: {}", sensor_id, e)));
+        }
+        if let Some(Err(e)) = self.options.outlier_rejection.map(|r| r.validate()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid outlier rejection: {}", e)));
+        }
+        let mut pipelines = HashMap::new();
+        for pending in &self.sensors {
+            let config = self.options.transforms.get(&pending.sensor_id).cloned().unwrap_or_default();
+            let pipeline = TransformPipeline::from_config(config).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid transforms for {}: {}", pending.sensor_id, e),
+                )
+            })?;
+            pipelines.insert(pending.sensor_id.clone(), Arc::new(Mutex::new(pipeline)));
+        }
+
+        // Bind and open everything before spawning anything, so a failure leaves nothing running
+        let auth = self.options.auth.as_ref().map(AuthConfig::build).transpose()?;
+        let mut listeners = Listeners::bind(&self.options.listeners)?;
+        let mut stores = HashMap::new();
+        for pending in &self.sensors {
+            let store = match &self.options.persistence {
+                Some(persistence) => {
+                    let path = persistence.dir.join(format!("{}.log", pending.sensor_id));
+                    TemperatureStore::open(self.options.store_capacity, path, persistence.policy)?
+                }
+                None => TemperatureStore::new(self.options.store_capacity),
+            };
+            store.set_outlier_rejection(self.options.outlier_rejection);
+            if let Some(readings) = self.history.get(&pending.sensor_id) {
+                store.add_readings(readings);
+            }
+            stores.insert(pending.sensor_id.clone(), store);
+        }
+
+        let mut handler =
+            TemperatureProtocolHandler::without_sensors().with_disabled_commands(self.options.disabled_commands);
+        if let Some(provider) = auth {
+            handler = handler.with_auth(provider);
+        }
+        if let Some(acl) = self.options.access_control {
+            handler = handler.with_access_control(acl);
+        }
+        if let Some(history) = self.alert_history {
+            handler.set_alert_history(history);
+        }
+        // Every key names a sensor, checked above
+        for sensor_id in &ids {
+            if let Some(info) = self.options.sensor_info.remove(sensor_id.as_str()) {
+                handler.attach_sensor_info(sensor_id.clone(), info);
+            }
+            if let Some(tags) = self.options.tags.remove(sensor_id.as_str()) {
+                handler.set_tags(sensor_id.clone(), tags);
+            }
+        }
+
+        let mut monitors = HashMap::new();
+        let mut tasks = Vec::new();
+        let mut flushers = Vec::new();
+        for (pending, sensor_id) in self.sensors.into_iter().zip(ids) {
+            let store = &stores[&pending.sensor_id];
+            if let Some(persistence) = &self.options.persistence {
+                flushers.push(BackgroundFlusher::spawn(store.clone_handle(), persistence.flush_check_interval));
+            }
+
+            let pipeline = pipelines.remove(&pending.sensor_id).unwrap_or_default();
+            let health = SharedHealth::default();
+            let mut monitor = AsyncTemperatureMonitor::with_store(store.clone_handle())
+                .with_transforms(Arc::clone(&pipeline))
+                .with_health(Arc::clone(&health));
+            if let Some(multiplier) = self.options.watchdog_multiplier {
+                monitor = monitor.with_watchdog(multiplier);
+            }
+            #[cfg(feature = "systemd")]
+            {
+                monitor = monitor.with_systemd_watchdog();
+            }
+            monitors.insert(pending.sensor_id.clone(), monitor.get_handle());
+            tasks.push((pending.spawn)(monitor));
+            handler.attach_transforms(sensor_id.clone(), pipeline);
+            handler.attach_self_test(sensor_id.clone(), health);
+            handler.attach_store(sensor_id, store.clone_handle());
+        }
+
+        let protocol = Arc::new(Mutex::new(handler));
+        listeners.serve(Arc::clone(&protocol))?;
+        #[cfg(feature = "systemd")]
+        crate::systemd::notify_ready();
+        Ok(TempService {
+            monitors,
+            stores,
+            tasks,
+            flushers,
+            protocol,
+            listeners,
+        })
+    }
+}
+
+/// Handles to a running service built by [`TempServiceBuilder`].
+pub struct TempService {
+    monitors: HashMap<String, MonitorHandle>,
+    stores: HashMap<String, TemperatureStore>,
+    tasks: Vec<JoinHandle<()>>,
+    flushers: Vec<BackgroundFlusher>,
+    protocol: Arc<Mutex<TemperatureProtocolHandler>>,
+    listeners: Listeners,
+}
+
+impl TempService {
+    pub fn monitor(&self, sensor_id: &str) -> Option<&MonitorHandle> {
+        self.monitors.get(sensor_id)
+    }
+
+    pub fn store(&self, sensor_id: &str) -> Option<&TemperatureStore> {
+        self.stores.get(sensor_id)
+    }
+
+    /// Sorted ids of the configured sensors.
+    pub fn sensor_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.stores.keys().map(String::as_str).collect();
+        ids.sort();
+        ids
+    }
+
+    /// The protocol handler serving the sensors' stores, to be shared with a transport.
+    pub fn protocol(&self) -> Arc<Mutex<TemperatureProtocolHandler>> {
+        Arc::clone(&self.protocol)
+    }
+
+    /// The configured listeners, for their addresses and per-transport stats.
+    pub fn listeners(&self) -> &Listeners {
+        &self.listeners
+    }
+
+    /// Stops the listeners and every monitor, waits for the monitors to
+    /// finish and flushes the stores.
+    pub async fn shutdown(mut self) {
+        #[cfg(feature = "systemd")]
+        crate::systemd::notify_stopping();
+        self.listeners.shutdown();
+        for monitor in self.monitors.values() {
+            let _ = monitor.stop().await;
+        }
+        for task in self.tasks {
+            if let Err(e) = task.await {
+                error_hook::report(SwallowedKind::TaskFailed, "Monitor task failed", &e);
+            }
+        }
+        for flusher in self.flushers {
+            flusher.shutdown().await;
+        }
+    }
+
+    /// Keeps serving until SIGTERM (from systemd, docker or `kill`) or
+    /// Ctrl-C, then [`shutdown`](Self::shutdown)s.
+    pub async fn shutdown_on_signal(self) -> io::Result<()> {
+        terminated().await?;
+        println!("Shutting down");
+        self.shutdown().await;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+async fn terminated() -> io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = terminate.recv() => Ok(()),
+        interrupted = tokio::signal::ctrl_c() => interrupted,
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminated() -> io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AsyncMockSensor;
+    use temp_protocol::{Command, MessagePayload, Response};
+    use tokio::time::sleep;
+
+    #[tokio::test(start_paused = true)]
+    async fn wires_monitors_into_the_protocol_handler() {
+        let service = TempServiceBuilder::new()
+            .store_capacity(10)
+            .sensor(AsyncMockSensor::new("kitchen".to_string(), 21.0), Duration::from_secs(1))
+            .sensor(AsyncMockSensor::new("cellar".to_string(), 12.0), Duration::from_secs(1))
+            .sensor_info("cellar", &temp_core::mock::MockTemperatureSensor::new("cellar".to_string(), 12.0))
+            .tags("cellar", tags::parse("location=basement").unwrap())
+            .history("cellar", vec![TemperatureReading::with_timestamp(temp_core::Temperature::new(11.0), 1_000)])
+            .build()
+            .unwrap();
+        assert_eq!(service.sensor_ids(), vec!["cellar", "kitchen"]);
+
+        sleep(Duration::from_millis(3500)).await;
+        let stats = service.monitor("kitchen").unwrap().get_stats().await.unwrap().unwrap();
+        assert!(stats.count >= 3);
+        let seeded = service.store("cellar").unwrap().window_stats(0, 2_000).unwrap();
+        assert_eq!((seeded.count, seeded.min.celsius), (1, 11.0));
+
+        let protocol = service.protocol();
+        let response = {
+            let mut handler = protocol.lock().unwrap();
+            let command = handler.create_command(Command::GetReading { sensor_id: "cellar".parse().unwrap() });
+            handler.process_command(command)
+        };
+        match response.payload {
+            MessagePayload::Response(Response::Reading { temperature, .. }) => assert_eq!(temperature, 12.0),
+            other => panic!("Unexpected response: {:?}", other),
+        }
+        let response = {
+            let mut handler = protocol.lock().unwrap();
+            let command = handler.create_command(Command::GetSensorInfo { sensor_id: "cellar".parse().unwrap() });
+            handler.process_command(command)
+        };
+        assert!(matches!(response.payload, MessagePayload::Response(Response::SensorInfo { info, .. }) if info.model == "mock"));
+        // The monitors self-tested their sensors on startup
+        let status = protocol.lock().unwrap().sensor_status();
+        assert!(status.iter().all(|s| s.self_test == Some(temp_core::SensorHealth::Ok)));
+        assert_eq!(status[0].tags, tags::parse("location=basement").unwrap());
+
+        service.shutdown().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn persists_readings_and_rejects_duplicate_sensors() {
+        let dir = std::env::temp_dir().join(format!("temp_service_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let service = TempServiceBuilder::new()
+            .persist_to(&dir, FlushPolicy::EveryN(100))
+            .sensor(AsyncMockSensor::new("kitchen".to_string(), 21.0), Duration::from_secs(1))
+            .build()
+            .unwrap();
+        sleep(Duration::from_millis(2500)).await;
+        service.shutdown().await;
+
+        let path = dir.join("kitchen.log");
+        assert!(!temp_store::FileBackend::load(&path).unwrap().is_empty());
+
+        let duplicate = TempServiceBuilder::new()
+            .sensor(AsyncMockSensor::new("kitchen".to_string(), 21.0), Duration::from_secs(1))
+            .sensor(AsyncMockSensor::new("kitchen".to_string(), 22.0), Duration::from_secs(1))
+            .build();
+        assert_eq!(duplicate.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sessions_authenticate_with_the_configured_provider() {
+        let dir = std::env::temp_dir().join(format!("temp_service_auth_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("tokens"), "s3cret admin\n").unwrap();
+
+        let service = TempServiceBuilder::new()
+            .sensor(AsyncMockSensor::new("kitchen".to_string(), 21.0), Duration::from_secs(1))
+            .auth(AuthConfig::Tokens { path: dir.join("tokens") })
+            .build()
+            .unwrap();
+        let protocol = service.protocol();
+        let response = {
+            let mut handler = protocol.lock().unwrap();
+            let command = handler.create_command(Command::GetStatus);
+            handler.process_command(command)
+        };
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 401, .. })));
+        service.shutdown().await;
+
+        let missing = TempServiceBuilder::new()
+            .sensor(AsyncMockSensor::new("kitchen".to_string(), 21.0), Duration::from_secs(1))
+            .auth(AuthConfig::Tokens { path: dir.join("missing") })
+            .build();
+        assert_eq!(missing.err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transforms_apply_before_storing_and_follow_the_protocol() {
+        let service = TempServiceBuilder::new()
+            .sensor(AsyncMockSensor::new("attic".to_string(), 68.0), Duration::from_secs(1))
+            .transforms("attic", vec![TransformConfig::Convert { from: temp_core::transform::Unit::Fahrenheit }])
+            .build()
+            .unwrap();
+        sleep(Duration::from_millis(1500)).await;
+        let monitor = service.monitor("attic").unwrap();
+        assert_eq!(monitor.get_latest().await.unwrap().unwrap().temperature.celsius, 20.0);
+
+        let protocol = service.protocol();
+        {
+            let mut handler = protocol.lock().unwrap();
+            let command = handler.create_command(Command::SetTransforms {
+                sensor_id: "attic".parse().unwrap(),
+                transforms: vec![TransformConfig::Offset { celsius: -8.0 }],
+            });
+            handler.process_command(command);
+        }
+        sleep(Duration::from_secs(1)).await;
+        assert_eq!(monitor.get_latest().await.unwrap().unwrap().temperature.celsius, 60.0);
+        service.shutdown().await;
+
+        let unknown = TempServiceBuilder::new()
+            .sensor(AsyncMockSensor::new("attic".to_string(), 21.0), Duration::from_secs(1))
+            .transforms("cellar", Vec::new())
+            .build();
+        assert_eq!(unknown.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+    }
+}
@@ -0,0 +1,304 @@
+//! Simulated room for tuning and testing control loops without hardware.
+//!
+//! [`ThermalModel`] is a lumped single-node model: the room has one thermal
+//! mass, loses heat to the ambient through a fixed conductance and gains the
+//! heater's power scaled by the actuator level. Split it into a
+//! [`SimulatedSensor`] for the monitor and a [`SimulatedHeater`] for the
+//! control loop; both share the same model.
+
+use std::convert::Infallible;
+use std::f32::consts::TAU;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use temp_core::Temperature;
+use temp_core::control::Actuator;
+use temp_core::mock::Noise;
+
+use crate::AsyncTemperatureSensor;
+
+/// Longest explicit integration step; longer steps are subdivided.
+const MAX_STEP_SECONDS: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermalParams {
+    /// Heat needed to warm the room by 1 K, in J/K.
+    pub thermal_mass: f32,
+    /// Heat lost to the ambient per kelvin of difference, in W/K.
+    pub heat_loss: f32,
+    /// Power at full actuator output, in W. Negative for a cooler.
+    pub heater_power: f32,
+}
+
+impl ThermalParams {
+    /// A small, moderately insulated room with a 1.5 kW heater.
+    pub const fn room() -> Self {
+        Self {
+            thermal_mass: 200_000.0,
+            heat_loss: 50.0,
+            heater_power: 1500.0,
+        }
+    }
+}
+
+/// Ambient temperature over simulated time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AmbientProfile {
+    Constant(Temperature),
+    /// Day/night swing around `mean`, coldest at time zero.
+    Sinusoidal {
+        mean: Temperature,
+        amplitude: f32,
+        period: Duration,
+    },
+    /// Piecewise constant: each entry applies from its offset on. Entries must
+    /// be sorted; before the first one its temperature applies.
+    Steps(Vec<(Duration, Temperature)>),
+}
+
+impl AmbientProfile {
+    pub fn at(&self, elapsed: Duration) -> Temperature {
+        match self {
+            AmbientProfile::Constant(temperature) => *temperature,
+            AmbientProfile::Sinusoidal { mean, amplitude, period } => {
+                let phase = elapsed.as_secs_f32() / period.as_secs_f32();
+                Temperature::new(mean.celsius - amplitude * (TAU * phase).cos())
+            }
+            AmbientProfile::Steps(steps) => steps
+                .iter()
+                .rev()
+                .find(|(offset, _)| *offset <= elapsed)
+                .or(steps.first())
+                .map_or(Temperature::new(0.0), |(_, temperature)| *temperature),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ThermalModel {
+    params: ThermalParams,
+    ambient: AmbientProfile,
+    temperature: f32,
+    heater_level: f32,
+    elapsed: Duration,
+}
+
+impl ThermalModel {
+    pub fn new(initial: Temperature, params: ThermalParams, ambient: AmbientProfile) -> Self {
+        Self {
+            params,
+            ambient,
+            temperature: initial.celsius,
+            heater_level: 0.0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Advances the simulation by `dt`.
+    pub fn step(&mut self, dt: Duration) {
+        let mut remaining = dt.as_secs_f32();
+        while remaining > 0.0 {
+            let h = remaining.min(MAX_STEP_SECONDS);
+            let ambient = self.ambient.at(self.elapsed).celsius;
+            let power = self.heater_level * self.params.heater_power
+                - self.params.heat_loss * (self.temperature - ambient);
+            self.temperature += power / self.params.thermal_mass * h;
+            self.elapsed += Duration::from_secs_f32(h);
+            remaining -= h;
+        }
+    }
+
+    pub fn temperature(&self) -> Temperature {
+        Temperature::new(self.temperature)
+    }
+
+    pub fn ambient(&self) -> Temperature {
+        self.ambient.at(self.elapsed)
+    }
+
+    pub fn heater_level(&self) -> f32 {
+        self.heater_level
+    }
+
+    pub fn set_heater_level(&mut self, level: f32) {
+        self.heater_level = level.clamp(0.0, 1.0);
+    }
+
+    /// Simulated time since the start.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Temperature the room settles at with the heater held at `level`.
+    pub fn equilibrium(&self, level: f32) -> Temperature {
+        let ambient = self.ambient().celsius;
+        Temperature::new(ambient + level.clamp(0.0, 1.0) * self.params.heater_power / self.params.heat_loss)
+    }
+
+    /// Splits the model into a sensor and a heater sharing its state. Each
+    /// sensor read advances the simulation by `time_step`, so a monitor
+    /// sampling every few milliseconds can cover hours of simulated time.
+    pub fn split(self, id: String, time_step: Duration) -> (SimulatedSensor, SimulatedHeater) {
+        let model = Arc::new(Mutex::new(self));
+        let sensor = SimulatedSensor {
+            id,
+            model: Arc::clone(&model),
+            time_step,
+            noise: None,
+        };
+        (sensor, SimulatedHeater { model })
+    }
+}
+
+pub struct SimulatedSensor {
+    id: String,
+    model: Arc<Mutex<ThermalModel>>,
+    time_step: Duration,
+    noise: Option<Noise>,
+}
+
+impl SimulatedSensor {
+    /// Adds seeded measurement noise of up to `amplitude` °C; the model itself stays exact.
+    pub fn with_noise(mut self, amplitude: f32, seed: u64) -> Self {
+        self.noise = Some(Noise::new(amplitude, seed));
+        self
+    }
+
+    /// Current model state, without advancing the simulation.
+    pub fn snapshot(&self) -> ThermalModel {
+        self.model.lock().unwrap().clone()
+    }
+}
+
+impl AsyncTemperatureSensor for SimulatedSensor {
+    type Error = Infallible;
+
+    async fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+        let mut model = self.model.lock().unwrap();
+        model.step(self.time_step);
+        let noise = self.noise.as_mut().map_or(0.0, |noise| noise.sample());
+        Ok(Temperature::new(model.temperature().celsius + noise))
+    }
+
+    fn sensor_id(&self) -> &str {
+        &self.id
+    }
+}
+
+pub struct SimulatedHeater {
+    model: Arc<Mutex<ThermalModel>>,
+}
+
+impl Actuator for SimulatedHeater {
+    type Error = Infallible;
+
+    fn set_output(&mut self, level: f32) -> Result<(), Self::Error> {
+        self.model.lock().unwrap().set_heater_level(level);
+        Ok(())
+    }
+
+    fn output(&self) -> f32 {
+        self.model.lock().unwrap().heater_level()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AsyncTemperatureMonitor;
+    use temp_core::control::{BangBangController, ControlLoop, ControlMode, PidController, PidGains};
+    use tokio::time::{sleep, timeout};
+
+    fn cold_room() -> ThermalModel {
+        ThermalModel::new(
+            Temperature::new(10.0),
+            ThermalParams::room(),
+            AmbientProfile::Constant(Temperature::new(10.0)),
+        )
+    }
+
+    #[test]
+    fn model_approaches_equilibrium() {
+        let mut model = cold_room();
+        model.set_heater_level(1.0);
+        let target = model.equilibrium(1.0);
+        assert_eq!(target.celsius, 40.0);
+
+        // Ten time constants (thermal_mass / heat_loss = 4000 s)
+        model.step(Duration::from_secs(40_000));
+        assert!((model.temperature().celsius - target.celsius).abs() < 0.1);
+
+        model.set_heater_level(0.0);
+        model.step(Duration::from_secs(40_000));
+        assert!((model.temperature().celsius - 10.0).abs() < 0.1);
+        assert_eq!(model.elapsed(), Duration::from_secs(80_000));
+    }
+
+    #[test]
+    fn ambient_profiles() {
+        let day = AmbientProfile::Sinusoidal {
+            mean: Temperature::new(15.0),
+            amplitude: 5.0,
+            period: Duration::from_secs(86_400),
+        };
+        assert!((day.at(Duration::ZERO).celsius - 10.0).abs() < 1e-4);
+        assert!((day.at(Duration::from_secs(43_200)).celsius - 20.0).abs() < 1e-4);
+
+        let steps = AmbientProfile::Steps(vec![
+            (Duration::from_secs(60), Temperature::new(5.0)),
+            (Duration::from_secs(120), Temperature::new(-5.0)),
+        ]);
+        assert_eq!(steps.at(Duration::ZERO).celsius, 5.0);
+        assert_eq!(steps.at(Duration::from_secs(119)).celsius, 5.0);
+        assert_eq!(steps.at(Duration::from_secs(500)).celsius, -5.0);
+    }
+
+    #[tokio::test]
+    async fn pid_holds_setpoint_in_simulation() {
+        let (sensor, heater) = cold_room().split("sim".to_string(), Duration::from_secs(30));
+        let seed = temp_core::mock::test_seed();
+        let mut sensor = sensor.with_noise(0.1, seed);
+        let gains = PidGains { kp: 0.5, ki: 0.002, kd: 0.0 };
+        let mut control = ControlLoop::new(
+            PidController::new(Temperature::new(21.0), gains, ControlMode::Heating),
+            heater,
+        );
+
+        // Six simulated hours
+        for _ in 0..720 {
+            let temperature = sensor.read_temperature().await.unwrap();
+            control.step(temperature, 30.0).unwrap();
+        }
+
+        let settled = sensor.snapshot().temperature().celsius;
+        assert!((settled - 21.0).abs() < 0.2, "settled at {} with {}={}", settled, temp_core::mock::TEST_SEED_VAR, seed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn monitor_runs_against_simulated_room() {
+        let (sensor, heater) = cold_room().split("sim".to_string(), Duration::from_secs(60));
+        let probe = SimulatedSensor {
+            id: "probe".to_string(),
+            model: Arc::clone(&sensor.model),
+            time_step: Duration::ZERO,
+            noise: None,
+        };
+
+        let controller = BangBangController::new(Temperature::new(21.0), 0.5, ControlMode::Heating);
+        let mut monitor = AsyncTemperatureMonitor::new(200).with_control_loop(ControlLoop::new(controller, heater));
+        let handle = monitor.get_handle();
+
+        let monitor_task = tokio::spawn(async move {
+            monitor.run(sensor, Duration::from_millis(2)).await;
+        });
+
+        sleep(Duration::from_millis(400)).await;
+        handle.stop().await.unwrap();
+        timeout(Duration::from_millis(500), monitor_task).await.unwrap().unwrap();
+
+        let model = probe.snapshot();
+        assert!(model.elapsed() >= Duration::from_secs(3600), "only {:?} simulated", model.elapsed());
+        let temperature = model.temperature().celsius;
+        assert!((19.5..=22.5).contains(&temperature), "room at {}", temperature);
+    }
+}
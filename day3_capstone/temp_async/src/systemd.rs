@@ -0,0 +1,65 @@
+//! Notifications to systemd, with the `systemd` feature.
+//!
+//! Run as a `Type=notify` unit, the service reports `READY=1` once its
+//! listeners are bound, so units ordered after it only start when it takes
+//! connections, and `STOPPING=1` when it shuts down. With `WatchdogSec=` set,
+//! every monitor pings the watchdog from its loop at half the timeout, so
+//! systemd restarts a service whose runtime stalled. Outside systemd, where
+//! `NOTIFY_SOCKET` is unset, all of this does nothing.
+
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+
+pub fn notify_ready() {
+    notify(NotifyState::Ready);
+}
+
+pub fn notify_stopping() {
+    notify(NotifyState::Stopping);
+}
+
+pub fn notify_watchdog() {
+    notify(NotifyState::Watchdog);
+}
+
+fn notify(state: NotifyState) {
+    if let Err(e) = sd_notify::notify(false, &[state]) {
+        eprintln!("Failed to notify systemd: {}", e);
+    }
+}
+
+/// How often to ping the watchdog: half its timeout, `None` when the unit
+/// has no watchdog for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    let mut usec = 0;
+    let enabled = sd_notify::watchdog_enabled(false, &mut usec);
+    Some(Duration::from_micros(usec / 2)).filter(|interval| enabled && !interval.is_zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn reads_the_watchdog_timeout_and_notifies_the_socket() {
+        std::env::set_var("WATCHDOG_USEC", "3000000");
+        std::env::set_var("WATCHDOG_PID", std::process::id().to_string());
+        assert_eq!(watchdog_interval(), Some(Duration::from_millis(1500)));
+        std::env::set_var("WATCHDOG_PID", (std::process::id() + 1).to_string());
+        assert_eq!(watchdog_interval(), None);
+
+        let path = std::env::temp_dir().join(format!("temp_async_notify_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).unwrap();
+        std::env::set_var("NOTIFY_SOCKET", &path);
+        notify_watchdog();
+        std::env::remove_var("NOTIFY_SOCKET");
+
+        let mut buf = [0; 64];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"WATCHDOG=1\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+//! TLS for the TCP and WebSocket protocol transports.
+//!
+//! A TCP or WebSocket [`ListenerConfig`](crate::listener::ListenerConfig)
+//! with a [`TlsServerConfig`] serves its line protocol or WebSocket frames
+//! over TLS (`wss://`), with the server's certificate and key read from PEM
+//! files. With a client CA it also requires every client to present a
+//! certificate signed by that CA, and hands the certificate to the
+//! handler's auth provider, so
+//! [`ClientCertificates`](temp_protocol::auth::ClientCertificates) can tell
+//! who the session is without a token.
+//!
+//! [`TlsClientConfig`] is the other end: it trusts the server's CA and may
+//! present a client certificate. [`connect`] wraps any stream, a direct one
+//! or one through a [proxy](crate::proxy), and checks the server's
+//! certificate against the host the client asked for.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::client;
+
+pub use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use crate::proxy;
+
+/// Environment variable the command line tools take the server's CA
+/// certificate from; TLS is off without it.
+pub const CA_VAR: &str = "TEMP_TLS_CA";
+/// Environment variable the command line tools take a client certificate from.
+pub const CERT_VAR: &str = "TEMP_TLS_CERT";
+/// Environment variable the command line tools take the client certificate's key from.
+pub const KEY_VAR: &str = "TEMP_TLS_KEY";
+
+/// TLS for a listener, e.g. in TOML
+/// `tls = { cert = "/etc/temp/server.pem", key = "/etc/temp/server.key" }`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsServerConfig {
+    /// The server's certificate chain, leaf first.
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// Requires client certificates signed by one of these CAs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ca: Option<PathBuf>,
+}
+
+impl TlsServerConfig {
+    pub fn new(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        Self {
+            cert: cert.into(),
+            key: key.into(),
+            client_ca: None,
+        }
+    }
+
+    pub fn with_client_ca(mut self, path: impl Into<PathBuf>) -> Self {
+        self.client_ca = Some(path.into());
+        self
+    }
+
+    /// Reads the certificates and key; a missing or invalid file fails here
+    /// rather than on the first connection.
+    pub fn acceptor(&self) -> io::Result<TlsAcceptor> {
+        let provider = provider();
+        let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_data)?;
+        let builder = match &self.client_ca {
+            Some(path) => {
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(load_roots(path)?), provider)
+                    .build()
+                    .map_err(invalid_data)?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let config = builder
+            .with_single_cert(load_certs(&self.cert)?, load_key(&self.key)?)
+            .map_err(invalid_data)?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// TLS for a client connection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsClientConfig {
+    /// The CA the server's certificate has to be signed by.
+    pub ca: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<PathBuf>,
+}
+
+impl TlsClientConfig {
+    pub fn new(ca: impl Into<PathBuf>) -> Self {
+        Self {
+            ca: ca.into(),
+            cert: None,
+            key: None,
+        }
+    }
+
+    /// Presents this certificate to servers that ask for one.
+    pub fn with_client_cert(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.cert = Some(cert.into());
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Reads [`CA_VAR`], [`CERT_VAR`] and [`KEY_VAR`]; `None` without a CA.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+        let (ca, cert, key) = (var(CA_VAR), var(CERT_VAR), var(KEY_VAR));
+        match (ca, cert, key) {
+            (None, None, None) => Ok(None),
+            (Some(ca), None, None) => Ok(Some(Self::new(ca))),
+            (Some(ca), Some(cert), Some(key)) => Ok(Some(Self::new(ca).with_client_cert(cert, key))),
+            (None, _, _) => {
+                let message = format!("{} and {} need {}", CERT_VAR, KEY_VAR, CA_VAR);
+                Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+            }
+            _ => {
+                let message = format!("{} and {} have to be set together", CERT_VAR, KEY_VAR);
+                Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+            }
+        }
+    }
+
+    pub fn connector(&self) -> io::Result<TlsConnector> {
+        let builder = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_data)?
+            .with_root_certificates(load_roots(&self.ca)?);
+        let config = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+                .map_err(invalid_data)?,
+            _ => builder.with_no_client_auth(),
+        };
+        Ok(TlsConnector::from(Arc::new(config)))
+    }
+}
+
+/// Starts TLS on `stream`, connected to `target` (`host:port`), checking
+/// the server's certificate against the host.
+pub async fn connect<S>(stream: S, target: &str, connector: &TlsConnector) -> io::Result<client::TlsStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (host, _) = proxy::target_host_port(target)?;
+    let name = ServerName::try_from(host.to_string()).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid server name '{}': {}", host, e))
+    })?;
+    connector.connect(name, stream).await
+}
+
+/// Set explicitly rather than installed as the process default, which
+/// would clash with another library installing its own.
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| pem_error(path, e))?;
+    if certs.is_empty() {
+        let message = format!("No certificate in {}", path.display());
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path).map_err(|e| pem_error(path, e))
+}
+
+fn load_roots(path: &Path) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert).map_err(invalid_data)?;
+    }
+    Ok(roots)
+}
+
+fn pem_error(path: &Path, error: rustls::pki_types::pem::Error) -> io::Error {
+    match error {
+        rustls::pki_types::pem::Error::Io(e) => io::Error::new(e.kind(), format!("{}: {}", path.display(), e)),
+        e => io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)),
+    }
+}
+
+fn invalid_data(error: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
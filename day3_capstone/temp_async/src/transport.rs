@@ -0,0 +1,593 @@
+//! Protocol messages over a byte stream, one JSON message per line.
+//!
+//! [`ProtocolClient`] multiplexes concurrent requests over one connection:
+//! each request gets its own message id, and a reader task routes every
+//! response to the caller waiting on that id, so callers never queue behind
+//! each other's round trips. [`ClientPool`] spreads requests over several
+//! connections, and [`serve_connection`] answers a connection from a handler.
+//!
+//! [`ProtocolClient::connect`] opens the connection itself, through a
+//! [proxy](crate::proxy) and over [TLS](crate::tls) if asked to.
+//!
+//! A client can also cache stats and storage info responses for a while
+//! ([`ProtocolClient::with_cache`]), dropping entries as soon as it sends a
+//! command that changes what they describe.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use temp_store::error_hook::{self, SwallowedKind};
+use temp_core::SensorId;
+use temp_protocol::ids::{IdGenerator, MessageId, MonotonicIds};
+use temp_protocol::trace::{self, TraceContext};
+use temp_protocol::ingest::ExternalReading;
+use temp_protocol::{Command, Headers, MessageBuffers, MessagePayload, ProtocolMessage, Response, SessionState, TemperatureProtocolHandler};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::cache::LruCache;
+use crate::proxy::{self, ProxyConfig};
+use crate::tls::{self, TlsConnector};
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The connection closed before the response arrived.
+    Closed,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "Connection error: {}", e),
+            ClientError::Json(e) => write!(f, "Invalid message: {}", e),
+            ClientError::Closed => write!(f, "Connection closed"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(e: serde_json::Error) -> Self {
+        ClientError::Json(e)
+    }
+}
+
+/// Callers waiting for a response, by message id; `None` once the connection closed.
+type Pending = Arc<Mutex<Option<HashMap<MessageId, oneshot::Sender<Response>>>>>;
+
+fn lock(pending: &Pending) -> MutexGuard<'_, Option<HashMap<MessageId, oneshot::Sender<Response>>>> {
+    error_hook::lock(pending, "Recovered the pending requests lock")
+}
+
+/// Which read responses a client caches, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CachePolicy {
+    pub capacity: usize,
+    pub stats_ttl: Duration,
+    pub storage_info_ttl: Duration,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            capacity: 32,
+            stats_ttl: Duration::from_secs(5),
+            storage_info_ttl: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    Stats(SensorId),
+    StorageInfo,
+}
+
+/// Cache entries a command makes stale once it succeeded.
+enum Invalidation {
+    Nothing,
+    Sensor(SensorId),
+    Everything,
+}
+
+fn invalidation(command: &Command) -> Invalidation {
+    match command {
+        Command::GetStatus
+        | Command::GetHistory { .. }
+        | Command::GetStats { .. }
+        | Command::GetRange { .. }
+        | Command::GetStorageInfo
+        | Command::GetDegreeDays { .. }
+        | Command::GetForecast { .. }
+        | Command::GetHealth
+        | Command::ListAlerts { .. }
+        | Command::QueryAlertHistory { .. }
+        | Command::ExportCalibrations
+        | Command::ExportBackup { .. }
+        | Command::SetLanguage { .. }
+        | Command::SetCommandEnabled { .. }
+        | Command::GetCommandFlags
+        | Command::GetSensorInfo { .. }
+        | Command::SetTags { .. }
+        | Command::QuerySensors { .. }
+        // Only affects readings taken from now on
+        | Command::SetTransforms { .. } => Invalidation::Nothing,
+        // Taking a reading adds it to the sensor's store
+        Command::GetReading { sensor_id }
+        | Command::Calibrate { sensor_id, .. }
+        | Command::StartEpoch { sensor_id }
+        | Command::SubmitReading { reading: ExternalReading { sensor_id, .. }, .. }
+        | Command::SetSensorState { sensor_id, .. }
+        | Command::RestoreBackup { sensor_id, .. } => Invalidation::Sensor(sensor_id.clone()),
+        // Another identity may be granted other sensors
+        Command::Authenticate { .. } => Invalidation::Everything,
+        _ => Invalidation::Everything,
+    }
+}
+
+struct ResponseCache {
+    policy: CachePolicy,
+    entries: LruCache<CacheKey, Response>,
+}
+
+impl ResponseCache {
+    fn key(&self, command: &Command) -> Option<(CacheKey, Duration)> {
+        match command {
+            Command::GetStats { sensor_id } => Some((CacheKey::Stats(sensor_id.clone()), self.policy.stats_ttl)),
+            Command::GetStorageInfo => Some((CacheKey::StorageInfo, self.policy.storage_info_ttl)),
+            _ => None,
+        }
+    }
+
+    fn invalidate(&mut self, stale: Invalidation) {
+        match stale {
+            Invalidation::Nothing => {}
+            Invalidation::Sensor(sensor_id) => self.entries.retain(|key| match key {
+                CacheKey::Stats(id) => *id != sensor_id,
+                CacheKey::StorageInfo => false,
+            }),
+            Invalidation::Everything => self.entries.clear(),
+        }
+    }
+}
+
+pub struct ProtocolClient {
+    writer: tokio::sync::Mutex<Box<dyn AsyncWrite + Send + Unpin>>,
+    pending: Pending,
+    ids: Mutex<Box<dyn IdGenerator>>,
+    reader: JoinHandle<()>,
+    cache: Option<Mutex<ResponseCache>>,
+}
+
+impl ProtocolClient {
+    /// Starts routing responses read from `connection`; needs a tokio runtime.
+    pub fn new<T>(connection: T) -> Self
+    where
+        T: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (read_half, write_half) = tokio::io::split(connection);
+        let pending: Pending = Arc::new(Mutex::new(Some(HashMap::new())));
+        let reader = tokio::spawn(route_responses(read_half, Arc::clone(&pending)));
+        Self {
+            writer: tokio::sync::Mutex::new(Box::new(write_half)),
+            pending,
+            ids: Mutex::new(Box::new(MonotonicIds::new())),
+            reader,
+            cache: None,
+        }
+    }
+
+    /// Connects to `target` (`host:port`), through `proxy` if there is one
+    /// and over TLS if there is a `tls` connector.
+    pub async fn connect(target: &str, proxy: Option<&ProxyConfig>, tls: Option<&TlsConnector>) -> io::Result<Self> {
+        let stream = proxy::connect(target, proxy).await?;
+        Ok(match tls {
+            Some(connector) => Self::new(tls::connect(stream, target, connector).await?),
+            None => Self::new(stream),
+        })
+    }
+
+    /// Replaces the message id generator; [`temp_protocol::ids::UlidIds`]
+    /// keeps ids unique across reconnects.
+    pub fn with_id_generator(mut self, ids: impl IdGenerator + 'static) -> Self {
+        self.ids = Mutex::new(Box::new(ids));
+        self
+    }
+
+    /// Answers `GetStats` and `GetStorageInfo` from a cache while fresh.
+    pub fn with_cache(mut self, policy: CachePolicy) -> Self {
+        self.cache = Some(Mutex::new(ResponseCache {
+            policy,
+            entries: LruCache::new(policy.capacity),
+        }));
+        self
+    }
+
+    /// Drops every cached response.
+    pub fn invalidate_cache(&self) {
+        if let Some(cache) = &self.cache {
+            error_hook::lock(cache, "Recovered the response cache lock").entries.clear();
+        }
+    }
+
+    /// Sends `command` and waits for its response. Other requests may be sent
+    /// and answered while this one is in flight.
+    pub async fn request(&self, command: Command) -> Result<Response, ClientError> {
+        self.request_with(command, Headers::new()).await
+    }
+
+    /// Sends `command` as part of the trace `context`, so the server's spans
+    /// join it; see [`temp_protocol::trace`].
+    pub async fn request_traced(&self, command: Command, context: &TraceContext) -> Result<Response, ClientError> {
+        let headers = Headers::from([(trace::TRACEPARENT.to_string(), context.to_string())]);
+        self.request_with(command, headers).await
+    }
+
+    async fn request_with(&self, command: Command, headers: Headers) -> Result<Response, ClientError> {
+        let Some(cache) = &self.cache else {
+            return self.send(command, headers).await;
+        };
+
+        let (key, stale) = {
+            let mut cache = error_hook::lock(cache, "Recovered the response cache lock");
+            let key = cache.key(&command);
+            if let Some(response) = key.as_ref().and_then(|(key, _)| cache.entries.get(key, Instant::now())) {
+                return Ok(response);
+            }
+            (key, invalidation(&command))
+        };
+
+        let response = self.send(command, headers).await?;
+        if !matches!(response, Response::Error { .. }) {
+            let mut cache = error_hook::lock(cache, "Recovered the response cache lock");
+            cache.invalidate(stale);
+            if let Some((key, ttl)) = key {
+                cache.entries.insert(key, response.clone(), ttl, Instant::now());
+            }
+        }
+        Ok(response)
+    }
+
+    async fn send(&self, command: Command, headers: Headers) -> Result<Response, ClientError> {
+        let id = error_hook::lock(&self.ids, "Recovered the message id lock").next_id();
+        let message = ProtocolMessage {
+            version: 1,
+            id,
+            payload: MessagePayload::Command(command),
+            headers,
+        };
+        let mut line = serde_json::to_string(&message)?;
+        line.push('\n');
+
+        let (tx, rx) = oneshot::channel();
+        match lock(&self.pending).as_mut() {
+            Some(waiting) => waiting.insert(id, tx),
+            None => return Err(ClientError::Closed),
+        };
+
+        // The writer lock covers a single line, not the round trip
+        let written = async {
+            let mut writer = self.writer.lock().await;
+            writer.write_all(line.as_bytes()).await?;
+            writer.flush().await
+        };
+        if let Err(e) = written.await {
+            if let Some(waiting) = lock(&self.pending).as_mut() {
+                waiting.remove(&id);
+            }
+            return Err(e.into());
+        }
+
+        rx.await.map_err(|_| ClientError::Closed)
+    }
+
+    /// Requests sent and not answered yet.
+    pub fn in_flight(&self) -> usize {
+        lock(&self.pending).as_ref().map_or(0, HashMap::len)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        lock(&self.pending).is_none()
+    }
+}
+
+impl Drop for ProtocolClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+async fn route_responses<R: AsyncRead + Unpin>(reader: R, pending: Pending) {
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                let message: ProtocolMessage = match serde_json::from_str(&line) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        error_hook::report(SwallowedKind::Protocol, "Ignoring malformed message", &e);
+                        continue;
+                    }
+                };
+                let MessagePayload::Response(response) = message.payload else {
+                    let context = format!("Ignoring command {}", message.id);
+                    error_hook::report(SwallowedKind::Protocol, &context, &"commands cannot be sent to a client");
+                    continue;
+                };
+                let waiting = lock(&pending).as_mut().and_then(|waiting| waiting.remove(&message.id));
+                match waiting {
+                    Some(tx) => {
+                        if tx.send(response).is_err() {
+                            let context = format!("Requester of {} went away", message.id);
+                            error_hook::report(SwallowedKind::ReplyDropped, &context, &"reply dropped");
+                        }
+                    }
+                    None => {
+                        let context = format!("Ignoring response to {}", message.id);
+                        error_hook::report(SwallowedKind::Protocol, &context, &"unknown request");
+                    }
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                error_hook::report(SwallowedKind::Protocol, "Connection failed", &e);
+                break;
+            }
+        }
+    }
+    // Dropping the senders fails every waiting request with `Closed`
+    lock(&pending).take();
+}
+
+/// Spreads requests over several connections, picking the open one with the
+/// fewest requests in flight.
+pub struct ClientPool {
+    clients: Vec<ProtocolClient>,
+    next: AtomicUsize,
+}
+
+impl ClientPool {
+    pub fn new(clients: Vec<ProtocolClient>) -> Self {
+        Self {
+            clients,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub async fn request(&self, command: Command) -> Result<Response, ClientError> {
+        self.pick().ok_or(ClientError::Closed)?.request(command).await
+    }
+
+    fn pick(&self) -> Option<&ProtocolClient> {
+        // Rotate the starting point so ties don't always go to the first client
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let count = self.clients.len();
+        (0..count)
+            .map(|offset| &self.clients[(start + offset) % count])
+            .filter(|client| !client.is_closed())
+            .min_by_key(|client| client.in_flight())
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+}
+
+/// Answers commands read from `connection` until the peer closes it.
+pub async fn serve_connection<T>(connection: T, handler: Arc<Mutex<TemperatureProtocolHandler>>) -> io::Result<()>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    let (read_half, mut write_half) = tokio::io::split(connection);
+    let mut lines = BufReader::new(read_half).lines();
+    // Language and identity are negotiated per connection
+    let mut session = SessionState::default();
+    let buffers = error_hook::lock(&handler, "Recovered the protocol handler lock").buffers();
+    while let Some(line) = lines.next_line().await? {
+        let Some(response) = answer(&handler, &line, &mut session) else {
+            continue;
+        };
+        write_line(&mut write_half, &buffers, response).await?;
+    }
+    Ok(())
+}
+
+/// Sends `response` as one line, encoded into a pooled buffer, and hands
+/// its buffers back.
+pub(crate) async fn write_line(
+    out: &mut (impl AsyncWrite + Unpin),
+    buffers: &MessageBuffers,
+    response: ProtocolMessage,
+) -> io::Result<()> {
+    let mut line = buffers.encode_json(&response).map_err(io::Error::other)?;
+    line.push(b'\n');
+    out.write_all(&line).await?;
+    out.flush().await?;
+    buffers.recycle(response);
+    Ok(())
+}
+
+/// Processes one serialized message for `session`, which picks up the
+/// language or identity the message negotiated. Malformed messages are
+/// logged and get no response.
+pub(crate) fn answer(
+    handler: &Mutex<TemperatureProtocolHandler>,
+    line: &str,
+    session: &mut SessionState,
+) -> Option<ProtocolMessage> {
+    let message: ProtocolMessage = match serde_json::from_str(line) {
+        Ok(message) => message,
+        Err(e) => {
+            error_hook::report(SwallowedKind::Protocol, "Ignoring malformed message", &e);
+            return None;
+        }
+    };
+    let response =
+        error_hook::lock(handler, "Recovered the protocol handler lock").process_command_for(message, session);
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    #[tokio::test]
+    async fn matches_out_of_order_responses_by_id() {
+        let (client_side, server_side) = duplex(4096);
+        let client = ProtocolClient::new(client_side);
+
+        // A server that answers two requests in reverse order
+        let server = tokio::spawn(async move {
+            let (read_half, mut write_half) = tokio::io::split(server_side);
+            let mut lines = BufReader::new(read_half).lines();
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let line = lines.next_line().await.unwrap().unwrap();
+                requests.push(serde_json::from_str::<ProtocolMessage>(&line).unwrap());
+            }
+            for request in requests.into_iter().rev() {
+                let MessagePayload::Command(Command::GetReading { sensor_id }) = request.payload else {
+                    panic!("Unexpected request: {:?}", request);
+                };
+                let response = ProtocolMessage {
+                    version: 1,
+                    id: request.id,
+                    payload: MessagePayload::Response(Response::Reading { sensor_id, temperature: 20.0, timestamp: 0 }),
+                    headers: Headers::new(),
+                };
+                let line = serde_json::to_string(&response).unwrap() + "\n";
+                write_half.write_all(line.as_bytes()).await.unwrap();
+            }
+            lines
+        });
+
+        let (first, second) = tokio::join!(
+            client.request(Command::GetReading { sensor_id: "a".parse().unwrap() }),
+            client.request(Command::GetReading { sensor_id: "b".parse().unwrap() }),
+        );
+        assert!(matches!(first.unwrap(), Response::Reading { sensor_id, .. } if sensor_id == "a"));
+        assert!(matches!(second.unwrap(), Response::Reading { sensor_id, .. } if sensor_id == "b"));
+        assert_eq!(client.in_flight(), 0);
+
+        // Once the server hangs up, requests fail instead of waiting forever
+        drop(server.await.unwrap());
+        let closed = client.request(Command::GetStatus).await;
+        assert!(matches!(closed, Err(ClientError::Closed | ClientError::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn pool_spreads_requests_over_served_connections() {
+        let handler = Arc::new(Mutex::new(TemperatureProtocolHandler::new()));
+        let mut clients = Vec::new();
+        for _ in 0..2 {
+            let (client_side, server_side) = duplex(4096);
+            tokio::spawn(serve_connection(server_side, Arc::clone(&handler)));
+            clients.push(ProtocolClient::new(client_side));
+        }
+        let pool = Arc::new(ClientPool::new(clients));
+
+        let mut requests = tokio::task::JoinSet::new();
+        for _ in 0..10 {
+            let pool = Arc::clone(&pool);
+            requests.spawn(async move { pool.request(Command::GetStatus).await });
+        }
+        let responses = requests.join_all().await;
+        assert_eq!(responses.len(), 10);
+        assert!(responses.into_iter().all(|r| matches!(r, Ok(Response::Status { .. }))));
+
+        assert!(matches!(ClientPool::new(Vec::new()).request(Command::GetStatus).await, Err(ClientError::Closed)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn caches_stats_until_expired_or_invalidated() {
+        let handler = Arc::new(Mutex::new(TemperatureProtocolHandler::new()));
+        let (client_side, server_side) = duplex(4096);
+        tokio::spawn(serve_connection(server_side, Arc::clone(&handler)));
+        let client = ProtocolClient::new(client_side).with_cache(CachePolicy::default());
+
+        // Readings taken behind the client's back
+        let read_on_server = || {
+            let mut handler = handler.lock().unwrap();
+            let command = handler.create_command(Command::GetReading { sensor_id: "temp_01".parse().unwrap() });
+            handler.process_command(command);
+        };
+        let stats_count = || async {
+            match client.request(Command::GetStats { sensor_id: "temp_01".parse().unwrap() }).await.unwrap() {
+                Response::Stats { stats, .. } => stats.count,
+                other => panic!("Unexpected response: {:?}", other),
+            }
+        };
+
+        read_on_server();
+        assert_eq!(stats_count().await, 1);
+        read_on_server();
+        assert_eq!(stats_count().await, 1);
+
+        tokio::time::advance(CachePolicy::default().stats_ttl).await;
+        assert_eq!(stats_count().await, 2);
+
+        // A reading taken through the client invalidates the sensor's stats
+        client.request(Command::GetReading { sensor_id: "temp_01".parse().unwrap() }).await.unwrap();
+        assert_eq!(stats_count().await, 3);
+    }
+
+    #[tokio::test]
+    async fn each_connection_authenticates_on_its_own() {
+        use temp_protocol::auth::StaticTokens;
+        use temp_protocol::Identity;
+
+        let tokens = StaticTokens::new().with_token("s3cret", Identity::new("kitchen-display"));
+        let handler = Arc::new(Mutex::new(TemperatureProtocolHandler::new().with_auth(tokens)));
+        let mut clients = Vec::new();
+        for _ in 0..2 {
+            let (client_side, server_side) = duplex(4096);
+            tokio::spawn(serve_connection(server_side, Arc::clone(&handler)));
+            clients.push(ProtocolClient::new(client_side));
+        }
+
+        let authenticated = clients[0].request(Command::Authenticate { token: "s3cret".to_string() }).await.unwrap();
+        assert!(matches!(authenticated, Response::Authenticated { identity } if identity.subject == "kitchen-display"));
+        assert!(matches!(clients[0].request(Command::GetStatus).await, Ok(Response::Status { .. })));
+        assert!(matches!(clients[1].request(Command::GetStatus).await, Ok(Response::Error { code: 401, .. })));
+    }
+
+    #[tokio::test]
+    async fn traced_requests_join_the_callers_trace() {
+        use temp_protocol::trace::RecordingExporter;
+
+        let exporter = RecordingExporter::new();
+        let handler = Arc::new(Mutex::new(TemperatureProtocolHandler::new().with_tracing(exporter.clone())));
+        let (client_side, server_side) = duplex(4096);
+        tokio::spawn(serve_connection(server_side, handler));
+        let client = ProtocolClient::new(client_side);
+
+        let caller = TraceContext::new_root();
+        let response = client.request_traced(Command::GetStats { sensor_id: "temp_01".parse().unwrap() }, &caller).await.unwrap();
+        assert!(matches!(response, Response::Stats { .. }));
+
+        let spans = exporter.spans();
+        assert_eq!(spans.iter().map(|span| span.name.as_str()).collect::<Vec<_>>(), ["store.get_stats", "GetStats"]);
+        assert!(spans.iter().all(|span| span.context.trace_id == caller.trace_id));
+        assert_eq!(spans[1].parent_span_id, Some(caller.span_id));
+    }
+}
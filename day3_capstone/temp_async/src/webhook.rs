@@ -0,0 +1,215 @@
+//! Delivery of outbound webhooks, see [`temp_alert::webhook`].
+//!
+//! A [`WebhookSender`] posts each payload to every webhook that wants it,
+//! concurrently and in a task of its own, so a slow endpoint holds up
+//! neither the monitor nor the other webhooks. A post that fails, times out
+//! or is answered with a 5xx, 408 or 429 is retried after the webhook's
+//! backoff, signed afresh; any other status is final. A payload that could
+//! not be delivered is reported through the error hook and dropped.
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use temp_alert::webhook::{Webhook, WebhookError, WebhookPayload, WebhookRequest};
+use temp_store::error_hook::{self, SwallowedKind};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{sleep, timeout};
+
+use crate::proxy::{self, ProxyConfig};
+
+/// How long one post may take, connecting included.
+pub const POST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest status line accepted from an endpoint.
+const MAX_STATUS_LINE: u64 = 1024;
+
+#[derive(Debug, Clone)]
+pub struct WebhookSender {
+    webhooks: Arc<[Webhook]>,
+    proxy: Option<ProxyConfig>,
+}
+
+impl WebhookSender {
+    /// Fails on the first webhook whose URL, headers or template are invalid.
+    pub fn new(webhooks: Vec<Webhook>) -> Result<Self, WebhookError> {
+        webhooks.iter().try_for_each(Webhook::validate)?;
+        Ok(Self { webhooks: webhooks.into(), proxy: None })
+    }
+
+    /// Connects through `proxy`, e.g. one terminating TLS for HTTPS endpoints.
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    pub fn webhooks(&self) -> &[Webhook] {
+        &self.webhooks
+    }
+
+    /// Starts delivering `payload`; the task resolves to how many webhooks
+    /// took it. Awaiting it is optional.
+    pub fn send(&self, payload: WebhookPayload) -> JoinHandle<usize> {
+        let payload = Arc::new(payload);
+        let mut deliveries = JoinSet::new();
+        for webhook in self.webhooks.iter().filter(|webhook| webhook.wants(&payload)) {
+            deliveries.spawn(deliver(webhook.clone(), Arc::clone(&payload), self.proxy.clone()));
+        }
+        tokio::spawn(async move {
+            let mut delivered = 0;
+            while let Some(result) = deliveries.join_next().await {
+                match result {
+                    Ok(true) => delivered += 1,
+                    Ok(false) => {}
+                    Err(e) => error_hook::report(SwallowedKind::TaskFailed, "Webhook delivery failed", &e),
+                }
+            }
+            delivered
+        })
+    }
+}
+
+async fn deliver(webhook: Webhook, payload: Arc<WebhookPayload>, proxy: Option<ProxyConfig>) -> bool {
+    let mut attempt = 1;
+    loop {
+        let request = match webhook.request(&payload, crate::unix_now()) {
+            Ok(request) => request,
+            Err(e) => {
+                error_hook::report(SwallowedKind::Notification, &format!("Cannot render webhook {}", webhook.url), &e);
+                return false;
+            }
+        };
+        let error = match timeout(POST_TIMEOUT, post(&request, proxy.as_ref())).await {
+            Ok(Ok(status)) if (200..300).contains(&status) => return true,
+            Ok(Ok(status)) if status >= 500 || status == 408 || status == 429 => format!("HTTP {}", status),
+            Ok(Ok(status)) => {
+                let context = format!("Webhook {} refused the payload", webhook.url);
+                error_hook::report(SwallowedKind::Notification, &context, &format_args!("HTTP {}", status));
+                return false;
+            }
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => format!("no answer within {:?}", POST_TIMEOUT),
+        };
+
+        match webhook.retry_delay(attempt) {
+            Some(delay) => sleep(Duration::from_secs(delay)).await,
+            None => {
+                let context = format!("Gave up on webhook {} after {} attempts", webhook.url, attempt);
+                error_hook::report(SwallowedKind::Notification, &context, &error);
+                return false;
+            }
+        }
+        attempt += 1;
+    }
+}
+
+/// Posts `request` and returns the response's status code.
+pub async fn post(request: &WebhookRequest, proxy: Option<&ProxyConfig>) -> io::Result<u16> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let rest = request.url.strip_prefix("http://").ok_or_else(|| invalid(format!("Not an http:// URL: {}", request.url)))?;
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    let target = match proxy::split_host_port(authority) {
+        Some(_) => authority.to_string(),
+        None => format!("{}:80", authority),
+    };
+
+    let mut stream = proxy::connect(&target, proxy).await?;
+    let mut head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        path,
+        authority,
+        request.body.len()
+    );
+    for (name, value) in &request.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(request.body.as_bytes()).await?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).take(MAX_STATUS_LINE).read_line(&mut status_line).await?;
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Not an HTTP response: {:?}", status_line.trim_end())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temp_alert::webhook::{sign, AlertNotice, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+    use temp_alert::Severity;
+    use tokio::net::TcpListener;
+
+    /// Answers each connection with the next status and returns the requests.
+    async fn endpoint(statuses: &'static [u16]) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hooks/alerts", listener.local_addr().unwrap());
+        let task = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                // The body ends with the JSON's closing brace
+                while !request.ends_with(b"}") {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let answer = format!("HTTP/1.1 {} Whatever\r\nContent-Length: 0\r\n\r\n", status);
+                stream.write_all(answer.as_bytes()).await.unwrap();
+                requests.push(String::from_utf8(request).unwrap());
+            }
+            requests
+        });
+        (url, task)
+    }
+
+    fn alert() -> WebhookPayload {
+        WebhookPayload::Alert(AlertNotice {
+            event: "fired".to_string(),
+            alert_id: 7,
+            sensor_id: "freezer".to_string(),
+            kind: "high temperature".to_string(),
+            severity: Severity::Critical,
+            message: "freezer is -10.0°C, high temperature limit -15.0°C".to_string(),
+            value: -10.0,
+            timestamp: 1_700_000_000,
+        })
+    }
+
+    #[tokio::test]
+    async fn posts_signed_payloads_and_retries() {
+        let (url, requests) = endpoint(&[503, 204]).await;
+        let webhook = Webhook::new(&url)
+            .with_template(r#"{"text": "{{message}}"}"#)
+            .with_secret("s3cret")
+            .with_retries(3, 0);
+        let quiet = Webhook::new(&url).with_min_severity(Severity::Critical).with_retries(1, 0);
+        let sender = WebhookSender::new(vec![webhook]).unwrap();
+
+        assert_eq!(sender.send(alert()).await.unwrap(), 1);
+        let requests = requests.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        let request = &requests[1];
+        assert!(request.starts_with("POST /hooks/alerts HTTP/1.1\r\n"));
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        assert_eq!(body, r#"{"text": "freezer is -10.0°C, high temperature limit -15.0°C"}"#);
+        let header = |name: &str| head.lines().find_map(|line| line.strip_prefix(&format!("{}: ", name))).unwrap();
+        let timestamp: u64 = header(TIMESTAMP_HEADER).parse().unwrap();
+        assert_eq!(header(SIGNATURE_HEADER), format!("sha256={}", sign("s3cret", timestamp, body)));
+
+        // Final refusals are not retried
+        let (url, requests) = endpoint(&[400]).await;
+        let sender = WebhookSender::new(vec![Webhook { url, ..quiet }]).unwrap();
+        assert_eq!(sender.send(alert()).await.unwrap(), 0);
+        assert_eq!(requests.await.unwrap().len(), 1);
+
+        assert!(WebhookSender::new(vec![Webhook::new("https://hooks.example")]).is_err());
+    }
+}
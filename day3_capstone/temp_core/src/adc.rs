@@ -0,0 +1,148 @@
+//! Converting between ADC counts and temperatures for analog sensors.
+//!
+//! An analog sensor puts out `offset` millivolts at 0 °C plus `mv_per_degree`
+//! per degree, which an ADC with `bits` of resolution samples against `vref`
+//! volts. Boards differ in all four, so they are configuration rather than
+//! constants in the conversion code: an LM35 on a 12-bit 3.3 V ADC is
+//! [`AdcConfig::DEFAULT`], a TMP36 on a 10-bit one is
+//! `AdcConfig::new(3.3, 10, 10.0, 500.0)`.
+
+use core::fmt;
+
+use crate::Temperature;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdcConfig {
+    vref: f32,
+    bits: u8,
+    mv_per_degree: f32,
+    offset: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdcError {
+    NotFinite,
+    NonPositiveReference,
+    /// Counts are reported as `u16`.
+    UnsupportedResolution,
+    ZeroSlope,
+}
+
+impl fmt::Display for AdcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdcError::NotFinite => write!(f, "ADC parameters must be finite numbers"),
+            AdcError::NonPositiveReference => write!(f, "Reference voltage must be positive"),
+            AdcError::UnsupportedResolution => write!(f, "ADC resolution must be 1 to 16 bits"),
+            AdcError::ZeroSlope => write!(f, "Sensor slope must not be zero"),
+        }
+    }
+}
+
+impl AdcConfig {
+    /// 10 mV/°C sensor without offset (LM35) on a 12-bit ADC with a 3.3 V reference.
+    pub const DEFAULT: AdcConfig = AdcConfig {
+        vref: 3.3,
+        bits: 12,
+        mv_per_degree: 10.0,
+        offset: 0.0,
+    };
+
+    /// `vref` in volts, `mv_per_degree` and `offset` (the output at 0 °C) in millivolts.
+    pub const fn new(vref: f32, bits: u8, mv_per_degree: f32, offset: f32) -> Result<Self, AdcError> {
+        if !vref.is_finite() || !mv_per_degree.is_finite() || !offset.is_finite() {
+            return Err(AdcError::NotFinite);
+        }
+        if vref <= 0.0 {
+            return Err(AdcError::NonPositiveReference);
+        }
+        if bits == 0 || bits > 16 {
+            return Err(AdcError::UnsupportedResolution);
+        }
+        if mv_per_degree == 0.0 {
+            return Err(AdcError::ZeroSlope);
+        }
+        Ok(Self { vref, bits, mv_per_degree, offset })
+    }
+
+    pub const fn vref(&self) -> f32 {
+        self.vref
+    }
+
+    pub const fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    pub const fn mv_per_degree(&self) -> f32 {
+        self.mv_per_degree
+    }
+
+    pub const fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    /// The highest count the ADC reports, at `vref`.
+    pub const fn max_count(&self) -> u16 {
+        ((1u32 << self.bits) - 1) as u16
+    }
+
+    pub const fn to_celsius(&self, count: u16) -> f32 {
+        let millivolts = count as f32 / self.max_count() as f32 * self.vref * 1000.0;
+        (millivolts - self.offset) / self.mv_per_degree
+    }
+
+    /// The count the ADC reads at `celsius`, clamped to its range.
+    pub const fn to_count(&self, celsius: f32) -> u16 {
+        let millivolts = celsius * self.mv_per_degree + self.offset;
+        let count = millivolts / (self.vref * 1000.0) * self.max_count() as f32 + 0.5;
+        if count <= 0.0 {
+            0
+        } else if count >= self.max_count() as f32 {
+            self.max_count()
+        } else {
+            count as u16
+        }
+    }
+
+    pub const fn temperature(&self, count: u16) -> Temperature {
+        Temperature::new(self.to_celsius(count))
+    }
+}
+
+impl Default for AdcConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_both_ways_for_different_boards() {
+        let lm35 = AdcConfig::DEFAULT;
+        assert_eq!(lm35.max_count(), 4095);
+        assert!((lm35.to_celsius(310) - 24.98).abs() < 0.01);
+        assert!((lm35.to_celsius(lm35.to_count(23.5)) - 23.5).abs() < 0.05);
+
+        // TMP36 on a 10-bit ADC: 750 mV at 25 °C
+        let tmp36 = AdcConfig::new(3.3, 10, 10.0, 500.0).unwrap();
+        assert_eq!(tmp36.to_count(25.0), 233);
+        assert!((tmp36.to_celsius(233) - 25.16).abs() < 0.01);
+        assert!(tmp36.to_celsius(0) < -49.9);
+
+        // Out of range temperatures clamp to the ADC's range
+        assert_eq!(tmp36.to_count(-100.0), 0);
+        assert_eq!(tmp36.to_count(500.0), 1023);
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        assert_eq!(AdcConfig::new(f32::NAN, 12, 10.0, 0.0), Err(AdcError::NotFinite));
+        assert_eq!(AdcConfig::new(0.0, 12, 10.0, 0.0), Err(AdcError::NonPositiveReference));
+        assert_eq!(AdcConfig::new(3.3, 0, 10.0, 0.0), Err(AdcError::UnsupportedResolution));
+        assert_eq!(AdcConfig::new(3.3, 17, 10.0, 0.0), Err(AdcError::UnsupportedResolution));
+        assert_eq!(AdcConfig::new(3.3, 12, 0.0, 0.0), Err(AdcError::ZeroSlope));
+    }
+}
@@ -0,0 +1,273 @@
+//! Correcting a sensor's raw readings against a reference.
+//!
+//! A [`Calibration`] maps what the sensor reports to the true temperature:
+//! a linear correction fixes offset and gain errors, which is what comparing
+//! against a reference thermometer at one or two points yields; a polynomial
+//! (up to cubic) also fixes curvature across a wide range. A
+//! [`CalibratedSensor`] wraps any [`TemperatureSensor`] and applies its
+//! calibration to every reading.
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::environment::{EnvironmentalReading, EnvironmentalSensor};
+use crate::info::{MeasurementRange, SensorInfo};
+use crate::{SensorHealth, Temperature, TemperatureSensor};
+
+/// Correction from the raw reading `x` in °C to the true temperature.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Calibration {
+    /// `gain * x + offset`.
+    Linear { offset: f32, gain: f32 },
+    /// `c[0] + c[1] x + c[2] x² + c[3] x³`.
+    Polynomial { coefficients: [f32; 4] },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalibrationError {
+    NotFinite,
+    /// The correction would map every reading to the same temperature.
+    ZeroGain,
+    /// Two-point calibration needs two different raw readings.
+    SamePoints,
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::NotFinite => write!(f, "Calibration coefficients must be finite numbers"),
+            CalibrationError::ZeroGain => write!(f, "Calibration gain must not be zero"),
+            CalibrationError::SamePoints => write!(f, "Calibration points must have different raw readings"),
+        }
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Calibration {
+    /// Leaves readings as they are.
+    pub const IDENTITY: Calibration = Calibration::Linear { offset: 0.0, gain: 1.0 };
+
+    pub const fn offset(offset: f32) -> Self {
+        Calibration::Linear { offset, gain: 1.0 }
+    }
+
+    pub const fn linear(offset: f32, gain: f32) -> Self {
+        Calibration::Linear { offset, gain }
+    }
+
+    pub const fn polynomial(coefficients: [f32; 4]) -> Self {
+        Calibration::Polynomial { coefficients }
+    }
+
+    /// The linear correction through two `(raw, actual)` points, e.g. an ice
+    /// bath and boiling water.
+    pub fn from_two_points(low: (f32, f32), high: (f32, f32)) -> Result<Self, CalibrationError> {
+        let (raw_low, actual_low) = low;
+        let (raw_high, actual_high) = high;
+        if raw_low == raw_high {
+            return Err(CalibrationError::SamePoints);
+        }
+        let gain = (actual_high - actual_low) / (raw_high - raw_low);
+        let calibration = Self::linear(actual_low - gain * raw_low, gain);
+        calibration.validate()?;
+        Ok(calibration)
+    }
+
+    pub fn validate(&self) -> Result<(), CalibrationError> {
+        match self {
+            Calibration::Linear { offset, gain } => {
+                if !offset.is_finite() || !gain.is_finite() {
+                    return Err(CalibrationError::NotFinite);
+                }
+                if *gain == 0.0 {
+                    return Err(CalibrationError::ZeroGain);
+                }
+            }
+            Calibration::Polynomial { coefficients } => {
+                if !coefficients.iter().all(|c| c.is_finite()) {
+                    return Err(CalibrationError::NotFinite);
+                }
+                if coefficients[1..].iter().all(|&c| c == 0.0) {
+                    return Err(CalibrationError::ZeroGain);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The corrected value of a raw reading in °C.
+    pub fn correct(&self, raw: f32) -> f32 {
+        match self {
+            Calibration::Linear { offset, gain } => gain * raw + offset,
+            Calibration::Polynomial { coefficients } => coefficients.iter().rev().fold(0.0, |acc, &c| acc * raw + c),
+        }
+    }
+
+    pub fn apply(&self, raw: Temperature) -> Temperature {
+        Temperature::new(self.correct(raw.celsius))
+    }
+}
+
+/// A sensor whose readings pass through a [`Calibration`].
+#[derive(Debug, Clone)]
+pub struct CalibratedSensor<S> {
+    sensor: S,
+    calibration: Calibration,
+}
+
+impl<S: TemperatureSensor> CalibratedSensor<S> {
+    pub fn new(sensor: S, calibration: Calibration) -> Self {
+        Self { sensor, calibration }
+    }
+
+    /// Not calibrated yet; readings pass through unchanged.
+    pub fn uncalibrated(sensor: S) -> Self {
+        Self::new(sensor, Calibration::IDENTITY)
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    /// Reads the wrapped sensor without correction, e.g. to calibrate it.
+    pub fn read_raw(&mut self) -> Result<Temperature, S::Error> {
+        self.sensor.read_temperature()
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.sensor
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.sensor
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sensor
+    }
+}
+
+impl<S: TemperatureSensor> TemperatureSensor for CalibratedSensor<S> {
+    type Error = S::Error;
+
+    fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+        self.read_raw().map(|raw| self.calibration.apply(raw))
+    }
+
+    fn read_temperatures(&mut self, out: &mut [Temperature]) -> Result<usize, Self::Error> {
+        let count = self.sensor.read_temperatures(out)?;
+        for sample in &mut out[..count] {
+            *sample = self.calibration.apply(*sample);
+        }
+        Ok(count)
+    }
+
+    fn sensor_id(&self) -> &str {
+        self.sensor.sensor_id()
+    }
+
+    fn self_test(&mut self) -> Result<SensorHealth, Self::Error> {
+        self.sensor.self_test()
+    }
+}
+
+/// The wrapped sensor's datasheet figures; a calibration corrects offsets,
+/// not the resolution or range.
+impl<S: SensorInfo> SensorInfo for CalibratedSensor<S> {
+    fn accuracy(&self) -> f32 {
+        self.sensor.accuracy()
+    }
+
+    fn resolution(&self) -> f32 {
+        self.sensor.resolution()
+    }
+
+    fn range(&self) -> MeasurementRange {
+        self.sensor.range()
+    }
+
+    fn manufacturer(&self) -> &str {
+        self.sensor.manufacturer()
+    }
+
+    fn model(&self) -> &str {
+        self.sensor.model()
+    }
+}
+
+/// Only the temperature is corrected.
+impl<S: EnvironmentalSensor> EnvironmentalSensor for CalibratedSensor<S> {
+    fn read_environment(&mut self) -> Result<EnvironmentalReading, Self::Error> {
+        let mut reading = self.sensor.read_environment()?;
+        reading.temperature = self.calibration.apply(reading.temperature);
+        Ok(reading)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrects_raw_readings() {
+        assert_eq!(Calibration::IDENTITY.correct(21.5), 21.5);
+        assert_eq!(Calibration::offset(-0.5).correct(21.5), 21.0);
+        assert_eq!(Calibration::linear(1.0, 0.5).correct(20.0), 11.0);
+        // 0.1 + 1.01 x - 0.0002 x²
+        let curve = Calibration::polynomial([0.1, 1.01, -0.0002, 0.0]);
+        assert!((curve.correct(100.0) - 99.1).abs() < 1e-4);
+
+        // Reads 0.4 in an ice bath and 99.0 in boiling water
+        let two_point = Calibration::from_two_points((0.4, 0.0), (99.0, 100.0)).unwrap();
+        assert!(two_point.correct(0.4).abs() < 1e-4);
+        assert!((two_point.correct(99.0) - 100.0).abs() < 1e-4);
+        assert!((two_point.correct(49.7) - 50.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rejects_degenerate_corrections() {
+        assert_eq!(Calibration::from_two_points((20.0, 20.0), (20.0, 25.0)), Err(CalibrationError::SamePoints));
+        assert_eq!(Calibration::from_two_points((10.0, 20.0), (30.0, 20.0)), Err(CalibrationError::ZeroGain));
+        assert_eq!(Calibration::linear(f32::NAN, 1.0).validate(), Err(CalibrationError::NotFinite));
+        assert_eq!(Calibration::polynomial([5.0, 0.0, 0.0, 0.0]).validate(), Err(CalibrationError::ZeroGain));
+        assert!(Calibration::polynomial([0.0, 0.0, 1.0, 0.0]).validate().is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn calibrated_sensor_applies_the_correction() {
+        use crate::mock::MockTemperatureSensor;
+        extern crate std;
+        use std::string::ToString;
+
+        let mock = MockTemperatureSensor::new("probe".to_string(), 20.0).with_humidity(40.0);
+        let mut sensor = CalibratedSensor::new(mock, Calibration::offset(1.5));
+        assert_eq!(sensor.sensor_id(), "probe");
+        assert_eq!(sensor.read_temperature().unwrap(), Temperature::new(21.5));
+        assert_eq!(sensor.read_raw().unwrap(), Temperature::new(20.0));
+
+        let reading = sensor.read_environment().unwrap();
+        assert_eq!(reading.temperature, Temperature::new(21.5));
+        assert_eq!(reading.humidity.map(|h| h.percent), Some(40.0));
+
+        sensor.inner_mut().fail_next_read();
+        assert!(sensor.read_temperature().is_err());
+
+        assert_eq!(sensor.model(), "mock");
+
+        sensor.inner_mut().set_fifo_depth(4);
+        let mut batch = [Temperature::new(0.0); 3];
+        assert_eq!(sensor.read_temperatures(&mut batch).unwrap(), 3);
+        assert_eq!(batch, [Temperature::new(21.5); 3]);
+    }
+}
@@ -0,0 +1,72 @@
+//! Wall-clock time source.
+//!
+//! Components that timestamp readings or report uptime take a [`Clock`]
+//! instead of calling `SystemTime::now()`, so tests can pin and advance time.
+
+#[cfg(feature = "std")]
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+/// Seconds since the Unix epoch.
+pub trait Clock: Send {
+    fn now(&self) -> u64;
+
+    /// Milliseconds since the Unix epoch; clocks with whole seconds only
+    /// can keep the default.
+    fn now_millis(&self) -> u64 {
+        self.now().saturating_mul(1000)
+    }
+}
+
+/// The system clock; a clock set before 1970 reads as 0.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    }
+
+    fn now_millis(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64)
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so a
+/// test can keep one and advance the one it injected.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<AtomicU64>,
+}
+
+#[cfg(feature = "std")]
+impl ManualClock {
+    pub fn new(now: u64) -> Self {
+        Self {
+            now: Arc::new(AtomicU64::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, seconds: u64) {
+        self.now.fetch_add(seconds, Ordering::SeqCst);
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,272 @@
+//! One virtual sensor made of several redundant ones.
+//!
+//! Three probes in the same place give three readings that should agree.
+//! A [`CompositeSensor`] reads all of them and reports their mean or median
+//! as a single sensor, so code that takes one [`TemperatureSensor`] gets the
+//! redundancy without knowing about it. A member that fails, or returns a
+//! reading that is not a possible temperature, is left out; the composite
+//! only fails once fewer than its quorum of members are left. The median
+//! also outvotes a member that drifted but still reads plausibly.
+//!
+//! Members share a type. Probes of different types can be combined behind
+//! an enum implementing [`TemperatureSensor`].
+
+use core::fmt;
+
+use crate::info::{MeasurementRange, SensorInfo};
+use crate::{SensorHealth, Temperature, TemperatureSensor};
+
+/// How the members' readings are combined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Combine {
+    #[default]
+    Mean,
+    /// The middle reading, or the mean of the middle two.
+    Median,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompositeError<E> {
+    /// Fewer than `required` members gave a reading. `last_error` is the
+    /// last member error, `None` if the failures were implausible readings.
+    TooFewReadings {
+        valid: usize,
+        required: usize,
+        last_error: Option<E>,
+    },
+}
+
+impl<E: fmt::Debug> fmt::Display for CompositeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompositeError::TooFewReadings { valid, required, last_error: Some(e) } => {
+                write!(f, "Only {} of {} required sensors could be read, last error: {:?}", valid, required, e)
+            }
+            CompositeError::TooFewReadings { valid, required, last_error: None } => {
+                write!(f, "Only {} of {} required sensors gave a plausible reading", valid, required)
+            }
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for CompositeError<E> {}
+
+/// `N` sensors read as one; see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct CompositeSensor<S, const N: usize> {
+    sensors: [S; N],
+    combine: Combine,
+    required: usize,
+    failures: [u32; N],
+    id: &'static str,
+}
+
+impl<S: TemperatureSensor, const N: usize> CompositeSensor<S, N> {
+    /// Averages the members, tolerating a failed minority: one of three, or
+    /// none of two.
+    pub fn new(sensors: [S; N]) -> Self {
+        const { assert!(N > 0, "a composite sensor needs at least one member") };
+        Self {
+            sensors,
+            combine: Combine::Mean,
+            required: N / 2 + 1,
+            failures: [0; N],
+            id: "composite",
+        }
+    }
+
+    pub fn with_combine(mut self, combine: Combine) -> Self {
+        self.combine = combine;
+        self
+    }
+
+    /// Keeps reading while up to `failures` members fail; limited to `N - 1`.
+    pub fn with_tolerance(mut self, failures: usize) -> Self {
+        self.required = N - failures.min(N - 1);
+        self
+    }
+
+    pub fn with_id(mut self, id: &'static str) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// How many reads in a row each member failed, to tell which probe to
+    /// replace while the composite still reads fine.
+    pub fn failures(&self) -> &[u32; N] {
+        &self.failures
+    }
+
+    pub fn members(&self) -> &[S; N] {
+        &self.sensors
+    }
+
+    pub fn members_mut(&mut self) -> &mut [S; N] {
+        &mut self.sensors
+    }
+
+    pub fn into_members(self) -> [S; N] {
+        self.sensors
+    }
+
+    fn combine(&self, readings: &mut [f32]) -> Temperature {
+        let count = readings.len();
+        match self.combine {
+            Combine::Mean => Temperature::new(readings.iter().sum::<f32>() / count as f32),
+            Combine::Median => {
+                readings.sort_unstable_by(f32::total_cmp);
+                let middle = count / 2;
+                if count % 2 == 1 {
+                    Temperature::new(readings[middle])
+                } else {
+                    Temperature::new((readings[middle - 1] + readings[middle]) / 2.0)
+                }
+            }
+        }
+    }
+}
+
+impl<S: TemperatureSensor, const N: usize> TemperatureSensor for CompositeSensor<S, N> {
+    type Error = CompositeError<S::Error>;
+
+    fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+        let mut readings = [0.0; N];
+        let mut valid = 0;
+        let mut last_error = None;
+        for (sensor, failures) in self.sensors.iter_mut().zip(&mut self.failures) {
+            match sensor.read_temperature() {
+                Ok(reading) if reading.validate().is_ok() => {
+                    readings[valid] = reading.celsius;
+                    valid += 1;
+                    *failures = 0;
+                }
+                Ok(_) => *failures = failures.saturating_add(1),
+                Err(e) => {
+                    *failures = failures.saturating_add(1);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        if valid < self.required {
+            return Err(CompositeError::TooFewReadings { valid, required: self.required, last_error });
+        }
+        Ok(self.combine(&mut readings[..valid]))
+    }
+
+    fn sensor_id(&self) -> &str {
+        self.id
+    }
+
+    /// Degraded while a tolerated member has failed or is degraded itself.
+    fn self_test(&mut self) -> Result<SensorHealth, Self::Error> {
+        let mut working = 0;
+        let mut worst = SensorHealth::Ok;
+        let mut last_error = None;
+        for sensor in &mut self.sensors {
+            match sensor.self_test() {
+                Ok(health) if health.is_working() => {
+                    working += 1;
+                    worst = worst.max(health);
+                }
+                Ok(_) => worst = SensorHealth::Degraded,
+                Err(e) => {
+                    worst = SensorHealth::Degraded;
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        match last_error {
+            Some(last_error) if working < self.required => Err(CompositeError::TooFewReadings {
+                valid: working,
+                required: self.required,
+                last_error: Some(last_error),
+            }),
+            _ if working < self.required => Ok(SensorHealth::Failed),
+            _ => Ok(worst),
+        }
+    }
+}
+
+/// The first member's datasheet figures. Combining readings reduces noise,
+/// not the error the probes share.
+impl<S: SensorInfo, const N: usize> SensorInfo for CompositeSensor<S, N> {
+    fn accuracy(&self) -> f32 {
+        self.sensors[0].accuracy()
+    }
+
+    fn resolution(&self) -> f32 {
+        self.sensors[0].resolution()
+    }
+
+    fn range(&self) -> MeasurementRange {
+        self.sensors[0].range()
+    }
+
+    fn manufacturer(&self) -> &str {
+        self.sensors[0].manufacturer()
+    }
+
+    fn model(&self) -> &str {
+        self.sensors[0].model()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the given readings in turn, then fails.
+    struct Script<'a> {
+        readings: &'a [f32],
+    }
+
+    impl TemperatureSensor for Script<'_> {
+        type Error = ();
+
+        fn read_temperature(&mut self) -> Result<Temperature, ()> {
+            let (&first, rest) = self.readings.split_first().ok_or(())?;
+            self.readings = rest;
+            Ok(Temperature::new(first))
+        }
+
+        fn sensor_id(&self) -> &str {
+            "script"
+        }
+    }
+
+    #[test]
+    fn combines_the_members_that_work() {
+        let probes = || {
+            [
+                Script { readings: &[20.0, 20.0, 20.0] },
+                Script { readings: &[21.0, f32::NAN] },
+                Script { readings: &[29.0, 29.0] },
+            ]
+        };
+
+        let mut mean = CompositeSensor::new(probes());
+        assert_eq!(mean.read_temperature(), Ok(Temperature::new(70.0 / 3.0)));
+        // The implausible reading is left out, but counted against its probe
+        assert_eq!(mean.read_temperature(), Ok(Temperature::new(24.5)));
+        assert_eq!(mean.failures(), &[0, 1, 0]);
+        // Two of three failed
+        assert_eq!(
+            mean.read_temperature(),
+            Err(CompositeError::TooFewReadings { valid: 1, required: 2, last_error: Some(()) })
+        );
+        assert_eq!(mean.failures(), &[0, 2, 1]);
+
+        let mut median = CompositeSensor::new(probes()).with_combine(Combine::Median).with_id("tank");
+        assert_eq!(median.read_temperature(), Ok(Temperature::new(21.0)));
+        assert_eq!(median.read_temperature(), Ok(Temperature::new(24.5)));
+        assert_eq!(median.sensor_id(), "tank");
+
+        let mut tolerant = CompositeSensor::new(probes()).with_tolerance(5);
+        tolerant.read_temperature().unwrap();
+        tolerant.read_temperature().unwrap();
+        assert_eq!(tolerant.read_temperature(), Ok(Temperature::new(20.0)));
+        assert!(tolerant.read_temperature().is_err());
+    }
+}
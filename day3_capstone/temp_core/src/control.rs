@@ -0,0 +1,287 @@
+//! Closed-loop temperature control.
+//!
+//! A [`Controller`] turns measured temperatures into an output level between
+//! 0.0 (off) and 1.0 (full power), and an [`Actuator`] applies that level to a
+//! heater or cooler. [`ControlLoop`] ties both together.
+
+use core::fmt;
+
+use crate::Temperature;
+
+/// Range of setpoints accepted from the protocols.
+pub const SETPOINT_MIN_CELSIUS: f32 = -40.0;
+pub const SETPOINT_MAX_CELSIUS: f32 = 125.0;
+
+/// Something that heats or cools, driven with a level from 0.0 to 1.0.
+///
+/// On/off devices (relays) should treat any level above 0.5 as on.
+pub trait Actuator {
+    type Error: fmt::Debug;
+
+    fn set_output(&mut self, level: f32) -> Result<(), Self::Error>;
+    fn output(&self) -> f32;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlMode {
+    /// Output rises when the temperature is below the setpoint.
+    Heating,
+    /// Output rises when the temperature is above the setpoint.
+    Cooling,
+}
+
+impl ControlMode {
+    fn error(&self, setpoint: Temperature, measured: Temperature) -> f32 {
+        match self {
+            ControlMode::Heating => setpoint.celsius - measured.celsius,
+            ControlMode::Cooling => measured.celsius - setpoint.celsius,
+        }
+    }
+}
+
+pub trait Controller {
+    /// Computes the next output level; `dt_seconds` is the time since the last update.
+    fn update(&mut self, measured: Temperature, dt_seconds: f32) -> f32;
+    fn setpoint(&self) -> Temperature;
+    fn set_setpoint(&mut self, setpoint: Temperature);
+}
+
+/// On/off control with a hysteresis band around the setpoint to avoid relay chatter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BangBangController {
+    setpoint: Temperature,
+    hysteresis: f32,
+    mode: ControlMode,
+    on: bool,
+}
+
+impl BangBangController {
+    pub const fn new(setpoint: Temperature, hysteresis: f32, mode: ControlMode) -> Self {
+        Self {
+            setpoint,
+            hysteresis,
+            mode,
+            on: false,
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+}
+
+impl Controller for BangBangController {
+    fn update(&mut self, measured: Temperature, _dt_seconds: f32) -> f32 {
+        let error = self.mode.error(self.setpoint, measured);
+        if error > self.hysteresis {
+            self.on = true;
+        } else if error < -self.hysteresis {
+            self.on = false;
+        }
+
+        if self.on { 1.0 } else { 0.0 }
+    }
+
+    fn setpoint(&self) -> Temperature {
+        self.setpoint
+    }
+
+    fn set_setpoint(&mut self, setpoint: Temperature) {
+        self.setpoint = setpoint;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PidGains {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+/// PID controller with output clamping and integrator anti-windup.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PidController {
+    setpoint: Temperature,
+    gains: PidGains,
+    mode: ControlMode,
+    integral: f32,
+    last_error: Option<f32>,
+}
+
+impl PidController {
+    pub const fn new(setpoint: Temperature, gains: PidGains, mode: ControlMode) -> Self {
+        Self {
+            setpoint,
+            gains,
+            mode,
+            integral: 0.0,
+            last_error: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.last_error = None;
+    }
+
+    pub fn gains(&self) -> PidGains {
+        self.gains
+    }
+}
+
+impl Controller for PidController {
+    fn update(&mut self, measured: Temperature, dt_seconds: f32) -> f32 {
+        let error = self.mode.error(self.setpoint, measured);
+
+        let derivative = match self.last_error {
+            Some(last) if dt_seconds > 0.0 => (error - last) / dt_seconds,
+            _ => 0.0,
+        };
+        self.last_error = Some(error);
+
+        let integral = self.integral + error * dt_seconds;
+        let unclamped = self.gains.kp * error + self.gains.ki * integral + self.gains.kd * derivative;
+        let output = unclamped.clamp(0.0, 1.0);
+
+        // Anti-windup: only keep integrating while the output is not saturated,
+        // or when the error pulls it back out of saturation
+        if output == unclamped || (unclamped > 1.0 && error < 0.0) || (unclamped < 0.0 && error > 0.0) {
+            self.integral = integral;
+        }
+
+        output
+    }
+
+    fn setpoint(&self) -> Temperature {
+        self.setpoint
+    }
+
+    fn set_setpoint(&mut self, setpoint: Temperature) {
+        self.setpoint = setpoint;
+    }
+}
+
+/// Drives an actuator from measured temperatures.
+pub struct ControlLoop<C, A> {
+    controller: C,
+    actuator: A,
+}
+
+impl<C: Controller, A: Actuator> ControlLoop<C, A> {
+    pub fn new(controller: C, actuator: A) -> Self {
+        Self { controller, actuator }
+    }
+
+    /// Feeds one measurement through the controller and applies the result.
+    pub fn step(&mut self, measured: Temperature, dt_seconds: f32) -> Result<f32, A::Error> {
+        let output = self.controller.update(measured, dt_seconds);
+        self.actuator.set_output(output)?;
+        Ok(output)
+    }
+
+    pub fn setpoint(&self) -> Temperature {
+        self.controller.setpoint()
+    }
+
+    pub fn set_setpoint(&mut self, setpoint: Temperature) {
+        self.controller.set_setpoint(setpoint);
+    }
+
+    pub fn controller(&self) -> &C {
+        &self.controller
+    }
+
+    pub fn actuator(&self) -> &A {
+        &self.actuator
+    }
+
+    pub fn actuator_mut(&mut self) -> &mut A {
+        &mut self.actuator
+    }
+
+    pub fn into_parts(self) -> (C, A) {
+        (self.controller, self.actuator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestActuator {
+        level: f32,
+    }
+
+    impl Actuator for TestActuator {
+        type Error = ();
+
+        fn set_output(&mut self, level: f32) -> Result<(), ()> {
+            self.level = level;
+            Ok(())
+        }
+
+        fn output(&self) -> f32 {
+            self.level
+        }
+    }
+
+    #[test]
+    fn bang_bang_hysteresis() {
+        let mut controller = BangBangController::new(Temperature::new(20.0), 0.5, ControlMode::Heating);
+
+        assert_eq!(controller.update(Temperature::new(19.0), 1.0), 1.0);
+        // Inside the band: keeps heating
+        assert_eq!(controller.update(Temperature::new(20.3), 1.0), 1.0);
+        assert_eq!(controller.update(Temperature::new(20.6), 1.0), 0.0);
+        // Inside the band: stays off
+        assert_eq!(controller.update(Temperature::new(19.7), 1.0), 0.0);
+        assert_eq!(controller.update(Temperature::new(19.4), 1.0), 1.0);
+    }
+
+    #[test]
+    fn bang_bang_cooling() {
+        let mut controller = BangBangController::new(Temperature::new(5.0), 1.0, ControlMode::Cooling);
+
+        assert_eq!(controller.update(Temperature::new(7.0), 1.0), 1.0);
+        assert_eq!(controller.update(Temperature::new(3.5), 1.0), 0.0);
+    }
+
+    #[test]
+    fn pid_output_is_clamped_and_proportional() {
+        let gains = PidGains { kp: 0.5, ki: 0.0, kd: 0.0 };
+        let mut controller = PidController::new(Temperature::new(20.0), gains, ControlMode::Heating);
+
+        assert_eq!(controller.update(Temperature::new(10.0), 1.0), 1.0);
+        assert!((controller.update(Temperature::new(19.0), 1.0) - 0.5).abs() < 1e-6);
+        assert_eq!(controller.update(Temperature::new(25.0), 1.0), 0.0);
+    }
+
+    #[test]
+    fn pid_integral_does_not_wind_up() {
+        let gains = PidGains { kp: 0.1, ki: 0.1, kd: 0.0 };
+        let mut controller = PidController::new(Temperature::new(20.0), gains, ControlMode::Heating);
+
+        // Long saturation far below the setpoint
+        for _ in 0..100 {
+            controller.update(Temperature::new(0.0), 1.0);
+        }
+
+        // Once above the setpoint the output must drop quickly
+        let output = controller.update(Temperature::new(21.0), 1.0);
+        assert!(output < 1.0, "output stuck at {}", output);
+    }
+
+    #[test]
+    fn control_loop_drives_actuator() {
+        let controller = BangBangController::new(Temperature::new(20.0), 0.5, ControlMode::Heating);
+        let mut control = ControlLoop::new(controller, TestActuator { level: 0.0 });
+
+        control.step(Temperature::new(18.0), 1.0).unwrap();
+        assert_eq!(control.actuator().output(), 1.0);
+
+        control.set_setpoint(Temperature::new(15.0));
+        control.step(Temperature::new(18.0), 1.0).unwrap();
+        assert_eq!(control.actuator().output(), 0.0);
+        assert_eq!(control.setpoint().celsius, 15.0);
+    }
+}
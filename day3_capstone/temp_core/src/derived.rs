@@ -0,0 +1,119 @@
+//! Comfort metrics derived from temperature and relative humidity.
+//!
+//! - Dew point, by the Magnus formula with Sonntag's coefficients: the
+//!   temperature at which the air would saturate, and condensation form.
+//! - Heat index, by the NWS's Rothfusz regression and its adjustments: how
+//!   hot humid air feels. Below about 27 °C it is close to the temperature.
+//! - Wet-bulb temperature, by Stull's formula, within 1 °C for 5 to 99 %RH
+//!   and -20 to 50 °C: the lowest temperature evaporation can cool to, and
+//!   so the limit for cooling by sweat.
+//!
+//! All of them work on `f32` with `libm`, so nodes can report them too.
+
+use serde::{Deserialize, Serialize};
+
+use crate::environment::{EnvironmentalReading, Humidity};
+use crate::Temperature;
+
+/// Dew point at `temperature` and `humidity`; `None` for perfectly dry air.
+pub fn dew_point(temperature: Temperature, humidity: Humidity) -> Option<Temperature> {
+    const B: f32 = 17.62;
+    const C: f32 = 243.12;
+    if humidity.percent <= 0.0 {
+        return None;
+    }
+    let t = temperature.celsius;
+    let gamma = libm::logf(humidity.percent / 100.0) + B * t / (C + t);
+    Some(Temperature::new(C * gamma / (B - gamma)))
+}
+
+/// How hot it feels at `temperature` and `humidity`.
+pub fn heat_index(temperature: Temperature, humidity: Humidity) -> Temperature {
+    let t = temperature.to_fahrenheit();
+    let rh = humidity.percent;
+
+    // Steadman's simpler fit is close enough where the heat index is mild
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+    if (simple + t) / 2.0 < 80.0 {
+        return Temperature::from_fahrenheit(simple);
+    }
+
+    let mut index = -42.379 + 2.049_015_2 * t + 10.143_332 * rh
+        - 0.224_755_4 * t * rh
+        - 6.837_83e-3 * t * t
+        - 5.481_717e-2 * rh * rh
+        + 1.228_74e-3 * t * t * rh
+        + 8.528_2e-4 * t * rh * rh
+        - 1.99e-6 * t * t * rh * rh;
+    if rh < 13.0 && (80.0..=112.0).contains(&t) {
+        index -= (13.0 - rh) / 4.0 * libm::sqrtf((17.0 - libm::fabsf(t - 95.0)) / 17.0);
+    } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+        index += (rh - 85.0) / 10.0 * (87.0 - t) / 5.0;
+    }
+    Temperature::from_fahrenheit(index)
+}
+
+/// Wet-bulb temperature at `temperature` and `humidity`.
+pub fn wet_bulb(temperature: Temperature, humidity: Humidity) -> Temperature {
+    let t = temperature.celsius;
+    let rh = humidity.percent;
+    let wet_bulb = t * libm::atanf(0.151_977 * libm::sqrtf(rh + 8.313_659)) + libm::atanf(t + rh)
+        - libm::atanf(rh - 1.676_331)
+        + 0.003_918_38 * libm::powf(rh, 1.5) * libm::atanf(0.023_101 * rh)
+        - 4.686_035;
+    Temperature::new(wet_bulb)
+}
+
+/// The metrics of one reading, for reporting next to it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Comfort {
+    pub dew_point: Option<Temperature>,
+    pub heat_index: Temperature,
+    pub wet_bulb: Temperature,
+}
+
+impl Comfort {
+    pub fn of(temperature: Temperature, humidity: Humidity) -> Self {
+        Self {
+            dew_point: dew_point(temperature, humidity),
+            heat_index: heat_index(temperature, humidity),
+            wet_bulb: wet_bulb(temperature, humidity),
+        }
+    }
+}
+
+impl EnvironmentalReading {
+    /// `None` without a humidity.
+    pub fn comfort(&self) -> Option<Comfort> {
+        self.humidity.map(|humidity| Comfort::of(self.temperature, humidity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_published_values() {
+        // NWS heat index chart: 90 °F at 70 %RH feels like 106 °F
+        let hot = heat_index(Temperature::from_fahrenheit(90.0), Humidity::new(70.0));
+        assert!((hot.to_fahrenheit() - 106.0).abs() < 1.0, "{}", hot);
+        // With the low humidity adjustment: 100 °F at 10 %RH feels like 95 °F
+        let dry = heat_index(Temperature::from_fahrenheit(100.0), Humidity::new(10.0));
+        assert!((dry.to_fahrenheit() - 95.0).abs() < 1.0, "{}", dry);
+        let mild = heat_index(Temperature::new(20.0), Humidity::new(50.0));
+        assert!((mild.celsius - 20.0).abs() < 1.0, "{}", mild);
+
+        // Stull's own example
+        let wet = wet_bulb(Temperature::new(20.0), Humidity::new(50.0));
+        assert!((wet.celsius - 13.7).abs() < 0.1, "{}", wet);
+        let saturated = wet_bulb(Temperature::new(25.0), Humidity::new(99.0));
+        assert!((saturated.celsius - 25.0).abs() < 0.3, "{}", saturated);
+
+        let reading = EnvironmentalReading::new(Temperature::new(20.0)).with_humidity(Humidity::new(50.0));
+        let comfort = reading.comfort().unwrap();
+        assert!((comfort.dew_point.unwrap().celsius - 9.26).abs() < 0.05);
+        assert_eq!(comfort.wet_bulb, wet);
+        assert_eq!(EnvironmentalReading::new(Temperature::new(20.0)).comfort(), None);
+    }
+}
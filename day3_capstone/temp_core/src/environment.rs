@@ -0,0 +1,128 @@
+//! Humidity and pressure next to temperature.
+//!
+//! Combined sensors such as the SHT31 (temperature and humidity) or the
+//! BME280 (plus pressure) report several quantities from one measurement.
+//! An [`EnvironmentalSensor`] returns them together as an
+//! [`EnvironmentalReading`], leaving out what it does not measure. Every
+//! [`TemperatureSensor`] can opt in without changes, reporting temperature only.
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Temperature, TemperatureSensor};
+
+/// Relative humidity in percent, 0 to 100.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Humidity {
+    pub percent: f32,
+}
+
+impl Humidity {
+    /// Clamps to 0..=100, as sensors slightly overshoot near saturation.
+    pub fn new(percent: f32) -> Self {
+        Self { percent: percent.clamp(0.0, 100.0) }
+    }
+
+    /// Dew point at `temperature`; `None` when dry. See [`derived`](crate::derived).
+    pub fn dew_point(&self, temperature: Temperature) -> Option<Temperature> {
+        crate::derived::dew_point(temperature, *self)
+    }
+}
+
+impl fmt::Display for Humidity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} %RH", self.percent)
+    }
+}
+
+/// Barometric pressure in hectopascals.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Pressure {
+    pub hectopascals: f32,
+}
+
+impl Pressure {
+    pub fn new(hectopascals: f32) -> Self {
+        Self { hectopascals }
+    }
+
+    /// Most drivers report pascals.
+    pub fn from_pascals(pascals: f32) -> Self {
+        Self::new(pascals / 100.0)
+    }
+
+    pub fn to_pascals(&self) -> f32 {
+        self.hectopascals * 100.0
+    }
+}
+
+impl fmt::Display for Pressure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} hPa", self.hectopascals)
+    }
+}
+
+/// The quantities of one measurement; `None` for what the sensor lacks.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentalReading {
+    pub temperature: Temperature,
+    #[serde(default)]
+    pub humidity: Option<Humidity>,
+    #[serde(default)]
+    pub pressure: Option<Pressure>,
+}
+
+impl EnvironmentalReading {
+    pub fn new(temperature: Temperature) -> Self {
+        Self { temperature, humidity: None, pressure: None }
+    }
+
+    pub fn with_humidity(mut self, humidity: Humidity) -> Self {
+        self.humidity = Some(humidity);
+        self
+    }
+
+    pub fn with_pressure(mut self, pressure: Pressure) -> Self {
+        self.pressure = Some(pressure);
+        self
+    }
+}
+
+impl From<Temperature> for EnvironmentalReading {
+    fn from(temperature: Temperature) -> Self {
+        Self::new(temperature)
+    }
+}
+
+pub trait EnvironmentalSensor: TemperatureSensor {
+    /// Reads every quantity the sensor has in one go, so they belong to the
+    /// same moment. Defaults to temperature only.
+    fn read_environment(&mut self) -> Result<EnvironmentalReading, Self::Error> {
+        self.read_temperature().map(EnvironmentalReading::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    extern crate std;
+
+    #[test]
+    fn derived_values_and_display() {
+        let dew_point = Humidity::new(50.0).dew_point(Temperature::new(20.0)).unwrap();
+        assert!((dew_point.celsius - 9.26).abs() < 0.05);
+        assert_eq!(Humidity::new(0.0).dew_point(Temperature::new(20.0)), None);
+        assert_eq!(Humidity::new(100.4).percent, 100.0);
+
+        assert_eq!(Pressure::from_pascals(101_325.0), Pressure::new(1013.25));
+        assert_eq!(std::format!("{}", Humidity::new(45.0)), "45.0 %RH");
+        assert_eq!(std::format!("{}", Pressure::new(1013.25)), "1013.2 hPa");
+    }
+
+    #[test]
+    fn readings_without_humidity_or_pressure_deserialize() {
+        let reading: EnvironmentalReading = serde_json::from_str(r#"{"temperature":{"celsius":21.5}}"#).unwrap();
+        assert_eq!(reading, EnvironmentalReading::new(Temperature::new(21.5)));
+    }
+}
@@ -0,0 +1,302 @@
+//! Sensors whose readings are smoothed before they are returned.
+//!
+//! Noisy sensors, such as a thermistor on an ADC, jitter by a few counts
+//! between readings. Wrapping one in a [`MovingAverageSensor`] or an
+//! [`EmaSensor`] smooths every reading at the source, so each consumer sees
+//! the same filtered values. Both keep their state inline and work without
+//! `std`.
+//!
+//! A reading that is not a possible temperature is returned as it is and
+//! left out of the average, so a single glitch neither poisons the next
+//! readings nor gets hidden from whoever validates them.
+
+use crate::info::{MeasurementRange, SensorInfo};
+use crate::transform::{ExponentialSmoothing, Transform};
+use crate::{SensorHealth, Temperature, TemperatureSensor};
+
+/// Mean of the last `N` readings of the wrapped sensor. Until `N` readings
+/// came in, it is the mean of those there are.
+#[derive(Debug, Clone)]
+pub struct MovingAverageSensor<S, const N: usize> {
+    sensor: S,
+    samples: [f32; N],
+    len: usize,
+    next: usize,
+}
+
+impl<S: TemperatureSensor, const N: usize> MovingAverageSensor<S, N> {
+    pub fn new(sensor: S) -> Self {
+        const { assert!(N > 0, "a moving average needs a window of at least one reading") };
+        Self {
+            sensor,
+            samples: [0.0; N],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    /// The current average, `None` before the first reading.
+    pub fn average(&self) -> Option<Temperature> {
+        (self.len > 0).then(|| {
+            let sum: f32 = self.samples[..self.len].iter().sum();
+            Temperature::new(sum / self.len as f32)
+        })
+    }
+
+    /// Forgets the readings so far, e.g. after the sensor was moved.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.next = 0;
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.sensor
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.sensor
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sensor
+    }
+
+    fn smooth(&mut self, temperature: Temperature) -> Temperature {
+        if temperature.validate().is_err() {
+            return temperature;
+        }
+        self.samples[self.next] = temperature.celsius;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        self.average().unwrap_or(temperature)
+    }
+}
+
+/// Exponential moving average of the wrapped sensor's readings; see
+/// [`ExponentialSmoothing`] for what `alpha` does.
+#[derive(Debug, Clone)]
+pub struct EmaSensor<S> {
+    sensor: S,
+    smoothing: ExponentialSmoothing,
+    average: Option<Temperature>,
+}
+
+impl<S: TemperatureSensor> EmaSensor<S> {
+    /// `alpha` is limited to `0.0..=1.0`.
+    pub fn new(sensor: S, alpha: f32) -> Self {
+        Self {
+            sensor,
+            smoothing: ExponentialSmoothing::new(alpha),
+            average: None,
+        }
+    }
+
+    /// The current average, `None` before the first reading.
+    pub fn average(&self) -> Option<Temperature> {
+        self.average
+    }
+
+    /// Forgets the readings so far; the next one starts the average afresh.
+    pub fn reset(&mut self) {
+        self.smoothing.reset();
+        self.average = None;
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.sensor
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.sensor
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sensor
+    }
+
+    fn smooth(&mut self, temperature: Temperature) -> Temperature {
+        if temperature.validate().is_err() {
+            return temperature;
+        }
+        self.average = self.smoothing.apply(temperature);
+        self.average.unwrap_or(temperature)
+    }
+}
+
+impl<S: TemperatureSensor, const N: usize> TemperatureSensor for MovingAverageSensor<S, N> {
+    type Error = S::Error;
+
+    fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+        let raw = self.sensor.read_temperature()?;
+        Ok(self.smooth(raw))
+    }
+
+    /// Every sample of the batch enters the average in order, and each is
+    /// replaced by the average up to it.
+    fn read_temperatures(&mut self, out: &mut [Temperature]) -> Result<usize, Self::Error> {
+        let count = self.sensor.read_temperatures(out)?;
+        for sample in &mut out[..count] {
+            *sample = self.smooth(*sample);
+        }
+        Ok(count)
+    }
+
+    fn sensor_id(&self) -> &str {
+        self.sensor.sensor_id()
+    }
+
+    fn self_test(&mut self) -> Result<SensorHealth, Self::Error> {
+        self.sensor.self_test()
+    }
+}
+
+impl<S: TemperatureSensor> TemperatureSensor for EmaSensor<S> {
+    type Error = S::Error;
+
+    fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+        let raw = self.sensor.read_temperature()?;
+        Ok(self.smooth(raw))
+    }
+
+    fn read_temperatures(&mut self, out: &mut [Temperature]) -> Result<usize, Self::Error> {
+        let count = self.sensor.read_temperatures(out)?;
+        for sample in &mut out[..count] {
+            *sample = self.smooth(*sample);
+        }
+        Ok(count)
+    }
+
+    fn sensor_id(&self) -> &str {
+        self.sensor.sensor_id()
+    }
+
+    fn self_test(&mut self) -> Result<SensorHealth, Self::Error> {
+        self.sensor.self_test()
+    }
+}
+
+/// The wrapped sensor's datasheet figures; averaging reduces noise but
+/// does not make the sensor more accurate.
+impl<S: SensorInfo, const N: usize> SensorInfo for MovingAverageSensor<S, N> {
+    fn accuracy(&self) -> f32 {
+        self.sensor.accuracy()
+    }
+
+    fn resolution(&self) -> f32 {
+        self.sensor.resolution()
+    }
+
+    fn range(&self) -> MeasurementRange {
+        self.sensor.range()
+    }
+
+    fn manufacturer(&self) -> &str {
+        self.sensor.manufacturer()
+    }
+
+    fn model(&self) -> &str {
+        self.sensor.model()
+    }
+}
+
+impl<S: SensorInfo> SensorInfo for EmaSensor<S> {
+    fn accuracy(&self) -> f32 {
+        self.sensor.accuracy()
+    }
+
+    fn resolution(&self) -> f32 {
+        self.sensor.resolution()
+    }
+
+    fn range(&self) -> MeasurementRange {
+        self.sensor.range()
+    }
+
+    fn manufacturer(&self) -> &str {
+        self.sensor.manufacturer()
+    }
+
+    fn model(&self) -> &str {
+        self.sensor.model()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the given readings in turn, then fails.
+    struct Script<'a> {
+        readings: &'a [f32],
+    }
+
+    impl TemperatureSensor for Script<'_> {
+        type Error = ();
+
+        fn read_temperature(&mut self) -> Result<Temperature, ()> {
+            let (&first, rest) = self.readings.split_first().ok_or(())?;
+            self.readings = rest;
+            Ok(Temperature::new(first))
+        }
+
+        fn sensor_id(&self) -> &str {
+            "script"
+        }
+    }
+
+    fn read_all<T: TemperatureSensor>(sensor: &mut T) -> ([f32; 8], usize) {
+        let mut out = [0.0; 8];
+        let mut count = 0;
+        while let Ok(reading) = sensor.read_temperature() {
+            out[count] = reading.celsius;
+            count += 1;
+        }
+        (out, count)
+    }
+
+    #[test]
+    fn moving_average_over_the_window() {
+        let mut sensor = MovingAverageSensor::<_, 3>::new(Script {
+            readings: &[20.0, 22.0, 24.0, 30.0, f32::NAN, 21.0],
+        });
+        assert_eq!(sensor.average(), None);
+        assert_eq!(sensor.sensor_id(), "script");
+
+        let (out, count) = read_all(&mut sensor);
+        assert_eq!(count, 6);
+        assert_eq!(&out[..4], &[20.0, 21.0, 22.0, 76.0 / 3.0]);
+        // The glitch is passed on but not averaged in
+        assert!(out[4].is_nan());
+        assert_eq!(out[5], 25.0);
+
+        sensor.reset();
+        assert_eq!(sensor.average(), None);
+        sensor.inner_mut().readings = &[18.0];
+        assert_eq!(sensor.read_temperature(), Ok(Temperature::new(18.0)));
+    }
+
+    #[test]
+    fn ema_follows_by_alpha() {
+        let mut sensor = EmaSensor::new(Script { readings: &[20.0, 24.0, f32::NAN, 24.0] }, 0.5);
+        let (out, count) = read_all(&mut sensor);
+        assert_eq!(count, 4);
+        assert_eq!(&out[..2], &[20.0, 22.0]);
+        assert!(out[2].is_nan());
+        assert_eq!(out[3], 23.0);
+        assert_eq!(sensor.average(), Some(Temperature::new(23.0)));
+
+        sensor.reset();
+        sensor.inner_mut().readings = &[10.0];
+        assert_eq!(sensor.read_temperature(), Ok(Temperature::new(10.0)));
+    }
+
+    #[test]
+    fn batches_are_smoothed_sample_by_sample() {
+        let mut sensor = MovingAverageSensor::<_, 2>::new(Script { readings: &[20.0, 22.0] });
+        let mut batch = [Temperature::new(0.0); 1];
+        assert_eq!(sensor.read_temperatures(&mut batch), Ok(1));
+        assert_eq!(sensor.read_temperatures(&mut batch), Ok(1));
+        assert_eq!(batch, [Temperature::new(21.0)]);
+        assert!(sensor.read_temperatures(&mut batch).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Fixed-point temperatures for MCUs without an FPU.
+//!
+//! On a Cortex-M0 every `f32` operation is a soft-float library call, which
+//! is slow and pulls kilobytes into the binary. [`TemperatureFixed`] holds
+//! hundredths of a degree Celsius in an `i32`, enough for any sensor's
+//! resolution, and its arithmetic, comparisons, display and ADC conversion
+//! only use integers. Convert to [`Temperature`] at the edges, e.g. when
+//! handing readings to the host.
+
+use core::fmt;
+use core::ops::{Add, Sub};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Temperature;
+
+/// Hundredths of a degree Celsius.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TemperatureFixed(i32);
+
+impl TemperatureFixed {
+    pub const MIN: TemperatureFixed = TemperatureFixed(i32::MIN);
+    pub const MAX: TemperatureFixed = TemperatureFixed(i32::MAX);
+
+    pub const fn from_centidegrees(centidegrees: i32) -> Self {
+        Self(centidegrees)
+    }
+
+    pub const fn from_degrees(degrees: i16) -> Self {
+        Self(degrees as i32 * 100)
+    }
+
+    pub const fn centidegrees(&self) -> i32 {
+        self.0
+    }
+
+    /// Whole degrees, rounded toward zero.
+    pub const fn degrees(&self) -> i32 {
+        self.0 / 100
+    }
+
+    /// Reading of a linear sensor putting out `offset_mv` at 0 °C plus
+    /// `mv_per_degree` per degree, sampled as `count` out of `max_count`
+    /// against `vref_mv`. The integer counterpart of
+    /// [`AdcConfig::to_celsius`](crate::adc::AdcConfig::to_celsius).
+    pub const fn from_linear_adc(count: u16, max_count: u16, vref_mv: u32, offset_mv: i32, mv_per_degree: u32) -> Self {
+        if max_count == 0 || mv_per_degree == 0 {
+            return Self(0);
+        }
+        // Microvolts keep the division exact enough for centidegrees
+        let microvolts = count as i64 * vref_mv as i64 * 1000 / max_count as i64;
+        let above_offset = microvolts - offset_mv as i64 * 1000;
+        Self((above_offset / (mv_per_degree as i64 * 10)) as i32)
+    }
+
+    pub const fn saturating_add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+
+    pub const fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    pub const fn abs_diff(self, other: Self) -> u32 {
+        self.0.abs_diff(other.0)
+    }
+
+    /// Mean of `readings`, rounded toward zero; `None` for none.
+    pub fn mean(readings: &[TemperatureFixed]) -> Option<Self> {
+        if readings.is_empty() {
+            return None;
+        }
+        let sum: i64 = readings.iter().map(|t| t.0 as i64).sum();
+        Some(Self((sum / readings.len() as i64) as i32))
+    }
+}
+
+impl Add for TemperatureFixed {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.saturating_add(other)
+    }
+}
+
+impl Sub for TemperatureFixed {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.saturating_sub(other)
+    }
+}
+
+impl From<Temperature> for TemperatureFixed {
+    /// Rounds to the nearest hundredth; saturates beyond the `i32` range, NaN becomes 0.
+    fn from(temperature: Temperature) -> Self {
+        let centidegrees = temperature.celsius * 100.0;
+        Self(if centidegrees < 0.0 { centidegrees - 0.5 } else { centidegrees + 0.5 } as i32)
+    }
+}
+
+impl From<TemperatureFixed> for Temperature {
+    fn from(temperature: TemperatureFixed) -> Self {
+        Temperature::new(temperature.0 as f32 / 100.0)
+    }
+}
+
+impl fmt::Display for TemperatureFixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let magnitude = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}°C", sign, magnitude / 100, magnitude % 100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    extern crate std;
+
+    #[test]
+    fn converts_to_and_from_float() {
+        assert_eq!(TemperatureFixed::from(Temperature::new(23.456)), TemperatureFixed::from_centidegrees(2346));
+        assert_eq!(TemperatureFixed::from(Temperature::new(-0.005)), TemperatureFixed::from_centidegrees(-1));
+        assert_eq!(TemperatureFixed::from(Temperature::new(f32::MAX)), TemperatureFixed::MAX);
+        assert_eq!(TemperatureFixed::from(Temperature::new(f32::NAN)), TemperatureFixed::default());
+        assert_eq!(Temperature::from(TemperatureFixed::from_degrees(-40)), Temperature::new(-40.0));
+    }
+
+    #[test]
+    fn integer_arithmetic_and_display() {
+        let a = TemperatureFixed::from_centidegrees(2150);
+        let b = TemperatureFixed::from_centidegrees(-75);
+        assert_eq!(a + b, TemperatureFixed::from_centidegrees(2075));
+        assert_eq!(TemperatureFixed::MAX + a, TemperatureFixed::MAX);
+        assert_eq!(a.abs_diff(b), 2225);
+        assert_eq!(TemperatureFixed::mean(&[a, b, b]), Some(TemperatureFixed::from_centidegrees(666)));
+        assert_eq!(TemperatureFixed::mean(&[]), None);
+
+        assert_eq!(std::format!("{}", a), "21.50°C");
+        assert_eq!(std::format!("{}", b), "-0.75°C");
+        assert_eq!(b.degrees(), 0);
+    }
+
+    #[test]
+    fn linear_adc_matches_the_float_conversion() {
+        let config = crate::adc::AdcConfig::DEFAULT;
+        for count in [0, 310, 1000, 4095] {
+            let fixed = TemperatureFixed::from_linear_adc(count, 4095, 3300, 0, 10);
+            let float = TemperatureFixed::from(Temperature::new(config.to_celsius(count)));
+            assert!(fixed.abs_diff(float) <= 1, "{} vs {} at {}", fixed, float, count);
+        }
+
+        // TMP36 on a 10-bit ADC, 500 mV offset: 750 mV is 25 °C
+        let tmp36 = TemperatureFixed::from_linear_adc(1023, 1023, 750, 500, 10);
+        assert_eq!(tmp36, TemperatureFixed::from_degrees(25));
+    }
+}
@@ -0,0 +1,247 @@
+//! How fast a temperature changes, in °C per minute.
+//!
+//! A freezer whose door was left open, or a heater that runs away, is
+//! often noticed by its rate of change well before it crosses a limit.
+//! A [`TemperatureGradient`] is fed successive readings with the seconds
+//! between them, like a [`ControlLoop`](crate::control::ControlLoop), and
+//! reports the rate between them. Sensors jitter, so between readings a
+//! few seconds apart the rate does too; [`with_smoothing`] averages it over
+//! roughly the given number of seconds, weighting each interval by its
+//! length so irregular sampling does not skew it.
+//!
+//! A [`GradientThreshold`] says how fast is too fast, e.g. "rising faster
+//! than 2 °C/min". Like a [`ThresholdConfig`](crate::threshold::ThresholdConfig)
+//! it can only be built valid, and deserializing one checks the same rules.
+//!
+//! [`with_smoothing`]: TemperatureGradient::with_smoothing
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Temperature;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TemperatureGradient {
+    smoothing_seconds: f32,
+    last: Option<f32>,
+    rate: Option<f32>,
+    /// Seconds since the last reading kept.
+    skipped_seconds: f32,
+}
+
+impl TemperatureGradient {
+    /// The rate between the last two readings, unsmoothed.
+    pub const fn new() -> Self {
+        Self { smoothing_seconds: 0.0, last: None, rate: None, skipped_seconds: 0.0 }
+    }
+
+    /// Averages the rate over about `seconds`; negative values count as 0.
+    pub const fn with_smoothing(mut self, seconds: f32) -> Self {
+        self.smoothing_seconds = seconds.max(0.0);
+        self
+    }
+
+    /// Adds a reading taken `elapsed_seconds` after the previous one and
+    /// returns the rate, `None` until there are two readings. A reading that
+    /// is not a possible temperature is skipped, and so is one that took no
+    /// time, such as the rest of a FIFO batch: the next reading's change is
+    /// then measured from the last one kept.
+    pub fn update(&mut self, temperature: Temperature, elapsed_seconds: f32) -> Option<f32> {
+        if temperature.validate().is_err() {
+            self.skipped_seconds += elapsed_seconds.max(0.0);
+            return self.rate;
+        }
+        let elapsed_seconds = elapsed_seconds + core::mem::take(&mut self.skipped_seconds);
+        let celsius = temperature.celsius;
+        let Some(last) = self.last else {
+            self.last = Some(celsius);
+            return None;
+        };
+        if !elapsed_seconds.is_finite() || elapsed_seconds <= 0.0 {
+            return self.rate;
+        }
+
+        let rate = (celsius - last) / elapsed_seconds * 60.0;
+        let weight = elapsed_seconds / (self.smoothing_seconds + elapsed_seconds);
+        self.rate = Some(match self.rate {
+            Some(previous) => previous + weight * (rate - previous),
+            None => rate,
+        });
+        self.last = Some(celsius);
+        self.rate
+    }
+
+    /// °C per minute, positive while rising; `None` before two readings.
+    pub fn rate(&self) -> Option<f32> {
+        self.rate
+    }
+
+    /// Which limit of `threshold` the current rate exceeds, if any.
+    pub fn check(&self, threshold: &GradientThreshold) -> Option<GradientViolation> {
+        self.rate.and_then(|rate| threshold.check(rate))
+    }
+
+    /// Forgets the readings so far, e.g. after a gap in the data or a clock
+    /// that was set back.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.rate = None;
+        self.skipped_seconds = 0.0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GradientViolation {
+    Rising,
+    Falling,
+}
+
+impl fmt::Display for GradientViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GradientViolation::Rising => write!(f, "rising"),
+            GradientViolation::Falling => write!(f, "falling"),
+        }
+    }
+}
+
+/// Fastest rise and fall allowed, in °C per minute; `None` does not limit
+/// that direction.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawGradientThreshold")]
+pub struct GradientThreshold {
+    max_rise: Option<f32>,
+    max_fall: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientError {
+    /// A limit is NaN, infinite, zero or negative; falls are limited by how
+    /// fast, not by a negative rate.
+    NotPositive,
+    NoLimit,
+}
+
+impl fmt::Display for GradientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GradientError::NotPositive => write!(f, "Rate limits must be positive finite numbers"),
+            GradientError::NoLimit => write!(f, "At least one of the rise and fall limits must be set"),
+        }
+    }
+}
+
+impl GradientThreshold {
+    pub const fn new(max_rise: Option<f32>, max_fall: Option<f32>) -> Result<Self, GradientError> {
+        if max_rise.is_none() && max_fall.is_none() {
+            return Err(GradientError::NoLimit);
+        }
+        if !Self::positive(max_rise) || !Self::positive(max_fall) {
+            return Err(GradientError::NotPositive);
+        }
+        Ok(Self { max_rise, max_fall })
+    }
+
+    pub const fn rising(max_rise: f32) -> Result<Self, GradientError> {
+        Self::new(Some(max_rise), None)
+    }
+
+    pub const fn falling(max_fall: f32) -> Result<Self, GradientError> {
+        Self::new(None, Some(max_fall))
+    }
+
+    /// The same limit both ways.
+    pub const fn either(max_rate: f32) -> Result<Self, GradientError> {
+        Self::new(Some(max_rate), Some(max_rate))
+    }
+
+    const fn positive(limit: Option<f32>) -> bool {
+        match limit {
+            Some(limit) => limit.is_finite() && limit > 0.0,
+            None => true,
+        }
+    }
+
+    pub const fn max_rise(&self) -> Option<f32> {
+        self.max_rise
+    }
+
+    pub const fn max_fall(&self) -> Option<f32> {
+        self.max_fall
+    }
+
+    /// Which limit `rate`, in °C per minute, exceeds, if any.
+    pub fn check(&self, rate: f32) -> Option<GradientViolation> {
+        if self.max_rise.is_some_and(|max_rise| rate > max_rise) {
+            Some(GradientViolation::Rising)
+        } else if self.max_fall.is_some_and(|max_fall| -rate > max_fall) {
+            Some(GradientViolation::Falling)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawGradientThreshold {
+    #[serde(default)]
+    max_rise: Option<f32>,
+    #[serde(default)]
+    max_fall: Option<f32>,
+}
+
+impl TryFrom<RawGradientThreshold> for GradientThreshold {
+    type Error = GradientError;
+
+    fn try_from(raw: RawGradientThreshold) -> Result<Self, Self::Error> {
+        Self::new(raw.max_rise, raw.max_fall)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_degrees_per_minute() {
+        let mut gradient = TemperatureGradient::new();
+        assert_eq!(gradient.update(Temperature::new(20.0), 0.0), None);
+        assert_eq!(gradient.update(Temperature::new(21.0), 30.0), Some(2.0));
+        // Skipped: no time passed, or not a temperature
+        assert_eq!(gradient.update(Temperature::new(30.0), 0.0), Some(2.0));
+        assert_eq!(gradient.update(Temperature::new(f32::NAN), 30.0), Some(2.0));
+        assert_eq!(gradient.update(Temperature::new(20.0), 30.0), Some(-1.0));
+
+        let rising = GradientThreshold::rising(1.5).unwrap();
+        assert_eq!(rising.check(2.0), Some(GradientViolation::Rising));
+        assert_eq!(rising.check(-10.0), None);
+        assert_eq!(gradient.check(&GradientThreshold::either(0.5).unwrap()), Some(GradientViolation::Falling));
+
+        gradient.reset();
+        assert_eq!(gradient.rate(), None);
+    }
+
+    #[test]
+    fn smoothing_weighs_intervals_by_length() {
+        let mut gradient = TemperatureGradient::new().with_smoothing(60.0);
+        gradient.update(Temperature::new(20.0), 0.0);
+        assert_eq!(gradient.update(Temperature::new(22.0), 60.0), Some(2.0));
+        // A flat minute halves it, as two flat half minutes nearly do
+        assert_eq!(gradient.update(Temperature::new(22.0), 60.0), Some(1.0));
+        gradient.update(Temperature::new(22.0), 30.0);
+        let rate = gradient.update(Temperature::new(22.0), 30.0).unwrap();
+        assert!((rate - 0.44).abs() < 0.01, "{}", rate);
+    }
+
+    #[test]
+    fn thresholds_are_validated() {
+        assert_eq!(GradientThreshold::new(None, None), Err(GradientError::NoLimit));
+        assert_eq!(GradientThreshold::rising(-2.0), Err(GradientError::NotPositive));
+        assert_eq!(GradientThreshold::falling(f32::NAN), Err(GradientError::NotPositive));
+
+        let threshold: GradientThreshold = serde_json::from_str(r#"{"max_rise": 2.0}"#).unwrap();
+        assert_eq!(threshold, GradientThreshold::rising(2.0).unwrap());
+        assert!(serde_json::from_str::<GradientThreshold>(r#"{"max_fall": 0.0}"#).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+//! Sensor self-tests.
+//!
+//! A sensor that stops reporting may be dead, or just quiet: a deadband
+//! transform drops readings that did not change. [`self_test`] asks the
+//! sensor itself. Drivers check what their hardware offers, such as status
+//! and configuration registers; by default one reading is taken and has to
+//! be a possible temperature. A self-test that fails with an error could
+//! not reach the sensor at all.
+//!
+//! [`self_test`]: crate::TemperatureSensor::self_test
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Temperature;
+
+/// From best to worst, so results compare by severity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SensorHealth {
+    #[default]
+    Ok,
+    /// Readings still come but are less trustworthy, e.g. outside the
+    /// specified range or with settings lost to a reset.
+    Degraded,
+    /// The sensor answers with readings that cannot be right.
+    Failed,
+}
+
+impl SensorHealth {
+    /// What a single reading says about the sensor.
+    pub fn of_reading(temperature: Temperature) -> Self {
+        match temperature.validate() {
+            Ok(()) => SensorHealth::Ok,
+            Err(_) => SensorHealth::Failed,
+        }
+    }
+
+    /// Whether readings can still be used, if with care.
+    pub fn is_working(self) -> bool {
+        self != SensorHealth::Failed
+    }
+}
+
+impl fmt::Display for SensorHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SensorHealth::Ok => write!(f, "ok"),
+            SensorHealth::Degraded => write!(f, "degraded"),
+            SensorHealth::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// The last self-test result of a sensor, shared by the task sampling it
+/// and whoever reports on it; `None` until the first test.
+#[cfg(feature = "std")]
+pub type SharedHealth = std::sync::Arc<std::sync::Mutex<Option<SensorHealth>>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readings_and_ordering() {
+        assert_eq!(SensorHealth::of_reading(Temperature::new(21.0)), SensorHealth::Ok);
+        assert_eq!(SensorHealth::of_reading(Temperature::new(f32::NAN)), SensorHealth::Failed);
+        assert_eq!(SensorHealth::Ok.max(SensorHealth::Degraded), SensorHealth::Degraded);
+        assert_eq!(SensorHealth::Failed.max(SensorHealth::Degraded), SensorHealth::Failed);
+        assert!(SensorHealth::Degraded.is_working() && !SensorHealth::Failed.is_working());
+    }
+}
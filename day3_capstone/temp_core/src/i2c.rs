@@ -0,0 +1,621 @@
+//! Drivers for I2C temperature sensors, with the `i2c` feature.
+//!
+//! Both drivers work over any [`embedded_hal::i2c::I2c`] bus and take a
+//! [`DelayNs`] to wait for conversions, so they run on every HAL with
+//! embedded-hal 1.0 support and only need the bus in between reads.
+//!
+//! - [`Tmp102`]: 0.0625 °C resolution. Configured for continuous conversion
+//!   at a chosen rate, or kept in shutdown and woken for a one-shot
+//!   conversion per read, which draws under 1 µA in between.
+//! - [`Sht31`]: temperature and humidity from single-shot measurements,
+//!   checked against the sensor's CRC. It is an [`EnvironmentalSensor`].
+//!
+//! A conversion that is not ready within its datasheet maximum (with some
+//! margin) fails with [`I2cSensorError::NotReady`] instead of blocking.
+//!
+//! Both self-tests read back registers besides taking a reading: the TMP102
+//! its configuration, which a brown-out resets, and the SHT31 its status,
+//! which shows whether the heater is warming the sensor.
+
+use core::fmt;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{Error as _, ErrorKind, I2c, NoAcknowledgeSource};
+
+use crate::environment::{EnvironmentalReading, EnvironmentalSensor, Humidity};
+use crate::info::{MeasurementRange, SensorInfo};
+use crate::{SensorHealth, Temperature, TemperatureSensor};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum I2cSensorError<E> {
+    Bus(E),
+    /// A received word did not match its checksum.
+    Crc,
+    /// The conversion did not finish in time.
+    NotReady,
+}
+
+impl<E: fmt::Debug> fmt::Display for I2cSensorError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            I2cSensorError::Bus(e) => write!(f, "I2C bus error: {:?}", e),
+            I2cSensorError::Crc => write!(f, "Sensor data failed its checksum"),
+            I2cSensorError::NotReady => write!(f, "Sensor conversion did not finish in time"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for I2cSensorError<E> {}
+
+impl<E> From<E> for I2cSensorError<E> {
+    fn from(e: E) -> Self {
+        I2cSensorError::Bus(e)
+    }
+}
+
+/// How often a TMP102 in continuous mode converts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConversionRate {
+    QuarterHertz,
+    OneHertz,
+    #[default]
+    FourHertz,
+    EightHertz,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tmp102Mode {
+    /// Converts continuously; reads return the latest conversion.
+    #[default]
+    Continuous,
+    /// Sleeps between reads, each of which triggers and waits for a conversion.
+    OneShot,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tmp102Config {
+    pub mode: Tmp102Mode,
+    pub rate: ConversionRate,
+    /// 13-bit readings, extending the range from 128 °C to 150 °C.
+    pub extended: bool,
+}
+
+impl Tmp102Config {
+    /// The configuration register; the read-only resolution bits are written
+    /// as they read.
+    fn register(&self, start_conversion: bool) -> [u8; 2] {
+        let shutdown = self.mode == Tmp102Mode::OneShot;
+        let high = 0x60 | (u8::from(start_conversion) << 7) | u8::from(shutdown);
+        let low = ((self.rate as u8) << 6) | (u8::from(self.extended) << 4);
+        [high, low]
+    }
+}
+
+pub struct Tmp102<I2C, D> {
+    i2c: I2C,
+    delay: D,
+    address: u8,
+    config: Tmp102Config,
+    id: &'static str,
+}
+
+impl<I2C: I2c, D: DelayNs> Tmp102<I2C, D> {
+    /// With ADD0 tied to ground; 0x49 to 0x4B for V+, SDA and SCL.
+    pub const DEFAULT_ADDRESS: u8 = 0x48;
+
+    const TEMPERATURE: u8 = 0x00;
+    const CONFIG: u8 = 0x01;
+    /// One-shot bit in the configuration's first byte.
+    const ONE_SHOT: u8 = 0x80;
+    const RESOLUTION: u8 = 0x60;
+    const SHUTDOWN: u8 = 0x01;
+    /// Conversion rate and extended mode bits in the second byte.
+    const RATE_AND_EXTENDED: u8 = 0xD0;
+    /// A conversion takes 26 ms typically, 35 ms at most.
+    const POLL_MS: u32 = 5;
+    const MAX_POLLS: u32 = 10;
+
+    /// Writes `config` to the sensor at `address`.
+    pub fn new(i2c: I2C, delay: D, address: u8, config: Tmp102Config) -> Result<Self, I2cSensorError<I2C::Error>> {
+        let mut sensor = Self { i2c, delay, address, config, id: "tmp102" };
+        sensor.configure(config)?;
+        Ok(sensor)
+    }
+
+    pub fn with_id(mut self, id: &'static str) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn config(&self) -> Tmp102Config {
+        self.config
+    }
+
+    pub fn configure(&mut self, config: Tmp102Config) -> Result<(), I2cSensorError<I2C::Error>> {
+        let [high, low] = config.register(false);
+        self.i2c.write(self.address, &[Self::CONFIG, high, low])?;
+        self.config = config;
+        Ok(())
+    }
+
+    /// Triggers a conversion and polls until it is done.
+    fn convert(&mut self) -> Result<(), I2cSensorError<I2C::Error>> {
+        let [high, low] = self.config.register(true);
+        self.i2c.write(self.address, &[Self::CONFIG, high, low])?;
+        for _ in 0..Self::MAX_POLLS {
+            self.delay.delay_ms(Self::POLL_MS);
+            let mut config = [0; 2];
+            self.i2c.write_read(self.address, &[Self::CONFIG], &mut config)?;
+            // The one-shot bit reads 0 while converting
+            if config[0] & Self::ONE_SHOT != 0 {
+                return Ok(());
+            }
+        }
+        Err(I2cSensorError::NotReady)
+    }
+
+    pub fn read(&mut self) -> Result<Temperature, I2cSensorError<I2C::Error>> {
+        if self.config.mode == Tmp102Mode::OneShot {
+            self.convert()?;
+        }
+        let mut raw = [0; 2];
+        self.i2c.write_read(self.address, &[Self::TEMPERATURE], &mut raw)?;
+        Ok(Temperature::new(tmp102_celsius(raw)))
+    }
+
+    /// Failed if the configuration does not read like a TMP102's, degraded
+    /// if it lost the one written, e.g. to a brown-out, or the reading is
+    /// outside the specified range.
+    pub fn self_test(&mut self) -> Result<SensorHealth, I2cSensorError<I2C::Error>> {
+        let mut config = [0; 2];
+        self.i2c.write_read(self.address, &[Self::CONFIG], &mut config)?;
+        // The resolution bits always read as ones
+        if config[0] & Self::RESOLUTION != Self::RESOLUTION {
+            return Ok(SensorHealth::Failed);
+        }
+        let expected = self.config.register(false);
+        let kept = config[0] & Self::SHUTDOWN == expected[0] & Self::SHUTDOWN
+            && config[1] & Self::RATE_AND_EXTENDED == expected[1] & Self::RATE_AND_EXTENDED;
+
+        let temperature = self.read()?;
+        let health = match SensorHealth::of_reading(temperature) {
+            SensorHealth::Ok if !kept || !self.range().contains(temperature) => SensorHealth::Degraded,
+            health => health,
+        };
+        Ok(health)
+    }
+
+    /// Gives back the bus and the delay.
+    pub fn release(self) -> (I2C, D) {
+        (self.i2c, self.delay)
+    }
+}
+
+/// The temperature register holds a left-justified two's complement value
+/// of 1/16 °C; its lowest bit flags the 13-bit extended format.
+fn tmp102_celsius(raw: [u8; 2]) -> f32 {
+    let word = i16::from_be_bytes(raw);
+    let sixteenths = if raw[1] & 1 != 0 { word >> 3 } else { word >> 4 };
+    f32::from(sixteenths) * 0.0625
+}
+
+impl<I2C: I2c, D: DelayNs> TemperatureSensor for Tmp102<I2C, D> {
+    type Error = I2cSensorError<I2C::Error>;
+
+    fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+        self.read()
+    }
+
+    fn sensor_id(&self) -> &str {
+        self.id
+    }
+
+    fn self_test(&mut self) -> Result<SensorHealth, Self::Error> {
+        Tmp102::self_test(self)
+    }
+}
+
+impl<I2C: I2c, D: DelayNs> SensorInfo for Tmp102<I2C, D> {
+    /// Between -25 and 85 °C; up to ±2 °C over the whole range.
+    fn accuracy(&self) -> f32 {
+        0.5
+    }
+
+    fn resolution(&self) -> f32 {
+        0.0625
+    }
+
+    fn range(&self) -> MeasurementRange {
+        MeasurementRange::new(-40.0, 125.0)
+    }
+
+    fn manufacturer(&self) -> &str {
+        "Texas Instruments"
+    }
+
+    fn model(&self) -> &str {
+        "TMP102"
+    }
+}
+
+/// Higher repeatability means less noise but longer, costlier measurements.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Repeatability {
+    #[default]
+    High,
+    Medium,
+    Low,
+}
+
+impl Repeatability {
+    /// Second byte of the single-shot command without clock stretching.
+    fn command(self) -> u8 {
+        match self {
+            Repeatability::High => 0x00,
+            Repeatability::Medium => 0x0B,
+            Repeatability::Low => 0x16,
+        }
+    }
+
+    /// Maximum measurement duration from the datasheet.
+    fn duration_ms(self) -> u32 {
+        match self {
+            Repeatability::High => 15,
+            Repeatability::Medium => 6,
+            Repeatability::Low => 4,
+        }
+    }
+}
+
+pub struct Sht31<I2C, D> {
+    i2c: I2C,
+    delay: D,
+    address: u8,
+    repeatability: Repeatability,
+    id: &'static str,
+}
+
+impl<I2C: I2c, D: DelayNs> Sht31<I2C, D> {
+    /// With ADDR low; 0x45 with ADDR high.
+    pub const DEFAULT_ADDRESS: u8 = 0x44;
+
+    const SINGLE_SHOT: u8 = 0x24;
+    const SOFT_RESET: [u8; 2] = [0x30, 0xA2];
+    const HEATER_ON: [u8; 2] = [0x30, 0x6D];
+    const HEATER_OFF: [u8; 2] = [0x30, 0x66];
+    const READ_STATUS: [u8; 2] = [0xF3, 0x2D];
+    const STATUS_HEATER_ON: u16 = 1 << 13;
+    /// Until the measurement is done the sensor does not acknowledge reads.
+    const POLL_MS: u32 = 1;
+    const MAX_POLLS: u32 = 10;
+
+    pub fn new(i2c: I2C, delay: D, address: u8) -> Self {
+        Self { i2c, delay, address, repeatability: Repeatability::default(), id: "sht31" }
+    }
+
+    pub fn with_id(mut self, id: &'static str) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_repeatability(mut self, repeatability: Repeatability) -> Self {
+        self.repeatability = repeatability;
+        self
+    }
+
+    /// Resets the sensor, which takes up to 1.5 ms.
+    pub fn soft_reset(&mut self) -> Result<(), I2cSensorError<I2C::Error>> {
+        self.i2c.write(self.address, &Self::SOFT_RESET)?;
+        self.delay.delay_ms(2);
+        Ok(())
+    }
+
+    /// The built-in heater, for checking the sensor or drying off condensation.
+    pub fn set_heater(&mut self, on: bool) -> Result<(), I2cSensorError<I2C::Error>> {
+        let command = if on { Self::HEATER_ON } else { Self::HEATER_OFF };
+        self.i2c.write(self.address, &command)?;
+        Ok(())
+    }
+
+    /// The status register: alerts, heater and reset flags.
+    pub fn status(&mut self) -> Result<u16, I2cSensorError<I2C::Error>> {
+        let mut data = [0; 3];
+        self.i2c.write_read(self.address, &Self::READ_STATUS, &mut data)?;
+        sht31_word([data[0], data[1]], data[2])
+    }
+
+    /// Degraded while the heater is on, since it warms the sensor, or for a
+    /// reading outside the specified range.
+    pub fn self_test(&mut self) -> Result<SensorHealth, I2cSensorError<I2C::Error>> {
+        let heating = self.status()? & Self::STATUS_HEATER_ON != 0;
+        let (temperature, _) = self.measure()?;
+        let health = match SensorHealth::of_reading(temperature) {
+            SensorHealth::Ok if heating || !self.range().contains(temperature) => SensorHealth::Degraded,
+            health => health,
+        };
+        Ok(health)
+    }
+
+    /// Runs a single-shot measurement and waits for its result.
+    pub fn measure(&mut self) -> Result<(Temperature, Humidity), I2cSensorError<I2C::Error>> {
+        self.i2c.write(self.address, &[Self::SINGLE_SHOT, self.repeatability.command()])?;
+        self.delay.delay_ms(self.repeatability.duration_ms());
+
+        let mut data = [0; 6];
+        let mut polls = 0;
+        while let Err(e) = self.i2c.read(self.address, &mut data) {
+            // A NACK of the address means the measurement is still running
+            if !matches!(e.kind(), ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address | NoAcknowledgeSource::Unknown)) {
+                return Err(I2cSensorError::Bus(e));
+            }
+            polls += 1;
+            if polls > Self::MAX_POLLS {
+                return Err(I2cSensorError::NotReady);
+            }
+            self.delay.delay_ms(Self::POLL_MS);
+        }
+
+        let temperature = sht31_word([data[0], data[1]], data[2])?;
+        let humidity = sht31_word([data[3], data[4]], data[5])?;
+        Ok((
+            Temperature::new(-45.0 + 175.0 * f32::from(temperature) / 65535.0),
+            Humidity::new(100.0 * f32::from(humidity) / 65535.0),
+        ))
+    }
+
+    /// Gives back the bus and the delay.
+    pub fn release(self) -> (I2C, D) {
+        (self.i2c, self.delay)
+    }
+}
+
+fn sht31_word<E>(word: [u8; 2], crc: u8) -> Result<u16, I2cSensorError<E>> {
+    if sht31_crc(word) != crc {
+        return Err(I2cSensorError::Crc);
+    }
+    Ok(u16::from_be_bytes(word))
+}
+
+/// CRC-8 with polynomial 0x31 and initial value 0xFF.
+fn sht31_crc(data: [u8; 2]) -> u8 {
+    data.iter().fold(0xFF, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| if crc & 0x80 != 0 { (crc << 1) ^ 0x31 } else { crc << 1 })
+    })
+}
+
+impl<I2C: I2c, D: DelayNs> TemperatureSensor for Sht31<I2C, D> {
+    type Error = I2cSensorError<I2C::Error>;
+
+    fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+        self.measure().map(|(temperature, _)| temperature)
+    }
+
+    fn sensor_id(&self) -> &str {
+        self.id
+    }
+
+    fn self_test(&mut self) -> Result<SensorHealth, Self::Error> {
+        Sht31::self_test(self)
+    }
+}
+
+impl<I2C: I2c, D: DelayNs> SensorInfo for Sht31<I2C, D> {
+    /// Between 0 and 90 °C.
+    fn accuracy(&self) -> f32 {
+        0.2
+    }
+
+    fn resolution(&self) -> f32 {
+        0.015
+    }
+
+    fn range(&self) -> MeasurementRange {
+        MeasurementRange::new(-40.0, 125.0)
+    }
+
+    fn manufacturer(&self) -> &str {
+        "Sensirion"
+    }
+
+    fn model(&self) -> &str {
+        "SHT31"
+    }
+}
+
+impl<I2C: I2c, D: DelayNs> EnvironmentalSensor for Sht31<I2C, D> {
+    fn read_environment(&mut self) -> Result<EnvironmentalReading, Self::Error> {
+        let (temperature, humidity) = self.measure()?;
+        Ok(EnvironmentalReading::new(temperature).with_humidity(humidity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::{ErrorType, Operation};
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    /// A TMP102 whose one-shot conversion takes `busy_polls` config reads.
+    struct FakeTmp102 {
+        pointer: u8,
+        config: [u8; 2],
+        temperature: [u8; 2],
+        busy_polls: u32,
+    }
+
+    /// An SHT31 that does not acknowledge the first `busy_reads` reads after a command.
+    struct FakeSht31 {
+        last_command: [u8; 2],
+        busy_reads: u32,
+        data: [u8; 6],
+        heater: bool,
+    }
+
+    impl ErrorType for FakeTmp102 {
+        type Error = ErrorKind;
+    }
+
+    impl ErrorType for FakeSht31 {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for FakeTmp102 {
+        fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), ErrorKind> {
+            assert_eq!(address, 0x48);
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) => {
+                        self.pointer = bytes[0];
+                        if let [0x01, high, low] = **bytes {
+                            self.config = [high & !0x80, low];
+                            if high & 0x80 != 0 && self.busy_polls == 0 {
+                                self.config[0] |= 0x80;
+                            }
+                        }
+                    }
+                    Operation::Read(buffer) => {
+                        if self.pointer == 0x01 {
+                            if self.busy_polls > 0 {
+                                self.busy_polls -= 1;
+                            } else {
+                                self.config[0] |= 0x80;
+                            }
+                            buffer.copy_from_slice(&self.config);
+                        } else {
+                            buffer.copy_from_slice(&self.temperature);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl I2c for FakeSht31 {
+        fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), ErrorKind> {
+            assert_eq!(address, 0x44);
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) => {
+                        self.last_command = [bytes[0], bytes[1]];
+                        match self.last_command {
+                            [0x30, 0x6D] => self.heater = true,
+                            [0x30, 0x66] => self.heater = false,
+                            _ => {}
+                        }
+                    }
+                    Operation::Read(buffer) if self.last_command == [0xF3, 0x2D] => {
+                        let status = (u16::from(self.heater) << 13).to_be_bytes();
+                        buffer.copy_from_slice(&[status[0], status[1], sht31_crc(status)]);
+                    }
+                    Operation::Read(buffer) => {
+                        if self.busy_reads > 0 {
+                            self.busy_reads -= 1;
+                            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+                        }
+                        buffer.copy_from_slice(&self.data);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn sht31_data(temperature: u16, humidity: u16) -> [u8; 6] {
+        let [t0, t1] = temperature.to_be_bytes();
+        let [h0, h1] = humidity.to_be_bytes();
+        [t0, t1, sht31_crc([t0, t1]), h0, h1, sht31_crc([h0, h1])]
+    }
+
+    #[test]
+    fn converts_tmp102_registers() {
+        assert_eq!(tmp102_celsius([0x19, 0x00]), 25.0);
+        assert_eq!(tmp102_celsius([0xFF, 0xF0]), -0.0625);
+        assert_eq!(tmp102_celsius([0xE7, 0x00]), -25.0);
+        // Extended format: 150 °C
+        assert_eq!(tmp102_celsius([0x4B, 0x01]), 150.0);
+    }
+
+    #[test]
+    fn tmp102_configures_and_polls_one_shot_conversions() {
+        let bus = FakeTmp102 { pointer: 0, config: [0x60, 0xA0], temperature: [0x19, 0x40], busy_polls: 3 };
+        let config = Tmp102Config { mode: Tmp102Mode::OneShot, rate: ConversionRate::OneHertz, extended: false };
+        let mut sensor = Tmp102::new(bus, NoDelay, Tmp102::<FakeTmp102, NoDelay>::DEFAULT_ADDRESS, config).unwrap();
+        assert_eq!(sensor.read_temperature().unwrap(), Temperature::new(25.25));
+        assert_eq!(sensor.sensor_id(), "tmp102");
+
+        let (mut bus, delay) = sensor.release();
+        assert_eq!(bus.config, [0xE1, 0x40]);
+        bus.busy_polls = 20;
+        let mut sensor = Tmp102::new(bus, delay, 0x48, config).unwrap().with_id("boiler");
+        assert_eq!(sensor.read_temperature(), Err(I2cSensorError::NotReady));
+        assert_eq!(sensor.sensor_id(), "boiler");
+    }
+
+    #[test]
+    fn sht31_waits_for_the_measurement_and_checks_crcs() {
+        // 0x6666 is 25 °C, 0x8000 about 50 %RH
+        let bus = FakeSht31 { last_command: [0; 2], busy_reads: 2, data: sht31_data(0x6666, 0x8000), heater: false };
+        let mut sensor = Sht31::new(bus, NoDelay, 0x44).with_repeatability(Repeatability::Medium);
+        let reading = sensor.read_environment().unwrap();
+        assert!((reading.temperature.celsius - 25.0).abs() < 0.01);
+        assert!((reading.humidity.unwrap().percent - 50.0).abs() < 0.01);
+
+        let (mut bus, delay) = sensor.release();
+        assert_eq!(bus.last_command, [0x24, 0x0B]);
+        bus.data[2] ^= 0xFF;
+        let mut sensor = Sht31::new(bus, delay, 0x44);
+        assert_eq!(sensor.read_temperature(), Err(I2cSensorError::Crc));
+
+        let (mut bus, delay) = sensor.release();
+        bus.busy_reads = 20;
+        let mut sensor = Sht31::new(bus, delay, 0x44);
+        assert_eq!(sensor.read_temperature(), Err(I2cSensorError::NotReady));
+    }
+
+    #[test]
+    fn tmp102_self_test_checks_the_configuration() {
+        let bus = FakeTmp102 { pointer: 0, config: [0x60, 0xA0], temperature: [0x19, 0x00], busy_polls: 0 };
+        let mut sensor = Tmp102::new(bus, NoDelay, 0x48, Tmp102Config::default()).unwrap();
+        assert_eq!(sensor.self_test(), Ok(SensorHealth::Ok));
+
+        // A brown-out put it back into shutdown
+        let (mut bus, delay) = sensor.release();
+        bus.config[0] |= 0x01;
+        let mut sensor = Tmp102 { i2c: bus, delay, address: 0x48, config: Tmp102Config::default(), id: "tmp102" };
+        assert_eq!(sensor.self_test(), Ok(SensorHealth::Degraded));
+
+        // Not a TMP102 answering, or a stuck bus
+        let (mut bus, delay) = sensor.release();
+        bus.config = [0x00, 0x00];
+        let mut sensor = Tmp102 { i2c: bus, delay, address: 0x48, config: Tmp102Config::default(), id: "tmp102" };
+        assert_eq!(sensor.self_test(), Ok(SensorHealth::Failed));
+    }
+
+    #[test]
+    fn sht31_self_test_reads_the_heater_status() {
+        let bus = FakeSht31 { last_command: [0; 2], busy_reads: 0, data: sht31_data(0x6666, 0x8000), heater: false };
+        let mut sensor = Sht31::new(bus, NoDelay, 0x44);
+        assert_eq!(sensor.status(), Ok(0));
+        assert_eq!(TemperatureSensor::self_test(&mut sensor), Ok(SensorHealth::Ok));
+
+        sensor.set_heater(true).unwrap();
+        assert_eq!(sensor.self_test(), Ok(SensorHealth::Degraded));
+        sensor.set_heater(false).unwrap();
+        assert_eq!(sensor.self_test(), Ok(SensorHealth::Ok));
+
+        let (mut bus, delay) = sensor.release();
+        bus.data[5] ^= 0xFF;
+        assert_eq!(Sht31::new(bus, delay, 0x44).self_test(), Err(I2cSensorError::Crc));
+    }
+
+    #[test]
+    fn sht31_crc_matches_the_datasheet() {
+        assert_eq!(sht31_crc([0xBE, 0xEF]), 0x92);
+    }
+}
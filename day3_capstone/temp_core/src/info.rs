@@ -0,0 +1,87 @@
+//! What a sensor's readings can be trusted to.
+//!
+//! A datasheet gives a sensor's accuracy (how far a reading may be off the
+//! true temperature), its resolution (the smallest step it reports) and the
+//! range it measures. [`SensorInfo`] exposes them, so a client can tell
+//! whether a change is real: a 0.5 °C step is one count of a DS18B20 at 9
+//! bits, but thirty of an SHT31.
+
+use serde::{Deserialize, Serialize};
+
+use crate::Temperature;
+
+/// Lowest and highest temperature a sensor is specified for.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MeasurementRange {
+    pub min: Temperature,
+    pub max: Temperature,
+}
+
+impl MeasurementRange {
+    pub fn new(min_celsius: f32, max_celsius: f32) -> Self {
+        Self {
+            min: Temperature::new(min_celsius),
+            max: Temperature::new(max_celsius),
+        }
+    }
+
+    pub fn contains(&self, temperature: Temperature) -> bool {
+        (self.min.celsius..=self.max.celsius).contains(&temperature.celsius)
+    }
+}
+
+pub trait SensorInfo {
+    /// Typical accuracy from the datasheet, ± °C.
+    fn accuracy(&self) -> f32;
+    /// Smallest step between two readings, °C; 0 if continuous.
+    fn resolution(&self) -> f32;
+    fn range(&self) -> MeasurementRange;
+    fn manufacturer(&self) -> &str;
+    /// Part number, e.g. "DS18B20".
+    fn model(&self) -> &str;
+
+    /// Whether a change of `delta` °C between two readings is more than the
+    /// single count that quantization alone can produce.
+    fn is_significant_change(&self, delta: f32) -> bool {
+        libm::fabsf(delta) > self.resolution()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Datasheet;
+
+    impl SensorInfo for Datasheet {
+        fn accuracy(&self) -> f32 {
+            0.5
+        }
+
+        fn resolution(&self) -> f32 {
+            0.5
+        }
+
+        fn range(&self) -> MeasurementRange {
+            MeasurementRange::new(-55.0, 125.0)
+        }
+
+        fn manufacturer(&self) -> &str {
+            "Maxim Integrated"
+        }
+
+        fn model(&self) -> &str {
+            "DS18B20"
+        }
+    }
+
+    #[test]
+    fn tells_changes_from_quantization_steps() {
+        let info = Datasheet;
+        assert!(!info.is_significant_change(0.5));
+        assert!(!info.is_significant_change(-0.25));
+        assert!(info.is_significant_change(-1.0));
+        assert!(info.range().contains(Temperature::new(-55.0)));
+        assert!(!info.range().contains(Temperature::new(130.0)));
+    }
+}
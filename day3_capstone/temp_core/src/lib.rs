@@ -1,4 +1,6 @@
 #![cfg_attr(not(feature = "std"), no_std)]
+#![forbid(unsafe_code)]
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used, clippy::panic))]
 
 use core::fmt;
 use serde::{Deserialize, Serialize};
@@ -8,37 +10,100 @@ pub struct Temperature {
     pub celsius: f32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TemperatureError {
+    /// NaN or infinite, e.g. from a division by zero in a conversion.
+    NotFinite,
+    BelowAbsoluteZero,
+}
+
+impl fmt::Display for TemperatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemperatureError::NotFinite => write!(f, "Temperature must be a finite number"),
+            TemperatureError::BelowAbsoluteZero => write!(f, "Temperature must not be below absolute zero"),
+        }
+    }
+}
+
+impl core::error::Error for TemperatureError {}
+
 impl Temperature {
-    pub fn new(celsius: f32) -> Self {
+    pub const ABSOLUTE_ZERO_CELSIUS: f32 = -273.15;
+
+    /// Not checked; use [`try_new`](Self::try_new) for values from outside,
+    /// as a NaN would poison every min, max and average it ends up in.
+    pub const fn new(celsius: f32) -> Self {
         Self { celsius }
     }
 
-    pub fn from_fahrenheit(fahrenheit: f32) -> Self {
+    pub const fn try_new(celsius: f32) -> Result<Self, TemperatureError> {
+        let temperature = Self { celsius };
+        match temperature.validate() {
+            Ok(()) => Ok(temperature),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Checks a temperature built with [`new`](Self::new) or deserialized.
+    pub const fn validate(&self) -> Result<(), TemperatureError> {
+        if !self.celsius.is_finite() {
+            return Err(TemperatureError::NotFinite);
+        }
+        if self.celsius < Self::ABSOLUTE_ZERO_CELSIUS {
+            return Err(TemperatureError::BelowAbsoluteZero);
+        }
+        Ok(())
+    }
+
+    pub const fn from_fahrenheit(fahrenheit: f32) -> Self {
         Self {
             celsius: (fahrenheit - 32.0) * 5.0 / 9.0,
         }
     }
 
-    pub fn from_kelvin(kelvin: f32) -> Self {
+    pub const fn from_kelvin(kelvin: f32) -> Self {
         Self {
-            celsius: kelvin - 273.15,
+            celsius: kelvin + Self::ABSOLUTE_ZERO_CELSIUS,
         }
     }
 
     /// Convert from embedded sensor ADC value to temperature
     /// Assumes 10mV/°C sensor with 3.3V reference and 12-bit ADC
-    pub fn from_embedded_sensor(adc_value: u16) -> Self {
-        let voltage = (adc_value as f32 / 4095.0) * 3.3;
-        let celsius = voltage / 0.01; // 10mV/°C sensor
-        Self { celsius }
+    pub const fn from_embedded_sensor(adc_value: u16) -> Self {
+        adc::AdcConfig::DEFAULT.temperature(adc_value)
+    }
+
+    /// Convert an ADC value from a sensor on a board described by `config`
+    pub const fn from_adc(adc_value: u16, config: &adc::AdcConfig) -> Self {
+        config.temperature(adc_value)
+    }
+
+    /// The ADC value a sensor on a board described by `config` reads at
+    /// this temperature, clamped to the ADC's range
+    pub const fn to_adc(&self, config: &adc::AdcConfig) -> u16 {
+        config.to_count(self.celsius)
     }
 
-    pub fn to_fahrenheit(&self) -> f32 {
+    /// Convert a thermocouple's EMF in microvolts, compensating for the
+    /// cold junction at `cold_junction`; `None` outside the probe's table
+    pub const fn from_thermocouple(
+        microvolts: i32,
+        cold_junction: Temperature,
+        thermocouple: &probe::Thermocouple,
+    ) -> Option<Self> {
+        match thermocouple.microvolts_to_celsius(microvolts, cold_junction.celsius) {
+            Some(celsius) => Some(Self::new(celsius)),
+            None => None,
+        }
+    }
+
+    pub const fn to_fahrenheit(&self) -> f32 {
         self.celsius * 9.0 / 5.0 + 32.0
     }
 
-    pub fn to_kelvin(&self) -> f32 {
-        self.celsius + 273.15
+    pub const fn to_kelvin(&self) -> f32 {
+        self.celsius - Self::ABSOLUTE_ZERO_CELSIUS
     }
 }
 
@@ -53,8 +118,67 @@ pub trait TemperatureSensor {
 
     fn read_temperature(&mut self) -> Result<Temperature, Self::Error>;
     fn sensor_id(&self) -> &str;
+
+    /// Reads up to `out.len()` samples, oldest first, and returns how many
+    /// were written. Drivers with a hardware FIFO override this to drain it
+    /// in one bus transaction; by default it reads a single sample.
+    fn read_temperatures(&mut self, out: &mut [Temperature]) -> Result<usize, Self::Error> {
+        match out.first_mut() {
+            Some(slot) => {
+                *slot = self.read_temperature()?;
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Checks that the sensor works, to tell a dead sensor from a quiet
+    /// one; see [`health`]. An error means it could not be reached.
+    fn self_test(&mut self) -> Result<SensorHealth, Self::Error> {
+        self.read_temperature().map(SensorHealth::of_reading)
+    }
 }
 
+pub mod adc;
+pub mod calibration;
+pub mod clock;
+pub mod composite;
+pub mod control;
+pub mod derived;
+pub mod environment;
+pub mod filter;
+pub mod fixed;
+pub mod gradient;
+pub mod health;
+#[cfg(feature = "i2c")]
+pub mod i2c;
+pub mod info;
+#[cfg(feature = "onewire")]
+pub mod onewire;
+pub mod precise;
+pub mod probe;
+pub mod retry;
+pub mod sensor_id;
+#[cfg(feature = "spi")]
+pub mod spi;
+pub mod threshold;
+pub mod transform;
+pub mod units;
+
+pub use calibration::{CalibratedSensor, Calibration};
+pub use composite::{Combine, CompositeSensor};
+pub use environment::{EnvironmentalReading, EnvironmentalSensor, Humidity, Pressure};
+pub use filter::{EmaSensor, MovingAverageSensor};
+pub use fixed::TemperatureFixed;
+pub use gradient::{GradientThreshold, TemperatureGradient};
+pub use health::SensorHealth;
+pub use info::{MeasurementRange, SensorInfo};
+pub use precise::PreciseTemperature;
+pub use retry::{RetryPolicy, RetrySensor};
+pub use sensor_id::SensorId;
+pub use threshold::{Alarm, AlarmState, ThresholdConfig};
+pub use units::{Celsius, Fahrenheit, Kelvin, ParseTemperatureError};
+
 #[cfg(feature = "std")]
 pub mod mock;
 
@@ -76,6 +200,26 @@ mod tests {
         assert!((from_k.celsius - 20.0).abs() < 0.1);
     }
 
+    #[test]
+    fn conversions_are_usable_in_constants() {
+        const BOILING: Temperature = Temperature::from_fahrenheit(212.0);
+        const BOILING_COUNT: u16 = BOILING.to_adc(&adc::AdcConfig::DEFAULT);
+        const { assert!(BOILING.celsius == 100.0 && BOILING.to_kelvin() == 373.15) };
+        const { assert!(Temperature::try_new(-300.0).is_err()) };
+        assert_eq!(BOILING_COUNT, 1241);
+        assert!((Temperature::from_embedded_sensor(BOILING_COUNT).celsius - 100.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn try_new_rejects_impossible_temperatures() {
+        assert_eq!(Temperature::try_new(21.5), Ok(Temperature::new(21.5)));
+        assert_eq!(Temperature::try_new(-273.15), Ok(Temperature::new(-273.15)));
+        assert_eq!(Temperature::try_new(-273.2), Err(TemperatureError::BelowAbsoluteZero));
+        assert_eq!(Temperature::try_new(f32::NAN), Err(TemperatureError::NotFinite));
+        assert_eq!(Temperature::try_new(f32::NEG_INFINITY), Err(TemperatureError::NotFinite));
+        assert!(Temperature::from_kelvin(-1.0).validate().is_err());
+    }
+
     #[test]
     fn temperature_display() {
         let temp = Temperature::new(23.456);
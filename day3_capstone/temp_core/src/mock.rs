@@ -1,7 +1,17 @@
-use crate::{Temperature, TemperatureSensor};
+use crate::clock::{Clock, SystemClock};
+use crate::control::Actuator;
+use crate::environment::{EnvironmentalReading, EnvironmentalSensor, Humidity, Pressure};
+use crate::info::{MeasurementRange, SensorInfo};
+use crate::{SensorHealth, Temperature, TemperatureSensor};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::TAU;
 use std::fmt;
+use std::ops::Range;
 extern crate alloc;
+use alloc::boxed::Box;
 use alloc::string::String;
+use alloc::vec::Vec;
 
 #[derive(Debug)]
 pub enum MockError {
@@ -18,11 +28,236 @@ impl fmt::Display for MockError {
     }
 }
 
+/// Environment variable that pins the seed returned by [`test_seed`].
+#[cfg(any(test, feature = "test-support"))]
+pub const TEST_SEED_VAR: &str = "TEMP_TEST_SEED";
+
+/// Seed for randomized tests: taken from `TEMP_TEST_SEED` if set, otherwise
+/// from the clock. Tests name it in their failure messages, e.g.
+/// `"{}={}", TEST_SEED_VAR, seed`, so a failing run can be replayed.
+#[cfg(any(test, feature = "test-support"))]
+pub fn test_seed() -> u64 {
+    std::env::var(TEST_SEED_VAR)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64)
+        })
+}
+
+/// Seconds in the period of [`NoiseProfile::daily_amplitude`].
+pub const DAY_SECONDS: u64 = 86_400;
+
+/// What a mock adds to its base temperature, in °C. Every part is off by
+/// default; combine them with `..Default::default()`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NoiseProfile {
+    /// Uniform noise of up to this much in either direction.
+    pub uniform: f32,
+    /// Standard deviation of Gaussian noise.
+    pub gaussian: f32,
+    /// Standard deviation of each step of a random walk; the drift builds
+    /// up from reading to reading, like an ageing sensor.
+    pub drift: f32,
+    /// Swing of a daily sine cycle, coldest at midnight UTC and warmest at noon.
+    pub daily_amplitude: f32,
+    /// Chance of a reading being a spike, off by `spike_magnitude` in
+    /// either direction.
+    pub spike_probability: f64,
+    pub spike_magnitude: f32,
+}
+
+/// Noise following a [`NoiseProfile`], reproducible from its seed.
+#[derive(Debug, Clone)]
+pub struct Noise {
+    profile: NoiseProfile,
+    seed: u64,
+    rng: SmallRng,
+    drift: f32,
+}
+
+impl Noise {
+    /// Uniform noise of up to `amplitude` in either direction.
+    pub fn new(amplitude: f32, seed: u64) -> Self {
+        Self::with_profile(NoiseProfile { uniform: amplitude, ..Default::default() }, seed)
+    }
+
+    pub fn with_profile(profile: NoiseProfile, seed: u64) -> Self {
+        Self {
+            profile,
+            seed,
+            rng: SmallRng::seed_from_u64(seed),
+            drift: 0.0,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn profile(&self) -> NoiseProfile {
+        self.profile
+    }
+
+    /// The noise of the next reading, but for the daily cycle. Parts that are
+    /// off draw nothing from the stream, so adding one keeps the others'
+    /// samples of a seed.
+    pub fn sample(&mut self) -> f32 {
+        let NoiseProfile { uniform, gaussian, drift, spike_probability, spike_magnitude, .. } = self.profile;
+        let mut noise = 0.0;
+        if uniform > 0.0 {
+            noise += self.rng.gen_range(-uniform..=uniform);
+        }
+        if gaussian > 0.0 {
+            noise += gaussian * self.standard_normal();
+        }
+        if drift > 0.0 {
+            self.drift += drift * self.standard_normal();
+            noise += self.drift;
+        }
+        if self.chance(spike_probability) {
+            noise += if self.rng.gen_bool(0.5) { spike_magnitude } else { -spike_magnitude };
+        }
+        noise
+    }
+
+    /// The daily cycle's offset at `now`, in seconds since the Unix epoch.
+    pub fn daily_cycle(&self, now: u64) -> f32 {
+        if self.profile.daily_amplitude == 0.0 {
+            return 0.0;
+        }
+        let phase = (now % DAY_SECONDS) as f32 / DAY_SECONDS as f32;
+        -self.profile.daily_amplitude * (TAU * phase).cos()
+    }
+
+    /// How far the random walk has drifted.
+    pub fn drift(&self) -> f32 {
+        self.drift
+    }
+
+    /// Starts the random walk over, as after recalibrating.
+    pub fn reset_drift(&mut self) {
+        self.drift = 0.0;
+    }
+
+    /// Box-Muller transform of two uniform samples.
+    fn standard_normal(&mut self) -> f32 {
+        let u1: f32 = 1.0 - self.rng.gen::<f32>();
+        let u2: f32 = self.rng.gen();
+        (-2.0 * u1.ln()).sqrt() * (TAU * u2).cos()
+    }
+
+    /// True with probability `p`, drawn from the same seeded stream.
+    pub fn chance(&mut self, p: f64) -> bool {
+        p > 0.0 && self.rng.gen_bool(p.min(1.0))
+    }
+}
+
+/// What a [`FailureSchedule`] does to a read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    /// The read fails.
+    Fail,
+    /// The read fails as if the sensor were unplugged.
+    Offline,
+    /// The read succeeds with this value instead of a measurement, like a
+    /// DS18B20 returning its 85 °C power-on value.
+    Garbage(f32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FaultRule {
+    Every(usize, Fault),
+    Reads(Range<usize>, Fault),
+    OfflineBetween(Range<u64>),
+}
+
+/// Scripted faults for a mock sensor, so tests can drive retries, watchdogs
+/// and error reports the same way on every run. Reads are numbered from 1
+/// and counted whether they succeed or not; a batch read counts once and a
+/// fault hits the whole batch. Times are the sensor's clock seconds.
+///
+/// When several rules hit the same read, being offline wins over failing,
+/// and failing over garbage.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FailureSchedule {
+    rules: Vec<FaultRule>,
+}
+
+impl FailureSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fails read `n`, `2n`, `3n` and so on; `n` of 0 never fails.
+    pub fn fail_every(self, n: usize) -> Self {
+        self.rule(FaultRule::Every(n, Fault::Fail))
+    }
+
+    /// Fails the reads numbered in `reads`, e.g. `3..6` for the third to the fifth.
+    pub fn fail_reads(self, reads: Range<usize>) -> Self {
+        self.rule(FaultRule::Reads(reads, Fault::Fail))
+    }
+
+    /// Answers every `n`th read with `celsius`.
+    pub fn garbage_every(self, n: usize, celsius: f32) -> Self {
+        self.rule(FaultRule::Every(n, Fault::Garbage(celsius)))
+    }
+
+    /// Answers the reads numbered in `reads` with `celsius`.
+    pub fn garbage_reads(self, reads: Range<usize>, celsius: f32) -> Self {
+        self.rule(FaultRule::Reads(reads, Fault::Garbage(celsius)))
+    }
+
+    /// Takes the sensor offline while the clock is in `window`; self-tests
+    /// fail then too.
+    pub fn offline_between(self, window: Range<u64>) -> Self {
+        self.rule(FaultRule::OfflineBetween(window))
+    }
+
+    fn rule(mut self, rule: FaultRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Whether the sensor is in an offline window at `now`.
+    pub fn is_offline(&self, now: u64) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| matches!(rule, FaultRule::OfflineBetween(window) if window.contains(&now)))
+    }
+
+    /// The fault of read number `read` at `now`, if any.
+    pub fn fault(&self, read: usize, now: u64) -> Option<Fault> {
+        if self.is_offline(now) {
+            return Some(Fault::Offline);
+        }
+        let mut faults = self.rules.iter().filter_map(|rule| match rule {
+            FaultRule::Every(n, fault) if read.is_multiple_of(*n) => Some(*fault),
+            FaultRule::Reads(reads, fault) if reads.contains(&read) => Some(*fault),
+            _ => None,
+        });
+        let first = faults.next()?;
+        Some(if faults.any(|fault| fault == Fault::Fail) { Fault::Fail } else { first })
+    }
+}
+
 pub struct MockTemperatureSensor {
     id: String,
     temperature: f32,
     fail_next: bool,
     offline: bool,
+    noise: Noise,
+    clock: Box<dyn Clock>,
+    failure_rate: f64,
+    schedule: FailureSchedule,
+    humidity: Option<f32>,
+    pressure: Option<f32>,
+    fifo_depth: usize,
+    transactions: usize,
+    health: SensorHealth,
 }
 
 impl MockTemperatureSensor {
@@ -32,9 +267,71 @@ impl MockTemperatureSensor {
             temperature,
             fail_next: false,
             offline: false,
+            noise: Noise::new(0.0, 0),
+            clock: Box::new(SystemClock),
+            failure_rate: 0.0,
+            schedule: FailureSchedule::new(),
+            humidity: None,
+            pressure: None,
+            fifo_depth: 1,
+            transactions: 0,
+            health: SensorHealth::Ok,
         }
     }
 
+    /// Also reports relative humidity, like an SHT31.
+    pub fn with_humidity(mut self, percent: f32) -> Self {
+        self.humidity = Some(percent);
+        self
+    }
+
+    /// Also reports pressure in hPa, like a BME280.
+    pub fn with_pressure(mut self, hectopascals: f32) -> Self {
+        self.pressure = Some(hectopascals);
+        self
+    }
+
+    /// Adds uniform noise of up to `amplitude` °C to every reading.
+    pub fn with_noise(mut self, amplitude: f32, seed: u64) -> Self {
+        self.noise = Noise::new(amplitude, seed);
+        self
+    }
+
+    /// Adds noise following `profile` to every reading.
+    pub fn with_profile(mut self, profile: NoiseProfile, seed: u64) -> Self {
+        self.noise = Noise::with_profile(profile, seed);
+        self
+    }
+
+    /// Time source of the daily cycle.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Fails each read with probability `rate`, drawn from the noise seed.
+    pub fn with_failure_rate(mut self, rate: f64) -> Self {
+        self.failure_rate = rate;
+        self
+    }
+
+    /// Injects the faults of `schedule`, see [`FailureSchedule`].
+    pub fn with_failure_schedule(mut self, schedule: FailureSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Replaces the failure schedule; read numbers keep counting from
+    /// [`transactions`](Self::transactions).
+    pub fn set_failure_schedule(&mut self, schedule: FailureSchedule) {
+        self.schedule = schedule;
+    }
+
+    /// Seed of the noise and failure stream, for reproducing a run.
+    pub fn seed(&self) -> u64 {
+        self.noise.seed()
+    }
+
     pub fn set_temperature(&mut self, temp: f32) {
         self.temperature = temp;
     }
@@ -47,29 +344,168 @@ impl MockTemperatureSensor {
         self.offline = offline;
     }
 
+    pub fn noise_mut(&mut self) -> &mut Noise {
+        &mut self.noise
+    }
+
     pub fn fail_next_read(&mut self) {
         self.fail_next = true;
     }
+
+    /// Returns up to `depth` samples per batch read, like a sensor with a
+    /// hardware FIFO. Failures hit the whole batch.
+    pub fn set_fifo_depth(&mut self, depth: usize) {
+        self.fifo_depth = depth.max(1);
+    }
+
+    /// What self-tests report while the sensor is online; reads are not affected.
+    pub fn set_health(&mut self, health: SensorHealth) {
+        self.health = health;
+    }
+
+    /// Reads so far, counting a batch read once.
+    pub fn transactions(&self) -> usize {
+        self.transactions
+    }
+
+    /// Checks whether this read fails; `Some` holds a garbage value to
+    /// return in place of the samples.
+    fn begin_read(&mut self) -> Result<Option<Temperature>, MockError> {
+        self.transactions += 1;
+        let fault = self.schedule.fault(self.transactions, self.clock.now());
+
+        if self.offline || fault == Some(Fault::Offline) {
+            return Err(MockError::SensorOffline);
+        }
+
+        if self.fail_next || fault == Some(Fault::Fail) {
+            self.fail_next = false;
+            return Err(MockError::ReadFailed);
+        }
+
+        if self.noise.chance(self.failure_rate) {
+            return Err(MockError::ReadFailed);
+        }
+
+        Ok(match fault {
+            Some(Fault::Garbage(celsius)) => Some(Temperature::new(celsius)),
+            _ => None,
+        })
+    }
+
+    fn sample(&mut self) -> Temperature {
+        let now = self.clock.now();
+        Temperature::new(self.temperature + self.noise.daily_cycle(now) + self.noise.sample())
+    }
 }
 
 impl TemperatureSensor for MockTemperatureSensor {
     type Error = MockError;
 
     fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
-        if self.offline {
+        let garbage = self.begin_read()?;
+        Ok(garbage.unwrap_or_else(|| self.sample()))
+    }
+
+    fn read_temperatures(&mut self, out: &mut [Temperature]) -> Result<usize, Self::Error> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        let garbage = self.begin_read()?;
+        let count = out.len().min(self.fifo_depth);
+        for slot in &mut out[..count] {
+            *slot = garbage.unwrap_or_else(|| self.sample());
+        }
+        Ok(count)
+    }
+
+    fn sensor_id(&self) -> &str {
+        &self.id
+    }
+
+    /// Takes no reading, so the noise and failure stream stay as they were.
+    fn self_test(&mut self) -> Result<SensorHealth, Self::Error> {
+        if self.offline || self.schedule.is_offline(self.clock.now()) {
             return Err(MockError::SensorOffline);
         }
+        Ok(self.health)
+    }
+}
+
+/// Readings are exact but for the noise, over any range. Drift, cycles and
+/// spikes are what tests look for, not inaccuracy.
+impl SensorInfo for MockTemperatureSensor {
+    fn accuracy(&self) -> f32 {
+        let profile = self.noise.profile();
+        profile.uniform + profile.gaussian
+    }
+
+    fn resolution(&self) -> f32 {
+        0.0
+    }
+
+    fn range(&self) -> MeasurementRange {
+        MeasurementRange::new(Temperature::ABSOLUTE_ZERO_CELSIUS, f32::MAX)
+    }
+
+    fn manufacturer(&self) -> &str {
+        "temp_core"
+    }
+
+    fn model(&self) -> &str {
+        "mock"
+    }
+}
+
+impl EnvironmentalSensor for MockTemperatureSensor {
+    fn read_environment(&mut self) -> Result<EnvironmentalReading, Self::Error> {
+        let temperature = self.read_temperature()?;
+        Ok(EnvironmentalReading {
+            temperature,
+            humidity: self.humidity.map(Humidity::new),
+            pressure: self.pressure.map(Pressure::new),
+        })
+    }
+}
+
+/// Actuator that records every output level it is driven with.
+#[derive(Debug, Default)]
+pub struct MockActuator {
+    level: f32,
+    history: Vec<f32>,
+    fail_next: bool,
+}
+
+impl MockActuator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn history(&self) -> &[f32] {
+        &self.history
+    }
+
+    pub fn fail_next_write(&mut self) {
+        self.fail_next = true;
+    }
+}
+
+impl Actuator for MockActuator {
+    type Error = MockError;
 
+    fn set_output(&mut self, level: f32) -> Result<(), Self::Error> {
         if self.fail_next {
             self.fail_next = false;
             return Err(MockError::ReadFailed);
         }
 
-        Ok(Temperature::new(self.temperature))
+        self.level = level;
+        self.history.push(level);
+        Ok(())
     }
 
-    fn sensor_id(&self) -> &str {
-        &self.id
+    fn output(&self) -> f32 {
+        self.level
     }
 }
 
@@ -86,6 +522,18 @@ mod tests {
         assert_eq!(sensor.sensor_id(), "test-sensor");
     }
 
+    #[test]
+    fn mock_sensor_reports_the_environment() {
+        let mut sensor = MockTemperatureSensor::new("bme280".to_string(), 21.0).with_humidity(40.0).with_pressure(1013.0);
+        let reading = sensor.read_environment().unwrap();
+        assert_eq!(reading.temperature.celsius, 21.0);
+        assert_eq!(reading.humidity, Some(Humidity::new(40.0)));
+        assert_eq!(reading.pressure, Some(Pressure::new(1013.0)));
+
+        let mut plain = MockTemperatureSensor::new("ds18b20".to_string(), 21.0);
+        assert_eq!(plain.read_environment().unwrap(), EnvironmentalReading::new(Temperature::new(21.0)));
+    }
+
     #[test]
     fn mock_sensor_can_fail() {
         let mut sensor = MockTemperatureSensor::new("test-sensor".to_string(), 25.0);
@@ -112,6 +560,62 @@ mod tests {
         assert_eq!(reading.celsius, 25.0);
     }
 
+    #[test]
+    fn mock_sensor_self_test_tells_dead_from_quiet() {
+        let mut sensor = MockTemperatureSensor::new("test-sensor".to_string(), 25.0).with_failure_rate(1.0);
+        assert_eq!(sensor.self_test().unwrap(), SensorHealth::Ok);
+        assert_eq!(sensor.transactions(), 0);
+
+        sensor.set_health(SensorHealth::Degraded);
+        assert_eq!(sensor.self_test().unwrap(), SensorHealth::Degraded);
+        sensor.set_offline(true);
+        assert!(matches!(sensor.self_test(), Err(MockError::SensorOffline)));
+    }
+
+    #[test]
+    fn mock_sensor_follows_its_failure_schedule() {
+        let clock = crate::clock::ManualClock::new(1_000);
+        let schedule = FailureSchedule::new()
+            .fail_every(3)
+            .garbage_reads(4..7, 85.0)
+            .offline_between(2_000..2_060);
+        let mut sensor = MockTemperatureSensor::new("test-sensor".to_string(), 25.0)
+            .with_failure_schedule(schedule)
+            .with_clock(clock.clone());
+
+        let results: Vec<_> = (0..6).map(|_| sensor.read_temperature().map(|t| t.celsius)).collect();
+        assert!(matches!(
+            results[..],
+            [Ok(25.0), Ok(25.0), Err(MockError::ReadFailed), Ok(85.0), Ok(85.0), Err(MockError::ReadFailed)]
+        ));
+
+        // Read 6 would be garbage too, but failing wins; read 7 is clean
+        assert_eq!(sensor.read_temperature().unwrap().celsius, 25.0);
+
+        clock.set(2_000);
+        assert!(matches!(sensor.read_temperature(), Err(MockError::SensorOffline)));
+        assert!(matches!(sensor.self_test(), Err(MockError::SensorOffline)));
+        clock.advance(60);
+        assert_eq!(sensor.self_test().unwrap(), SensorHealth::Ok);
+        assert_eq!(sensor.transactions(), 8);
+    }
+
+    #[test]
+    fn mock_sensor_drains_its_fifo_in_one_read() {
+        let mut sensor = MockTemperatureSensor::new("test-sensor".to_string(), 25.0);
+        let mut batch = [Temperature::new(0.0); 4];
+        assert_eq!(sensor.read_temperatures(&mut batch).unwrap(), 1);
+        assert_eq!(sensor.read_temperatures(&mut []).unwrap(), 0);
+
+        sensor.set_fifo_depth(3);
+        assert_eq!(sensor.read_temperatures(&mut batch).unwrap(), 3);
+        assert_eq!(&batch[..3], &[Temperature::new(25.0); 3]);
+        assert_eq!(sensor.transactions(), 2);
+
+        sensor.fail_next_read();
+        assert!(matches!(sensor.read_temperatures(&mut batch), Err(MockError::ReadFailed)));
+    }
+
     #[test]
     fn mock_sensor_temperature_can_change() {
         let mut sensor = MockTemperatureSensor::new("test-sensor".to_string(), 25.0);
@@ -123,4 +627,79 @@ mod tests {
         let reading2 = sensor.read_temperature().unwrap();
         assert_eq!(reading2.celsius, 30.0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn seeded_noise_is_reproducible() {
+        let seed = test_seed();
+        let read_all = |seed| {
+            let mut sensor = MockTemperatureSensor::new("noisy".to_string(), 20.0)
+                .with_noise(0.5, seed)
+                .with_failure_rate(0.2);
+            (0..50).map(|_| sensor.read_temperature().ok().map(|t| t.celsius)).collect::<Vec<_>>()
+        };
+
+        let replay = format!("{}={}", TEST_SEED_VAR, seed);
+        let first = read_all(seed);
+        assert_eq!(first, read_all(seed), "{}", replay);
+        assert!(first.iter().flatten().all(|c| (19.5..=20.5).contains(c)), "{}", replay);
+        assert!(first.iter().any(|r| r.is_none()), "{}", replay);
+        assert!(first.iter().flatten().any(|c| *c != 20.0), "{}", replay);
+    }
+
+    #[test]
+    fn noise_profiles() {
+        use crate::clock::ManualClock;
+
+        let seed = test_seed();
+        let replay = format!("{}={}", TEST_SEED_VAR, seed);
+        let read = |sensor: &mut MockTemperatureSensor, n: usize| {
+            (0..n).map(|_| sensor.read_temperature().unwrap().celsius).collect::<Vec<_>>()
+        };
+
+        let gaussian = NoiseProfile { gaussian: 0.5, ..Default::default() };
+        let mut sensor = MockTemperatureSensor::new("gauss".to_string(), 20.0).with_profile(gaussian, seed);
+        let samples = read(&mut sensor, 2000);
+        let mean = samples.iter().sum::<f32>() / 2000.0;
+        let sd = (samples.iter().map(|c| (c - mean).powi(2)).sum::<f32>() / 2000.0).sqrt();
+        assert!((mean - 20.0).abs() < 0.1, "mean {} with {}", mean, replay);
+        assert!((sd - 0.5).abs() < 0.1, "sd {} with {}", sd, replay);
+
+        // The drift is whatever the walk added up to
+        let drift = NoiseProfile { drift: 0.1, ..Default::default() };
+        let mut sensor = MockTemperatureSensor::new("walk".to_string(), 20.0).with_profile(drift, seed);
+        let last = read(&mut sensor, 500).pop().unwrap();
+        assert!((last - 20.0 - sensor.noise_mut().drift()).abs() < 1e-3);
+        sensor.noise_mut().reset_drift();
+
+        let clock = ManualClock::new(0);
+        let daily = NoiseProfile { daily_amplitude: 3.0, ..Default::default() };
+        let mut sensor = MockTemperatureSensor::new("day".to_string(), 20.0).with_profile(daily, seed).with_clock(clock.clone());
+        assert!((read(&mut sensor, 1)[0] - 17.0).abs() < 1e-3);
+        clock.advance(DAY_SECONDS / 4);
+        assert!((read(&mut sensor, 1)[0] - 20.0).abs() < 1e-3);
+        clock.advance(DAY_SECONDS / 4);
+        assert!((read(&mut sensor, 1)[0] - 23.0).abs() < 1e-3);
+
+        let spikes = NoiseProfile { spike_probability: 0.1, spike_magnitude: 15.0, ..Default::default() };
+        let mut sensor = MockTemperatureSensor::new("spiky".to_string(), 20.0).with_profile(spikes, seed);
+        let samples = read(&mut sensor, 500);
+        assert!(samples.iter().all(|c| [5.0, 20.0, 35.0].contains(c)));
+        let spiked = samples.iter().filter(|c| **c != 20.0).count();
+        assert!((20..=80).contains(&spiked), "{} spikes with {}", spiked, replay);
+        let mut again = MockTemperatureSensor::new("spiky".to_string(), 20.0).with_profile(spikes, seed);
+        assert_eq!(read(&mut again, 500), samples);
+    }
+
+    #[test]
+    fn mock_actuator_records_levels() {
+        let mut actuator = MockActuator::new();
+
+        actuator.set_output(1.0).unwrap();
+        actuator.fail_next_write();
+        assert!(actuator.set_output(0.5).is_err());
+        actuator.set_output(0.0).unwrap();
+
+        assert_eq!(actuator.history(), &[1.0, 0.0]);
+        assert_eq!(actuator.output(), 0.0);
+    }
+}
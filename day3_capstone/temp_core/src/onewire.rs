@@ -0,0 +1,684 @@
+//! DS18B20 sensors on a 1-Wire bus, with the `onewire` feature.
+//!
+//! Any number of DS18B20s can share one data line. [`RomSearch`] finds them
+//! by their 64-bit ROM codes, and each one becomes its own [`Ds18b20`]
+//! implementing [`TemperatureSensor`], addressed by ROM code over the shared
+//! bus. The sensors borrow the bus through a `RefCell`, so no allocator is
+//! needed:
+//!
+//! ```ignore
+//! let bus = RefCell::new(BitBang::new(pin, delay));
+//! let mut roms = [Rom::default(); 4];
+//! let found = Ds18b20::discover(&mut bus.borrow_mut(), &mut roms)?;
+//! let mut sensors = roms[..found].iter().map(|&rom| Ds18b20::new(&bus, rom));
+//! ```
+//!
+//! The bus itself is a [`OneWireBus`]: [`BitBang`] drives an open-drain
+//! GPIO with a 4.7 kΩ pull-up through embedded-hal, and a UART or DS2482
+//! bridge can implement the trait instead. Bit-banged timing is in
+//! microseconds, so keep interrupts short while the bus is in use.
+
+use core::cell::RefCell;
+use core::fmt;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{InputPin, OutputPin};
+
+use crate::info::{MeasurementRange, SensorInfo};
+use crate::{SensorHealth, Temperature, TemperatureSensor};
+
+/// Reset and bit time slots of a 1-Wire bus master.
+pub trait OneWireBus {
+    type Error: fmt::Debug;
+
+    /// Resets the bus; true if any device answered with a presence pulse.
+    fn reset(&mut self) -> Result<bool, Self::Error>;
+    fn write_bit(&mut self, bit: bool) -> Result<(), Self::Error>;
+    fn read_bit(&mut self) -> Result<bool, Self::Error>;
+
+    /// Least significant bit first, as 1-Wire sends bytes.
+    fn write_byte(&mut self, byte: u8) -> Result<(), Self::Error> {
+        (0..8).try_for_each(|bit| self.write_bit(byte >> bit & 1 != 0))
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        (0..8).try_fold(0, |byte, bit| Ok(byte | (u8::from(self.read_bit()?) << bit)))
+    }
+}
+
+/// A 1-Wire master bit-banged on an open-drain pin, at standard speed.
+pub struct BitBang<P, D> {
+    pin: P,
+    delay: D,
+}
+
+impl<P: InputPin + OutputPin, D: DelayNs> BitBang<P, D> {
+    pub fn new(pin: P, delay: D) -> Self {
+        Self { pin, delay }
+    }
+
+    pub fn release(self) -> (P, D) {
+        (self.pin, self.delay)
+    }
+}
+
+impl<P: InputPin + OutputPin, D: DelayNs> OneWireBus for BitBang<P, D> {
+    type Error = P::Error;
+
+    fn reset(&mut self) -> Result<bool, Self::Error> {
+        self.pin.set_low()?;
+        self.delay.delay_us(480);
+        self.pin.set_high()?;
+        self.delay.delay_us(70);
+        let present = self.pin.is_low()?;
+        self.delay.delay_us(410);
+        Ok(present)
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), Self::Error> {
+        self.pin.set_low()?;
+        self.delay.delay_us(if bit { 6 } else { 60 });
+        self.pin.set_high()?;
+        self.delay.delay_us(if bit { 64 } else { 10 });
+        Ok(())
+    }
+
+    fn read_bit(&mut self) -> Result<bool, Self::Error> {
+        self.pin.set_low()?;
+        self.delay.delay_us(6);
+        self.pin.set_high()?;
+        self.delay.delay_us(9);
+        let bit = self.pin.is_high()?;
+        self.delay.delay_us(55);
+        Ok(bit)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OneWireError<E> {
+    Bus(E),
+    /// No device answered the reset.
+    NoPresence,
+    /// A ROM code or scratchpad did not match its CRC.
+    Crc,
+    /// The conversion did not finish in time.
+    NotReady,
+    /// Another sensor on the bus is using it.
+    BusBusy,
+}
+
+impl<E: fmt::Debug> fmt::Display for OneWireError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OneWireError::Bus(e) => write!(f, "1-Wire bus error: {:?}", e),
+            OneWireError::NoPresence => write!(f, "No device on the 1-Wire bus"),
+            OneWireError::Crc => write!(f, "1-Wire data failed its CRC"),
+            OneWireError::NotReady => write!(f, "Sensor conversion did not finish in time"),
+            OneWireError::BusBusy => write!(f, "1-Wire bus is in use"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for OneWireError<E> {}
+
+impl<E> From<E> for OneWireError<E> {
+    fn from(e: E) -> Self {
+        OneWireError::Bus(e)
+    }
+}
+
+const SEARCH_ROM: u8 = 0xF0;
+const MATCH_ROM: u8 = 0x55;
+const CONVERT_T: u8 = 0x44;
+const WRITE_SCRATCHPAD: u8 = 0x4E;
+const READ_SCRATCHPAD: u8 = 0xBE;
+
+/// Dallas/Maxim CRC-8 (reflected polynomial 0x8C) used by every 1-Wire device.
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8)
+            .fold((crc, byte), |(crc, byte), _| {
+                let mix = (crc ^ byte) & 1 != 0;
+                (if mix { (crc >> 1) ^ 0x8C } else { crc >> 1 }, byte >> 1)
+            })
+            .0
+    })
+}
+
+/// A device's 64-bit ROM code: family code, 48-bit serial, CRC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Rom(pub [u8; 8]);
+
+impl Rom {
+    pub fn family_code(&self) -> u8 {
+        self.0[0]
+    }
+
+    pub fn is_valid(&self) -> bool {
+        crc8(&self.0[..7]) == self.0[7]
+    }
+}
+
+/// Like the Linux w1 driver names devices: family, then the serial as a number.
+impl fmt::Display for Rom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}-", self.family_code())?;
+        self.0[1..7].iter().rev().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+/// Enumerates the devices on a bus, one ROM code per [`next`](Self::next).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RomSearch {
+    rom: [u8; 8],
+    /// Bit (1-based) where the last pass took the 0 branch of a conflict; 0 for none.
+    last_discrepancy: u8,
+    done: bool,
+}
+
+impl RomSearch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The next device, `None` once all were found.
+    pub fn next<B: OneWireBus>(&mut self, bus: &mut B) -> Result<Option<Rom>, OneWireError<B::Error>> {
+        if self.done {
+            return Ok(None);
+        }
+        if !bus.reset()? {
+            self.done = true;
+            return Ok(None);
+        }
+        bus.write_byte(SEARCH_ROM)?;
+
+        let mut last_zero = 0;
+        for bit_number in 1..=64u8 {
+            let (byte, mask) = (usize::from((bit_number - 1) / 8), 1 << ((bit_number - 1) % 8));
+            let id_bit = bus.read_bit()?;
+            let complement = bus.read_bit()?;
+            let direction = match (id_bit, complement) {
+                // Every remaining device left during the search
+                (true, true) => {
+                    self.done = true;
+                    return Ok(None);
+                }
+                (true, false) => true,
+                (false, true) => false,
+                // Devices disagree: retrace earlier passes, then take the other branch
+                (false, false) => {
+                    let direction = if bit_number < self.last_discrepancy {
+                        self.rom[byte] & mask != 0
+                    } else {
+                        bit_number == self.last_discrepancy
+                    };
+                    if !direction {
+                        last_zero = bit_number;
+                    }
+                    direction
+                }
+            };
+            if direction {
+                self.rom[byte] |= mask;
+            } else {
+                self.rom[byte] &= !mask;
+            }
+            bus.write_bit(direction)?;
+        }
+
+        self.last_discrepancy = last_zero;
+        self.done = last_zero == 0;
+        let rom = Rom(self.rom);
+        if !rom.is_valid() {
+            return Err(OneWireError::Crc);
+        }
+        Ok(Some(rom))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Resolution {
+    Bits9,
+    Bits10,
+    Bits11,
+    /// 0.0625 °C; the power-on default.
+    #[default]
+    Bits12,
+}
+
+impl Resolution {
+    fn config(self) -> u8 {
+        ((self as u8) << 5) | 0x1F
+    }
+
+    fn from_config(config: u8) -> Self {
+        match (config >> 5) & 0b11 {
+            0 => Resolution::Bits9,
+            1 => Resolution::Bits10,
+            2 => Resolution::Bits11,
+            _ => Resolution::Bits12,
+        }
+    }
+
+    /// Maximum conversion time from the datasheet.
+    pub fn conversion_ms(self) -> u32 {
+        750 >> (3 - self as u32)
+    }
+
+    /// Value of the lowest bit: 0.5 °C at 9 bits down to 0.0625 °C at 12.
+    pub fn celsius(self) -> f32 {
+        0.5 / f32::from(1u8 << self as u8)
+    }
+}
+
+pub struct Ds18b20<'bus, B> {
+    bus: &'bus RefCell<B>,
+    rom: Rom,
+    resolution: Resolution,
+    /// The ROM code as text, e.g. `28-0316831e64ff`.
+    id: [u8; 15],
+}
+
+impl<'bus, B: OneWireBus> Ds18b20<'bus, B> {
+    pub const FAMILY_CODE: u8 = 0x28;
+
+    /// A read slot takes about 70 µs; polling for the conversion to finish
+    /// gives up after twice the datasheet maximum.
+    const SLOTS_PER_MS: u32 = 1000 / 70;
+    /// 85 °C, little endian.
+    const POWER_ON_TEMPERATURE: [u8; 2] = [0x50, 0x05];
+
+    /// Fills `roms` with the DS18B20s on the bus, returning how many were found.
+    pub fn discover(bus: &mut B, roms: &mut [Rom]) -> Result<usize, OneWireError<B::Error>> {
+        let mut search = RomSearch::new();
+        let mut found = 0;
+        while found < roms.len() {
+            match search.next(bus)? {
+                Some(rom) if rom.family_code() == Self::FAMILY_CODE => {
+                    roms[found] = rom;
+                    found += 1;
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        Ok(found)
+    }
+
+    /// Assumes the power-on resolution of 12 bits until
+    /// [`set_resolution`](Self::set_resolution) is called.
+    pub fn new(bus: &'bus RefCell<B>, rom: Rom) -> Self {
+        let mut id = *b"28-000000000000";
+        let mut text = IdWriter { buf: &mut id, len: 0 };
+        let _ = fmt::write(&mut text, format_args!("{}", rom));
+        Self { bus, rom, resolution: Resolution::default(), id }
+    }
+
+    pub fn rom(&self) -> Rom {
+        self.rom
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Lower resolutions convert faster: 94 ms at 9 bits against 750 ms at 12.
+    pub fn set_resolution(&mut self, resolution: Resolution) -> Result<(), OneWireError<B::Error>> {
+        let mut bus = self.bus.try_borrow_mut().map_err(|_| OneWireError::BusBusy)?;
+        select(&mut *bus, self.rom)?;
+        bus.write_byte(WRITE_SCRATCHPAD)?;
+        // Alarm thresholds at their power-on defaults of 75 °C and 70 °C
+        for byte in [75, 70, resolution.config()] {
+            bus.write_byte(byte)?;
+        }
+        self.resolution = resolution;
+        Ok(())
+    }
+
+    /// Converts and reads back the temperature.
+    pub fn read(&mut self) -> Result<Temperature, OneWireError<B::Error>> {
+        let scratchpad = self.convert()?;
+        // Bits below the resolution are undefined
+        let resolution = Resolution::from_config(scratchpad[4]);
+        let undefined = 3 - resolution as u8;
+        let raw = i16::from_le_bytes([scratchpad[0], scratchpad[1]]) >> undefined << undefined;
+        Ok(Temperature::new(f32::from(raw) * 0.0625))
+    }
+
+    /// Failed if the temperature register still holds its power-on value
+    /// of 85 °C, which is what a sensor without the power to convert keeps
+    /// returning, e.g. on parasite power without a strong pull-up. Degraded
+    /// if the sensor lost its resolution to a reset.
+    pub fn self_test(&mut self) -> Result<SensorHealth, OneWireError<B::Error>> {
+        let scratchpad = self.convert()?;
+        if scratchpad[..2] == Self::POWER_ON_TEMPERATURE {
+            return Ok(SensorHealth::Failed);
+        }
+        if Resolution::from_config(scratchpad[4]) != self.resolution {
+            return Ok(SensorHealth::Degraded);
+        }
+        Ok(SensorHealth::Ok)
+    }
+
+    /// Starts a conversion, waits for it and returns the scratchpad.
+    fn convert(&mut self) -> Result<[u8; 9], OneWireError<B::Error>> {
+        let mut bus = self.bus.try_borrow_mut().map_err(|_| OneWireError::BusBusy)?;
+        select(&mut *bus, self.rom)?;
+        bus.write_byte(CONVERT_T)?;
+        // The sensor reads 0 while converting
+        let max_slots = 2 * self.resolution.conversion_ms() * Self::SLOTS_PER_MS;
+        let mut slots = 0;
+        while !bus.read_bit()? {
+            slots += 1;
+            if slots > max_slots {
+                return Err(OneWireError::NotReady);
+            }
+        }
+
+        select(&mut *bus, self.rom)?;
+        bus.write_byte(READ_SCRATCHPAD)?;
+        let mut scratchpad = [0; 9];
+        for byte in &mut scratchpad {
+            *byte = bus.read_byte()?;
+        }
+        if crc8(&scratchpad[..8]) != scratchpad[8] {
+            return Err(OneWireError::Crc);
+        }
+        Ok(scratchpad)
+    }
+}
+
+fn select<B: OneWireBus>(bus: &mut B, rom: Rom) -> Result<(), OneWireError<B::Error>> {
+    if !bus.reset()? {
+        return Err(OneWireError::NoPresence);
+    }
+    bus.write_byte(MATCH_ROM)?;
+    rom.0.iter().try_for_each(|&byte| bus.write_byte(byte))?;
+    Ok(())
+}
+
+/// Formats into a fixed buffer, for sensor ids without an allocator.
+struct IdWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl fmt::Write for IdWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.buf.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+impl<B: OneWireBus> TemperatureSensor for Ds18b20<'_, B> {
+    type Error = OneWireError<B::Error>;
+
+    fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+        self.read()
+    }
+
+    fn sensor_id(&self) -> &str {
+        core::str::from_utf8(&self.id).unwrap_or("ds18b20")
+    }
+
+    fn self_test(&mut self) -> Result<SensorHealth, Self::Error> {
+        Ds18b20::self_test(self)
+    }
+}
+
+impl<B: OneWireBus> SensorInfo for Ds18b20<'_, B> {
+    /// Between -10 and 85 °C.
+    fn accuracy(&self) -> f32 {
+        0.5
+    }
+
+    fn resolution(&self) -> f32 {
+        self.resolution.celsius()
+    }
+
+    fn range(&self) -> MeasurementRange {
+        MeasurementRange::new(-55.0, 125.0)
+    }
+
+    fn manufacturer(&self) -> &str {
+        "Maxim Integrated"
+    }
+
+    fn model(&self) -> &str {
+        "DS18B20"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    extern crate std;
+    use std::vec::Vec;
+
+    fn rom(family: u8, serial: u8) -> Rom {
+        let mut code = [family, serial, 0x64, 0x1E, 0x83, 0x16, 0x03, 0];
+        code[7] = crc8(&code[..7]);
+        Rom(code)
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        RomCommand,
+        Search { bit: usize, phase: u8 },
+        MatchRom,
+        Function,
+        Converting,
+        Scratchpad,
+        Writing,
+        Idle,
+    }
+
+    struct Device {
+        rom: Rom,
+        scratchpad: [u8; 9],
+        selected: bool,
+    }
+
+    /// Simulates DS18B20s (and other devices) at the level of time slots.
+    struct SimulatedBus {
+        devices: Vec<Device>,
+        state: State,
+        bits: Vec<bool>,
+        received: Vec<u8>,
+        output: Vec<bool>,
+        conversion_slots: u32,
+        busy: u32,
+    }
+
+    impl SimulatedBus {
+        fn new(devices: &[(Rom, i16)]) -> Self {
+            let devices = devices
+                .iter()
+                .map(|&(rom, raw)| {
+                    let [lsb, msb] = raw.to_le_bytes();
+                    let mut scratchpad = [lsb, msb, 75, 70, 0x7F, 0xFF, 0x0C, 0x10, 0];
+                    scratchpad[8] = crc8(&scratchpad[..8]);
+                    Device { rom, scratchpad, selected: true }
+                })
+                .collect();
+            Self {
+                devices,
+                state: State::Idle,
+                bits: Vec::new(),
+                received: Vec::new(),
+                output: Vec::new(),
+                conversion_slots: 5,
+                busy: 0,
+            }
+        }
+
+        fn rom_bit(rom: Rom, bit: usize) -> bool {
+            rom.0[bit / 8] >> (bit % 8) & 1 != 0
+        }
+
+        fn selected(&mut self) -> impl Iterator<Item = &mut Device> {
+            self.devices.iter_mut().filter(|d| d.selected)
+        }
+
+        fn on_byte(&mut self, byte: u8) {
+            match self.state {
+                State::RomCommand => match byte {
+                    SEARCH_ROM => self.state = State::Search { bit: 0, phase: 0 },
+                    MATCH_ROM => self.state = State::MatchRom,
+                    _ => self.state = State::Idle,
+                },
+                State::MatchRom => {
+                    self.received.push(byte);
+                    if self.received.len() == 8 {
+                        let code: [u8; 8] = self.received.drain(..).collect::<Vec<_>>().try_into().unwrap();
+                        self.devices.iter_mut().for_each(|d| d.selected = d.rom.0 == code);
+                        self.state = State::Function;
+                    }
+                }
+                State::Function => match byte {
+                    CONVERT_T => {
+                        self.busy = self.conversion_slots;
+                        self.state = State::Converting;
+                    }
+                    READ_SCRATCHPAD => {
+                        let scratchpad = self.selected().next().map(|d| d.scratchpad).unwrap_or([0xFF; 9]);
+                        self.output = scratchpad.iter().flat_map(|b| (0..8).map(move |i| b >> i & 1 != 0)).rev().collect();
+                        self.state = State::Scratchpad;
+                    }
+                    WRITE_SCRATCHPAD => self.state = State::Writing,
+                    _ => self.state = State::Idle,
+                },
+                State::Writing => {
+                    self.received.push(byte);
+                    if self.received.len() == 3 {
+                        let [th, tl, config] = [self.received[0], self.received[1], self.received[2]];
+                        self.received.clear();
+                        for device in self.selected() {
+                            device.scratchpad[2..5].copy_from_slice(&[th, tl, config]);
+                            device.scratchpad[8] = crc8(&device.scratchpad[..8]);
+                        }
+                        self.state = State::Idle;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    impl OneWireBus for SimulatedBus {
+        type Error = core::convert::Infallible;
+
+        fn reset(&mut self) -> Result<bool, Self::Error> {
+            self.devices.iter_mut().for_each(|d| d.selected = true);
+            self.state = State::RomCommand;
+            self.bits.clear();
+            self.received.clear();
+            Ok(!self.devices.is_empty())
+        }
+
+        fn write_bit(&mut self, bit: bool) -> Result<(), Self::Error> {
+            if let State::Search { bit: index, .. } = self.state {
+                self.devices.iter_mut().filter(|d| Self::rom_bit(d.rom, index) != bit).for_each(|d| d.selected = false);
+                self.state = State::Search { bit: index + 1, phase: 0 };
+                return Ok(());
+            }
+            self.bits.push(bit);
+            if self.bits.len() == 8 {
+                let byte = self.bits.drain(..).enumerate().fold(0, |byte, (i, bit)| byte | (u8::from(bit) << i));
+                self.on_byte(byte);
+            }
+            Ok(())
+        }
+
+        fn read_bit(&mut self) -> Result<bool, Self::Error> {
+            Ok(match self.state {
+                // Open drain: the line is high unless some device pulls it low
+                State::Search { bit, phase } => {
+                    self.state = State::Search { bit, phase: phase + 1 };
+                    let complement = phase == 1;
+                    self.selected().all(|d| Self::rom_bit(d.rom, bit) != complement)
+                }
+                State::Converting if self.busy > 0 => {
+                    self.busy -= 1;
+                    false
+                }
+                State::Scratchpad => self.output.pop().unwrap_or(true),
+                _ => true,
+            })
+        }
+    }
+
+    #[test]
+    fn crc_matches_the_reference() {
+        assert_eq!(crc8(b"123456789"), 0xA1);
+        assert!(rom(0x28, 1).is_valid());
+        assert_eq!(std::format!("{}", rom(0x28, 0xFF)), "28-0316831e64ff");
+    }
+
+    #[test]
+    fn finds_every_device_on_the_bus() {
+        let roms = [rom(0x28, 0x01), rom(0x28, 0x81), rom(0x10, 0x01), rom(0x28, 0x40)];
+        let mut bus = SimulatedBus::new(&roms.map(|rom| (rom, 0)));
+        let mut search = RomSearch::new();
+        let mut found = Vec::new();
+        while let Some(rom) = search.next(&mut bus).unwrap() {
+            found.push(rom);
+        }
+        found.sort_by_key(|rom| rom.0);
+        let mut expected = roms.to_vec();
+        expected.sort_by_key(|rom| rom.0);
+        assert_eq!(found, expected);
+
+        // Only DS18B20s, as many as fit
+        let mut discovered = [Rom::default(); 2];
+        assert_eq!(Ds18b20::discover(&mut bus, &mut discovered).unwrap(), 2);
+        assert!(discovered.iter().all(|rom| rom.family_code() == 0x28));
+
+        let mut empty = SimulatedBus::new(&[]);
+        assert_eq!(RomSearch::new().next(&mut empty).unwrap(), None);
+    }
+
+    #[test]
+    fn each_device_is_its_own_sensor() {
+        // 25.0625 °C and -10.125 °C in sixteenths
+        let bus = RefCell::new(SimulatedBus::new(&[(rom(0x28, 1), 401), (rom(0x28, 2), -162)]));
+        let mut first = Ds18b20::new(&bus, rom(0x28, 1));
+        let mut second = Ds18b20::new(&bus, rom(0x28, 2));
+        assert_eq!(first.read_temperature().unwrap(), Temperature::new(25.0625));
+        assert_eq!(second.read_temperature().unwrap(), Temperature::new(-10.125));
+        assert_eq!(first.sensor_id(), "28-0316831e6401");
+
+        // At 9 bits the lowest three bits are dropped
+        second.set_resolution(Resolution::Bits9).unwrap();
+        assert_eq!(second.read_temperature().unwrap(), Temperature::new(-10.5));
+        assert_eq!(SensorInfo::resolution(&second), 0.5);
+        assert!(!second.is_significant_change(0.5) && first.is_significant_change(0.5));
+        assert_eq!(first.read_temperature().unwrap(), Temperature::new(25.0625));
+
+        bus.borrow_mut().conversion_slots = 1_000_000;
+        assert_eq!(second.read_temperature(), Err(OneWireError::NotReady));
+        assert_eq!(Resolution::Bits9.conversion_ms(), 93);
+
+        let _guard = bus.borrow_mut();
+        assert_eq!(first.read_temperature(), Err(OneWireError::BusBusy));
+    }
+
+    #[test]
+    fn self_test_spots_conversions_that_never_ran() {
+        // 85 °C is 1360 sixteenths
+        let bus = RefCell::new(SimulatedBus::new(&[(rom(0x28, 1), 401), (rom(0x28, 2), 1360)]));
+        let mut healthy = Ds18b20::new(&bus, rom(0x28, 1));
+        assert_eq!(healthy.self_test(), Ok(SensorHealth::Ok));
+        assert_eq!(Ds18b20::new(&bus, rom(0x28, 2)).self_test(), Ok(SensorHealth::Failed));
+
+        // The sensor's resolution no longer is the one the driver set
+        healthy.set_resolution(Resolution::Bits9).unwrap();
+        assert_eq!(TemperatureSensor::self_test(&mut healthy), Ok(SensorHealth::Ok));
+        assert_eq!(Ds18b20::new(&bus, rom(0x28, 1)).self_test(), Ok(SensorHealth::Degraded));
+
+        let missing = RefCell::new(SimulatedBus::new(&[]));
+        assert_eq!(Ds18b20::new(&missing, rom(0x28, 1)).self_test(), Err(OneWireError::NoPresence));
+    }
+}
@@ -0,0 +1,63 @@
+//! Double-precision temperatures for aggregates.
+//!
+//! A single reading fits an `f32` with room to spare, but an average over
+//! millions of them carries digits an `f32` rounds away. [`PreciseTemperature`]
+//! holds degrees Celsius in an `f64` for such results; convert to
+//! [`Temperature`] where a reading is expected.
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Temperature;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct PreciseTemperature {
+    pub celsius: f64,
+}
+
+impl PreciseTemperature {
+    pub const fn new(celsius: f64) -> Self {
+        Self { celsius }
+    }
+
+    /// Rounded to the nearest `f32`.
+    pub const fn to_temperature(&self) -> Temperature {
+        Temperature::new(self.celsius as f32)
+    }
+
+    pub const fn to_fahrenheit(&self) -> f64 {
+        self.celsius * 9.0 / 5.0 + 32.0
+    }
+
+    pub const fn to_kelvin(&self) -> f64 {
+        self.celsius - Temperature::ABSOLUTE_ZERO_CELSIUS as f64
+    }
+}
+
+impl From<Temperature> for PreciseTemperature {
+    fn from(temperature: Temperature) -> Self {
+        Self::new(temperature.celsius as f64)
+    }
+}
+
+impl fmt::Display for PreciseTemperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.3}°C", self.celsius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    extern crate std;
+
+    #[test]
+    fn keeps_digits_an_f32_loses() {
+        let precise = PreciseTemperature::new(20.000_000_1);
+        assert_eq!(precise.to_temperature().celsius, 20.0);
+        assert!(precise.celsius > 20.0);
+        assert_eq!(PreciseTemperature::from(Temperature::new(21.5)).celsius, 21.5);
+        assert_eq!(std::format!("{}", PreciseTemperature::new(21.25)), "21.250°C");
+    }
+}
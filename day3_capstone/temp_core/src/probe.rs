@@ -0,0 +1,246 @@
+//! Conversions for probes that are not linear: NTC thermistors and type K
+//! and J thermocouples.
+//!
+//! [`AdcConfig`](crate::adc::AdcConfig) covers sensors with a fixed slope.
+//! A thermistor's resistance falls exponentially with temperature, and is
+//! described by its beta value, Steinhart-Hart coefficients or a datasheet
+//! table. A thermocouple puts out a few millivolts that depend on the
+//! difference to its cold junction; types K and J are tabulated here from
+//! the NIST reference tables. Tables are `const` slices, so they live in
+//! flash, and table lookups are `const fn`, so fixed conversions can be
+//! worked out at compile time.
+
+use crate::Temperature;
+
+const ZERO_CELSIUS_IN_KELVIN: f32 = 273.15;
+
+/// Points `(x, y)` of a monotonic curve, ordered by `x` in either direction,
+/// interpolated linearly in between. Nothing is extrapolated: values outside
+/// the table give `None`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LookupTable {
+    points: &'static [(f32, f32)],
+}
+
+impl LookupTable {
+    pub const fn new(points: &'static [(f32, f32)]) -> Self {
+        Self { points }
+    }
+
+    /// `y` at `x`.
+    pub const fn lookup(&self, x: f32) -> Option<f32> {
+        interpolate(self.points, x, false)
+    }
+
+    /// `x` at `y`.
+    pub const fn reverse(&self, y: f32) -> Option<f32> {
+        interpolate(self.points, y, true)
+    }
+}
+
+/// Interpolates `y` at `x = at`, or `x` at `y = at` if `reverse`.
+const fn interpolate(points: &[(f32, f32)], at: f32, reverse: bool) -> Option<f32> {
+    let mut index = 1;
+    while index < points.len() {
+        let (mut previous, mut next) = (points[index - 1], points[index]);
+        if reverse {
+            previous = (previous.1, previous.0);
+            next = (next.1, next.0);
+        }
+        let (low, high) = if previous.0 <= next.0 { (previous.0, next.0) } else { (next.0, previous.0) };
+        if low <= at && at <= high {
+            if next.0 == previous.0 {
+                return Some(previous.1);
+            }
+            let fraction = (at - previous.0) / (next.0 - previous.0);
+            return Some(previous.1 + fraction * (next.1 - previous.1));
+        }
+        index += 1;
+    }
+    None
+}
+
+/// An NTC thermistor's resistance curve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ntc {
+    /// `resistance` ohms at `celsius`, with the datasheet's beta value.
+    Beta { beta: f32, resistance: f32, celsius: f32 },
+    /// `1/T = a + b ln(R) + c ln(R)^3`, with T in kelvin and R in ohms.
+    SteinhartHart { a: f32, b: f32, c: f32 },
+    /// Datasheet points `(ohms, °C)`.
+    Table(LookupTable),
+}
+
+/// The common 10 kΩ thermistor with B25/85 = 3950.
+pub const NTC_10K_3950: Ntc = Ntc::Beta { beta: 3950.0, resistance: 10_000.0, celsius: 25.0 };
+
+impl Ntc {
+    /// The temperature at `ohms`; `None` outside a table or for a resistance
+    /// that is not positive.
+    pub fn to_celsius(&self, ohms: f32) -> Option<f32> {
+        if ohms.is_nan() || ohms <= 0.0 {
+            return None;
+        }
+        match *self {
+            Ntc::Beta { beta, resistance, celsius } => {
+                let inverse = 1.0 / (celsius + ZERO_CELSIUS_IN_KELVIN) + libm::logf(ohms / resistance) / beta;
+                Some(1.0 / inverse - ZERO_CELSIUS_IN_KELVIN)
+            }
+            Ntc::SteinhartHart { a, b, c } => {
+                let ln = libm::logf(ohms);
+                Some(1.0 / (a + b * ln + c * ln * ln * ln) - ZERO_CELSIUS_IN_KELVIN)
+            }
+            Ntc::Table(table) => table.lookup(ohms),
+        }
+    }
+
+    pub fn temperature(&self, ohms: f32) -> Option<Temperature> {
+        self.to_celsius(ohms).map(Temperature::new)
+    }
+}
+
+/// The thermistor's resistance in a divider below a `series_ohms` resistor
+/// to the ADC reference, from the ADC `count` out of `max_count`. `None` at
+/// the rails, where the probe is shorted or disconnected.
+pub fn divider_resistance(count: u16, max_count: u16, series_ohms: f32) -> Option<f32> {
+    if count == 0 || count >= max_count {
+        return None;
+    }
+    Some(series_ohms * count as f32 / (max_count - count) as f32)
+}
+
+/// A thermocouple's EMF in millivolts by temperature in °C, with the cold
+/// junction at 0 °C.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thermocouple {
+    table: LookupTable,
+}
+
+/// NIST ITS-90 type K reference values every 50 °C.
+const TYPE_K_MILLIVOLTS: [(f32, f32); 31] = [
+    (-200.0, -5.891), (-150.0, -4.913), (-100.0, -3.554), (-50.0, -1.889),
+    (0.0, 0.000), (50.0, 2.023), (100.0, 4.096), (150.0, 6.138),
+    (200.0, 8.138), (250.0, 10.153), (300.0, 12.209), (350.0, 14.293),
+    (400.0, 16.397), (450.0, 18.516), (500.0, 20.644), (550.0, 22.776),
+    (600.0, 24.905), (650.0, 27.025), (700.0, 29.129), (750.0, 31.213),
+    (800.0, 33.275), (850.0, 35.314), (900.0, 37.326), (950.0, 39.314),
+    (1000.0, 41.276), (1050.0, 43.211), (1100.0, 45.119), (1150.0, 46.995),
+    (1200.0, 48.838), (1250.0, 50.644), (1300.0, 52.410),
+];
+
+/// Type K (chromel-alumel), -200 to 1300 °C.
+pub const TYPE_K: Thermocouple = Thermocouple::new(LookupTable::new(&TYPE_K_MILLIVOLTS));
+
+/// NIST ITS-90 type J reference values every 50 °C.
+const TYPE_J_MILLIVOLTS: [(f32, f32); 29] = [
+    (-200.0, -7.890), (-150.0, -6.500), (-100.0, -4.633), (-50.0, -2.431),
+    (0.0, 0.000), (50.0, 2.585), (100.0, 5.269), (150.0, 8.010),
+    (200.0, 10.779), (250.0, 13.555), (300.0, 16.327), (350.0, 19.090),
+    (400.0, 21.848), (450.0, 24.610), (500.0, 27.393), (550.0, 30.216),
+    (600.0, 33.102), (650.0, 36.071), (700.0, 39.132), (750.0, 42.281),
+    (800.0, 45.494), (850.0, 48.715), (900.0, 51.877), (950.0, 54.956),
+    (1000.0, 57.953), (1050.0, 60.890), (1100.0, 63.792), (1150.0, 66.679),
+    (1200.0, 69.553),
+];
+
+/// Type J (iron-constantan), -200 to 1200 °C.
+pub const TYPE_J: Thermocouple = Thermocouple::new(LookupTable::new(&TYPE_J_MILLIVOLTS));
+
+impl Thermocouple {
+    /// `table` maps °C to millivolts with the cold junction at 0 °C.
+    pub const fn new(table: LookupTable) -> Self {
+        Self { table }
+    }
+
+    pub const fn millivolts(&self, celsius: f32) -> Option<f32> {
+        self.table.lookup(celsius)
+    }
+
+    /// The hot junction's temperature from the measured `millivolts`, with
+    /// the cold junction (the terminals) at `cold_junction_celsius`.
+    pub const fn to_celsius(&self, millivolts: f32, cold_junction_celsius: f32) -> Option<f32> {
+        match self.millivolts(cold_junction_celsius) {
+            Some(compensation) => self.table.reverse(millivolts + compensation),
+            None => None,
+        }
+    }
+
+    /// Like [`to_celsius`](Self::to_celsius) for an EMF in microvolts, as
+    /// thermocouple front ends such as the MAX31856 report it.
+    pub const fn microvolts_to_celsius(&self, microvolts: i32, cold_junction_celsius: f32) -> Option<f32> {
+        self.to_celsius(microvolts as f32 / 1000.0, cold_junction_celsius)
+    }
+
+    pub const fn temperature(&self, millivolts: f32, cold_junction: Temperature) -> Option<Temperature> {
+        match self.to_celsius(millivolts, cold_junction.celsius) {
+            Some(celsius) => Some(Temperature::new(celsius)),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: Option<f32>, expected: f32, tolerance: f32) {
+        let actual = actual.unwrap();
+        assert!((actual - expected).abs() < tolerance, "{} is not within {} of {}", actual, tolerance, expected);
+    }
+
+    #[test]
+    fn thermistor_models_agree_on_a_10k_probe() {
+        assert_near(NTC_10K_3950.to_celsius(10_000.0), 25.0, 0.01);
+        // Datasheet: 32.65 kΩ at 0 °C, 3.60 kΩ at 50 °C
+        assert_near(NTC_10K_3950.to_celsius(32_650.0), 0.0, 1.0);
+        assert_near(NTC_10K_3950.to_celsius(3_600.0), 50.0, 1.0);
+
+        // Generic coefficients for 10 kΩ probes, not fitted to this one
+        let steinhart_hart = Ntc::SteinhartHart { a: 1.009_249_5e-3, b: 2.378_405_4e-4, c: 2.019_202_7e-7 };
+        assert_near(steinhart_hart.to_celsius(10_000.0), 25.0, 0.5);
+
+        const POINTS: [(f32, f32); 3] = [(32_650.0, 0.0), (10_000.0, 25.0), (3_600.0, 50.0)];
+        let table = Ntc::Table(LookupTable::new(&POINTS));
+        assert_near(table.to_celsius(10_000.0), 25.0, 0.01);
+        assert_near(table.to_celsius(6_800.0), 37.5, 0.01);
+        assert_eq!(table.to_celsius(50_000.0), None);
+        assert_eq!(NTC_10K_3950.to_celsius(0.0), None);
+    }
+
+    #[test]
+    fn thermistor_in_a_divider() {
+        // 10 kΩ series resistor, 12-bit ADC at mid-scale
+        assert_near(divider_resistance(2048, 4095, 10_000.0), 10_000.0, 10.0);
+        assert_eq!(divider_resistance(0, 4095, 10_000.0), None);
+        assert_eq!(divider_resistance(4095, 4095, 10_000.0), None);
+    }
+
+    #[test]
+    fn type_k_with_cold_junction_compensation() {
+        assert_near(TYPE_K.to_celsius(20.644, 0.0), 500.0, 0.01);
+        assert_near(TYPE_K.to_celsius(3.0, 0.0), 73.5, 0.5);
+
+        // Terminals at 25 °C (1.000 mV) cut the measured EMF by as much
+        assert_near(TYPE_K.to_celsius(20.644 - 1.000, 25.0), 500.0, 0.5);
+        assert_near(TYPE_K.to_celsius(-1.000, 25.0), 0.0, 0.5);
+
+        assert_eq!(TYPE_K.to_celsius(60.0, 25.0), None);
+        assert_eq!(TYPE_K.to_celsius(1.0, 2000.0), None);
+    }
+
+    #[test]
+    fn type_j_from_microvolts_at_compile_time() {
+        const BOILING: Option<f32> = TYPE_J.microvolts_to_celsius(5_269, 0.0);
+        assert_near(BOILING, 100.0, 0.01);
+        // Between table points the error stays well below a degree: 5.812 mV is 110 °C
+        assert_near(TYPE_J.microvolts_to_celsius(5_812, 0.0), 110.0, 0.5);
+
+        // Terminals at 25 °C (1.277 mV)
+        assert_near(TYPE_J.microvolts_to_celsius(27_393 - 1_277, 25.0), 500.0, 0.5);
+        assert_near(TYPE_J.microvolts_to_celsius(-4_633 - 1_277, 25.0), -100.0, 0.5);
+        assert_eq!(TYPE_J.microvolts_to_celsius(80_000, 25.0), None);
+
+        // Same EMF, different alloys
+        assert!(TYPE_J.to_celsius(10.0, 0.0).unwrap() < TYPE_K.to_celsius(10.0, 0.0).unwrap());
+    }
+}
@@ -0,0 +1,286 @@
+//! Sensors whose failed reads are tried again before giving up.
+//!
+//! A bus glitch or a conversion that was not ready fails one read and not
+//! the next. Wrapping a sensor in a [`RetrySensor`] reads it up to
+//! [`RetryPolicy::attempts`] times per call, so a single glitch no longer
+//! reaches the caller. Between attempts it calls a delay hook with how long
+//! to wait, e.g. `|ms| delay.delay_ms(ms)` with an embedded-hal delay or
+//! `|ms| std::thread::sleep(Duration::from_millis(ms as u64))` on a host; by
+//! default it retries at once. When every attempt failed, the
+//! [`RetryError`] holds each attempt's error.
+//!
+//! Only errors are retried. A reading that is not a possible temperature is
+//! returned as it is, for whoever validates readings to count.
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::info::{MeasurementRange, SensorInfo};
+use crate::{SensorHealth, Temperature, TemperatureSensor};
+
+/// Most attempts per read; the errors of all of them are kept inline.
+pub const MAX_ATTEMPTS: u8 = 8;
+
+/// How often and how patiently a [`RetrySensor`] reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Reads per call, the first included; 1 to [`MAX_ATTEMPTS`].
+    pub attempts: u8,
+    /// Wait before the first retry; it doubles with every further one.
+    pub delay_ms: u32,
+    /// Longest wait between two attempts, no shorter than `delay_ms`.
+    pub max_delay_ms: u32,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, without waiting.
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl RetryPolicy {
+    /// `attempts` reads per call without waiting between them; limited to
+    /// `1..=MAX_ATTEMPTS`.
+    pub const fn new(attempts: u8) -> Self {
+        let attempts = if attempts == 0 {
+            1
+        } else if attempts > MAX_ATTEMPTS {
+            MAX_ATTEMPTS
+        } else {
+            attempts
+        };
+        Self { attempts, delay_ms: 0, max_delay_ms: 0 }
+    }
+
+    /// Waits `delay_ms` before the first retry, doubling up to `max_delay_ms`.
+    pub const fn with_delay(mut self, delay_ms: u32, max_delay_ms: u32) -> Self {
+        self.delay_ms = delay_ms;
+        self.max_delay_ms = max_delay_ms;
+        self
+    }
+
+    /// Milliseconds to wait after failed attempt number `attempt` (from 1),
+    /// or `None` once all attempts are used up.
+    pub fn retry_delay(&self, attempt: u8) -> Option<u32> {
+        if attempt >= self.attempts {
+            return None;
+        }
+        let factor = 1u32.checked_shl(u32::from(attempt) - 1).unwrap_or(u32::MAX);
+        Some(self.delay_ms.saturating_mul(factor).min(self.max_delay_ms.max(self.delay_ms)))
+    }
+}
+
+/// Every attempt of a read failed; see the [module docs](self).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryError<E> {
+    earlier: heapless::Vec<E, { MAX_ATTEMPTS as usize - 1 }>,
+    last: E,
+}
+
+impl<E> RetryError<E> {
+    /// The errors of the attempts before the final one, oldest first.
+    pub fn earlier(&self) -> &[E] {
+        &self.earlier
+    }
+
+    /// The error of the final attempt.
+    pub fn last(&self) -> &E {
+        &self.last
+    }
+
+    pub fn into_last(self) -> E {
+        self.last
+    }
+
+    pub fn attempts(&self) -> usize {
+        self.earlier.len() + 1
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for RetryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Sensor read failed {} times, last error: {:?}", self.attempts(), self.last())
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for RetryError<E> {}
+
+fn no_delay(_ms: u32) {}
+
+/// Reads the wrapped sensor up to `policy.attempts` times per call, calling
+/// `delay` with the wait before each retry.
+#[derive(Debug, Clone)]
+pub struct RetrySensor<S, D = fn(u32)> {
+    sensor: S,
+    policy: RetryPolicy,
+    delay: D,
+    retried: u32,
+}
+
+impl<S: TemperatureSensor> RetrySensor<S> {
+    pub fn new(sensor: S, policy: RetryPolicy) -> Self {
+        Self { sensor, policy, delay: no_delay, retried: 0 }
+    }
+}
+
+impl<S: TemperatureSensor, D: FnMut(u32)> RetrySensor<S, D> {
+    /// Calls `delay` with the milliseconds to wait before each retry, even
+    /// when the policy waits for none, so it can also count or log them.
+    pub fn with_delay<F: FnMut(u32)>(self, delay: F) -> RetrySensor<S, F> {
+        RetrySensor { sensor: self.sensor, policy: self.policy, delay, retried: self.retried }
+    }
+
+    pub fn policy(&self) -> RetryPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: RetryPolicy) {
+        self.policy = policy;
+    }
+
+    /// How many reads failed at first and were tried again, to tell a
+    /// flaky sensor from a sound one while it still reads fine.
+    pub fn retried(&self) -> u32 {
+        self.retried
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.sensor
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.sensor
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sensor
+    }
+
+    fn retry<T>(&mut self, mut read: impl FnMut(&mut S) -> Result<T, S::Error>) -> Result<T, RetryError<S::Error>> {
+        let mut earlier = heapless::Vec::new();
+        let mut attempt = 1;
+        loop {
+            let error = match read(&mut self.sensor) {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            let Some(ms) = self.policy.retry_delay(attempt) else {
+                return Err(RetryError { earlier, last: error });
+            };
+            if attempt == 1 {
+                self.retried = self.retried.saturating_add(1);
+            }
+            // Fits: the policy allows at most MAX_ATTEMPTS
+            let _ = earlier.push(error);
+            (self.delay)(ms);
+            attempt += 1;
+        }
+    }
+}
+
+impl<S: TemperatureSensor, D: FnMut(u32)> TemperatureSensor for RetrySensor<S, D> {
+    type Error = RetryError<S::Error>;
+
+    fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+        self.retry(S::read_temperature)
+    }
+
+    fn read_temperatures(&mut self, out: &mut [Temperature]) -> Result<usize, Self::Error> {
+        self.retry(|sensor| sensor.read_temperatures(out))
+    }
+
+    fn sensor_id(&self) -> &str {
+        self.sensor.sensor_id()
+    }
+
+    fn self_test(&mut self) -> Result<SensorHealth, Self::Error> {
+        self.retry(S::self_test)
+    }
+}
+
+impl<S: SensorInfo, D: FnMut(u32)> SensorInfo for RetrySensor<S, D> {
+    fn accuracy(&self) -> f32 {
+        self.sensor.accuracy()
+    }
+
+    fn resolution(&self) -> f32 {
+        self.sensor.resolution()
+    }
+
+    fn range(&self) -> MeasurementRange {
+        self.sensor.range()
+    }
+
+    fn manufacturer(&self) -> &str {
+        self.sensor.manufacturer()
+    }
+
+    fn model(&self) -> &str {
+        self.sensor.model()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails with the next error of `errors`, then reads 21°C.
+    struct Flaky<'a> {
+        errors: &'a [u8],
+    }
+
+    impl TemperatureSensor for Flaky<'_> {
+        type Error = u8;
+
+        fn read_temperature(&mut self) -> Result<Temperature, u8> {
+            match self.errors.split_first() {
+                Some((&error, rest)) => {
+                    self.errors = rest;
+                    Err(error)
+                }
+                None => Ok(Temperature::new(21.0)),
+            }
+        }
+
+        fn sensor_id(&self) -> &str {
+            "flaky"
+        }
+    }
+
+    #[test]
+    fn retries_until_a_read_succeeds() {
+        let mut waits = heapless::Vec::<u32, 8>::new();
+        let policy = RetryPolicy::new(3).with_delay(10, 15);
+        let mut sensor = RetrySensor::new(Flaky { errors: &[1, 2] }, policy).with_delay(|ms| waits.push(ms).unwrap());
+        assert_eq!(sensor.read_temperature(), Ok(Temperature::new(21.0)));
+        assert_eq!(sensor.retried(), 1);
+        assert_eq!(sensor.sensor_id(), "flaky");
+
+        sensor.inner_mut().errors = &[3, 4, 5, 6];
+        let error = sensor.read_temperature().unwrap_err();
+        assert_eq!(error.earlier(), &[3, 4]);
+        assert_eq!(error.attempts(), 3);
+        assert_eq!(error.into_last(), 5);
+        // The fourth error is left for the next read
+        let mut batch = [Temperature::new(0.0); 2];
+        assert_eq!(sensor.read_temperatures(&mut batch), Ok(1));
+        assert_eq!(sensor.retried(), 3);
+        assert_eq!(waits[..], [10, 15, 10, 15, 10]);
+    }
+
+    #[test]
+    fn policies_stay_in_bounds() {
+        assert_eq!(RetryPolicy::new(0).attempts, 1);
+        assert_eq!(RetryPolicy::new(100).attempts, MAX_ATTEMPTS);
+        assert_eq!(RetryPolicy::new(1).retry_delay(1), None);
+
+        let policy = RetryPolicy::new(MAX_ATTEMPTS).with_delay(u32::MAX / 2, 0);
+        assert_eq!(policy.retry_delay(7), Some(u32::MAX / 2));
+        assert_eq!(policy.retry_delay(8), None);
+
+        let mut sensor = RetrySensor::new(Flaky { errors: &[1; 9] }, RetryPolicy::new(MAX_ATTEMPTS));
+        assert_eq!(sensor.read_temperature().unwrap_err().attempts(), MAX_ATTEMPTS as usize);
+    }
+}
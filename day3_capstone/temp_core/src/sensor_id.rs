@@ -0,0 +1,179 @@
+//! Names of sensors, checked once where they come in.
+//!
+//! A [`SensorId`] holds at most [`MAX_SENSOR_ID_LEN`] ASCII letters, digits,
+//! `_`, `-` and `.`, inline, so it needs no allocator: nodes can name
+//! themselves in a fixed-size frame, and on the host cloning one is a copy
+//! of a few bytes rather than an allocation. The charset keeps ids safe in
+//! file names, URLs and topics alike. Ids compare, order and hash as their
+//! text, so maps keyed by them can be looked up with a `&str`.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::ops::Deref;
+use core::str::FromStr;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Longest sensor id, in bytes; short enough for a node to send its name
+/// within the smallest frame it supports.
+pub const MAX_SENSOR_ID_LEN: usize = 24;
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SensorId(heapless::String<MAX_SENSOR_ID_LEN>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorIdError {
+    Empty,
+    TooLong,
+    InvalidChar(char),
+}
+
+impl fmt::Display for SensorIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SensorIdError::Empty => write!(f, "Sensor id must not be empty"),
+            SensorIdError::TooLong => write!(f, "Sensor id must be at most {} characters", MAX_SENSOR_ID_LEN),
+            SensorIdError::InvalidChar(c) => {
+                write!(f, "Sensor id may only contain letters, digits, '_', '-' and '.', not {:?}", c)
+            }
+        }
+    }
+}
+
+impl core::error::Error for SensorIdError {}
+
+impl SensorId {
+    pub fn new(id: &str) -> Result<Self, SensorIdError> {
+        if let Some(c) = id.chars().find(|&c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))) {
+            return Err(SensorIdError::InvalidChar(c));
+        }
+        if id.is_empty() {
+            return Err(SensorIdError::Empty);
+        }
+        let mut text = heapless::String::new();
+        text.push_str(id).map_err(|()| SensorIdError::TooLong)?;
+        Ok(Self(text))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for SensorId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for SensorId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for SensorId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for SensorId {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for SensorId {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Display for SensorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for SensorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl FromStr for SensorId {
+    type Err = SensorIdError;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        Self::new(id)
+    }
+}
+
+impl TryFrom<&str> for SensorId {
+    type Error = SensorIdError;
+
+    fn try_from(id: &str) -> Result<Self, Self::Error> {
+        Self::new(id)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<SensorId> for String {
+    fn from(id: SensorId) -> Self {
+        id.as_str().to_string()
+    }
+}
+
+impl Serialize for SensorId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for SensorId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SensorIdVisitor;
+
+        impl Visitor<'_> for SensorIdVisitor {
+            type Value = SensorId;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a sensor id")
+            }
+
+            fn visit_str<E: de::Error>(self, id: &str) -> Result<SensorId, E> {
+                SensorId::new(id).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(SensorIdVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_and_round_trips() {
+        let id = SensorId::new("living_room-2.north").unwrap();
+        assert_eq!(id, "living_room-2.north");
+        assert_eq!(id.len(), 19);
+
+        assert_eq!(SensorId::new(""), Err(SensorIdError::Empty));
+        assert!(SensorId::new("greenhouse_north_bench_3").is_ok());
+        assert_eq!(SensorId::new("greenhouse_north_bench_12"), Err(SensorIdError::TooLong));
+        assert_eq!(SensorId::new("living room"), Err(SensorIdError::InvalidChar(' ')));
+        assert_eq!("café".parse::<SensorId>(), Err(SensorIdError::InvalidChar('é')));
+
+        assert_eq!(serde_json::to_string(&id).unwrap(), r#""living_room-2.north""#);
+        assert_eq!(serde_json::from_str::<SensorId>(r#""living_room-2.north""#).unwrap(), id);
+        assert!(serde_json::from_str::<SensorId>(r#""../etc/passwd""#).is_err());
+        // Maps keyed by ids can be looked up by text
+        assert_eq!(Borrow::<str>::borrow(&id), "living_room-2.north");
+    }
+}
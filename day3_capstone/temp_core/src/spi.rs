@@ -0,0 +1,282 @@
+//! Drivers for SPI temperature sensors, with the `spi` feature.
+//!
+//! - [`Max31855`]: a type K thermocouple converter with its own cold
+//!   junction sensor, for temperatures beyond what silicon sensors reach.
+//!   Each read is a single 32-bit transfer holding both temperatures and the
+//!   thermocouple's fault flags.
+//!
+//! Drivers take an [`embedded_hal::spi::SpiDevice`], which owns chip select,
+//! so one constructor call is enough even with several sensors on a bus:
+//!
+//! ```ignore
+//! let mut sensor = Max31855::new(ExclusiveDevice::new(bus, cs, delay)?);
+//! let temperature = sensor.read_temperature()?;
+//! ```
+//!
+//! The MAX31855 converts with a linear 41.276 µV/°C, which is off by
+//! several degrees below 0 °C and above 300 °C. Reads undo that slope and
+//! look the voltage up in the NIST table of [`probe::TYPE_K`] instead.
+
+use core::fmt;
+
+use embedded_hal::spi::SpiDevice;
+
+use crate::info::{MeasurementRange, SensorInfo};
+use crate::probe;
+use crate::{SensorHealth, Temperature, TemperatureSensor};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpiSensorError<E> {
+    Bus(E),
+    /// No thermocouple is connected, or a wire broke.
+    OpenCircuit,
+    ShortToGround,
+    ShortToVcc,
+}
+
+impl<E: fmt::Debug> fmt::Display for SpiSensorError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpiSensorError::Bus(e) => write!(f, "SPI bus error: {:?}", e),
+            SpiSensorError::OpenCircuit => write!(f, "Thermocouple is not connected"),
+            SpiSensorError::ShortToGround => write!(f, "Thermocouple is shorted to ground"),
+            SpiSensorError::ShortToVcc => write!(f, "Thermocouple is shorted to VCC"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for SpiSensorError<E> {}
+
+impl<E> From<E> for SpiSensorError<E> {
+    fn from(e: E) -> Self {
+        SpiSensorError::Bus(e)
+    }
+}
+
+/// One MAX31855 conversion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermocoupleReading {
+    /// At the probe tip, linearized.
+    pub hot_junction: Temperature,
+    /// At the chip, where the thermocouple wires end.
+    pub cold_junction: Temperature,
+}
+
+pub struct Max31855<SPI> {
+    spi: SPI,
+    id: &'static str,
+}
+
+impl<SPI: SpiDevice> Max31855<SPI> {
+    /// Set along with one of the three lowest bits.
+    const FAULT: u32 = 1 << 16;
+    const SHORT_TO_VCC: u32 = 1 << 2;
+    const SHORT_TO_GROUND: u32 = 1 << 1;
+    /// Bits that always read as zero.
+    const RESERVED: u32 = (1 << 17) | (1 << 3);
+    /// The chip's own conversion slope, in millivolts per degree.
+    const MV_PER_DEGREE: f32 = 0.041276;
+
+    pub fn new(spi: SPI) -> Self {
+        Self { spi, id: "max31855" }
+    }
+
+    pub fn with_id(mut self, id: &'static str) -> Self {
+        self.id = id;
+        self
+    }
+
+    fn read_word(&mut self) -> Result<u32, SpiSensorError<SPI::Error>> {
+        let mut word = [0; 4];
+        self.spi.read(&mut word)?;
+        Ok(u32::from_be_bytes(word))
+    }
+
+    /// Reads both junctions, failing on a thermocouple fault.
+    pub fn measure(&mut self) -> Result<ThermocoupleReading, SpiSensorError<SPI::Error>> {
+        let word = self.read_word()?;
+        if word & Self::FAULT != 0 {
+            return Err(Self::fault(word));
+        }
+        let (hot, cold) = max31855_celsius(word);
+        Ok(ThermocoupleReading {
+            hot_junction: Temperature::new(Self::linearize(hot, cold)),
+            cold_junction: Temperature::new(cold),
+        })
+    }
+
+    fn fault(word: u32) -> SpiSensorError<SPI::Error> {
+        if word & Self::SHORT_TO_VCC != 0 {
+            SpiSensorError::ShortToVcc
+        } else if word & Self::SHORT_TO_GROUND != 0 {
+            SpiSensorError::ShortToGround
+        } else {
+            SpiSensorError::OpenCircuit
+        }
+    }
+
+    /// Recovers the thermocouple voltage from the chip's linear conversion
+    /// and converts it by the NIST table; outside the table the chip's
+    /// value is the best there is.
+    fn linearize(hot: f32, cold: f32) -> f32 {
+        let millivolts = (hot - cold) * Self::MV_PER_DEGREE;
+        probe::TYPE_K.to_celsius(millivolts, cold).unwrap_or(hot)
+    }
+
+    pub fn read(&mut self) -> Result<Temperature, SpiSensorError<SPI::Error>> {
+        self.measure().map(|reading| reading.hot_junction)
+    }
+
+    /// Failed if the reserved bits are set, as when no chip drives MISO;
+    /// degraded if the chip itself is outside its operating range.
+    /// Thermocouple faults are errors, as on a read.
+    pub fn self_test(&mut self) -> Result<SensorHealth, SpiSensorError<SPI::Error>> {
+        let word = self.read_word()?;
+        if word & Self::RESERVED != 0 {
+            return Ok(SensorHealth::Failed);
+        }
+        if word & Self::FAULT != 0 {
+            return Err(Self::fault(word));
+        }
+        let (hot, cold) = max31855_celsius(word);
+        let temperature = Temperature::new(Self::linearize(hot, cold));
+        let health = match SensorHealth::of_reading(temperature) {
+            SensorHealth::Ok if !(-40.0..=125.0).contains(&cold) || !self.range().contains(temperature) => {
+                SensorHealth::Degraded
+            }
+            health => health,
+        };
+        Ok(health)
+    }
+
+    /// Gives back the device.
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+}
+
+/// The hot junction is a 14-bit two's complement value of 1/4 °C at the
+/// top of the word, the cold junction a 12-bit one of 1/16 °C below it.
+fn max31855_celsius(word: u32) -> (f32, f32) {
+    let word = word as i32;
+    let hot = (word >> 18) as f32 * 0.25;
+    let cold = ((word << 16) >> 20) as f32 * 0.0625;
+    (hot, cold)
+}
+
+impl<SPI: SpiDevice> TemperatureSensor for Max31855<SPI> {
+    type Error = SpiSensorError<SPI::Error>;
+
+    fn read_temperature(&mut self) -> Result<Temperature, Self::Error> {
+        self.read()
+    }
+
+    fn sensor_id(&self) -> &str {
+        self.id
+    }
+
+    fn self_test(&mut self) -> Result<SensorHealth, Self::Error> {
+        Max31855::self_test(self)
+    }
+}
+
+impl<SPI: SpiDevice> SensorInfo for Max31855<SPI> {
+    /// Between -200 and 700 °C, plus the thermocouple's own tolerance.
+    fn accuracy(&self) -> f32 {
+        2.0
+    }
+
+    fn resolution(&self) -> f32 {
+        0.25
+    }
+
+    /// What a type K thermocouple covers.
+    fn range(&self) -> MeasurementRange {
+        MeasurementRange::new(-200.0, 1350.0)
+    }
+
+    fn manufacturer(&self) -> &str {
+        "Analog Devices"
+    }
+
+    fn model(&self) -> &str {
+        "MAX31855K"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::spi::{ErrorKind, ErrorType, Operation};
+
+    /// A MAX31855 answering every read with `word`.
+    struct FakeMax31855 {
+        word: u32,
+    }
+
+    impl ErrorType for FakeMax31855 {
+        type Error = ErrorKind;
+    }
+
+    impl SpiDevice for FakeMax31855 {
+        fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), ErrorKind> {
+            for operation in operations {
+                match operation {
+                    Operation::Read(buffer) => buffer.copy_from_slice(&self.word.to_be_bytes()),
+                    _ => return Err(ErrorKind::Other),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// The word the chip sends for `hot` and `cold` in its own resolution.
+    fn word(hot: f32, cold: f32) -> u32 {
+        let hot = ((hot / 0.25) as i32 as u32 & 0x3FFF) << 18;
+        let cold = ((cold / 0.0625) as i32 as u32 & 0xFFF) << 4;
+        hot | cold
+    }
+
+    #[test]
+    fn converts_max31855_words() {
+        // From the datasheet's table of example values
+        assert_eq!(max31855_celsius(0x6400_0000), (1600.0, 0.0));
+        assert_eq!(max31855_celsius(0xFFFC_0000), (-0.25, 0.0));
+        assert_eq!(max31855_celsius(0xF060_0000), (-250.0, 0.0));
+        assert_eq!(max31855_celsius(0x0000_7F00), (0.0, 127.0));
+        assert_eq!(max31855_celsius(0x0000_EC00), (0.0, -20.0));
+        assert_eq!(word(-10.25, -20.0), 0xFF5C_EC00);
+    }
+
+    #[test]
+    fn max31855_linearizes_by_the_nist_table() {
+        let mut sensor = Max31855::new(FakeMax31855 { word: word(25.0, 25.0) });
+        let reading = sensor.measure().unwrap();
+        assert!((reading.hot_junction.celsius - 25.0).abs() < 0.01);
+        assert_eq!(reading.cold_junction, Temperature::new(25.0));
+        assert_eq!(sensor.sensor_id(), "max31855");
+
+        // The chip reads about -85.5 °C for a probe at -100 °C
+        let millivolts = probe::TYPE_K.millivolts(-100.0).unwrap() - probe::TYPE_K.millivolts(25.0).unwrap();
+        let chip = 25.0 + millivolts / 0.041276;
+        assert!((chip + 85.5).abs() < 0.5, "{}", chip);
+        let mut sensor = Max31855::new(FakeMax31855 { word: word(chip, 25.0) }).with_id("kiln");
+        assert!((sensor.read_temperature().unwrap().celsius + 100.0).abs() < 0.5);
+        assert_eq!(sensor.sensor_id(), "kiln");
+    }
+
+    #[test]
+    fn max31855_reports_thermocouple_faults() {
+        let faulty = |flag| Max31855::new(FakeMax31855 { word: word(0.0, 25.0) | 1 << 16 | flag });
+        assert_eq!(faulty(1).read_temperature(), Err(SpiSensorError::OpenCircuit));
+        assert_eq!(faulty(1 << 1).read_temperature(), Err(SpiSensorError::ShortToGround));
+        assert_eq!(faulty(1 << 2).self_test(), Err(SpiSensorError::ShortToVcc));
+
+        let health = |word| Max31855::new(FakeMax31855 { word }).self_test();
+        assert_eq!(health(word(200.0, 25.0)), Ok(SensorHealth::Ok));
+        // The chip itself is too hot
+        assert_eq!(health(word(200.0, 126.0)), Ok(SensorHealth::Degraded));
+        // MISO floating high
+        assert_eq!(health(u32::MAX), Ok(SensorHealth::Failed));
+    }
+}
@@ -0,0 +1,280 @@
+//! Alarm thresholds shared by the host protocol, the alert engine and the
+//! embedded firmware.
+//!
+//! A reading above `max` or below `min` is a violation once it has lasted
+//! `consecutive` readings in a row. It clears only when the reading is back
+//! inside the range by at least `hysteresis`, so a value hovering at the
+//! limit does not flap. A [`ThresholdConfig`] can only be built valid, and
+//! deserializing one checks the same rules. An [`Alarm`] applies these rules
+//! to successive readings and tracks the resulting [`AlarmState`], for the
+//! alert engine and firmware alike.
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Temperature;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawThresholdConfig")]
+pub struct ThresholdConfig {
+    min: f32,
+    max: f32,
+    hysteresis: f32,
+    consecutive: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThresholdError {
+    NotFinite,
+    MinNotBelowMax,
+    NegativeHysteresis,
+    /// The clear levels of both limits would overlap.
+    HysteresisTooWide,
+    ZeroConsecutive,
+}
+
+impl fmt::Display for ThresholdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThresholdError::NotFinite => write!(f, "Thresholds must be finite numbers"),
+            ThresholdError::MinNotBelowMax => write!(f, "Min temperature must be less than max temperature"),
+            ThresholdError::NegativeHysteresis => write!(f, "Hysteresis must not be negative"),
+            ThresholdError::HysteresisTooWide => write!(f, "Hysteresis must be less than half the range"),
+            ThresholdError::ZeroConsecutive => write!(f, "At least one reading must violate a threshold"),
+        }
+    }
+}
+
+impl ThresholdConfig {
+    pub const fn new(min: f32, max: f32, hysteresis: f32, consecutive: u32) -> Result<Self, ThresholdError> {
+        if !min.is_finite() || !max.is_finite() || !hysteresis.is_finite() {
+            return Err(ThresholdError::NotFinite);
+        }
+        if min >= max {
+            return Err(ThresholdError::MinNotBelowMax);
+        }
+        if hysteresis < 0.0 {
+            return Err(ThresholdError::NegativeHysteresis);
+        }
+        if hysteresis * 2.0 >= max - min {
+            return Err(ThresholdError::HysteresisTooWide);
+        }
+        if consecutive == 0 {
+            return Err(ThresholdError::ZeroConsecutive);
+        }
+        Ok(Self { min, max, hysteresis, consecutive })
+    }
+
+    /// Plain limits: no hysteresis, a single reading is enough.
+    pub const fn range(min: f32, max: f32) -> Result<Self, ThresholdError> {
+        Self::new(min, max, 0.0, 1)
+    }
+
+    /// Plain limits between two temperatures, e.g. for a firmware's defaults.
+    pub const fn between(min: Temperature, max: Temperature) -> Result<Self, ThresholdError> {
+        Self::range(min.celsius, max.celsius)
+    }
+
+    pub const fn min(&self) -> f32 {
+        self.min
+    }
+
+    pub const fn max(&self) -> f32 {
+        self.max
+    }
+
+    pub const fn hysteresis(&self) -> f32 {
+        self.hysteresis
+    }
+
+    pub const fn consecutive(&self) -> u32 {
+        self.consecutive
+    }
+
+    pub const fn min_temperature(&self) -> Temperature {
+        Temperature::new(self.min)
+    }
+
+    pub const fn max_temperature(&self) -> Temperature {
+        Temperature::new(self.max)
+    }
+
+    /// Whether a high violation that is already active still holds.
+    pub const fn still_above(&self, celsius: f32) -> bool {
+        celsius > self.max - self.hysteresis
+    }
+
+    /// Whether a low violation that is already active still holds.
+    pub const fn still_below(&self, celsius: f32) -> bool {
+        celsius < self.min + self.hysteresis
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlarmState {
+    #[default]
+    Normal,
+    High,
+    Low,
+}
+
+impl AlarmState {
+    pub const fn is_alarm(&self) -> bool {
+        !matches!(self, AlarmState::Normal)
+    }
+}
+
+impl fmt::Display for AlarmState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlarmState::Normal => write!(f, "normal"),
+            AlarmState::High => write!(f, "high"),
+            AlarmState::Low => write!(f, "low"),
+        }
+    }
+}
+
+/// The state of one sensor's alarm, fed its readings in order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Alarm {
+    thresholds: ThresholdConfig,
+    state: AlarmState,
+    /// Readings in a row past the limit of `pending`.
+    breaches: u32,
+    pending: AlarmState,
+}
+
+impl Alarm {
+    pub const fn new(thresholds: ThresholdConfig) -> Self {
+        Self { thresholds, state: AlarmState::Normal, breaches: 0, pending: AlarmState::Normal }
+    }
+
+    /// Evaluates a reading and returns the state after it. An active alarm
+    /// holds until the reading clears its limit by the hysteresis; a new one
+    /// is raised once `consecutive` readings in a row are past a limit.
+    pub fn update(&mut self, celsius: f32) -> AlarmState {
+        let holds = match self.state {
+            AlarmState::High => self.thresholds.still_above(celsius),
+            AlarmState::Low => self.thresholds.still_below(celsius),
+            AlarmState::Normal => false,
+        };
+        if holds {
+            return self.state;
+        }
+
+        let breached = if celsius > self.thresholds.max {
+            AlarmState::High
+        } else if celsius < self.thresholds.min {
+            AlarmState::Low
+        } else {
+            AlarmState::Normal
+        };
+        self.breaches = if breached == self.pending { self.breaches.saturating_add(1) } else { 1 };
+        self.pending = breached;
+        self.state = if breached.is_alarm() && self.breaches >= self.thresholds.consecutive {
+            breached
+        } else {
+            AlarmState::Normal
+        };
+        self.state
+    }
+
+    pub const fn state(&self) -> AlarmState {
+        self.state
+    }
+
+    pub const fn thresholds(&self) -> ThresholdConfig {
+        self.thresholds
+    }
+
+    /// Replaces the thresholds; an active alarm is then held or cleared by
+    /// the new ones.
+    pub fn set_thresholds(&mut self, thresholds: ThresholdConfig) {
+        self.thresholds = thresholds;
+    }
+
+    /// Back to normal, e.g. after the sensor was replaced.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.thresholds);
+    }
+}
+
+/// Limits may be given with a unit, e.g. `"max": "80F"`; see
+/// [`deserialize_celsius`](crate::units::deserialize_celsius).
+#[derive(Deserialize)]
+struct RawThresholdConfig {
+    #[serde(deserialize_with = "crate::units::deserialize_celsius")]
+    min: f32,
+    #[serde(deserialize_with = "crate::units::deserialize_celsius")]
+    max: f32,
+    hysteresis: f32,
+    consecutive: u32,
+}
+
+impl TryFrom<RawThresholdConfig> for ThresholdConfig {
+    type Error = ThresholdError;
+
+    fn try_from(raw: RawThresholdConfig) -> Result<Self, Self::Error> {
+        Self::new(raw.min, raw.max, raw.hysteresis, raw.consecutive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_on_construction() {
+        assert_eq!(ThresholdConfig::range(26.0, 18.0), Err(ThresholdError::MinNotBelowMax));
+        assert_eq!(ThresholdConfig::range(f32::NAN, 18.0), Err(ThresholdError::NotFinite));
+        assert_eq!(ThresholdConfig::new(18.0, 26.0, -1.0, 1), Err(ThresholdError::NegativeHysteresis));
+        assert_eq!(ThresholdConfig::new(18.0, 26.0, 4.0, 1), Err(ThresholdError::HysteresisTooWide));
+        assert_eq!(ThresholdConfig::new(18.0, 26.0, 0.5, 0), Err(ThresholdError::ZeroConsecutive));
+
+        let thresholds = ThresholdConfig::new(18.0, 26.0, 0.5, 3).unwrap();
+        assert!(thresholds.still_above(25.8));
+        assert!(!thresholds.still_above(25.5));
+        assert!(thresholds.still_below(18.2));
+
+        let freezer = ThresholdConfig::between(Temperature::from_fahrenheit(-4.0), Temperature::new(-15.0)).unwrap();
+        assert_eq!(freezer.min_temperature(), Temperature::new(-20.0));
+        assert_eq!(freezer.max_temperature().to_fahrenheit(), 5.0);
+    }
+
+    #[test]
+    fn alarms_need_consecutive_readings_and_clear_past_the_hysteresis() {
+        let mut alarm = Alarm::new(ThresholdConfig::new(18.0, 26.0, 0.5, 2).unwrap());
+        assert_eq!(alarm.update(27.0), AlarmState::Normal);
+        assert_eq!(alarm.update(22.0), AlarmState::Normal);
+        assert_eq!(alarm.update(27.0), AlarmState::Normal);
+        assert_eq!(alarm.update(27.0), AlarmState::High);
+        // Inside the range, but not by the hysteresis
+        assert_eq!(alarm.update(25.8), AlarmState::High);
+        assert_eq!(alarm.update(25.4), AlarmState::Normal);
+
+        // Straight from one limit to the other still needs two readings
+        alarm.update(27.0);
+        assert_eq!(alarm.update(27.0), AlarmState::High);
+        assert_eq!(alarm.update(10.0), AlarmState::Normal);
+        assert_eq!(alarm.update(10.0), AlarmState::Low);
+        assert!(alarm.state().is_alarm());
+
+        alarm.reset();
+        assert_eq!(alarm.state(), AlarmState::Normal);
+    }
+
+    #[test]
+    fn deserializing_checks_the_same_rules() {
+        let json = r#"{"min":18.0,"max":26.0,"hysteresis":0.5,"consecutive":2}"#;
+        let thresholds: ThresholdConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(thresholds.consecutive(), 2);
+
+        let json = r#"{"min":"64.4F","max":"299.15K","hysteresis":0.5,"consecutive":2}"#;
+        let thresholds: ThresholdConfig = serde_json::from_str(json).unwrap();
+        assert!((thresholds.min() - 18.0).abs() < 1e-4 && (thresholds.max() - 26.0).abs() < 1e-4);
+
+        let inverted = r#"{"min":26.0,"max":18.0,"hysteresis":0.0,"consecutive":1}"#;
+        assert!(serde_json::from_str::<ThresholdConfig>(inverted).is_err());
+    }
+}
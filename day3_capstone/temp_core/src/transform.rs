@@ -0,0 +1,479 @@
+//! Transforms applied to readings between the sensor and the store.
+//!
+//! Each [`Transform`] maps one temperature to another, or drops the reading
+//! altogether. With the `alloc` feature, a `TransformConfig` is how a
+//! transform is written in configuration files and sent over the protocol.
+//! With the `std` feature, a `TransformPipeline` chains them per sensor and
+//! is built from those.
+
+use serde::{Deserialize, Serialize};
+
+use crate::Temperature;
+
+pub trait Transform {
+    /// The transformed temperature, or `None` to drop the reading.
+    fn apply(&mut self, temperature: Temperature) -> Option<Temperature>;
+
+    /// Forgets state carried between readings.
+    fn reset(&mut self) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+/// Adds a fixed offset, e.g. from a calibration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Offset(pub f32);
+
+impl Transform for Offset {
+    fn apply(&mut self, temperature: Temperature) -> Option<Temperature> {
+        Some(Temperature::new(temperature.celsius + self.0))
+    }
+}
+
+/// Interprets the raw value in `from` and converts it to Celsius, for
+/// sensors reporting in another unit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnitConversion {
+    pub from: Unit,
+}
+
+impl Transform for UnitConversion {
+    fn apply(&mut self, temperature: Temperature) -> Option<Temperature> {
+        let raw = temperature.celsius;
+        Some(match self.from {
+            Unit::Celsius => temperature,
+            Unit::Fahrenheit => Temperature::from_fahrenheit(raw),
+            Unit::Kelvin => Temperature::from_kelvin(raw),
+        })
+    }
+}
+
+/// Exponential moving average (EMA); `alpha` near 1.0 follows the sensor closely,
+/// near 0.0 smooths heavily.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExponentialSmoothing {
+    alpha: f32,
+    average: Option<f32>,
+}
+
+impl ExponentialSmoothing {
+    pub fn new(alpha: f32) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 1.0),
+            average: None,
+        }
+    }
+}
+
+impl Transform for ExponentialSmoothing {
+    fn apply(&mut self, temperature: Temperature) -> Option<Temperature> {
+        let average = match self.average {
+            Some(average) => average + self.alpha * (temperature.celsius - average),
+            None => temperature.celsius,
+        };
+        self.average = Some(average);
+        Some(Temperature::new(average))
+    }
+
+    fn reset(&mut self) {
+        self.average = None;
+    }
+}
+
+/// Clamps readings into `min..=max` and, with `max_step`, limits how far a
+/// reading may move from the previous one, taming single-sample spikes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutlierClamp {
+    pub min: f32,
+    pub max: f32,
+    pub max_step: Option<f32>,
+    last: Option<f32>,
+}
+
+impl OutlierClamp {
+    pub fn new(min: f32, max: f32, max_step: Option<f32>) -> Self {
+        Self {
+            min,
+            max,
+            max_step,
+            last: None,
+        }
+    }
+}
+
+impl Transform for OutlierClamp {
+    fn apply(&mut self, temperature: Temperature) -> Option<Temperature> {
+        let mut celsius = temperature.celsius.clamp(self.min, self.max);
+        if let (Some(step), Some(last)) = (self.max_step, self.last) {
+            celsius = celsius.clamp(last - step, last + step);
+        }
+        self.last = Some(celsius);
+        Some(Temperature::new(celsius))
+    }
+
+    fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Drops readings outside what the sensor can physically report, such as
+/// the -127 °C a disconnected 1-Wire sensor returns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeFilter {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Transform for RangeFilter {
+    fn apply(&mut self, temperature: Temperature) -> Option<Temperature> {
+        (self.min..=self.max)
+            .contains(&temperature.celsius)
+            .then_some(temperature)
+    }
+}
+
+/// Largest window a [`MedianFilter`] keeps.
+pub const MAX_MEDIAN_WINDOW: usize = 15;
+
+/// Median of the last `window` readings. Unlike averaging, a single spike
+/// never shows up in the output as long as it is a minority of the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MedianFilter {
+    window: usize,
+    samples: [f32; MAX_MEDIAN_WINDOW],
+    len: usize,
+    next: usize,
+}
+
+impl MedianFilter {
+    /// `window` is limited to `1..=MAX_MEDIAN_WINDOW`.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.clamp(1, MAX_MEDIAN_WINDOW),
+            samples: [0.0; MAX_MEDIAN_WINDOW],
+            len: 0,
+            next: 0,
+        }
+    }
+}
+
+impl Transform for MedianFilter {
+    fn apply(&mut self, temperature: Temperature) -> Option<Temperature> {
+        self.samples[self.next] = temperature.celsius;
+        self.next = (self.next + 1) % self.window;
+        self.len = (self.len + 1).min(self.window);
+
+        let mut sorted = self.samples;
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_unstable_by(f32::total_cmp);
+        let mid = self.len / 2;
+        let median = if self.len.is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        };
+        Some(Temperature::new(median))
+    }
+
+    fn reset(&mut self) {
+        self.len = 0;
+        self.next = 0;
+    }
+}
+
+/// A one-dimensional Kalman filter for a temperature assumed to drift
+/// slowly. `process_noise` is how much the true temperature is expected to
+/// vary between readings and `measurement_noise` the sensor's variance;
+/// the lower their ratio, the smoother and slower the output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KalmanFilter {
+    process_noise: f32,
+    measurement_noise: f32,
+    estimate: Option<f32>,
+    error: f32,
+}
+
+impl KalmanFilter {
+    pub fn new(process_noise: f32, measurement_noise: f32) -> Self {
+        Self {
+            process_noise,
+            measurement_noise,
+            estimate: None,
+            error: 0.0,
+        }
+    }
+}
+
+impl Transform for KalmanFilter {
+    fn apply(&mut self, temperature: Temperature) -> Option<Temperature> {
+        let measured = temperature.celsius;
+        let estimate = match self.estimate {
+            Some(estimate) => {
+                self.error += self.process_noise;
+                let gain = self.error / (self.error + self.measurement_noise);
+                self.error *= 1.0 - gain;
+                estimate + gain * (measured - estimate)
+            }
+            None => {
+                self.error = self.measurement_noise;
+                measured
+            }
+        };
+        self.estimate = Some(estimate);
+        Some(Temperature::new(estimate))
+    }
+
+    fn reset(&mut self) {
+        self.estimate = None;
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransformConfig {
+    Offset {
+        celsius: f32,
+    },
+    Convert {
+        from: Unit,
+    },
+    Smooth {
+        alpha: f32,
+    },
+    Clamp {
+        min: f32,
+        max: f32,
+        max_step: Option<f32>,
+    },
+    Filter {
+        min: f32,
+        max: f32,
+    },
+    Median {
+        window: usize,
+    },
+    Kalman {
+        process_noise: f32,
+        measurement_noise: f32,
+    },
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidTransform {
+    pub reason: &'static str,
+}
+
+#[cfg(feature = "alloc")]
+impl core::fmt::Display for InvalidTransform {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.reason)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl TransformConfig {
+    pub fn validate(&self) -> Result<(), InvalidTransform> {
+        let reason = match *self {
+            TransformConfig::Offset { celsius } if !celsius.is_finite() => {
+                "Offset must be finite"
+            }
+            TransformConfig::Smooth { alpha } if alpha.is_nan() || alpha <= 0.0 || alpha > 1.0 => {
+                "Smoothing alpha must be in (0, 1]"
+            }
+            TransformConfig::Clamp { min, max, .. } | TransformConfig::Filter { min, max }
+                if !min.is_finite() || !max.is_finite() || min >= max =>
+            {
+                "Min must be less than max"
+            }
+            TransformConfig::Clamp {
+                max_step: Some(step),
+                ..
+            } if step.is_nan() || step <= 0.0 => "Max step must be positive",
+            TransformConfig::Median { window } if window == 0 || window > MAX_MEDIAN_WINDOW => {
+                "Median window must be between 1 and 15"
+            }
+            TransformConfig::Kalman {
+                process_noise,
+                measurement_noise,
+            } if !(process_noise.is_finite() && measurement_noise.is_finite())
+                || process_noise <= 0.0
+                || measurement_noise <= 0.0 =>
+            {
+                "Kalman noise must be positive"
+            }
+            _ => return Ok(()),
+        };
+        Err(InvalidTransform { reason })
+    }
+}
+
+#[cfg(feature = "std")]
+pub use pipeline::{SharedPipeline, TransformPipeline};
+
+#[cfg(feature = "std")]
+mod pipeline {
+    use super::*;
+
+    /// A pipeline shared by the task sampling a sensor and whoever reconfigures it.
+    pub type SharedPipeline = std::sync::Arc<std::sync::Mutex<TransformPipeline>>;
+
+    /// Transforms applied in order; a dropped reading stops the chain.
+    #[derive(Default)]
+    pub struct TransformPipeline {
+        config: Vec<TransformConfig>,
+        stages: Vec<Box<dyn Transform + Send>>,
+    }
+
+    impl TransformPipeline {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn from_config(config: Vec<TransformConfig>) -> Result<Self, InvalidTransform> {
+            config.iter().try_for_each(TransformConfig::validate)?;
+            let stages = config.iter().map(build).collect();
+            Ok(Self { config, stages })
+        }
+
+        /// Appends a transform that has no [`TransformConfig`] form.
+        pub fn push(&mut self, transform: impl Transform + Send + 'static) {
+            self.stages.push(Box::new(transform));
+        }
+
+        /// The configured stages; custom ones added with [`push`](Self::push) are not listed.
+        pub fn config(&self) -> &[TransformConfig] {
+            &self.config
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.stages.is_empty()
+        }
+
+        pub fn apply(&mut self, temperature: Temperature) -> Option<Temperature> {
+            self.stages
+                .iter_mut()
+                .try_fold(temperature, |t, stage| stage.apply(t))
+        }
+
+        pub fn reset(&mut self) {
+            self.stages.iter_mut().for_each(|stage| stage.reset());
+        }
+    }
+
+    fn build(config: &TransformConfig) -> Box<dyn Transform + Send> {
+        match *config {
+            TransformConfig::Offset { celsius } => Box::new(Offset(celsius)),
+            TransformConfig::Convert { from } => Box::new(UnitConversion { from }),
+            TransformConfig::Smooth { alpha } => Box::new(ExponentialSmoothing::new(alpha)),
+            TransformConfig::Clamp { min, max, max_step } => {
+                Box::new(OutlierClamp::new(min, max, max_step))
+            }
+            TransformConfig::Filter { min, max } => Box::new(RangeFilter { min, max }),
+            TransformConfig::Median { window } => Box::new(MedianFilter::new(window)),
+            TransformConfig::Kalman {
+                process_noise,
+                measurement_noise,
+            } => Box::new(KalmanFilter::new(process_noise, measurement_noise)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipeline_chains_and_drops() {
+        let config = vec![
+            TransformConfig::Filter {
+                min: -100.0,
+                max: 300.0,
+            },
+            TransformConfig::Convert {
+                from: Unit::Fahrenheit,
+            },
+            TransformConfig::Clamp {
+                min: -40.0,
+                max: 125.0,
+                max_step: Some(2.0),
+            },
+            TransformConfig::Offset { celsius: 0.5 },
+        ];
+        let mut pipeline = TransformPipeline::from_config(config).unwrap();
+
+        assert_eq!(
+            pipeline.apply(Temperature::new(68.0)),
+            Some(Temperature::new(20.5))
+        );
+        // Disconnected sensor: dropped before it can move the clamp
+        assert_eq!(pipeline.apply(Temperature::new(-127.0)), None);
+        // A spike only moves by max_step
+        assert_eq!(
+            pipeline.apply(Temperature::new(212.0)),
+            Some(Temperature::new(22.5))
+        );
+
+        let mut smoothing = ExponentialSmoothing::new(0.5);
+        assert_eq!(
+            smoothing.apply(Temperature::new(20.0)),
+            Some(Temperature::new(20.0))
+        );
+        assert_eq!(
+            smoothing.apply(Temperature::new(22.0)),
+            Some(Temperature::new(21.0))
+        );
+    }
+
+    #[test]
+    fn filters_steady_noisy_readings() {
+        let mut median = MedianFilter::new(3);
+        let outputs: Vec<f32> = [20.0, 20.4, 85.0, 20.2, 20.1]
+            .into_iter()
+            .filter_map(|c| median.apply(Temperature::new(c)))
+            .map(|t| t.celsius)
+            .collect();
+        assert_eq!(outputs, vec![20.0, 20.2, 20.4, 20.4, 20.2]);
+
+        // Alternating ±1 °C noise around 20 °C settles close to 20
+        let mut kalman = KalmanFilter::new(0.01, 1.0);
+        let mut last = 0.0;
+        for i in 0..50 {
+            let noisy = if i % 2 == 0 { 21.0 } else { 19.0 };
+            last = kalman.apply(Temperature::new(noisy)).unwrap().celsius;
+        }
+        assert!((last - 20.0).abs() < 0.3, "{}", last);
+
+        let json = r#"[{"type":"median","window":5},{"type":"kalman","process_noise":0.01,"measurement_noise":0.5}]"#;
+        let config: Vec<TransformConfig> = serde_json::from_str(json).unwrap();
+        assert!(TransformPipeline::from_config(config).is_ok());
+        assert!(TransformPipeline::from_config(vec![TransformConfig::Median { window: 16 }]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        assert!(
+            TransformPipeline::from_config(vec![TransformConfig::Smooth { alpha: 0.0 }]).is_err()
+        );
+        assert!(
+            TransformPipeline::from_config(vec![TransformConfig::Filter { min: 5.0, max: 5.0 }])
+                .is_err()
+        );
+        let json = r#"[{"type":"smooth","alpha":0.3},{"type":"convert","from":"kelvin"}]"#;
+        let config: Vec<TransformConfig> = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            TransformPipeline::from_config(config)
+                .unwrap()
+                .config()
+                .len(),
+            2
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Temperatures tagged with their unit.
+//!
+//! [`Temperature`] always holds Celsius, but a bare `f32` does not say which
+//! unit it is in, and a Fahrenheit value stored by mistake goes unnoticed.
+//! [`Celsius`], [`Fahrenheit`] and [`Kelvin`] carry the unit in the type, so
+//! values only cross units through `From`/`Into`:
+//!
+//! ```
+//! use temp_core::{Fahrenheit, Kelvin, Temperature};
+//!
+//! let body: Temperature = Fahrenheit(98.6).into();
+//! let kelvin = Kelvin::from(body);
+//! assert!((kelvin.0 - 310.15).abs() < 0.01);
+//! ```
+//!
+//! Text such as command line flags and config values names its unit too:
+//! `Temperature` parses from `"23.5C"`, `"74 °F"` or `"296.65K"`, and a bare
+//! number is rejected rather than taken as Celsius. Config fields that used
+//! to be plain Celsius numbers take either with [`deserialize_celsius`].
+
+use core::fmt;
+use core::str::FromStr;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{Temperature, TemperatureError};
+
+macro_rules! unit {
+    ($(#[$doc:meta])* $name:ident, $symbol:literal, $from_celsius:expr, $to_celsius:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(pub f32);
+
+        impl From<Temperature> for $name {
+            fn from(temperature: Temperature) -> Self {
+                let from_celsius: fn(f32) -> f32 = $from_celsius;
+                Self(from_celsius(temperature.celsius))
+            }
+        }
+
+        impl From<$name> for Temperature {
+            fn from(value: $name) -> Self {
+                let to_celsius: fn(f32) -> f32 = $to_celsius;
+                Temperature::new(to_celsius(value.0))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, concat!("{:.1}", $symbol), self.0)
+            }
+        }
+    };
+}
+
+unit!(Celsius, "°C", |c| c, |c| c);
+unit!(Fahrenheit, "°F", |c| Temperature::new(c).to_fahrenheit(), |f| Temperature::from_fahrenheit(f).celsius);
+unit!(
+    /// Absolute temperature; shown without a degree sign.
+    Kelvin,
+    " K",
+    |c| Temperature::new(c).to_kelvin(),
+    |k| Temperature::from_kelvin(k).celsius
+);
+
+macro_rules! convert {
+    ($from:ident => $($to:ident),+) => {
+        $(
+            impl From<$from> for $to {
+                fn from(value: $from) -> Self {
+                    Temperature::from(value).into()
+                }
+            }
+        )+
+    };
+}
+
+convert!(Celsius => Fahrenheit, Kelvin);
+convert!(Fahrenheit => Celsius, Kelvin);
+convert!(Kelvin => Celsius, Fahrenheit);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseTemperatureError {
+    /// The text does not end in `C`, `F` or `K`.
+    MissingUnit,
+    InvalidNumber,
+    Invalid(TemperatureError),
+}
+
+impl fmt::Display for ParseTemperatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseTemperatureError::MissingUnit => write!(f, "Temperature must end in a unit of C, F or K"),
+            ParseTemperatureError::InvalidNumber => write!(f, "Temperature is not a number"),
+            ParseTemperatureError::Invalid(e) => e.fmt(f),
+        }
+    }
+}
+
+impl core::error::Error for ParseTemperatureError {}
+
+impl From<TemperatureError> for ParseTemperatureError {
+    fn from(e: TemperatureError) -> Self {
+        ParseTemperatureError::Invalid(e)
+    }
+}
+
+/// A number followed by its unit, `C`, `F` or `K` in either case,
+/// optionally with a degree sign and spaces in between.
+impl FromStr for Temperature {
+    type Err = ParseTemperatureError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let unit = text.chars().last().ok_or(ParseTemperatureError::MissingUnit)?;
+        let number = text[..text.len() - unit.len_utf8()].trim_end();
+        let number = number.strip_suffix('°').unwrap_or(number).trim_end();
+        let convert: fn(f32) -> Temperature = match unit.to_ascii_uppercase() {
+            'C' => Temperature::new,
+            'F' => Temperature::from_fahrenheit,
+            'K' => Temperature::from_kelvin,
+            _ => return Err(ParseTemperatureError::MissingUnit),
+        };
+        let value = number.parse().map_err(|_| ParseTemperatureError::InvalidNumber)?;
+        let temperature = convert(value);
+        temperature.validate()?;
+        Ok(temperature)
+    }
+}
+
+impl TryFrom<&str> for Temperature {
+    type Error = ParseTemperatureError;
+
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+/// Deserializes Celsius from a plain number, as such fields always took,
+/// or from a string with a unit, such as `"64F"`; use it with
+/// `#[serde(deserialize_with = "...")]`. Binary formats only take numbers.
+pub fn deserialize_celsius<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    struct CelsiusVisitor;
+
+    impl Visitor<'_> for CelsiusVisitor {
+        type Value = f32;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("degrees Celsius or a temperature with its unit")
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<f32, E> {
+            Ok(value as f32)
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<f32, E> {
+            Ok(value as f32)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<f32, E> {
+            Ok(value as f32)
+        }
+
+        fn visit_str<E: de::Error>(self, text: &str) -> Result<f32, E> {
+            text.parse::<Temperature>().map(|temperature| temperature.celsius).map_err(E::custom)
+        }
+    }
+
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(CelsiusVisitor)
+    } else {
+        f32::deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    extern crate std;
+
+    #[test]
+    fn converts_between_units() {
+        assert_eq!(Fahrenheit::from(Celsius(100.0)), Fahrenheit(212.0));
+        assert_eq!(Celsius::from(Fahrenheit(-40.0)), Celsius(-40.0));
+        assert!((Kelvin::from(Celsius(0.0)).0 - 273.15).abs() < 1e-4);
+        assert!((Celsius::from(Kelvin(0.0)).0 + 273.15).abs() < 1e-4);
+        assert!((Fahrenheit::from(Kelvin(300.0)).0 - 80.33).abs() < 0.01);
+
+        let temperature: Temperature = Fahrenheit(68.0).into();
+        assert!((temperature.celsius - 20.0).abs() < 1e-4);
+        assert_eq!(Celsius::from(temperature).0, temperature.celsius);
+    }
+
+    #[test]
+    fn displays_with_the_unit() {
+        assert_eq!(std::format!("{}", Celsius(23.456)), "23.5°C");
+        assert_eq!(std::format!("{}", Fahrenheit(74.2)), "74.2°F");
+        assert_eq!(std::format!("{}", Kelvin(296.6)), "296.6 K");
+    }
+
+    #[test]
+    fn serializes_as_a_plain_number() {
+        assert_eq!(serde_json::to_string(&Fahrenheit(98.5)).unwrap(), "98.5");
+        assert_eq!(serde_json::from_str::<Kelvin>("300.0").unwrap(), Kelvin(300.0));
+    }
+
+    #[test]
+    fn parses_temperatures_with_their_unit() {
+        assert_eq!("23.5C".parse(), Ok(Temperature::new(23.5)));
+        assert_eq!(Temperature::try_from(" -40 °F "), Ok(Temperature::new(-40.0)));
+        assert!(("296.65K".parse::<Temperature>().unwrap().celsius - 23.5).abs() < 1e-4);
+        assert!(("74f".parse::<Temperature>().unwrap().celsius - 23.33).abs() < 0.01);
+
+        assert_eq!("23.5".parse::<Temperature>(), Err(ParseTemperatureError::MissingUnit));
+        assert_eq!("23.5 R".parse::<Temperature>(), Err(ParseTemperatureError::MissingUnit));
+        assert_eq!("".parse::<Temperature>(), Err(ParseTemperatureError::MissingUnit));
+        assert_eq!("warm C".parse::<Temperature>(), Err(ParseTemperatureError::InvalidNumber));
+        assert_eq!("-5K".parse::<Temperature>(), Err(TemperatureError::BelowAbsoluteZero.into()));
+        assert_eq!("NaN C".parse::<Temperature>(), Err(TemperatureError::NotFinite.into()));
+
+        #[derive(Deserialize)]
+        struct Limit {
+            #[serde(deserialize_with = "deserialize_celsius")]
+            max: f32,
+        }
+        assert_eq!(serde_json::from_str::<Limit>(r#"{"max": 26}"#).unwrap().max, 26.0);
+        assert_eq!(serde_json::from_str::<Limit>(r#"{"max": "212F"}"#).unwrap().max, 100.0);
+        assert!(serde_json::from_str::<Limit>(r#"{"max": "26"}"#).is_err());
+    }
+}
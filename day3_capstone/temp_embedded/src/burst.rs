@@ -0,0 +1,97 @@
+//! Burst capture: a short run of samples at a high rate.
+//!
+//! A rapid transient, such as a compressor kicking in, only shows between
+//! samples taken milliseconds apart, but sustaining such a rate would flush
+//! the reading buffer within seconds and cost power. `StartBurst` raises the
+//! sample rate until the requested number of samples is captured into a
+//! buffer of their own, then drops back to the configured rate. The regular
+//! buffer gets no readings meanwhile. The host pages the capture out with
+//! `GetBurst`, as it does the history; sample `i` was taken `i / rate_hz`
+//! seconds after `started_at`.
+
+use heapless::Vec;
+
+use crate::{EmbeddedError, Temperature};
+
+/// Most samples in one burst.
+pub const BURST_BUFFER_SIZE: usize = 128;
+/// Highest burst rate; sensors with a FIFO deliver several samples per read.
+pub const MAX_BURST_RATE_HZ: u32 = 1000;
+/// Most samples in a `Burst` page; fewer fit a small MTU.
+pub const PAGE_LEN: usize = 48;
+
+pub struct BurstCapture {
+    rate_hz: u32,
+    requested: u16,
+    started_at: u32,
+    samples: Vec<Temperature, BURST_BUFFER_SIZE>,
+}
+
+impl BurstCapture {
+    pub const fn new() -> Self {
+        Self {
+            rate_hz: 0,
+            requested: 0,
+            started_at: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Starts a new burst, dropping the last one's samples.
+    pub fn start(&mut self, rate_hz: u32, samples: u16) -> Result<(), EmbeddedError> {
+        if !(1..=MAX_BURST_RATE_HZ).contains(&rate_hz) || samples == 0 || samples as usize > BURST_BUFFER_SIZE {
+            return Err(EmbeddedError::InvalidBurst);
+        }
+        *self = Self {
+            rate_hz,
+            requested: samples,
+            ..Self::new()
+        };
+        Ok(())
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.remaining() > 0
+    }
+
+    /// The burst's sample rate while it is capturing.
+    pub fn rate_hz(&self) -> Option<u32> {
+        self.is_capturing().then_some(self.rate_hz)
+    }
+
+    /// Samples still to capture, 0 once the burst is done.
+    pub fn remaining(&self) -> u16 {
+        self.requested - self.samples.len() as u16
+    }
+
+    /// Keeps as much of `batch` as the burst still needs, returning how
+    /// many samples that was; the rest of a FIFO drain is dropped.
+    pub fn capture(&mut self, batch: &[Temperature], timestamp: u32) -> usize {
+        if self.samples.is_empty() {
+            self.started_at = timestamp;
+        }
+        let taken = batch.len().min(self.remaining() as usize);
+        // Cannot fail: `requested` is at most the buffer size
+        self.samples.extend_from_slice(&batch[..taken]).ok();
+        taken
+    }
+
+    pub fn samples(&self) -> &[Temperature] {
+        &self.samples
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate_hz
+    }
+
+    /// When the first sample was captured.
+    pub fn started_at(&self) -> u32 {
+        self.started_at
+    }
+}
+
+impl Default for BurstCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,155 @@
+//! Counters that survive resets.
+//!
+//! A watchdog reboot or brown-out would otherwise zero `total_readings` and
+//! the uptime reported by `GetStatus`. [`CounterStore`] keeps both in a small
+//! record in non-volatile memory next to the pairing record. Save it
+//! periodically and before planned resets: flash wears out, so saving on every
+//! reading is not an option, and an unplanned reset loses at most what
+//! happened since the last save.
+
+use serde::{Deserialize, Serialize};
+
+use crate::pairing::NvmStorage;
+use crate::EmbeddedError;
+
+/// Offset of the counters record in non-volatile memory, after the pairing record.
+pub const COUNTERS_RECORD_OFFSET: u32 = 32;
+
+const RECORD_MAGIC: [u8; 4] = *b"TPC1";
+pub(crate) const RECORD_LEN: usize = 4 + 4 + 4 + 1;
+
+/// Why the node last started, as reported by the MCU's reset cause register.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResetReason {
+    #[default]
+    Unknown,
+    PowerOn,
+    BrownOut,
+    Watchdog,
+    Software,
+    External,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PersistedCounters {
+    pub total_readings: u32,
+    /// Uptime summed over all boots, in seconds.
+    pub uptime_seconds: u32,
+}
+
+impl PersistedCounters {
+    fn encode(&self) -> [u8; RECORD_LEN] {
+        let mut buf = [0u8; RECORD_LEN];
+        buf[..4].copy_from_slice(&RECORD_MAGIC);
+        buf[4..8].copy_from_slice(&self.total_readings.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.uptime_seconds.to_le_bytes());
+        buf[RECORD_LEN - 1] = checksum(&buf[..RECORD_LEN - 1]);
+        buf
+    }
+
+    fn decode(buf: &[u8; RECORD_LEN]) -> Option<Self> {
+        // Erased flash or a save torn by the reset: start from zero
+        if buf[..4] != RECORD_MAGIC || buf[RECORD_LEN - 1] != checksum(&buf[..RECORD_LEN - 1]) {
+            return None;
+        }
+
+        Some(Self {
+            total_readings: u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]),
+            uptime_seconds: u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]),
+        })
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |acc, b| acc.rotate_left(1) ^ b)
+}
+
+pub struct CounterStore<S: NvmStorage> {
+    nvm: S,
+    saved: PersistedCounters,
+}
+
+impl<S: NvmStorage> CounterStore<S> {
+    /// Reads the counters saved before the reset; zero if there are none.
+    pub fn load(mut nvm: S) -> Self {
+        let mut buf = [0u8; RECORD_LEN];
+        let saved = match nvm.read(COUNTERS_RECORD_OFFSET, &mut buf) {
+            Ok(()) => PersistedCounters::decode(&buf).unwrap_or_default(),
+            Err(_) => PersistedCounters::default(),
+        };
+
+        Self { nvm, saved }
+    }
+
+    /// The counters as last saved.
+    pub fn saved(&self) -> PersistedCounters {
+        self.saved
+    }
+
+    /// Saves `counters`, skipping the write if nothing changed.
+    pub fn save(&mut self, counters: PersistedCounters) -> Result<(), EmbeddedError> {
+        if counters == self.saved {
+            return Ok(());
+        }
+        self.nvm
+            .write(COUNTERS_RECORD_OFFSET, &counters.encode())
+            .map_err(|_| EmbeddedError::StorageFailed)?;
+        self.saved = counters;
+        Ok(())
+    }
+
+    pub fn into_nvm(self) -> S {
+        self.nvm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pairing::{PairingManager, PairingRecord, RamNvm};
+    use crate::{EmbeddedCommand, EmbeddedProtocolHandler, EmbeddedResponse, Temperature};
+
+    #[test]
+    fn counters_survive_a_watchdog_reset() {
+        let mut pairing = PairingManager::load(RamNvm::<64>::new(), 7);
+        pairing.pair(PairingRecord { sensor_id: 3, key: [1; 16] }).unwrap();
+        let mut counters = CounterStore::load(pairing.into_nvm());
+        assert_eq!(counters.saved(), PersistedCounters::default());
+
+        let mut handler: EmbeddedProtocolHandler<8> = EmbeddedProtocolHandler::new();
+        handler.init(100);
+        for t in 0..5 {
+            handler.add_reading(Temperature::new(21.0), 100 + t).unwrap();
+        }
+        counters.save(handler.counters(160)).unwrap();
+
+        // Reboot: RAM is gone, NVM is not
+        let nvm = counters.into_nvm();
+        let counters = CounterStore::load(nvm);
+        let mut handler: EmbeddedProtocolHandler<8> = EmbeddedProtocolHandler::new();
+        handler.init(0);
+        handler.restore(counters.saved(), ResetReason::Watchdog);
+        handler.add_reading(Temperature::new(21.5), 3).unwrap();
+
+        let status = handler.process_command(EmbeddedCommand::GetStatus, 10);
+        let EmbeddedResponse::Status { uptime_seconds, total_uptime_seconds, reading_count, reset_reason, .. } = status else {
+            panic!("Expected Status response");
+        };
+        assert_eq!((uptime_seconds, total_uptime_seconds), (10, 70));
+        assert_eq!(reading_count, 6);
+        assert_eq!(reset_reason, ResetReason::Watchdog);
+
+        // The counters record leaves the pairing intact
+        assert_eq!(PairingManager::load(counters.into_nvm(), 7).sensor_id(), Some(3));
+    }
+
+    #[test]
+    fn corrupt_record_starts_from_zero() {
+        let mut nvm = RamNvm::<64>::new();
+        let mut record = PersistedCounters { total_readings: 9, uptime_seconds: 9 }.encode();
+        record[5] ^= 0x40;
+        nvm.write(COUNTERS_RECORD_OFFSET, &record).unwrap();
+
+        assert_eq!(CounterStore::load(nvm).saved(), PersistedCounters::default());
+    }
+}
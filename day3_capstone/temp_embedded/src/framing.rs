@@ -0,0 +1,95 @@
+//! Framing of commands and responses on a byte stream such as a UART.
+//!
+//! Each postcard message is COBS encoded, which leaves no zero byte in it,
+//! and followed by a zero. A receiver that starts listening mid-frame, or
+//! loses bytes to line noise, drops what it has at the next zero and is in
+//! step again from the frame after.
+
+use heapless::Vec;
+
+use crate::EmbeddedError;
+
+pub const DELIMITER: u8 = 0;
+
+/// Longest frame, delimiter included, for a message of `len` bytes.
+pub const fn max_frame_len(len: usize) -> usize {
+    cobs::max_encoding_length(len) + 1
+}
+
+/// Frames `message`, which must fit `B` bytes once framed.
+pub fn encode<const B: usize>(message: &[u8]) -> Result<Vec<u8, B>, EmbeddedError> {
+    let mut frame: Vec<u8, B> = Vec::new();
+    frame.resize_default(B).map_err(|_| EmbeddedError::ResponseTooLarge)?;
+    let body = B.checked_sub(1).ok_or(EmbeddedError::ResponseTooLarge)?;
+    let len = cobs::try_encode(message, &mut frame[..body]).map_err(|_| EmbeddedError::ResponseTooLarge)?;
+    frame.truncate(len);
+    frame.push(DELIMITER).map_err(|_| EmbeddedError::ResponseTooLarge)?;
+    Ok(frame)
+}
+
+/// Collects received bytes into messages, for frames of up to `B` bytes.
+#[derive(Debug, Default)]
+pub struct FrameDecoder<const B: usize> {
+    frame: Vec<u8, B>,
+    overflowed: bool,
+}
+
+impl<const B: usize> FrameDecoder<B> {
+    pub const fn new() -> Self {
+        Self {
+            frame: Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    /// Takes the next byte and, at the end of a frame, returns its message.
+    /// A frame that is too long or not valid COBS gives a
+    /// `DeserializationError`; empty frames are skipped.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Vec<u8, B>, EmbeddedError>> {
+        if byte != DELIMITER {
+            self.overflowed |= self.frame.push(byte).is_err();
+            return None;
+        }
+        let mut frame = core::mem::take(&mut self.frame);
+        if core::mem::take(&mut self.overflowed) {
+            return Some(Err(EmbeddedError::DeserializationError));
+        }
+        if frame.is_empty() {
+            return None;
+        }
+        Some(match cobs::decode_in_place(&mut frame) {
+            Ok(len) => {
+                frame.truncate(len);
+                Ok(frame)
+            }
+            Err(_) => Err(EmbeddedError::DeserializationError),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmbeddedCommand;
+
+    #[test]
+    fn frames_survive_noise_and_overruns() {
+        let command = EmbeddedCommand::SetSampleRate(0);
+        let message: Vec<u8, 16> = postcard::to_vec(&command).unwrap();
+        assert!(message.contains(&0));
+        let frame = encode::<{ max_frame_len(16) }>(&message).unwrap();
+        assert_eq!(frame.iter().position(|&b| b == DELIMITER), Some(frame.len() - 1));
+
+        // Joining mid-frame costs that frame only
+        let mut decoder = FrameDecoder::<8>::new();
+        let stream = frame[2..].iter().chain(&[DELIMITER, DELIMITER]).chain(&frame);
+        let messages: Vec<_, 4> = stream.filter_map(|&b| decoder.push(b)).collect();
+        assert_eq!(messages.len(), 2);
+        assert_ne!(messages[0], Ok(message.iter().copied().collect()));
+        assert_eq!(postcard::from_bytes::<EmbeddedCommand>(messages[1].as_ref().unwrap()).unwrap(), command);
+
+        let long = [1u8; 12].iter().chain(&[DELIMITER]).filter_map(|&b| decoder.push(b)).next();
+        assert_eq!(long, Some(Err(EmbeddedError::DeserializationError)));
+        assert!(encode::<3>(&message).is_err());
+    }
+}
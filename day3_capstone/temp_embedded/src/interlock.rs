@@ -0,0 +1,685 @@
+//! Safety interlock for nodes driving real heaters.
+//!
+//! [`SafetyInterlock`] wraps the node's actuator. Once a reading exceeds the
+//! critical limit, or is NaN or infinite as from a failed sensor, it
+//! latches: the actuator is forced off and stays off, whatever the control
+//! loop asks for, until the host sends a `ResetInterlock` command
+//! authenticated with the pairing key. Every trip is kept as an [`Incident`].
+//!
+//! A watchdog or brown-out reset must not clear a trip, and reset tags are
+//! bound to the incident's sequence number, so neither may start over when
+//! the node reboots. [`InterlockStore`] keeps the latch, the last incident and
+//! the next sequence in non-volatile memory next to the counters record, which
+//! takes at least [`MIN_NVM_LEN`] bytes. The interlock owns its store and
+//! saves every trip, reset and new peak itself.
+//!
+//! A record that cannot be read, or does not check out, fails closed: the
+//! interlock boots tripped, and as the sequence is lost too, it refuses
+//! resets until the node is paired with a new key, see
+//! [`rekey`](SafetyInterlock::rekey). A factory-fresh node, whose memory is
+//! erased, is commissioned the same way.
+
+use core::hash::Hasher;
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+use siphasher::sip::SipHasher24;
+use temp_core::control::Actuator;
+
+use crate::counters::{self, COUNTERS_RECORD_OFFSET};
+use crate::pairing::{NvmStorage, PAIRING_KEY_LEN};
+use crate::{EmbeddedCommand, EmbeddedError, EmbeddedResponse, Temperature, TEMP_CRITICAL_TEMPERATURE};
+
+/// Number of incidents kept on the node; the oldest is dropped first.
+pub const MAX_INCIDENTS: usize = 8;
+/// Offset of the interlock record in non-volatile memory, after the counters record.
+pub const INTERLOCK_RECORD_OFFSET: u32 = 48;
+
+/// Bytes of non-volatile memory a node with an interlock needs.
+pub const MIN_NVM_LEN: usize = INTERLOCK_RECORD_OFFSET as usize + RECORD_LEN;
+
+const RECORD_MAGIC: [u8; 4] = *b"TPI1";
+const RECORD_LEN: usize = 4 + 4 + 1 + 4 + 4 + 4 + 4 + 4 + 1;
+const _: () = assert!(
+    INTERLOCK_RECORD_OFFSET as usize >= COUNTERS_RECORD_OFFSET as usize + counters::RECORD_LEN,
+    "overlaps the counters record"
+);
+const FLAG_TRIPPED: u8 = 1;
+const FLAG_INCIDENT: u8 = 2;
+const FLAG_CLEARED: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Incident {
+    /// Increases with every trip; the reset tag is bound to it, so a captured
+    /// `ResetInterlock` command cannot be replayed against a later incident.
+    pub sequence: u32,
+    pub tripped_at: u32,
+    pub trip_temperature: Temperature,
+    pub peak_temperature: Temperature,
+    pub cleared_at: Option<u32>,
+}
+
+/// What of the interlock has to survive a reset. Tripped without an
+/// incident means the saved state was lost.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterlockState {
+    /// Sequence number the next incident gets.
+    pub next_sequence: u32,
+    pub tripped: bool,
+    pub last_incident: Option<Incident>,
+}
+
+impl Default for InterlockState {
+    /// A node that never tripped.
+    fn default() -> Self {
+        Self {
+            next_sequence: 1,
+            tripped: false,
+            last_incident: None,
+        }
+    }
+}
+
+impl InterlockState {
+    /// What an unreadable record stands for: tripped, with no incident a
+    /// reset could be bound to.
+    const LOST: Self = Self {
+        next_sequence: 0,
+        tripped: true,
+        last_incident: None,
+    };
+
+    fn encode(&self) -> [u8; RECORD_LEN] {
+        let mut buf = [0u8; RECORD_LEN];
+        buf[..4].copy_from_slice(&RECORD_MAGIC);
+        buf[4..8].copy_from_slice(&self.next_sequence.to_le_bytes());
+        let mut flags = if self.tripped { FLAG_TRIPPED } else { 0 };
+        if let Some(incident) = &self.last_incident {
+            flags |= FLAG_INCIDENT;
+            buf[9..13].copy_from_slice(&incident.sequence.to_le_bytes());
+            buf[13..17].copy_from_slice(&incident.tripped_at.to_le_bytes());
+            buf[17..21].copy_from_slice(&incident.trip_temperature.celsius.to_le_bytes());
+            buf[21..25].copy_from_slice(&incident.peak_temperature.celsius.to_le_bytes());
+            if let Some(cleared_at) = incident.cleared_at {
+                flags |= FLAG_CLEARED;
+                buf[25..29].copy_from_slice(&cleared_at.to_le_bytes());
+            }
+        }
+        buf[8] = flags;
+        buf[RECORD_LEN - 1] = checksum(&buf[..RECORD_LEN - 1]);
+        buf
+    }
+
+    fn decode(buf: &[u8; RECORD_LEN]) -> Option<Self> {
+        if buf[..4] != RECORD_MAGIC || buf[RECORD_LEN - 1] != checksum(&buf[..RECORD_LEN - 1]) {
+            return None;
+        }
+
+        let word = |at: usize| [buf[at], buf[at + 1], buf[at + 2], buf[at + 3]];
+        let flags = buf[8];
+        let last_incident = (flags & FLAG_INCIDENT != 0).then(|| Incident {
+            sequence: u32::from_le_bytes(word(9)),
+            tripped_at: u32::from_le_bytes(word(13)),
+            trip_temperature: Temperature::new(f32::from_le_bytes(word(17))),
+            peak_temperature: Temperature::new(f32::from_le_bytes(word(21))),
+            cleared_at: (flags & FLAG_CLEARED != 0).then(|| u32::from_le_bytes(word(25))),
+        });
+        Some(Self {
+            next_sequence: u32::from_le_bytes(word(4)),
+            tripped: flags & FLAG_TRIPPED != 0,
+            last_incident,
+        })
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |acc, b| acc.rotate_left(1) ^ b)
+}
+
+pub struct InterlockStore<S: NvmStorage> {
+    nvm: S,
+    saved: InterlockState,
+}
+
+impl<S: NvmStorage> InterlockStore<S> {
+    /// Reads the state saved before the reset. Without a valid record, be it
+    /// erased, torn by the reset or unreadable, the state is lost and the
+    /// interlock built from it boots tripped.
+    pub fn load(mut nvm: S) -> Self {
+        const {
+            assert!(
+                match S::LEN {
+                    Some(len) => len >= MIN_NVM_LEN,
+                    None => true,
+                },
+                "NVM too small for the interlock record"
+            )
+        }
+        let mut buf = [0u8; RECORD_LEN];
+        let saved = match nvm.read(INTERLOCK_RECORD_OFFSET, &mut buf) {
+            Ok(()) => InterlockState::decode(&buf).unwrap_or(InterlockState::LOST),
+            Err(_) => InterlockState::LOST,
+        };
+
+        Self { nvm, saved }
+    }
+
+    /// The state as last saved.
+    pub fn saved(&self) -> InterlockState {
+        self.saved
+    }
+
+    /// Saves `state`, skipping the write if nothing changed. Only trips,
+    /// resets, rekeying and a rising peak during an incident change it.
+    pub(crate) fn save(&mut self, state: InterlockState) -> Result<(), EmbeddedError> {
+        if state == self.saved {
+            return Ok(());
+        }
+        self.nvm
+            .write(INTERLOCK_RECORD_OFFSET, &state.encode())
+            .map_err(|_| EmbeddedError::StorageFailed)?;
+        self.saved = state;
+        Ok(())
+    }
+
+    pub fn into_nvm(self) -> S {
+        self.nvm
+    }
+}
+
+/// Authentication tag the host sends to clear the incident with `sequence`.
+pub fn reset_tag(key: &[u8; PAIRING_KEY_LEN], device_uid: u64, sequence: u32) -> u64 {
+    let mut hasher = SipHasher24::new_with_key(key);
+    hasher.write(b"interlock-reset");
+    hasher.write(&device_uid.to_le_bytes());
+    hasher.write(&sequence.to_le_bytes());
+    hasher.finish()
+}
+
+pub struct SafetyInterlock<A: Actuator, S: NvmStorage> {
+    actuator: A,
+    store: InterlockStore<S>,
+    device_uid: u64,
+    key: [u8; PAIRING_KEY_LEN],
+    limit: Temperature,
+    tripped: bool,
+    last_temperature: Option<Temperature>,
+    next_sequence: u32,
+    incidents: Vec<Incident, MAX_INCIDENTS>,
+}
+
+impl<A: Actuator, S: NvmStorage> SafetyInterlock<A, S> {
+    /// Creates an interlock tripping above `TEMP_CRITICAL`, accepting resets
+    /// signed with the node's pairing key, from the state in `store`: a trip
+    /// latched before the reset stays latched, and so does a lost state.
+    pub fn new(actuator: A, device_uid: u64, key: [u8; PAIRING_KEY_LEN], store: InterlockStore<S>) -> Self {
+        let saved = store.saved();
+        let mut incidents = Vec::new();
+        if let Some(incident) = saved.last_incident {
+            // Cannot fail: the log is empty
+            let _ = incidents.push(incident);
+        }
+        Self {
+            actuator,
+            store,
+            device_uid,
+            key,
+            limit: TEMP_CRITICAL_TEMPERATURE,
+            tripped: saved.tripped,
+            last_temperature: None,
+            next_sequence: saved.next_sequence,
+            incidents,
+        }
+    }
+
+    pub fn with_limit(mut self, limit: Temperature) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Feeds a reading to the interlock; returns `true` while it is tripped.
+    /// A reading that is not a temperature at all, such as the NaN or
+    /// infinity of a shorted or open sensor, trips it like one above the
+    /// limit. A trip switches the actuator off before it is saved.
+    pub fn check(&mut self, temperature: Temperature, timestamp: u32) -> Result<bool, EmbeddedError> {
+        self.last_temperature = Some(temperature);
+
+        if self.tripped {
+            if let Some(incident) = self.incidents.last_mut() {
+                if temperature.celsius > incident.peak_temperature.celsius {
+                    incident.peak_temperature = temperature;
+                }
+            }
+        } else if self.exceeds_limit(temperature) {
+            self.trip(temperature, timestamp);
+        }
+
+        if self.tripped {
+            self.actuator.set_output(0.0).map_err(|_| EmbeddedError::ActuatorFailed)?;
+        }
+        // A failed save is tried again with the next reading
+        self.store.save(self.state())?;
+        Ok(self.tripped)
+    }
+
+    /// Clears a tripped interlock once that is saved. Refused while the
+    /// temperature is above the limit, or not known to be below it because
+    /// there was no reading since boot, when the tag does not match the
+    /// active incident, and while a lost state has not been rekeyed.
+    pub fn reset(&mut self, sequence: u32, tag: u64, timestamp: u32) -> Result<(), EmbeddedError> {
+        if !self.tripped {
+            return Err(EmbeddedError::InvalidCommand);
+        }
+        let Some(incident) = self.incidents.last().copied() else {
+            return Err(EmbeddedError::InterlockTripped);
+        };
+
+        if sequence != incident.sequence || tag != reset_tag(&self.key, self.device_uid, sequence) {
+            return Err(EmbeddedError::AuthenticationFailed);
+        }
+        if self.last_temperature.is_none_or(|t| self.exceeds_limit(t)) {
+            return Err(EmbeddedError::InterlockTripped);
+        }
+
+        let cleared = Incident { cleared_at: Some(timestamp), ..incident };
+        self.store.save(InterlockState { tripped: false, last_incident: Some(cleared), ..self.state() })?;
+        if let Some(last) = self.incidents.last_mut() {
+            *last = cleared;
+        }
+        self.tripped = false;
+        Ok(())
+    }
+
+    /// Takes the key of a new pairing. Reset tags are bound to the key, so
+    /// once the saved state was lost, a new key is what makes the interlock
+    /// resettable again: it opens incident 1 under the new key, which the
+    /// host clears with [`reset`](Self::reset) as usual. Refused for the key
+    /// in use, whose tags may have been captured.
+    pub fn rekey(&mut self, key: [u8; PAIRING_KEY_LEN], timestamp: u32) -> Result<(), EmbeddedError> {
+        if key == self.key {
+            return Err(EmbeddedError::InvalidCommand);
+        }
+        if self.tripped && self.incidents.is_empty() {
+            // Stands for whatever trip was lost with the record
+            let recovered = Incident {
+                sequence: 1,
+                tripped_at: timestamp,
+                trip_temperature: self.limit,
+                peak_temperature: self.last_temperature.unwrap_or(self.limit),
+                cleared_at: None,
+            };
+            self.store.save(InterlockState { next_sequence: 2, tripped: true, last_incident: Some(recovered) })?;
+            // Cannot fail: the log is empty
+            let _ = self.incidents.push(recovered);
+            self.next_sequence = 2;
+        }
+        self.key = key;
+        Ok(())
+    }
+
+    /// Handles the interlock commands; returns `None` for everything else so
+    /// the caller can pass the command on to the protocol handler.
+    pub fn handle_command(&mut self, command: &EmbeddedCommand, current_time: u32) -> Option<EmbeddedResponse> {
+        let response = match command {
+            EmbeddedCommand::ResetInterlock { sequence, tag } => match self.reset(*sequence, *tag, current_time) {
+                Ok(()) => EmbeddedResponse::InterlockReset { sequence: *sequence },
+                Err(e) => EmbeddedResponse::Error(e.error_code()),
+            },
+            EmbeddedCommand::GetLastIncident => EmbeddedResponse::Incident(self.last_incident().copied()),
+            _ => return None,
+        };
+        Some(response)
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.tripped
+    }
+
+    pub fn limit(&self) -> Temperature {
+        self.limit
+    }
+
+    /// Sequence number the next incident gets; changes with every trip.
+    pub fn next_sequence(&self) -> u32 {
+        self.next_sequence
+    }
+
+    /// What is saved for the next boot, see [`InterlockStore`].
+    pub fn state(&self) -> InterlockState {
+        InterlockState {
+            next_sequence: self.next_sequence,
+            tripped: self.tripped,
+            last_incident: self.last_incident().copied(),
+        }
+    }
+
+    pub fn last_incident(&self) -> Option<&Incident> {
+        self.incidents.last()
+    }
+
+    /// Recorded incidents, oldest first.
+    pub fn incidents(&self) -> &[Incident] {
+        &self.incidents
+    }
+
+    pub fn actuator(&self) -> &A {
+        &self.actuator
+    }
+
+    pub fn store(&self) -> &InterlockStore<S> {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> A {
+        self.actuator
+    }
+
+    /// The actuator and the store, e.g. to rebuild the interlock after a reboot.
+    pub fn into_parts(self) -> (A, InterlockStore<S>) {
+        (self.actuator, self.store)
+    }
+
+    fn exceeds_limit(&self, temperature: Temperature) -> bool {
+        temperature.validate().is_err() || temperature.celsius > self.limit.celsius
+    }
+
+    fn trip(&mut self, temperature: Temperature, timestamp: u32) {
+        if self.incidents.is_full() {
+            self.incidents.remove(0);
+        }
+        // Cannot fail: a slot was freed above
+        let _ = self.incidents.push(Incident {
+            sequence: self.next_sequence,
+            tripped_at: timestamp,
+            trip_temperature: temperature,
+            peak_temperature: temperature,
+            cleared_at: None,
+        });
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        self.tripped = true;
+    }
+}
+
+/// Stands in for the actuator of a node that only measures: its interlock
+/// still latches and records incidents, with nothing to switch off.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NoActuator;
+
+impl Actuator for NoActuator {
+    type Error = core::convert::Infallible;
+
+    fn set_output(&mut self, _level: f32) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn output(&self) -> f32 {
+        0.0
+    }
+}
+
+/// Lets a `ControlLoop` drive the actuator through the interlock, which
+/// overrides every request with "off" while tripped.
+impl<A: Actuator, S: NvmStorage> Actuator for SafetyInterlock<A, S> {
+    type Error = A::Error;
+
+    fn set_output(&mut self, level: f32) -> Result<(), Self::Error> {
+        let level = if self.tripped { 0.0 } else { level };
+        self.actuator.set_output(level)
+    }
+
+    fn output(&self) -> f32 {
+        self.actuator.output()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pairing::RamNvm;
+    use temp_core::control::{BangBangController, ControlLoop, ControlMode};
+
+    const UID: u64 = 0xC3_0000_1234;
+    const KEY: [u8; PAIRING_KEY_LEN] = [9; PAIRING_KEY_LEN];
+
+    type Nvm = RamNvm<128>;
+
+    /// The store of a node that never tripped.
+    fn fresh() -> InterlockStore<Nvm> {
+        let mut store = InterlockStore::load(Nvm::new());
+        store.save(InterlockState::default()).unwrap();
+        store
+    }
+
+    /// Storage whose every access fails.
+    struct BrokenNvm;
+
+    impl NvmStorage for BrokenNvm {
+        type Error = ();
+
+        fn read(&mut self, _offset: u32, _buf: &mut [u8]) -> Result<(), ()> {
+            Err(())
+        }
+
+        fn write(&mut self, _offset: u32, _data: &[u8]) -> Result<(), ()> {
+            Err(())
+        }
+    }
+
+    struct Heater {
+        level: f32,
+    }
+
+    impl Actuator for Heater {
+        type Error = ();
+
+        fn set_output(&mut self, level: f32) -> Result<(), ()> {
+            self.level = level;
+            Ok(())
+        }
+
+        fn output(&self) -> f32 {
+            self.level
+        }
+    }
+
+    #[test]
+    fn trips_and_forces_actuator_off() {
+        let controller = BangBangController::new(Temperature::new(60.0), 0.5, ControlMode::Heating);
+        let interlock = SafetyInterlock::new(Heater { level: 0.0 }, UID, KEY, fresh());
+        let mut control = ControlLoop::new(controller, interlock);
+
+        control.step(Temperature::new(45.0), 1.0).unwrap();
+        assert_eq!(control.actuator().output(), 1.0);
+
+        assert!(control.actuator_mut().check(Temperature::new(51.0), 100).unwrap());
+        assert_eq!(control.actuator().output(), 0.0);
+
+        // The controller still wants heat, but the latch holds even after cooling down
+        assert!(control.actuator_mut().check(Temperature::new(40.0), 110).unwrap());
+        control.step(Temperature::new(40.0), 1.0).unwrap();
+        assert_eq!(control.actuator().output(), 0.0);
+
+        let incident = control.actuator().last_incident().unwrap();
+        assert_eq!(incident.sequence, 1);
+        assert_eq!(incident.tripped_at, 100);
+        assert_eq!(incident.cleared_at, None);
+    }
+
+    #[test]
+    fn reset_requires_valid_tag() {
+        let mut interlock = SafetyInterlock::new(Heater { level: 1.0 }, UID, KEY, fresh());
+        interlock.check(Temperature::new(55.0), 10).unwrap();
+        interlock.check(Temperature::new(58.0), 11).unwrap();
+
+        // Still too hot
+        let tag = reset_tag(&KEY, UID, 1);
+        assert_eq!(interlock.reset(1, tag, 12), Err(EmbeddedError::InterlockTripped));
+
+        interlock.check(Temperature::new(30.0), 20).unwrap();
+        assert_eq!(interlock.reset(1, tag ^ 1, 21), Err(EmbeddedError::AuthenticationFailed));
+        assert_eq!(
+            interlock.reset(1, reset_tag(&[0; PAIRING_KEY_LEN], UID, 1), 21),
+            Err(EmbeddedError::AuthenticationFailed)
+        );
+
+        let response = interlock.handle_command(&EmbeddedCommand::ResetInterlock { sequence: 1, tag }, 22);
+        assert_eq!(response, Some(EmbeddedResponse::InterlockReset { sequence: 1 }));
+        assert!(!interlock.is_tripped());
+
+        let incident = interlock.last_incident().unwrap();
+        assert_eq!(incident.peak_temperature.celsius, 58.0);
+        assert_eq!(incident.cleared_at, Some(22));
+    }
+
+    #[test]
+    fn old_tags_cannot_be_replayed() {
+        let mut interlock = SafetyInterlock::new(Heater { level: 0.0 }, UID, KEY, fresh());
+        let first_tag = reset_tag(&KEY, UID, 1);
+
+        interlock.check(Temperature::new(60.0), 1).unwrap();
+        interlock.check(Temperature::new(20.0), 2).unwrap();
+        interlock.reset(1, first_tag, 3).unwrap();
+
+        interlock.check(Temperature::new(60.0), 4).unwrap();
+        interlock.check(Temperature::new(20.0), 5).unwrap();
+        assert_eq!(interlock.reset(1, first_tag, 6), Err(EmbeddedError::AuthenticationFailed));
+        assert!(interlock.is_tripped());
+        assert_eq!(interlock.incidents().len(), 2);
+    }
+
+    #[test]
+    fn tags_cannot_be_replayed_after_a_reboot() {
+        let mut interlock = SafetyInterlock::new(Heater { level: 0.0 }, UID, KEY, fresh());
+        let first_tag = reset_tag(&KEY, UID, 1);
+        interlock.check(Temperature::new(60.0), 1).unwrap();
+        interlock.check(Temperature::new(20.0), 2).unwrap();
+        interlock.reset(1, first_tag, 3).unwrap();
+
+        let (heater, store) = interlock.into_parts();
+        let store = InterlockStore::load(store.into_nvm());
+        assert_eq!(store.saved().next_sequence, 2);
+        let mut interlock = SafetyInterlock::new(heater, UID, KEY, store);
+        interlock.check(Temperature::new(60.0), 1).unwrap();
+        interlock.check(Temperature::new(20.0), 2).unwrap();
+        assert_eq!(interlock.reset(1, first_tag, 3), Err(EmbeddedError::AuthenticationFailed));
+        assert!(interlock.is_tripped());
+        interlock.reset(2, reset_tag(&KEY, UID, 2), 4).unwrap();
+    }
+
+    #[test]
+    fn trip_survives_a_watchdog_reset() {
+        let mut interlock = SafetyInterlock::new(Heater { level: 1.0 }, UID, KEY, fresh());
+        interlock.check(Temperature::new(55.0), 10).unwrap();
+        interlock.check(Temperature::new(57.0), 11).unwrap();
+
+        // Reboot: RAM is gone, NVM is not, and the heater comes up on
+        let (_, store) = interlock.into_parts();
+        let store = InterlockStore::load(store.into_nvm());
+        let mut interlock = SafetyInterlock::new(Heater { level: 1.0 }, UID, KEY, store);
+        assert!(interlock.is_tripped());
+        let incident = *interlock.last_incident().unwrap();
+        assert_eq!((incident.sequence, incident.tripped_at), (1, 10));
+        assert_eq!(incident.peak_temperature.celsius, 57.0);
+        assert_eq!(incident.cleared_at, None);
+
+        // Cooled down meanwhile, but the control loop cannot turn the heater back on
+        assert!(interlock.check(Temperature::new(30.0), 2).unwrap());
+        interlock.set_output(1.0).unwrap();
+        assert_eq!(interlock.output(), 0.0);
+
+        interlock.reset(1, reset_tag(&KEY, UID, 1), 3).unwrap();
+        assert_eq!(interlock.state().next_sequence, 2);
+        let (_, store) = interlock.into_parts();
+        assert!(!InterlockStore::load(store.into_nvm()).saved().tripped);
+    }
+
+    #[test]
+    fn faulty_sensor_trips() {
+        for reading in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let mut interlock = SafetyInterlock::new(Heater { level: 1.0 }, UID, KEY, fresh());
+            assert!(interlock.check(Temperature::new(reading), 1).unwrap());
+            assert_eq!(interlock.output(), 0.0);
+
+            // Still faulty, so no evidence it cooled down
+            interlock.check(Temperature::new(f32::NAN), 2).unwrap();
+            assert_eq!(interlock.reset(1, reset_tag(&KEY, UID, 1), 3), Err(EmbeddedError::InterlockTripped));
+        }
+    }
+
+    #[test]
+    fn reset_waits_for_a_reading_after_a_reboot() {
+        let mut interlock = SafetyInterlock::new(Heater { level: 0.0 }, UID, KEY, fresh());
+        interlock.check(Temperature::new(60.0), 1).unwrap();
+        let (heater, store) = interlock.into_parts();
+        let mut interlock = SafetyInterlock::new(heater, UID, KEY, InterlockStore::load(store.into_nvm()));
+
+        // Nothing says it cooled down yet
+        let tag = reset_tag(&KEY, UID, 1);
+        assert_eq!(interlock.reset(1, tag, 2), Err(EmbeddedError::InterlockTripped));
+        assert!(interlock.is_tripped());
+
+        interlock.check(Temperature::new(25.0), 3).unwrap();
+        interlock.reset(1, tag, 4).unwrap();
+    }
+
+    #[test]
+    fn lost_record_latches_until_rekeyed() {
+        // Erased, torn by a reset, or not readable at all
+        let mut torn = fresh().into_nvm();
+        let mut record = InterlockState { tripped: true, ..InterlockState::default() }.encode();
+        record[8] ^= FLAG_INCIDENT;
+        torn.write(INTERLOCK_RECORD_OFFSET, &record).unwrap();
+        assert_eq!(InterlockStore::load(torn).saved(), InterlockState::LOST);
+        assert_eq!(InterlockStore::load(BrokenNvm).saved(), InterlockState::LOST);
+
+        let mut interlock = SafetyInterlock::new(Heater { level: 1.0 }, UID, KEY, InterlockStore::load(Nvm::new()));
+        assert!(interlock.check(Temperature::new(20.0), 1).unwrap());
+        assert_eq!(interlock.output(), 0.0);
+
+        // A tag captured before the record was lost is refused, and so is
+        // rekeying to the key it was made with
+        assert_eq!(interlock.reset(1, reset_tag(&KEY, UID, 1), 2), Err(EmbeddedError::InterlockTripped));
+        assert_eq!(interlock.rekey(KEY, 2), Err(EmbeddedError::InvalidCommand));
+
+        let new_key = [4; PAIRING_KEY_LEN];
+        interlock.rekey(new_key, 3).unwrap();
+        assert_eq!(interlock.last_incident().map(|incident| incident.sequence), Some(1));
+        assert_eq!(interlock.reset(1, reset_tag(&KEY, UID, 1), 4), Err(EmbeddedError::AuthenticationFailed));
+        interlock.reset(1, reset_tag(&new_key, UID, 1), 4).unwrap();
+        interlock.set_output(1.0).unwrap();
+        assert_eq!(interlock.output(), 1.0);
+
+        let (_, store) = interlock.into_parts();
+        let saved = InterlockStore::load(store.into_nvm()).saved();
+        assert_eq!((saved.tripped, saved.next_sequence), (false, 2));
+    }
+
+    #[test]
+    fn unsaved_rekey_keeps_the_latch() {
+        let mut interlock = SafetyInterlock::new(Heater { level: 1.0 }, UID, KEY, InterlockStore::load(BrokenNvm));
+        assert_eq!(interlock.rekey([4; PAIRING_KEY_LEN], 1), Err(EmbeddedError::StorageFailed));
+        assert_eq!(interlock.last_incident(), None);
+        assert_eq!(interlock.check(Temperature::new(20.0), 2), Ok(true));
+        assert_eq!(interlock.output(), 0.0);
+    }
+
+    #[test]
+    fn incident_log_is_bounded() {
+        let mut interlock = SafetyInterlock::new(Heater { level: 0.0 }, UID, KEY, fresh())
+            .with_limit(Temperature::new(30.0));
+
+        for i in 0..(MAX_INCIDENTS as u32 + 2) {
+            interlock.check(Temperature::new(35.0), i * 10).unwrap();
+            interlock.check(Temperature::new(20.0), i * 10 + 1).unwrap();
+            let sequence = i + 1;
+            interlock.reset(sequence, reset_tag(&KEY, UID, sequence), i * 10 + 2).unwrap();
+        }
+
+        assert_eq!(interlock.incidents().len(), MAX_INCIDENTS);
+        assert_eq!(interlock.incidents()[0].sequence, 3);
+        assert_eq!(
+            interlock.handle_command(&EmbeddedCommand::GetLastIncident, 0),
+            Some(EmbeddedResponse::Incident(interlock.last_incident().copied()))
+        );
+    }
+}
@@ -1,21 +1,97 @@
 #![no_std]
 
-use heapless::{Vec, String};
+use heapless::{Deque, Vec, String};
 use serde::{Deserialize, Serialize};
 
 // Re-export core temperature types
-pub use temp_core::Temperature;
+pub use temp_core::{SensorId, Temperature, TemperatureSensor};
+pub use temp_core::adc::AdcConfig;
+use temp_core::control::{Actuator, SETPOINT_MAX_CELSIUS, SETPOINT_MIN_CELSIUS};
+pub use temp_core::gradient::{GradientThreshold, GradientViolation, TemperatureGradient};
+pub use temp_core::retry::{RetryPolicy, RetrySensor};
+pub use temp_core::threshold::{Alarm, AlarmState, ThresholdConfig};
+
+pub mod burst;
+pub mod counters;
+pub mod framing;
+pub mod interlock;
+pub mod pairing;
+pub mod rtc;
+
+use burst::BurstCapture;
+use counters::{PersistedCounters, ResetReason};
+use interlock::{InterlockState, NoActuator, SafetyInterlock};
+use pairing::{NvmStorage, RamNvm};
+use rtc::{RtcProvider, TimeSource};
 
 // Fixed-capacity temperature reading for embedded systems
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct EmbeddedTemperatureReading {
     pub temperature: Temperature,
-    pub timestamp: u32, // Using u32 for embedded systems (seconds since boot)
+    pub timestamp: u32, // Using u32 for embedded systems (seconds since boot, or Unix time from an RTC)
+    pub source: TimeSource,
 }
 
 impl EmbeddedTemperatureReading {
     pub fn new(temperature: Temperature, timestamp: u32) -> Self {
-        Self { temperature, timestamp }
+        Self { temperature, timestamp, source: TimeSource::Uptime }
+    }
+
+    pub fn with_source(mut self, source: TimeSource) -> Self {
+        self.source = source;
+        self
+    }
+}
+
+// Min, max and sum of the buffered readings, updated as they come and go so
+// stats don't rescan the buffer. The deques hold `(sequence, celsius)` of the
+// readings that can still become the min or max once older ones are evicted.
+struct RunningStats<const N: usize> {
+    min: Deque<(u32, f32), N>,
+    max: Deque<(u32, f32), N>,
+    sum: f64, // Exact for f32 temperatures, so it doesn't drift
+    next: u32,
+    oldest: u32,
+}
+
+impl<const N: usize> RunningStats<N> {
+    const fn new() -> Self {
+        Self {
+            min: Deque::new(),
+            max: Deque::new(),
+            sum: 0.0,
+            next: 0,
+            oldest: 0,
+        }
+    }
+
+    // Callers evict first, so the deques never hold more than N entries
+    fn push(&mut self, celsius: f32) {
+        let sequence = self.next;
+        self.next = self.next.wrapping_add(1);
+        self.sum += celsius as f64;
+
+        while self.min.back().is_some_and(|&(_, back)| back >= celsius) {
+            self.min.pop_back();
+        }
+        self.min.push_back((sequence, celsius)).ok();
+        while self.max.back().is_some_and(|&(_, back)| back <= celsius) {
+            self.max.pop_back();
+        }
+        self.max.push_back((sequence, celsius)).ok();
+    }
+
+    fn evict(&mut self, celsius: f32) {
+        let sequence = self.oldest;
+        self.oldest = self.oldest.wrapping_add(1);
+        self.sum -= celsius as f64;
+
+        if self.min.front().is_some_and(|&(front, _)| front == sequence) {
+            self.min.pop_front();
+        }
+        if self.max.front().is_some_and(|&(front, _)| front == sequence) {
+            self.max.pop_front();
+        }
     }
 }
 
@@ -23,6 +99,10 @@ impl EmbeddedTemperatureReading {
 pub struct EmbeddedTemperatureStore<const N: usize> {
     readings: Vec<EmbeddedTemperatureReading, N>,
     total_readings: u32,
+    running: RunningStats<N>,
+    epoch: u32,
+    // Buffered readings from before the epoch, at the front and left out of `running`
+    pre_epoch: usize,
 }
 
 impl<const N: usize> EmbeddedTemperatureStore<N> {
@@ -30,18 +110,27 @@ impl<const N: usize> EmbeddedTemperatureStore<N> {
         Self {
             readings: Vec::new(),
             total_readings: 0,
+            running: RunningStats::new(),
+            epoch: 0,
+            pre_epoch: 0,
         }
     }
 
-    pub fn add_reading(&mut self, reading: EmbeddedTemperatureReading) -> Result<(), &'static str> {
+    pub fn add_reading(&mut self, reading: EmbeddedTemperatureReading) -> Result<(), EmbeddedError> {
         self.total_readings += 1;
 
         if self.readings.len() >= N {
             // Circular buffer behavior - remove oldest reading
-            self.readings.remove(0);
+            let evicted = self.readings.remove(0);
+            if self.pre_epoch > 0 {
+                self.pre_epoch -= 1;
+            } else {
+                self.running.evict(evicted.temperature.celsius);
+            }
         }
 
-        self.readings.push(reading).map_err(|_| "Storage full")?;
+        self.readings.push(reading).map_err(|_| EmbeddedError::BufferFull)?;
+        self.running.push(reading.temperature.celsius);
         Ok(())
     }
 
@@ -49,43 +138,45 @@ impl<const N: usize> EmbeddedTemperatureStore<N> {
         self.readings.last().copied()
     }
 
+    // O(1): kept up to date by add_reading instead of scanning the buffer.
+    // Covers the readings since the epoch started.
     pub fn get_stats(&self) -> EmbeddedTemperatureStats {
-        if self.readings.is_empty() {
-            return EmbeddedTemperatureStats {
+        let count = self.readings.len() - self.pre_epoch;
+        match (self.running.min.front(), self.running.max.front()) {
+            (Some(&(_, min)), Some(&(_, max))) => EmbeddedTemperatureStats {
+                min: Temperature::new(min),
+                max: Temperature::new(max),
+                average: Temperature::new((self.running.sum / count as f64) as f32),
+                count,
+                epoch: self.epoch,
+            },
+            _ => EmbeddedTemperatureStats {
                 min: Temperature::new(0.0),
                 max: Temperature::new(0.0),
                 average: Temperature::new(0.0),
                 count: 0,
-            };
-        }
-
-        let mut min_temp = self.readings[0].temperature.celsius;
-        let mut max_temp = self.readings[0].temperature.celsius;
-        let mut sum = 0.0;
-
-        for reading in &self.readings {
-            let temp = reading.temperature.celsius;
-            if temp < min_temp {
-                min_temp = temp;
-            }
-            if temp > max_temp {
-                max_temp = temp;
-            }
-            sum += temp;
+                epoch: self.epoch,
+            },
         }
+    }
 
-        let average = sum / self.readings.len() as f32;
+    /// Restarts the stats, e.g. at the start of a production batch, and
+    /// returns the new epoch's id. The buffered readings are kept.
+    pub fn start_epoch(&mut self) -> u32 {
+        self.epoch = self.epoch.wrapping_add(1);
+        self.pre_epoch = self.readings.len();
+        self.running = RunningStats::new();
+        self.epoch
+    }
 
-        EmbeddedTemperatureStats {
-            min: Temperature::new(min_temp),
-            max: Temperature::new(max_temp),
-            average: Temperature::new(average),
-            count: self.readings.len(),
-        }
+    pub fn epoch(&self) -> u32 {
+        self.epoch
     }
 
     pub fn clear(&mut self) {
         self.readings.clear();
+        self.running = RunningStats::new();
+        self.pre_epoch = 0;
     }
 
     pub const fn capacity(&self) -> usize {
@@ -108,11 +199,22 @@ impl<const N: usize> EmbeddedTemperatureStore<N> {
         self.total_readings
     }
 
+    /// Continues counting from a total saved before a reset.
+    pub fn restore_total_readings(&mut self, total_readings: u32) {
+        self.total_readings = total_readings;
+    }
+
     pub fn get_readings(&self) -> &[EmbeddedTemperatureReading] {
         &self.readings
     }
 }
 
+impl<const N: usize> Default for EmbeddedTemperatureStore<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // Statistics without heap allocation
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct EmbeddedTemperatureStats {
@@ -120,6 +222,7 @@ pub struct EmbeddedTemperatureStats {
     pub max: Temperature,
     pub average: Temperature,
     pub count: usize,
+    pub epoch: u32, // Counted from 0 at boot; see EmbeddedCommand::StartEpoch
 }
 
 // Const configuration functions for zero-cost configuration
@@ -133,21 +236,38 @@ pub const fn validate_buffer_size(size: usize) -> usize {
     size
 }
 
-pub const fn celsius_to_adc_value(celsius: f32) -> u16 {
-    // Simple linear conversion: 10mV/°C, 3.3V reference, 12-bit ADC
-    let voltage = celsius * 0.01; // 10mV/°C
-    let adc_value = (voltage / 3.3) * 4095.0;
-    adc_value as u16
-}
-
 // Configuration constants computed at compile time
 pub const SYSTEM_CLOCK_HZ: u32 = 16_000_000; // 16 MHz
 pub const SAMPLE_RATE_HZ: u32 = 10; // 10 Hz sampling
+pub const SAMPLE_BATCH: usize = 8; // Most samples drained from a sensor FIFO at once
 pub const TIMER_DIVISOR: u32 = calculate_sample_rate(SAMPLE_RATE_HZ, SYSTEM_CLOCK_HZ);
 pub const READING_BUFFER_SIZE: usize = validate_buffer_size(64);
-pub const TEMP_THRESHOLD_LOW: u16 = celsius_to_adc_value(5.0);   // 5°C
-pub const TEMP_THRESHOLD_HIGH: u16 = celsius_to_adc_value(35.0); // 35°C
-pub const TEMP_CRITICAL: u16 = celsius_to_adc_value(50.0);       // 50°C
+/// Sensor and ADC the firmware is built for; the thresholds below are in its counts.
+pub const ADC_CONFIG: AdcConfig = AdcConfig::DEFAULT;
+/// Alarm limits the firmware ships with, in the type the host configures them with.
+pub const DEFAULT_THRESHOLDS: ThresholdConfig = match ThresholdConfig::between(Temperature::new(5.0), Temperature::new(35.0)) {
+    Ok(thresholds) => thresholds,
+    Err(_) => panic!("Invalid default thresholds"),
+};
+pub const TEMP_THRESHOLD_LOW: u16 = DEFAULT_THRESHOLDS.min_temperature().to_adc(&ADC_CONFIG);
+pub const TEMP_THRESHOLD_HIGH: u16 = DEFAULT_THRESHOLDS.max_temperature().to_adc(&ADC_CONFIG);
+pub const TEMP_CRITICAL_TEMPERATURE: Temperature = Temperature::new(50.0);
+pub const TEMP_CRITICAL: u16 = TEMP_CRITICAL_TEMPERATURE.to_adc(&ADC_CONFIG);
+/// Seconds the rate of change is averaged over; a reading per second
+/// jitters by a count or two of the ADC.
+pub const GRADIENT_SMOOTHING_SECONDS: f32 = 30.0;
+/// Response frame size used unless the handler is given its transport's MTU.
+pub const DEFAULT_MTU: usize = 256;
+/// Most readings in a `History` page; fewer fit a small MTU.
+pub const HISTORY_PAGE_LEN: usize = 32;
+
+// Worst-case postcard sizes: variant and Option tags take a byte, f32 four,
+// and varints one byte per 7 bits (u16 3, u32 5, u64 and usize 10)
+const MAX_READING_LEN: usize = 4 + 5 + 1;
+const HISTORY_HEADER_LEN: usize = 1 + 3 + 1 + 1; // Variant, start, page length, truncated
+const BURST_HEADER_LEN: usize = 1 + 5 + 5 + 3 + 1 + 3 + 1; // Variant, rate, started_at, start, page length, remaining, truncated
+/// Largest encoding of any response but `History` and `Burst`: `Stats`.
+pub const MAX_FIXED_RESPONSE_LEN: usize = 1 + 4 + 4 + 4 + 10 + 5;
 
 // Binary protocol for embedded communication
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -158,8 +278,51 @@ pub enum EmbeddedCommand {
     GetStats,
     ClearReadings,
     SetSampleRate(u32),
+    SetSetpoint(f32),
+    // Pairing, handled by pairing::PairingManager
+    Identify,
+    Pair {
+        device_uid: u64,
+        sensor_id: u16,
+        key: [u8; pairing::PAIRING_KEY_LEN],
+    },
+    Unpair {
+        device_uid: u64,
+    },
+    // Safety interlock, see EmbeddedProtocolHandler::with_interlock
+    ResetInterlock {
+        sequence: u32,
+        tag: u64, // interlock::reset_tag under the pairing key
+    },
+    GetLastIncident,
+    // Buffered readings, oldest first, from index `start`
+    GetHistory {
+        start: u16,
+    },
+    // Captures `samples` at `rate_hz` into the burst buffer, see burst
+    StartBurst {
+        rate_hz: u32,
+        samples: u16,
+    },
+    // The last burst's samples from index `start`
+    GetBurst {
+        start: u16,
+    },
+    // Restarts the stats at an epoch boundary, keeping the buffered readings
+    StartEpoch,
+    // Rate of change alarm; None turns it off
+    SetGradientLimit(Option<GradientThreshold>),
+    GetGradient,
+    // Alarm limits, DEFAULT_THRESHOLDS until set
+    SetThresholds(ThresholdConfig),
+    GetAlarm,
+    // The name the host knows the node by, kept until the next reset
+    SetName(SensorId),
+    GetName,
 }
 
+// No allocator to box the History page with
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum EmbeddedResponse {
     Status {
@@ -167,27 +330,140 @@ pub enum EmbeddedResponse {
         reading_count: u32,
         sample_rate: u32,
         buffer_usage: u8, // Percentage as u8 (0-100)
+        total_uptime_seconds: u32, // Summed over all boots, see counters
+        reset_reason: ResetReason,
     },
     Reading(EmbeddedTemperatureReading),
     ReadingCount(u32),
     Stats(EmbeddedTemperatureStats),
     Cleared,
     SampleRateSet(u32),
+    SetpointSet(f32),
+    Announce {
+        device_uid: u64,
+        sensor_id: Option<u16>, // None until the host has paired the node
+    },
+    Paired {
+        sensor_id: u16,
+    },
+    InterlockReset {
+        sequence: u32,
+    },
+    Incident(Option<interlock::Incident>),
     Error(u8), // Error code as u8 for compact binary encoding
+    History {
+        start: u16,
+        readings: Vec<EmbeddedTemperatureReading, HISTORY_PAGE_LEN>,
+        truncated: bool, // More readings follow; ask again from start + readings.len()
+    },
+    BurstStarted {
+        rate_hz: u32,
+        samples: u16,
+    },
+    Burst {
+        rate_hz: u32,
+        started_at: u32,
+        start: u16,
+        samples: Vec<Temperature, { burst::PAGE_LEN }>,
+        remaining: u16, // Samples still to capture, 0 once the burst is done
+        truncated: bool, // More samples follow; ask again from start + samples.len()
+    },
+    EpochStarted(u32),
+    GradientLimitSet,
+    Gradient {
+        celsius_per_minute: Option<f32>, // None until two samples a second apart
+        violation: Option<GradientViolation>,
+    },
+    ThresholdsSet,
+    Alarm(AlarmState),
+    NameSet,
+    Name(Option<SensorId>), // None until the host names the node
 }
 
-pub struct EmbeddedProtocolHandler<const N: usize> {
+/// Handles commands for a node buffering `N` readings, answering in frames
+/// of at most `MTU` bytes. A node driving a heater or cooler `A` hands it to
+/// the handler behind a [`SafetyInterlock`] saving to `M`, see
+/// [`with_interlock`](Self::with_interlock).
+pub struct EmbeddedProtocolHandler<
+    const N: usize,
+    const MTU: usize = DEFAULT_MTU,
+    A: Actuator = NoActuator,
+    M: NvmStorage = RamNvm<{ interlock::MIN_NVM_LEN }>,
+> {
     store: EmbeddedTemperatureStore<N>,
     sample_rate: u32,
     start_time: u32,
+    setpoint: Option<Temperature>,
+    uptime_before_boot: u32,
+    reset_reason: ResetReason,
+    burst: BurstCapture,
+    gradient: TemperatureGradient,
+    gradient_limit: Option<GradientThreshold>,
+    last_sampled: Option<u32>,
+    alarm: Alarm,
+    name: Option<SensorId>,
+    interlock: Option<SafetyInterlock<A, M>>,
 }
 
-impl<const N: usize> EmbeddedProtocolHandler<N> {
+impl<const N: usize, const MTU: usize> EmbeddedProtocolHandler<N, MTU> {
     pub const fn new() -> Self {
+        let () = Self::VALID_MTU;
         Self {
             store: EmbeddedTemperatureStore::new(),
             sample_rate: SAMPLE_RATE_HZ,
             start_time: 0,
+            setpoint: None,
+            uptime_before_boot: 0,
+            reset_reason: ResetReason::Unknown,
+            burst: BurstCapture::new(),
+            gradient: TemperatureGradient::new().with_smoothing(GRADIENT_SMOOTHING_SECONDS),
+            gradient_limit: None,
+            last_sampled: None,
+            alarm: Alarm::new(DEFAULT_THRESHOLDS),
+            name: None,
+            interlock: None,
+        }
+    }
+}
+
+impl<const N: usize, const MTU: usize, A: Actuator, M: NvmStorage> EmbeddedProtocolHandler<N, MTU, A, M> {
+    const VALID_MTU: () = assert!(MTU >= MAX_FIXED_RESPONSE_LEN, "MTU too small for the largest response");
+
+    /// Readings per `History` page that fit the MTU even at their largest.
+    pub const PAGE_LEN: usize = {
+        let fitting = (MTU - HISTORY_HEADER_LEN) / MAX_READING_LEN;
+        if fitting < HISTORY_PAGE_LEN { fitting } else { HISTORY_PAGE_LEN }
+    };
+
+    /// Samples per `Burst` page that fit the MTU.
+    pub const BURST_PAGE_LEN: usize = {
+        let fitting = (MTU - BURST_HEADER_LEN) / 4;
+        if fitting < burst::PAGE_LEN { fitting } else { burst::PAGE_LEN }
+    };
+
+    /// Puts the node's actuator under `interlock`: every sample is checked
+    /// against its limit, setpoints are refused while it is tripped, and
+    /// [`drive`](Self::drive) is the control loop's way to the actuator.
+    /// The interlock saves its state to its
+    /// [`InterlockStore`](interlock::InterlockStore) as it changes.
+    pub fn with_interlock<B: Actuator, C: NvmStorage>(
+        self,
+        interlock: SafetyInterlock<B, C>,
+    ) -> EmbeddedProtocolHandler<N, MTU, B, C> {
+        EmbeddedProtocolHandler {
+            store: self.store,
+            sample_rate: self.sample_rate,
+            start_time: self.start_time,
+            setpoint: self.setpoint,
+            uptime_before_boot: self.uptime_before_boot,
+            reset_reason: self.reset_reason,
+            burst: self.burst,
+            gradient: self.gradient,
+            gradient_limit: self.gradient_limit,
+            last_sampled: self.last_sampled,
+            alarm: self.alarm,
+            name: self.name,
+            interlock: Some(interlock),
         }
     }
 
@@ -195,21 +471,34 @@ impl<const N: usize> EmbeddedProtocolHandler<N> {
         self.start_time = start_time;
     }
 
+    /// Picks up the counters saved before the reset, see [`counters`].
+    pub fn restore(&mut self, saved: PersistedCounters, reset_reason: ResetReason) {
+        self.store.restore_total_readings(saved.total_readings);
+        self.uptime_before_boot = saved.uptime_seconds;
+        self.reset_reason = reset_reason;
+    }
+
+    /// The counters to save, as of `current_time`.
+    pub fn counters(&self, current_time: u32) -> PersistedCounters {
+        PersistedCounters {
+            total_readings: self.store.total_readings(),
+            uptime_seconds: self.uptime_before_boot.saturating_add(current_time.saturating_sub(self.start_time)),
+        }
+    }
+
     pub fn process_command(&mut self, command: EmbeddedCommand, current_time: u32) -> EmbeddedResponse {
         match command {
             EmbeddedCommand::GetStatus => {
                 let uptime = current_time.saturating_sub(self.start_time);
-                let buffer_usage = if N > 0 {
-                    ((self.store.len() * 100) / N) as u8
-                } else {
-                    0
-                };
+                let buffer_usage = (self.store.len() * 100).checked_div(N).unwrap_or(0) as u8;
 
                 EmbeddedResponse::Status {
                     uptime_seconds: uptime,
                     reading_count: self.store.total_readings(),
                     sample_rate: self.sample_rate,
                     buffer_usage,
+                    total_uptime_seconds: self.uptime_before_boot.saturating_add(uptime),
+                    reset_reason: self.reset_reason,
                 }
             }
             EmbeddedCommand::GetLatestReading => {
@@ -226,8 +515,30 @@ impl<const N: usize> EmbeddedProtocolHandler<N> {
             }
             EmbeddedCommand::ClearReadings => {
                 self.store.clear();
+                self.gradient.reset();
                 EmbeddedResponse::Cleared
             }
+            EmbeddedCommand::StartEpoch => {
+                EmbeddedResponse::EpochStarted(self.store.start_epoch())
+            }
+            EmbeddedCommand::SetGradientLimit(limit) => {
+                self.gradient_limit = limit;
+                EmbeddedResponse::GradientLimitSet
+            }
+            EmbeddedCommand::GetGradient => EmbeddedResponse::Gradient {
+                celsius_per_minute: self.gradient.rate(),
+                violation: self.gradient_alarm(),
+            },
+            EmbeddedCommand::SetThresholds(thresholds) => {
+                self.alarm.set_thresholds(thresholds);
+                EmbeddedResponse::ThresholdsSet
+            }
+            EmbeddedCommand::GetAlarm => EmbeddedResponse::Alarm(self.alarm.state()),
+            EmbeddedCommand::SetName(name) => {
+                self.name = Some(name);
+                EmbeddedResponse::NameSet
+            }
+            EmbeddedCommand::GetName => EmbeddedResponse::Name(self.name.clone()),
             EmbeddedCommand::SetSampleRate(rate) => {
                 if rate > 0 && rate <= 1000 {
                     self.sample_rate = rate;
@@ -236,32 +547,235 @@ impl<const N: usize> EmbeddedProtocolHandler<N> {
                     EmbeddedResponse::Error(EmbeddedError::InvalidSampleRate.error_code())
                 }
             }
+            EmbeddedCommand::SetSetpoint(celsius) => {
+                if self.is_interlocked() {
+                    EmbeddedResponse::Error(EmbeddedError::InterlockTripped.error_code())
+                } else if (SETPOINT_MIN_CELSIUS..=SETPOINT_MAX_CELSIUS).contains(&celsius) {
+                    self.setpoint = Some(Temperature::new(celsius));
+                    EmbeddedResponse::SetpointSet(celsius)
+                } else {
+                    EmbeddedResponse::Error(EmbeddedError::InvalidSetpoint.error_code())
+                }
+            }
+            EmbeddedCommand::Identify | EmbeddedCommand::Pair { .. } | EmbeddedCommand::Unpair { .. } => {
+                // Needs non-volatile storage, see pairing::PairingManager
+                EmbeddedResponse::Error(EmbeddedError::InvalidCommand.error_code())
+            }
+            command @ (EmbeddedCommand::ResetInterlock { .. } | EmbeddedCommand::GetLastIncident) => {
+                match self.interlock.as_mut().and_then(|interlock| interlock.handle_command(&command, current_time)) {
+                    Some(response) => response,
+                    // No actuator on this node, see with_interlock
+                    None => EmbeddedResponse::Error(EmbeddedError::InvalidCommand.error_code()),
+                }
+            }
+            EmbeddedCommand::GetHistory { start } => {
+                let readings = self.store.get_readings();
+                let from = (start as usize).min(readings.len());
+                let page: Vec<_, HISTORY_PAGE_LEN> = readings[from..].iter().take(Self::PAGE_LEN).copied().collect();
+                EmbeddedResponse::History {
+                    start,
+                    truncated: from + page.len() < readings.len(),
+                    readings: page,
+                }
+            }
+            EmbeddedCommand::StartBurst { rate_hz, samples } => match self.burst.start(rate_hz, samples) {
+                Ok(()) => EmbeddedResponse::BurstStarted { rate_hz, samples },
+                Err(e) => EmbeddedResponse::Error(e.error_code()),
+            },
+            EmbeddedCommand::GetBurst { start } => {
+                let samples = self.burst.samples();
+                let from = (start as usize).min(samples.len());
+                let page: Vec<_, { burst::PAGE_LEN }> = samples[from..].iter().take(Self::BURST_PAGE_LEN).copied().collect();
+                EmbeddedResponse::Burst {
+                    rate_hz: self.burst.rate(),
+                    started_at: self.burst.started_at(),
+                    start,
+                    truncated: from + page.len() < samples.len(),
+                    samples: page,
+                    remaining: self.burst.remaining(),
+                }
+            }
         }
     }
 
-    pub fn serialize_response(&self, response: &EmbeddedResponse) -> Result<Vec<u8, 256>, &'static str> {
-        postcard::to_vec(response).map_err(|_| "Serialization failed")
+    /// Encodes `response` into at most `MTU` bytes. Only a `History` or
+    /// `Burst` page built with more than [`PAGE_LEN`](Self::PAGE_LEN) or
+    /// [`BURST_PAGE_LEN`](Self::BURST_PAGE_LEN) entries can outgrow it; it
+    /// is cut down to that and marked truncated.
+    pub fn serialize_response(&self, response: &EmbeddedResponse) -> Result<Vec<u8, MTU>, EmbeddedError> {
+        if let Ok(frame) = postcard::to_vec(response) {
+            return Ok(frame);
+        }
+
+        let fitting = match response {
+            EmbeddedResponse::History { start, readings, .. } if readings.len() > Self::PAGE_LEN => {
+                EmbeddedResponse::History {
+                    start: *start,
+                    readings: readings.iter().take(Self::PAGE_LEN).copied().collect(),
+                    truncated: true,
+                }
+            }
+            EmbeddedResponse::Burst { rate_hz, started_at, start, samples, remaining, .. }
+                if samples.len() > Self::BURST_PAGE_LEN =>
+            {
+                EmbeddedResponse::Burst {
+                    rate_hz: *rate_hz,
+                    started_at: *started_at,
+                    start: *start,
+                    samples: samples.iter().take(Self::BURST_PAGE_LEN).copied().collect(),
+                    remaining: *remaining,
+                    truncated: true,
+                }
+            }
+            _ => EmbeddedResponse::Error(EmbeddedError::ResponseTooLarge.error_code()),
+        };
+        postcard::to_vec(&fitting).map_err(|_| EmbeddedError::SerializationError)
     }
 
-    pub fn deserialize_command(&self, data: &[u8]) -> Result<EmbeddedCommand, &'static str> {
-        postcard::from_bytes(data).map_err(|_| "Deserialization failed")
+    pub fn deserialize_command(&self, data: &[u8]) -> Result<EmbeddedCommand, EmbeddedError> {
+        postcard::from_bytes(data).map_err(|_| EmbeddedError::DeserializationError)
     }
 
-    pub fn add_reading(&mut self, temperature: Temperature, timestamp: u32) -> Result<(), &'static str> {
+    /// Stores a reading taken outside [`sample`](Self::sample), checking it
+    /// against the interlock like a sampled one.
+    pub fn add_reading(&mut self, temperature: Temperature, timestamp: u32) -> Result<(), EmbeddedError> {
+        let interlocked = self.check_interlock(&[temperature], timestamp);
         let reading = EmbeddedTemperatureReading::new(temperature, timestamp);
-        self.store.add_reading(reading)
+        self.store.add_reading(reading)?;
+        interlocked
+    }
+
+    /// Drains up to [`SAMPLE_BATCH`] samples from `sensor` in one read and
+    /// stores them, returning how many were taken. At the sample rate a batch
+    /// spans well under a second, so all of them get `timestamp`. During a
+    /// burst the samples go to the burst buffer instead, see [`burst`]. A
+    /// failed read is a [`EmbeddedError::SensorTimeout`]; wrap the sensor
+    /// in a [`RetrySensor`] to try it again first.
+    pub fn sample<S: TemperatureSensor>(&mut self, sensor: &mut S, timestamp: u32) -> Result<usize, EmbeddedError> {
+        self.sample_at(sensor, timestamp, TimeSource::Uptime)
+    }
+
+    /// Like [`sample`](Self::sample), stamping the batch with `rtc`'s time,
+    /// or with `uptime` while the RTC has none; see [`rtc`].
+    pub fn sample_with_rtc<S: TemperatureSensor, R: RtcProvider + ?Sized>(
+        &mut self,
+        sensor: &mut S,
+        rtc: &mut R,
+        uptime: u32,
+    ) -> Result<usize, EmbeddedError> {
+        let (timestamp, source) = rtc::timestamp(rtc, uptime);
+        self.sample_at(sensor, timestamp, source)
+    }
+
+    fn sample_at<S: TemperatureSensor>(&mut self, sensor: &mut S, timestamp: u32, source: TimeSource) -> Result<usize, EmbeddedError> {
+        let mut batch = [Temperature::new(0.0); SAMPLE_BATCH];
+        let count = sensor.read_temperatures(&mut batch).map_err(|_| EmbeddedError::SensorTimeout)?;
+        // Checked before the samples go anywhere, bursts included; a failed
+        // actuator is reported once they are stored
+        let interlocked = self.check_interlock(&batch[..count], timestamp);
+        if self.burst.is_capturing() {
+            let captured = self.burst.capture(&batch[..count], timestamp);
+            return interlocked.map(|()| captured);
+        }
+        for &temperature in &batch[..count] {
+            self.store.add_reading(EmbeddedTemperatureReading::new(temperature, timestamp).with_source(source))?;
+            if temperature.validate().is_ok() {
+                self.alarm.update(temperature.celsius);
+            }
+        }
+        if let Some(&latest) = batch[..count].last() {
+            self.track_gradient(latest, timestamp);
+        }
+        interlocked.map(|()| count)
+    }
+
+    /// Feeds the interlock, if any, every sample of a batch; invalid ones
+    /// trip it, as they come from a faulty sensor.
+    fn check_interlock(&mut self, samples: &[Temperature], timestamp: u32) -> Result<(), EmbeddedError> {
+        let Some(interlock) = self.interlock.as_mut() else {
+            return Ok(());
+        };
+        for &temperature in samples {
+            interlock.check(temperature, timestamp)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the actuator's output for the node's control loop. The interlock
+    /// holds it off while tripped; a node without one has nothing to drive.
+    pub fn drive(&mut self, level: f32) -> Result<(), EmbeddedError> {
+        let interlock = self.interlock.as_mut().ok_or(EmbeddedError::InvalidCommand)?;
+        interlock.set_output(level).map_err(|_| EmbeddedError::ActuatorFailed)
+    }
+
+    /// Whether the interlock has latched a critical temperature.
+    pub fn is_interlocked(&self) -> bool {
+        self.interlock.as_ref().is_some_and(|interlock| interlock.is_tripped())
+    }
+
+    pub fn interlock(&self) -> Option<&SafetyInterlock<A, M>> {
+        self.interlock.as_ref()
+    }
+
+    /// The interlock's state as saved, see [`InterlockStore`](interlock::InterlockStore).
+    pub fn interlock_state(&self) -> Option<InterlockState> {
+        self.interlock.as_ref().map(|interlock| interlock.state())
+    }
+
+    /// Passes the key of a new pairing on to the interlock, see
+    /// [`SafetyInterlock::rekey`].
+    pub fn rekey_interlock(&mut self, key: [u8; pairing::PAIRING_KEY_LEN], current_time: u32) -> Result<(), EmbeddedError> {
+        let interlock = self.interlock.as_mut().ok_or(EmbeddedError::InvalidCommand)?;
+        interlock.rekey(key, current_time)
+    }
+
+    /// Feeds the gradient a batch's newest sample. Readings within the same
+    /// second are left to the next second's; a clock set back, e.g. when the
+    /// RTC first has the time, starts over.
+    fn track_gradient(&mut self, temperature: Temperature, timestamp: u32) {
+        match self.last_sampled.map(|last| timestamp.checked_sub(last)) {
+            Some(Some(0)) => return,
+            Some(Some(elapsed)) => {
+                self.gradient.update(temperature, elapsed as f32);
+            }
+            Some(None) => {
+                self.gradient.reset();
+                self.gradient.update(temperature, 0.0);
+            }
+            None => {
+                self.gradient.update(temperature, 0.0);
+            }
+        }
+        self.last_sampled = Some(timestamp);
+    }
+
+    /// Whether the sampled readings are past the thresholds, see [`Alarm`].
+    pub fn alarm_state(&self) -> AlarmState {
+        self.alarm.state()
+    }
+
+    /// Which limit set with `SetGradientLimit` the rate of change exceeds, for
+    /// the firmware to act on locally.
+    pub fn gradient_alarm(&self) -> Option<GradientViolation> {
+        self.gradient_limit.and_then(|limit| self.gradient.check(&limit))
     }
 
     pub fn get_store(&self) -> &EmbeddedTemperatureStore<N> {
         &self.store
     }
 
+    /// The rate to sample at: a burst's while one is capturing.
     pub fn get_sample_rate(&self) -> u32 {
-        self.sample_rate
+        self.burst.rate_hz().unwrap_or(self.sample_rate)
+    }
+
+    /// Setpoint for the node's control loop, once the host has set one.
+    pub fn setpoint(&self) -> Option<Temperature> {
+        self.setpoint
     }
 }
 
-impl<const N: usize> Default for EmbeddedProtocolHandler<N> {
+impl<const N: usize, const MTU: usize> Default for EmbeddedProtocolHandler<N, MTU> {
     fn default() -> Self {
         Self::new()
     }
@@ -276,6 +790,15 @@ pub enum EmbeddedError {
     InvalidCommand,
     SerializationError,
     NoReadings,
+    PairingFailed,
+    InvalidSetpoint,
+    InterlockTripped,
+    AuthenticationFailed,
+    ResponseTooLarge,
+    DeserializationError,
+    StorageFailed,
+    InvalidBurst,
+    ActuatorFailed,
 }
 
 impl EmbeddedError {
@@ -287,6 +810,15 @@ impl EmbeddedError {
             EmbeddedError::InvalidCommand => 4,
             EmbeddedError::SerializationError => 5,
             EmbeddedError::NoReadings => 6,
+            EmbeddedError::PairingFailed => 7,
+            EmbeddedError::InvalidSetpoint => 8,
+            EmbeddedError::InterlockTripped => 9,
+            EmbeddedError::AuthenticationFailed => 10,
+            EmbeddedError::ResponseTooLarge => 11,
+            EmbeddedError::DeserializationError => 12,
+            EmbeddedError::StorageFailed => 13,
+            EmbeddedError::InvalidBurst => 14,
+            EmbeddedError::ActuatorFailed => 15,
         }
     }
 
@@ -298,10 +830,27 @@ impl EmbeddedError {
             EmbeddedError::InvalidCommand => "Invalid command",
             EmbeddedError::SerializationError => "Serialization error",
             EmbeddedError::NoReadings => "No readings available",
+            EmbeddedError::PairingFailed => "Pairing record could not be stored",
+            EmbeddedError::InvalidSetpoint => "Setpoint out of range",
+            EmbeddedError::InterlockTripped => "Safety interlock tripped",
+            EmbeddedError::AuthenticationFailed => "Authentication failed",
+            EmbeddedError::ResponseTooLarge => "Response does not fit the MTU",
+            EmbeddedError::DeserializationError => "Deserialization error",
+            EmbeddedError::StorageFailed => "Non-volatile storage write failed",
+            EmbeddedError::InvalidBurst => "Burst rate or length out of range",
+            EmbeddedError::ActuatorFailed => "Actuator could not be switched",
         }
     }
 }
 
+impl core::fmt::Display for EmbeddedError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl core::error::Error for EmbeddedError {}
+
 // Utility function for creating fixed-capacity strings without std::format!
 pub fn create_status_string(reading_count: u32, sample_rate: u32) -> String<128> {
     let mut status = String::new();
@@ -412,6 +961,139 @@ mod tests {
         assert_eq!(latest.timestamp, 1000);
     }
 
+    struct FifoSensor {
+        queued: u8,
+        reads: u32,
+    }
+
+    impl TemperatureSensor for FifoSensor {
+        type Error = ();
+
+        fn read_temperature(&mut self) -> Result<Temperature, ()> {
+            let mut sample = [Temperature::new(0.0)];
+            self.read_temperatures(&mut sample)?;
+            Ok(sample[0])
+        }
+
+        fn sensor_id(&self) -> &str {
+            "fifo"
+        }
+
+        fn read_temperatures(&mut self, out: &mut [Temperature]) -> Result<usize, ()> {
+            self.reads += 1;
+            if self.queued == 0 {
+                return Err(());
+            }
+            let count = out.len().min(self.queued as usize);
+            for (i, slot) in out[..count].iter_mut().enumerate() {
+                *slot = Temperature::new(20.0 + i as f32);
+            }
+            self.queued -= count as u8;
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn test_handler_drains_a_sensor_fifo_per_read() {
+        let mut handler: EmbeddedProtocolHandler<16> = EmbeddedProtocolHandler::new();
+        let mut sensor = FifoSensor { queued: 11, reads: 0 };
+
+        assert_eq!(handler.sample(&mut sensor, 5), Ok(SAMPLE_BATCH));
+        assert_eq!(handler.sample(&mut sensor, 6), Ok(3));
+        assert_eq!(handler.sample(&mut sensor, 7), Err(EmbeddedError::SensorTimeout));
+        assert_eq!(sensor.reads, 3);
+
+        let store = handler.get_store();
+        assert_eq!(store.len(), 11);
+        let latest = store.get_latest().unwrap();
+        assert_eq!((latest.temperature.celsius, latest.timestamp), (22.0, 6));
+    }
+
+    /// Reads back whatever time it was last set to, like a battery-backed RTC.
+    struct SetRtc(Option<u32>);
+
+    impl RtcProvider for SetRtc {
+        fn unix_time(&mut self) -> Option<u32> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_handler_stamps_readings_from_an_rtc() {
+        let mut handler: EmbeddedProtocolHandler<16> = EmbeddedProtocolHandler::new();
+        let mut sensor = FifoSensor { queued: 3, reads: 0 };
+        let stamp = |handler: &EmbeddedProtocolHandler<16>| {
+            let latest = handler.get_store().get_latest().unwrap();
+            (latest.timestamp, latest.source)
+        };
+
+        handler.sample_with_rtc(&mut sensor, &mut SetRtc(Some(1_700_000_000)), 5).unwrap();
+        assert_eq!(stamp(&handler), (1_700_000_000, TimeSource::Rtc));
+
+        // Lost its time with the backup battery and restarted at 2000-01-01
+        sensor.queued = 1;
+        handler.sample_with_rtc(&mut sensor, &mut SetRtc(Some(946_684_800)), 6).unwrap();
+        assert_eq!(stamp(&handler), (6, TimeSource::Uptime));
+
+        sensor.queued = 1;
+        handler.sample_with_rtc(&mut sensor, &mut rtc::NoRtc, 7).unwrap();
+        assert_eq!(stamp(&handler), (7, TimeSource::Uptime));
+
+        // The source goes out with each reading
+        let response = handler.process_command(EmbeddedCommand::GetHistory { start: 0 }, 8);
+        let frame = handler.serialize_response(&response).unwrap();
+        let EmbeddedResponse::History { readings, .. } = postcard::from_bytes(&frame).unwrap() else {
+            panic!("expected a history page");
+        };
+        assert_eq!(readings[0].source, TimeSource::Rtc);
+        assert_eq!((readings.len(), readings[3].source), (5, TimeSource::Uptime));
+    }
+
+    #[test]
+    fn test_burst_captures_at_its_own_rate() {
+        let mut handler: EmbeddedProtocolHandler<16> = EmbeddedProtocolHandler::new();
+        let mut sensor = FifoSensor { queued: 40, reads: 0 };
+        handler.sample(&mut sensor, 1).unwrap();
+
+        let started = handler.process_command(EmbeddedCommand::StartBurst { rate_hz: 200, samples: 20 }, 2);
+        assert_eq!(started, EmbeddedResponse::BurstStarted { rate_hz: 200, samples: 20 });
+        assert_eq!(handler.get_sample_rate(), 200);
+        assert_eq!(handler.sample(&mut sensor, 3), Ok(SAMPLE_BATCH));
+        assert_eq!(handler.sample(&mut sensor, 3), Ok(SAMPLE_BATCH));
+        let partial = handler.process_command(EmbeddedCommand::GetBurst { start: 0 }, 3);
+        assert!(matches!(partial, EmbeddedResponse::Burst { remaining: 4, truncated: false, ref samples, .. } if samples.len() == 16));
+
+        // The last batch is only partly needed, and then the regular rate is back
+        assert_eq!(handler.sample(&mut sensor, 3), Ok(4));
+        assert_eq!(handler.get_sample_rate(), SAMPLE_RATE_HZ);
+        assert_eq!(handler.get_store().len(), SAMPLE_BATCH);
+        handler.sample(&mut sensor, 4).unwrap();
+        assert_eq!(handler.get_store().len(), 2 * SAMPLE_BATCH);
+
+        // Pages fit a small MTU
+        let mut small: EmbeddedProtocolHandler<4, 32> = EmbeddedProtocolHandler::new();
+        assert_eq!(EmbeddedProtocolHandler::<4, 32>::BURST_PAGE_LEN, 3);
+        small.process_command(EmbeddedCommand::StartBurst { rate_hz: 50, samples: 5 }, 0);
+        small.sample(&mut FifoSensor { queued: 5, reads: 0 }, 7).unwrap();
+        let page = small.process_command(EmbeddedCommand::GetBurst { start: 3 }, 8);
+        let frame = small.serialize_response(&page).unwrap();
+        let EmbeddedResponse::Burst { rate_hz: 50, started_at: 7, start: 3, samples, remaining: 0, truncated: false } =
+            postcard::from_bytes(&frame).unwrap()
+        else {
+            panic!("Expected the last Burst page");
+        };
+        assert_eq!(samples[..], [Temperature::new(23.0), Temperature::new(24.0)]);
+
+        let full = handler.process_command(EmbeddedCommand::GetBurst { start: 0 }, 5);
+        let frame = small.serialize_response(&full).unwrap();
+        assert!(matches!(postcard::from_bytes(&frame).unwrap(), EmbeddedResponse::Burst { truncated: true, samples, .. } if samples.len() == 3));
+
+        for (rate_hz, samples) in [(0, 10), (2000, 10), (100, 0), (100, 1000)] {
+            let response = handler.process_command(EmbeddedCommand::StartBurst { rate_hz, samples }, 6);
+            assert_eq!(response, EmbeddedResponse::Error(EmbeddedError::InvalidBurst.error_code()));
+        }
+    }
+
     #[test]
     fn test_embedded_store_circular_buffer() {
         let mut store: EmbeddedTemperatureStore<3> = EmbeddedTemperatureStore::new();
@@ -460,6 +1142,249 @@ mod tests {
         assert_eq!(stats.max.celsius, 50.0);
         assert_eq!(stats.average.celsius, 30.0);
         assert_eq!(stats.count, 5);
+
+        // Stats follow the window as the extremes are evicted
+        for (i, &temp) in [35.0, 25.0].iter().enumerate() {
+            store.add_reading(EmbeddedTemperatureReading::new(Temperature::new(temp), 2000 + i as u32)).unwrap();
+        }
+        let stats = store.get_stats();
+        assert_eq!((stats.min.celsius, stats.max.celsius), (25.0, 50.0));
+        assert_eq!(stats.average.celsius, 36.0);
+
+        for _ in 0..3 {
+            store.add_reading(EmbeddedTemperatureReading::new(Temperature::new(30.0), 3000)).unwrap();
+        }
+        let stats = store.get_stats();
+        assert_eq!((stats.min.celsius, stats.max.celsius, stats.count), (25.0, 35.0, 5));
+
+        store.clear();
+        assert_eq!(store.get_stats().count, 0);
+    }
+
+    #[test]
+    fn test_epochs_restart_the_stats() {
+        let mut handler: EmbeddedProtocolHandler<4> = EmbeddedProtocolHandler::new();
+        for (i, &temp) in [30.0, 32.0, 34.0].iter().enumerate() {
+            handler.store.add_reading(EmbeddedTemperatureReading::new(Temperature::new(temp), i as u32)).unwrap();
+        }
+
+        assert_eq!(handler.process_command(EmbeddedCommand::StartEpoch, 3), EmbeddedResponse::EpochStarted(1));
+        let EmbeddedResponse::Stats(stats) = handler.process_command(EmbeddedCommand::GetStats, 3) else {
+            panic!("Expected stats");
+        };
+        assert_eq!((stats.count, stats.epoch), (0, 1));
+        assert_eq!(handler.store.len(), 3);
+
+        // The pre-epoch readings are evicted without touching the stats
+        for (i, &temp) in [4.0, 6.0, 8.0].iter().enumerate() {
+            handler.store.add_reading(EmbeddedTemperatureReading::new(Temperature::new(temp), 4 + i as u32)).unwrap();
+        }
+        let stats = handler.store.get_stats();
+        assert_eq!((stats.min.celsius, stats.max.celsius, stats.count), (4.0, 8.0, 3));
+        assert_eq!(stats.average.celsius, 6.0);
+    }
+
+    /// Reads a fixed temperature.
+    struct Probe(f32);
+
+    impl TemperatureSensor for Probe {
+        type Error = ();
+
+        fn read_temperature(&mut self) -> Result<Temperature, ()> {
+            Ok(Temperature::new(self.0))
+        }
+
+        fn sensor_id(&self) -> &str {
+            "probe"
+        }
+    }
+
+    /// Fails `failures` reads, then reads `celsius`.
+    struct Glitchy {
+        failures: u8,
+        celsius: f32,
+    }
+
+    impl TemperatureSensor for Glitchy {
+        type Error = ();
+
+        fn read_temperature(&mut self) -> Result<Temperature, ()> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(());
+            }
+            Ok(Temperature::new(self.celsius))
+        }
+
+        fn sensor_id(&self) -> &str {
+            "glitchy"
+        }
+    }
+
+    #[test]
+    fn test_sampling_retries() {
+        let mut handler: EmbeddedProtocolHandler<8> = EmbeddedProtocolHandler::new();
+        assert_eq!(handler.sample(&mut Glitchy { failures: 1, celsius: 21.0 }, 1), Err(EmbeddedError::SensorTimeout));
+
+        let mut waited = 0;
+        let mut sensor = RetrySensor::new(Glitchy { failures: 2, celsius: 21.0 }, RetryPolicy::new(3).with_delay(5, 5))
+            .with_delay(|ms| waited += ms);
+        assert_eq!(handler.sample(&mut sensor, 2), Ok(1));
+        sensor.inner_mut().failures = 3;
+        assert_eq!(handler.sample(&mut sensor, 3), Err(EmbeddedError::SensorTimeout));
+        assert_eq!(sensor.retried(), 2);
+        assert_eq!(waited, 20);
+        assert_eq!(handler.store.len(), 1);
+    }
+
+    #[test]
+    fn test_threshold_alarm() {
+        let mut handler: EmbeddedProtocolHandler<8> = EmbeddedProtocolHandler::new();
+        handler.sample(&mut Probe(36.0), 1).unwrap();
+        assert_eq!(handler.process_command(EmbeddedCommand::GetAlarm, 1), EmbeddedResponse::Alarm(AlarmState::High));
+
+        let thresholds = ThresholdConfig::new(0.0, 40.0, 1.0, 2).unwrap();
+        let response = handler.process_command(EmbeddedCommand::SetThresholds(thresholds), 1);
+        assert_eq!(response, EmbeddedResponse::ThresholdsSet);
+        handler.sample(&mut Probe(39.5), 2).unwrap();
+        assert_eq!(handler.alarm_state(), AlarmState::High);
+        handler.sample(&mut Probe(38.5), 3).unwrap();
+        assert_eq!(handler.alarm_state(), AlarmState::Normal);
+        handler.sample(&mut Probe(-1.0), 4).unwrap();
+        assert_eq!(handler.alarm_state(), AlarmState::Normal);
+        handler.sample(&mut Probe(-1.0), 5).unwrap();
+        assert_eq!(handler.alarm_state(), AlarmState::Low);
+    }
+
+    /// Records the level it was last set to.
+    struct Heater(f32);
+
+    impl Actuator for Heater {
+        type Error = ();
+
+        fn set_output(&mut self, level: f32) -> Result<(), ()> {
+            self.0 = level;
+            Ok(())
+        }
+
+        fn output(&self) -> f32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_interlock() {
+        const UID: u64 = 0xC3_0000_1234;
+        const KEY: [u8; pairing::PAIRING_KEY_LEN] = [9; pairing::PAIRING_KEY_LEN];
+
+        // Without an actuator there is nothing to interlock
+        let mut handler: EmbeddedProtocolHandler<8> = EmbeddedProtocolHandler::new();
+        let response = handler.process_command(EmbeddedCommand::GetLastIncident, 1);
+        assert_eq!(response, EmbeddedResponse::Error(EmbeddedError::InvalidCommand.error_code()));
+        assert_eq!(handler.drive(1.0), Err(EmbeddedError::InvalidCommand));
+
+        let mut store = interlock::InterlockStore::load(RamNvm::<128>::new());
+        store.save(InterlockState::default()).unwrap();
+        let interlock = SafetyInterlock::new(Heater(0.0), UID, KEY, store);
+        let mut handler = handler.with_interlock(interlock);
+        assert_eq!(handler.process_command(EmbeddedCommand::SetSetpoint(45.0), 1), EmbeddedResponse::SetpointSet(45.0));
+        handler.drive(1.0).unwrap();
+        assert_eq!(handler.interlock().unwrap().output(), 1.0);
+
+        // Crossing TEMP_CRITICAL turns the heater off and keeps it off,
+        // also across a reboot
+        handler.sample(&mut Probe(52.0), 10).unwrap();
+        assert!(handler.is_interlocked());
+        assert_eq!(handler.interlock().unwrap().output(), 0.0);
+        assert!(handler.interlock().unwrap().store().saved().tripped);
+        handler.sample(&mut Probe(30.0), 20).unwrap();
+        handler.drive(1.0).unwrap();
+        assert_eq!(handler.interlock().unwrap().output(), 0.0);
+        let response = handler.process_command(EmbeddedCommand::SetSetpoint(40.0), 21);
+        assert_eq!(response, EmbeddedResponse::Error(EmbeddedError::InterlockTripped.error_code()));
+
+        let EmbeddedResponse::Incident(Some(incident)) = handler.process_command(EmbeddedCommand::GetLastIncident, 22) else {
+            panic!("Expected an incident");
+        };
+        assert_eq!((incident.sequence, incident.tripped_at, incident.cleared_at), (1, 10, None));
+        assert_eq!(handler.interlock_state().unwrap().last_incident, Some(incident));
+
+        let forged = EmbeddedCommand::ResetInterlock { sequence: 1, tag: 0 };
+        let response = handler.process_command(forged, 23);
+        assert_eq!(response, EmbeddedResponse::Error(EmbeddedError::AuthenticationFailed.error_code()));
+        let reset = EmbeddedCommand::ResetInterlock { sequence: 1, tag: interlock::reset_tag(&KEY, UID, 1) };
+        assert_eq!(handler.process_command(reset, 24), EmbeddedResponse::InterlockReset { sequence: 1 });
+        assert!(!handler.is_interlocked());
+        assert_eq!(handler.interlock().unwrap().store().saved(), handler.interlock_state().unwrap());
+        assert_eq!(handler.process_command(EmbeddedCommand::SetSetpoint(40.0), 25), EmbeddedResponse::SetpointSet(40.0));
+        handler.drive(1.0).unwrap();
+        assert_eq!(handler.interlock().unwrap().output(), 1.0);
+
+        // Readings added outside sampling count too
+        handler.add_reading(Temperature::new(55.0), 30).unwrap();
+        assert!(handler.is_interlocked());
+        assert_eq!(handler.interlock().unwrap().store().saved().last_incident.map(|i| i.sequence), Some(2));
+
+        // Paired anew after the record was lost
+        let lost = interlock::InterlockStore::load(RamNvm::<128>::new());
+        let mut handler = EmbeddedProtocolHandler::<8>::new().with_interlock(SafetyInterlock::new(Heater(1.0), UID, KEY, lost));
+        handler.add_reading(Temperature::new(20.0), 1).unwrap();
+        assert!(handler.is_interlocked());
+        assert_eq!(handler.rekey_interlock(KEY, 2), Err(EmbeddedError::InvalidCommand));
+        let key = [4; pairing::PAIRING_KEY_LEN];
+        handler.rekey_interlock(key, 2).unwrap();
+        let reset = EmbeddedCommand::ResetInterlock { sequence: 1, tag: interlock::reset_tag(&key, UID, 1) };
+        assert_eq!(handler.process_command(reset, 3), EmbeddedResponse::InterlockReset { sequence: 1 });
+
+        // An open sensor reads NaN, which must not leave the heater on
+        handler.drive(1.0).unwrap();
+        handler.sample(&mut Probe(f32::NAN), 4).unwrap();
+        assert!(handler.is_interlocked());
+        assert_eq!(handler.interlock().unwrap().output(), 0.0);
+    }
+
+    #[test]
+    fn test_naming() {
+        let mut handler: EmbeddedProtocolHandler<4> = EmbeddedProtocolHandler::new();
+        assert_eq!(handler.process_command(EmbeddedCommand::GetName, 1), EmbeddedResponse::Name(None));
+
+        let name = SensorId::new("cellar.north").unwrap();
+        let frame: Vec<u8, 32> = postcard::to_vec(&EmbeddedCommand::SetName(name.clone())).unwrap();
+        let command = handler.deserialize_command(&frame).unwrap();
+        assert_eq!(handler.process_command(command, 1), EmbeddedResponse::NameSet);
+        assert_eq!(handler.process_command(EmbeddedCommand::GetName, 2), EmbeddedResponse::Name(Some(name)));
+
+        // Names are checked as they are decoded
+        let mut frame = frame;
+        frame[2] = b' ';
+        assert_eq!(handler.deserialize_command(&frame), Err(EmbeddedError::DeserializationError));
+    }
+
+    #[test]
+    fn test_gradient_alarm() {
+        let mut handler: EmbeddedProtocolHandler<16> = EmbeddedProtocolHandler::new();
+        let limit = GradientThreshold::rising(2.0).unwrap();
+        assert_eq!(handler.process_command(EmbeddedCommand::SetGradientLimit(Some(limit)), 0), EmbeddedResponse::GradientLimitSet);
+
+        // 3 °C/min, sampled twice a second
+        for second in 0..10 {
+            handler.sample(&mut Probe(20.0 + second as f32 * 0.05), second).unwrap();
+            handler.sample(&mut Probe(80.0), second).unwrap();
+        }
+        let EmbeddedResponse::Gradient { celsius_per_minute: Some(rate), violation } =
+            handler.process_command(EmbeddedCommand::GetGradient, 10)
+        else {
+            panic!("Expected a gradient");
+        };
+        assert!((rate - 3.0).abs() < 0.01, "{}", rate);
+        assert_eq!(violation, Some(GradientViolation::Rising));
+
+        // The RTC set the clock back
+        handler.sample(&mut Probe(20.0), 5).unwrap();
+        assert_eq!(handler.gradient_alarm(), None);
+        handler.process_command(EmbeddedCommand::SetGradientLimit(None), 10);
+        handler.sample(&mut Probe(80.0), 6).unwrap();
+        assert_eq!(handler.gradient_alarm(), None);
     }
 
     #[test]
@@ -475,8 +1400,10 @@ mod tests {
         assert_eq!(validate_buffer_size(32), 32);
 
         // Test temperature thresholds
-        assert!(TEMP_THRESHOLD_LOW < TEMP_THRESHOLD_HIGH);
-        assert!(TEMP_THRESHOLD_HIGH < TEMP_CRITICAL);
+        const { assert!(TEMP_THRESHOLD_LOW < TEMP_THRESHOLD_HIGH) };
+        const { assert!(TEMP_THRESHOLD_HIGH < TEMP_CRITICAL) };
+        assert_eq!(TEMP_CRITICAL, 620);
+        assert!((Temperature::from_adc(TEMP_THRESHOLD_HIGH, &ADC_CONFIG).celsius - 35.0).abs() < 0.1);
     }
 
     #[test]
@@ -486,7 +1413,7 @@ mod tests {
 
         // Test GetStatus command
         let response = handler.process_command(EmbeddedCommand::GetStatus, 2000);
-        if let EmbeddedResponse::Status { uptime_seconds, reading_count, sample_rate, buffer_usage } = response {
+        if let EmbeddedResponse::Status { uptime_seconds, reading_count, sample_rate, buffer_usage, .. } = response {
             assert_eq!(uptime_seconds, 1000);
             assert_eq!(reading_count, 0);
             assert_eq!(sample_rate, SAMPLE_RATE_HZ);
@@ -514,6 +1441,15 @@ mod tests {
             panic!("Expected ReadingCount response");
         }
 
+        // Test setpoint
+        assert_eq!(handler.setpoint(), None);
+        let response = handler.process_command(EmbeddedCommand::SetSetpoint(21.5), 2000);
+        assert_eq!(response, EmbeddedResponse::SetpointSet(21.5));
+        assert_eq!(handler.setpoint(), Some(Temperature::new(21.5)));
+
+        let response = handler.process_command(EmbeddedCommand::SetSetpoint(500.0), 2000);
+        assert_eq!(response, EmbeddedResponse::Error(EmbeddedError::InvalidSetpoint.error_code()));
+
         // Test sample rate setting
         let response = handler.process_command(EmbeddedCommand::SetSampleRate(20), 2000);
         if let EmbeddedResponse::SampleRateSet(rate) = response {
@@ -540,11 +1476,13 @@ mod tests {
             reading_count: 42,
             sample_rate: 10,
             buffer_usage: 50,
+            total_uptime_seconds: 86_400,
+            reset_reason: ResetReason::Watchdog,
         };
 
         let serialized = handler.serialize_response(&response).unwrap();
         // Postcard produces compact binary output
-        assert!(serialized.len() > 0 && serialized.len() < 32);
+        assert!(!serialized.is_empty() && serialized.len() < 32);
 
         // Test command with parameter
         let command_with_param = EmbeddedCommand::SetSampleRate(100);
@@ -553,6 +1491,85 @@ mod tests {
         assert_eq!(deserialized_command, EmbeddedCommand::SetSampleRate(100));
     }
 
+    #[test]
+    fn test_history_pages_fit_the_mtu() {
+        let mut handler: EmbeddedProtocolHandler<64> = EmbeddedProtocolHandler::new();
+        for t in 0..40 {
+            handler.add_reading(Temperature::new(20.0 + t as f32 / 10.0), 1000 + t).unwrap();
+        }
+
+        // A page fits the default MTU, with more to come
+        let response = handler.process_command(EmbeddedCommand::GetHistory { start: 0 }, 2000);
+        let frame = handler.serialize_response(&response).unwrap();
+        assert_eq!(postcard::from_bytes::<EmbeddedResponse>(&frame).unwrap(), response);
+        let EmbeddedResponse::History { readings, truncated: true, .. } = &response else {
+            panic!("Expected truncated History response");
+        };
+        assert_eq!(readings.len(), EmbeddedProtocolHandler::<64>::PAGE_LEN);
+
+        // The last page is complete
+        let last = handler.process_command(EmbeddedCommand::GetHistory { start: 36 }, 2000);
+        let EmbeddedResponse::History { readings: tail, truncated: false, .. } = &last else {
+            panic!("Expected complete History response");
+        };
+        assert_eq!(tail.len(), 4);
+
+        // A small MTU gets fewer readings per page, and cuts down pages built elsewhere
+        let mut small: EmbeddedProtocolHandler<64, 32> = EmbeddedProtocolHandler::new();
+        for reading in readings {
+            small.add_reading(reading.temperature, reading.timestamp).unwrap();
+        }
+        assert_eq!(EmbeddedProtocolHandler::<64, 32>::PAGE_LEN, 2);
+        let page = small.process_command(EmbeddedCommand::GetHistory { start: 0 }, 2000);
+        assert!(matches!(&page, EmbeddedResponse::History { readings, truncated: true, .. } if readings.len() == 2));
+
+        let frame = small.serialize_response(&response).unwrap();
+        let EmbeddedResponse::History { readings: cut, truncated: true, .. } = postcard::from_bytes(&frame).unwrap() else {
+            panic!("Expected truncated History response");
+        };
+        assert_eq!(cut[..], readings[..2]);
+    }
+
+    #[test]
+    fn test_fixed_responses_fit_the_smallest_mtu() {
+        let incident = interlock::Incident {
+            sequence: u32::MAX,
+            tripped_at: u32::MAX,
+            trip_temperature: Temperature::new(f32::MAX),
+            peak_temperature: Temperature::new(f32::MAX),
+            cleared_at: Some(u32::MAX),
+        };
+        let stats = EmbeddedTemperatureStats {
+            min: Temperature::new(f32::MAX),
+            max: Temperature::new(f32::MAX),
+            average: Temperature::new(f32::MAX),
+            count: usize::MAX,
+            epoch: u32::MAX,
+        };
+        let largest = [
+            EmbeddedResponse::Status {
+                uptime_seconds: u32::MAX,
+                reading_count: u32::MAX,
+                sample_rate: u32::MAX,
+                buffer_usage: 100,
+                total_uptime_seconds: u32::MAX,
+                reset_reason: ResetReason::External,
+            },
+            EmbeddedResponse::Stats(stats),
+            EmbeddedResponse::Announce { device_uid: u64::MAX, sensor_id: Some(u16::MAX) },
+            EmbeddedResponse::Incident(Some(incident)),
+            EmbeddedResponse::Name(Some(SensorId::new("greenhouse_north_bench_3").unwrap())),
+        ];
+
+        let handler: EmbeddedProtocolHandler<4, MAX_FIXED_RESPONSE_LEN> = EmbeddedProtocolHandler::new();
+        for response in &largest {
+            let frame = handler.serialize_response(response).unwrap();
+            assert_eq!(postcard::from_bytes::<EmbeddedResponse>(&frame).unwrap(), *response);
+        }
+        let frame = handler.serialize_response(&largest[1]).unwrap();
+        assert_eq!(frame.len(), MAX_FIXED_RESPONSE_LEN);
+    }
+
     #[test]
     fn test_error_handling() {
         let mut handler: EmbeddedProtocolHandler<2> = EmbeddedProtocolHandler::new();
@@ -579,6 +1596,9 @@ mod tests {
         } else {
             panic!("Expected error response");
         }
+
+        // Test malformed command bytes
+        assert_eq!(handler.deserialize_command(&[0xff]), Err(EmbeddedError::DeserializationError));
     }
 
     #[test]
@@ -599,8 +1619,21 @@ mod tests {
         assert_eq!(EmbeddedError::InvalidCommand.error_code(), 4);
         assert_eq!(EmbeddedError::SerializationError.error_code(), 5);
         assert_eq!(EmbeddedError::NoReadings.error_code(), 6);
+        assert_eq!(EmbeddedError::PairingFailed.error_code(), 7);
+        assert_eq!(EmbeddedError::InvalidSetpoint.error_code(), 8);
+        assert_eq!(EmbeddedError::InterlockTripped.error_code(), 9);
+        assert_eq!(EmbeddedError::AuthenticationFailed.error_code(), 10);
+        assert_eq!(EmbeddedError::ResponseTooLarge.error_code(), 11);
+        assert_eq!(EmbeddedError::DeserializationError.error_code(), 12);
+        assert_eq!(EmbeddedError::StorageFailed.error_code(), 13);
+        assert_eq!(EmbeddedError::InvalidBurst.error_code(), 14);
 
         assert_eq!(EmbeddedError::BufferFull.description(), "Buffer full");
         assert_eq!(EmbeddedError::NoReadings.description(), "No readings available");
+
+        use core::fmt::Write;
+        let mut message: String<32> = String::new();
+        write!(message, "{}", EmbeddedError::BufferFull).unwrap();
+        assert_eq!(message.as_str(), "Buffer full");
     }
 }
\ No newline at end of file
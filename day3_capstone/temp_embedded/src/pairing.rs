@@ -0,0 +1,262 @@
+//! Host-driven pairing of new nodes.
+//!
+//! A fresh node answers `Identify` with an `Announce` carrying its device UID.
+//! The host replies with `Pair`, assigning a sensor id and a shared key, which
+//! the node persists through [`NvmStorage`] so the assignment survives resets.
+//! No firmware rebuild with a hard-coded id is needed.
+
+use crate::{EmbeddedCommand, EmbeddedError, EmbeddedResponse};
+
+pub const PAIRING_KEY_LEN: usize = 16;
+/// Offset of the pairing record in non-volatile memory.
+pub const PAIRING_RECORD_OFFSET: u32 = 0;
+
+const RECORD_MAGIC: [u8; 4] = *b"TPR1";
+const RECORD_LEN: usize = 4 + 2 + PAIRING_KEY_LEN + 1;
+
+/// Minimal non-volatile memory access (flash page, EEPROM, FRAM, ...).
+pub trait NvmStorage {
+    type Error: core::fmt::Debug;
+
+    /// Size in bytes, where the type knows it. An
+    /// [`InterlockStore`](crate::interlock::InterlockStore) refuses storage
+    /// too small for its record at compile time.
+    const LEN: Option<usize> = None;
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error>;
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PairingRecord {
+    pub sensor_id: u16,
+    pub key: [u8; PAIRING_KEY_LEN],
+}
+
+impl PairingRecord {
+    fn encode(&self) -> [u8; RECORD_LEN] {
+        let mut buf = [0u8; RECORD_LEN];
+        buf[..4].copy_from_slice(&RECORD_MAGIC);
+        buf[4..6].copy_from_slice(&self.sensor_id.to_le_bytes());
+        buf[6..6 + PAIRING_KEY_LEN].copy_from_slice(&self.key);
+        buf[RECORD_LEN - 1] = checksum(&buf[..RECORD_LEN - 1]);
+        buf
+    }
+
+    fn decode(buf: &[u8; RECORD_LEN]) -> Option<Self> {
+        // Erased flash reads as 0xFF, so a missing magic simply means "not paired"
+        if buf[..4] != RECORD_MAGIC || buf[RECORD_LEN - 1] != checksum(&buf[..RECORD_LEN - 1]) {
+            return None;
+        }
+
+        let mut key = [0u8; PAIRING_KEY_LEN];
+        key.copy_from_slice(&buf[6..6 + PAIRING_KEY_LEN]);
+        Some(Self {
+            sensor_id: u16::from_le_bytes([buf[4], buf[5]]),
+            key,
+        })
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |acc, b| acc.rotate_left(1) ^ b)
+}
+
+pub struct PairingManager<S: NvmStorage> {
+    nvm: S,
+    device_uid: u64,
+    record: Option<PairingRecord>,
+}
+
+impl<S: NvmStorage> PairingManager<S> {
+    /// Restores a previous pairing from `nvm`, if there is one.
+    pub fn load(mut nvm: S, device_uid: u64) -> Self {
+        let mut buf = [0u8; RECORD_LEN];
+        let record = match nvm.read(PAIRING_RECORD_OFFSET, &mut buf) {
+            Ok(()) => PairingRecord::decode(&buf),
+            Err(_) => None,
+        };
+
+        Self { nvm, device_uid, record }
+    }
+
+    pub fn announce(&self) -> EmbeddedResponse {
+        EmbeddedResponse::Announce {
+            device_uid: self.device_uid,
+            sensor_id: self.sensor_id(),
+        }
+    }
+
+    /// Handles the pairing commands; returns `None` for everything else so the
+    /// caller can pass the command on to the protocol handler.
+    pub fn handle_command(&mut self, command: &EmbeddedCommand) -> Option<EmbeddedResponse> {
+        let response = match command {
+            EmbeddedCommand::Identify => self.announce(),
+            EmbeddedCommand::Pair { device_uid, sensor_id, key } => {
+                if *device_uid != self.device_uid {
+                    // Addressed to another node on the same bus
+                    return None;
+                }
+                match self.pair(PairingRecord { sensor_id: *sensor_id, key: *key }) {
+                    Ok(()) => EmbeddedResponse::Paired { sensor_id: *sensor_id },
+                    Err(e) => EmbeddedResponse::Error(e.error_code()),
+                }
+            }
+            EmbeddedCommand::Unpair { device_uid } => {
+                if *device_uid != self.device_uid {
+                    return None;
+                }
+                match self.unpair() {
+                    Ok(()) => self.announce(),
+                    Err(e) => EmbeddedResponse::Error(e.error_code()),
+                }
+            }
+            _ => return None,
+        };
+        Some(response)
+    }
+
+    pub fn pair(&mut self, record: PairingRecord) -> Result<(), EmbeddedError> {
+        self.nvm
+            .write(PAIRING_RECORD_OFFSET, &record.encode())
+            .map_err(|_| EmbeddedError::PairingFailed)?;
+        self.record = Some(record);
+        Ok(())
+    }
+
+    pub fn unpair(&mut self) -> Result<(), EmbeddedError> {
+        self.nvm
+            .write(PAIRING_RECORD_OFFSET, &[0xFF; RECORD_LEN])
+            .map_err(|_| EmbeddedError::PairingFailed)?;
+        self.record = None;
+        Ok(())
+    }
+
+    pub fn is_paired(&self) -> bool {
+        self.record.is_some()
+    }
+
+    pub fn sensor_id(&self) -> Option<u16> {
+        self.record.map(|r| r.sensor_id)
+    }
+
+    pub fn key(&self) -> Option<&[u8; PAIRING_KEY_LEN]> {
+        self.record.as_ref().map(|r| &r.key)
+    }
+
+    pub fn device_uid(&self) -> u64 {
+        self.device_uid
+    }
+
+    pub fn into_nvm(self) -> S {
+        self.nvm
+    }
+}
+
+/// RAM-backed storage, useful for tests and the desktop simulation.
+pub struct RamNvm<const N: usize> {
+    data: [u8; N],
+}
+
+impl<const N: usize> RamNvm<N> {
+    pub const fn new() -> Self {
+        Self { data: [0xFF; N] }
+    }
+}
+
+impl<const N: usize> Default for RamNvm<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> NvmStorage for RamNvm<N> {
+    type Error = EmbeddedError;
+    const LEN: Option<usize> = Some(N);
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error> {
+        let start = offset as usize;
+        let src = self.data.get(start..start + buf.len()).ok_or(EmbeddedError::BufferFull)?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error> {
+        let start = offset as usize;
+        let dst = self.data.get_mut(start..start + data.len()).ok_or(EmbeddedError::BufferFull)?;
+        dst.copy_from_slice(data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UID: u64 = 0xC3_0000_1234;
+
+    #[test]
+    fn fresh_node_announces_unpaired() {
+        let manager = PairingManager::load(RamNvm::<64>::new(), UID);
+
+        assert!(!manager.is_paired());
+        assert_eq!(
+            manager.announce(),
+            EmbeddedResponse::Announce { device_uid: UID, sensor_id: None }
+        );
+    }
+
+    #[test]
+    fn pairing_survives_reload() {
+        let mut manager = PairingManager::load(RamNvm::<64>::new(), UID);
+
+        let response = manager.handle_command(&EmbeddedCommand::Pair {
+            device_uid: UID,
+            sensor_id: 7,
+            key: [0xAB; PAIRING_KEY_LEN],
+        });
+        assert_eq!(response, Some(EmbeddedResponse::Paired { sensor_id: 7 }));
+
+        // Simulated reset: reload from the same memory
+        let manager = PairingManager::load(manager.into_nvm(), UID);
+        assert_eq!(manager.sensor_id(), Some(7));
+        assert_eq!(manager.key(), Some(&[0xAB; PAIRING_KEY_LEN]));
+    }
+
+    #[test]
+    fn ignores_commands_for_other_nodes() {
+        let mut manager = PairingManager::load(RamNvm::<64>::new(), UID);
+
+        let response = manager.handle_command(&EmbeddedCommand::Pair {
+            device_uid: UID + 1,
+            sensor_id: 7,
+            key: [0; PAIRING_KEY_LEN],
+        });
+        assert_eq!(response, None);
+        assert_eq!(manager.handle_command(&EmbeddedCommand::GetStatus), None);
+        assert!(!manager.is_paired());
+    }
+
+    #[test]
+    fn unpair_clears_record() {
+        let mut manager = PairingManager::load(RamNvm::<64>::new(), UID);
+        manager.pair(PairingRecord { sensor_id: 3, key: [1; PAIRING_KEY_LEN] }).unwrap();
+
+        let response = manager.handle_command(&EmbeddedCommand::Unpair { device_uid: UID });
+        assert_eq!(response, Some(EmbeddedResponse::Announce { device_uid: UID, sensor_id: None }));
+
+        let manager = PairingManager::load(manager.into_nvm(), UID);
+        assert!(!manager.is_paired());
+    }
+
+    #[test]
+    fn corrupted_record_is_ignored() {
+        let mut nvm = RamNvm::<64>::new();
+        let mut record = PairingRecord { sensor_id: 9, key: [2; PAIRING_KEY_LEN] }.encode();
+        record[5] ^= 0x01;
+        nvm.write(PAIRING_RECORD_OFFSET, &record).unwrap();
+
+        let manager = PairingManager::load(nvm, UID);
+        assert!(!manager.is_paired());
+    }
+}
@@ -0,0 +1,50 @@
+//! Wall-clock timestamps from a real-time clock.
+//!
+//! Without an RTC a node only knows its seconds since boot, which the host
+//! has to map to wall-clock time and which restart at every reset. A node
+//! with an RTC implements [`RtcProvider`] and samples through
+//! [`EmbeddedProtocolHandler::sample_with_rtc`](crate::EmbeddedProtocolHandler::sample_with_rtc),
+//! which stamps readings with Unix time instead. An RTC that has not been
+//! set, or that lost its time with its backup battery, reads as the chip's
+//! reset date; anything before [`MIN_PLAUSIBLE_UNIX_TIME`] falls back to
+//! seconds since boot. Each reading's [`TimeSource`] says which it got.
+
+use serde::{Deserialize, Serialize};
+
+/// 2020-01-01 00:00 UTC. RTC chips reset to 2000-01-01 or 1970-01-01.
+pub const MIN_PLAUSIBLE_UNIX_TIME: u32 = 1_577_836_800;
+
+/// A real-time clock, e.g. a DS3231 on I2C or the MCU's own RTC peripheral.
+pub trait RtcProvider {
+    /// The current Unix time in seconds, or `None` if the clock cannot be
+    /// read or knows it has not been set.
+    fn unix_time(&mut self) -> Option<u32>;
+}
+
+/// For nodes without a clock: every reading is stamped since boot.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoRtc;
+
+impl RtcProvider for NoRtc {
+    fn unix_time(&mut self) -> Option<u32> {
+        None
+    }
+}
+
+/// What a reading's timestamp counts from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeSource {
+    /// Seconds since the node booted.
+    #[default]
+    Uptime,
+    /// Unix time from the node's RTC.
+    Rtc,
+}
+
+/// The RTC's time if it has a plausible one, `uptime` otherwise.
+pub fn timestamp<R: RtcProvider + ?Sized>(rtc: &mut R, uptime: u32) -> (u32, TimeSource) {
+    match rtc.unix_time() {
+        Some(time) if time >= MIN_PLAUSIBLE_UNIX_TIME => (time, TimeSource::Rtc),
+        _ => (uptime, TimeSource::Uptime),
+    }
+}
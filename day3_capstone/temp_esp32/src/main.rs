@@ -10,7 +10,7 @@
 
 use temp_embedded::{
     EmbeddedTemperatureStore, EmbeddedProtocolHandler, EmbeddedCommand, EmbeddedResponse,
-    EmbeddedTemperatureReading, Temperature, READING_BUFFER_SIZE
+    EmbeddedTemperatureReading, EmbeddedError, Temperature, ADC_CONFIG, READING_BUFFER_SIZE
 };
 
 #[cfg(feature = "simulation")]
@@ -101,7 +101,7 @@ fn simulate_monitoring_loop(
     for cycle in 0..50 {
         // Simulate ADC reading from temperature sensor
         let adc_value = simulate_adc_reading(reading_count);
-        let temperature = Temperature::from_embedded_sensor(adc_value);
+        let temperature = Temperature::from_adc(adc_value, &ADC_CONFIG);
         let timestamp = get_current_timestamp();
 
         // Add reading to the system
@@ -144,7 +144,8 @@ fn print_status_update(
         uptime_seconds,
         reading_count,
         sample_rate,
-        buffer_usage
+        buffer_usage,
+        ..
     } = status_response {
         println!("  ⏱️  Uptime: {}s", uptime_seconds);
         println!("  📊 Readings: {}", reading_count);
@@ -257,7 +258,7 @@ fn main() -> ! {
     loop {
         // Simulate temperature reading (in real hardware, read from sensor)
         let adc_value = simulate_adc_reading_hardware(reading_count);
-        let temperature = Temperature::from_embedded_sensor(adc_value);
+        let temperature = Temperature::from_adc(adc_value, &ADC_CONFIG);
 
         // Get timestamp (simple counter for this demo)
         let timestamp = get_hardware_timestamp();
@@ -305,9 +306,9 @@ fn demonstrate_json_commands(protocol_handler: &mut EmbeddedProtocolHandler<READ
     esp_println!("📝 Demonstrating JSON command processing with serde:");
 
     // Add some sample readings first
-    let temp1 = Temperature::from_embedded_sensor(simulate_adc_reading_hardware(0));
-    let temp2 = Temperature::from_embedded_sensor(simulate_adc_reading_hardware(10));
-    let temp3 = Temperature::from_embedded_sensor(simulate_adc_reading_hardware(20));
+    let temp1 = Temperature::from_adc(simulate_adc_reading_hardware(0), &ADC_CONFIG);
+    let temp2 = Temperature::from_adc(simulate_adc_reading_hardware(10), &ADC_CONFIG);
+    let temp3 = Temperature::from_adc(simulate_adc_reading_hardware(20), &ADC_CONFIG);
 
     let _ = protocol_handler.add_reading(temp1, timestamp);
     let _ = protocol_handler.add_reading(temp2, timestamp + 1);
@@ -358,11 +359,7 @@ fn simulate_adc_reading(count: u32) -> u16 {
     let variation = libm::sinf((count as f32) * 0.1) * 5.0;
     let temp_celsius = base_temp + variation;
 
-    // Convert to 12-bit ADC value
-    // Assuming 10mV/°C sensor, 3.3V reference
-    let voltage = temp_celsius * 0.01; // 10mV/°C
-    let adc_value: f32 = (voltage / 3.3) * 4095.0;
-    if adc_value < 0.0 { 0 } else if adc_value > 4095.0 { 4095 } else { adc_value as u16 }
+    ADC_CONFIG.to_count(temp_celsius)
 }
 
 #[cfg(feature = "hardware")]
@@ -373,10 +370,7 @@ fn simulate_adc_reading_hardware(count: u32) -> u16 {
     let variation = ((count % 100) as f32 / 10.0) - 5.0; // ±5°C variation
     let temp_celsius = base_temp + variation;
 
-    // Convert to 12-bit ADC value
-    let voltage = temp_celsius * 0.01; // 10mV/°C sensor
-    let adc_value: f32 = (voltage / 3.3) * 4095.0;
-    if adc_value < 0.0 { 0 } else if adc_value > 4095.0 { 4095 } else { adc_value as u16 }
+    ADC_CONFIG.to_count(temp_celsius)
 }
 
 #[cfg(feature = "simulation")]
@@ -425,7 +419,7 @@ impl ESP32TemperatureMonitor {
         }
     }
 
-    pub fn add_temperature_reading(&mut self, celsius: f32) -> Result<(), &'static str> {
+    pub fn add_temperature_reading(&mut self, celsius: f32) -> Result<(), EmbeddedError> {
         let temperature = Temperature::new(celsius);
         let timestamp = self.get_timestamp();
         self.protocol_handler.add_reading(temperature, timestamp)
@@ -519,7 +513,7 @@ mod tests {
             assert!(adc_value <= 4095);
 
             // Convert back to temperature to verify range
-            let temp = Temperature::from_embedded_sensor(adc_value);
+            let temp = Temperature::from_adc(adc_value, &ADC_CONFIG);
             // Should be roughly 20-30°C for our simulation
             assert!(temp.celsius >= 18.0 && temp.celsius <= 32.0);
         }
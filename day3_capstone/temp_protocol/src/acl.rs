@@ -0,0 +1,209 @@
+//! Access control lists binding identities to sensors and zones.
+//!
+//! In a shared deployment each tenant should only see their own rooms. An
+//! [`AccessControl`] lists [`Grant`]s of read or write permission on a
+//! sensor, on every sensor in a zone, or on the whole site. A handler with
+//! one checks every command against the session's [`Identity`]:
+//! - commands on a sensor the session may not read are answered as if the
+//!   sensor did not exist, so tenants cannot probe for each other's sensors;
+//!   lacking only write permission gives a 403,
+//! - listings such as `GetStatus` or `ListAlerts` leave out what the session
+//!   may not read,
+//! - site-wide settings, like the time zone or command flags, need a grant
+//!   on the site.
+//!
+//! Sessions that did not authenticate only get what is granted to everyone.
+//! Write permission includes read permission.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Identity;
+
+/// Write includes read.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    Read,
+    Write,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Permission::Read => write!(f, "read"),
+            Permission::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// Whom a grant is for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Principal {
+    Subject(String),
+    Role(String),
+    /// Every session, authenticated or not.
+    Everyone,
+}
+
+impl Principal {
+    fn matches(&self, identity: Option<&Identity>) -> bool {
+        match self {
+            Principal::Subject(subject) => identity.is_some_and(|i| &i.subject == subject),
+            Principal::Role(role) => identity.is_some_and(|i| i.has_role(role)),
+            Principal::Everyone => true,
+        }
+    }
+}
+
+/// What a grant is on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Resource {
+    Sensor(String),
+    /// Every sensor currently in the zone.
+    Zone(String),
+    /// Every sensor and the site-wide settings.
+    Site,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Grant {
+    pub principal: Principal,
+    pub resource: Resource,
+    pub permission: Permission,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccessControl {
+    grants: Vec<Grant>,
+}
+
+impl AccessControl {
+    /// Grants nothing to anyone.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn grant(mut self, principal: Principal, resource: Resource, permission: Permission) -> Self {
+        self.grants.push(Grant { principal, resource, permission });
+        self
+    }
+
+    /// Reads an ACL file of `<principal> <resource> <permission>` lines,
+    /// see [`parse`](Self::parse).
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses `<principal> <resource> <permission>` lines, skipping blank
+    /// lines and `#` comments. A principal is a subject, `role:<name>` or
+    /// `*` for everyone; a resource `sensor:<id>`, `zone:<name>` or `*` for
+    /// the site; the permission `read` or `write`.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut acl = Self::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number + 1, message));
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [principal, resource, permission] = fields[..] else {
+                return Err(invalid("expected a principal, a resource and a permission"));
+            };
+            let principal = match principal {
+                "*" => Principal::Everyone,
+                _ => match principal.strip_prefix("role:") {
+                    Some(role) => Principal::Role(role.to_string()),
+                    None => Principal::Subject(principal.to_string()),
+                },
+            };
+            let resource = match resource.split_once(':') {
+                _ if resource == "*" => Resource::Site,
+                Some(("sensor", id)) if !id.is_empty() => Resource::Sensor(id.to_string()),
+                Some(("zone", zone)) if !zone.is_empty() => Resource::Zone(zone.to_string()),
+                _ => return Err(invalid("resources are sensor:<id>, zone:<name> or *")),
+            };
+            let permission = match permission {
+                "read" => Permission::Read,
+                "write" => Permission::Write,
+                _ => return Err(invalid("permissions are read or write")),
+            };
+            acl = acl.grant(principal, resource, permission);
+        }
+        Ok(acl)
+    }
+
+    pub fn grants(&self) -> &[Grant] {
+        &self.grants
+    }
+
+    fn allows(&self, identity: Option<&Identity>, permission: Permission, covers: impl Fn(&Resource) -> bool) -> bool {
+        self.grants
+            .iter()
+            .any(|grant| grant.permission >= permission && grant.principal.matches(identity) && covers(&grant.resource))
+    }
+
+    /// Whether `identity` may access the sensor, which is in `zone` if any.
+    pub fn allows_sensor(&self, identity: Option<&Identity>, sensor_id: &str, zone: Option<&str>, permission: Permission) -> bool {
+        self.allows(identity, permission, |resource| match resource {
+            Resource::Sensor(id) => id == sensor_id,
+            Resource::Zone(name) => zone == Some(name.as_str()),
+            Resource::Site => true,
+        })
+    }
+
+    /// Whether `identity` may access the zone as a whole, e.g. move sensors into it.
+    pub fn allows_zone(&self, identity: Option<&Identity>, zone: &str, permission: Permission) -> bool {
+        self.allows(identity, permission, |resource| match resource {
+            Resource::Sensor(_) => false,
+            Resource::Zone(name) => name == zone,
+            Resource::Site => true,
+        })
+    }
+
+    pub fn allows_site(&self, identity: Option<&Identity>, permission: Permission) -> bool {
+        self.allows(identity, permission, |resource| *resource == Resource::Site)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grants_from_a_file() {
+        let acl = AccessControl::parse(
+            "# tenants\n\
+             alice zone:flat_a write\n\
+             role:auditor * read\n\
+             * sensor:outdoor read\n",
+        )
+        .unwrap();
+        assert_eq!(acl.grants().len(), 3);
+
+        let alice = Identity::new("alice");
+        let bob = Identity::new("bob").with_roles(["auditor"]);
+        assert!(acl.allows_sensor(Some(&alice), "kitchen", Some("flat_a"), Permission::Write));
+        assert!(!acl.allows_sensor(Some(&alice), "bedroom", Some("flat_b"), Permission::Read));
+        assert!(acl.allows_zone(Some(&alice), "flat_a", Permission::Write));
+        assert!(!acl.allows_site(Some(&alice), Permission::Read));
+
+        assert!(acl.allows_sensor(Some(&bob), "bedroom", Some("flat_b"), Permission::Read));
+        assert!(!acl.allows_sensor(Some(&bob), "bedroom", Some("flat_b"), Permission::Write));
+        assert!(acl.allows_site(Some(&bob), Permission::Read));
+
+        assert!(acl.allows_sensor(None, "outdoor", None, Permission::Read));
+        assert!(!acl.allows_sensor(None, "kitchen", Some("flat_a"), Permission::Read));
+
+        for bad in ["alice zone:flat_a", "alice room:1 read", "alice * own", "alice sensor: read"] {
+            assert_eq!(AccessControl::parse(bad).unwrap_err().kind(), io::ErrorKind::InvalidData, "{}", bad);
+        }
+    }
+}
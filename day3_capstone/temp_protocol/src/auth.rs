@@ -0,0 +1,625 @@
+//! Pluggable authentication of protocol sessions.
+//!
+//! A handler without an [`AuthProvider`] answers every session, as suits a
+//! home lab. With one, a session runs nothing but `Authenticate` and
+//! `SetLanguage` until it has authenticated; everything else is answered
+//! with a 401. The provider turns [`Credentials`] into an [`Identity`],
+//! which the session then keeps like its language, see
+//! [`SessionState`](crate::SessionState). What the identity may then
+//! access is up to the handler's [`acl`](crate::acl).
+//!
+//! [`AuthConfig`] picks one of the providers that come with the crate:
+//! - [`StaticTokens`], bearer tokens listed in a file,
+//! - [`JwtValidator`], HS256 JSON Web Tokens issued by an identity provider,
+//! - [`ClientCertificates`], peers known by the SHA-256 fingerprint of their
+//!   TLS client certificate. The certificate is handed over by whatever
+//!   terminated TLS, through
+//!   [`authenticate`](crate::TemperatureProtocolHandler::authenticate),
+//!   never by a command.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "std")]
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+#[cfg(feature = "std")]
+use base64::Engine;
+#[cfg(feature = "std")]
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use sha2::{Digest, Sha256};
+#[cfg(feature = "std")]
+use temp_core::clock::{Clock, SystemClock};
+
+/// Who a session authenticated as.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub subject: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Unix time the identity stops being valid, from a token's expiry.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+impl Identity {
+    pub fn new(subject: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            roles: Vec::new(),
+            expires_at: None,
+        }
+    }
+
+    pub fn with_roles<I, S>(mut self, roles: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.roles = roles.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| now >= at)
+    }
+}
+
+/// What a session presents to authenticate.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// A bearer token sent with `Authenticate`, static or a JWT.
+    Token(String),
+    /// The DER encoded certificate a TLS client presented.
+    ClientCertificate(Vec<u8>),
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credentials::Token(_) => f.write_str("Token(<redacted>)"),
+            Credentials::ClientCertificate(der) => write!(f, "ClientCertificate({} bytes)", der.len()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// The provider does not take this kind of credentials.
+    Unsupported,
+    /// An unknown token or certificate.
+    Rejected,
+    /// Not a well-formed JWT, or one not signed with HS256.
+    Malformed(String),
+    InvalidSignature,
+    Expired,
+    NotYetValid,
+    /// The named claim does not match the configured one.
+    WrongClaim(&'static str),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Unsupported => write!(f, "credentials of this kind are not accepted"),
+            AuthError::Rejected => write!(f, "unknown credentials"),
+            AuthError::Malformed(reason) => write!(f, "malformed token: {}", reason),
+            AuthError::InvalidSignature => write!(f, "invalid token signature"),
+            AuthError::Expired => write!(f, "token expired"),
+            AuthError::NotYetValid => write!(f, "token not valid yet"),
+            AuthError::WrongClaim(claim) => write!(f, "unexpected {} claim", claim),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AuthError {}
+
+pub trait AuthProvider: Send {
+    fn authenticate(&self, credentials: &Credentials) -> Result<Identity, AuthError>;
+}
+
+impl<P: AuthProvider + ?Sized> AuthProvider for Box<P> {
+    fn authenticate(&self, credentials: &Credentials) -> Result<Identity, AuthError> {
+        (**self).authenticate(credentials)
+    }
+}
+
+#[cfg(feature = "std")]
+type Digest256 = [u8; 32];
+
+#[cfg(feature = "std")]
+fn sha256(data: &[u8]) -> Digest256 {
+    Sha256::digest(data).into()
+}
+
+/// Parses a file of `<secret> <subject> [role,role...]` lines, skipping
+/// blank lines and `#` comments.
+#[cfg(feature = "std")]
+fn parse_entries(text: &str) -> io::Result<Vec<(&str, Identity)>> {
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (Some(secret), Some(subject)) = (fields.next(), fields.next()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: expected a secret and a subject", number + 1),
+            ));
+        };
+        let roles = fields.next().map(|roles| roles.split(',').filter(|r| !r.is_empty()).collect::<Vec<_>>());
+        if fields.next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: roles must be separated by commas only", number + 1),
+            ));
+        }
+        entries.push((secret, Identity::new(subject).with_roles(roles.unwrap_or_default())));
+    }
+    Ok(entries)
+}
+
+/// Bearer tokens, each standing for a fixed identity. Only digests of the
+/// tokens are kept.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct StaticTokens {
+    tokens: HashMap<Digest256, Identity>,
+}
+
+#[cfg(feature = "std")]
+impl StaticTokens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_token(mut self, token: &str, identity: Identity) -> Self {
+        self.tokens.insert(sha256(token.as_bytes()), identity);
+        self
+    }
+
+    /// Reads a token file of `<token> <subject> [role,role...]` lines.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let tokens = parse_entries(text)?
+            .into_iter()
+            .fold(Self::new(), |tokens, (token, identity)| tokens.with_token(token, identity));
+        Ok(tokens)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+}
+
+#[cfg(feature = "std")]
+impl AuthProvider for StaticTokens {
+    fn authenticate(&self, credentials: &Credentials) -> Result<Identity, AuthError> {
+        let Credentials::Token(token) = credentials else {
+            return Err(AuthError::Unsupported);
+        };
+        self.tokens.get(&sha256(token.as_bytes())).cloned().ok_or(AuthError::Rejected)
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+#[cfg(feature = "std")]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[cfg(feature = "std")]
+impl Audience {
+    fn contains(&self, audience: &str) -> bool {
+        match self {
+            Audience::One(one) => one == audience,
+            Audience::Many(many) => many.iter().any(|a| a == audience),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Deserialize)]
+struct JwtClaims {
+    sub: String,
+    exp: Option<u64>,
+    nbf: Option<u64>,
+    iss: Option<String>,
+    aud: Option<Audience>,
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+/// Validates HS256 JSON Web Tokens signed with a shared secret. The `sub`
+/// claim becomes the subject and a `roles` array claim the roles; a token
+/// without `exp` does not expire.
+#[cfg(feature = "std")]
+pub struct JwtValidator {
+    secret: Vec<u8>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    /// Seconds of clock skew tolerated on `exp` and `nbf`.
+    leeway: u64,
+    clock: Box<dyn Clock>,
+}
+
+#[cfg(feature = "std")]
+impl JwtValidator {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+            issuer: None,
+            audience: None,
+            leeway: 0,
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Only accepts tokens whose `iss` is `issuer`.
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// Only accepts tokens whose `aud` names `audience`.
+    pub fn audience(mut self, audience: impl Into<String>) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+
+    pub fn leeway(mut self, seconds: u64) -> Self {
+        self.leeway = seconds;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    fn decode(part: &str, what: &str) -> Result<Vec<u8>, AuthError> {
+        URL_SAFE_NO_PAD
+            .decode(part)
+            .map_err(|e| AuthError::Malformed(format!("{} is not base64url: {}", what, e)))
+    }
+
+    pub fn validate(&self, token: &str) -> Result<Identity, AuthError> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(claims), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(AuthError::Malformed("expected three parts".to_string()));
+        };
+
+        let parsed: JwtHeader = serde_json::from_slice(&Self::decode(header, "header")?)
+            .map_err(|e| AuthError::Malformed(format!("header: {}", e)))?;
+        // Anything but the one algorithm we verify, "none" in particular, is refused
+        if parsed.alg != "HS256" {
+            return Err(AuthError::Malformed(format!("unsupported algorithm {}", parsed.alg)));
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret)
+            .map_err(|e| AuthError::Malformed(e.to_string()))?;
+        mac.update(header.as_bytes());
+        mac.update(b".");
+        mac.update(claims.as_bytes());
+        mac.verify_slice(&Self::decode(signature, "signature")?)
+            .map_err(|_| AuthError::InvalidSignature)?;
+
+        let claims: JwtClaims = serde_json::from_slice(&Self::decode(claims, "claims")?)
+            .map_err(|e| AuthError::Malformed(format!("claims: {}", e)))?;
+        let now = self.clock.now();
+        if claims.exp.is_some_and(|exp| now >= exp.saturating_add(self.leeway)) {
+            return Err(AuthError::Expired);
+        }
+        if claims.nbf.is_some_and(|nbf| now.saturating_add(self.leeway) < nbf) {
+            return Err(AuthError::NotYetValid);
+        }
+        if let Some(issuer) = &self.issuer {
+            if claims.iss.as_ref() != Some(issuer) {
+                return Err(AuthError::WrongClaim("iss"));
+            }
+        }
+        if let Some(audience) = &self.audience {
+            if !claims.aud.is_some_and(|aud| aud.contains(audience)) {
+                return Err(AuthError::WrongClaim("aud"));
+            }
+        }
+        Ok(Identity {
+            subject: claims.sub,
+            roles: claims.roles,
+            expires_at: claims.exp,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl AuthProvider for JwtValidator {
+    fn authenticate(&self, credentials: &Credentials) -> Result<Identity, AuthError> {
+        match credentials {
+            Credentials::Token(token) => self.validate(token),
+            Credentials::ClientCertificate(_) => Err(AuthError::Unsupported),
+        }
+    }
+}
+
+/// TLS client certificates, known by their SHA-256 fingerprint.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct ClientCertificates {
+    fingerprints: HashMap<Digest256, Identity>,
+}
+
+#[cfg(feature = "std")]
+impl ClientCertificates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts the certificate with `fingerprint`, in hex with or without
+    /// colons as `openssl x509 -fingerprint -sha256` prints it.
+    pub fn with_fingerprint(mut self, fingerprint: &str, identity: Identity) -> io::Result<Self> {
+        let digest = parse_fingerprint(fingerprint).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Invalid SHA-256 fingerprint {}", fingerprint))
+        })?;
+        self.fingerprints.insert(digest, identity);
+        Ok(self)
+    }
+
+    /// Reads a file of `<fingerprint> <subject> [role,role...]` lines.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        parse_entries(text)?
+            .into_iter()
+            .try_fold(Self::new(), |certificates, (fingerprint, identity)| {
+                certificates.with_fingerprint(fingerprint, identity)
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fingerprints.is_empty()
+    }
+}
+
+#[cfg(feature = "std")]
+fn parse_fingerprint(fingerprint: &str) -> Option<Digest256> {
+    let hex: Vec<u8> = fingerprint.bytes().filter(|b| *b != b':').collect();
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut digest = [0; 32];
+    for (byte, pair) in digest.iter_mut().zip(hex.chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[cfg(feature = "std")]
+impl AuthProvider for ClientCertificates {
+    fn authenticate(&self, credentials: &Credentials) -> Result<Identity, AuthError> {
+        let Credentials::ClientCertificate(der) = credentials else {
+            return Err(AuthError::Unsupported);
+        };
+        self.fingerprints.get(&sha256(der)).cloned().ok_or(AuthError::Rejected)
+    }
+}
+
+/// The provider a server authenticates sessions with, e.g. in TOML
+/// `auth = { provider = "jwt", secret_file = "/etc/temp/jwt.key", issuer = "https://sso.example.com" }`.
+/// Secrets are read from files so they stay out of the config.
+#[cfg(feature = "std")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum AuthConfig {
+    /// A [`StaticTokens`] file.
+    Tokens { path: PathBuf },
+    Jwt {
+        secret_file: PathBuf,
+        #[serde(default)]
+        issuer: Option<String>,
+        #[serde(default)]
+        audience: Option<String>,
+        #[serde(default)]
+        leeway_seconds: u64,
+    },
+    /// A [`ClientCertificates`] file.
+    ClientCertificate { path: PathBuf },
+}
+
+#[cfg(feature = "std")]
+impl AuthConfig {
+    /// Reads the files the provider needs.
+    pub fn build(&self) -> io::Result<Box<dyn AuthProvider>> {
+        match self {
+            AuthConfig::Tokens { path } => Ok(Box::new(StaticTokens::load(path)?)),
+            AuthConfig::Jwt { secret_file, issuer, audience, leeway_seconds } => {
+                let mut secret = std::fs::read(secret_file)?;
+                while secret.last().is_some_and(u8::is_ascii_whitespace) {
+                    secret.pop();
+                }
+                if secret.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} holds no JWT secret", secret_file.display()),
+                    ));
+                }
+                let mut validator = JwtValidator::new(secret).leeway(*leeway_seconds);
+                if let Some(issuer) = issuer {
+                    validator = validator.issuer(issuer);
+                }
+                if let Some(audience) = audience {
+                    validator = validator.audience(audience);
+                }
+                Ok(Box::new(validator))
+            }
+            AuthConfig::ClientCertificate { path } => Ok(Box::new(ClientCertificates::load(path)?)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use temp_core::clock::ManualClock;
+
+    const SECRET: &[u8] = b"correct horse battery staple";
+
+    fn jwt(header: &str, claims: &str, secret: &[u8]) -> String {
+        let signed = format!("{}.{}", URL_SAFE_NO_PAD.encode(header), URL_SAFE_NO_PAD.encode(claims));
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(signed.as_bytes());
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+    }
+
+    fn token(claims: &str) -> Credentials {
+        Credentials::Token(jwt(r#"{"alg":"HS256","typ":"JWT"}"#, claims, SECRET))
+    }
+
+    #[test]
+    fn static_tokens_from_a_file() {
+        let tokens = StaticTokens::parse("# home lab\n\nk1tchen-display kitchen\ns3cret admin admin,operator\n").unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens.authenticate(&Credentials::Token("k1tchen-display".into())), Ok(Identity::new("kitchen")));
+        let admin = tokens.authenticate(&Credentials::Token("s3cret".into())).unwrap();
+        assert!(admin.has_role("operator") && admin.has_role("admin"));
+        assert_eq!(tokens.authenticate(&Credentials::Token("guess".into())), Err(AuthError::Rejected));
+        assert_eq!(tokens.authenticate(&Credentials::ClientCertificate(vec![1])), Err(AuthError::Unsupported));
+
+        let err = StaticTokens::parse("ok subject\nlonely\n").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn jwt_claims_become_the_identity() {
+        let validator = JwtValidator::new(SECRET).with_clock(ManualClock::new(1_000));
+        let identity = validator
+            .authenticate(&token(r#"{"sub":"alice","exp":2000,"roles":["operator"]}"#))
+            .unwrap();
+        assert_eq!(identity, Identity { subject: "alice".into(), roles: vec!["operator".into()], expires_at: Some(2000) });
+        assert!(!identity.is_expired(1_999) && identity.is_expired(2_000));
+    }
+
+    #[test]
+    fn jwt_validation_failures() {
+        let clock = ManualClock::new(1_000);
+        let validator = JwtValidator::new(SECRET)
+            .issuer("https://sso.example.com")
+            .audience("temp")
+            .leeway(30)
+            .with_clock(clock.clone());
+        let valid = r#"{"sub":"bob","iss":"https://sso.example.com","aud":["temp","grafana"]"#;
+
+        assert!(validator.authenticate(&token(&format!("{},\"exp\":1010}}", valid))).is_ok());
+        // Within the leeway
+        assert!(validator.authenticate(&token(&format!("{},\"exp\":990}}", valid))).is_ok());
+        assert_eq!(validator.authenticate(&token(&format!("{},\"exp\":960}}", valid))), Err(AuthError::Expired));
+        assert_eq!(validator.authenticate(&token(&format!("{},\"nbf\":1100}}", valid))), Err(AuthError::NotYetValid));
+        assert_eq!(
+            validator.authenticate(&token(r#"{"sub":"bob","iss":"https://evil.example.com","aud":"temp"}"#)),
+            Err(AuthError::WrongClaim("iss"))
+        );
+        assert_eq!(
+            validator.authenticate(&token(r#"{"sub":"bob","iss":"https://sso.example.com"}"#)),
+            Err(AuthError::WrongClaim("aud"))
+        );
+
+        let forged = jwt(r#"{"alg":"HS256"}"#, r#"{"sub":"mallory"}"#, b"another secret");
+        assert_eq!(validator.validate(&forged), Err(AuthError::InvalidSignature));
+        let unsigned = format!(
+            "{}.{}.",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#),
+            URL_SAFE_NO_PAD.encode(r#"{"sub":"mallory"}"#)
+        );
+        assert!(matches!(validator.validate(&unsigned), Err(AuthError::Malformed(_))));
+        assert!(matches!(validator.validate("not.a-jwt"), Err(AuthError::Malformed(_))));
+    }
+
+    #[test]
+    fn client_certificates_by_fingerprint() {
+        let der = b"pretend this is DER".to_vec();
+        let hex: Vec<String> = sha256(&der).iter().map(|b| format!("{:02X}", b)).collect();
+        let certificates = ClientCertificates::parse(&format!("{} gateway-01 gateway\n", hex.join(":"))).unwrap();
+
+        let identity = certificates.authenticate(&Credentials::ClientCertificate(der)).unwrap();
+        assert_eq!(identity, Identity::new("gateway-01").with_roles(["gateway"]));
+        assert_eq!(
+            certificates.authenticate(&Credentials::ClientCertificate(b"other".to_vec())),
+            Err(AuthError::Rejected)
+        );
+        // A token cannot stand in for a certificate
+        assert_eq!(certificates.authenticate(&Credentials::Token(hex.concat())), Err(AuthError::Unsupported));
+        assert!(ClientCertificates::parse("abcd gateway\n").is_err());
+    }
+
+    #[test]
+    fn config_selects_the_provider() {
+        let dir = std::env::temp_dir().join(format!("temp_auth_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("jwt.key"), b"correct horse battery staple\n").unwrap();
+        std::fs::write(dir.join("tokens"), "s3cret admin\n").unwrap();
+
+        let config: AuthConfig = config_from(&format!(
+            r#"{{"provider":"jwt","secret_file":{:?},"audience":"temp"}}"#,
+            dir.join("jwt.key")
+        ));
+        let provider = config.build().unwrap();
+        // The trailing newline of the secret file is not part of the secret
+        let identity = provider.authenticate(&token(r#"{"sub":"carol","aud":"temp"}"#)).unwrap();
+        assert_eq!(identity.subject, "carol");
+
+        let config: AuthConfig = config_from(&format!(r#"{{"provider":"tokens","path":{:?}}}"#, dir.join("tokens")));
+        let provider = config.build().unwrap();
+        assert_eq!(provider.authenticate(&Credentials::Token("s3cret".into())), Ok(Identity::new("admin")));
+
+        std::fs::write(dir.join("empty.key"), b"\n").unwrap();
+        let empty = AuthConfig::Jwt {
+            secret_file: dir.join("empty.key"),
+            issuer: None,
+            audience: None,
+            leeway_seconds: 0,
+        };
+        assert_eq!(empty.build().err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn config_from(json: &str) -> AuthConfig {
+        serde_json::from_str(json).unwrap()
+    }
+}
@@ -0,0 +1,90 @@
+//! Pooled buffers for answering at high request rates.
+//!
+//! Every history response carries a batch of readings copied out of a store,
+//! and every response is encoded into a buffer of its own. The handler takes
+//! history batches from its [`MessageBuffers`]; a transport that encodes with
+//! [`MessageBuffers::encode_json`] and hands each response back with
+//! [`MessageBuffers::recycle`] once sent reuses both instead of allocating
+//! them per request.
+
+use temp_store::{PoolStats, Pooled, ReadingPool, TemperatureReading, VecPool};
+
+use crate::{MessagePayload, ProtocolMessage, Response};
+
+/// The handler's pools. Clones share them, so a transport can keep one
+/// without holding the handler lock.
+#[derive(Clone, Debug, Default)]
+pub struct MessageBuffers {
+    readings: ReadingPool,
+    encoded: VecPool<u8>,
+}
+
+impl MessageBuffers {
+    pub fn new(readings: ReadingPool, encoded: VecPool<u8>) -> Self {
+        Self { readings, encoded }
+    }
+
+    pub(crate) fn take_batch(&self) -> Vec<TemperatureReading> {
+        self.readings.take()
+    }
+
+    pub fn encode_json(&self, message: &ProtocolMessage) -> Result<Pooled<u8>, serde_json::Error> {
+        let mut buffer = self.encoded.get();
+        serde_json::to_writer(&mut *buffer, message)?;
+        Ok(buffer)
+    }
+
+    pub fn encode_binary(&self, message: &ProtocolMessage) -> Result<Pooled<u8>, postcard::Error> {
+        let mut buffer = self.encoded.get();
+        let encoded = postcard::to_extend(message, std::mem::take(&mut *buffer))?;
+        *buffer = encoded;
+        Ok(buffer)
+    }
+
+    /// Takes back the buffers of a response that has been sent.
+    pub fn recycle(&self, message: ProtocolMessage) {
+        if let MessagePayload::Response(Response::History { readings, .. }) = message.payload {
+            self.readings.give(readings);
+        }
+    }
+
+    pub fn reading_stats(&self) -> PoolStats {
+        self.readings.stats()
+    }
+
+    pub fn encoded_stats(&self) -> PoolStats {
+        self.encoded.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Command, TemperatureProtocolHandler};
+
+    #[test]
+    fn history_batches_and_encode_buffers_are_reused() {
+        let mut handler = TemperatureProtocolHandler::new();
+        let buffers = handler.buffers();
+        for _ in 0..3 {
+            let reading = handler.create_command(Command::GetReading { sensor_id: "temp_01".parse().unwrap() });
+            handler.process_command(reading);
+        }
+
+        for _ in 0..3 {
+            let history = handler.create_command(Command::GetHistory { sensor_id: "temp_01".parse().unwrap(), last_n: 10 });
+            let response = handler.process_command(history);
+            let encoded = buffers.encode_json(&response).unwrap();
+            let decoded = handler.deserialize_json(std::str::from_utf8(&encoded).unwrap()).unwrap();
+            assert_eq!(decoded, response);
+            let binary = buffers.encode_binary(&response).unwrap();
+            assert_eq!(handler.deserialize_binary(&binary).unwrap(), response);
+            buffers.recycle(response);
+        }
+
+        // Only the first round allocated: one batch, and two encode buffers in use at once
+        let readings = buffers.reading_stats();
+        assert_eq!((readings.hits, readings.misses), (2, 1));
+        let encoded = buffers.encoded_stats();
+        assert_eq!((encoded.hits, encoded.misses), (4, 2));
+    }
+}
@@ -0,0 +1,109 @@
+//! Bulk export and import of calibration offsets.
+//!
+//! An export captures every sensor's offset so it can be carried to another
+//! instance, e.g. after replacing the hardware. Imports are all-or-nothing:
+//! if any entry has a blocking conflict nothing is applied, and a dry run
+//! reports the conflicts without applying anything either way.
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::collections::HashSet;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use temp_core::SensorId;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CalibrationEntry {
+    pub sensor_id: SensorId,
+    /// Degrees Celsius added to the raw reading.
+    pub offset: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CalibrationExport {
+    pub exported_at: u64,
+    /// Sorted by sensor id.
+    pub entries: Vec<CalibrationEntry>,
+}
+
+#[cfg(feature = "std")]
+impl CalibrationExport {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Conflicts within the export itself; sensor checks are up to the importer.
+    pub(crate) fn entry_conflicts(&self) -> Vec<CalibrationConflict> {
+        let mut seen = HashSet::new();
+        let mut conflicts = Vec::new();
+        for entry in &self.entries {
+            if !seen.insert(entry.sensor_id.as_str()) {
+                conflicts.push(CalibrationConflict::Duplicate { sensor_id: entry.sensor_id.clone() });
+            }
+            if !entry.offset.is_finite() {
+                conflicts.push(CalibrationConflict::InvalidOffset { sensor_id: entry.sensor_id.clone() });
+            }
+        }
+        conflicts
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CalibrationConflict {
+    UnknownSensor { sensor_id: SensorId },
+    Decommissioned { sensor_id: SensorId },
+    Duplicate { sensor_id: SensorId },
+    InvalidOffset { sensor_id: SensorId },
+    /// The sensor already has a different offset; importing replaces it.
+    Overwrites { sensor_id: SensorId, current: f32, imported: f32 },
+}
+
+impl CalibrationConflict {
+    /// Blocking conflicts stop the whole import; the rest are warnings.
+    pub fn is_blocking(&self) -> bool {
+        !matches!(self, CalibrationConflict::Overwrites { .. })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    /// Entries applied; zero for dry runs and blocked imports.
+    pub applied: usize,
+    pub conflicts: Vec<CalibrationConflict>,
+}
+
+impl ImportReport {
+    pub fn is_blocked(&self) -> bool {
+        self.conflicts.iter().any(CalibrationConflict::is_blocking)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_and_loads_exports() {
+        let path = std::env::temp_dir().join(format!("temp_protocol_{}_calibration.json", std::process::id()));
+        let export = CalibrationExport {
+            exported_at: 1_700_000_000,
+            entries: vec![CalibrationEntry { sensor_id: "temp_01".parse().unwrap(), offset: -0.4 }],
+        };
+        export.save(&path).unwrap();
+        assert_eq!(CalibrationExport::load(&path).unwrap(), export);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
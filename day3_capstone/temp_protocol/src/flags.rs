@@ -0,0 +1,136 @@
+//! Runtime switches for individual commands.
+//!
+//! Commands are named as by [`command_name`](crate::middleware::command_name),
+//! e.g. `"RestoreBackup"`, and a disabled one is answered with a 403 before
+//! it runs. Commands can be disabled from the start, e.g. in production, and
+//! toggled at runtime with `SetCommandEnabled`, which cannot be disabled
+//! itself so flags can always be turned back. Runtime changes are kept in
+//! an audit trail.
+
+use alloc::collections::{BTreeSet, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+
+/// The command toggling flags, which always stays enabled.
+pub const FLAG_COMMAND: &str = "SetCommandEnabled";
+
+/// Changes kept in the audit trail; older ones are dropped.
+const MAX_AUDIT_ENTRIES: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FlagChange {
+    pub command: String,
+    pub enabled: bool,
+    pub changed_at: u64,
+    pub changed_by: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CommandFlags {
+    disabled: BTreeSet<String>,
+    audit: VecDeque<FlagChange>,
+}
+
+impl CommandFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Flags with `commands` disabled from the start; not recorded in the audit trail.
+    pub fn disabling<I, S>(commands: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let disabled = commands
+            .into_iter()
+            .map(Into::into)
+            .filter(|command: &String| command != FLAG_COMMAND)
+            .collect();
+        Self { disabled, audit: VecDeque::new() }
+    }
+
+    pub fn is_enabled(&self, command: &str) -> bool {
+        !self.disabled.contains(command)
+    }
+
+    /// Whether any command is disabled, to skip naming commands when none is.
+    pub fn any_disabled(&self) -> bool {
+        !self.disabled.is_empty()
+    }
+
+    /// Names of the disabled commands, sorted.
+    pub fn disabled(&self) -> Vec<String> {
+        self.disabled.iter().cloned().collect()
+    }
+
+    /// Applies `change` and records it. Returns false, changing nothing, for
+    /// an attempt to disable [`FLAG_COMMAND`].
+    pub fn apply(&mut self, change: FlagChange) -> bool {
+        if change.command == FLAG_COMMAND && !change.enabled {
+            return false;
+        }
+        if change.enabled {
+            self.disabled.remove(&change.command);
+        } else {
+            self.disabled.insert(change.command.clone());
+        }
+        if self.audit.len() >= MAX_AUDIT_ENTRIES {
+            self.audit.pop_front();
+        }
+        self.audit.push_back(change);
+        true
+    }
+
+    /// Runtime changes, oldest first.
+    pub fn audit(&self) -> Vec<FlagChange> {
+        self.audit.iter().cloned().collect()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{Command, MessagePayload, Response, TemperatureProtocolHandler};
+
+    fn process(handler: &mut TemperatureProtocolHandler, command: Command) -> Response {
+        let message = handler.create_command(command);
+        match handler.process_command(message).payload {
+            MessagePayload::Response(response) => response,
+            other => panic!("Unexpected payload: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn disabled_commands_are_refused_until_enabled() {
+        let mut handler = TemperatureProtocolHandler::new().with_disabled_commands(["CompactStorage", FLAG_COMMAND]);
+        assert!(matches!(process(&mut handler, Command::CompactStorage), Response::Error { code: 403, .. }));
+
+        let enable = Command::SetCommandEnabled {
+            command: "CompactStorage".to_string(),
+            enabled: true,
+            changed_by: Some("ops".to_string()),
+            reason: Some("maintenance window".to_string()),
+        };
+        assert!(matches!(process(&mut handler, enable), Response::CommandFlagSet { .. }));
+        assert!(matches!(process(&mut handler, Command::CompactStorage), Response::StorageCompacted { .. }));
+
+        let lockout = Command::SetCommandEnabled {
+            command: FLAG_COMMAND.to_string(),
+            enabled: false,
+            changed_by: None,
+            reason: None,
+        };
+        assert!(matches!(process(&mut handler, lockout), Response::Error { code: 400, .. }));
+
+        let Response::CommandFlags { disabled, audit } = process(&mut handler, Command::GetCommandFlags) else {
+            panic!("Expected flags");
+        };
+        assert!(disabled.is_empty());
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].changed_by.as_deref(), Some("ops"));
+    }
+}
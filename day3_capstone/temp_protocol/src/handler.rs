@@ -0,0 +1,2669 @@
+//! The handler answering commands from the sensors, stores and alert
+//! engine it owns.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use temp_core::{CalibratedSensor, Calibration, RetryPolicy, RetrySensor, SensorId, Temperature, TemperatureSensor, mock::MockTemperatureSensor};
+use temp_alert::{AlertEngine, AlertError, AlertHistory, AlertQuery, AlertRecord, Notifier, Transition};
+use temp_core::clock::{Clock, SystemClock};
+use temp_core::control::{SETPOINT_MAX_CELSIUS, SETPOINT_MIN_CELSIUS};
+use temp_core::health::SharedHealth;
+use temp_core::threshold::ThresholdConfig;
+use temp_core::transform::{SharedPipeline, TransformPipeline};
+use temp_store::{BackupFormat, TemperatureStore, TemperatureReading, DivergenceConfig, PeerComparison};
+use temp_store::Tz;
+use temp_store::error_hook;
+use temp_store::degree_days::DEFAULT_BASE_CELSIUS;
+use temp_store::rollup::DAY_SECONDS;
+
+use crate::acl::{AccessControl, Permission};
+use crate::auth::{AuthError, AuthProvider, Credentials, Identity};
+use crate::buffers::MessageBuffers;
+use crate::calibration::{CalibrationConflict, CalibrationEntry, CalibrationExport, ImportReport};
+use crate::homeassistant::{HomeAssistantDiscovery, MqttMessage};
+use crate::i18n::{self, Language};
+use crate::ids::{IdGenerator, MessageId, MonotonicIds};
+use crate::ingest::{self, ExternalReading, RateLimit, SourceLimits, SubmitOutcome};
+use crate::flags::{CommandFlags, FlagChange};
+use crate::middleware::{command_name, Middleware};
+use crate::tags::{self, Tags};
+use crate::trace::{self, SpanExporter, SpanGuard, SpanKind, SpanRecord, TraceContext};
+use crate::{Command, Headers, MessagePayload, ProtocolMessage, Response, SensorCompaction, SensorHealth, SensorSpec, SensorState, SensorStatus, SensorStorageInfo};
+
+#[derive(Debug, Clone)]
+pub enum ProtocolError {
+    /// Unknown, or not a valid [`SensorId`] at all.
+    InvalidSensorId { sensor_id: String },
+    SensorNotResponding { sensor_id: SensorId },
+    InvalidThreshold { min: f32, max: f32, reason: String },
+    InvalidSetpoint { setpoint: f32, reason: String },
+    /// NaN, infinite or below absolute zero.
+    InvalidTemperature { celsius: f32, reason: String },
+    InvalidTimeZone { time_zone: String },
+    CalibrationFailed { sensor_id: SensorId, reason: String },
+    SensorUnavailable { sensor_id: SensorId, state: SensorState },
+    InvalidStateTransition { sensor_id: SensorId, from: SensorState, to: SensorState },
+    InvalidRange { start: u64, end: u64 },
+    InvalidForecastHorizon,
+    InsufficientHistory { sensor_id: SensorId },
+    UnknownAnnotation { sensor_id: SensorId, annotation_id: u64 },
+    InvalidTransform { reason: String },
+    InvalidTags { reason: String },
+    /// Further in the future than [`ingest::MAX_CLOCK_SKEW_SECONDS`].
+    InvalidTimestamp { timestamp: u64 },
+    /// The source submitted more readings than its [`RateLimit`] allows.
+    RateLimited { source: String },
+    /// The sensor answered but a transform dropped the reading.
+    ReadingFiltered { sensor_id: SensorId },
+    /// The sensor is sampled elsewhere and nothing registered its datasheet figures.
+    SensorInfoUnavailable { sensor_id: SensorId },
+    CommandDisabled { command: String },
+    /// Authentication is required and the session has not, or its token expired.
+    Unauthenticated,
+    AuthenticationFailed { reason: String },
+    /// The session's identity lacks a grant; see [`acl`].
+    AccessDenied { resource: String },
+    /// Disabling this command would lock out the flags themselves.
+    ProtectedCommand { command: String },
+    UnexpectedResponse,
+    Alert(AlertError),
+    SystemError { code: u16, details: String },
+    ProtocolVersionMismatch { expected: u8, received: u8 },
+}
+
+impl ProtocolError {
+    pub fn code(&self) -> u16 {
+        match self {
+            ProtocolError::InvalidSensorId { .. }
+            | ProtocolError::UnknownAnnotation { .. }
+            | ProtocolError::SensorInfoUnavailable { .. } => 404,
+            ProtocolError::SensorNotResponding { .. } => 503,
+            ProtocolError::InvalidThreshold { .. }
+            | ProtocolError::InvalidSetpoint { .. }
+            | ProtocolError::InvalidTemperature { .. }
+            | ProtocolError::InvalidTimeZone { .. }
+            | ProtocolError::InvalidRange { .. }
+            | ProtocolError::InvalidForecastHorizon
+            | ProtocolError::InvalidTransform { .. }
+            | ProtocolError::InvalidTags { .. }
+            | ProtocolError::InvalidTimestamp { .. }
+            | ProtocolError::ProtectedCommand { .. }
+            | ProtocolError::UnexpectedResponse => 400,
+            ProtocolError::CommandDisabled { .. } | ProtocolError::AccessDenied { .. } => 403,
+            ProtocolError::Unauthenticated | ProtocolError::AuthenticationFailed { .. } => 401,
+            ProtocolError::CalibrationFailed { .. }
+            | ProtocolError::InsufficientHistory { .. }
+            | ProtocolError::ReadingFiltered { .. } => 422,
+            ProtocolError::SensorUnavailable { .. } | ProtocolError::InvalidStateTransition { .. } => 409,
+            ProtocolError::RateLimited { .. } => 429,
+            ProtocolError::Alert(error) => match error {
+                AlertError::UnknownAlert(_) | AlertError::UnknownSilence(_) => 404,
+                AlertError::InvalidSilence { .. } => 400,
+            },
+            ProtocolError::SystemError { code, .. } => *code,
+            ProtocolError::ProtocolVersionMismatch { .. } => 505,
+        }
+    }
+
+    /// The error with an English message.
+    pub fn to_response(&self) -> Response {
+        self.to_localized_response(Language::English)
+    }
+
+    pub fn to_localized_response(&self, language: Language) -> Response {
+        Response::Error {
+            code: self.code(),
+            message: i18n::error_message(self, language),
+        }
+    }
+}
+
+fn threshold_from(min_temp: f32, max_temp: f32, hysteresis: f32, consecutive: u32) -> Result<ThresholdConfig, ProtocolError> {
+    let invalid = |reason: String| ProtocolError::InvalidThreshold { min: min_temp, max: max_temp, reason };
+    Temperature::try_new(min_temp).and(Temperature::try_new(max_temp)).map_err(|e| invalid(e.to_string()))?;
+    ThresholdConfig::new(min_temp, max_temp, hysteresis, consecutive).map_err(|e| invalid(e.to_string()))
+}
+
+/// Readings kept per sensor.
+pub const STORE_CAPACITY_PER_SENSOR: usize = 100;
+
+/// Where a sensor's readings come from.
+enum SensorSource {
+    /// Read by the handler itself on `GetReading`, retrying failed reads, and
+    /// corrected by its calibration.
+    Mock(Box<CalibratedSensor<RetrySensor<MockTemperatureSensor>>>),
+    /// Sampled elsewhere, e.g. by an async monitor writing into the shared store.
+    External,
+}
+
+pub struct TemperatureProtocolHandler {
+    ids: Box<dyn IdGenerator>,
+    sensors: HashMap<SensorId, SensorSource>,
+    stores: HashMap<SensorId, TemperatureStore>,
+    setpoints: HashMap<SensorId, f32>,
+    states: HashMap<SensorId, SensorState>,
+    failures: HashMap<SensorId, u32>,
+    calibrations: HashMap<SensorId, f32>,
+    sensor_info: HashMap<SensorId, SensorSpec>,
+    transforms: HashMap<SensorId, SharedPipeline>,
+    self_tests: HashMap<SensorId, SharedHealth>,
+    zones: HashMap<SensorId, String>,
+    tags: HashMap<SensorId, Tags>,
+    divergence: DivergenceConfig,
+    alerts: AlertEngine,
+    alert_history: AlertHistory,
+    /// Site time zone; daily reports start at its local midnight.
+    time_zone: Tz,
+    clock: Box<dyn Clock>,
+    /// Unix time the handler started, read from `clock`.
+    start_time: u64,
+    middleware: Vec<Box<dyn Middleware>>,
+    flags: CommandFlags,
+    /// Language of error messages for the command being processed.
+    language: Language,
+    /// Sessions must authenticate when set.
+    auth: Option<Box<dyn AuthProvider>>,
+    /// Identity of the session whose command is being processed.
+    identity: Option<Identity>,
+    /// Who may access which sensors; everything is allowed when unset.
+    acl: Option<AccessControl>,
+    ingest_limits: SourceLimits,
+    /// Receives spans when set; see [`trace`].
+    tracer: Option<Box<dyn SpanExporter>>,
+    /// Server span of the command being processed.
+    trace: Option<TraceContext>,
+    buffers: MessageBuffers,
+}
+
+/// What a transport keeps per session between messages.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionState {
+    pub language: Language,
+    pub identity: Option<Identity>,
+}
+
+impl TemperatureProtocolHandler {
+    pub fn new() -> Self {
+        let mut handler = Self::without_sensors();
+
+        // Initialize with some mock sensors
+        for (id, celsius) in [("temp_01", 23.5), ("temp_02", 21.8), ("temp_03", 25.1)] {
+            let sensor_id = SensorId::new(id).expect("built-in sensor ids are valid");
+            let mock = MockTemperatureSensor::new(id.to_string(), celsius);
+            let sensor = CalibratedSensor::uncalibrated(RetrySensor::new(mock, RetryPolicy::default()));
+            handler.sensors.insert(sensor_id.clone(), SensorSource::Mock(Box::new(sensor)));
+            handler.stores.insert(sensor_id, TemperatureStore::new(STORE_CAPACITY_PER_SENSOR));
+        }
+        handler
+    }
+
+    /// A handler with no sensors; register them with [`attach_store`](Self::attach_store).
+    pub fn without_sensors() -> Self {
+        Self {
+            ids: Box::new(MonotonicIds::new()),
+            sensors: HashMap::new(),
+            stores: HashMap::new(),
+            setpoints: HashMap::new(),
+            states: HashMap::new(),
+            failures: HashMap::new(),
+            calibrations: HashMap::new(),
+            sensor_info: HashMap::new(),
+            transforms: HashMap::new(),
+            self_tests: HashMap::new(),
+            zones: HashMap::new(),
+            tags: HashMap::new(),
+            divergence: DivergenceConfig::default(),
+            alerts: AlertEngine::new(),
+            alert_history: AlertHistory::new(),
+            time_zone: Tz::UTC,
+            clock: Box::new(SystemClock),
+            start_time: SystemClock.now(),
+            middleware: Vec::new(),
+            flags: CommandFlags::new(),
+            language: Language::English,
+            auth: None,
+            identity: None,
+            acl: None,
+            ingest_limits: SourceLimits::default(),
+            tracer: None,
+            trace: None,
+            buffers: MessageBuffers::default(),
+        }
+    }
+
+    /// Replaces the clock used for reading timestamps, alert times and
+    /// uptime; uptime restarts from the new clock's current time.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.start_time = clock.now();
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Replaces the default pools, e.g. to keep larger history batches.
+    pub fn with_buffers(mut self, buffers: MessageBuffers) -> Self {
+        self.buffers = buffers;
+        self
+    }
+
+    /// The pools history batches come from; see [`buffers`].
+    pub fn buffers(&self) -> MessageBuffers {
+        self.buffers.clone()
+    }
+
+    /// Adds an interceptor around command processing; see [`middleware`].
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Refuses `commands` until they are enabled with `SetCommandEnabled`.
+    pub fn with_disabled_commands<I, S>(mut self, commands: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.flags = CommandFlags::disabling(commands);
+        self
+    }
+
+    /// Requires sessions to authenticate with `provider`; see [`auth`].
+    pub fn with_auth(mut self, provider: impl AuthProvider + 'static) -> Self {
+        self.auth = Some(Box::new(provider));
+        self
+    }
+
+    /// Limits how many readings each source may submit; unlimited by default.
+    pub fn with_ingest_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.ingest_limits.set_limit(Some(limit));
+        self
+    }
+
+    /// Limits each session to the sensors granted to its identity; see [`acl`].
+    pub fn with_access_control(mut self, acl: AccessControl) -> Self {
+        self.acl = Some(acl);
+        self
+    }
+
+    /// Records a span for every command and the store calls it makes; see [`trace`].
+    pub fn with_tracing(mut self, exporter: impl SpanExporter + 'static) -> Self {
+        self.tracer = Some(Box::new(exporter));
+        self
+    }
+
+    /// Checks credentials a transport got outside the protocol, such as a
+    /// TLS client certificate, and returns the identity to keep in the
+    /// session's [`SessionState`].
+    pub fn authenticate(&self, credentials: &Credentials) -> Result<Identity, AuthError> {
+        match &self.auth {
+            Some(provider) => provider.authenticate(credentials),
+            None => Err(AuthError::Unsupported),
+        }
+    }
+
+    /// Replaces the generator of command message ids, e.g. with [`ids::UlidIds`].
+    pub fn with_id_generator(mut self, ids: impl IdGenerator + 'static) -> Self {
+        self.ids = Box::new(ids);
+        self
+    }
+
+    /// Registers a sensor sampled outside the handler. `GetReading` answers
+    /// with the latest reading in `store` instead of polling the sensor.
+    pub fn attach_store(&mut self, sensor_id: SensorId, store: TemperatureStore) {
+        self.sensors.insert(sensor_id.clone(), SensorSource::External);
+        self.stores.insert(sensor_id, store);
+    }
+
+    /// Datasheet figures of a sensor sampled outside the handler, for `GetSensorInfo`.
+    pub fn attach_sensor_info(&mut self, sensor_id: SensorId, info: SensorSpec) {
+        self.sensor_info.insert(sensor_id, info);
+    }
+
+    /// Shares the transform pipeline of a sensor with whatever samples it,
+    /// so `SetTransforms` takes effect there too.
+    pub fn attach_transforms(&mut self, sensor_id: SensorId, pipeline: SharedPipeline) {
+        self.transforms.insert(sensor_id, pipeline);
+    }
+
+    /// Where whatever samples a sensor publishes its self-test results, for
+    /// `GetStatus`. Sensors the handler reads itself are tested on every
+    /// `GetStatus` instead.
+    pub fn attach_self_test(&mut self, sensor_id: SensorId, health: SharedHealth) {
+        self.self_tests.insert(sensor_id, health);
+    }
+
+    /// Replaces a sensor's tags, e.g. from configuration; see [`tags`].
+    pub fn set_tags(&mut self, sensor_id: SensorId, tags: Tags) {
+        if tags.is_empty() {
+            self.tags.remove(&sensor_id);
+        } else {
+            self.tags.insert(sensor_id, tags);
+        }
+    }
+
+    pub fn create_command(&mut self, command: Command) -> ProtocolMessage {
+        let id = self.ids.next_id();
+
+        ProtocolMessage {
+            version: 1,
+            id,
+            payload: MessagePayload::Command(command),
+            headers: Headers::new(),
+        }
+    }
+
+    pub fn create_response(&self, request_id: MessageId, response: Response) -> ProtocolMessage {
+        ProtocolMessage {
+            version: 1,
+            id: request_id,
+            payload: MessagePayload::Response(response),
+            headers: Headers::new(),
+        }
+    }
+
+    pub fn process_command(&mut self, message: ProtocolMessage) -> ProtocolMessage {
+        if self.tracer.is_none() {
+            return self.run_middleware(message);
+        }
+
+        let parent = message.headers.get(trace::TRACEPARENT).and_then(|header| TraceContext::parse(header));
+        let context = parent.map_or_else(TraceContext::new_root, |parent| parent.child());
+        let trace_state = message.headers.get(trace::TRACESTATE).cloned();
+        let name = match &message.payload {
+            MessagePayload::Command(command) => command_name(command),
+            MessagePayload::Response(_) => "Response".to_string(),
+        };
+        let start_millis = self.clock.now_millis();
+
+        let previous = self.trace.replace(context);
+        let mut response = self.run_middleware(message);
+        self.trace = previous;
+
+        response.headers.insert(trace::TRACEPARENT.to_string(), context.to_string());
+        if let Some(state) = trace_state {
+            response.headers.insert(trace::TRACESTATE.to_string(), state);
+        }
+        if let (Some(tracer), true) = (&self.tracer, context.sampled) {
+            let code = match &response.payload {
+                MessagePayload::Response(Response::Error { code, .. }) => *code,
+                _ => 200,
+            };
+            tracer.export(SpanRecord {
+                name,
+                kind: SpanKind::Server,
+                context,
+                parent_span_id: parent.map(|parent| parent.span_id),
+                start_millis,
+                end_millis: self.clock.now_millis(),
+                attributes: vec![(trace::STATUS_CODE_ATTRIBUTE.to_string(), code.to_string())],
+            });
+        }
+        response
+    }
+
+    /// An internal span within the command being processed, if it is traced.
+    fn span(&self, name: &str) -> Option<SpanGuard<'_>> {
+        let tracer = self.tracer.as_deref()?;
+        let parent = self.trace.filter(|context| context.sampled)?;
+        Some(SpanGuard::start(tracer, self.clock.as_ref(), &parent, name))
+    }
+
+    fn run_middleware(&mut self, message: ProtocolMessage) -> ProtocolMessage {
+        if self.middleware.is_empty() {
+            return self.dispatch(message);
+        }
+
+        let request = message.clone();
+        let mut passed = 0;
+        let mut rejection = None;
+        for middleware in &mut self.middleware {
+            passed += 1;
+            rejection = middleware.before(&request);
+            if rejection.is_some() {
+                break;
+            }
+        }
+
+        let response = match rejection {
+            Some(response) => self.create_response(request.id, response),
+            None => self.dispatch(message),
+        };
+        for middleware in self.middleware[..passed].iter_mut().rev() {
+            middleware.after(&request, &response);
+        }
+        response
+    }
+
+    /// Processes `message` for a session that negotiated `language`. The
+    /// handler is shared between sessions, so the language is only in
+    /// effect for this call; a `SetLanguage` answer tells the session what
+    /// to pass from then on.
+    pub fn process_command_in(&mut self, message: ProtocolMessage, language: Language) -> ProtocolMessage {
+        let previous = std::mem::replace(&mut self.language, language);
+        let response = self.process_command(message);
+        self.language = previous;
+        response
+    }
+
+    /// Processes `message` for a session, like
+    /// [`process_command_in`](Self::process_command_in) but also as the
+    /// session's identity. `session` picks up a negotiated language or a
+    /// new identity from the answer.
+    pub fn process_command_for(&mut self, message: ProtocolMessage, session: &mut SessionState) -> ProtocolMessage {
+        let previous = std::mem::replace(&mut self.identity, session.identity.clone());
+        let response = self.process_command_in(message, session.language);
+        self.identity = previous;
+        match &response.payload {
+            MessagePayload::Response(Response::LanguageSet { language }) => session.language = *language,
+            MessagePayload::Response(Response::Authenticated { identity }) => session.identity = Some(identity.clone()),
+            _ => {}
+        }
+        response
+    }
+
+    /// The sensor's store, if `session` may run `ExportBackup` on it, or
+    /// `RestoreBackup` for [`Permission::Write`], so a transport can stream
+    /// a backup through the store instead of carrying it in one message.
+    pub fn backup_store_for(
+        &mut self,
+        sensor_id: &SensorId,
+        permission: Permission,
+        session: &SessionState,
+    ) -> Result<TemperatureStore, ProtocolError> {
+        let command = match permission {
+            Permission::Read => Command::ExportBackup { sensor_id: sensor_id.clone(), format: BackupFormat::default() },
+            Permission::Write => Command::RestoreBackup { sensor_id: sensor_id.clone(), data: Vec::new() },
+        };
+        let name = command_name(&command);
+        if !self.flags.is_enabled(&name) {
+            return Err(ProtocolError::CommandDisabled { command: name });
+        }
+        let previous = std::mem::replace(&mut self.identity, session.identity.clone());
+        let allowed = self.check_authenticated(&command).and_then(|()| self.check_access(&command));
+        self.identity = previous;
+        allowed?;
+        self.stores
+            .get(sensor_id)
+            .map(TemperatureStore::clone_handle)
+            .ok_or_else(|| ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() })
+    }
+
+    /// Whether the current session may run `command`.
+    fn check_authenticated(&self, command: &Command) -> Result<(), ProtocolError> {
+        if self.auth.is_none() || matches!(command, Command::Authenticate { .. } | Command::SetLanguage { .. }) {
+            return Ok(());
+        }
+        match &self.identity {
+            Some(identity) if !identity.is_expired(self.clock.now()) => Ok(()),
+            _ => Err(ProtocolError::Unauthenticated),
+        }
+    }
+
+    /// Whether the current session may access the sensor.
+    fn may_access(&self, sensor_id: &str, permission: Permission) -> bool {
+        self.acl.as_ref().is_none_or(|acl| {
+            let zone = self.zones.get(sensor_id).map(String::as_str);
+            acl.allows_sensor(self.identity.as_ref(), sensor_id, zone, permission)
+        })
+    }
+
+    fn check_sensor_access(&self, sensor_id: &str, permission: Permission) -> Result<(), ProtocolError> {
+        if self.may_access(sensor_id, permission) {
+            Ok(())
+        } else if self.may_access(sensor_id, Permission::Read) {
+            Err(ProtocolError::AccessDenied { resource: format!("sensor:{}", sensor_id) })
+        } else {
+            // Sensors the session may not see do not exist as far as it is concerned
+            Err(ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() })
+        }
+    }
+
+    fn check_site_access(&self, permission: Permission) -> Result<(), ProtocolError> {
+        match &self.acl {
+            Some(acl) if !acl.allows_site(self.identity.as_ref(), permission) => {
+                Err(ProtocolError::AccessDenied { resource: "site".to_string() })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the current session's grants cover `command`. Listings are
+    /// filtered afterwards by [`restrict`](Self::restrict) instead.
+    fn check_access(&self, command: &Command) -> Result<(), ProtocolError> {
+        let Some(acl) = &self.acl else {
+            return Ok(());
+        };
+        match command {
+            Command::GetReading { sensor_id }
+            | Command::GetHistory { sensor_id, .. }
+            | Command::GetStats { sensor_id }
+            | Command::GetRange { sensor_id, .. }
+            | Command::ExportBackup { sensor_id, .. }
+            | Command::GetDegreeDays { sensor_id, .. }
+            | Command::GetForecast { sensor_id, .. }
+            | Command::GetSensorInfo { sensor_id } => self.check_sensor_access(sensor_id, Permission::Read),
+            Command::SetThreshold { sensor_id, .. }
+            | Command::RestoreBackup { sensor_id, .. }
+            | Command::SetSetpoint { sensor_id, .. }
+            | Command::Calibrate { sensor_id, .. }
+            | Command::SetSensorState { sensor_id, .. }
+            | Command::Annotate { sensor_id, .. }
+            | Command::RemoveAnnotation { sensor_id, .. }
+            | Command::SetTransforms { sensor_id, .. }
+            | Command::SetTags { sensor_id, .. }
+            | Command::StartEpoch { sensor_id }
+            | Command::SubmitReading { reading: ExternalReading { sensor_id, .. }, .. }
+            | Command::SilenceAlerts { sensor_id: Some(sensor_id), .. } => self.check_sensor_access(sensor_id, Permission::Write),
+            Command::SetZone { sensor_id, zone } => {
+                self.check_sensor_access(sensor_id, Permission::Write)?;
+                // Moving a sensor into a zone shares it with everyone granted the zone
+                if acl.allows_zone(self.identity.as_ref(), zone, Permission::Write) {
+                    Ok(())
+                } else {
+                    Err(ProtocolError::AccessDenied { resource: format!("zone:{}", zone) })
+                }
+            }
+            Command::AckAlert { alert_id, .. } => match self.alerts.alert(*alert_id) {
+                Some(alert) => self.check_sensor_access(&alert.key.sensor_id, Permission::Write),
+                None => Ok(()),
+            },
+            Command::RemoveSilence { silence_id } => match self.alerts.silence_by_id(*silence_id).map(|s| &s.sensor_id) {
+                Some(Some(sensor_id)) => self.check_sensor_access(sensor_id, Permission::Write),
+                Some(None) => self.check_site_access(Permission::Write),
+                None => Ok(()),
+            },
+            Command::QueryAlertHistory { query, .. } => match &query.sensor_id {
+                Some(sensor_id) => self.check_sensor_access(sensor_id, Permission::Read),
+                None => Ok(()),
+            },
+            Command::GetStatus
+            | Command::QuerySensors { .. }
+            | Command::GetHealth
+            | Command::GetStorageInfo
+            | Command::ListAlerts { .. } => Ok(()),
+            // Only sensors the session may write are matched
+            Command::SetThresholdForTags { .. } => Ok(()),
+            Command::ExportCalibrations | Command::GetCommandFlags => self.check_site_access(Permission::Read),
+            Command::SilenceAlerts { sensor_id: None, .. }
+            | Command::CompactStorage
+            | Command::SetEscalationPolicy { .. }
+            | Command::ImportCalibrations { .. }
+            | Command::SetTimeZone { .. }
+            | Command::SetCommandEnabled { .. } => self.check_site_access(Permission::Write),
+            Command::Authenticate { .. } | Command::SetLanguage { .. } => Ok(()),
+        }
+    }
+
+    /// Leaves out of listings what the current session may not read.
+    fn restrict(&self, response: Response) -> Response {
+        let readable = |sensor_id: &str| self.may_access(sensor_id, Permission::Read);
+        match response {
+            Response::Status { mut sensors, uptime_seconds, .. } => {
+                sensors.retain(|s| readable(&s.sensor_id));
+                Response::Status {
+                    readings_count: sensors
+                        .iter()
+                        .filter_map(|s| self.stores.get(&s.sensor_id))
+                        .map(|store| store.reading_count())
+                        .sum(),
+                    sensors,
+                    uptime_seconds,
+                }
+            }
+            Response::Health { mut sensors } => {
+                sensors.retain(|s| readable(&s.sensor_id));
+                Response::Health { sensors }
+            }
+            Response::StorageInfo { mut sensors, .. } => {
+                sensors.retain(|s| readable(&s.sensor_id));
+                Response::StorageInfo {
+                    total_memory_bytes: sensors.iter().map(|s| s.storage.memory_bytes).sum(),
+                    sensors,
+                }
+            }
+            Response::Alerts { mut alerts, mut silences } => {
+                alerts.retain(|a| readable(&a.key.sensor_id));
+                let site = self.check_site_access(Permission::Read).is_ok();
+                silences.retain(|s| s.sensor_id.as_deref().map_or(site, readable));
+                Response::Alerts { alerts, silences }
+            }
+            Response::AlertHistory { mut records } => {
+                records.retain(|r| readable(&r.sensor_id));
+                Response::AlertHistory { records }
+            }
+            response => response,
+        }
+    }
+
+    fn error_response(&self, error: &ProtocolError) -> Response {
+        error.to_localized_response(self.language)
+    }
+
+    fn dispatch(&mut self, message: ProtocolMessage) -> ProtocolMessage {
+        // Check protocol version
+        if message.version != 1 {
+            let error = ProtocolError::ProtocolVersionMismatch {
+                expected: 1,
+                received: message.version
+            };
+            let response = self.error_response(&error);
+            return self.create_response(message.id, response);
+        }
+
+        if let MessagePayload::Command(command) = &message.payload {
+            if let Err(error) = self.check_authenticated(command).and_then(|()| self.check_access(command)) {
+                let response = self.error_response(&error);
+                return self.create_response(message.id, response);
+            }
+        }
+
+        let response = match message.payload {
+            MessagePayload::Command(command) if self.flags.any_disabled() => {
+                let name = command_name(&command);
+                if self.flags.is_enabled(&name) {
+                    self.handle_command(command)
+                } else {
+                    self.error_response(&ProtocolError::CommandDisabled { command: name })
+                }
+            }
+            MessagePayload::Command(command) => self.handle_command(command),
+            MessagePayload::Response(_) => self.error_response(&ProtocolError::UnexpectedResponse),
+        };
+        let response = match self.acl {
+            Some(_) => self.restrict(response),
+            None => response,
+        };
+
+        self.create_response(message.id, response)
+    }
+
+    fn handle_command(&mut self, command: Command) -> Response {
+        match command {
+            Command::GetStatus => self.status(&Tags::new()),
+            Command::QuerySensors { tags } => self.status(&tags),
+            Command::GetReading { sensor_id } => {
+                if let Err(error) = self.ensure_active(&sensor_id) {
+                    return self.error_response(&error);
+                }
+                let reading = match self.sensors.get_mut(&sensor_id) {
+                    Some(SensorSource::Mock(sensor)) => match sensor.read_temperature() {
+                        Ok(raw) => {
+                            self.failures.remove(&sensor_id);
+                            let transformed = match self.transforms.get(&sensor_id) {
+                                Some(pipeline) => error_hook::lock(pipeline, "Recovered the transform pipeline lock").apply(raw),
+                                None => Some(raw),
+                            };
+                            let Some(temp) = transformed else {
+                                return self.error_response(&ProtocolError::ReadingFiltered { sensor_id });
+                            };
+                            // A NaN or sub-zero-kelvin value means a faulty sensor, like a failed read
+                            if temp.validate().is_err() {
+                                *self.failures.entry(sensor_id.clone()).or_default() += 1;
+                                return self.error_response(&ProtocolError::SensorNotResponding { sensor_id });
+                            }
+                            let reading = TemperatureReading::with_timestamp(temp, self.clock.now());
+                            if let Some(store) = self.stores.get(&sensor_id) {
+                                let _span = self.span("store.add_reading");
+                                store.add_reading(reading);
+                            }
+                            reading
+                        }
+                        Err(_) => {
+                            *self.failures.entry(sensor_id.clone()).or_default() += 1;
+                            let error = ProtocolError::SensorNotResponding { sensor_id };
+                            return self.error_response(&error);
+                        }
+                    },
+                    Some(SensorSource::External) => match self.stores.get(&sensor_id).and_then(|s| s.get_latest()) {
+                        Some(reading) => reading,
+                        None => return self.error_response(&ProtocolError::SensorNotResponding { sensor_id }),
+                    },
+                    None => return self.error_response(&ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() }),
+                };
+                for event in self.alerts.evaluate(&sensor_id, reading.temperature.celsius, reading.timestamp) {
+                    self.alert_history.record_event(&event);
+                }
+
+                Response::Reading {
+                    sensor_id,
+                    temperature: reading.temperature.celsius,
+                    timestamp: reading.timestamp,
+                }
+            }
+            Command::SetThreshold { sensor_id, min_temp, max_temp, hysteresis, consecutive } => {
+                let threshold = match threshold_from(min_temp, max_temp, hysteresis, consecutive) {
+                    Ok(threshold) => threshold,
+                    Err(error) => return self.error_response(&error),
+                };
+
+                if !self.sensors.contains_key(&sensor_id) {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                }
+                if let Err(error) = self.ensure_not_decommissioned(&sensor_id) {
+                    return self.error_response(&error);
+                }
+
+                self.alerts.set_thresholds(&sensor_id, threshold);
+                Response::ThresholdSet { sensor_id, threshold }
+            }
+            Command::SetThresholdForTags { tags, min_temp, max_temp, hysteresis, consecutive } => {
+                let threshold = match threshold_from(min_temp, max_temp, hysteresis, consecutive) {
+                    Ok(threshold) => threshold,
+                    Err(error) => return self.error_response(&error),
+                };
+
+                let mut sensor_ids: Vec<SensorId> = self
+                    .sensors_tagged(&tags)
+                    .filter(|sensor_id| self.may_access(sensor_id, Permission::Write))
+                    .cloned()
+                    .collect();
+                sensor_ids.sort();
+                for sensor_id in &sensor_ids {
+                    self.alerts.set_thresholds(sensor_id, threshold);
+                }
+                Response::ThresholdsSet { sensor_ids, threshold }
+            }
+            Command::SetTags { sensor_id, tags } => {
+                if !self.sensors.contains_key(&sensor_id) {
+                    return self.error_response(&ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() });
+                }
+                if let Err(error) = self.ensure_not_decommissioned(&sensor_id) {
+                    return self.error_response(&error);
+                }
+                if let Err(reason) = tags::validate(&tags) {
+                    return self.error_response(&ProtocolError::InvalidTags { reason });
+                }
+
+                self.set_tags(sensor_id.clone(), tags.clone());
+                Response::TagsSet { sensor_id, tags }
+            }
+            Command::GetHistory { sensor_id, last_n } => {
+                let Some(store) = self.stores.get(&sensor_id) else {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                };
+
+                let _span = self.span("store.recent_readings");
+                let mut readings = self.buffers.take_batch();
+                store.recent_readings_into(last_n, &mut readings);
+                let annotations = match (readings.first(), readings.last()) {
+                    (Some(first), Some(last)) => store.annotations(first.timestamp, last.timestamp + 1),
+                    _ => Vec::new(),
+                };
+                Response::History {
+                    sensor_id,
+                    readings,
+                    annotations,
+                }
+            }
+            Command::GetStats { sensor_id } => {
+                let Some(store) = self.stores.get(&sensor_id) else {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                };
+
+                let stats = {
+                    let _span = self.span("store.get_stats");
+                    store.get_stats()
+                };
+                Response::Stats {
+                    sensor_id,
+                    stats,
+                    epoch: store.epoch(),
+                }
+            }
+            Command::SubmitReading { source, reading } => self.submit_reading(&source, reading),
+            Command::StartEpoch { sensor_id } => {
+                let Some(store) = self.stores.get(&sensor_id) else {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                };
+
+                let epoch = store.start_epoch(self.clock.now());
+                Response::EpochStarted { sensor_id, epoch }
+            }
+            Command::GetRange { sensor_id, start, end } => {
+                let Some(store) = self.stores.get(&sensor_id) else {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                };
+
+                if start >= end {
+                    return self.error_response(&ProtocolError::InvalidRange { start, end });
+                }
+
+                let _span = self.span("store.query_range");
+                let result = store.query_range(start, end);
+                Response::Range {
+                    annotations: store.annotations(start, end),
+                    sensor_id,
+                    resolution: result.resolution,
+                    points: result.points,
+                }
+            }
+            Command::GetStorageInfo => {
+                let mut sensors: Vec<SensorStorageInfo> = self
+                    .stores
+                    .iter()
+                    .map(|(sensor_id, store)| SensorStorageInfo {
+                        sensor_id: sensor_id.clone(),
+                        storage: store.storage_info(),
+                    })
+                    .collect();
+                sensors.sort_by(|a, b| a.sensor_id.cmp(&b.sensor_id));
+
+                Response::StorageInfo {
+                    total_memory_bytes: sensors.iter().map(|s| s.storage.memory_bytes).sum(),
+                    sensors,
+                }
+            }
+            Command::CompactStorage => {
+                let mut sensors = Vec::new();
+                for (sensor_id, store) in &self.stores {
+                    let compacted = {
+                        let mut span = self.span("store.compact");
+                        if let Some(span) = &mut span {
+                            span.set_attribute("sensor_id", sensor_id.as_str());
+                        }
+                        store.compact()
+                    };
+                    match compacted {
+                        Ok(Some(report)) => sensors.push(SensorCompaction { sensor_id: sensor_id.clone(), report }),
+                        Ok(None) => {}
+                        Err(e) => {
+                            let error = ProtocolError::SystemError {
+                                code: 500,
+                                details: format!("Compacting storage of '{}' failed: {}", sensor_id, e),
+                            };
+                            return self.error_response(&error);
+                        }
+                    }
+                }
+                sensors.sort_by(|a, b| a.sensor_id.cmp(&b.sensor_id));
+
+                Response::StorageCompacted {
+                    reclaimed_bytes: sensors.iter().map(|s| s.report.reclaimed_bytes()).sum(),
+                    sensors,
+                }
+            }
+            Command::ExportBackup { sensor_id, format } => {
+                let Some(store) = self.stores.get(&sensor_id) else {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                };
+                let mut data = Vec::new();
+                let written = {
+                    let _span = self.span("store.write_backup");
+                    store.write_backup(&mut data, format)
+                };
+                match written {
+                    Ok(readings) => Response::Backup { sensor_id, format, readings, data },
+                    Err(e) => self.error_response(&ProtocolError::SystemError {
+                        code: 500,
+                        details: format!("Backup of '{}' failed: {}", sensor_id, e),
+                    }),
+                }
+            }
+            Command::RestoreBackup { sensor_id, data } => {
+                let Some(store) = self.stores.get(&sensor_id) else {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                };
+                let restored = {
+                    let _span = self.span("store.restore_backup");
+                    store.restore_backup(data.as_slice())
+                };
+                match restored {
+                    Ok(readings) => Response::BackupRestored { sensor_id, readings },
+                    Err(e) => self.error_response(&ProtocolError::SystemError {
+                        code: 400,
+                        details: format!("Restoring '{}' failed: {}", sensor_id, e),
+                    }),
+                }
+            }
+            Command::GetDegreeDays { sensor_id, start, end, base_celsius } => {
+                let Some(store) = self.stores.get(&sensor_id) else {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                };
+
+                if start >= end {
+                    return self.error_response(&ProtocolError::InvalidRange { start, end });
+                }
+
+                let base = match Temperature::try_new(base_celsius.unwrap_or(DEFAULT_BASE_CELSIUS)) {
+                    Ok(base) => base,
+                    Err(e) => {
+                        let celsius = base_celsius.unwrap_or_default();
+                        return self.error_response(&ProtocolError::InvalidTemperature { celsius, reason: e.to_string() });
+                    }
+                };
+                let _span = self.span("store.degree_days");
+                Response::DegreeDays {
+                    report: store.degree_days_in(start, end, base, self.time_zone),
+                    sensor_id,
+                }
+            }
+            Command::GetForecast { sensor_id, horizon } => {
+                let Some(store) = self.stores.get(&sensor_id) else {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                };
+
+                if horizon == 0 {
+                    return self.error_response(&ProtocolError::InvalidForecastHorizon);
+                }
+
+                let forecast = {
+                    let _span = self.span("store.forecast");
+                    store.forecast(horizon)
+                };
+                match forecast {
+                    Some(forecast) => Response::Forecast { sensor_id, forecast },
+                    None => self.error_response(&ProtocolError::InsufficientHistory { sensor_id }),
+                }
+            }
+            Command::SetSetpoint { sensor_id, setpoint } => {
+                if !(SETPOINT_MIN_CELSIUS..=SETPOINT_MAX_CELSIUS).contains(&setpoint) {
+                    let error = ProtocolError::InvalidSetpoint {
+                        setpoint,
+                        reason: format!("Setpoint must be between {} and {} °C", SETPOINT_MIN_CELSIUS, SETPOINT_MAX_CELSIUS),
+                    };
+                    return self.error_response(&error);
+                }
+
+                if !self.sensors.contains_key(&sensor_id) {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                }
+                if let Err(error) = self.ensure_not_decommissioned(&sensor_id) {
+                    return self.error_response(&error);
+                }
+
+                self.setpoints.insert(sensor_id.clone(), setpoint);
+                Response::SetpointSet { sensor_id, setpoint }
+            }
+            Command::SetZone { sensor_id, zone } => {
+                if !self.sensors.contains_key(&sensor_id) {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                }
+                if let Err(error) = self.ensure_not_decommissioned(&sensor_id) {
+                    return self.error_response(&error);
+                }
+
+                self.zones.insert(sensor_id.clone(), zone.clone());
+                Response::ZoneSet { sensor_id, zone }
+            }
+            Command::GetSensorInfo { sensor_id } => {
+                let info = match self.sensors.get(&sensor_id) {
+                    Some(SensorSource::Mock(sensor)) => Some(SensorSpec::of(&**sensor)),
+                    Some(SensorSource::External) => self.sensor_info.get(&sensor_id).cloned(),
+                    None => return self.error_response(&ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() }),
+                };
+                let Some(info) = info else {
+                    return self.error_response(&ProtocolError::SensorInfoUnavailable { sensor_id });
+                };
+                Response::SensorInfo { sensor_id, info }
+            }
+            Command::GetHealth => Response::Health {
+                sensors: self.health_report(),
+            },
+            Command::ListAlerts { include_resolved } => {
+                let mut alerts = self.alerts.active_alerts();
+                if include_resolved {
+                    alerts.extend(self.alerts.resolved_alerts().cloned());
+                }
+                Response::Alerts {
+                    alerts,
+                    silences: self.alerts.silences(self.clock.now()).to_vec(),
+                }
+            }
+            Command::AckAlert { alert_id, by } => {
+                let now = self.clock.now();
+                match self.alerts.acknowledge(alert_id, &by, now) {
+                    Ok(alert) => {
+                        let record = AlertRecord::new(alert, Transition::Acknowledged { by }, now);
+                        self.alert_history.record(record);
+                        Response::AlertAcknowledged { alert_id }
+                    }
+                    Err(e) => self.error_response(&ProtocolError::Alert(e)),
+                }
+            }
+            Command::SilenceAlerts { sensor_id, duration_seconds, reason } => {
+                if let Some(id) = sensor_id.as_ref().filter(|id| !self.sensors.contains_key(*id)) {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: id.to_string() };
+                    return self.error_response(&error);
+                }
+
+                let now = self.clock.now();
+                match self.alerts.silence(sensor_id.map(String::from), now, now.saturating_add(duration_seconds), reason) {
+                    Ok(silence) => Response::Silenced { silence: silence.clone() },
+                    Err(e) => self.error_response(&ProtocolError::Alert(e)),
+                }
+            }
+            Command::RemoveSilence { silence_id } => match self.alerts.remove_silence(silence_id) {
+                Ok(_) => Response::SilenceRemoved { silence_id },
+                Err(e) => self.error_response(&ProtocolError::Alert(e)),
+            },
+            Command::SetEscalationPolicy { policy } => {
+                self.alerts.set_escalation_policy(policy);
+                Response::EscalationPolicySet
+            }
+            Command::QueryAlertHistory { query, tags } => Response::AlertHistory {
+                records: self
+                    .alert_history
+                    .query(&query)
+                    .into_iter()
+                    .filter(|record| tags::matches(self.tags.get(record.sensor_id.as_str()).unwrap_or(&Tags::new()), &tags))
+                    .cloned()
+                    .collect(),
+            },
+            Command::Calibrate { sensor_id, actual_temp } => {
+                if let Err(e) = Temperature::try_new(actual_temp) {
+                    let error = ProtocolError::InvalidTemperature { celsius: actual_temp, reason: e.to_string() };
+                    return self.error_response(&error);
+                }
+                if let Err(error) = self.ensure_active(&sensor_id) {
+                    return self.error_response(&error);
+                }
+                if let Some(source) = self.sensors.get_mut(&sensor_id) {
+                    let SensorSource::Mock(sensor) = source else {
+                        let error = ProtocolError::CalibrationFailed {
+                            sensor_id,
+                            reason: "Sensor is sampled externally".to_string(),
+                        };
+                        return self.error_response(&error);
+                    };
+                    // One-point calibration: the offset from the raw reading to the reference
+                    match sensor.read_raw() {
+                        Ok(raw) => {
+                            let offset = actual_temp - raw.celsius;
+                            sensor.set_calibration(Calibration::offset(offset));
+                            self.calibrations.insert(sensor_id.clone(), offset);
+
+                            Response::CalibrationComplete {
+                                sensor_id,
+                                offset_adjustment: offset,
+                            }
+                        }
+                        Err(_) => {
+                            let error = ProtocolError::CalibrationFailed {
+                                sensor_id,
+                                reason: "Sensor not responding during calibration".to_string(),
+                            };
+                            self.error_response(&error)
+                        }
+                    }
+                } else {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    self.error_response(&error)
+                }
+            }
+            Command::ExportCalibrations => Response::Calibrations {
+                export: self.export_calibrations(),
+            },
+            Command::ImportCalibrations { export, dry_run } => Response::CalibrationsImported {
+                report: self.import_calibrations(&export, dry_run),
+            },
+            Command::SetSensorState { sensor_id, state } => {
+                if !self.sensors.contains_key(&sensor_id) {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                }
+                let previous = self.sensor_state(&sensor_id);
+                if !previous.can_transition_to(state) {
+                    let error = ProtocolError::InvalidStateTransition { sensor_id, from: previous, to: state };
+                    return self.error_response(&error);
+                }
+
+                if state == SensorState::Decommissioned && previous != state {
+                    self.decommission(&sensor_id);
+                }
+                self.states.insert(sensor_id.clone(), state);
+                Response::SensorStateChanged { sensor_id, previous, state }
+            }
+            Command::SetTimeZone { time_zone } => match time_zone.parse::<Tz>() {
+                Ok(tz) => {
+                    self.time_zone = tz;
+                    Response::TimeZoneSet { time_zone: tz.name().to_string() }
+                }
+                Err(_) => self.error_response(&ProtocolError::InvalidTimeZone { time_zone }),
+            },
+            Command::Annotate { sensor_id, start, end, text, author } => {
+                let Some(store) = self.stores.get(&sensor_id) else {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                };
+
+                match store.annotate(start, end, text, author) {
+                    Some(annotation) => Response::Annotated { sensor_id, annotation },
+                    None => self.error_response(&ProtocolError::InvalidRange { start, end }),
+                }
+            }
+            Command::RemoveAnnotation { sensor_id, annotation_id } => {
+                let Some(store) = self.stores.get(&sensor_id) else {
+                    let error = ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() };
+                    return self.error_response(&error);
+                };
+
+                match store.remove_annotation(annotation_id) {
+                    Some(_) => Response::AnnotationRemoved { sensor_id, annotation_id },
+                    None => self.error_response(&ProtocolError::UnknownAnnotation { sensor_id, annotation_id }),
+                }
+            }
+            Command::SetLanguage { preferences } => {
+                self.language = Language::negotiate(&preferences);
+                Response::LanguageSet { language: self.language }
+            }
+            Command::SetTransforms { sensor_id, transforms } => {
+                let pipeline = match TransformPipeline::from_config(transforms) {
+                    Ok(pipeline) => pipeline,
+                    Err(e) => return self.error_response(&ProtocolError::InvalidTransform { reason: e.to_string() }),
+                };
+                if !self.sensors.contains_key(&sensor_id) {
+                    return self.error_response(&ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() });
+                }
+                if let Err(error) = self.ensure_not_decommissioned(&sensor_id) {
+                    return self.error_response(&error);
+                }
+
+                let transforms = pipeline.config().to_vec();
+                match self.transforms.get(&sensor_id) {
+                    Some(shared) => *error_hook::lock(shared, "Recovered the transform pipeline lock") = pipeline,
+                    None => {
+                        self.transforms.insert(sensor_id.clone(), Arc::new(Mutex::new(pipeline)));
+                    }
+                }
+                Response::TransformsSet { sensor_id, transforms }
+            }
+            Command::SetCommandEnabled { command, enabled, changed_by, reason } => {
+                let change = FlagChange {
+                    command,
+                    enabled,
+                    changed_at: self.clock.now(),
+                    changed_by: changed_by.or_else(|| self.identity.as_ref().map(|identity| identity.subject.clone())),
+                    reason,
+                };
+                if !self.flags.apply(change.clone()) {
+                    return self.error_response(&ProtocolError::ProtectedCommand { command: change.command });
+                }
+                Response::CommandFlagSet { change }
+            }
+            Command::GetCommandFlags => Response::CommandFlags {
+                disabled: self.flags.disabled(),
+                audit: self.flags.audit(),
+            },
+            Command::Authenticate { token } => match &self.auth {
+                // Nothing to check, so any token will do
+                None => Response::Authenticated { identity: Identity::new("anonymous") },
+                Some(provider) => match provider.authenticate(&Credentials::Token(token)) {
+                    Ok(identity) => Response::Authenticated { identity },
+                    Err(e) => self.error_response(&ProtocolError::AuthenticationFailed { reason: e.to_string() }),
+                },
+            },
+        }
+    }
+
+    /// Calibration offsets of every sensor that has one, sorted by sensor id.
+    pub fn export_calibrations(&self) -> CalibrationExport {
+        let mut entries: Vec<CalibrationEntry> = self
+            .calibrations
+            .iter()
+            .map(|(sensor_id, offset)| CalibrationEntry { sensor_id: sensor_id.clone(), offset: *offset })
+            .collect();
+        entries.sort_by(|a, b| a.sensor_id.cmp(&b.sensor_id));
+        CalibrationExport {
+            exported_at: self.clock.now(),
+            entries,
+        }
+    }
+
+    /// Applies the offsets in `export` unless a conflict blocks the import or
+    /// this is a dry run.
+    pub fn import_calibrations(&mut self, export: &CalibrationExport, dry_run: bool) -> ImportReport {
+        let mut conflicts = export.entry_conflicts();
+        for entry in &export.entries {
+            let sensor_id = entry.sensor_id.clone();
+            if !self.sensors.contains_key(&sensor_id) {
+                conflicts.push(CalibrationConflict::UnknownSensor { sensor_id });
+            } else if self.sensor_state(&sensor_id) == SensorState::Decommissioned {
+                conflicts.push(CalibrationConflict::Decommissioned { sensor_id });
+            } else if let Some(&current) = self.calibrations.get(&sensor_id).filter(|&&c| c != entry.offset) {
+                conflicts.push(CalibrationConflict::Overwrites { sensor_id, current, imported: entry.offset });
+            }
+        }
+
+        let mut report = ImportReport { dry_run, applied: 0, conflicts };
+        if dry_run || report.is_blocked() {
+            return report;
+        }
+        for entry in &export.entries {
+            if let Some(SensorSource::Mock(sensor)) = self.sensors.get_mut(&entry.sensor_id) {
+                sensor.set_calibration(Calibration::offset(entry.offset));
+            }
+            self.calibrations.insert(entry.sensor_id.clone(), entry.offset);
+        }
+        report.applied = export.entries.len();
+        report
+    }
+
+    /// Replaces the thresholds used to flag sensors diverging from their zone.
+    pub fn set_divergence_config(&mut self, config: DivergenceConfig) {
+        self.divergence = config;
+    }
+
+    pub fn sensor_state(&self, sensor_id: &str) -> SensorState {
+        self.states.get(sensor_id).copied().unwrap_or_default()
+    }
+
+    fn ensure_active(&self, sensor_id: &SensorId) -> Result<(), ProtocolError> {
+        match self.sensor_state(sensor_id) {
+            SensorState::Active => Ok(()),
+            state => Err(ProtocolError::SensorUnavailable { sensor_id: sensor_id.clone(), state }),
+        }
+    }
+
+    fn ensure_not_decommissioned(&self, sensor_id: &SensorId) -> Result<(), ProtocolError> {
+        match self.sensor_state(sensor_id) {
+            SensorState::Decommissioned => Err(ProtocolError::SensorUnavailable {
+                sensor_id: sensor_id.clone(),
+                state: SensorState::Decommissioned,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Drops a sensor's live configuration and alerts; its stored history stays.
+    fn decommission(&mut self, sensor_id: &str) {
+        self.setpoints.remove(sensor_id);
+        self.zones.remove(sensor_id);
+        self.tags.remove(sensor_id);
+        self.failures.remove(sensor_id);
+        for event in self.alerts.clear_sensor(sensor_id, self.clock.now()) {
+            self.alert_history.record_event(&event);
+        }
+    }
+
+    /// Self-tests the sensors read by the handler itself; one that cannot
+    /// be reached counts as failed.
+    fn self_test_own_sensors(&mut self) {
+        for (sensor_id, source) in &mut self.sensors {
+            if let SensorSource::Mock(sensor) = source {
+                let health = sensor.self_test().unwrap_or(temp_core::SensorHealth::Failed);
+                let shared = self.self_tests.entry(sensor_id.clone()).or_default();
+                *error_hook::lock(shared, "Recovered the sensor health lock") = Some(health);
+            }
+        }
+    }
+
+    /// Stores a reading from an external system, see [`ingest`].
+    fn submit_reading(&mut self, source: &str, reading: ExternalReading) -> Response {
+        let ExternalReading { sensor_id, temperature, timestamp } = reading;
+        if !self.stores.contains_key(&sensor_id) {
+            return self.error_response(&ProtocolError::InvalidSensorId { sensor_id: sensor_id.to_string() });
+        }
+        if let Err(error) = self.ensure_active(&sensor_id) {
+            return self.error_response(&error);
+        }
+        let now = self.clock.now();
+        let timestamp = timestamp.unwrap_or(now);
+        if timestamp > now + ingest::MAX_CLOCK_SKEW_SECONDS {
+            return self.error_response(&ProtocolError::InvalidTimestamp { timestamp });
+        }
+        let temperature = match Temperature::try_new(temperature) {
+            Ok(temperature) => temperature,
+            Err(e) => {
+                let error = ProtocolError::InvalidTemperature { celsius: temperature, reason: e.to_string() };
+                return self.error_response(&error);
+            }
+        };
+        if !self.ingest_limits.admit(source, now) {
+            return self.error_response(&ProtocolError::RateLimited { source: source.to_string() });
+        }
+
+        let store = &self.stores[&sensor_id];
+        let duplicate = store.with_recent_readings(ingest::DEDUP_WINDOW, |recent| {
+            recent.iter().any(|r| r.timestamp == timestamp)
+        });
+        if duplicate {
+            return Response::ReadingSubmitted { sensor_id, timestamp, outcome: SubmitOutcome::Duplicate };
+        }
+        let transformed = match self.transforms.get(&sensor_id) {
+            Some(pipeline) => error_hook::lock(pipeline, "Recovered the transform pipeline lock").apply(temperature),
+            None => Some(temperature),
+        };
+        let Some(temperature) = transformed else {
+            return self.error_response(&ProtocolError::ReadingFiltered { sensor_id });
+        };
+        {
+            let _span = self.span("store.add_reading");
+            store.add_reading(TemperatureReading::with_timestamp(temperature, timestamp));
+        }
+        for event in self.alerts.evaluate(&sensor_id, temperature.celsius, timestamp) {
+            self.alert_history.record_event(&event);
+        }
+        Response::ReadingSubmitted { sensor_id, timestamp, outcome: SubmitOutcome::Accepted }
+    }
+
+    /// Status of the sensors with all of `tags`, every sensor for none.
+    fn status(&mut self, tags: &Tags) -> Response {
+        self.self_test_own_sensors();
+        let mut sensors = self.sensor_status();
+        sensors.retain(|s| tags::matches(&s.tags, tags));
+        Response::Status {
+            uptime_seconds: self.uptime_seconds(),
+            readings_count: sensors
+                .iter()
+                .filter_map(|s| self.stores.get(&s.sensor_id))
+                .map(|store| store.reading_count())
+                .sum(),
+            sensors,
+        }
+    }
+
+    /// Registered sensors with all of `tags`, except decommissioned ones.
+    fn sensors_tagged<'a>(&'a self, tags: &'a Tags) -> impl Iterator<Item = &'a SensorId> + 'a {
+        self.sensors.keys().filter(move |sensor_id| {
+            self.sensor_state(sensor_id) != SensorState::Decommissioned
+                && tags::matches(self.tags.get(*sensor_id).unwrap_or(&Tags::new()), tags)
+        })
+    }
+
+    /// Per-sensor status for dashboards, sorted by sensor id. Decommissioned
+    /// sensors are left out.
+    pub fn sensor_status(&self) -> Vec<SensorStatus> {
+        let now = self.clock.now();
+        let mut status: Vec<SensorStatus> = self
+            .sensors
+            .keys()
+            .filter(|sensor_id| self.sensor_state(sensor_id) != SensorState::Decommissioned)
+            .map(|sensor_id| {
+                let last_reading_ts = self.stores.get(sensor_id).and_then(|s| s.get_latest()).map(|r| r.timestamp);
+                SensorStatus {
+                    sensor_id: sensor_id.clone(),
+                    state: self.sensor_state(sensor_id),
+                    last_reading_ts,
+                    last_reading_age_seconds: last_reading_ts.map(|ts| now.saturating_sub(ts)),
+                    consecutive_failures: self.failures.get(sensor_id).copied().unwrap_or(0),
+                    calibration_offset: self.calibrations.get(sensor_id).copied(),
+                    threshold: self.alerts.thresholds(sensor_id),
+                    transforms: self
+                        .transforms
+                        .get(sensor_id)
+                        .map(|p| error_hook::lock(p, "Recovered the transform pipeline lock").config().to_vec())
+                        .unwrap_or_default(),
+                    self_test: self
+                        .self_tests
+                        .get(sensor_id)
+                        .and_then(|health| *error_hook::lock(health, "Recovered the sensor health lock")),
+                    tags: self.tags.get(sensor_id).cloned().unwrap_or_default(),
+                }
+            })
+            .collect();
+        status.sort_by(|a, b| a.sensor_id.cmp(&b.sensor_id));
+        status
+    }
+
+    /// Per-sensor health, comparing each zoned sensor with its peers.
+    /// Decommissioned sensors are left out.
+    pub fn health_report(&self) -> Vec<SensorHealth> {
+        let mut by_zone: HashMap<&str, Vec<(&str, &TemperatureStore)>> = HashMap::new();
+        for (sensor_id, zone) in &self.zones {
+            by_zone.entry(zone).or_default().push((sensor_id, &self.stores[sensor_id]));
+        }
+
+        let mut peers: HashMap<String, PeerComparison> = HashMap::new();
+        for sensors in by_zone.values().filter(|sensors| sensors.len() >= 3) {
+            // Analyse up to the newest reading in the zone
+            let Some(end) = sensors.iter().filter_map(|(_, store)| store.get_latest()).map(|r| r.timestamp + 1).max() else {
+                continue;
+            };
+            for comparison in temp_store::correlation::compare_peers(sensors, end, &self.divergence) {
+                peers.insert(comparison.sensor_id.clone(), comparison);
+            }
+        }
+
+        let now = self.clock.now();
+        let mut report: Vec<SensorHealth> = self
+            .stores
+            .iter()
+            .filter(|(sensor_id, _)| self.sensor_state(sensor_id) != SensorState::Decommissioned)
+            .map(|(sensor_id, store)| SensorHealth {
+                sensor_id: sensor_id.clone(),
+                zone: self.zones.get(sensor_id).cloned(),
+                reading_count: store.reading_count(),
+                peers: peers.remove(sensor_id.as_str()),
+                alerts: self.alert_history.counts(&AlertQuery {
+                    sensor_id: Some(sensor_id.to_string()),
+                    start: Some(now.saturating_sub(DAY_SECONDS)),
+                    ..Default::default()
+                }),
+            })
+            .collect();
+        report.sort_by(|a, b| a.sensor_id.cmp(&b.sensor_id));
+        report
+    }
+
+    /// Keeps alert history in `history` from now on, e.g. one opened on disk.
+    pub fn set_alert_history(&mut self, history: AlertHistory) {
+        self.alert_history = history;
+    }
+
+    /// Seconds since the handler started. A clock stepped backwards reads as 0
+    /// rather than wrapping around.
+    pub fn uptime_seconds(&self) -> u64 {
+        self.clock.now().saturating_sub(self.start_time)
+    }
+
+    /// Sends escalation notifications that have come due; call periodically.
+    pub fn dispatch_notifications<N: Notifier>(&mut self, notifier: &mut N) -> usize {
+        self.alerts.dispatch(self.clock.now(), notifier)
+    }
+
+    /// Setpoint requested for a sensor's control loop, if any.
+    pub fn setpoint(&self, sensor_id: &str) -> Option<f32> {
+        self.setpoints.get(sensor_id).copied()
+    }
+
+    /// Home Assistant discovery config messages for every registered sensor.
+    pub fn home_assistant_discovery(&self, discovery: &HomeAssistantDiscovery) -> Result<Vec<MqttMessage>, serde_json::Error> {
+        let mut sensor_ids: Vec<&SensorId> = self.sensors.keys().collect();
+        sensor_ids.sort();
+
+        sensor_ids
+            .into_iter()
+            .map(|sensor_id| discovery.discovery_message(sensor_id))
+            .collect()
+    }
+
+    pub fn serialize_json(&self, message: &ProtocolMessage) -> Result<String, serde_json::Error> {
+        serde_json::to_string(message)
+    }
+
+    pub fn serialize_binary(&self, message: &ProtocolMessage) -> Result<Vec<u8>, postcard::Error> {
+        postcard::to_allocvec(message)
+    }
+
+    pub fn deserialize_json(&self, data: &str) -> Result<ProtocolMessage, serde_json::Error> {
+        serde_json::from_str(data)
+    }
+
+    /// Also reads frames from before headers, which end after the payload.
+    pub fn deserialize_binary(&self, data: &[u8]) -> Result<ProtocolMessage, postcard::Error> {
+        let ((version, id, payload), rest) = postcard::take_from_bytes(data)?;
+        let headers = if rest.is_empty() { Headers::new() } else { postcard::from_bytes(rest)? };
+        Ok(ProtocolMessage { version, id, payload, headers })
+    }
+}
+
+impl Default for TemperatureProtocolHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{acl, auth, calibration};
+    use temp_alert::EscalationPolicy;
+    use temp_core::transform::TransformConfig;
+    use temp_core::{MeasurementRange, SensorInfo};
+    use temp_store::Resolution;
+
+    #[test]
+    fn test_command_serialization() {
+        let command = Command::GetReading {
+            sensor_id: "temp_01".parse().unwrap(),
+        };
+
+        let message = ProtocolMessage {
+            version: 1,
+            id: 123,
+            payload: MessagePayload::Command(command),
+            headers: Headers::new(),
+        };
+
+        // Test JSON serialization
+        let json_str = serde_json::to_string(&message).unwrap();
+        let parsed_message: ProtocolMessage = serde_json::from_str(&json_str).unwrap();
+        assert_eq!(message, parsed_message);
+
+        // Test binary serialization
+        let binary_data = postcard::to_allocvec(&message).unwrap();
+        let parsed_message: ProtocolMessage = postcard::from_bytes(&binary_data).unwrap();
+        assert_eq!(message, parsed_message);
+    }
+
+    #[test]
+    fn test_binary_from_first_version() {
+        // As encoded by the first release, before headers and later commands
+        let handler = TemperatureProtocolHandler::new();
+        let calibrate = [1, 7, 0, 5, 7, 116, 101, 109, 112, 95, 48, 49, 0, 0, 200, 65];
+        let get_stats = [1, 7, 0, 4, 7, 116, 101, 109, 112, 95, 48, 49];
+        let calibrated = [1, 7, 1, 5, 7, 116, 101, 109, 112, 95, 48, 49, 0, 0, 192, 63];
+        let error = [1, 7, 1, 6, 148, 3, 4, 103, 111, 110, 101];
+
+        let sensor_id: SensorId = "temp_01".parse().unwrap();
+        let decoded = handler.deserialize_binary(&calibrate).unwrap();
+        assert_eq!((decoded.version, decoded.id), (1, 7));
+        assert!(decoded.headers.is_empty());
+        assert_eq!(decoded.payload, MessagePayload::Command(Command::Calibrate { sensor_id: sensor_id.clone(), actual_temp: 25.0 }));
+        assert_eq!(
+            handler.deserialize_binary(&get_stats).unwrap().payload,
+            MessagePayload::Command(Command::GetStats { sensor_id: sensor_id.clone() })
+        );
+        assert_eq!(
+            handler.deserialize_binary(&calibrated).unwrap().payload,
+            MessagePayload::Response(Response::CalibrationComplete { sensor_id, offset_adjustment: 1.5 })
+        );
+        assert_eq!(
+            handler.deserialize_binary(&error).unwrap().payload,
+            MessagePayload::Response(Response::Error { code: 404, message: "gone".to_string() })
+        );
+
+        // Frames with headers still round-trip
+        let mut message = handler.create_response(7, Response::Error { code: 404, message: "gone".to_string() });
+        message.headers.insert("traceparent".to_string(), "00-01".to_string());
+        assert_eq!(handler.deserialize_binary(&handler.serialize_binary(&message).unwrap()).unwrap(), message);
+    }
+
+    #[test]
+    fn test_binary_vs_json_size() {
+        let command = Command::GetHistory {
+            sensor_id: "long_sensor_name_testing".parse().unwrap(),
+            last_n: 100,
+        };
+
+        let message = ProtocolMessage {
+            version: 1,
+            id: 12345,
+            payload: MessagePayload::Command(command),
+            headers: Headers::new(),
+        };
+
+        let json_data = serde_json::to_string(&message).unwrap();
+        let binary_data = postcard::to_allocvec(&message).unwrap();
+
+        println!("JSON size: {} bytes", json_data.len());
+        println!("Binary size: {} bytes", binary_data.len());
+
+        // Binary should be significantly smaller than JSON
+        assert!(binary_data.len() < json_data.len());
+
+        // For this message, we expect at least 30% space savings
+        let savings_ratio = (json_data.len() - binary_data.len()) as f32 / json_data.len() as f32;
+        assert!(savings_ratio > 0.3, "Expected at least 30% space savings, got {:.1}%", savings_ratio * 100.0);
+    }
+
+    #[test]
+    fn test_protocol_versioning() {
+        let mut handler = TemperatureProtocolHandler::new();
+
+        // Create message with wrong version
+        let message = ProtocolMessage {
+            version: 2, // Wrong version
+            id: 1,
+            payload: MessagePayload::Command(Command::GetStatus),
+            headers: Headers::new(),
+        };
+
+        let response = handler.process_command(message);
+
+        if let MessagePayload::Response(Response::Error { code, message: msg }) = response.payload {
+            assert_eq!(code, 505);
+            assert!(msg.contains("version mismatch"));
+        } else {
+            panic!("Expected version mismatch error");
+        }
+    }
+
+    #[test]
+    fn test_error_responses() {
+        let mut handler = TemperatureProtocolHandler::new();
+
+        // Test invalid sensor ID
+        let message = handler.create_command(Command::GetReading {
+            sensor_id: "nonexistent_sensor".parse().unwrap(),
+        });
+
+        let response = handler.process_command(message);
+
+        if let MessagePayload::Response(Response::Error { code, message: msg }) = response.payload {
+            assert_eq!(code, 404);
+            assert!(msg.contains("not found"));
+        } else {
+            panic!("Expected sensor not found error");
+        }
+
+        // Test invalid threshold
+        let message = handler.create_command(Command::SetThreshold {
+            sensor_id: "temp_01".parse().unwrap(),
+            min_temp: 30.0,
+            max_temp: 20.0, // Invalid: min > max
+            hysteresis: 0.0,
+            consecutive: 1,
+        });
+
+        let response = handler.process_command(message);
+
+        if let MessagePayload::Response(Response::Error { code, message: msg }) = response.payload {
+            assert_eq!(code, 400);
+            assert!(msg.contains("Invalid threshold"));
+        } else {
+            panic!("Expected invalid threshold error");
+        }
+    }
+
+    #[test]
+    fn test_command_processing() {
+        let mut handler = TemperatureProtocolHandler::new();
+
+        // Test GetStatus command
+        let message = handler.create_command(Command::GetStatus);
+        let response = handler.process_command(message);
+
+        if let MessagePayload::Response(Response::Status { sensors, uptime_seconds: _, readings_count }) = response.payload {
+            assert_eq!(sensors.len(), 3); // We have 3 mock sensors
+            assert_eq!(sensors[0].sensor_id, "temp_01");
+            assert!(sensors.iter().all(|s| s.last_reading_ts.is_none() && s.threshold.is_none()));
+            assert_eq!(readings_count, 0); // No readings yet
+        } else {
+            panic!("Expected status response");
+        }
+
+        // Test GetReading command
+        let message = handler.create_command(Command::GetReading {
+            sensor_id: "temp_01".parse().unwrap(),
+        });
+        let response = handler.process_command(message);
+
+        if let MessagePayload::Response(Response::Reading { sensor_id, temperature, timestamp: _ }) = response.payload {
+            assert_eq!(sensor_id, "temp_01");
+            assert!((temperature - 23.5).abs() < 1.0); // Should be close to base temp (23.5) with some variation
+        } else {
+            panic!("Expected reading response");
+        }
+
+        // Test SetThreshold command
+        let message = handler.create_command(Command::SetThreshold {
+            sensor_id: "temp_01".parse().unwrap(),
+            min_temp: 15.0,
+            max_temp: 35.0,
+            hysteresis: 0.0,
+            consecutive: 1,
+        });
+        let response = handler.process_command(message);
+
+        if let MessagePayload::Response(Response::ThresholdSet { sensor_id, threshold }) = response.payload {
+            assert_eq!(sensor_id, "temp_01");
+            assert_eq!(threshold, ThresholdConfig::range(15.0, 35.0).unwrap());
+        } else {
+            panic!("Expected threshold set response");
+        }
+    }
+
+    #[test]
+    fn test_calibration() {
+        let mut handler = TemperatureProtocolHandler::new();
+
+        // Test calibration
+        let message = handler.create_command(Command::Calibrate {
+            sensor_id: "temp_01".parse().unwrap(),
+            actual_temp: 25.0,
+        });
+        let response = handler.process_command(message);
+
+        if let MessagePayload::Response(Response::CalibrationComplete { sensor_id, offset_adjustment }) = response.payload {
+            assert_eq!(sensor_id, "temp_01");
+            // The offset should be the difference between actual and measured temperature
+            assert!((offset_adjustment - 1.5).abs() < 1e-4);
+        } else {
+            panic!("Expected calibration complete response");
+        }
+
+        // Readings are corrected while the sensor itself still reports raw values
+        let message = handler.create_command(Command::GetReading { sensor_id: "temp_01".parse().unwrap() });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Reading { temperature, .. }) if temperature == 25.0));
+        if let Some(SensorSource::Mock(sensor)) = handler.sensors.get_mut("temp_01") {
+            assert_eq!(sensor.read_raw().unwrap().celsius, 23.5);
+            sensor.inner_mut().inner_mut().set_temperature(20.0);
+        }
+        let message = handler.create_command(Command::GetReading { sensor_id: "temp_01".parse().unwrap() });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Reading { temperature, .. }) if temperature == 21.5));
+    }
+
+    #[test]
+    fn test_home_assistant_discovery() {
+        let handler = TemperatureProtocolHandler::new();
+        let discovery = HomeAssistantDiscovery::new("capstone".to_string());
+
+        let messages = handler.home_assistant_discovery(&discovery).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].topic, "homeassistant/sensor/capstone/temp_01/config");
+        assert!(messages.iter().all(|m| m.retain && m.payload.contains("\"device_class\":\"temperature\"")));
+    }
+
+    #[test]
+    fn test_range_query() {
+        let mut handler = TemperatureProtocolHandler::new();
+        let store = &handler.stores["temp_01"];
+        store.add_reading(TemperatureReading::with_timestamp(temp_core::Temperature::new(20.0), 1_200));
+        store.add_reading(TemperatureReading::with_timestamp(temp_core::Temperature::new(22.0), 1_230));
+
+        let message = handler.create_command(Command::GetRange {
+            sensor_id: "temp_01".parse().unwrap(),
+            start: 0,
+            end: 2 * 3600,
+        });
+        let response = handler.process_command(message);
+
+        if let MessagePayload::Response(Response::Range { resolution, points, .. }) = response.payload {
+            assert_eq!(resolution, Resolution::Minute);
+            assert_eq!(points.len(), 1);
+            assert_eq!(points[0].average.celsius, 21.0);
+        } else {
+            panic!("Expected range response");
+        }
+
+        let message = handler.create_command(Command::GetRange {
+            sensor_id: "temp_01".parse().unwrap(),
+            start: 10,
+            end: 10,
+        });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 400, .. })));
+    }
+
+    #[test]
+    fn test_degree_days() {
+        let mut handler = TemperatureProtocolHandler::new();
+        let store = &handler.stores["temp_01"];
+        for hour in 0..24u64 {
+            store.add_reading(TemperatureReading::with_timestamp(temp_core::Temperature::new(12.0), hour * 3600));
+        }
+
+        let message = handler.create_command(Command::GetDegreeDays {
+            sensor_id: "temp_01".parse().unwrap(),
+            start: 0,
+            end: 86_400,
+            base_celsius: None,
+        });
+        let response = handler.process_command(message);
+
+        if let MessagePayload::Response(Response::DegreeDays { report, .. }) = response.payload {
+            assert_eq!(report.base.celsius, DEFAULT_BASE_CELSIUS);
+            assert_eq!(report.days.len(), 1);
+            assert_eq!(report.total_heating, 6.0);
+        } else {
+            panic!("Expected degree-day response");
+        }
+
+        // Local days in New York split the same UTC day in two
+        let message = handler.create_command(Command::SetTimeZone { time_zone: "America/New_York".to_string() });
+        handler.process_command(message);
+        let message = handler.create_command(Command::GetDegreeDays {
+            sensor_id: "temp_01".parse().unwrap(),
+            start: 0,
+            end: 86_400,
+            base_celsius: None,
+        });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::DegreeDays { report, .. }) if report.days.len() == 2));
+
+        let message = handler.create_command(Command::SetTimeZone { time_zone: "Mars/Olympus_Mons".to_string() });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 400, .. })));
+
+        let message = handler.create_command(Command::GetDegreeDays {
+            sensor_id: "missing".parse().unwrap(),
+            start: 0,
+            end: 86_400,
+            base_celsius: Some(15.5),
+        });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 404, .. })));
+    }
+
+    #[test]
+    fn test_forecast() {
+        let mut handler = TemperatureProtocolHandler::new();
+
+        let message = handler.create_command(Command::GetForecast {
+            sensor_id: "temp_01".parse().unwrap(),
+            horizon: 1200,
+        });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 422, .. })));
+
+        let store = &handler.stores["temp_01"];
+        for minute in 0..30u64 {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(20.0 + 0.5 * minute as f32), minute * 60));
+        }
+
+        let message = handler.create_command(Command::GetForecast {
+            sensor_id: "temp_01".parse().unwrap(),
+            horizon: 1200,
+        });
+        let response = handler.process_command(message);
+
+        if let MessagePayload::Response(Response::Forecast { forecast, .. }) = response.payload {
+            assert_eq!(forecast.points.len(), 20);
+            assert!(forecast.first_above(Temperature::new(40.0)).is_some());
+        } else {
+            panic!("Expected forecast response");
+        }
+    }
+
+    #[test]
+    fn test_sensor_info() {
+        let mut handler = TemperatureProtocolHandler::new();
+        let message = handler.create_command(Command::GetSensorInfo { sensor_id: "temp_01".parse().unwrap() });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::SensorInfo { info, .. }) if info.model == "mock"));
+
+        let spec = SensorSpec {
+            manufacturer: "Maxim Integrated".to_string(),
+            model: "DS18B20".to_string(),
+            accuracy: 0.5,
+            resolution: 0.0625,
+            range: MeasurementRange::new(-55.0, 125.0),
+        };
+        assert!(spec.is_significant_change(0.5));
+        handler.attach_store("boiler".parse().unwrap(), TemperatureStore::new(10));
+        let message = handler.create_command(Command::GetSensorInfo { sensor_id: "boiler".parse().unwrap() });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 404, .. })));
+
+        handler.attach_sensor_info("boiler".parse().unwrap(), spec.clone());
+        let message = handler.create_command(Command::GetSensorInfo { sensor_id: "boiler".parse().unwrap() });
+        let response = handler.process_command(message);
+        assert_eq!(response.payload, MessagePayload::Response(Response::SensorInfo { sensor_id: "boiler".parse().unwrap(), info: spec }));
+    }
+
+    #[test]
+    fn test_status_reports_self_tests() {
+        let mut handler = TemperatureProtocolHandler::new();
+        if let Some(SensorSource::Mock(sensor)) = handler.sensors.get_mut("temp_01") {
+            sensor.inner_mut().inner_mut().set_offline(true);
+        }
+        if let Some(SensorSource::Mock(sensor)) = handler.sensors.get_mut("temp_02") {
+            sensor.inner_mut().inner_mut().set_health(temp_core::SensorHealth::Degraded);
+        }
+        let published: SharedHealth = Arc::new(Mutex::new(None));
+        handler.attach_store("boiler".parse().unwrap(), TemperatureStore::new(10));
+        handler.attach_self_test("boiler".parse().unwrap(), Arc::clone(&published));
+
+        let message = handler.create_command(Command::GetStatus);
+        let MessagePayload::Response(Response::Status { sensors, .. }) = handler.process_command(message).payload else {
+            panic!("Expected a status");
+        };
+        let results: Vec<_> = sensors.iter().map(|s| (s.sensor_id.as_str(), s.self_test)).collect();
+        assert_eq!(
+            results,
+            vec![
+                ("boiler", None),
+                ("temp_01", Some(temp_core::SensorHealth::Failed)),
+                ("temp_02", Some(temp_core::SensorHealth::Degraded)),
+                ("temp_03", Some(temp_core::SensorHealth::Ok)),
+            ]
+        );
+
+        *published.lock().unwrap() = Some(temp_core::SensorHealth::Ok);
+        assert_eq!(handler.sensor_status()[0].self_test, Some(temp_core::SensorHealth::Ok));
+    }
+
+    #[test]
+    fn test_authentication() {
+        let clock = temp_core::clock::ManualClock::new(1_000);
+        let tokens = auth::StaticTokens::new().with_token("s3cret", Identity::new("operator"));
+        let mut handler = TemperatureProtocolHandler::new().with_clock(clock.clone()).with_auth(tokens);
+        let mut session = SessionState::default();
+
+        // Errors can be localized before authenticating, nothing else runs
+        let message = handler.create_command(Command::SetLanguage { preferences: "de".to_string() });
+        handler.process_command_for(message, &mut session);
+        assert_eq!(session.language, Language::German);
+        let message = handler.create_command(Command::GetStatus);
+        let response = handler.process_command_for(message, &mut session);
+        assert_eq!(
+            response.payload,
+            MessagePayload::Response(Response::Error { code: 401, message: "Bitte zuerst anmelden".to_string() })
+        );
+
+        let message = handler.create_command(Command::Authenticate { token: "guess".to_string() });
+        let response = handler.process_command_for(message, &mut session);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 401, .. })));
+        assert_eq!(session.identity, None);
+
+        let message = handler.create_command(Command::Authenticate { token: "s3cret".to_string() });
+        handler.process_command_for(message, &mut session);
+        assert_eq!(session.identity, Some(Identity::new("operator")));
+        let message = handler.create_command(Command::GetStatus);
+        let response = handler.process_command_for(message, &mut session);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Status { .. })));
+
+        // Flag changes are attributed to the session
+        let message = handler.create_command(Command::SetCommandEnabled {
+            command: "RestoreBackup".to_string(),
+            enabled: false,
+            changed_by: None,
+            reason: None,
+        });
+        let response = handler.process_command_for(message, &mut session);
+        assert!(matches!(
+            response.payload,
+            MessagePayload::Response(Response::CommandFlagSet { change }) if change.changed_by.as_deref() == Some("operator")
+        ));
+
+        // The identity is only in effect for the session's own commands
+        let message = handler.create_command(Command::GetStatus);
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 401, .. })));
+
+        // An identity from an expired token no longer counts
+        session.identity = Some(Identity { expires_at: Some(1_060), ..Identity::new("operator") });
+        clock.advance(60);
+        let message = handler.create_command(Command::GetStatus);
+        let response = handler.process_command_for(message, &mut session);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 401, .. })));
+    }
+
+    #[test]
+    fn test_access_control() {
+        use acl::{AccessControl, Principal, Resource};
+
+        let acl = AccessControl::new()
+            .grant(Principal::Subject("admin".to_string()), Resource::Site, Permission::Write)
+            .grant(Principal::Subject("tenant".to_string()), Resource::Zone("flat_a".to_string()), Permission::Write)
+            .grant(Principal::Subject("tenant".to_string()), Resource::Sensor("temp_03".to_string()), Permission::Read);
+        let mut handler = TemperatureProtocolHandler::new().with_access_control(acl);
+        let mut admin = SessionState { identity: Some(Identity::new("admin")), ..Default::default() };
+        let mut tenant = SessionState { identity: Some(Identity::new("tenant")), ..Default::default() };
+
+        let message = handler.create_command(Command::SetZone { sensor_id: "temp_01".parse().unwrap(), zone: "flat_a".to_string() });
+        let response = handler.process_command_for(message, &mut admin);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::ZoneSet { .. })));
+
+        let mut code_for = |handler: &mut TemperatureProtocolHandler, command: Command| {
+            let message = handler.create_command(command);
+            match handler.process_command_for(message, &mut tenant).payload {
+                MessagePayload::Response(Response::Error { code, .. }) => code,
+                _ => 200,
+            }
+        };
+        let reading = |sensor_id: &str| Command::GetReading { sensor_id: sensor_id.parse().unwrap() };
+        let setpoint = |sensor_id: &str| Command::SetSetpoint { sensor_id: sensor_id.parse().unwrap(), setpoint: 21.0 };
+
+        // Their own zone, a sensor shared read-only, and one they cannot even see
+        assert_eq!(code_for(&mut handler, reading("temp_01")), 200);
+        assert_eq!(code_for(&mut handler, setpoint("temp_01")), 200);
+        assert_eq!(code_for(&mut handler, reading("temp_03")), 200);
+        assert_eq!(code_for(&mut handler, setpoint("temp_03")), 403);
+        assert_eq!(code_for(&mut handler, reading("temp_02")), 404);
+        assert_eq!(code_for(&mut handler, setpoint("temp_02")), 404);
+        assert_eq!(code_for(&mut handler, Command::SetTimeZone { time_zone: "UTC".to_string() }), 403);
+        let into_other_zone = Command::SetZone { sensor_id: "temp_01".parse().unwrap(), zone: "flat_b".to_string() };
+        assert_eq!(code_for(&mut handler, into_other_zone), 403);
+
+        let message = handler.create_command(Command::GetStatus);
+        let MessagePayload::Response(Response::Status { sensors, readings_count, .. }) = handler.process_command_for(message, &mut tenant).payload else {
+            panic!("Expected status");
+        };
+        let ids: Vec<&str> = sensors.iter().map(|s| s.sensor_id.as_str()).collect();
+        assert_eq!(ids, ["temp_01", "temp_03"]);
+        assert_eq!(readings_count, 2);
+
+        // Without an identity nothing is granted
+        let message = handler.create_command(reading("temp_01"));
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 404, .. })));
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut handler = TemperatureProtocolHandler::new();
+        for (sensor_id, tags) in [("temp_01", "floor=1,owner=ops"), ("temp_02", "floor=2,owner=ops"), ("temp_03", "floor=2")] {
+            let tags = tags::parse(tags).unwrap();
+            let message = handler.create_command(Command::SetTags { sensor_id: sensor_id.parse().unwrap(), tags });
+            assert!(matches!(handler.process_command(message).payload, MessagePayload::Response(Response::TagsSet { .. })));
+        }
+        let message = handler.create_command(Command::SetTags {
+            sensor_id: "temp_01".parse().unwrap(),
+            tags: Tags::from([("a=b".to_string(), "c".to_string())]),
+        });
+        assert!(matches!(handler.process_command(message).payload, MessagePayload::Response(Response::Error { code: 400, .. })));
+
+        let message = handler.create_command(Command::QuerySensors { tags: tags::parse("owner=ops").unwrap() });
+        let MessagePayload::Response(Response::Status { sensors, .. }) = handler.process_command(message).payload else {
+            panic!("Expected status");
+        };
+        let ids: Vec<&str> = sensors.iter().map(|s| s.sensor_id.as_str()).collect();
+        assert_eq!(ids, ["temp_01", "temp_02"]);
+        assert_eq!(sensors[0].tags["floor"], "1");
+
+        // A threshold for the second floor alerts on its sensors only
+        let message = handler.create_command(Command::SetThresholdForTags {
+            tags: tags::parse("floor=2").unwrap(),
+            min_temp: 0.0,
+            max_temp: 22.0,
+            hysteresis: 0.0,
+            consecutive: 1,
+        });
+        let MessagePayload::Response(Response::ThresholdsSet { sensor_ids, .. }) = handler.process_command(message).payload else {
+            panic!("Expected thresholds");
+        };
+        assert_eq!(sensor_ids, ["temp_02", "temp_03"]);
+        for sensor_id in ["temp_01", "temp_02", "temp_03"] {
+            let message = handler.create_command(Command::GetReading { sensor_id: sensor_id.parse().unwrap() });
+            handler.process_command(message);
+        }
+        // temp_03 reads 25.1 °C, temp_02 is within range at 21.8 °C
+        let message = handler.create_command(Command::QueryAlertHistory {
+            query: AlertQuery::default(),
+            tags: tags::parse("owner=ops").unwrap(),
+        });
+        assert_eq!(handler.process_command(message).payload, MessagePayload::Response(Response::AlertHistory { records: Vec::new() }));
+        let message = handler.create_command(Command::QueryAlertHistory { query: AlertQuery::default(), tags: tags::parse("floor=2").unwrap() });
+        let MessagePayload::Response(Response::AlertHistory { records }) = handler.process_command(message).payload else {
+            panic!("Expected alert history");
+        };
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].sensor_id, "temp_03");
+    }
+
+    #[test]
+    fn test_tracing() {
+        use trace::{RecordingExporter, TRACEPARENT, TRACESTATE};
+
+        let exporter = RecordingExporter::new();
+        let mut handler = TemperatureProtocolHandler::new().with_tracing(exporter.clone());
+        let caller = TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+
+        let mut message = handler.create_command(Command::GetHistory { sensor_id: "temp_01".parse().unwrap(), last_n: 5 });
+        message.headers.insert(TRACEPARENT.to_string(), caller.to_string());
+        message.headers.insert(TRACESTATE.to_string(), "vendor=1".to_string());
+        let response = handler.process_command(message);
+        let server = TraceContext::parse(&response.headers[TRACEPARENT]).unwrap();
+        assert_eq!(server.trace_id, caller.trace_id);
+        assert_eq!(response.headers[TRACESTATE], "vendor=1");
+
+        // The store call is a child of the server span, which is a child of the caller's
+        let spans = exporter.spans();
+        assert_eq!(spans.len(), 2);
+        assert_eq!((spans[0].name.as_str(), spans[0].kind), ("store.recent_readings", SpanKind::Internal));
+        assert_eq!(spans[0].parent_span_id, Some(server.span_id));
+        assert_eq!(spans[1].name, "GetHistory");
+        assert_eq!(spans[1].context, server);
+        assert_eq!(spans[1].parent_span_id, Some(caller.span_id));
+        assert!(spans.iter().all(|span| span.context.trace_id == caller.trace_id));
+
+        // Without a trace context a new trace starts, and errors are recorded
+        let message = handler.create_command(Command::GetStats { sensor_id: "missing".parse().unwrap() });
+        let response = handler.process_command(message);
+        let root = exporter.spans().pop().unwrap();
+        assert_eq!(root.parent_span_id, None);
+        assert_ne!(root.context.trace_id, caller.trace_id);
+        assert_eq!(response.headers[TRACEPARENT], root.context.to_string());
+        assert_eq!(root.attributes, [(trace::STATUS_CODE_ATTRIBUTE.to_string(), "404".to_string())]);
+
+        // A trace the caller does not record is passed on but not exported
+        let mut message = handler.create_command(Command::GetStatus);
+        let unsampled = TraceContext { sampled: false, ..caller };
+        message.headers.insert(TRACEPARENT.to_string(), unsampled.to_string());
+        let response = handler.process_command(message);
+        assert!(!TraceContext::parse(&response.headers[TRACEPARENT]).unwrap().sampled);
+        assert_eq!(exporter.spans().len(), 3);
+    }
+
+    #[test]
+    fn test_health_flags_divergent_sensor() {
+        let mut handler = TemperatureProtocolHandler::new();
+
+        for (sensor_id, offset) in [("temp_01", 0.0), ("temp_02", 0.4), ("temp_03", 6.0)] {
+            let message = handler.create_command(Command::SetZone {
+                sensor_id: sensor_id.parse().unwrap(),
+                zone: "lab".to_string(),
+            });
+            handler.process_command(message);
+
+            let store = &handler.stores[sensor_id];
+            for minute in 0..20u64 {
+                store.add_reading(TemperatureReading::with_timestamp(Temperature::new(21.0 + offset), minute * 60));
+            }
+        }
+
+        let message = handler.create_command(Command::GetHealth);
+        let response = handler.process_command(message);
+
+        if let MessagePayload::Response(Response::Health { sensors }) = response.payload {
+            assert_eq!(sensors.len(), 3);
+            assert_eq!(sensors[0].zone.as_deref(), Some("lab"));
+            assert!(!sensors[0].suspected_faulty());
+            assert!(!sensors[1].suspected_faulty());
+            assert!(sensors[2].suspected_faulty());
+        } else {
+            panic!("Expected health response");
+        }
+
+        let message = handler.create_command(Command::SetZone {
+            sensor_id: "missing".parse().unwrap(),
+            zone: "lab".to_string(),
+        });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 404, .. })));
+    }
+
+    #[test]
+    fn test_alert_commands() {
+        let mut handler = TemperatureProtocolHandler::new();
+
+        let message = handler.create_command(Command::SetThreshold {
+            sensor_id: "temp_03".parse().unwrap(),
+            min_temp: 10.0,
+            max_temp: 20.0,
+            hysteresis: 0.0,
+            consecutive: 1,
+        });
+        handler.process_command(message);
+
+        // temp_03 reads about 25 °C
+        let message = handler.create_command(Command::GetReading { sensor_id: "temp_03".parse().unwrap() });
+        handler.process_command(message);
+
+        let message = handler.create_command(Command::ListAlerts { include_resolved: false });
+        let alert_id = match handler.process_command(message).payload {
+            MessagePayload::Response(Response::Alerts { alerts, silences }) => {
+                assert_eq!(alerts.len(), 1);
+                assert_eq!(alerts[0].key.sensor_id, "temp_03");
+                assert!(silences.is_empty());
+                alerts[0].id
+            }
+            other => panic!("Expected alerts response, got {:?}", other),
+        };
+
+        let message = handler.create_command(Command::SetEscalationPolicy {
+            policy: Some(EscalationPolicy::new(temp_alert::Severity::Warning).then(
+                0,
+                temp_alert::Channel::Webhook { url: "https://hooks.example/ops".to_string() },
+            )),
+        });
+        handler.process_command(message);
+
+        let mut notifier = temp_alert::escalation::RecordingNotifier::default();
+        assert_eq!(handler.dispatch_notifications(&mut notifier), 1);
+        assert_eq!(notifier.sent[0].alert_id, alert_id);
+
+        let message = handler.create_command(Command::AckAlert { alert_id, by: "ops".to_string() });
+        let response = handler.process_command(message);
+        assert_eq!(response.payload, MessagePayload::Response(Response::AlertAcknowledged { alert_id }));
+        assert_eq!(handler.dispatch_notifications(&mut notifier), 0);
+
+        let message = handler.create_command(Command::QueryAlertHistory {
+            query: AlertQuery {
+                sensor_id: Some("temp_03".to_string()),
+                ..Default::default()
+            },
+            tags: Tags::new(),
+        });
+        if let MessagePayload::Response(Response::AlertHistory { records }) = handler.process_command(message).payload {
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0].transition, Transition::Fired);
+            assert_eq!(records[1].transition, Transition::Acknowledged { by: "ops".to_string() });
+        } else {
+            panic!("Expected alert history response");
+        }
+        let health = handler.health_report();
+        let temp_03 = health.iter().find(|h| h.sensor_id == "temp_03").unwrap();
+        assert_eq!(temp_03.alerts.fired, 1);
+        assert_eq!(temp_03.alerts.acknowledged, 1);
+
+        let message = handler.create_command(Command::SilenceAlerts {
+            sensor_id: Some("temp_03".parse().unwrap()),
+            duration_seconds: 600,
+            reason: "maintenance".to_string(),
+        });
+        let silence_id = match handler.process_command(message).payload {
+            MessagePayload::Response(Response::Silenced { silence }) => silence.id,
+            other => panic!("Expected silence response, got {:?}", other),
+        };
+
+        let message = handler.create_command(Command::RemoveSilence { silence_id });
+        handler.process_command(message);
+        let message = handler.create_command(Command::RemoveSilence { silence_id });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 404, .. })));
+    }
+
+    #[test]
+    fn test_storage_info() {
+        let mut handler = TemperatureProtocolHandler::new();
+
+        let message = handler.create_command(Command::GetReading {
+            sensor_id: "temp_02".parse().unwrap(),
+        });
+        handler.process_command(message);
+
+        let message = handler.create_command(Command::GetStorageInfo);
+        let response = handler.process_command(message);
+
+        if let MessagePayload::Response(Response::StorageInfo { total_memory_bytes, sensors }) = response.payload {
+            assert_eq!(sensors.len(), 3);
+            assert_eq!(sensors[1].sensor_id, "temp_02");
+            assert_eq!(sensors[1].storage.readings, 1);
+            assert_eq!(sensors[0].storage.readings, 0);
+            assert_eq!(sensors[0].storage.capacity, STORE_CAPACITY_PER_SENSOR);
+            assert_eq!(total_memory_bytes, sensors.iter().map(|s| s.storage.memory_bytes).sum::<usize>());
+        } else {
+            panic!("Expected storage info response");
+        }
+    }
+
+    #[test]
+    fn test_compact_storage() {
+        let path = std::env::temp_dir().join(format!("temp_protocol_{}_compact.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = TemperatureStore::open(2, &path, temp_store::FlushPolicy::EveryWrite).unwrap();
+        for ts in 0..5 {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(20.0), ts * 3600));
+        }
+
+        let mut handler = TemperatureProtocolHandler::without_sensors();
+        handler.attach_store("cellar".parse().unwrap(), store);
+        let message = handler.create_command(Command::CompactStorage);
+        let MessagePayload::Response(Response::StorageCompacted { reclaimed_bytes, sensors }) = handler.process_command(message).payload else {
+            panic!("Expected compaction response");
+        };
+        // The hour tier still covers every reading, so nothing is dropped
+        assert_eq!(sensors.len(), 1);
+        assert_eq!(sensors[0].report.records_kept, 5);
+        assert_eq!(reclaimed_bytes, 0);
+
+        drop(handler);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let mut source = TemperatureProtocolHandler::new();
+        for _ in 0..3 {
+            let message = source.create_command(Command::GetReading { sensor_id: "temp_01".parse().unwrap() });
+            source.process_command(message);
+        }
+
+        let message = source.create_command(Command::ExportBackup {
+            sensor_id: "temp_01".parse().unwrap(),
+            format: BackupFormat::Compact,
+        });
+        let MessagePayload::Response(Response::Backup { readings: 3, data, .. }) = source.process_command(message).payload else {
+            panic!("Expected backup of three readings");
+        };
+
+        let mut target = TemperatureProtocolHandler::new();
+        let message = target.create_command(Command::RestoreBackup { sensor_id: "temp_01".parse().unwrap(), data: data.clone() });
+        let response = target.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::BackupRestored { readings: 3, .. })));
+        assert_eq!(target.stores["temp_01"].get_latest(), source.stores["temp_01"].get_latest());
+
+        let message = target.create_command(Command::RestoreBackup { sensor_id: "temp_01".parse().unwrap(), data: data[..data.len() - 1].to_vec() });
+        let response = target.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 400, .. })));
+    }
+
+    #[test]
+    fn test_setpoint() {
+        let mut handler = TemperatureProtocolHandler::new();
+
+        let message = handler.create_command(Command::SetSetpoint {
+            sensor_id: "temp_01".parse().unwrap(),
+            setpoint: 21.0,
+        });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::SetpointSet { setpoint, .. }) if setpoint == 21.0));
+        assert_eq!(handler.setpoint("temp_01"), Some(21.0));
+
+        let message = handler.create_command(Command::SetSetpoint {
+            sensor_id: "temp_01".parse().unwrap(),
+            setpoint: f32::NAN,
+        });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 400, .. })));
+        assert_eq!(handler.setpoint("temp_01"), Some(21.0));
+    }
+
+    #[test]
+    fn test_impossible_temperatures_are_rejected() {
+        let mut handler = TemperatureProtocolHandler::new();
+        let commands = [
+            Command::Calibrate { sensor_id: "temp_01".parse().unwrap(), actual_temp: f32::NAN },
+            Command::GetDegreeDays { sensor_id: "temp_01".parse().unwrap(), start: 0, end: 86_400, base_celsius: Some(-300.0) },
+            Command::SetThreshold {
+                sensor_id: "temp_01".parse().unwrap(),
+                min_temp: -300.0,
+                max_temp: 20.0,
+                hysteresis: 0.0,
+                consecutive: 1,
+            },
+        ];
+        for command in commands {
+            let message = handler.create_command(command);
+            let response = handler.process_command(message);
+            assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 400, .. })), "{:?}", response);
+        }
+        assert_eq!(handler.calibrations.get("temp_01"), None);
+        assert!(handler.alerts.thresholds("temp_01").is_none());
+
+        let error = ProtocolError::InvalidTemperature { celsius: -300.0, reason: "Temperature must not be below absolute zero".to_string() };
+        assert!(matches!(error.to_response(), Response::Error { message, .. } if message == "Invalid temperature -300: Temperature must not be below absolute zero"));
+    }
+
+    #[test]
+    fn test_status_details() {
+        let mut handler = TemperatureProtocolHandler::new();
+
+        let message = handler.create_command(Command::GetReading { sensor_id: "temp_01".parse().unwrap() });
+        handler.process_command(message);
+        let message = handler.create_command(Command::SetThreshold {
+            sensor_id: "temp_01".parse().unwrap(),
+            min_temp: 18.0,
+            max_temp: 26.0,
+            hysteresis: 0.0,
+            consecutive: 1,
+        });
+        handler.process_command(message);
+        let message = handler.create_command(Command::Calibrate {
+            sensor_id: "temp_03".parse().unwrap(),
+            actual_temp: 24.0,
+        });
+        handler.process_command(message);
+        if let Some(SensorSource::Mock(sensor)) = handler.sensors.get_mut("temp_02") {
+            sensor.inner_mut().inner_mut().set_offline(true);
+        }
+        for _ in 0..2 {
+            let message = handler.create_command(Command::GetReading { sensor_id: "temp_02".parse().unwrap() });
+            handler.process_command(message);
+        }
+
+        let message = handler.create_command(Command::GetStatus);
+        let MessagePayload::Response(Response::Status { sensors, readings_count, .. }) = handler.process_command(message).payload else {
+            panic!("Expected status response");
+        };
+        assert_eq!(readings_count, 1);
+
+        assert!(sensors[0].last_reading_ts.is_some());
+        assert!(sensors[0].last_reading_age_seconds.unwrap() <= 1);
+        assert_eq!(sensors[0].threshold, ThresholdConfig::range(18.0, 26.0).ok());
+        assert_eq!(sensors[0].consecutive_failures, 0);
+        assert_eq!(sensors[1].consecutive_failures, 2);
+        assert!(sensors[1].last_reading_ts.is_none());
+        assert!(sensors[2].calibration_offset.is_some());
+        assert!(sensors[0].calibration_offset.is_none());
+
+        // A single failed read is retried rather than counted
+        if let Some(SensorSource::Mock(sensor)) = handler.sensors.get_mut("temp_02") {
+            sensor.inner_mut().inner_mut().set_offline(false);
+            sensor.inner_mut().inner_mut().fail_next_read();
+        }
+        let message = handler.create_command(Command::GetReading { sensor_id: "temp_02".parse().unwrap() });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Reading { .. })));
+        assert_eq!(handler.sensor_status()[1].consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_sensor_lifecycle() {
+        let mut handler = TemperatureProtocolHandler::new();
+
+        let message = handler.create_command(Command::GetReading { sensor_id: "temp_03".parse().unwrap() });
+        handler.process_command(message);
+        let message = handler.create_command(Command::SetThreshold {
+            sensor_id: "temp_03".parse().unwrap(),
+            min_temp: 10.0,
+            max_temp: 20.0,
+            hysteresis: 0.0,
+            consecutive: 1,
+        });
+        handler.process_command(message);
+        let message = handler.create_command(Command::GetReading { sensor_id: "temp_03".parse().unwrap() });
+        handler.process_command(message);
+        assert_eq!(handler.alerts.active_alerts().len(), 1);
+
+        // Paused sensors are not read
+        let message = handler.create_command(Command::SetSensorState {
+            sensor_id: "temp_03".parse().unwrap(),
+            state: SensorState::Paused,
+        });
+        let response = handler.process_command(message);
+        assert_eq!(
+            response.payload,
+            MessagePayload::Response(Response::SensorStateChanged {
+                sensor_id: "temp_03".parse().unwrap(),
+                previous: SensorState::Active,
+                state: SensorState::Paused,
+            })
+        );
+        let message = handler.create_command(Command::GetReading { sensor_id: "temp_03".parse().unwrap() });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 409, .. })));
+        assert_eq!(handler.sensor_status()[2].state, SensorState::Paused);
+
+        // Decommissioning resolves alerts and hides the sensor from live stats
+        let message = handler.create_command(Command::SetSensorState {
+            sensor_id: "temp_03".parse().unwrap(),
+            state: SensorState::Decommissioned,
+        });
+        handler.process_command(message);
+        assert!(handler.alerts.active_alerts().is_empty());
+        assert_eq!(handler.sensor_status().len(), 2);
+        assert_eq!(handler.health_report().len(), 2);
+        let message = handler.create_command(Command::GetStatus);
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Status { readings_count: 0, .. })));
+
+        // History is retained
+        let message = handler.create_command(Command::GetHistory { sensor_id: "temp_03".parse().unwrap(), last_n: 10 });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::History { readings, .. }) if readings.len() == 2));
+
+        // There is no way back
+        let message = handler.create_command(Command::SetSensorState {
+            sensor_id: "temp_03".parse().unwrap(),
+            state: SensorState::Active,
+        });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 409, .. })));
+        let message = handler.create_command(Command::SetThreshold {
+            sensor_id: "temp_03".parse().unwrap(),
+            min_temp: 10.0,
+            max_temp: 20.0,
+            hysteresis: 0.0,
+            consecutive: 1,
+        });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 409, .. })));
+    }
+
+    #[test]
+    fn test_annotations() {
+        let mut handler = TemperatureProtocolHandler::new();
+        let store = &handler.stores["temp_01"];
+        for minute in 0..10u64 {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(21.0), minute * 60));
+        }
+
+        let message = handler.create_command(Command::Annotate {
+            sensor_id: "temp_01".parse().unwrap(),
+            start: 120,
+            end: 300,
+            text: "Window open for maintenance".to_string(),
+            author: Some("ops".to_string()),
+        });
+        let annotation = match handler.process_command(message).payload {
+            MessagePayload::Response(Response::Annotated { annotation, .. }) => annotation,
+            other => panic!("Expected annotated response, got {:?}", other),
+        };
+
+        let message = handler.create_command(Command::GetHistory { sensor_id: "temp_01".parse().unwrap(), last_n: 5 });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::History { annotations, .. }) if annotations.is_empty()));
+
+        let message = handler.create_command(Command::GetHistory { sensor_id: "temp_01".parse().unwrap(), last_n: 10 });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::History { annotations, .. }) if annotations == vec![annotation.clone()]));
+
+        let message = handler.create_command(Command::GetRange { sensor_id: "temp_01".parse().unwrap(), start: 0, end: 600 });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Range { annotations, .. }) if annotations.len() == 1));
+
+        let message = handler.create_command(Command::Annotate {
+            sensor_id: "temp_01".parse().unwrap(),
+            start: 300,
+            end: 120,
+            text: "Backwards".to_string(),
+            author: None,
+        });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 400, .. })));
+
+        let message = handler.create_command(Command::RemoveAnnotation { sensor_id: "temp_01".parse().unwrap(), annotation_id: annotation.id });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::AnnotationRemoved { .. })));
+        let message = handler.create_command(Command::RemoveAnnotation { sensor_id: "temp_01".parse().unwrap(), annotation_id: annotation.id });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 404, .. })));
+    }
+
+    #[test]
+    fn test_stats_epochs() {
+        let mut handler = TemperatureProtocolHandler::new();
+        let store = &handler.stores["temp_01"];
+        store.add_reading(TemperatureReading::with_timestamp(Temperature::new(21.0), 60));
+
+        let message = handler.create_command(Command::StartEpoch { sensor_id: "temp_01".parse().unwrap() });
+        let epoch = match handler.process_command(message).payload {
+            MessagePayload::Response(Response::EpochStarted { epoch, .. }) => epoch,
+            other => panic!("Expected epoch started response, got {:?}", other),
+        };
+        assert_eq!(epoch.id, 1);
+
+        handler.stores["temp_01"].add_reading(TemperatureReading::with_timestamp(Temperature::new(4.0), 120));
+        let message = handler.create_command(Command::GetStats { sensor_id: "temp_01".parse().unwrap() });
+        let response = handler.process_command(message);
+        assert!(matches!(
+            response.payload,
+            MessagePayload::Response(Response::Stats { stats, epoch: e, .. }) if stats.count == 1 && e == epoch
+        ));
+        assert_eq!(handler.stores["temp_01"].len(), 2);
+
+        let message = handler.create_command(Command::StartEpoch { sensor_id: "nope".parse().unwrap() });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 404, .. })));
+    }
+
+    #[test]
+    fn test_transforms() {
+        let mut handler = TemperatureProtocolHandler::new();
+        let transforms = vec![
+            TransformConfig::Filter { min: -40.0, max: 125.0 },
+            TransformConfig::Offset { celsius: 0.5 },
+        ];
+
+        let message = handler.create_command(Command::SetTransforms {
+            sensor_id: "temp_01".parse().unwrap(),
+            transforms: transforms.clone(),
+        });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::TransformsSet { .. })));
+
+        let message = handler.create_command(Command::GetReading { sensor_id: "temp_01".parse().unwrap() });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Reading { temperature, .. }) if temperature == 24.0));
+
+        // A disconnected sensor's reading never reaches the store
+        if let Some(SensorSource::Mock(sensor)) = handler.sensors.get_mut("temp_01") {
+            sensor.inner_mut().inner_mut().set_temperature(-127.0);
+        }
+        let message = handler.create_command(Command::GetReading { sensor_id: "temp_01".parse().unwrap() });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 422, .. })));
+        assert_eq!(handler.stores["temp_01"].reading_count(), 1);
+        assert_eq!(handler.sensor_status()[0].transforms, transforms);
+
+        let message = handler.create_command(Command::SetTransforms {
+            sensor_id: "temp_01".parse().unwrap(),
+            transforms: vec![TransformConfig::Smooth { alpha: 2.0 }],
+        });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 400, .. })));
+        assert_eq!(handler.sensor_status()[0].transforms, transforms);
+    }
+
+    #[test]
+    fn test_injected_clock() {
+        let clock = temp_core::clock::ManualClock::new(1_700_000_000);
+        let mut handler = TemperatureProtocolHandler::new().with_clock(clock.clone());
+
+        let message = handler.create_command(Command::GetReading { sensor_id: "temp_01".parse().unwrap() });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Reading { timestamp: 1_700_000_000, .. })));
+
+        // Three months of uptime, reported to the second
+        clock.advance(90 * DAY_SECONDS + 42);
+        let message = handler.create_command(Command::GetStatus);
+        let response = handler.process_command(message);
+        assert!(matches!(
+            response.payload,
+            MessagePayload::Response(Response::Status { uptime_seconds, .. }) if uptime_seconds == 90 * DAY_SECONDS + 42
+        ));
+
+        // A clock stepped back before the start does not wrap around
+        clock.set(1_600_000_000);
+        assert_eq!(handler.uptime_seconds(), 0);
+    }
+
+    #[test]
+    fn test_calibration_import_export() {
+        let mut source = TemperatureProtocolHandler::new();
+        for (sensor_id, actual_temp) in [("temp_01", 23.0), ("temp_02", 22.0)] {
+            let message = source.create_command(Command::Calibrate { sensor_id: sensor_id.parse().unwrap(), actual_temp });
+            source.process_command(message);
+        }
+        let message = source.create_command(Command::ExportCalibrations);
+        let MessagePayload::Response(Response::Calibrations { mut export }) = source.process_command(message).payload else {
+            panic!("Expected calibrations");
+        };
+        assert_eq!(export.entries.len(), 2);
+        assert_eq!(export.entries[0].sensor_id, "temp_01");
+
+        let mut target = TemperatureProtocolHandler::new();
+        target.calibrations.insert("temp_01".parse().unwrap(), 9.0);
+        export.entries.push(calibration::CalibrationEntry { sensor_id: "temp_99".parse().unwrap(), offset: 0.5 });
+
+        // Dry run reports the overwrite and the unknown sensor
+        let message = target.create_command(Command::ImportCalibrations { export: export.clone(), dry_run: true });
+        let MessagePayload::Response(Response::CalibrationsImported { report }) = target.process_command(message).payload else {
+            panic!("Expected import report");
+        };
+        assert_eq!(report.conflicts.len(), 2);
+        assert!(report.is_blocked());
+        assert!(matches!(report.conflicts[0], CalibrationConflict::Overwrites { current: 9.0, .. }));
+
+        // A blocked import applies nothing
+        let message = target.create_command(Command::ImportCalibrations { export: export.clone(), dry_run: false });
+        let MessagePayload::Response(Response::CalibrationsImported { report }) = target.process_command(message).payload else {
+            panic!("Expected import report");
+        };
+        assert_eq!(report.applied, 0);
+        assert_eq!(target.calibrations["temp_01"], 9.0);
+
+        export.entries.pop();
+        let report = target.import_calibrations(&export, false);
+        assert!(!report.is_blocked());
+        assert_eq!(report.applied, 2);
+        assert_eq!(target.calibrations["temp_01"], export.entries[0].offset);
+        assert_eq!(target.export_calibrations().entries, export.entries);
+    }
+}
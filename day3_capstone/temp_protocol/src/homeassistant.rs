@@ -0,0 +1,166 @@
+//! Home Assistant MQTT discovery support.
+//!
+//! Home Assistant picks up entities from retained config messages published to
+//! `<discovery_prefix>/sensor/<node_id>/<object_id>/config`. This module builds
+//! those messages (and the matching state messages) so any MQTT publisher can
+//! push them without hand-written YAML on the Home Assistant side.
+
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+pub const DEFAULT_STATE_PREFIX: &str = "temp_monitor";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DiscoveryDevice {
+    pub identifiers: Vec<String>,
+    pub name: String,
+    pub manufacturer: String,
+    pub model: String,
+    /// Where the node answers the protocol, as `["tcp", "192.0.2.2:7878"]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub connections: Vec<(String, String)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DiscoveryConfig {
+    pub name: String,
+    pub unique_id: String,
+    pub device_class: String,
+    pub state_class: String,
+    pub unit_of_measurement: String,
+    pub state_topic: String,
+    pub value_template: String,
+    pub device: DiscoveryDevice,
+}
+
+/// A message ready to be handed to an MQTT client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttMessage {
+    pub topic: String,
+    pub payload: String,
+    pub retain: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct HomeAssistantDiscovery {
+    discovery_prefix: String,
+    state_prefix: String,
+    node_id: String,
+    connections: Vec<(String, String)>,
+}
+
+impl HomeAssistantDiscovery {
+    pub fn new(node_id: String) -> Self {
+        Self {
+            discovery_prefix: DEFAULT_DISCOVERY_PREFIX.to_string(),
+            state_prefix: DEFAULT_STATE_PREFIX.to_string(),
+            node_id,
+            connections: Vec::new(),
+        }
+    }
+
+    pub fn with_discovery_prefix(mut self, prefix: String) -> Self {
+        self.discovery_prefix = prefix;
+        self
+    }
+
+    pub fn with_state_prefix(mut self, prefix: String) -> Self {
+        self.state_prefix = prefix;
+        self
+    }
+
+    /// Advertises an address the node answers on over `transport`.
+    pub fn with_endpoint(mut self, transport: &str, addr: SocketAddr) -> Self {
+        self.connections.push((transport.to_string(), addr.to_string()));
+        self
+    }
+
+    pub fn config_topic(&self, sensor_id: &str) -> String {
+        format!("{}/sensor/{}/{}/config", self.discovery_prefix, self.node_id, sensor_id)
+    }
+
+    pub fn state_topic(&self, sensor_id: &str) -> String {
+        format!("{}/{}/{}/state", self.state_prefix, self.node_id, sensor_id)
+    }
+
+    pub fn config_for(&self, sensor_id: &str) -> DiscoveryConfig {
+        DiscoveryConfig {
+            name: format!("Temperature {}", sensor_id),
+            unique_id: format!("{}_{}", self.node_id, sensor_id),
+            device_class: "temperature".to_string(),
+            state_class: "measurement".to_string(),
+            unit_of_measurement: "°C".to_string(),
+            state_topic: self.state_topic(sensor_id),
+            value_template: "{{ value_json.temperature }}".to_string(),
+            device: DiscoveryDevice {
+                identifiers: vec![self.node_id.clone()],
+                name: self.node_id.clone(),
+                manufacturer: "temp_monitor".to_string(),
+                model: "temp_protocol".to_string(),
+                connections: self.connections.clone(),
+            },
+        }
+    }
+
+    /// Retained discovery config message for one sensor.
+    pub fn discovery_message(&self, sensor_id: &str) -> Result<MqttMessage, serde_json::Error> {
+        Ok(MqttMessage {
+            topic: self.config_topic(sensor_id),
+            payload: serde_json::to_string(&self.config_for(sensor_id))?,
+            retain: true,
+        })
+    }
+
+    /// State message matching the `value_template` of the discovery config.
+    pub fn state_message(&self, sensor_id: &str, temperature: f32, timestamp: u64) -> MqttMessage {
+        MqttMessage {
+            topic: self.state_topic(sensor_id),
+            payload: serde_json::json!({
+                "temperature": temperature,
+                "timestamp": timestamp,
+            })
+            .to_string(),
+            retain: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovery_message_layout() {
+        let discovery = HomeAssistantDiscovery::new("lab".to_string());
+        let message = discovery.discovery_message("temp_01").unwrap();
+
+        assert_eq!(message.topic, "homeassistant/sensor/lab/temp_01/config");
+        assert!(message.retain);
+
+        let config: DiscoveryConfig = serde_json::from_str(&message.payload).unwrap();
+        assert_eq!(config.device_class, "temperature");
+        assert_eq!(config.unit_of_measurement, "°C");
+        assert_eq!(config.unique_id, "lab_temp_01");
+        assert_eq!(config.state_topic, "temp_monitor/lab/temp_01/state");
+        assert!(!message.payload.contains("connections"));
+
+        let advertised = discovery.with_endpoint("udp", "[fd00::2]:7879".parse().unwrap());
+        let config: DiscoveryConfig = serde_json::from_str(&advertised.discovery_message("temp_01").unwrap().payload).unwrap();
+        assert_eq!(config.device.connections, vec![("udp".to_string(), "[fd00::2]:7879".to_string())]);
+    }
+
+    #[test]
+    fn state_message_matches_template() {
+        let discovery = HomeAssistantDiscovery::new("lab".to_string())
+            .with_state_prefix("house".to_string());
+        let message = discovery.state_message("temp_01", 21.5, 1000);
+
+        assert_eq!(message.topic, "house/lab/temp_01/state");
+        assert!(!message.retain);
+
+        let value: serde_json::Value = serde_json::from_str(&message.payload).unwrap();
+        assert_eq!(value["temperature"], 21.5);
+    }
+}
@@ -0,0 +1,328 @@
+//! Localized error messages.
+//!
+//! Only the human-facing text is translated: error codes and
+//! [`ProtocolError`] variants stay the machine contract. Messages are looked
+//! up by key in [`MESSAGES`] and `{name}` placeholders are filled from the
+//! error. Text coming from elsewhere, such as a failed import's reason or a
+//! sensor state, is inserted as it is.
+
+#[cfg(feature = "std")]
+use alloc::string::String;
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use temp_alert::AlertError;
+
+#[cfg(feature = "std")]
+use crate::ProtocolError;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    English,
+    German,
+    French,
+}
+
+impl Language {
+    pub const ALL: [Language; 3] = [Language::English, Language::German, Language::French];
+
+    /// The ISO 639-1 code.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+            Language::French => "fr",
+        }
+    }
+
+    /// Picks the preferred supported language from an Accept-Language list
+    /// such as `"de-CH, fr;q=0.8, *;q=0.1"`; English if none is supported.
+    pub fn negotiate(preferences: &str) -> Self {
+        let mut best: Option<(f32, Language)> = None;
+        for entry in preferences.split(',') {
+            let mut parts = entry.split(';');
+            let range = parts.next().unwrap_or_default().trim();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let primary = range.split('-').next().unwrap_or_default();
+            let Some(language) = Self::ALL.into_iter().find(|l| l.tag().eq_ignore_ascii_case(primary)) else {
+                continue;
+            };
+            // Earlier entries win ties
+            if quality > 0.0 && best.is_none_or(|(q, _)| quality > q) {
+                best = Some((quality, language));
+            }
+        }
+        best.map_or(Language::English, |(_, language)| language)
+    }
+
+    #[cfg(feature = "std")]
+    fn index(&self) -> usize {
+        match self {
+            Language::English => 0,
+            Language::German => 1,
+            Language::French => 2,
+        }
+    }
+}
+
+/// Message templates by key, in the order of [`Language::ALL`].
+#[cfg(feature = "std")]
+const MESSAGES: &[(&str, [&str; 3])] = &[
+    ("sensor-not-found", [
+        "Sensor '{sensor}' not found",
+        "Sensor '{sensor}' nicht gefunden",
+        "Capteur '{sensor}' introuvable",
+    ]),
+    ("sensor-not-responding", [
+        "Sensor '{sensor}' is not responding",
+        "Sensor '{sensor}' antwortet nicht",
+        "Le capteur '{sensor}' ne répond pas",
+    ]),
+    ("invalid-threshold", [
+        "Invalid threshold min={min}, max={max}: {reason}",
+        "Ungültiger Grenzwert min={min}, max={max}: {reason}",
+        "Seuil invalide min={min}, max={max} : {reason}",
+    ]),
+    ("invalid-setpoint", [
+        "Invalid setpoint {setpoint}: {reason}",
+        "Ungültiger Sollwert {setpoint}: {reason}",
+        "Consigne invalide {setpoint} : {reason}",
+    ]),
+    ("invalid-temperature", [
+        "Invalid temperature {celsius}: {reason}",
+        "Ungültige Temperatur {celsius}: {reason}",
+        "Température invalide {celsius} : {reason}",
+    ]),
+    ("unknown-time-zone", [
+        "Unknown time zone '{zone}'",
+        "Unbekannte Zeitzone '{zone}'",
+        "Fuseau horaire inconnu '{zone}'",
+    ]),
+    ("calibration-failed", [
+        "Calibration failed for '{sensor}': {reason}",
+        "Kalibrierung von '{sensor}' fehlgeschlagen: {reason}",
+        "Échec de l'étalonnage de '{sensor}' : {reason}",
+    ]),
+    ("sensor-unavailable", [
+        "Sensor '{sensor}' is {state}",
+        "Sensor '{sensor}' ist {state}",
+        "Le capteur '{sensor}' est {state}",
+    ]),
+    ("invalid-state-transition", [
+        "Sensor '{sensor}' cannot go from {from} to {to}",
+        "Sensor '{sensor}' kann nicht von {from} zu {to} wechseln",
+        "Le capteur '{sensor}' ne peut pas passer de {from} à {to}",
+    ]),
+    ("invalid-range", [
+        "Invalid range: start {start} must be before end {end}",
+        "Ungültiger Bereich: Beginn {start} muss vor Ende {end} liegen",
+        "Plage invalide : le début {start} doit précéder la fin {end}",
+    ]),
+    ("invalid-forecast-horizon", [
+        "Forecast horizon must be positive",
+        "Der Prognosehorizont muss positiv sein",
+        "L'horizon de prévision doit être positif",
+    ]),
+    ("insufficient-history", [
+        "Not enough recent history for '{sensor}' to forecast",
+        "Zu wenig aktuelle Daten von '{sensor}' für eine Prognose",
+        "Historique récent insuffisant pour prévoir '{sensor}'",
+    ]),
+    ("unknown-annotation", [
+        "Annotation {id} not found for '{sensor}'",
+        "Anmerkung {id} für '{sensor}' nicht gefunden",
+        "Annotation {id} introuvable pour '{sensor}'",
+    ]),
+    ("invalid-transform", [
+        "Invalid transform: {reason}",
+        "Ungültige Transformation: {reason}",
+        "Transformation invalide : {reason}",
+    ]),
+    ("invalid-tags", [
+        "Invalid tags: {reason}",
+        "Ungültige Tags: {reason}",
+        "Étiquettes invalides : {reason}",
+    ]),
+    ("invalid-timestamp", [
+        "Timestamp {timestamp} is in the future",
+        "Zeitstempel {timestamp} liegt in der Zukunft",
+        "L'horodatage {timestamp} est dans le futur",
+    ]),
+    ("rate-limited", [
+        "Too many readings from '{source}', slow down",
+        "Zu viele Messwerte von '{source}', bitte langsamer senden",
+        "Trop de mesures de '{source}', ralentissez",
+    ]),
+    ("reading-filtered", [
+        "Reading from '{sensor}' was dropped by its transforms",
+        "Messwert von '{sensor}' wurde von seinen Transformationen verworfen",
+        "La mesure de '{sensor}' a été écartée par ses transformations",
+    ]),
+    ("sensor-info-unavailable", [
+        "No datasheet information for sensor '{sensor}'",
+        "Keine Datenblattangaben für Sensor '{sensor}'",
+        "Aucune donnée constructeur pour le capteur '{sensor}'",
+    ]),
+    ("authentication-required", [
+        "Authenticate first",
+        "Bitte zuerst anmelden",
+        "Authentifiez-vous d'abord",
+    ]),
+    ("authentication-failed", [
+        "Authentication failed: {reason}",
+        "Anmeldung fehlgeschlagen: {reason}",
+        "Échec de l'authentification : {reason}",
+    ]),
+    ("access-denied", [
+        "Access to {resource} denied",
+        "Zugriff auf {resource} verweigert",
+        "Accès à {resource} refusé",
+    ]),
+    ("command-disabled", [
+        "Command {command} is disabled",
+        "Befehl {command} ist deaktiviert",
+        "La commande {command} est désactivée",
+    ]),
+    ("protected-command", [
+        "Command {command} cannot be disabled",
+        "Befehl {command} kann nicht deaktiviert werden",
+        "La commande {command} ne peut pas être désactivée",
+    ]),
+    ("unexpected-response", [
+        "Cannot process response messages",
+        "Antwortnachrichten können nicht verarbeitet werden",
+        "Impossible de traiter des messages de réponse",
+    ]),
+    ("unknown-alert", [
+        "Alert {id} not found",
+        "Alarm {id} nicht gefunden",
+        "Alerte {id} introuvable",
+    ]),
+    ("unknown-silence", [
+        "Silence {id} not found",
+        "Stummschaltung {id} nicht gefunden",
+        "Mise en sourdine {id} introuvable",
+    ]),
+    ("invalid-silence", [
+        "Silence must end after it starts ({start} >= {end})",
+        "Stummschaltung muss nach ihrem Beginn enden ({start} >= {end})",
+        "La mise en sourdine doit finir après son début ({start} >= {end})",
+    ]),
+    ("version-mismatch", [
+        "Protocol version mismatch: expected {expected}, got {received}",
+        "Protokollversion passt nicht: erwartet {expected}, erhalten {received}",
+        "Version de protocole incompatible : {expected} attendue, {received} reçue",
+    ]),
+];
+
+#[cfg(feature = "std")]
+pub(crate) fn error_message(error: &ProtocolError, language: Language) -> String {
+    let (key, args): (&str, Vec<(&str, String)>) = match error {
+        ProtocolError::InvalidSensorId { sensor_id } => ("sensor-not-found", vec![("sensor", sensor_id.to_string())]),
+        ProtocolError::SensorNotResponding { sensor_id } => ("sensor-not-responding", vec![("sensor", sensor_id.to_string())]),
+        ProtocolError::InvalidThreshold { min, max, reason } => (
+            "invalid-threshold",
+            vec![("min", min.to_string()), ("max", max.to_string()), ("reason", reason.clone())],
+        ),
+        ProtocolError::InvalidSetpoint { setpoint, reason } => {
+            ("invalid-setpoint", vec![("setpoint", setpoint.to_string()), ("reason", reason.clone())])
+        }
+        ProtocolError::InvalidTemperature { celsius, reason } => {
+            ("invalid-temperature", vec![("celsius", celsius.to_string()), ("reason", reason.clone())])
+        }
+        ProtocolError::InvalidTimeZone { time_zone } => ("unknown-time-zone", vec![("zone", time_zone.clone())]),
+        ProtocolError::CalibrationFailed { sensor_id, reason } => {
+            ("calibration-failed", vec![("sensor", sensor_id.to_string()), ("reason", reason.clone())])
+        }
+        ProtocolError::SensorUnavailable { sensor_id, state } => {
+            ("sensor-unavailable", vec![("sensor", sensor_id.to_string()), ("state", state.to_string())])
+        }
+        ProtocolError::InvalidStateTransition { sensor_id, from, to } => (
+            "invalid-state-transition",
+            vec![("sensor", sensor_id.to_string()), ("from", from.to_string()), ("to", to.to_string())],
+        ),
+        ProtocolError::InvalidRange { start, end } => {
+            ("invalid-range", vec![("start", start.to_string()), ("end", end.to_string())])
+        }
+        ProtocolError::InvalidForecastHorizon => ("invalid-forecast-horizon", Vec::new()),
+        ProtocolError::InsufficientHistory { sensor_id } => ("insufficient-history", vec![("sensor", sensor_id.to_string())]),
+        ProtocolError::UnknownAnnotation { sensor_id, annotation_id } => {
+            ("unknown-annotation", vec![("sensor", sensor_id.to_string()), ("id", annotation_id.to_string())])
+        }
+        ProtocolError::InvalidTransform { reason } => ("invalid-transform", vec![("reason", reason.clone())]),
+        ProtocolError::InvalidTags { reason } => ("invalid-tags", vec![("reason", reason.clone())]),
+        ProtocolError::InvalidTimestamp { timestamp } => ("invalid-timestamp", vec![("timestamp", timestamp.to_string())]),
+        ProtocolError::RateLimited { source } => ("rate-limited", vec![("source", source.clone())]),
+        ProtocolError::ReadingFiltered { sensor_id } => ("reading-filtered", vec![("sensor", sensor_id.to_string())]),
+        ProtocolError::SensorInfoUnavailable { sensor_id } => {
+            ("sensor-info-unavailable", vec![("sensor", sensor_id.to_string())])
+        }
+        ProtocolError::Unauthenticated => ("authentication-required", Vec::new()),
+        ProtocolError::AuthenticationFailed { reason } => ("authentication-failed", vec![("reason", reason.clone())]),
+        ProtocolError::AccessDenied { resource } => ("access-denied", vec![("resource", resource.clone())]),
+        ProtocolError::CommandDisabled { command } => ("command-disabled", vec![("command", command.clone())]),
+        ProtocolError::ProtectedCommand { command } => ("protected-command", vec![("command", command.clone())]),
+        ProtocolError::UnexpectedResponse => ("unexpected-response", Vec::new()),
+        ProtocolError::Alert(AlertError::UnknownAlert(id)) => ("unknown-alert", vec![("id", id.to_string())]),
+        ProtocolError::Alert(AlertError::UnknownSilence(id)) => ("unknown-silence", vec![("id", id.to_string())]),
+        ProtocolError::Alert(AlertError::InvalidSilence { start, end }) => {
+            ("invalid-silence", vec![("start", start.to_string()), ("end", end.to_string())])
+        }
+        // Details are composed by the caller, untranslated
+        ProtocolError::SystemError { details, .. } => return details.clone(),
+        ProtocolError::ProtocolVersionMismatch { expected, received } => (
+            "version-mismatch",
+            vec![("expected", expected.to_string()), ("received", received.to_string())],
+        ),
+    };
+
+    let template = MESSAGES
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map_or(key, |(_, templates)| templates[language.index()]);
+    args.iter()
+        .fold(template.to_string(), |message, (name, value)| message.replace(&format!("{{{}}}", name), value))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{Command, MessagePayload, Response, TemperatureProtocolHandler};
+
+    #[test]
+    fn negotiates_supported_languages() {
+        assert_eq!(Language::negotiate("de-CH, fr;q=0.8"), Language::German);
+        assert_eq!(Language::negotiate("it, fr;q=0.5, en;q=0.4"), Language::French);
+        assert_eq!(Language::negotiate("fr;q=0, de;q=0.1"), Language::German);
+        assert_eq!(Language::negotiate("ja"), Language::English);
+        assert_eq!(Language::negotiate(""), Language::English);
+    }
+
+    #[test]
+    fn errors_follow_the_session_language() {
+        let mut handler = TemperatureProtocolHandler::new();
+        let message = handler.create_command(Command::SetLanguage { preferences: "de".to_string() });
+        let response = handler.process_command_in(message, Language::English);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::LanguageSet { language: Language::German })));
+
+        let message = handler.create_command(Command::GetStats { sensor_id: "nope".parse().unwrap() });
+        let MessagePayload::Response(Response::Error { code, message }) = handler.process_command_in(message, Language::German).payload else {
+            panic!("Expected error");
+        };
+        assert_eq!((code, message.as_str()), (404, "Sensor 'nope' nicht gefunden"));
+
+        // Another session still gets English
+        let message = handler.create_command(Command::GetStats { sensor_id: "nope".parse().unwrap() });
+        let MessagePayload::Response(Response::Error { message, .. }) = handler.process_command(message).payload else {
+            panic!("Expected error");
+        };
+        assert_eq!(message, "Sensor 'nope' not found");
+
+        let error = ProtocolError::InvalidRange { start: 5, end: 1 };
+        assert!(matches!(error.to_localized_response(Language::French), Response::Error { code: 400, message } if message.contains("précéder")));
+    }
+}
@@ -0,0 +1,147 @@
+//! Message id generation.
+//!
+//! Ids only have to be unique per connection for responses to find their
+//! request, but a counter restarting at 1 after a reconnect makes logs from
+//! different sessions collide. [`UlidIds`] avoids that and encodes the
+//! creation time, which [`ulid_timestamp_ms`] recovers for tracing.
+
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+use alloc::string::String;
+
+#[cfg(feature = "std")]
+use rand::rngs::StdRng;
+#[cfg(feature = "std")]
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "std")]
+use temp_core::clock::{Clock, SystemClock};
+
+pub type MessageId = u128;
+
+pub trait IdGenerator: Send {
+    fn next_id(&mut self) -> MessageId;
+}
+
+/// 1, 2, 3, ... A `u64` counter does not wrap in any realistic lifetime.
+#[derive(Debug, Clone)]
+pub struct MonotonicIds {
+    next: u64,
+}
+
+impl MonotonicIds {
+    pub fn new() -> Self {
+        Self { next: 1 }
+    }
+}
+
+impl Default for MonotonicIds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdGenerator for MonotonicIds {
+    fn next_id(&mut self) -> MessageId {
+        let id = self.next;
+        self.next += 1;
+        id as MessageId
+    }
+}
+
+const ULID_RANDOM_BITS: u32 = 80;
+#[cfg(feature = "std")]
+const ULID_RANDOM_MASK: u128 = (1 << ULID_RANDOM_BITS) - 1;
+
+/// ULIDs: 48 bits of Unix milliseconds followed by 80 random bits. Ids made
+/// within the same millisecond increment the random part, so they stay
+/// strictly increasing.
+#[cfg(feature = "std")]
+pub struct UlidIds {
+    clock: Box<dyn Clock>,
+    rng: StdRng,
+    last: MessageId,
+}
+
+#[cfg(feature = "std")]
+impl UlidIds {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock, rand::random())
+    }
+
+    /// Deterministic ids for tests.
+    pub fn with_clock(clock: impl Clock + 'static, seed: u64) -> Self {
+        Self {
+            clock: Box::new(clock),
+            rng: StdRng::seed_from_u64(seed),
+            last: 0,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for UlidIds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl IdGenerator for UlidIds {
+    fn next_id(&mut self) -> MessageId {
+        let millis = (self.clock.now_millis() as u128) & ((1 << 48) - 1);
+        let random = self.rng.gen::<u128>() & ULID_RANDOM_MASK;
+        let id = (millis << ULID_RANDOM_BITS) | random;
+        // Same millisecond (or a clock stepped back): keep increasing
+        self.last = if id > self.last { id } else { self.last + 1 };
+        self.last
+    }
+}
+
+/// Unix milliseconds at which a ULID was made.
+pub fn ulid_timestamp_ms(id: MessageId) -> u64 {
+    (id >> ULID_RANDOM_BITS) as u64
+}
+
+/// The canonical 26-character Crockford base32 form of a ULID.
+pub fn format_ulid(id: MessageId) -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+    (0..26)
+        .rev()
+        .map(|i| ALPHABET[((id >> (i * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use temp_core::clock::ManualClock;
+
+    #[test]
+    fn ulids_increase_and_carry_their_timestamp() {
+        let clock = ManualClock::new(1_700_000_000);
+        let mut ids = UlidIds::with_clock(clock.clone(), 7);
+
+        let first = ids.next_id();
+        let second = ids.next_id();
+        assert!(second > first);
+        assert_eq!(ulid_timestamp_ms(first), 1_700_000_000_000);
+
+        clock.advance(1);
+        let later = ids.next_id();
+        assert_eq!(ulid_timestamp_ms(later), 1_700_000_001_000);
+        assert_eq!(format_ulid(later).len(), 26);
+        assert!(format_ulid(later) > format_ulid(second));
+
+        assert_eq!(format_ulid(0), "00000000000000000000000000");
+
+        // 128-bit ids survive both wire formats
+        let message = crate::ProtocolMessage { version: 1, id: later, payload: crate::MessagePayload::Command(crate::Command::GetStatus), headers: crate::Headers::new() };
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(serde_json::from_str::<crate::ProtocolMessage>(&json).unwrap().id, later);
+        let binary = postcard::to_allocvec(&message).unwrap();
+        assert_eq!(postcard::from_bytes::<crate::ProtocolMessage>(&binary).unwrap().id, later);
+
+        let mut counter = MonotonicIds::new();
+        assert_eq!((counter.next_id(), counter.next_id()), (1, 2));
+    }
+}
@@ -0,0 +1,182 @@
+//! Readings submitted by external systems.
+//!
+//! Hubs of other brands and scripts push readings with `SubmitReading`, or
+//! as JSON or CSV over HTTP (see `temp_async::http`), into sensors
+//! registered with the handler. Each submission names its source, such as
+//! the hub's hostname, and every source is rate limited on its own, so one
+//! misbehaving script cannot flood the stores.
+//!
+//! Readings are validated like sampled ones: a temperature that is not
+//! possible is refused, and so is a timestamp further in the future than
+//! [`MAX_CLOCK_SKEW_SECONDS`]. Senders retry after timeouts, so a reading
+//! with the timestamp of one of the sensor's last [`DEDUP_WINDOW`] readings
+//! is acknowledged as a duplicate and not stored twice.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use temp_core::units::deserialize_celsius;
+use temp_core::SensorId;
+
+/// How far ahead of the handler's clock a timestamp may be.
+pub const MAX_CLOCK_SKEW_SECONDS: u64 = 300;
+/// Newest readings of a sensor a submission is checked against for duplicates.
+pub const DEDUP_WINDOW: usize = 64;
+/// Sources whose rate limit is tracked before idle ones are forgotten.
+#[cfg(feature = "std")]
+const MAX_TRACKED_SOURCES: usize = 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExternalReading {
+    pub sensor_id: SensorId,
+    /// Celsius, or a string with its unit such as `"74F"`.
+    #[serde(deserialize_with = "deserialize_celsius")]
+    pub temperature: f32,
+    /// Unix seconds; when the handler receives it if missing.
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubmitOutcome {
+    Accepted,
+    /// Already stored, e.g. sent again after a timeout.
+    Duplicate,
+}
+
+/// Readings a source may submit: `burst` at once, refilled at `per_minute`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: u64,
+}
+
+/// A token bucket per source.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub(crate) struct SourceLimits {
+    limit: Option<RateLimit>,
+    buckets: HashMap<String, Bucket>,
+}
+
+#[cfg(feature = "std")]
+impl SourceLimits {
+    pub(crate) fn set_limit(&mut self, limit: Option<RateLimit>) {
+        self.limit = limit;
+        self.buckets.clear();
+    }
+
+    /// Takes a token from `source`'s bucket at `now`; false if it is empty.
+    pub(crate) fn admit(&mut self, source: &str, now: u64) -> bool {
+        let Some(limit) = self.limit else {
+            return true;
+        };
+        let burst = f64::from(limit.burst);
+        let refill = |bucket: &Bucket| {
+            let elapsed = now.saturating_sub(bucket.updated) as f64;
+            (bucket.tokens + elapsed * f64::from(limit.per_minute) / 60.0).min(burst)
+        };
+        if self.buckets.len() >= MAX_TRACKED_SOURCES && !self.buckets.contains_key(source) {
+            // A full bucket is the same as a new one
+            self.buckets.retain(|_, bucket| refill(bucket) < burst);
+        }
+
+        let bucket = self.buckets.entry(source.to_string()).or_insert(Bucket { tokens: burst, updated: now });
+        bucket.tokens = refill(bucket);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+/// Parses CSV with a header naming the `sensor_id` and `temperature`
+/// columns, and optionally `timestamp`, in any order. Temperatures take a
+/// unit as in JSON; an empty timestamp means now. Fields are not quoted.
+pub fn parse_csv(text: &str) -> Result<Vec<ExternalReading>, String> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let Some((_, header)) = lines.next() else {
+        return Ok(Vec::new());
+    };
+    let columns: Vec<&str> = header.split(',').map(str::trim).collect();
+    let column = |name: &str| columns.iter().position(|&column| column.eq_ignore_ascii_case(name));
+    let (Some(sensor_column), Some(temperature_column)) = (column("sensor_id"), column("temperature")) else {
+        return Err("CSV header must name the sensor_id and temperature columns".to_string());
+    };
+    let timestamp_column = column("timestamp");
+
+    lines
+        .map(|(index, line)| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() != columns.len() {
+                return Err(format!("Line {} has {} fields, expected {}", index + 1, fields.len(), columns.len()));
+            }
+            let temperature = fields[temperature_column];
+            let temperature = match temperature.parse::<f32>() {
+                Ok(celsius) => celsius,
+                Err(_) => temperature
+                    .parse::<temp_core::Temperature>()
+                    .map(|temperature| temperature.celsius)
+                    .map_err(|e| format!("Line {}: {}", index + 1, e))?,
+            };
+            let timestamp = match timestamp_column.map(|column| fields[column]).filter(|field| !field.is_empty()) {
+                Some(field) => {
+                    Some(field.parse().map_err(|_| format!("Line {}: '{}' is not a Unix timestamp", index + 1, field))?)
+                }
+                None => None,
+            };
+            let sensor_id = SensorId::new(fields[sensor_column]).map_err(|e| format!("Line {}: {}", index + 1, e))?;
+            Ok(ExternalReading { sensor_id, temperature, timestamp })
+        })
+        .collect()
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limits_each_source() {
+        let mut limits = SourceLimits::default();
+        assert!((0..100).all(|_| limits.admit("hub", 0)));
+
+        limits.set_limit(Some(RateLimit { per_minute: 6, burst: 2 }));
+        assert!(limits.admit("hub", 0) && limits.admit("hub", 0));
+        assert!(!limits.admit("hub", 0));
+        assert!(limits.admit("script", 0));
+        // One token every ten seconds
+        assert!(!limits.admit("hub", 9));
+        assert!(limits.admit("hub", 10));
+    }
+
+    #[test]
+    fn parses_csv_with_any_column_order() {
+        let csv = "timestamp,temperature,sensor_id\n1700000000,21.5,hall\n\n,70F,porch\n";
+        let readings = parse_csv(csv).unwrap();
+        assert_eq!(readings[0], ExternalReading { sensor_id: "hall".parse().unwrap(), temperature: 21.5, timestamp: Some(1_700_000_000) });
+        assert_eq!(readings[1].timestamp, None);
+        assert!((readings[1].temperature - 21.11).abs() < 0.01);
+
+        assert!(parse_csv("sensor,celsius\nhall,21").is_err());
+        assert_eq!(parse_csv("sensor_id,temperature\nhall").unwrap_err(), "Line 2 has 1 fields, expected 2");
+        assert!(parse_csv("sensor_id,temperature,timestamp\nhall,21,yesterday").is_err());
+        assert_eq!(parse_csv("sensor_id,temperature\nhall way,21").unwrap_err(), "Line 2: Sensor id may only contain letters, digits, '_', '-' and '.', not ' '");
+        assert_eq!(parse_csv("").unwrap(), Vec::new());
+
+        let json: ExternalReading = serde_json::from_str(r#"{"sensor_id": "hall", "temperature": "295.15K"}"#).unwrap();
+        assert!((json.temperature - 22.0).abs() < 1e-4);
+    }
+}
@@ -1,497 +1,538 @@
+//! Messages between temperature clients and servers, and the handler
+//! answering them.
+//!
+//! [`Command`], [`Response`] and everything they carry build without `std`,
+//! on `alloc`, so firmware can exchange the same messages: build with
+//! `default-features = false`. The [`TemperatureProtocolHandler`], the
+//! transports' helpers and the auth providers need the default `std` feature.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use temp_core::{TemperatureSensor, mock::MockTemperatureSensor};
-use temp_store::{TemperatureStore, TemperatureStats, TemperatureReading};
+use temp_core::{MeasurementRange, SensorId, SensorInfo};
+use temp_alert::{Alert, AlertCounts, AlertQuery, AlertRecord, EscalationPolicy, Silence};
+use temp_core::threshold::ThresholdConfig;
+use temp_core::transform::TransformConfig;
+use temp_store::{Annotation, BackupFormat, CompactionReport, Epoch, TemperatureStats, TemperatureReading, Resolution, RollupPoint, StorageInfo, DegreeDayReport, Forecast, PeerComparison};
+use ingest::{ExternalReading, SubmitOutcome};
+
+pub mod auth;
+pub mod calibration;
+pub mod flags;
+pub mod i18n;
+pub mod ids;
+pub mod ingest;
+pub mod tags;
+
+#[cfg(feature = "std")]
+pub mod acl;
+#[cfg(feature = "std")]
+pub mod buffers;
+#[cfg(feature = "std")]
+pub mod homeassistant;
+#[cfg(feature = "std")]
+pub mod middleware;
+#[cfg(feature = "std")]
+pub mod pairing;
+#[cfg(feature = "std")]
+pub mod shadow;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+mod handler;
+
+pub use auth::Identity;
+#[cfg(feature = "std")]
+pub use buffers::MessageBuffers;
+use calibration::{CalibrationExport, ImportReport};
+#[cfg(feature = "std")]
+pub use handler::{ProtocolError, SessionState, TemperatureProtocolHandler, STORE_CAPACITY_PER_SENSOR};
+pub use i18n::Language;
+use ids::MessageId;
+use flags::FlagChange;
+use tags::Tags;
+
+fn one() -> u32 {
+    1
+}
 
+/// New commands go at the end: the binary encoding numbers variants in
+/// order, so one inserted in between turns older clients' commands into
+/// others of the same version.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Command {
     GetStatus,
     GetReading {
-        sensor_id: String
+        sensor_id: SensorId
     },
     SetThreshold {
-        sensor_id: String,
+        sensor_id: SensorId,
         min_temp: f32,
         max_temp: f32,
+        #[serde(default)]
+        hysteresis: f32, // °C back inside the range before an alert clears
+        #[serde(default = "one")]
+        consecutive: u32, // Readings past a limit before an alert fires
     },
     GetHistory {
-        sensor_id: String,
+        sensor_id: SensorId,
         last_n: usize,
     },
     GetStats {
-        sensor_id: String,
+        sensor_id: SensorId,
     },
     Calibrate {
-        sensor_id: String,
+        sensor_id: SensorId,
         actual_temp: f32,
     },
+    GetRange {
+        sensor_id: SensorId,
+        start: u64,
+        end: u64,
+    },
+    GetStorageInfo,
+    /// Rewrites the persistent logs without data no tier keeps any more.
+    CompactStorage,
+    /// Every reading of the sensor's store, for backup tools.
+    ExportBackup {
+        sensor_id: SensorId,
+        format: BackupFormat,
+    },
+    RestoreBackup {
+        sensor_id: SensorId,
+        data: Vec<u8>, // As returned by ExportBackup
+    },
+    GetDegreeDays {
+        sensor_id: SensorId,
+        start: u64,
+        end: u64,
+        base_celsius: Option<f32>, // Defaults to 18 °C
+    },
+    GetForecast {
+        sensor_id: SensorId,
+        horizon: u64, // Seconds ahead
+    },
+    SetSetpoint {
+        sensor_id: SensorId,
+        setpoint: f32,
+    },
+    SetZone {
+        sensor_id: SensorId,
+        zone: String,
+    },
+    GetHealth,
+    ListAlerts {
+        include_resolved: bool,
+    },
+    AckAlert {
+        alert_id: u64,
+        by: String,
+    },
+    SilenceAlerts {
+        sensor_id: Option<SensorId>, // None silences every sensor
+        duration_seconds: u64,
+        reason: String,
+    },
+    RemoveSilence {
+        silence_id: u64,
+    },
+    SetEscalationPolicy {
+        policy: Option<EscalationPolicy>, // None disables escalation
+    },
+    QueryAlertHistory {
+        query: AlertQuery,
+        /// Only alerts of sensors with these tags; see [`tags`].
+        #[serde(default)]
+        tags: Tags,
+    },
+    ExportCalibrations,
+    ImportCalibrations {
+        export: CalibrationExport,
+        dry_run: bool, // Only report conflicts
+    },
+    SetSensorState {
+        sensor_id: SensorId,
+        state: SensorState,
+    },
+    SetTimeZone {
+        time_zone: String, // IANA name, e.g. "Europe/Zurich"
+    },
+    Annotate {
+        sensor_id: SensorId,
+        start: u64,
+        end: u64,
+        text: String,
+        author: Option<String>,
+    },
+    RemoveAnnotation {
+        sensor_id: SensorId,
+        annotation_id: u64,
+    },
+    /// Language of error messages, as an Accept-Language list, e.g. "de-CH, fr;q=0.8".
+    SetLanguage {
+        preferences: String,
+    },
+    /// Replaces the transforms applied to the sensor's readings before they
+    /// are stored; an empty list stores readings as they come.
+    SetTransforms {
+        sensor_id: SensorId,
+        transforms: Vec<TransformConfig>,
+    },
+    /// Turns a command, named like "RestoreBackup", on or off; see [`flags`].
+    SetCommandEnabled {
+        command: String,
+        enabled: bool,
+        #[serde(default)]
+        changed_by: Option<String>,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    GetCommandFlags,
+    /// Datasheet figures of the sensor, to tell real changes from noise.
+    GetSensorInfo {
+        sensor_id: SensorId,
+    },
+    /// Authenticates the session with a bearer token; see [`auth`].
+    Authenticate {
+        token: String,
+    },
+    /// Replaces the sensor's tags; an empty map removes them.
+    SetTags {
+        sensor_id: SensorId,
+        tags: Tags,
+    },
+    /// Status of the sensors with all of `tags`.
+    QuerySensors {
+        tags: Tags,
+    },
+    /// Sets the same threshold on every sensor that has all of `tags` now;
+    /// sensors tagged later keep theirs.
+    SetThresholdForTags {
+        tags: Tags,
+        min_temp: f32,
+        max_temp: f32,
+        #[serde(default)]
+        hysteresis: f32,
+        #[serde(default = "one")]
+        consecutive: u32,
+    },
+    /// Restarts the sensor's stats, e.g. at the start of a production batch;
+    /// its history is kept. See [`temp_store::epoch`].
+    StartEpoch {
+        sensor_id: SensorId,
+    },
+    /// A reading taken by an external system, such as another brand's hub;
+    /// see [`ingest`]. `source` names the system for its rate limit.
+    SubmitReading {
+        source: String,
+        reading: ExternalReading,
+    },
+}
+
+/// Lifecycle of a registered sensor.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SensorState {
+    #[default]
+    Active,
+    /// Temporarily not sampled, e.g. during maintenance.
+    Paused,
+    /// Retired for good: history is kept, but the sensor is left out of live
+    /// stats and alerting.
+    Decommissioned,
+}
+
+impl SensorState {
+    pub fn can_transition_to(self, next: SensorState) -> bool {
+        self != SensorState::Decommissioned || next == SensorState::Decommissioned
+    }
 }
 
+impl fmt::Display for SensorState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SensorState::Active => write!(f, "active"),
+            SensorState::Paused => write!(f, "paused"),
+            SensorState::Decommissioned => write!(f, "decommissioned"),
+        }
+    }
+}
+
+/// New responses go at the end, as with [`Command`].
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Response {
     Status {
-        active_sensors: Vec<String>,
+        sensors: Vec<SensorStatus>,
         uptime_seconds: u64,
         readings_count: usize,
     },
     Reading {
-        sensor_id: String,
+        sensor_id: SensorId,
         temperature: f32,
         timestamp: u64,
     },
     ThresholdSet {
-        sensor_id: String,
-        min_temp: f32,
-        max_temp: f32,
+        sensor_id: SensorId,
+        threshold: ThresholdConfig,
     },
     History {
-        sensor_id: String,
+        sensor_id: SensorId,
         readings: Vec<TemperatureReading>,
+        annotations: Vec<Annotation>,
     },
     Stats {
-        sensor_id: String,
+        sensor_id: SensorId,
         stats: TemperatureStats,
+        #[serde(default)]
+        epoch: Epoch, // What `stats` cover readings since
     },
     CalibrationComplete {
-        sensor_id: String,
+        sensor_id: SensorId,
         offset_adjustment: f32,
     },
     Error {
         code: u16,
-        message: String,
+        message: String, // Localized; `code` is what clients should match on
+    },
+    Range {
+        sensor_id: SensorId,
+        resolution: Resolution,
+        points: Vec<RollupPoint>,
+        annotations: Vec<Annotation>,
+    },
+    StorageInfo {
+        total_memory_bytes: usize,
+        sensors: Vec<SensorStorageInfo>,
+    },
+    StorageCompacted {
+        reclaimed_bytes: u64,
+        sensors: Vec<SensorCompaction>, // Only sensors with a persistent log
+    },
+    Backup {
+        sensor_id: SensorId,
+        format: BackupFormat,
+        readings: u64,
+        data: Vec<u8>,
+    },
+    BackupRestored {
+        sensor_id: SensorId,
+        readings: usize,
+    },
+    DegreeDays {
+        sensor_id: SensorId,
+        report: DegreeDayReport,
+    },
+    Forecast {
+        sensor_id: SensorId,
+        forecast: Forecast,
+    },
+    SetpointSet {
+        sensor_id: SensorId,
+        setpoint: f32,
+    },
+    ZoneSet {
+        sensor_id: SensorId,
+        zone: String,
+    },
+    Health {
+        sensors: Vec<SensorHealth>,
+    },
+    Alerts {
+        alerts: Vec<Alert>,
+        silences: Vec<Silence>,
+    },
+    AlertAcknowledged {
+        alert_id: u64,
+    },
+    Silenced {
+        silence: Silence,
+    },
+    SilenceRemoved {
+        silence_id: u64,
+    },
+    EscalationPolicySet,
+    SensorStateChanged {
+        sensor_id: SensorId,
+        previous: SensorState,
+        state: SensorState,
+    },
+    TimeZoneSet {
+        time_zone: String,
+    },
+    Annotated {
+        sensor_id: SensorId,
+        annotation: Annotation,
+    },
+    AnnotationRemoved {
+        sensor_id: SensorId,
+        annotation_id: u64,
+    },
+    AlertHistory {
+        records: Vec<AlertRecord>,
+    },
+    Calibrations {
+        export: CalibrationExport,
+    },
+    CalibrationsImported {
+        report: ImportReport,
+    },
+    LanguageSet {
+        language: Language,
+    },
+    TransformsSet {
+        sensor_id: SensorId,
+        transforms: Vec<TransformConfig>,
+    },
+    CommandFlagSet {
+        change: FlagChange,
+    },
+    CommandFlags {
+        disabled: Vec<String>,
+        audit: Vec<FlagChange>,
+    },
+    SensorInfo {
+        sensor_id: SensorId,
+        info: SensorSpec,
+    },
+    Authenticated {
+        identity: Identity,
+    },
+    TagsSet {
+        sensor_id: SensorId,
+        tags: Tags,
+    },
+    ThresholdsSet {
+        sensor_ids: Vec<SensorId>,
+        threshold: ThresholdConfig,
+    },
+    EpochStarted {
+        sensor_id: SensorId,
+        epoch: Epoch,
+    },
+    ReadingSubmitted {
+        sensor_id: SensorId,
+        timestamp: u64,
+        outcome: SubmitOutcome,
     },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub struct ProtocolMessage {
-    pub version: u8,
-    pub id: u32,
-    pub payload: MessagePayload,
+pub struct SensorStorageInfo {
+    pub sensor_id: SensorId,
+    pub storage: StorageInfo,
 }
 
+/// What a sensor's [`SensorInfo`] reports, owned so it can be sent.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub enum MessagePayload {
-    Command(Command),
-    Response(Response),
+pub struct SensorSpec {
+    pub manufacturer: String,
+    pub model: String,
+    /// Typical accuracy, ± °C.
+    pub accuracy: f32,
+    /// Smallest step between two readings, °C.
+    pub resolution: f32,
+    pub range: MeasurementRange,
 }
 
-#[derive(Debug, Clone)]
-pub enum ProtocolError {
-    InvalidSensorId { sensor_id: String },
-    SensorNotResponding { sensor_id: String },
-    InvalidThreshold { min: f32, max: f32, reason: String },
-    CalibrationFailed { sensor_id: String, reason: String },
-    SystemError { code: u16, details: String },
-    ProtocolVersionMismatch { expected: u8, received: u8 },
-}
-
-impl ProtocolError {
-    pub fn to_response(&self) -> Response {
-        match self {
-            ProtocolError::InvalidSensorId { sensor_id } => Response::Error {
-                code: 404,
-                message: format!("Sensor '{}' not found", sensor_id),
-            },
-            ProtocolError::SensorNotResponding { sensor_id } => Response::Error {
-                code: 503,
-                message: format!("Sensor '{}' is not responding", sensor_id),
-            },
-            ProtocolError::InvalidThreshold { min, max, reason } => Response::Error {
-                code: 400,
-                message: format!("Invalid threshold min={}, max={}: {}", min, max, reason),
-            },
-            ProtocolError::CalibrationFailed { sensor_id, reason } => Response::Error {
-                code: 422,
-                message: format!("Calibration failed for '{}': {}", sensor_id, reason),
-            },
-            ProtocolError::SystemError { code, details } => Response::Error {
-                code: *code,
-                message: details.clone(),
-            },
-            ProtocolError::ProtocolVersionMismatch { expected, received } => Response::Error {
-                code: 505,
-                message: format!("Protocol version mismatch: expected {}, got {}", expected, received),
-            },
-        }
-    }
-}
-
-pub struct TemperatureProtocolHandler {
-    next_message_id: u32,
-    sensors: HashMap<String, MockTemperatureSensor>,
-    store: TemperatureStore,
-    thresholds: HashMap<String, (f32, f32)>,
-    start_time: std::time::Instant,
-}
-
-impl TemperatureProtocolHandler {
-    pub fn new() -> Self {
-        let mut sensors = HashMap::new();
-
-        // Initialize with some mock sensors
-        sensors.insert("temp_01".to_string(),
-                      MockTemperatureSensor::new("temp_01".to_string(), 23.5));
-        sensors.insert("temp_02".to_string(),
-                      MockTemperatureSensor::new("temp_02".to_string(), 21.8));
-        sensors.insert("temp_03".to_string(),
-                      MockTemperatureSensor::new("temp_03".to_string(), 25.1));
-
+impl SensorSpec {
+    pub fn of(sensor: &impl SensorInfo) -> Self {
         Self {
-            next_message_id: 1,
-            sensors,
-            store: TemperatureStore::new(100), // Capacity of 100 readings
-            thresholds: HashMap::new(),
-            start_time: std::time::Instant::now(),
+            manufacturer: sensor.manufacturer().to_string(),
+            model: sensor.model().to_string(),
+            accuracy: sensor.accuracy(),
+            resolution: sensor.resolution(),
+            range: sensor.range(),
         }
     }
+}
 
-    pub fn create_command(&mut self, command: Command) -> ProtocolMessage {
-        let id = self.next_message_id;
-        self.next_message_id += 1;
-
-        ProtocolMessage {
-            version: 1,
-            id,
-            payload: MessagePayload::Command(command),
-        }
-    }
-
-    pub fn create_response(&self, request_id: u32, response: Response) -> ProtocolMessage {
-        ProtocolMessage {
-            version: 1,
-            id: request_id,
-            payload: MessagePayload::Response(response),
-        }
-    }
-
-    pub fn process_command(&mut self, message: ProtocolMessage) -> ProtocolMessage {
-        // Check protocol version
-        if message.version != 1 {
-            let error = ProtocolError::ProtocolVersionMismatch {
-                expected: 1,
-                received: message.version
-            };
-            return self.create_response(message.id, error.to_response());
-        }
-
-        let response = match message.payload {
-            MessagePayload::Command(command) => self.handle_command(command),
-            MessagePayload::Response(_) => {
-                Response::Error {
-                    code: 400,
-                    message: "Cannot process response messages".to_string(),
-                }
-            }
-        };
-
-        self.create_response(message.id, response)
-    }
-
-    fn handle_command(&mut self, command: Command) -> Response {
-        match command {
-            Command::GetStatus => {
-                let active_sensors: Vec<String> = self.sensors.keys().cloned().collect();
-                Response::Status {
-                    active_sensors,
-                    uptime_seconds: self.start_time.elapsed().as_secs(),
-                    readings_count: self.store.reading_count(),
-                }
-            }
-            Command::GetReading { sensor_id } => {
-                if let Some(sensor) = self.sensors.get_mut(&sensor_id) {
-                    match sensor.read_temperature() {
-                        Ok(temp) => {
-                            let reading = TemperatureReading::new(temp);
-                            self.store.add_reading(reading);
-
-                            Response::Reading {
-                                sensor_id,
-                                temperature: temp.celsius,
-                                timestamp: reading.timestamp,
-                            }
-                        }
-                        Err(_) => {
-                            let error = ProtocolError::SensorNotResponding { sensor_id };
-                            error.to_response()
-                        }
-                    }
-                } else {
-                    let error = ProtocolError::InvalidSensorId { sensor_id };
-                    error.to_response()
-                }
-            }
-            Command::SetThreshold { sensor_id, min_temp, max_temp } => {
-                if min_temp >= max_temp {
-                    let error = ProtocolError::InvalidThreshold {
-                        min: min_temp,
-                        max: max_temp,
-                        reason: "Min temperature must be less than max temperature".to_string(),
-                    };
-                    return error.to_response();
-                }
-
-                if !self.sensors.contains_key(&sensor_id) {
-                    let error = ProtocolError::InvalidSensorId { sensor_id };
-                    return error.to_response();
-                }
-
-                self.thresholds.insert(sensor_id.clone(), (min_temp, max_temp));
-                Response::ThresholdSet {
-                    sensor_id,
-                    min_temp,
-                    max_temp,
-                }
-            }
-            Command::GetHistory { sensor_id, last_n } => {
-                if !self.sensors.contains_key(&sensor_id) {
-                    let error = ProtocolError::InvalidSensorId { sensor_id };
-                    return error.to_response();
-                }
-
-                let readings = self.store.get_recent_readings(last_n);
-                Response::History {
-                    sensor_id,
-                    readings,
-                }
-            }
-            Command::GetStats { sensor_id } => {
-                if !self.sensors.contains_key(&sensor_id) {
-                    let error = ProtocolError::InvalidSensorId { sensor_id };
-                    return error.to_response();
-                }
-
-                let stats = self.store.get_stats();
-                Response::Stats {
-                    sensor_id,
-                    stats,
-                }
-            }
-            Command::Calibrate { sensor_id, actual_temp } => {
-                if let Some(sensor) = self.sensors.get_mut(&sensor_id) {
-                    // Simulate calibration by reading current temperature and calculating offset
-                    match sensor.read_temperature() {
-                        Ok(current_temp) => {
-                            let offset = actual_temp - current_temp.celsius;
-                            sensor.set_base_temperature(actual_temp);
-
-                            Response::CalibrationComplete {
-                                sensor_id,
-                                offset_adjustment: offset,
-                            }
-                        }
-                        Err(_) => {
-                            let error = ProtocolError::CalibrationFailed {
-                                sensor_id,
-                                reason: "Sensor not responding during calibration".to_string(),
-                            };
-                            error.to_response()
-                        }
-                    }
-                } else {
-                    let error = ProtocolError::InvalidSensorId { sensor_id };
-                    error.to_response()
-                }
-            }
-        }
+impl SensorInfo for SensorSpec {
+    fn accuracy(&self) -> f32 {
+        self.accuracy
     }
 
-    pub fn serialize_json(&self, message: &ProtocolMessage) -> Result<String, serde_json::Error> {
-        serde_json::to_string(message)
+    fn resolution(&self) -> f32 {
+        self.resolution
     }
 
-    pub fn serialize_binary(&self, message: &ProtocolMessage) -> Result<Vec<u8>, postcard::Error> {
-        postcard::to_allocvec(message)
+    fn range(&self) -> MeasurementRange {
+        self.range
     }
 
-    pub fn deserialize_json(&self, data: &str) -> Result<ProtocolMessage, serde_json::Error> {
-        serde_json::from_str(data)
+    fn manufacturer(&self) -> &str {
+        &self.manufacturer
     }
 
-    pub fn deserialize_binary(&self, data: &[u8]) -> Result<ProtocolMessage, postcard::Error> {
-        postcard::from_bytes(data)
+    fn model(&self) -> &str {
+        &self.model
     }
 }
 
-impl Default for TemperatureProtocolHandler {
-    fn default() -> Self {
-        Self::new()
-    }
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SensorCompaction {
+    pub sensor_id: SensorId,
+    pub report: CompactionReport,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_command_serialization() {
-        let command = Command::GetReading {
-            sensor_id: "temp_01".to_string(),
-        };
-
-        let message = ProtocolMessage {
-            version: 1,
-            id: 123,
-            payload: MessagePayload::Command(command),
-        };
-
-        // Test JSON serialization
-        let json_str = serde_json::to_string(&message).unwrap();
-        let parsed_message: ProtocolMessage = serde_json::from_str(&json_str).unwrap();
-        assert_eq!(message, parsed_message);
-
-        // Test binary serialization
-        let binary_data = postcard::to_allocvec(&message).unwrap();
-        let parsed_message: ProtocolMessage = postcard::from_bytes(&binary_data).unwrap();
-        assert_eq!(message, parsed_message);
-    }
-
-    #[test]
-    fn test_binary_vs_json_size() {
-        let command = Command::GetHistory {
-            sensor_id: "temp_sensor_with_very_long_name_for_testing".to_string(),
-            last_n: 100,
-        };
-
-        let message = ProtocolMessage {
-            version: 1,
-            id: 12345,
-            payload: MessagePayload::Command(command),
-        };
-
-        let json_data = serde_json::to_string(&message).unwrap();
-        let binary_data = postcard::to_allocvec(&message).unwrap();
-
-        println!("JSON size: {} bytes", json_data.len());
-        println!("Binary size: {} bytes", binary_data.len());
-
-        // Binary should be significantly smaller than JSON
-        assert!(binary_data.len() < json_data.len());
-
-        // For this message, we expect at least 30% space savings
-        let savings_ratio = (json_data.len() - binary_data.len()) as f32 / json_data.len() as f32;
-        assert!(savings_ratio > 0.3, "Expected at least 30% space savings, got {:.1}%", savings_ratio * 100.0);
-    }
-
-    #[test]
-    fn test_protocol_versioning() {
-        let mut handler = TemperatureProtocolHandler::new();
-
-        // Create message with wrong version
-        let message = ProtocolMessage {
-            version: 2, // Wrong version
-            id: 1,
-            payload: MessagePayload::Command(Command::GetStatus),
-        };
-
-        let response = handler.process_command(message);
-
-        if let MessagePayload::Response(Response::Error { code, message: msg }) = response.payload {
-            assert_eq!(code, 505);
-            assert!(msg.contains("version mismatch"));
-        } else {
-            panic!("Expected version mismatch error");
-        }
-    }
-
-    #[test]
-    fn test_error_responses() {
-        let mut handler = TemperatureProtocolHandler::new();
-
-        // Test invalid sensor ID
-        let message = handler.create_command(Command::GetReading {
-            sensor_id: "nonexistent_sensor".to_string(),
-        });
-
-        let response = handler.process_command(message);
-
-        if let MessagePayload::Response(Response::Error { code, message: msg }) = response.payload {
-            assert_eq!(code, 404);
-            assert!(msg.contains("not found"));
-        } else {
-            panic!("Expected sensor not found error");
-        }
-
-        // Test invalid threshold
-        let message = handler.create_command(Command::SetThreshold {
-            sensor_id: "temp_01".to_string(),
-            min_temp: 30.0,
-            max_temp: 20.0, // Invalid: min > max
-        });
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SensorStatus {
+    pub sensor_id: SensorId,
+    pub state: SensorState,
+    pub last_reading_ts: Option<u64>,
+    /// Seconds since the last reading, at the time of the status call.
+    pub last_reading_age_seconds: Option<u64>,
+    /// Failed reads since the last successful one.
+    pub consecutive_failures: u32,
+    /// Offset applied by the last calibration, if the sensor was calibrated.
+    pub calibration_offset: Option<f32>,
+    pub threshold: Option<ThresholdConfig>,
+    pub transforms: Vec<TransformConfig>,
+    /// Result of the sensor's last self-test, if it had one.
+    #[serde(default)]
+    pub self_test: Option<temp_core::SensorHealth>,
+    #[serde(default)]
+    pub tags: Tags,
+}
 
-        let response = handler.process_command(message);
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SensorHealth {
+    pub sensor_id: SensorId,
+    pub zone: Option<String>,
+    pub reading_count: usize,
+    /// Comparison with the rest of the zone, if it has at least three sensors.
+    pub peers: Option<PeerComparison>,
+    /// Alert activity over the last day.
+    pub alerts: AlertCounts,
+}
 
-        if let MessagePayload::Response(Response::Error { code, message: msg }) = response.payload {
-            assert_eq!(code, 400);
-            assert!(msg.contains("Invalid threshold"));
-        } else {
-            panic!("Expected invalid threshold error");
-        }
+impl SensorHealth {
+    pub fn suspected_faulty(&self) -> bool {
+        self.peers.as_ref().is_some_and(|p| p.suspected_faulty)
     }
+}
 
-    #[test]
-    fn test_command_processing() {
-        let mut handler = TemperatureProtocolHandler::new();
-
-        // Test GetStatus command
-        let message = handler.create_command(Command::GetStatus);
-        let response = handler.process_command(message);
-
-        if let MessagePayload::Response(Response::Status { active_sensors, uptime_seconds: _, readings_count }) = response.payload {
-            assert_eq!(active_sensors.len(), 3); // We have 3 mock sensors
-            assert!(active_sensors.contains(&"temp_01".to_string()));
-            assert_eq!(readings_count, 0); // No readings yet
-        } else {
-            panic!("Expected status response");
-        }
-
-        // Test GetReading command
-        let message = handler.create_command(Command::GetReading {
-            sensor_id: "temp_01".to_string(),
-        });
-        let response = handler.process_command(message);
-
-        if let MessagePayload::Response(Response::Reading { sensor_id, temperature, timestamp: _ }) = response.payload {
-            assert_eq!(sensor_id, "temp_01");
-            assert!((temperature - 23.5).abs() < 1.0); // Should be close to base temp (23.5) with some variation
-        } else {
-            panic!("Expected reading response");
-        }
-
-        // Test SetThreshold command
-        let message = handler.create_command(Command::SetThreshold {
-            sensor_id: "temp_01".to_string(),
-            min_temp: 15.0,
-            max_temp: 35.0,
-        });
-        let response = handler.process_command(message);
-
-        if let MessagePayload::Response(Response::ThresholdSet { sensor_id, min_temp, max_temp }) = response.payload {
-            assert_eq!(sensor_id, "temp_01");
-            assert_eq!(min_temp, 15.0);
-            assert_eq!(max_temp, 35.0);
-        } else {
-            panic!("Expected threshold set response");
-        }
-    }
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProtocolMessage {
+    pub version: u8,
+    pub id: MessageId,
+    pub payload: MessagePayload,
+    #[serde(default)]
+    pub headers: Headers,
+}
 
-    #[test]
-    fn test_calibration() {
-        let mut handler = TemperatureProtocolHandler::new();
+/// Metadata sent along with a message, by lowercase name, e.g. the trace
+/// context; see [`trace`].
+pub type Headers = BTreeMap<String, String>;
 
-        // Test calibration
-        let message = handler.create_command(Command::Calibrate {
-            sensor_id: "temp_01".to_string(),
-            actual_temp: 25.0,
-        });
-        let response = handler.process_command(message);
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum MessagePayload {
+    Command(Command),
+    Response(Response),
+}
 
-        if let MessagePayload::Response(Response::CalibrationComplete { sensor_id, offset_adjustment }) = response.payload {
-            assert_eq!(sensor_id, "temp_01");
-            // The offset should be the difference between actual and measured temperature
-            println!("Calibration offset: {}", offset_adjustment);
-            assert!(offset_adjustment.abs() < 10.0); // Reasonable calibration offset
-        } else {
-            panic!("Expected calibration complete response");
-        }
-    }
-}
\ No newline at end of file
@@ -0,0 +1,204 @@
+//! Interceptors around command processing.
+//!
+//! Cross-cutting concerns such as authentication, rate limiting, audit logs
+//! and metrics are written as [`Middleware`] and added to the handler with
+//! [`with_middleware`](crate::TemperatureProtocolHandler::with_middleware)
+//! instead of living in `process_command`. Before hooks run in the order the
+//! middleware was added, and the first one returning a response stops the
+//! command from running. After hooks then run in reverse order for every
+//! middleware whose before hook ran, so each sees the final response.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use temp_core::clock::{Clock, SystemClock};
+use temp_store::error_hook;
+
+use crate::{Command, MessagePayload, ProtocolMessage, Response};
+
+pub trait Middleware: Send {
+    /// Inspects a message before it is processed. Returning a response
+    /// rejects the message with it.
+    fn before(&mut self, _message: &ProtocolMessage) -> Option<Response> {
+        None
+    }
+
+    /// Inspects the response about to be sent for `request`.
+    fn after(&mut self, _request: &ProtocolMessage, _response: &ProtocolMessage) {}
+}
+
+/// The variant name of a command, e.g. `"GetReading"`.
+pub fn command_name(command: &Command) -> String {
+    let mut name = VariantName(String::new());
+    // Fails on purpose once the name is complete, so large payloads are never formatted
+    let _ = write!(name, "{:?}", command);
+    name.0
+}
+
+struct VariantName(String);
+
+impl fmt::Write for VariantName {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match s.find(|c: char| !c.is_alphanumeric()) {
+            Some(end) => {
+                self.0.push_str(&s[..end]);
+                Err(fmt::Error)
+            }
+            None => {
+                self.0.push_str(s);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn message_command_name(message: &ProtocolMessage) -> Option<String> {
+    match &message.payload {
+        MessagePayload::Command(command) => Some(command_name(command)),
+        MessagePayload::Response(_) => None,
+    }
+}
+
+/// Token bucket over all commands: `burst` commands at once, refilled at
+/// `per_second`. Rejected commands get a 429.
+pub struct RateLimit {
+    per_second: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill_ms: u64,
+    clock: Box<dyn Clock>,
+}
+
+impl RateLimit {
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        Self::with_clock(per_second, burst, SystemClock)
+    }
+
+    pub fn with_clock(per_second: u32, burst: u32, clock: impl Clock + 'static) -> Self {
+        let burst = burst.max(1) as f64;
+        Self {
+            per_second: per_second as f64,
+            burst,
+            tokens: burst,
+            last_refill_ms: clock.now_millis(),
+            clock: Box::new(clock),
+        }
+    }
+}
+
+impl Middleware for RateLimit {
+    fn before(&mut self, _message: &ProtocolMessage) -> Option<Response> {
+        let now = self.clock.now_millis();
+        let elapsed = now.saturating_sub(self.last_refill_ms) as f64 / 1000.0;
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.burst);
+        self.last_refill_ms = now;
+
+        if self.tokens < 1.0 {
+            return Some(Response::Error {
+                code: 429,
+                message: "Too many requests".to_string(),
+            });
+        }
+        self.tokens -= 1.0;
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommandCounts {
+    pub calls: u64,
+    /// Calls answered with an error, including rejections by other middleware.
+    pub errors: u64,
+}
+
+/// Counts calls and errors per command. Clones share the counts, so keep one
+/// to read them after handing the other to the handler.
+#[derive(Clone, Default)]
+pub struct CommandMetrics {
+    counts: Arc<Mutex<HashMap<String, CommandCounts>>>,
+}
+
+impl CommandMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn counts(&self, command: &str) -> CommandCounts {
+        let counts = error_hook::lock(&self.counts, "Recovered the command metrics lock");
+        counts.get(command).copied().unwrap_or_default()
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, CommandCounts> {
+        error_hook::lock(&self.counts, "Recovered the command metrics lock").clone()
+    }
+}
+
+impl Middleware for CommandMetrics {
+    fn after(&mut self, request: &ProtocolMessage, response: &ProtocolMessage) {
+        let Some(name) = message_command_name(request) else {
+            return;
+        };
+        let failed = matches!(response.payload, MessagePayload::Response(Response::Error { .. }));
+
+        let mut counts = error_hook::lock(&self.counts, "Recovered the command metrics lock");
+        let entry = counts.entry(name).or_default();
+        entry.calls += 1;
+        if failed {
+            entry.errors += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TemperatureProtocolHandler;
+    use temp_core::clock::ManualClock;
+
+    /// Rejects every command whose name is in the list.
+    struct Deny(Vec<&'static str>);
+
+    impl Middleware for Deny {
+        fn before(&mut self, message: &ProtocolMessage) -> Option<Response> {
+            let name = message_command_name(message)?;
+            self.0.contains(&name.as_str()).then(|| Response::Error {
+                code: 403,
+                message: format!("{} is not allowed", name),
+            })
+        }
+    }
+
+    #[test]
+    fn interceptors_reject_and_observe_commands() {
+        let clock = ManualClock::new(1_700_000_000);
+        let metrics = CommandMetrics::new();
+        let mut handler = TemperatureProtocolHandler::new()
+            .with_middleware(metrics.clone())
+            .with_middleware(Deny(vec!["Calibrate"]))
+            .with_middleware(RateLimit::with_clock(1, 2, clock.clone()));
+
+        let message = handler.create_command(Command::Calibrate { sensor_id: "temp_01".parse().unwrap(), actual_temp: 20.0 });
+        let response = handler.process_command(message);
+        assert!(matches!(response.payload, MessagePayload::Response(Response::Error { code: 403, .. })));
+
+        // The denied command never reached the rate limit, which allows two
+        for expected in [None, None, Some(429)] {
+            let message = handler.create_command(Command::GetStatus);
+            let code = match handler.process_command(message).payload {
+                MessagePayload::Response(Response::Error { code, .. }) => Some(code),
+                _ => None,
+            };
+            assert_eq!(code, expected);
+        }
+        clock.advance(1);
+        let message = handler.create_command(Command::GetStatus);
+        assert!(matches!(handler.process_command(message).payload, MessagePayload::Response(Response::Status { .. })));
+
+        assert_eq!(metrics.counts("Calibrate"), CommandCounts { calls: 1, errors: 1 });
+        assert_eq!(metrics.counts("GetStatus"), CommandCounts { calls: 4, errors: 1 });
+        assert_eq!(command_name(&Command::GetStorageInfo), "GetStorageInfo");
+        let restore = Command::RestoreBackup { sensor_id: "temp_01".parse().unwrap(), data: vec![0; 1 << 20] };
+        assert_eq!(command_name(&restore), "RestoreBackup");
+    }
+}
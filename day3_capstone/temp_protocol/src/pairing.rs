@@ -0,0 +1,209 @@
+//! Host side of the embedded node pairing handshake.
+//!
+//! The host collects `Announce` responses from nodes, hands out sensor ids
+//! and shared keys via `Pair` commands and keeps track of which nodes
+//! confirmed their assignment.
+
+use std::collections::HashMap;
+
+use temp_embedded::interlock;
+use temp_embedded::pairing::PAIRING_KEY_LEN;
+use temp_embedded::{EmbeddedCommand, EmbeddedResponse};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairedNode {
+    pub device_uid: u64,
+    pub sensor_id: u16,
+    pub key: [u8; PAIRING_KEY_LEN],
+    pub confirmed: bool,
+}
+
+pub struct PairingHost {
+    next_sensor_id: u16,
+    nodes: HashMap<u64, PairedNode>,
+}
+
+impl PairingHost {
+    pub fn new(first_sensor_id: u16) -> Self {
+        Self {
+            next_sensor_id: first_sensor_id,
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// Returns the `Pair` command to send in reply to an announcement, or
+    /// `None` if the node already holds the assignment we know about.
+    pub fn on_announce(
+        &mut self,
+        response: &EmbeddedResponse,
+        generate_key: impl FnOnce() -> [u8; PAIRING_KEY_LEN],
+    ) -> Option<EmbeddedCommand> {
+        let EmbeddedResponse::Announce { device_uid, sensor_id } = response else {
+            return None;
+        };
+
+        if let Some(node) = self.nodes.get(device_uid) {
+            if *sensor_id == Some(node.sensor_id) && node.confirmed {
+                return None;
+            }
+            return Some(Self::pair_command(node));
+        }
+
+        // A node paired by a previous host run keeps its id if it is still free
+        let sensor_id = match sensor_id {
+            Some(id) if !self.is_assigned(*id) => *id,
+            _ => self.allocate_sensor_id(),
+        };
+
+        let node = PairedNode {
+            device_uid: *device_uid,
+            sensor_id,
+            key: generate_key(),
+            confirmed: false,
+        };
+        let command = Self::pair_command(&node);
+        self.nodes.insert(*device_uid, node);
+        Some(command)
+    }
+
+    /// Marks the node as paired once it confirms the assignment.
+    pub fn on_paired(&mut self, device_uid: u64, response: &EmbeddedResponse) -> bool {
+        let EmbeddedResponse::Paired { sensor_id } = response else {
+            return false;
+        };
+
+        match self.nodes.get_mut(&device_uid) {
+            Some(node) if node.sensor_id == *sensor_id => {
+                node.confirmed = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn node(&self, device_uid: u64) -> Option<&PairedNode> {
+        self.nodes.get(&device_uid)
+    }
+
+    pub fn paired_nodes(&self) -> impl Iterator<Item = &PairedNode> {
+        self.nodes.values().filter(|node| node.confirmed)
+    }
+
+    /// Builds the authenticated command clearing incident `sequence` on a paired node.
+    pub fn reset_interlock(&self, device_uid: u64, sequence: u32) -> Option<EmbeddedCommand> {
+        let node = self.nodes.get(&device_uid).filter(|node| node.confirmed)?;
+        Some(EmbeddedCommand::ResetInterlock {
+            sequence,
+            tag: interlock::reset_tag(&node.key, device_uid, sequence),
+        })
+    }
+
+    /// Sensor id string used on the host side for a paired node.
+    pub fn sensor_name(sensor_id: u16) -> String {
+        format!("node_{:04}", sensor_id)
+    }
+
+    fn is_assigned(&self, sensor_id: u16) -> bool {
+        self.nodes.values().any(|node| node.sensor_id == sensor_id)
+    }
+
+    fn allocate_sensor_id(&mut self) -> u16 {
+        while self.is_assigned(self.next_sensor_id) {
+            self.next_sensor_id = self.next_sensor_id.wrapping_add(1);
+        }
+        let id = self.next_sensor_id;
+        self.next_sensor_id = self.next_sensor_id.wrapping_add(1);
+        id
+    }
+
+    fn pair_command(node: &PairedNode) -> EmbeddedCommand {
+        EmbeddedCommand::Pair {
+            device_uid: node.device_uid,
+            sensor_id: node.sensor_id,
+            key: node.key,
+        }
+    }
+}
+
+impl Default for PairingHost {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temp_embedded::pairing::{PairingManager, RamNvm};
+
+    #[test]
+    fn full_handshake() {
+        let mut host = PairingHost::new(10);
+        let mut node = PairingManager::load(RamNvm::<32>::new(), 0xABCD);
+
+        let announce = node.handle_command(&EmbeddedCommand::Identify).unwrap();
+        let pair = host.on_announce(&announce, || [5; PAIRING_KEY_LEN]).unwrap();
+
+        let confirmation = node.handle_command(&pair).unwrap();
+        assert!(host.on_paired(0xABCD, &confirmation));
+        assert_eq!(node.sensor_id(), Some(10));
+        assert_eq!(node.key(), Some(&[5; PAIRING_KEY_LEN]));
+
+        // Announcing again after a reset needs no new pairing
+        let node = PairingManager::load(node.into_nvm(), 0xABCD);
+        assert_eq!(host.on_announce(&node.announce(), || unreachable!()), None);
+        assert_eq!(host.paired_nodes().count(), 1);
+        assert_eq!(PairingHost::sensor_name(10), "node_0010");
+    }
+
+    #[test]
+    fn resets_interlock_with_pairing_key() {
+        use temp_core::mock::MockActuator;
+        use temp_core::Temperature;
+        use temp_embedded::interlock::{InterlockStore, SafetyInterlock};
+
+        // A fresh node boots with its interlock tripped until it is paired
+        let mut host = PairingHost::new(1);
+        let mut node = PairingManager::load(RamNvm::<32>::new(), 0x42);
+        let store = InterlockStore::load(RamNvm::<128>::new());
+        let mut interlock = SafetyInterlock::new(MockActuator::new(), 0x42, [0; PAIRING_KEY_LEN], store);
+        let pair = host.on_announce(&node.announce(), || [3; PAIRING_KEY_LEN]).unwrap();
+        let confirmation = node.handle_command(&pair).unwrap();
+
+        assert_eq!(host.reset_interlock(0x42, 1), None);
+        host.on_paired(0x42, &confirmation);
+        interlock.rekey(*node.key().unwrap(), 1).unwrap();
+        interlock.check(Temperature::new(25.0), 2).unwrap();
+
+        let sequence = interlock.last_incident().unwrap().sequence;
+        let reset = host.reset_interlock(0x42, sequence).unwrap();
+        assert_eq!(
+            interlock.handle_command(&reset, 3),
+            Some(EmbeddedResponse::InterlockReset { sequence })
+        );
+
+        interlock.check(Temperature::new(70.0), 4).unwrap();
+        interlock.check(Temperature::new(25.0), 5).unwrap();
+        let sequence = interlock.last_incident().unwrap().sequence;
+        let reset = host.reset_interlock(0x42, sequence).unwrap();
+        assert_eq!(
+            interlock.handle_command(&reset, 6),
+            Some(EmbeddedResponse::InterlockReset { sequence })
+        );
+    }
+
+    #[test]
+    fn assigns_distinct_ids() {
+        let mut host = PairingHost::new(1);
+
+        let first = EmbeddedResponse::Announce { device_uid: 1, sensor_id: None };
+        // Previously paired by another host run with id 1, which is now taken
+        let second = EmbeddedResponse::Announce { device_uid: 2, sensor_id: Some(1) };
+
+        host.on_announce(&first, || [0; PAIRING_KEY_LEN]);
+        host.on_announce(&second, || [0; PAIRING_KEY_LEN]);
+
+        assert_eq!(host.node(1).unwrap().sensor_id, 1);
+        assert_eq!(host.node(2).unwrap().sensor_id, 2);
+    }
+}
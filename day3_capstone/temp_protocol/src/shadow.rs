@@ -0,0 +1,174 @@
+//! Shadow mode for trying out a new handler on live traffic.
+//!
+//! [`Shadow`] is a [`Middleware`] that replays every command the primary
+//! handler answered on a candidate handler, compares the two responses and
+//! logs where they differ. Clients only ever see the primary's response, so
+//! a candidate with changed behavior can run next to the current one until
+//! its [`ShadowLog`] is clean.
+//!
+//! Add it last with [`with_middleware`](crate::TemperatureProtocolHandler::with_middleware),
+//! so it only sees commands that got past every other middleware and were
+//! actually processed by the primary.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use temp_store::error_hook;
+
+use crate::middleware::{command_name, Middleware};
+use crate::{MessagePayload, ProtocolMessage, Response, TemperatureProtocolHandler};
+
+/// Divergences kept by a [`ShadowLog`]; older ones are dropped.
+const MAX_DIVERGENCES: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub command: String,
+    pub primary: Response,
+    pub candidate: Response,
+}
+
+#[derive(Default)]
+struct LogInner {
+    compared: u64,
+    diverged: u64,
+    recent: VecDeque<Divergence>,
+}
+
+/// What the shadow found. Clones share the log, so keep one to read it
+/// after handing the shadow to the handler.
+#[derive(Clone, Default)]
+pub struct ShadowLog {
+    inner: Arc<Mutex<LogInner>>,
+}
+
+impl ShadowLog {
+    fn lock(&self) -> MutexGuard<'_, LogInner> {
+        error_hook::lock(&self.inner, "Recovered the shadow log lock")
+    }
+
+    /// Commands answered by both handlers so far.
+    pub fn compared(&self) -> u64 {
+        self.lock().compared
+    }
+
+    pub fn diverged(&self) -> u64 {
+        self.lock().diverged
+    }
+
+    /// The most recent divergences, oldest first.
+    pub fn divergences(&self) -> Vec<Divergence> {
+        self.lock().recent.iter().cloned().collect()
+    }
+
+    fn record(&self, divergence: Option<Divergence>) {
+        let mut inner = self.lock();
+        inner.compared += 1;
+        if let Some(divergence) = divergence {
+            inner.diverged += 1;
+            if inner.recent.len() >= MAX_DIVERGENCES {
+                inner.recent.pop_front();
+            }
+            inner.recent.push_back(divergence);
+        }
+    }
+}
+
+/// Whether two responses to the same command agree.
+pub type Comparator = fn(&Response, &Response) -> bool;
+
+/// Equal responses agree, and so do errors with the same code: their
+/// messages are localized, and the candidate always answers in English.
+pub fn same_response(primary: &Response, candidate: &Response) -> bool {
+    match (primary, candidate) {
+        (Response::Error { code: a, .. }, Response::Error { code: b, .. }) => a == b,
+        _ => primary == candidate,
+    }
+}
+
+pub struct Shadow {
+    candidate: TemperatureProtocolHandler,
+    compare: Comparator,
+    log: ShadowLog,
+}
+
+impl Shadow {
+    pub fn new(candidate: TemperatureProtocolHandler) -> Self {
+        Self {
+            candidate,
+            compare: same_response,
+            log: ShadowLog::default(),
+        }
+    }
+
+    /// Replaces [`same_response`], e.g. to ignore fields the candidate is
+    /// expected to change.
+    pub fn with_comparator(mut self, compare: Comparator) -> Self {
+        self.compare = compare;
+        self
+    }
+
+    pub fn log(&self) -> ShadowLog {
+        self.log.clone()
+    }
+}
+
+impl Middleware for Shadow {
+    fn after(&mut self, request: &ProtocolMessage, response: &ProtocolMessage) {
+        let MessagePayload::Command(command) = &request.payload else {
+            return;
+        };
+        let MessagePayload::Response(primary) = &response.payload else {
+            return;
+        };
+        let MessagePayload::Response(candidate) = self.candidate.process_command(request.clone()).payload else {
+            return;
+        };
+
+        if (self.compare)(primary, &candidate) {
+            self.log.record(None);
+            return;
+        }
+        let command = command_name(command);
+        eprintln!("Shadow divergence on {}: primary {:?}, candidate {:?}", command, primary, candidate);
+        self.log.record(Some(Divergence {
+            command,
+            primary: primary.clone(),
+            candidate,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, SensorState};
+    use temp_core::clock::ManualClock;
+
+    #[test]
+    fn logs_where_the_candidate_answers_differently() {
+        let clock = ManualClock::new(1_700_000_000);
+        let mut candidate = TemperatureProtocolHandler::new().with_clock(clock.clone());
+        let retire = candidate.create_command(Command::SetSensorState {
+            sensor_id: "temp_03".parse().unwrap(),
+            state: SensorState::Decommissioned,
+        });
+        candidate.process_command(retire);
+
+        let shadow = Shadow::new(candidate);
+        let log = shadow.log();
+        let mut handler = TemperatureProtocolHandler::new().with_clock(clock).with_middleware(shadow);
+
+        for sensor_id in ["temp_01", "temp_03", "nope"] {
+            let message = handler.create_command(Command::GetReading { sensor_id: sensor_id.parse().unwrap() });
+            let response = handler.process_command(message);
+            // Clients only see the primary
+            assert!(sensor_id == "nope" || matches!(response.payload, MessagePayload::Response(Response::Reading { .. })));
+        }
+
+        assert_eq!((log.compared(), log.diverged()), (3, 1));
+        let divergence = &log.divergences()[0];
+        assert_eq!(divergence.command, "GetReading");
+        assert!(matches!(divergence.candidate, Response::Error { code: 409, .. }));
+    }
+}
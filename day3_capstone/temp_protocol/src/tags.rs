@@ -0,0 +1,64 @@
+//! Key-value metadata on sensors.
+//!
+//! Rather than encoding a sensor's location or owner into its id, a sensor
+//! carries [`Tags`] such as `location=lab1` or `owner=ops`. A tag filter is
+//! itself a set of tags: a sensor matches when it has every one of them,
+//! so the empty filter matches every sensor.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+
+/// Tags by key, sorted so they list the same way every time.
+pub type Tags = BTreeMap<String, String>;
+
+/// Whether `tags` contain every tag of `filter`.
+pub fn matches(tags: &Tags, filter: &Tags) -> bool {
+    filter.iter().all(|(key, value)| tags.get(key) == Some(value))
+}
+
+/// Keys must be non-empty, and neither keys nor values may contain `=` or
+/// `,`, so tags can always be written as in [`parse`].
+pub fn validate(tags: &Tags) -> Result<(), String> {
+    for (key, value) in tags {
+        if key.trim().is_empty() {
+            return Err("Tag keys must not be empty".to_string());
+        }
+        if let Some(text) = [key, value].into_iter().find(|text| text.contains(['=', ','])) {
+            return Err(format!("Tag '{}' must not contain '=' or ','", text));
+        }
+    }
+    Ok(())
+}
+
+/// Parses `key=value` pairs separated by commas, e.g. `floor=2,owner=ops`.
+pub fn parse(text: &str) -> Result<Tags, String> {
+    let mut tags = Tags::new();
+    for pair in text.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let Some((key, value)) = pair.split_once('=') else {
+            return Err(format!("Expected key=value, got '{}'", pair));
+        };
+        tags.insert(key.trim().to_string(), value.trim().to_string());
+    }
+    validate(&tags)?;
+    Ok(tags)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_and_parsing() {
+        let tags = parse("location=lab1, floor=2,owner=ops").unwrap();
+        assert_eq!(tags.len(), 3);
+        assert!(matches(&tags, &Tags::new()));
+        assert!(matches(&tags, &parse("floor=2,owner=ops").unwrap()));
+        assert!(!matches(&tags, &parse("floor=3").unwrap()));
+        assert!(!matches(&tags, &parse("room=1").unwrap()));
+
+        assert!(parse("floor").is_err());
+        assert!(parse("=2").is_err());
+        assert!(parse("floor=2=3").is_err());
+    }
+}
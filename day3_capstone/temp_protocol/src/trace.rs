@@ -0,0 +1,237 @@
+//! Distributed tracing across the protocol, following W3C Trace Context.
+//!
+//! A caller that is part of a trace, such as an HTTP front end, sends the
+//! `traceparent` header with its command. A handler with a [`SpanExporter`]
+//! then records a server span for the command as a child of the caller's
+//! span, and internal spans for the store calls it makes, so the request
+//! shows as one trace from the front end down to the store. Commands
+//! without the header start a new trace. The response carries the server
+//! span's `traceparent`, and any `tracestate` is passed back unchanged.
+//!
+//! [`SpanRecord`]s carry what an OpenTelemetry span does: ids, parent, kind,
+//! timing and attributes. Sending them to a collector is up to the exporter.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use temp_core::clock::Clock;
+use temp_store::error_hook;
+
+pub const TRACEPARENT: &str = "traceparent";
+pub const TRACESTATE: &str = "tracestate";
+
+/// Attribute holding the code of the response, 200 for anything but an error.
+pub const STATUS_CODE_ATTRIBUTE: &str = "protocol.status_code";
+
+const VERSION: &str = "00";
+const FLAG_SAMPLED: u8 = 0x01;
+
+/// Where a span sits in a trace, as carried by `traceparent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    /// Whether the caller records this trace; unsampled traces are still
+    /// propagated but not exported.
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// The root span of a new, sampled trace.
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: random_id(),
+            span_id: random_id(),
+            sampled: true,
+        }
+    }
+
+    /// A new span in the same trace.
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: random_id(),
+            ..*self
+        }
+    }
+
+    /// Parses a `traceparent` header. Versions after `00` are read by their
+    /// first four fields, as the spec asks; `ff` and all-zero ids are invalid.
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let mut fields = traceparent.trim().split('-');
+        let (version, trace_id, span_id, flags) = (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+        if version.len() != 2 || version == "ff" || (version == VERSION && fields.next().is_some()) {
+            return None;
+        }
+        parse_hex::<1>(version)?;
+        let trace_id = parse_hex::<16>(trace_id)?;
+        let span_id = parse_hex::<8>(span_id)?;
+        let [flags] = parse_hex::<1>(flags)?;
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        Some(Self {
+            trace_id,
+            span_id,
+            sampled: flags & FLAG_SAMPLED != 0,
+        })
+    }
+}
+
+/// Formats as a version `00` `traceparent`.
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-", VERSION)?;
+        write_hex(f, &self.trace_id)?;
+        f.write_str("-")?;
+        write_hex(f, &self.span_id)?;
+        write!(f, "-{:02x}", if self.sampled { FLAG_SAMPLED } else { 0 })
+    }
+}
+
+fn random_id<const N: usize>() -> [u8; N] {
+    loop {
+        let id: [u8; N] = std::array::from_fn(|_| rand::random());
+        if id != [0; N] {
+            return id;
+        }
+    }
+}
+
+/// Lowercase hex only, as the spec requires.
+fn parse_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    if text.len() != 2 * N || !text.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(text.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    bytes.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    /// A command as the handler processed it.
+    Server,
+    /// Work within a command, such as a store query.
+    Internal,
+}
+
+/// A finished span.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanRecord {
+    pub name: String,
+    pub kind: SpanKind,
+    pub context: TraceContext,
+    /// The caller's span for a server span, the server span for an internal one.
+    pub parent_span_id: Option<[u8; 8]>,
+    /// Unix milliseconds, from the handler's clock.
+    pub start_millis: u64,
+    pub end_millis: u64,
+    pub attributes: Vec<(String, String)>,
+}
+
+/// Receives finished spans, e.g. to batch them to an OpenTelemetry collector.
+pub trait SpanExporter: Send {
+    fn export(&self, span: SpanRecord);
+}
+
+/// Keeps every span in memory; clones share the spans, so a test can keep
+/// one and hand the other to the handler.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingExporter {
+    spans: Arc<Mutex<Vec<SpanRecord>>>,
+}
+
+impl RecordingExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spans(&self) -> Vec<SpanRecord> {
+        error_hook::lock(&self.spans, "Recovered the recorded spans lock").clone()
+    }
+}
+
+impl SpanExporter for RecordingExporter {
+    fn export(&self, span: SpanRecord) {
+        error_hook::lock(&self.spans, "Recovered the recorded spans lock").push(span);
+    }
+}
+
+/// An internal span that ends, and is exported, when dropped.
+pub struct SpanGuard<'a> {
+    exporter: &'a dyn SpanExporter,
+    clock: &'a dyn Clock,
+    record: Option<SpanRecord>,
+}
+
+impl<'a> SpanGuard<'a> {
+    pub fn start(exporter: &'a dyn SpanExporter, clock: &'a dyn Clock, parent: &TraceContext, name: &str) -> Self {
+        let start_millis = clock.now_millis();
+        Self {
+            exporter,
+            clock,
+            record: Some(SpanRecord {
+                name: name.to_string(),
+                kind: SpanKind::Internal,
+                context: parent.child(),
+                parent_span_id: Some(parent.span_id),
+                start_millis,
+                end_millis: start_millis,
+                attributes: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn set_attribute(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        if let Some(record) = &mut self.record {
+            record.attributes.push((key.into(), value.into()));
+        }
+    }
+}
+
+impl Drop for SpanGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(mut record) = self.record.take() {
+            record.end_millis = self.clock.now_millis();
+            self.exporter.export(record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traceparent_round_trip() {
+        let header = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let context = TraceContext::parse(header).unwrap();
+        assert_eq!(context.span_id, [0x00, 0xf0, 0x67, 0xaa, 0x0b, 0xa9, 0x02, 0xb7]);
+        assert!(context.sampled);
+        assert_eq!(context.to_string(), header);
+
+        let child = context.child();
+        assert_eq!(child.trace_id, context.trace_id);
+        assert_ne!(child.span_id, context.span_id);
+        assert!(!TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00").unwrap().sampled);
+
+        // Later versions may add fields
+        assert!(TraceContext::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra").is_some());
+        for invalid in [
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736",
+        ] {
+            assert_eq!(TraceContext::parse(invalid), None, "{}", invalid);
+        }
+    }
+}
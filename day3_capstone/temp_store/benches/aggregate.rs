@@ -0,0 +1,44 @@
+//! Single- against multi-threaded aggregation of a month of readings.
+//!
+//! Run with `cargo bench -p temp_store --features parallel`; without the
+//! feature only the single-threaded side is measured.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use temp_core::Temperature;
+use temp_store::{aggregate, TemperatureReading};
+
+fn readings(count: u64) -> Vec<TemperatureReading> {
+    (0..count)
+        .map(|i| {
+            let celsius = 20.0 + ((i * 37) % 100) as f32 / 10.0;
+            TemperatureReading::with_timestamp(Temperature::new(celsius), 1_700_000_000 + i * 10)
+        })
+        .collect()
+}
+
+fn aggregation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("aggregate");
+    // A day, a week and a month at one reading every 10 s
+    for count in [8_640, 60_480, 267_840] {
+        let readings = readings(count);
+        group.bench_with_input(BenchmarkId::new("stats_sequential", count), &readings, |b, r| {
+            b.iter(|| aggregate::stats_sequential(black_box(r)))
+        });
+        group.bench_with_input(BenchmarkId::new("rollup_sequential", count), &readings, |b, r| {
+            b.iter(|| aggregate::rollup_sequential(black_box(r), 3600))
+        });
+        #[cfg(feature = "parallel")]
+        {
+            group.bench_with_input(BenchmarkId::new("stats_parallel", count), &readings, |b, r| {
+                b.iter(|| aggregate::stats_parallel(black_box(r)))
+            });
+            group.bench_with_input(BenchmarkId::new("rollup_parallel", count), &readings, |b, r| {
+                b.iter(|| aggregate::rollup_parallel(black_box(r), 3600))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, aggregation);
+criterion_main!(benches);
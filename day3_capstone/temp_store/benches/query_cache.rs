@@ -0,0 +1,36 @@
+//! A dashboard refreshing a window older than memory, which a persistent
+//! store pages in from its log, with and without the query cache.
+//!
+//! Run with `cargo bench -p temp_store --bench query_cache`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use temp_core::Temperature;
+use temp_store::{FlushPolicy, Resolution, TemperatureReading, TemperatureStore};
+
+fn refresh(c: &mut Criterion) {
+    let path = std::env::temp_dir().join(format!("temp_store_bench_query_cache_{}.log", std::process::id()));
+    let store = TemperatureStore::open(100, &path, FlushPolicy::EveryN(1000)).expect("open the log");
+    // Two days at one reading every 10 s; the minute tier only keeps the last day
+    let readings: Vec<_> = (0..17_280u64)
+        .map(|i| TemperatureReading::with_timestamp(Temperature::new(20.0 + (i % 50) as f32 / 10.0), i * 10))
+        .collect();
+    store.add_readings(&readings);
+    store.flush().expect("flush the log");
+
+    let mut group = c.benchmark_group("query_cache");
+    store.set_query_cache_capacity(0);
+    group.bench_function("first_hour_uncached", |b| {
+        b.iter(|| store.query_range_at(black_box(0), black_box(3600), Resolution::Minute))
+    });
+    store.set_query_cache_capacity(16);
+    group.bench_function("first_hour_cached", |b| {
+        b.iter(|| store.query_range_at(black_box(0), black_box(3600), Resolution::Minute))
+    });
+    group.finish();
+
+    drop(store);
+    let _ = std::fs::remove_file(&path);
+}
+
+criterion_group!(benches, refresh);
+criterion_main!(benches);
@@ -0,0 +1,182 @@
+//! Stats and rollups over large slices of readings.
+//!
+//! Range queries reaching into the log page in every reading of the range,
+//! easily hundreds of thousands for a month. With the `parallel` feature,
+//! slices of at least [`PARALLEL_THRESHOLD`] readings are folded on rayon's
+//! thread pool in chunks whose partial results are then merged; smaller ones
+//! aren't worth the hand-off. Sums are kept in `f64` either way, so a long
+//! range doesn't lose the average to rounding. `cargo bench -p temp_store
+//! --features parallel` compares both. The stats build without `std`;
+//! rollups need it.
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use temp_core::{PreciseTemperature, Temperature};
+
+#[cfg(feature = "std")]
+use crate::rollup::{RollupPoint, RollupTier};
+use crate::{TemperatureReading, TemperatureStats};
+
+/// Below this many readings the fold stays on the calling thread.
+pub const PARALLEL_THRESHOLD: usize = 16 * 1024;
+
+#[cfg(feature = "parallel")]
+const CHUNK_READINGS: usize = 8 * 1024;
+
+#[derive(Clone, Copy)]
+struct PartialStats {
+    min: f32,
+    max: f32,
+    sum: f64,
+    count: usize,
+}
+
+impl PartialStats {
+    const EMPTY: PartialStats = PartialStats { min: f32::INFINITY, max: f32::NEG_INFINITY, sum: 0.0, count: 0 };
+
+    fn add(mut self, reading: &TemperatureReading) -> Self {
+        let celsius = reading.temperature.celsius;
+        self.min = self.min.min(celsius);
+        self.max = self.max.max(celsius);
+        self.sum += f64::from(celsius);
+        self.count += 1;
+        self
+    }
+
+    #[cfg(feature = "parallel")]
+    fn merge(self, other: Self) -> Self {
+        PartialStats {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            sum: self.sum + other.sum,
+            count: self.count + other.count,
+        }
+    }
+
+    fn finish(self) -> Option<TemperatureStats> {
+        (self.count > 0).then(|| {
+            TemperatureStats::new(
+                Temperature::new(self.min),
+                Temperature::new(self.max),
+                PreciseTemperature::new(self.sum / self.count as f64),
+                self.count,
+            )
+        })
+    }
+}
+
+/// Min, max and average of `readings`, in parallel when large enough.
+pub fn stats(readings: &[TemperatureReading]) -> Option<TemperatureStats> {
+    #[cfg(feature = "parallel")]
+    if readings.len() >= PARALLEL_THRESHOLD {
+        return stats_parallel(readings);
+    }
+    stats_sequential(readings)
+}
+
+pub fn stats_sequential(readings: &[TemperatureReading]) -> Option<TemperatureStats> {
+    readings.iter().fold(PartialStats::EMPTY, PartialStats::add).finish()
+}
+
+#[cfg(feature = "parallel")]
+pub fn stats_parallel(readings: &[TemperatureReading]) -> Option<TemperatureStats> {
+    readings
+        .par_iter()
+        .fold(|| PartialStats::EMPTY, PartialStats::add)
+        .reduce(|| PartialStats::EMPTY, PartialStats::merge)
+        .finish()
+}
+
+/// One point per `bucket_seconds` bucket of `readings`, oldest first, in
+/// parallel when large enough.
+#[cfg(feature = "std")]
+pub fn rollup(readings: &[TemperatureReading], bucket_seconds: u64) -> Vec<RollupPoint> {
+    #[cfg(feature = "parallel")]
+    if readings.len() >= PARALLEL_THRESHOLD {
+        return rollup_parallel(readings, bucket_seconds);
+    }
+    rollup_sequential(readings, bucket_seconds)
+}
+
+#[cfg(feature = "std")]
+pub fn rollup_sequential(readings: &[TemperatureReading], bucket_seconds: u64) -> Vec<RollupPoint> {
+    let mut tier = RollupTier::new(bucket_seconds, usize::MAX);
+    readings.iter().for_each(|reading| tier.add(reading));
+    tier.range(0, u64::MAX)
+}
+
+/// Rolls up chunks independently; as readings are mostly in order, adjacent
+/// chunks only share the buckets at their edges, which merging combines.
+#[cfg(feature = "parallel")]
+pub fn rollup_parallel(readings: &[TemperatureReading], bucket_seconds: u64) -> Vec<RollupPoint> {
+    readings
+        .par_chunks(CHUNK_READINGS)
+        .map(|chunk| rollup_sequential(chunk, bucket_seconds))
+        .reduce(Vec::new, merge_points)
+}
+
+/// Merges two series sorted by bucket start into one.
+#[cfg(feature = "parallel")]
+fn merge_points(left: Vec<RollupPoint>, right: Vec<RollupPoint>) -> Vec<RollupPoint> {
+    let mut merged: Vec<RollupPoint> = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    loop {
+        let next = match (left.peek(), right.peek()) {
+            (Some(l), Some(r)) if l.start <= r.start => left.next(),
+            (Some(_), Some(_)) => right.next(),
+            (Some(_), None) => left.next(),
+            (None, _) => right.next(),
+        };
+        let Some(point) = next else {
+            return merged;
+        };
+        match merged.last_mut() {
+            Some(last) if last.start == point.start => last.combine(&point),
+            _ => merged.push(point),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn readings(count: u64) -> Vec<TemperatureReading> {
+        // Mostly in order, with some stragglers a few minutes late
+        (0..count)
+            .map(|i| {
+                let timestamp = if i % 97 == 0 { (i * 10).saturating_sub(300) } else { i * 10 };
+                let celsius = 20.0 + ((i * 37) % 100) as f32 / 10.0;
+                TemperatureReading::with_timestamp(Temperature::new(celsius), timestamp)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stats_cover_every_reading() {
+        let stats = stats(&readings(50_000)).unwrap();
+        assert_eq!(stats.count, 50_000);
+        assert_eq!(stats.min.celsius, 20.0);
+        assert_eq!(stats.max.celsius, 29.9);
+        assert!((stats.average.celsius - 24.95).abs() < 1e-3);
+        assert!(super::stats(&[]).is_none());
+
+        let few = readings(10);
+        let (stats, expected) = (super::stats(&few).unwrap(), TemperatureStats::from_readings(&few).unwrap());
+        assert_eq!((stats.min, stats.max, stats.count), (expected.min, expected.max, expected.count));
+        assert!((stats.average.celsius - expected.average.celsius).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rollup_matches_the_rollup_tier() {
+        let readings = readings(50_000);
+        let points = rollup(&readings, 60);
+        let expected = rollup_sequential(&readings, 60);
+        assert_eq!(points.len(), expected.len());
+        assert_eq!(points.iter().map(|p| p.count).sum::<usize>(), 50_000);
+        for (point, expected) in points.iter().zip(&expected) {
+            assert_eq!((point.start, point.count, point.min, point.max), (expected.start, expected.count, expected.min, expected.max));
+            assert!((point.average.celsius - expected.average.celsius).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Operator notes on time ranges.
+//!
+//! Annotations explain anomalies ("window open for maintenance") and are
+//! returned next to the readings they cover. They are kept in memory with the
+//! store's readings; the reading log does not include them.
+
+use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub id: u64,
+    /// Inclusive start of the annotated range.
+    pub start: u64,
+    /// Exclusive end of the annotated range.
+    pub end: u64,
+    pub text: String,
+    pub author: Option<String>,
+}
+
+impl Annotation {
+    /// True if the annotation covers any part of `start..end`.
+    pub fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub(crate) struct Annotations {
+    next_id: u64,
+    entries: Vec<Annotation>,
+}
+
+#[cfg(feature = "std")]
+impl Annotations {
+    pub(crate) fn add(&mut self, start: u64, end: u64, text: String, author: Option<String>) -> Option<Annotation> {
+        if start >= end {
+            return None;
+        }
+        self.next_id += 1;
+        let annotation = Annotation {
+            id: self.next_id,
+            start,
+            end,
+            text,
+            author,
+        };
+        let index = self.entries.partition_point(|a| a.start <= start);
+        self.entries.insert(index, annotation.clone());
+        Some(annotation)
+    }
+
+    pub(crate) fn remove(&mut self, id: u64) -> Option<Annotation> {
+        let index = self.entries.iter().position(|a| a.id == id)?;
+        Some(self.entries.remove(index))
+    }
+
+    /// Annotations overlapping `start..end`, ordered by start.
+    pub(crate) fn overlapping(&self, start: u64, end: u64) -> Vec<Annotation> {
+        self.entries.iter().filter(|a| a.overlaps(start, end)).cloned().collect()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::TemperatureStore;
+
+    #[test]
+    fn annotations_overlapping_range() {
+        let store = TemperatureStore::new(10);
+        let window = store.annotate(100, 200, "Window open".to_string(), Some("alice".to_string())).unwrap();
+        store.annotate(50, 60, "Door open".to_string(), None).unwrap();
+        assert!(store.annotate(300, 300, "Empty".to_string(), None).is_none());
+
+        let notes = store.annotations(0, 1000);
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0].text, "Door open");
+
+        assert_eq!(store.annotations(150, 160), vec![window.clone()]);
+        assert!(store.annotations(200, 300).is_empty());
+
+        assert_eq!(store.remove_annotation(window.id), Some(window));
+        assert!(store.annotations(150, 160).is_empty());
+        assert!(store.remove_annotation(99).is_none());
+    }
+}
@@ -0,0 +1,277 @@
+//! Backup streams of readings.
+//!
+//! [`BackupFormat::Json`] writes one reading per line, exactly like an
+//! unencrypted log, so a backup can also serve as a log to open a store on.
+//! [`BackupFormat::Compact`] stores the difference to the previous reading
+//! as variable-length integers: a few bytes per reading instead of ~50.
+//! Temperatures with at most two decimals are stored as hundredths, others
+//! as their raw bits, so the format is lossless either way.
+//! [`BackupFormat::Gzip`] is the JSON lines compressed with gzip, for tools
+//! that expect a `.gz` file; `gunzip` turns it back into a log.
+//!
+//! [`read_backup`] tells the formats apart by the gzip and compact headers.
+
+#[cfg(feature = "std")]
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+#[cfg(feature = "std")]
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use temp_core::Temperature;
+
+#[cfg(feature = "std")]
+use crate::TemperatureReading;
+
+#[cfg(feature = "std")]
+const COMPACT_MAGIC: &[u8] = b"TEMPBAK1\n";
+#[cfg(feature = "std")]
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BackupFormat {
+    #[default]
+    Json,
+    Compact,
+    Gzip,
+}
+
+/// The stream a [`BackupWriter`] writes to, compressed for [`BackupFormat::Gzip`].
+#[cfg(feature = "std")]
+enum Output<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(out) => out.write(buf),
+            Output::Gzip(out) => out.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(out) => out.flush(),
+            Output::Gzip(out) => out.flush(),
+        }
+    }
+}
+
+/// Writes readings to a backup stream one at a time.
+#[cfg(feature = "std")]
+pub struct BackupWriter<W: Write> {
+    out: Output<W>,
+    format: BackupFormat,
+    previous_timestamp: u64,
+    previous_centi: i64,
+    written: u64,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> BackupWriter<W> {
+    pub fn new(mut out: W, format: BackupFormat) -> io::Result<Self> {
+        let out = match format {
+            BackupFormat::Json => Output::Plain(out),
+            BackupFormat::Compact => {
+                out.write_all(COMPACT_MAGIC)?;
+                Output::Plain(out)
+            }
+            BackupFormat::Gzip => Output::Gzip(GzEncoder::new(out, Compression::default())),
+        };
+        Ok(Self {
+            out,
+            format,
+            previous_timestamp: 0,
+            previous_centi: 0,
+            written: 0,
+        })
+    }
+
+    pub fn write(&mut self, reading: &TemperatureReading) -> io::Result<()> {
+        match self.format {
+            BackupFormat::Json | BackupFormat::Gzip => {
+                serde_json::to_writer(&mut self.out, reading).map_err(io::Error::other)?;
+                self.out.write_all(b"\n")?;
+            }
+            BackupFormat::Compact => {
+                let delta = reading.timestamp.wrapping_sub(self.previous_timestamp) as i64;
+                write_varint(&mut self.out, zigzag(delta))?;
+                self.previous_timestamp = reading.timestamp;
+
+                let celsius = reading.temperature.celsius;
+                match exact_centi(celsius) {
+                    Some(centi) => {
+                        write_varint(&mut self.out, zigzag(centi - self.previous_centi) << 1)?;
+                        self.previous_centi = centi;
+                    }
+                    None => {
+                        write_varint(&mut self.out, 1)?;
+                        self.out.write_all(&celsius.to_bits().to_le_bytes())?;
+                    }
+                }
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Flushes the stream and returns the number of readings written.
+    pub fn finish(self) -> io::Result<u64> {
+        match self.out {
+            Output::Plain(mut out) => out.flush()?,
+            Output::Gzip(out) => out.finish()?.flush()?,
+        }
+        Ok(self.written)
+    }
+}
+
+/// Reads a backup in any format.
+#[cfg(feature = "std")]
+pub fn read_backup(input: impl Read) -> io::Result<Vec<TemperatureReading>> {
+    let mut input = BufReader::new(input);
+    if input.fill_buf()?.starts_with(GZIP_MAGIC) {
+        return read_uncompressed(BufReader::new(GzDecoder::new(input)));
+    }
+    read_uncompressed(input)
+}
+
+#[cfg(feature = "std")]
+fn read_uncompressed(mut input: impl BufRead) -> io::Result<Vec<TemperatureReading>> {
+    if !input.fill_buf()?.starts_with(COMPACT_MAGIC) {
+        return crate::FileBackend::load_from(input);
+    }
+    input.consume(COMPACT_MAGIC.len());
+
+    let mut readings = Vec::new();
+    let (mut timestamp, mut centi) = (0u64, 0i64);
+    while let Some(delta) = read_varint(&mut input)? {
+        timestamp = timestamp.wrapping_add(unzigzag(delta) as u64);
+        let value = read_varint(&mut input)?.ok_or_else(truncated)?;
+        let celsius = if value & 1 == 0 {
+            centi += unzigzag(value >> 1);
+            centi as f32 / 100.0
+        } else {
+            let mut bits = [0; 4];
+            input.read_exact(&mut bits)?;
+            f32::from_bits(u32::from_le_bytes(bits))
+        };
+        readings.push(TemperatureReading::with_timestamp(Temperature::new(celsius), timestamp));
+    }
+    Ok(readings)
+}
+
+/// Hundredths of a degree, if that represents `celsius` exactly.
+#[cfg(feature = "std")]
+fn exact_centi(celsius: f32) -> Option<i64> {
+    let centi = (celsius as f64 * 100.0).round();
+    let exact = centi.abs() < i32::MAX as f64 && (centi as f32 / 100.0).to_bits() == celsius.to_bits();
+    exact.then_some(centi as i64)
+}
+
+#[cfg(feature = "std")]
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+#[cfg(feature = "std")]
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+#[cfg(feature = "std")]
+fn write_varint(out: &mut impl Write, mut value: u64) -> io::Result<()> {
+    while value >= 0x80 {
+        out.write_all(&[(value as u8) | 0x80])?;
+        value >>= 7;
+    }
+    out.write_all(&[value as u8])
+}
+
+/// `None` at a clean end of the stream.
+#[cfg(feature = "std")]
+fn read_varint(input: &mut impl Read) -> io::Result<Option<u64>> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        if input.read(&mut byte)? == 0 {
+            return if shift == 0 { Ok(None) } else { Err(truncated()) };
+        }
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "varint too long"))
+}
+
+#[cfg(feature = "std")]
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "backup ends in the middle of a reading")
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_formats_round_trip_losslessly() {
+        let readings = vec![
+            TemperatureReading::with_timestamp(Temperature::new(21.5), 1_700_000_000),
+            TemperatureReading::with_timestamp(Temperature::new(-3.25), 1_700_000_060),
+            TemperatureReading::with_timestamp(Temperature::new(21.123_457), 1_700_000_120),
+            // Out of order
+            TemperatureReading::with_timestamp(Temperature::new(22.0), 1_699_999_000),
+        ];
+
+        let mut sizes = Vec::new();
+        for format in [BackupFormat::Json, BackupFormat::Compact, BackupFormat::Gzip] {
+            let mut out = Vec::new();
+            let mut writer = BackupWriter::new(&mut out, format).unwrap();
+            readings.iter().try_for_each(|r| writer.write(r)).unwrap();
+            assert_eq!(writer.finish().unwrap(), 4);
+
+            let restored = read_backup(out.as_slice()).unwrap();
+            assert_eq!(restored, readings);
+            sizes.push(out.len());
+        }
+        assert!(sizes[1] * 4 < sizes[0]);
+        assert!(sizes[2] < sizes[0]);
+    }
+
+    #[test]
+    fn gzip_backup_is_a_compressed_log() {
+        let readings: Vec<_> = (0..1000)
+            .map(|i| TemperatureReading::with_timestamp(Temperature::new(20.0 + (i % 7) as f32 * 0.25), 1_700_000_000 + i * 60))
+            .collect();
+        let mut written = Vec::new();
+        for format in [BackupFormat::Json, BackupFormat::Compact, BackupFormat::Gzip] {
+            let mut out = Vec::new();
+            let mut writer = BackupWriter::new(&mut out, format).unwrap();
+            readings.iter().try_for_each(|r| writer.write(r)).unwrap();
+            writer.finish().unwrap();
+            written.push(out);
+        }
+        let [json, compact, gzip] = written.try_into().unwrap();
+        assert!(gzip.len() * 10 < json.len());
+        assert!(compact.len() < gzip.len());
+
+        let mut log = Vec::new();
+        GzDecoder::new(gzip.as_slice()).read_to_end(&mut log).unwrap();
+        assert_eq!(log, json);
+        assert_eq!(read_backup(gzip.as_slice()).unwrap(), readings);
+    }
+
+    #[test]
+    fn truncated_compact_backup_is_an_error() {
+        let mut out = Vec::new();
+        let mut writer = BackupWriter::new(&mut out, BackupFormat::Compact).unwrap();
+        writer.write(&TemperatureReading::with_timestamp(Temperature::new(20.0), 1_700_000_000)).unwrap();
+        writer.finish().unwrap();
+
+        out.pop();
+        assert_eq!(read_backup(out.as_slice()).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,168 @@
+//! Comparing a time window against the same window at an earlier time.
+//!
+//! The earlier window is usually long gone from the raw buffer, so both
+//! windows are summarised from the finest tier that still covers them. Edges
+//! are therefore only as precise as that tier's buckets.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::rollup::{DAY_SECONDS, RollupPoint};
+use crate::{precise_mean, Accumulator, TemperatureStats};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ComparisonPeriod {
+    PreviousDay,
+    PreviousWeek,
+    /// Any other offset, in seconds.
+    Custom(u64),
+}
+
+impl ComparisonPeriod {
+    pub fn offset_seconds(&self) -> u64 {
+        match self {
+            ComparisonPeriod::PreviousDay => DAY_SECONDS,
+            ComparisonPeriod::PreviousWeek => 7 * DAY_SECONDS,
+            ComparisonPeriod::Custom(seconds) => *seconds,
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            ComparisonPeriod::PreviousDay => "yesterday".to_string(),
+            ComparisonPeriod::PreviousWeek => "the same time last week".to_string(),
+            ComparisonPeriod::Custom(seconds) => format!("{} s earlier", seconds),
+        }
+    }
+}
+
+/// Current minus previous, in °C.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StatsDelta {
+    pub average: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowComparison {
+    pub period: ComparisonPeriod,
+    pub current: TemperatureStats,
+    pub previous: TemperatureStats,
+    pub delta: StatsDelta,
+}
+
+impl WindowComparison {
+    pub fn new(period: ComparisonPeriod, current: TemperatureStats, previous: TemperatureStats) -> Self {
+        let delta = StatsDelta {
+            average: current.average.celsius - previous.average.celsius,
+            min: current.min.celsius - previous.min.celsius,
+            max: current.max.celsius - previous.max.celsius,
+        };
+        Self { period, current, previous, delta }
+    }
+}
+
+/// Human readable summary, e.g. "2.3 °C warmer than the same time last week".
+impl fmt::Display for WindowComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let delta = self.delta.average;
+        if delta.abs() < 0.05 {
+            write!(f, "About as warm as {}", self.period.label())
+        } else if delta > 0.0 {
+            write!(f, "{:.1} °C warmer than {}", delta, self.period.label())
+        } else {
+            write!(f, "{:.1} °C colder than {}", -delta, self.period.label())
+        }
+    }
+}
+
+/// Combines rollup points into one set of statistics, weighting by count.
+pub(crate) fn stats_from_points(points: &[RollupPoint]) -> Option<TemperatureStats> {
+    let first = points.first()?;
+    let (mut min, mut max) = (first.min, first.max);
+    let mut sum: Accumulator = 0.0;
+    let mut count = 0;
+
+    for point in points {
+        if point.min.celsius < min.celsius {
+            min = point.min;
+        }
+        if point.max.celsius > max.celsius {
+            max = point.max;
+        }
+        sum += point.sum;
+        count += point.count;
+    }
+
+    Some(TemperatureStats::new(min, max, precise_mean(sum, count), count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rollup::HOUR_SECONDS;
+    use crate::{TemperatureReading, TemperatureStore};
+    use temp_core::Temperature;
+
+    #[test]
+    fn combines_points_by_count() {
+        let point = |min: f32, max: f32, average: f32, count| RollupPoint {
+            start: 0,
+            min: Temperature::new(min),
+            max: Temperature::new(max),
+            average: Temperature::new(average),
+            count,
+            sum: average as Accumulator * count as Accumulator,
+        };
+
+        let stats = stats_from_points(&[point(10.0, 20.0, 15.0, 1), point(5.0, 30.0, 25.0, 3)]).unwrap();
+        assert_eq!(stats.min.celsius, 5.0);
+        assert_eq!(stats.max.celsius, 30.0);
+        assert_eq!(stats.average.celsius, 22.5);
+        assert_eq!(stats.count, 4);
+        assert!(stats_from_points(&[]).is_none());
+    }
+
+    #[test]
+    fn compares_against_last_week() {
+        let store = TemperatureStore::new(10);
+        let week = 7 * DAY_SECONDS;
+
+        // Hourly readings over eight days, 2.5 °C warmer in the second week
+        for hour in 0..(8 * 24) {
+            let timestamp = hour * HOUR_SECONDS;
+            let celsius = if timestamp >= week { 22.5 } else { 20.0 };
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(celsius), timestamp));
+        }
+
+        let start = week + DAY_SECONDS / 2;
+        let comparison = store.compare_window(start, start + 6 * HOUR_SECONDS, ComparisonPeriod::PreviousWeek).unwrap();
+        assert_eq!(comparison.current.count, 6);
+        assert_eq!(comparison.previous.count, 6);
+        assert!((comparison.delta.average - 2.5).abs() < 1e-4);
+        assert_eq!(comparison.to_string(), "2.5 °C warmer than the same time last week");
+
+        // Nothing stored two weeks back
+        assert!(store.compare_window(start, start + HOUR_SECONDS, ComparisonPeriod::Custom(2 * week)).is_none());
+    }
+
+    #[test]
+    fn recent_windows_use_raw_readings() {
+        let store = TemperatureStore::new(100);
+        for minute in 0..60 {
+            let celsius = if minute < 30 { 21.0 } else { 19.0 };
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(celsius), DAY_SECONDS + minute * 60));
+        }
+
+        let stats = store.window_stats(DAY_SECONDS + 30 * 60 + 15, DAY_SECONDS + 60 * 60).unwrap();
+        // Raw data: the 30th-minute reading at +1800 s is outside the window
+        assert_eq!(stats.count, 29);
+
+        let comparison = store
+            .compare_window(DAY_SECONDS + 1800, DAY_SECONDS + 3600, ComparisonPeriod::Custom(1800))
+            .unwrap();
+        assert_eq!(comparison.to_string(), "2.0 °C colder than 1800 s earlier");
+    }
+}
@@ -0,0 +1,191 @@
+//! Cross-sensor comparison within a zone.
+//!
+//! Sensors in the same room should roughly agree. Each sensor's minute
+//! rollups are compared with the zone median over a window; one that stays
+//! too far off for most of the window is suspected faulty. The median needs
+//! at least three sensors reporting to tell which one is the odd one out.
+
+use alloc::string::String;
+#[cfg(feature = "std")]
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::rollup::HOUR_SECONDS;
+#[cfg(feature = "std")]
+use crate::rollup::Resolution;
+#[cfg(feature = "std")]
+use crate::TemperatureStore;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DivergenceConfig {
+    /// Seconds of history to compare, ending at the analysis time.
+    pub window: u64,
+    /// Deviation from the zone median, in °C, that counts as divergent.
+    pub max_deviation: f32,
+    /// Share of compared minutes that must be divergent to flag the sensor.
+    pub min_fraction: f32,
+    /// Fewest compared minutes before a sensor can be flagged.
+    pub min_buckets: usize,
+}
+
+impl Default for DivergenceConfig {
+    fn default() -> Self {
+        Self {
+            window: 6 * HOUR_SECONDS,
+            max_deviation: 2.0,
+            min_fraction: 0.8,
+            min_buckets: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerComparison {
+    pub sensor_id: String,
+    /// Minutes in which at least three sensors of the zone, this one included, had data.
+    pub buckets: usize,
+    /// Average of sensor minus zone median, in °C.
+    pub mean_deviation: f32,
+    pub divergent_fraction: f32,
+    /// Pearson correlation with the zone median; `None` if either series is flat.
+    pub correlation: Option<f32>,
+    pub suspected_faulty: bool,
+}
+
+/// Compares every sensor with its zone over the window ending at `end`.
+#[cfg(feature = "std")]
+pub fn compare_peers(sensors: &[(&str, &TemperatureStore)], end: u64, config: &DivergenceConfig) -> Vec<PeerComparison> {
+    let start = end.saturating_sub(config.window);
+    let series: Vec<BTreeMap<u64, f32>> = sensors
+        .iter()
+        .map(|(_, store)| {
+            store
+                .query_range_at(start, end, Resolution::Minute)
+                .points
+                .iter()
+                .map(|p| (p.start, p.average.celsius))
+                .collect()
+        })
+        .collect();
+
+    sensors
+        .iter()
+        .enumerate()
+        .map(|(index, (sensor_id, _))| {
+            let mut own = Vec::new();
+            let mut zone = Vec::new();
+
+            for (bucket, value) in &series[index] {
+                let mut values: Vec<f32> = series.iter().filter_map(|s| s.get(bucket).copied()).collect();
+                if values.len() >= 3 {
+                    own.push(*value);
+                    zone.push(median(&mut values));
+                }
+            }
+
+            let buckets = own.len();
+            let deviations: Vec<f32> = own.iter().zip(&zone).map(|(o, z)| o - z).collect();
+            let divergent = deviations.iter().filter(|d| d.abs() > config.max_deviation).count();
+            let divergent_fraction = if buckets > 0 { divergent as f32 / buckets as f32 } else { 0.0 };
+
+            PeerComparison {
+                sensor_id: sensor_id.to_string(),
+                buckets,
+                mean_deviation: if buckets > 0 { deviations.iter().sum::<f32>() / buckets as f32 } else { 0.0 },
+                divergent_fraction,
+                correlation: pearson(&own, &zone),
+                suspected_faulty: buckets >= config.min_buckets && divergent_fraction >= config.min_fraction,
+            }
+        })
+        .collect()
+}
+
+#[cfg(feature = "std")]
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_by(f32::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(feature = "std")]
+fn pearson(xs: &[f32], ys: &[f32]) -> Option<f32> {
+    if xs.len() < 2 {
+        return None;
+    }
+    let n = xs.len() as f32;
+    let mean_x = xs.iter().sum::<f32>() / n;
+    let mean_y = ys.iter().sum::<f32>() / n;
+
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in xs.iter().zip(ys) {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+
+    if var_x <= f32::EPSILON || var_y <= f32::EPSILON {
+        return None;
+    }
+    Some(cov / (var_x * var_y).sqrt())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::rollup::MINUTE_SECONDS;
+    use crate::TemperatureReading;
+    use temp_core::Temperature;
+
+    fn zone(offsets: &[f32]) -> Vec<TemperatureStore> {
+        offsets
+            .iter()
+            .map(|offset| {
+                let store = TemperatureStore::new(10);
+                for minute in 0..30u64 {
+                    let celsius = 20.0 + (minute % 5) as f32 * 0.5 + offset;
+                    store.add_reading(TemperatureReading::with_timestamp(Temperature::new(celsius), minute * MINUTE_SECONDS));
+                }
+                store
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flags_sensor_diverging_from_peers() {
+        let stores = zone(&[0.0, 0.3, -0.2, 4.0, 0.1]);
+        let sensors: Vec<(&str, &TemperatureStore)> = ["a", "b", "c", "d", "e"].into_iter().zip(&stores).collect();
+
+        let results = compare_peers(&sensors, 30 * MINUTE_SECONDS, &DivergenceConfig::default());
+
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().filter(|r| r.sensor_id != "d").all(|r| !r.suspected_faulty));
+        let faulty = &results[3];
+        assert!(faulty.suspected_faulty);
+        assert_eq!(faulty.buckets, 30);
+        assert!((faulty.mean_deviation - 3.9).abs() < 1e-4);
+        // Offset but still tracking the room
+        assert!(faulty.correlation.unwrap() > 0.99);
+    }
+
+    #[test]
+    fn needs_three_sensors() {
+        let stores = zone(&[0.0, 5.0]);
+        let sensors: Vec<(&str, &TemperatureStore)> = ["a", "b"].into_iter().zip(&stores).collect();
+
+        let results = compare_peers(&sensors, 30 * MINUTE_SECONDS, &DivergenceConfig::default());
+        assert!(results.iter().all(|r| r.buckets == 0 && !r.suspected_faulty && r.correlation.is_none()));
+    }
+
+    #[test]
+    fn median_and_correlation() {
+        assert_eq!(median(&mut [3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&mut [4.0, 1.0, 2.0, 3.0]), 2.5);
+        assert!((pearson(&[1.0, 2.0, 3.0], &[3.0, 2.0, 1.0]).unwrap() + 1.0).abs() < 1e-6);
+        assert_eq!(pearson(&[1.0, 1.0], &[1.0, 2.0]), None);
+    }
+}
@@ -0,0 +1,375 @@
+//! Plain-text CSV logs of the readings.
+//!
+//! A [`RollingCsvWriter`] appends `timestamp,celsius` lines to files named
+//! `{prefix}-{YYYY-MM-DD}.csv` in a directory. It starts a new file at every
+//! UTC midnight (by reading timestamp) and, with a size limit, whenever the
+//! current file is full, numbering the extra files of a day
+//! `{prefix}-{YYYY-MM-DD}.1.csv` and so on. Only the newest `retain` files
+//! are kept. After a restart it appends to the newest file of the day.
+//!
+//! A [`CsvSink`] runs the writer on a thread fed by
+//! [`TemperatureStore::subscribe`], so every reading the store takes ends up
+//! in the log without anything else to run.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate};
+
+use crate::error_hook::{self, SwallowedKind};
+use crate::rollup::DAY_SECONDS;
+use crate::{TemperatureReading, TemperatureStore};
+
+const HEADER: &str = "timestamp,celsius\n";
+
+/// How often the sink thread checks whether it was stopped.
+const STOP_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvSinkConfig {
+    pub dir: PathBuf,
+    pub prefix: String,
+    /// Start a new file at UTC midnight.
+    pub daily: bool,
+    /// Start a new file once the current one would grow beyond this.
+    pub max_file_bytes: Option<u64>,
+    /// Files kept, the current one included; older ones are deleted.
+    pub retain: usize,
+}
+
+impl CsvSinkConfig {
+    /// Daily files named `readings-*.csv` in `dir`, keeping a week.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            prefix: "readings".to_string(),
+            daily: true,
+            max_file_bytes: None,
+            retain: 7,
+        }
+    }
+
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn with_max_file_bytes(mut self, max_file_bytes: u64) -> Self {
+        self.max_file_bytes = Some(max_file_bytes);
+        self
+    }
+
+    pub fn with_retain(mut self, retain: usize) -> Self {
+        self.retain = retain;
+        self
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        let invalid = |message: &str| Err(io::Error::new(io::ErrorKind::InvalidInput, message.to_string()));
+        if self.prefix.is_empty() || self.prefix.contains(['/', '\\']) {
+            return invalid("CSV file prefix must be a non-empty file name");
+        }
+        if self.retain == 0 {
+            return invalid("CSV sink must retain at least one file");
+        }
+        if self.max_file_bytes.is_some_and(|max| max <= HEADER.len() as u64) {
+            return invalid("CSV file size limit must leave room for readings");
+        }
+        Ok(())
+    }
+
+    fn file_name(&self, date: NaiveDate, part: u32) -> String {
+        match part {
+            0 => format!("{}-{}.csv", self.prefix, date.format("%Y-%m-%d")),
+            _ => format!("{}-{}.{}.csv", self.prefix, date.format("%Y-%m-%d"), part),
+        }
+    }
+
+    /// Date and part of a file this config names, `None` for other files.
+    fn parse_file_name(&self, name: &str) -> Option<(NaiveDate, u32)> {
+        let rest = name.strip_prefix(self.prefix.as_str())?.strip_prefix('-')?.strip_suffix(".csv")?;
+        let (date, part) = match rest.split_once('.') {
+            Some((date, part)) => (date, part.parse().ok().filter(|&part| part > 0)?),
+            None => (rest, 0),
+        };
+        Some((NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?, part))
+    }
+}
+
+struct CurrentFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    day: u64,
+    bytes: u64,
+}
+
+/// Appends readings to rotating CSV files; see the [module docs](self).
+pub struct RollingCsvWriter {
+    config: CsvSinkConfig,
+    current: Option<CurrentFile>,
+}
+
+impl RollingCsvWriter {
+    /// Creates the directory if needed. No file is opened until the first reading.
+    pub fn open(config: CsvSinkConfig) -> io::Result<Self> {
+        config.validate()?;
+        fs::create_dir_all(&config.dir)?;
+        Ok(Self { config, current: None })
+    }
+
+    /// The file readings currently go to, if one is open.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current.as_ref().map(|file| file.path.as_path())
+    }
+
+    pub fn write(&mut self, reading: &TemperatureReading) -> io::Result<()> {
+        let line = format!("{},{}\n", reading.timestamp, reading.temperature.celsius);
+        let day = reading.timestamp / DAY_SECONDS;
+
+        // Late readings go to the current file rather than reopening an old day
+        let next = match &self.current {
+            None => Some((day, self.latest_part(day)?.unwrap_or(0))),
+            Some(file) if self.config.daily && day > file.day => Some((day, self.latest_part(day)?.unwrap_or(0))),
+            Some(file) if self.is_full(file, line.len()) => {
+                let day = day.max(file.day);
+                Some((day, self.latest_part(day)?.map_or(0, |part| part + 1)))
+            }
+            _ => None,
+        };
+        if let Some((day, part)) = next {
+            if let Some(mut previous) = self.current.take() {
+                previous.writer.flush()?;
+            }
+            self.current = Some(self.start_file(day, part)?);
+            self.prune()?;
+        }
+
+        if let Some(file) = self.current.as_mut() {
+            file.writer.write_all(line.as_bytes())?;
+            file.bytes += line.len() as u64;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.current.as_mut() {
+            Some(file) => file.writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// A file holding at least one reading is full when `line` would take it
+    /// over the limit; a single reading always fits.
+    fn is_full(&self, file: &CurrentFile, line: usize) -> bool {
+        file.bytes > HEADER.len() as u64
+            && self.config.max_file_bytes.is_some_and(|max| file.bytes + line as u64 > max)
+    }
+
+    fn start_file(&self, day: u64, part: u32) -> io::Result<CurrentFile> {
+        let path = self.config.dir.join(self.config.file_name(date_of(day), part));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut bytes = file.metadata()?.len();
+        let mut writer = BufWriter::new(file);
+        if bytes == 0 {
+            writer.write_all(HEADER.as_bytes())?;
+            bytes = HEADER.len() as u64;
+        }
+        Ok(CurrentFile { path, writer, day, bytes })
+    }
+
+    /// This config's files in the directory, oldest first.
+    fn files(&self) -> io::Result<Vec<(NaiveDate, u32, PathBuf)>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.config.dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if let Some((date, part)) = name.to_str().and_then(|name| self.config.parse_file_name(name)) {
+                files.push((date, part, entry.path()));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    fn latest_part(&self, day: u64) -> io::Result<Option<u32>> {
+        let date = date_of(day);
+        Ok(self.files()?.into_iter().filter(|&(d, _, _)| d == date).map(|(_, part, _)| part).max())
+    }
+
+    /// Deletes the oldest files beyond `retain`, never the current one.
+    fn prune(&self) -> io::Result<()> {
+        let files = self.files()?;
+        let excess = files.len().saturating_sub(self.config.retain);
+        let current = self.current_path();
+        for (_, _, path) in files.into_iter().filter(|(_, _, path)| Some(path.as_path()) != current).take(excess) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+fn date_of(day: u64) -> NaiveDate {
+    i64::try_from(day * DAY_SECONDS)
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .map(|utc| utc.date_naive())
+        .unwrap_or_default()
+}
+
+/// Writes every reading a store takes to rotating CSV files from a
+/// background thread. Dropping the sink writes what is still queued and
+/// stops the thread.
+pub struct CsvSink {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CsvSink {
+    /// Follows `store` from now on; readings already in it are not written.
+    pub fn spawn(store: &TemperatureStore, config: CsvSinkConfig) -> io::Result<Self> {
+        let writer = RollingCsvWriter::open(config)?;
+        let readings = store.subscribe();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let stop = Arc::clone(&stop);
+            move || run(writer, readings, &stop)
+        });
+        Ok(Self { stop, thread: Some(thread) })
+    }
+}
+
+impl Drop for CsvSink {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error_hook::report(SwallowedKind::TaskFailed, "CSV sink thread panicked", &"readings were lost");
+            }
+        }
+    }
+}
+
+/// Writes readings as they arrive, flushing once the queue is drained.
+fn run(mut writer: RollingCsvWriter, readings: Receiver<TemperatureReading>, stop: &AtomicBool) {
+    let write = |writer: &mut RollingCsvWriter, reading: TemperatureReading| {
+        if let Err(e) = writer.write(&reading) {
+            let context = format!("Failed to write reading to CSV log in {}", writer.config.dir.display());
+            error_hook::report(SwallowedKind::Io, &context, &e);
+        }
+    };
+    let flush = |writer: &mut RollingCsvWriter| {
+        if let Err(e) = writer.flush() {
+            let context = format!("Failed to flush CSV log in {}", writer.config.dir.display());
+            error_hook::report(SwallowedKind::Io, &context, &e);
+        }
+    };
+    loop {
+        match readings.recv_timeout(STOP_POLL) {
+            Ok(reading) => {
+                write(&mut writer, reading);
+                readings.try_iter().for_each(|reading| write(&mut writer, reading));
+                flush(&mut writer);
+            }
+            Err(RecvTimeoutError::Timeout) if !stop.load(Ordering::Acquire) => {}
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    readings.try_iter().for_each(|reading| write(&mut writer, reading));
+    flush(&mut writer);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temp_core::Temperature;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("temp_store_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn reading(celsius: f32, timestamp: u64) -> TemperatureReading {
+        TemperatureReading::with_timestamp(Temperature::new(celsius), timestamp)
+    }
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn rotates_daily_and_keeps_the_newest_files() {
+        let dir = test_dir("csv_daily");
+        let mut writer = RollingCsvWriter::open(CsvSinkConfig::new(&dir).with_retain(2)).unwrap();
+        writer.write(&reading(21.5, 0)).unwrap();
+        writer.write(&reading(21.75, 3600)).unwrap();
+        writer.write(&reading(22.0, DAY_SECONDS)).unwrap();
+        // A late reading stays in the current file
+        writer.write(&reading(20.0, 7200)).unwrap();
+        writer.write(&reading(-3.5, 2 * DAY_SECONDS + 5)).unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(names(&dir), ["readings-1970-01-02.csv", "readings-1970-01-03.csv"]);
+        let content = fs::read_to_string(dir.join("readings-1970-01-02.csv")).unwrap();
+        assert_eq!(content, "timestamp,celsius\n86400,22\n7200,20\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_by_size_and_resumes_after_a_restart() {
+        let dir = test_dir("csv_size");
+        let config = CsvSinkConfig::new(&dir).with_prefix("probe").with_max_file_bytes(40);
+        let mut writer = RollingCsvWriter::open(config.clone()).unwrap();
+        for i in 0..3 {
+            writer.write(&reading(21.5, 1000 + i)).unwrap();
+        }
+        drop(writer);
+        assert_eq!(names(&dir), ["probe-1970-01-01.1.csv", "probe-1970-01-01.csv"]);
+
+        // The newest file still has room for one more
+        let mut writer = RollingCsvWriter::open(config).unwrap();
+        writer.write(&reading(21.5, 1003)).unwrap();
+        assert_eq!(writer.current_path(), Some(dir.join("probe-1970-01-01.1.csv").as_path()));
+        writer.write(&reading(21.5, 1004)).unwrap();
+        assert_eq!(writer.current_path(), Some(dir.join("probe-1970-01-01.2.csv").as_path()));
+        drop(writer);
+        let content = fs::read_to_string(dir.join("probe-1970-01-01.1.csv")).unwrap();
+        assert_eq!(content, "timestamp,celsius\n1002,21.5\n1003,21.5\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_unusable_configs() {
+        let dir = test_dir("csv_invalid");
+        assert!(RollingCsvWriter::open(CsvSinkConfig::new(&dir).with_retain(0)).is_err());
+        assert!(RollingCsvWriter::open(CsvSinkConfig::new(&dir).with_prefix("a/b")).is_err());
+        assert!(RollingCsvWriter::open(CsvSinkConfig::new(&dir).with_max_file_bytes(10)).is_err());
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn sink_follows_the_store() {
+        let dir = test_dir("csv_sink");
+        let store = TemperatureStore::new(10);
+        store.add_reading(reading(19.0, 10));
+        let sink = CsvSink::spawn(&store, CsvSinkConfig::new(&dir)).unwrap();
+        store.add_reading(reading(20.0, 20));
+        store.add_reading(reading(20.5, 30));
+        drop(sink);
+
+        let content = fs::read_to_string(dir.join("readings-1970-01-01.csv")).unwrap();
+        assert_eq!(content, "timestamp,celsius\n20,20\n30,20.5\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+//! Heating and cooling degree-days.
+//!
+//! Degree-days measure how much, and for how long, the temperature stayed
+//! below (heating) or above (cooling) a base temperature. They track heating
+//! and cooling energy use far better than raw averages. Each day uses the mean
+//! of its hour rollups, so up to a month of history is available. Days run
+//! from midnight UTC, or from local midnight in a given time zone.
+
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+use temp_core::Temperature;
+
+#[cfg(feature = "std")]
+use crate::local_time::{self, Tz};
+#[cfg(feature = "std")]
+use crate::rollup::RollupPoint;
+
+/// Common base temperature for heating degree-days (about 65 °F).
+pub const DEFAULT_BASE_CELSIUS: f32 = 18.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DailyDegreeDays {
+    /// Midnight starting the day, in UTC or the report's time zone.
+    pub day_start: u64,
+    pub mean: Temperature,
+    pub heating: f32,
+    pub cooling: f32,
+    /// Hours with data; days with gaps are less reliable.
+    pub hours_covered: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DegreeDayReport {
+    pub base: Temperature,
+    pub days: Vec<DailyDegreeDays>,
+    pub total_heating: f32,
+    pub total_cooling: f32,
+}
+
+#[cfg(feature = "std")]
+impl DegreeDayReport {
+    /// Builds daily totals from hour rollups, which must be sorted by start.
+    pub fn from_hourly(points: &[RollupPoint], base: Temperature) -> Self {
+        Self::from_hourly_in(points, base, Tz::UTC)
+    }
+
+    /// Like [`from_hourly`](Self::from_hourly), with days starting at local midnight in `tz`.
+    pub fn from_hourly_in(points: &[RollupPoint], base: Temperature, tz: Tz) -> Self {
+        let mut days: Vec<DailyDegreeDays> = Vec::new();
+        let mut day_sum = 0.0;
+        let mut day_count = 0;
+
+        for point in points {
+            let day_start = local_time::local_day_start(point.start, tz);
+            if days.last().is_none_or(|day| day.day_start != day_start) {
+                days.push(DailyDegreeDays {
+                    day_start,
+                    mean: point.average,
+                    heating: 0.0,
+                    cooling: 0.0,
+                    hours_covered: 0,
+                });
+                day_sum = 0.0;
+                day_count = 0;
+            }
+
+            // Weight every hour equally, however many readings it holds
+            let Some(day) = days.last_mut() else { continue };
+            day_sum += point.average.celsius;
+            day_count += 1;
+            day.hours_covered = day_count;
+            day.mean = Temperature::new(day_sum / day_count as f32);
+            day.heating = (base.celsius - day.mean.celsius).max(0.0);
+            day.cooling = (day.mean.celsius - base.celsius).max(0.0);
+        }
+
+        Self {
+            base,
+            total_heating: days.iter().map(|d| d.heating).sum(),
+            total_cooling: days.iter().map(|d| d.cooling).sum(),
+            days,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::rollup::{DAY_SECONDS, HOUR_SECONDS};
+    use crate::{TemperatureReading, TemperatureStore};
+
+    #[test]
+    fn daily_means_against_base() {
+        let store = TemperatureStore::new(10);
+
+        // Day 0 averages 10 °C, day 1 averages 25 °C
+        for hour in 0..48u64 {
+            let celsius = match (hour / 24, hour % 2) {
+                (0, 0) => 8.0,
+                (0, _) => 12.0,
+                (_, 0) => 24.0,
+                _ => 26.0,
+            };
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(celsius), hour * HOUR_SECONDS));
+        }
+
+        let report = store.degree_days(0, 2 * DAY_SECONDS, Temperature::new(DEFAULT_BASE_CELSIUS));
+        assert_eq!(report.days.len(), 2);
+        assert_eq!(report.days[0].mean.celsius, 10.0);
+        assert_eq!(report.days[0].heating, 8.0);
+        assert_eq!(report.days[0].cooling, 0.0);
+        assert_eq!(report.days[1].cooling, 7.0);
+        assert_eq!(report.days[1].hours_covered, 24);
+        assert_eq!(report.total_heating, 8.0);
+        assert_eq!(report.total_cooling, 7.0);
+
+        // A different base shifts the split
+        let report = store.degree_days(0, DAY_SECONDS, Temperature::new(5.0));
+        assert_eq!(report.days.len(), 1);
+        assert_eq!(report.total_heating, 0.0);
+        assert_eq!(report.total_cooling, 5.0);
+    }
+
+    #[test]
+    fn hours_weigh_equally() {
+        let store = TemperatureStore::new(100);
+        // Many readings in one hour must not dominate the daily mean
+        for i in 0..50 {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(30.0), i));
+        }
+        store.add_reading(TemperatureReading::with_timestamp(Temperature::new(10.0), HOUR_SECONDS));
+
+        let report = store.degree_days(0, DAY_SECONDS, Temperature::new(18.0));
+        assert_eq!(report.days[0].mean.celsius, 20.0);
+        assert_eq!(report.days[0].hours_covered, 2);
+        assert!(store.degree_days(DAY_SECONDS, 2 * DAY_SECONDS, Temperature::new(18.0)).days.is_empty());
+    }
+
+    #[test]
+    fn local_days_follow_time_zone() {
+        let store = TemperatureStore::new(10);
+        // Cold from 00:00 to 03:00 UTC, which is still the previous day in New York
+        for hour in 0..3 {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(8.0), DAY_SECONDS + hour * HOUR_SECONDS));
+        }
+
+        let utc = store.degree_days(0, 3 * DAY_SECONDS, Temperature::new(DEFAULT_BASE_CELSIUS));
+        assert_eq!(utc.days[0].day_start, DAY_SECONDS);
+
+        let new_york = store.degree_days_in(0, 3 * DAY_SECONDS, Temperature::new(DEFAULT_BASE_CELSIUS), chrono_tz::America::New_York);
+        assert_eq!(new_york.days.len(), 1);
+        assert_eq!(new_york.days[0].day_start, 5 * HOUR_SECONDS);
+        assert_eq!(new_york.total_heating, 10.0);
+    }
+}
@@ -0,0 +1,140 @@
+//! At-rest encryption for the file backend (`encryption` feature).
+//!
+//! Each record is sealed with ChaCha20-Poly1305 under a random nonce and
+//! stored as one hex line (`nonce || ciphertext`), so a stolen SD card only
+//! reveals how many readings were taken.
+
+use std::fmt;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+/// Environment variable holding the hex-encoded 256-bit key.
+pub const KEY_ENV_VAR: &str = "TEMP_STORE_KEY";
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncryptionError {
+    MissingKey,
+    InvalidKey,
+    MalformedRecord,
+    EncryptionFailed,
+    DecryptionFailed,
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::MissingKey => write!(f, "{} is not set", KEY_ENV_VAR),
+            EncryptionError::InvalidKey => write!(f, "Key must be 64 hex characters (256 bits)"),
+            EncryptionError::MalformedRecord => write!(f, "Encrypted record is malformed"),
+            EncryptionError::EncryptionFailed => write!(f, "Record could not be encrypted"),
+            EncryptionError::DecryptionFailed => write!(f, "Record could not be decrypted (wrong key or tampered data)"),
+        }
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+#[derive(Clone)]
+pub struct RecordCipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl RecordCipher {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+        }
+    }
+
+    pub fn from_hex(key: &str) -> Result<Self, EncryptionError> {
+        let bytes = decode_hex(key.trim()).ok_or(EncryptionError::InvalidKey)?;
+        let key: [u8; 32] = bytes.try_into().map_err(|_| EncryptionError::InvalidKey)?;
+        Ok(Self::new(&key))
+    }
+
+    /// Reads the key from the `TEMP_STORE_KEY` environment variable.
+    pub fn from_env() -> Result<Self, EncryptionError> {
+        let key = std::env::var(KEY_ENV_VAR).map_err(|_| EncryptionError::MissingKey)?;
+        Self::from_hex(&key)
+    }
+
+    pub fn encrypt_record(&self, plaintext: &str) -> Result<String, EncryptionError> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| EncryptionError::EncryptionFailed)?;
+
+        let mut record = encode_hex(&nonce);
+        record.push_str(&encode_hex(&ciphertext));
+        Ok(record)
+    }
+
+    pub fn decrypt_record(&self, record: &str) -> Result<String, EncryptionError> {
+        let bytes = decode_hex(record.trim()).ok_or(EncryptionError::MalformedRecord)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(EncryptionError::MalformedRecord);
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| EncryptionError::DecryptionFailed)?;
+        String::from_utf8(plaintext).map_err(|_| EncryptionError::MalformedRecord)
+    }
+}
+
+impl fmt::Debug for RecordCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print key material
+        f.write_str("RecordCipher { .. }")
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn record_round_trip() {
+        let cipher = RecordCipher::from_hex(KEY).unwrap();
+        let record = cipher.encrypt_record("{\"timestamp\":1}").unwrap();
+
+        assert!(!record.contains("timestamp"));
+        assert_eq!(cipher.decrypt_record(&record).unwrap(), "{\"timestamp\":1}");
+
+        // Random nonces: the same plaintext never produces the same record
+        assert_ne!(record, cipher.encrypt_record("{\"timestamp\":1}").unwrap());
+    }
+
+    #[test]
+    fn wrong_key_and_bad_input_are_rejected() {
+        let cipher = RecordCipher::from_hex(KEY).unwrap();
+        let other = RecordCipher::new(&[7; 32]);
+        let record = cipher.encrypt_record("secret").unwrap();
+
+        assert_eq!(other.decrypt_record(&record), Err(EncryptionError::DecryptionFailed));
+        assert_eq!(cipher.decrypt_record("zz"), Err(EncryptionError::MalformedRecord));
+        assert_eq!(RecordCipher::from_hex("abcd").unwrap_err(), EncryptionError::InvalidKey);
+    }
+}
@@ -0,0 +1,22 @@
+//! Statistics epochs.
+//!
+//! A production batch, or a freezer restocked after defrosting, should not
+//! be judged by the minimum, maximum and average of what came before it.
+//! [`TemperatureStore::start_epoch`](crate::TemperatureStore::start_epoch)
+//! starts a new epoch: stats only count readings added from then on, and the
+//! rollup buckets in progress are closed so none mixes readings of two
+//! epochs. Raw readings, rollups and the log are kept, so history queries
+//! still reach across the boundary.
+//!
+//! Epochs are kept in memory only; a reopened store starts over at epoch 0
+//! with every replayed reading in it.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Epoch {
+    /// 0 until the first epoch is started, then counting up.
+    pub id: u64,
+    /// When the epoch was started; 0 for epoch 0.
+    pub started_at: u64,
+}
@@ -0,0 +1,147 @@
+//! A process-wide hook for errors the service carries on after.
+//!
+//! Plenty of failures are handled by logging and moving on: a reading that
+//! could not be persisted, a lock poisoned by a panicked writer, a reply
+//! whose requester went away, a monitor task that died. They all go through
+//! [`report`], which hands them to the hook installed with
+//! [`set_error_hook`], so operators can count them in their own telemetry.
+//! A hook can also panic or abort to fail fast instead of carrying on:
+//!
+//! ```
+//! use temp_store::error_hook::{self, SwallowedKind};
+//!
+//! error_hook::set_error_hook(|error| {
+//!     if error.kind == SwallowedKind::LockPoisoned {
+//!         eprintln!("{}", error);
+//!         std::process::abort();
+//!     }
+//! });
+//! # error_hook::clear_error_hook();
+//! ```
+//!
+//! Without a hook, errors are printed to stderr, except poisoned locks and
+//! dropped replies, which are expected during shutdown and stay silent.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SwallowedKind {
+    /// Writing, flushing or paging in a file failed.
+    Io,
+    /// A sensor read failed.
+    Sensor,
+    /// Driving an actuator failed.
+    Actuator,
+    /// A peer sent something that could not be handled, or a connection failed.
+    Protocol,
+    /// An alert notification could not be delivered.
+    Notification,
+    /// A lock was poisoned by a panicking holder and recovered.
+    LockPoisoned,
+    /// A reply could not be sent because the requester is gone.
+    ReplyDropped,
+    /// A background task or thread panicked.
+    TaskFailed,
+}
+
+impl SwallowedKind {
+    fn printed_by_default(self) -> bool {
+        !matches!(self, SwallowedKind::LockPoisoned | SwallowedKind::ReplyDropped)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SwallowedError<'a> {
+    pub kind: SwallowedKind,
+    /// What was being done, e.g. "Failed to persist reading to kitchen.log".
+    pub context: &'a str,
+    pub error: &'a dyn fmt::Display,
+}
+
+impl fmt::Display for SwallowedError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.context, self.error)
+    }
+}
+
+impl fmt::Debug for SwallowedError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SwallowedError")
+            .field("kind", &self.kind)
+            .field("context", &self.context)
+            .field("error", &self.error.to_string())
+            .finish()
+    }
+}
+
+type Hook = Arc<dyn Fn(&SwallowedError<'_>) + Send + Sync>;
+
+static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+
+/// Replaces the hook; it is called from whichever thread hit the error.
+pub fn set_error_hook(hook: impl Fn(&SwallowedError<'_>) + Send + Sync + 'static) {
+    *HOOK.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(hook));
+}
+
+/// Goes back to printing errors to stderr.
+pub fn clear_error_hook() {
+    *HOOK.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+pub fn report(kind: SwallowedKind, context: &str, error: &dyn fmt::Display) {
+    let error = SwallowedError { kind, context, error };
+    // Called outside the lock so a hook may replace itself
+    let hook = HOOK.read().unwrap_or_else(PoisonError::into_inner).clone();
+    match hook {
+        Some(hook) => hook(&error),
+        None if kind.printed_by_default() => eprintln!("{}", error),
+        None => {}
+    }
+}
+
+/// Locks `mutex`, recovering it if a panicking holder poisoned it. The
+/// poisoning is reported once, as `context`, and then cleared.
+pub fn lock<'a, T: ?Sized>(mutex: &'a Mutex<T>, context: &str) -> MutexGuard<'a, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        report(SwallowedKind::LockPoisoned, context, &"a holder panicked");
+        mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hook_sees_reported_and_poisoned_errors() {
+        static SEEN: Mutex<Vec<(SwallowedKind, String)>> = Mutex::new(Vec::new());
+        set_error_hook(|error| {
+            if error.context.starts_with("hook test") {
+                SEEN.lock().unwrap().push((error.kind, error.to_string()));
+            }
+        });
+
+        report(SwallowedKind::Io, "hook test: flush", &"disk full");
+        let lock = Arc::new(Mutex::new(1));
+        let poisoner = Arc::clone(&lock);
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("poisoning the lock");
+        })
+        .join();
+        assert_eq!(*super::lock(&lock, "hook test: counter"), 1);
+        assert_eq!(*super::lock(&lock, "hook test: counter"), 1);
+        clear_error_hook();
+        report(SwallowedKind::Io, "hook test: after clearing", &"not seen");
+
+        assert_eq!(
+            *SEEN.lock().unwrap(),
+            [
+                (SwallowedKind::Io, "hook test: flush: disk full".to_string()),
+                (SwallowedKind::LockPoisoned, "hook test: counter: a holder panicked".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Short-horizon forecasts with Holt-Winters exponential smoothing.
+//!
+//! History is taken from the rollup tiers so it is evenly spaced: minute
+//! buckets for horizons below a day, hour buckets with a daily season for
+//! longer ones. The confidence band widens with the square root of the
+//! number of steps ahead, scaled by the model's one-step-ahead errors.
+
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+use temp_core::Temperature;
+
+use crate::rollup::Resolution;
+#[cfg(feature = "std")]
+use crate::rollup::RollupPoint;
+
+/// Fewest history points a forecast is made from.
+pub const MIN_HISTORY_POINTS: usize = 10;
+/// Longest gap, in buckets, bridged by repeating the last value. History
+/// before a longer gap is ignored.
+pub const MAX_FILLED_GAP: u64 = 5;
+/// z-score of the confidence band (95 %).
+#[cfg(feature = "std")]
+const BAND_Z: f32 = 1.96;
+
+/// Additive Holt-Winters: level, trend and an optional seasonal component.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HoltWinters {
+    pub alpha: f32,
+    pub beta: f32,
+    pub gamma: f32,
+    pub season_length: Option<usize>,
+}
+
+#[cfg(feature = "std")]
+impl HoltWinters {
+    /// Holt's linear method (no seasonality).
+    pub const fn new(alpha: f32, beta: f32) -> Self {
+        Self {
+            alpha,
+            beta,
+            gamma: 0.0,
+            season_length: None,
+        }
+    }
+
+    pub const fn with_season(mut self, season_length: usize, gamma: f32) -> Self {
+        self.season_length = Some(season_length);
+        self.gamma = gamma;
+        self
+    }
+
+    /// Fits the model to an evenly spaced series. Needs two full seasons for
+    /// the seasonal model, two points otherwise.
+    pub fn fit(&self, series: &[f32]) -> Option<FittedModel> {
+        let needed = match self.season_length {
+            Some(m) if m > 0 => 2 * m,
+            _ => 2,
+        };
+        if series.len() < needed {
+            return None;
+        }
+
+        let (mut level, mut trend, mut seasonal, start) = match self.season_length {
+            Some(m) if m > 0 => {
+                let first = series[..m].iter().sum::<f32>() / m as f32;
+                let second = series[m..2 * m].iter().sum::<f32>() / m as f32;
+                let seasonal: Vec<f32> = series[..m].iter().map(|y| y - first).collect();
+                (first, (second - first) / m as f32, seasonal, m)
+            }
+            _ => (series[0], series[1] - series[0], vec![0.0], 1),
+        };
+
+        let mut squared_error = 0.0;
+        let mut errors = 0;
+        for (t, &y) in series.iter().enumerate().skip(start) {
+            let index = t % seasonal.len();
+            let s = seasonal[index];
+            let error = y - (level + trend + s);
+            squared_error += error * error;
+            errors += 1;
+
+            let previous_level = level;
+            level = self.alpha * (y - s) + (1.0 - self.alpha) * (level + trend);
+            trend = self.beta * (level - previous_level) + (1.0 - self.beta) * trend;
+            if self.season_length.is_some() {
+                seasonal[index] = self.gamma * (y - level) + (1.0 - self.gamma) * s;
+            }
+        }
+
+        Some(FittedModel {
+            level,
+            trend,
+            seasonal,
+            next_index: series.len(),
+            sigma: (squared_error / errors.max(1) as f32).sqrt(),
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for HoltWinters {
+    fn default() -> Self {
+        Self::new(0.5, 0.1)
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FittedModel {
+    level: f32,
+    trend: f32,
+    seasonal: Vec<f32>,
+    next_index: usize,
+    sigma: f32,
+}
+
+#[cfg(feature = "std")]
+impl FittedModel {
+    /// Predicted value `steps` ahead (1 = next step).
+    pub fn predict(&self, steps: usize) -> f32 {
+        let s = self.seasonal[(self.next_index + steps - 1) % self.seasonal.len()];
+        self.level + steps as f32 * self.trend + s
+    }
+
+    /// Half-width of the confidence band `steps` ahead.
+    pub fn band(&self, steps: usize) -> f32 {
+        BAND_Z * self.sigma * (steps as f32).sqrt()
+    }
+
+    /// Root mean square of the one-step-ahead errors while fitting.
+    pub fn sigma(&self) -> f32 {
+        self.sigma
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ForecastPoint {
+    pub timestamp: u64,
+    pub predicted: Temperature,
+    pub lower: Temperature,
+    pub upper: Temperature,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Forecast {
+    pub resolution: Resolution,
+    pub points: Vec<ForecastPoint>,
+}
+
+#[cfg(feature = "std")]
+impl Forecast {
+    /// Builds a forecast from rollup points, filling short gaps with the last known value.
+    pub fn from_points(points: &[RollupPoint], resolution: Resolution, model: &HoltWinters, steps: usize) -> Option<Self> {
+        let bucket = resolution.bucket_seconds();
+        let last = points.last()?;
+
+        let run_start = points
+            .windows(2)
+            .rposition(|pair| (pair[1].start - pair[0].start) / bucket > MAX_FILLED_GAP)
+            .map_or(0, |i| i + 1);
+        let points = &points[run_start..];
+
+        let mut series = Vec::new();
+        for pair in points.windows(2) {
+            let missing = (pair[1].start - pair[0].start) / bucket;
+            series.extend(std::iter::repeat_n(pair[0].average.celsius, missing as usize));
+        }
+        series.push(last.average.celsius);
+        if series.len() < MIN_HISTORY_POINTS {
+            return None;
+        }
+
+        let fitted = model.fit(&series)?;
+        let points = (1..=steps)
+            .map(|step| {
+                let predicted = fitted.predict(step);
+                let band = fitted.band(step);
+                ForecastPoint {
+                    timestamp: last.start + step as u64 * bucket,
+                    predicted: Temperature::new(predicted),
+                    lower: Temperature::new(predicted - band),
+                    upper: Temperature::new(predicted + band),
+                }
+            })
+            .collect();
+
+        Some(Self { resolution, points })
+    }
+}
+
+impl Forecast {
+    /// First point predicted above `threshold`, for pre-emptive alerts.
+    pub fn first_above(&self, threshold: Temperature) -> Option<&ForecastPoint> {
+        self.points.iter().find(|p| p.predicted.celsius > threshold.celsius)
+    }
+
+    /// First point predicted below `threshold`.
+    pub fn first_below(&self, threshold: Temperature) -> Option<&ForecastPoint> {
+        self.points.iter().find(|p| p.predicted.celsius < threshold.celsius)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::rollup::{HOUR_SECONDS, MINUTE_SECONDS};
+    use crate::{TemperatureReading, TemperatureStore};
+
+    #[test]
+    fn holt_follows_linear_trend() {
+        let series: Vec<f32> = (0..30).map(|i| 20.0 + 0.1 * i as f32).collect();
+        let fitted = HoltWinters::default().fit(&series).unwrap();
+
+        assert!((fitted.predict(10) - 23.9).abs() < 0.01);
+        assert!(fitted.sigma() < 0.01);
+        assert!(HoltWinters::default().fit(&[1.0]).is_none());
+    }
+
+    #[test]
+    fn seasonal_model_repeats_pattern() {
+        let pattern = [10.0, 14.0, 18.0, 14.0];
+        let series: Vec<f32> = (0..20).map(|i| pattern[i % 4]).collect();
+        let fitted = HoltWinters::new(0.3, 0.05).with_season(4, 0.3).fit(&series).unwrap();
+
+        for step in 1..=4 {
+            assert!((fitted.predict(step) - pattern[(20 + step - 1) % 4]).abs() < 0.1);
+        }
+        assert!(HoltWinters::new(0.3, 0.05).with_season(4, 0.3).fit(&series[..7]).is_none());
+    }
+
+    #[test]
+    fn store_forecast_predicts_threshold_crossing() {
+        let store = TemperatureStore::new(10);
+        // Warming by 0.2 °C per minute, with a gap of two minutes
+        for minute in (0..30u64).filter(|m| !(12..14).contains(m)) {
+            let celsius = 25.0 + 0.2 * minute as f32;
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(celsius), minute * MINUTE_SECONDS));
+        }
+
+        let forecast = store.forecast(20 * MINUTE_SECONDS).unwrap();
+        assert_eq!(forecast.resolution, Resolution::Minute);
+        assert_eq!(forecast.points.len(), 20);
+        assert_eq!(forecast.points[0].timestamp, 30 * MINUTE_SECONDS);
+
+        // The trend reaches 32 °C at minute 35
+        let crossing = forecast.first_above(Temperature::new(32.0)).unwrap();
+        assert!((34..=37).contains(&(crossing.timestamp / MINUTE_SECONDS)));
+        assert!(forecast.points[19].upper.celsius - forecast.points[19].lower.celsius
+            > forecast.points[0].upper.celsius - forecast.points[0].lower.celsius);
+        assert!(forecast.first_below(Temperature::new(0.0)).is_none());
+    }
+
+    #[test]
+    fn store_forecast_needs_history() {
+        let store = TemperatureStore::new(10);
+        // Hours apart: too sparse for the minute model, too short for the hourly one
+        for hour in 0..3 {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(20.0), hour * HOUR_SECONDS));
+        }
+        assert!(store.forecast(2 * HOUR_SECONDS).is_none());
+        assert!(store.forecast(2 * 86_400).is_none());
+    }
+}
@@ -0,0 +1,148 @@
+//! Ingestion guard that sheds load during sensor storms.
+//!
+//! Readings are counted per second of their timestamp. As long as a second stays
+//! under `max_per_second` every reading is admitted; past that the configured
+//! [`SheddingPolicy`] decides which readings are still kept. Only a newer
+//! timestamp opens a new second; late or out-of-order readings count against
+//! the current one.
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SheddingPolicy {
+    /// Keep every Nth reading once the limit is exceeded.
+    EveryNth(u32),
+    /// Keep each excess reading with the given probability (0.0 - 1.0).
+    Probabilistic { keep_ratio: f32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct IngestGuard {
+    max_per_second: u32,
+    policy: SheddingPolicy,
+    window_start: u64,
+    window_count: u32,
+    excess_count: u32,
+    shed_count: u64,
+    rng: SmallRng,
+}
+
+impl IngestGuard {
+    pub fn new(max_per_second: u32, policy: SheddingPolicy) -> Self {
+        Self {
+            max_per_second,
+            policy,
+            window_start: 0,
+            window_count: 0,
+            excess_count: 0,
+            shed_count: 0,
+            rng: SmallRng::seed_from_u64(0),
+        }
+    }
+
+    /// Seed for the probabilistic policy, so shedding decisions are reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = SmallRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Returns true if a reading with this timestamp should be stored.
+    pub fn admit(&mut self, timestamp: u64) -> bool {
+        if timestamp > self.window_start {
+            self.window_start = timestamp;
+            self.window_count = 0;
+            self.excess_count = 0;
+        }
+
+        self.window_count = self.window_count.saturating_add(1);
+        if self.window_count <= self.max_per_second {
+            return true;
+        }
+
+        self.excess_count = self.excess_count.saturating_add(1);
+        let keep = match self.policy {
+            SheddingPolicy::EveryNth(n) => n > 0 && self.excess_count.is_multiple_of(n),
+            SheddingPolicy::Probabilistic { keep_ratio } => self.rng.gen::<f32>() < keep_ratio,
+        };
+
+        if !keep {
+            self.shed_count += 1;
+        }
+        keep
+    }
+
+    pub fn is_shedding(&self) -> bool {
+        self.window_count > self.max_per_second
+    }
+
+    pub fn shed_count(&self) -> u64 {
+        self.shed_count
+    }
+
+    pub fn max_per_second(&self) -> u32 {
+        self.max_per_second
+    }
+
+    pub fn policy(&self) -> SheddingPolicy {
+        self.policy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admits_everything_under_limit() {
+        let mut guard = IngestGuard::new(5, SheddingPolicy::EveryNth(2));
+
+        for _ in 0..5 {
+            assert!(guard.admit(100));
+        }
+        assert!(!guard.is_shedding());
+        assert_eq!(guard.shed_count(), 0);
+    }
+
+    #[test]
+    fn every_nth_sampling_over_limit() {
+        let mut guard = IngestGuard::new(2, SheddingPolicy::EveryNth(3));
+
+        let admitted = (0..11).filter(|_| guard.admit(100)).count();
+        // 2 under the limit, then 3 of the 9 excess readings
+        assert_eq!(admitted, 5);
+        assert_eq!(guard.shed_count(), 6);
+        assert!(guard.is_shedding());
+
+        // A new second resets the window
+        assert!(guard.admit(101));
+        assert!(!guard.is_shedding());
+    }
+
+    #[test]
+    fn interleaved_timestamps_do_not_reset_the_window() {
+        let mut guard = IngestGuard::new(2, SheddingPolicy::EveryNth(3));
+
+        // t, t-1, t, ... as sensors with skewed clocks report during a storm
+        let admitted = (0..12).filter(|i| guard.admit(100 - i % 2)).count();
+        // 2 under the limit, then 3 of the 10 excess readings
+        assert_eq!(admitted, 5);
+        assert_eq!(guard.shed_count(), 7);
+        assert!(guard.is_shedding());
+    }
+
+    #[test]
+    fn probabilistic_sampling_is_seeded() {
+        let policy = SheddingPolicy::Probabilistic { keep_ratio: 0.25 };
+        let mut a = IngestGuard::new(0, policy).with_seed(42);
+        let mut b = IngestGuard::new(0, policy).with_seed(42);
+
+        let kept_a: Vec<bool> = (0..1000).map(|_| a.admit(1)).collect();
+        let kept_b: Vec<bool> = (0..1000).map(|_| b.admit(1)).collect();
+        assert_eq!(kept_a, kept_b);
+
+        let kept = kept_a.iter().filter(|k| **k).count();
+        assert!(kept > 150 && kept < 350, "kept {}", kept);
+        assert_eq!(a.shed_count(), 1000 - kept as u64);
+    }
+}
@@ -1,8 +1,101 @@
-use std::sync::{Arc, Mutex};
-use std::time::{SystemTime, UNIX_EPOCH};
-use temp_core::Temperature;
+//! Storage and statistics of temperature readings.
+//!
+//! The readings, their stats and the math behind them build without `std`
+//! and without an allocator, so firmware can compute the same min, max and
+//! average as the host: build with `default-features = false`. The `alloc`
+//! feature adds the rollup, annotation, forecast and degree-day types that
+//! protocol messages carry, without the code that computes them. Everything
+//! else, the [`TemperatureStore`] with its rollups, log, caches and exports,
+//! needs the default `std` feature.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+#![forbid(unsafe_code)]
+// Ingestion must not take the process down: panicking shortcuts are for tests only.
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used, clippy::panic))]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
+use temp_core::{PreciseTemperature, Temperature};
 use serde::{Deserialize, Serialize};
 
+pub mod aggregate;
+pub mod backup;
+pub mod epoch;
+
+#[cfg(feature = "alloc")]
+pub mod annotation;
+#[cfg(feature = "std")]
+pub mod compare;
+#[cfg(feature = "alloc")]
+pub mod correlation;
+#[cfg(feature = "std")]
+pub mod csv_sink;
+#[cfg(feature = "alloc")]
+pub mod degree_days;
+#[cfg(feature = "std")]
+pub mod error_hook;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+#[cfg(feature = "alloc")]
+pub mod forecast;
+#[cfg(feature = "std")]
+pub mod ingest;
+#[cfg(feature = "std")]
+pub mod local_time;
+#[cfg(feature = "std")]
+pub mod outlier;
+#[cfg(feature = "std")]
+pub mod persist;
+#[cfg(feature = "std")]
+pub mod pool;
+#[cfg(feature = "std")]
+pub mod query_cache;
+#[cfg(feature = "alloc")]
+pub mod rollup;
+#[cfg(feature = "std")]
+mod store;
+#[cfg(feature = "std")]
+mod sync;
+#[cfg(feature = "std")]
+mod window;
+#[cfg(all(test, loom))]
+mod loom_tests;
+
+pub use backup::BackupFormat;
+pub use epoch::Epoch;
+
+#[cfg(feature = "alloc")]
+pub use annotation::Annotation;
+#[cfg(feature = "std")]
+pub use compare::{ComparisonPeriod, StatsDelta, WindowComparison};
+#[cfg(feature = "alloc")]
+pub use correlation::{DivergenceConfig, PeerComparison};
+#[cfg(feature = "std")]
+pub use csv_sink::{CsvSink, CsvSinkConfig, RollingCsvWriter};
+#[cfg(feature = "alloc")]
+pub use degree_days::{DailyDegreeDays, DegreeDayReport};
+#[cfg(feature = "alloc")]
+pub use forecast::{Forecast, ForecastPoint};
+#[cfg(feature = "std")]
+pub use ingest::{IngestGuard, SheddingPolicy};
+#[cfg(feature = "std")]
+pub use local_time::Tz;
+#[cfg(feature = "std")]
+pub use outlier::OutlierRejection;
+#[cfg(feature = "std")]
+pub use persist::{FileBackend, FlushPolicy, LogSnapshot};
+#[cfg(feature = "std")]
+pub use pool::{PoolStats, Pooled, ReadingPool, VecPool};
+#[cfg(feature = "std")]
+pub use query_cache::QueryCacheStats;
+#[cfg(feature = "alloc")]
+pub use rollup::{RangeQueryResult, Resolution, RollupPoint};
+#[cfg(feature = "std")]
+pub use store::TemperatureStore;
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub struct TemperatureReading {
     pub temperature: Temperature,
@@ -10,13 +103,17 @@ pub struct TemperatureReading {
 }
 
 impl TemperatureReading {
+    /// A reading taken now. A system clock set before 1970 yields timestamp 0;
+    /// use [`try_new`](Self::try_new) to treat that as an error instead.
+    #[cfg(feature = "std")]
     pub fn new(temperature: Temperature) -> Self {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        Self::try_new(temperature).unwrap_or(Self { temperature, timestamp: 0 })
+    }
 
-        Self { temperature, timestamp }
+    #[cfg(feature = "std")]
+    pub fn try_new(temperature: Temperature) -> Result<Self, SystemTimeError> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        Ok(Self { temperature, timestamp })
     }
 
     pub fn with_timestamp(temperature: Temperature, timestamp: u64) -> Self {
@@ -24,57 +121,46 @@ impl TemperatureReading {
     }
 }
 
+/// What sums of readings and running averages are kept in. An `f32` sum of
+/// millions of readings loses the fractional digits of each one added, which
+/// shows in long-run averages; the `high-precision` feature sums in `f64`.
+/// The sliding window and parallel aggregates always sum in `f64`.
+#[cfg(feature = "high-precision")]
+pub type Accumulator = f64;
+#[cfg(not(feature = "high-precision"))]
+pub type Accumulator = f32;
+
+/// The average of `count` readings summing to `sum`.
+#[allow(clippy::unnecessary_cast)] // f64 to f64 with `high-precision`
+pub(crate) fn precise_mean(sum: Accumulator, count: usize) -> PreciseTemperature {
+    PreciseTemperature::new(sum as f64 / count as f64)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TemperatureStats {
     pub min: Temperature,
     pub max: Temperature,
+    /// [`precise_average`](Self::precise_average) rounded to a reading.
     pub average: Temperature,
     pub count: usize,
+    /// Readings left out as outliers, see [`OutlierRejection`].
+    #[serde(default)]
+    pub excluded: usize,
+    /// The average before rounding to `f32`; exact to the sum's precision,
+    /// see [`Accumulator`].
+    #[serde(default)]
+    pub precise_average: PreciseTemperature,
 }
 
-pub struct TemperatureStore {
-    readings: Arc<Mutex<Vec<TemperatureReading>>>,
-    capacity: usize,
-}
-
-impl TemperatureStore {
-    pub fn new(capacity: usize) -> Self {
-        Self {
-            readings: Arc::new(Mutex::new(Vec::with_capacity(capacity))),
-            capacity,
-        }
-    }
-
-    pub fn add_reading(&self, reading: TemperatureReading) {
-        let mut readings = self.readings.lock().unwrap();
-
-        if readings.len() >= self.capacity {
-            readings.remove(0);
-        }
-
-        readings.push(reading);
-    }
-
-    pub fn get_latest(&self) -> Option<TemperatureReading> {
-        let readings = self.readings.lock().unwrap();
-        readings.last().copied()
-    }
-
-    pub fn get_all(&self) -> Vec<TemperatureReading> {
-        let readings = self.readings.lock().unwrap();
-        readings.clone()
-    }
-
-    pub fn calculate_stats(&self) -> Option<TemperatureStats> {
-        let readings = self.readings.lock().unwrap();
-
+impl TemperatureStats {
+    pub fn from_readings(readings: &[TemperatureReading]) -> Option<Self> {
         if readings.is_empty() {
             return None;
         }
 
         let mut min_temp = readings[0].temperature.celsius;
         let mut max_temp = readings[0].temperature.celsius;
-        let mut sum = 0.0;
+        let mut sum: Accumulator = 0.0;
 
         for reading in readings.iter() {
             let temp = reading.temperature.celsius;
@@ -84,157 +170,61 @@ impl TemperatureStore {
             if temp > max_temp {
                 max_temp = temp;
             }
-            sum += temp;
+            sum += Accumulator::from(temp);
         }
 
-        let average = sum / readings.len() as f32;
-
-        Some(TemperatureStats {
-            min: Temperature::new(min_temp),
-            max: Temperature::new(max_temp),
-            average: Temperature::new(average),
-            count: readings.len(),
-        })
+        Some(TemperatureStats::new(
+            Temperature::new(min_temp),
+            Temperature::new(max_temp),
+            precise_mean(sum, readings.len()),
+            readings.len(),
+        ))
     }
 
-    pub fn get_stats(&self) -> TemperatureStats {
-        self.calculate_stats().unwrap_or(TemperatureStats {
-            min: Temperature::new(0.0),
-            max: Temperature::new(0.0),
-            average: Temperature::new(0.0),
-            count: 0,
-        })
-    }
-
-    pub fn reading_count(&self) -> usize {
-        self.len()
-    }
-
-    pub fn get_recent_readings(&self, count: usize) -> Vec<TemperatureReading> {
-        let readings = self.readings.lock().unwrap();
-        let start_index = if readings.len() > count {
-            readings.len() - count
-        } else {
-            0
-        };
-        readings[start_index..].to_vec()
-    }
-
-    pub fn clear(&self) {
-        let mut readings = self.readings.lock().unwrap();
-        readings.clear();
+    /// Stats with nothing excluded, `average` rounded from `precise_average`.
+    pub fn new(min: Temperature, max: Temperature, precise_average: PreciseTemperature, count: usize) -> Self {
+        Self {
+            min,
+            max,
+            average: precise_average.to_temperature(),
+            count,
+            excluded: 0,
+            precise_average,
+        }
     }
+}
 
-    pub fn len(&self) -> usize {
-        let readings = self.readings.lock().unwrap();
-        readings.len()
-    }
+/// Capacity and memory figures for sizing a store.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct StorageInfo {
+    pub readings: usize,
+    pub capacity: usize,
+    pub minute_rollups: usize,
+    pub hour_rollups: usize,
+    pub memory_bytes: usize,
+}
 
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
-    }
+/// What a compaction did to a log.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CompactionReport {
+    pub records_kept: usize,
+    pub records_dropped: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
 
-    pub fn clone_handle(&self) -> Self {
-        Self {
-            readings: Arc::clone(&self.readings),
-            capacity: self.capacity,
-        }
+impl CompactionReport {
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread;
-
-    #[test]
-    fn store_basic_operations() {
-        let store = TemperatureStore::new(5);
-
-        assert!(store.is_empty());
-        assert_eq!(store.len(), 0);
-        assert!(store.get_latest().is_none());
-        assert!(store.calculate_stats().is_none());
-
-        let reading = TemperatureReading::new(Temperature::new(20.0));
-        store.add_reading(reading);
-
-        assert_eq!(store.len(), 1);
-        assert!(!store.is_empty());
-
-        let latest = store.get_latest().unwrap();
-        assert_eq!(latest.temperature.celsius, 20.0);
-    }
-
-    #[test]
-    fn store_circular_buffer() {
-        let store = TemperatureStore::new(3);
-
-        // Add more readings than capacity
-        for i in 0..5 {
-            let reading = TemperatureReading::new(Temperature::new(i as f32 * 10.0));
-            store.add_reading(reading);
-        }
-
-        assert_eq!(store.len(), 3);
-
-        let readings = store.get_all();
-        assert_eq!(readings.len(), 3);
-
-        // Should contain temperatures 20.0, 30.0, 40.0 (the last 3)
-        assert_eq!(readings[0].temperature.celsius, 20.0);
-        assert_eq!(readings[1].temperature.celsius, 30.0);
-        assert_eq!(readings[2].temperature.celsius, 40.0);
-    }
-
-    #[test]
-    fn store_statistics() {
-        let store = TemperatureStore::new(10);
-
-        let temps = vec![10.0, 20.0, 30.0, 40.0, 50.0];
-        for temp in temps {
-            let reading = TemperatureReading::new(Temperature::new(temp));
-            store.add_reading(reading);
-        }
-
-        let stats = store.calculate_stats().unwrap();
-        assert_eq!(stats.min.celsius, 10.0);
-        assert_eq!(stats.max.celsius, 50.0);
-        assert_eq!(stats.average.celsius, 30.0);
-        assert_eq!(stats.count, 5);
-    }
-
-    #[test]
-    fn store_thread_safety() {
-        let store = TemperatureStore::new(100);
-        let store1 = store.clone_handle();
-        let store2 = store.clone_handle();
-
-        let handle1 = thread::spawn(move || {
-            for i in 0..50 {
-                let reading = TemperatureReading::new(Temperature::new(i as f32));
-                store1.add_reading(reading);
-            }
-        });
-
-        let handle2 = thread::spawn(move || {
-            for i in 50..100 {
-                let reading = TemperatureReading::new(Temperature::new(i as f32));
-                store2.add_reading(reading);
-            }
-        });
-
-        handle1.join().unwrap();
-        handle2.join().unwrap();
-
-        assert_eq!(store.len(), 100);
-        let stats = store.calculate_stats().unwrap();
-        assert_eq!(stats.count, 100);
-        assert_eq!(stats.min.celsius, 0.0);
-        assert_eq!(stats.max.celsius, 99.0);
-    }
 
     #[test]
+    #[cfg(feature = "std")]
     fn temperature_reading_creation() {
         let temp = Temperature::new(25.0);
         let reading = TemperatureReading::new(temp);
@@ -245,4 +235,13 @@ mod tests {
         let custom_reading = TemperatureReading::with_timestamp(temp, 1234567890);
         assert_eq!(custom_reading.timestamp, 1234567890);
     }
+
+    #[test]
+    fn stats_without_a_store() {
+        let readings = [20.0, 23.0, 21.5].map(|celsius| TemperatureReading::with_timestamp(Temperature::new(celsius), 1000));
+        let stats = TemperatureStats::from_readings(&readings).unwrap();
+        assert_eq!((stats.min.celsius, stats.max.celsius, stats.average.celsius, stats.count), (20.0, 23.0, 21.5, 3));
+        assert_eq!(aggregate::stats(&readings), Some(stats));
+        assert!(TemperatureStats::from_readings(&[]).is_none());
+    }
 }
@@ -0,0 +1,116 @@
+//! Local-time bucketing for daily reports.
+//!
+//! Rollups are kept in UTC, but a site's "day" runs from its own local
+//! midnight, so daily buckets are built from hour rollups grouped by local
+//! date. Days around DST transitions have 23 or 25 hours. Zones whose offset
+//! is not a whole number of hours get their day boundaries rounded to the
+//! enclosing UTC hour bucket.
+
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, TimeZone};
+pub use chrono_tz::Tz;
+
+use crate::rollup::{HOUR_SECONDS, RollupPoint};
+
+/// Unix timestamp of the local midnight starting the day that contains `timestamp`.
+pub fn local_day_start(timestamp: u64, tz: Tz) -> u64 {
+    let Some(utc) = DateTime::from_timestamp(timestamp as i64, 0) else {
+        return timestamp;
+    };
+    day_start(utc.with_timezone(&tz).date_naive(), tz)
+}
+
+/// Unix timestamp of the local midnight ending the day that contains `timestamp`.
+pub fn local_day_end(timestamp: u64, tz: Tz) -> u64 {
+    let Some(utc) = DateTime::from_timestamp(timestamp as i64, 0) else {
+        return timestamp;
+    };
+    let date = utc.with_timezone(&tz).date_naive();
+    date.checked_add_days(Days::new(1)).map_or(u64::MAX, |next| day_start(next, tz))
+}
+
+/// First instant of `date`. Where a DST change skips midnight the day starts
+/// at the first local time that exists.
+fn day_start(date: NaiveDate, tz: Tz) -> u64 {
+    (0..24)
+        .find_map(|hour| {
+            let local = date.and_time(NaiveTime::from_hms_opt(hour, 0, 0)?);
+            tz.from_local_datetime(&local).earliest()
+        })
+        .map_or(0, |start| start.timestamp().max(0) as u64)
+}
+
+/// Combines hour rollups, sorted by start, into one point per local day.
+/// Each point starts at the local midnight; the average is weighted by reading count.
+pub fn daily_rollups(hourly: &[RollupPoint], tz: Tz) -> Vec<RollupPoint> {
+    let mut days: Vec<RollupPoint> = Vec::new();
+    for point in hourly {
+        let start = local_day_start(point.start, tz);
+        match days.last_mut() {
+            Some(day) if day.start == start => {
+                day.add_sum(point.sum, point.count);
+                if point.min.celsius < day.min.celsius {
+                    day.min = point.min;
+                }
+                if point.max.celsius > day.max.celsius {
+                    day.max = point.max;
+                }
+            }
+            _ => days.push(RollupPoint { start, ..*point }),
+        }
+    }
+    days
+}
+
+/// Hours in the local day starting at `day_start`: 24, or 23/25 on DST changes.
+pub fn hours_in_day(day_start: u64, tz: Tz) -> u64 {
+    (local_day_end(day_start, tz) - day_start) / HOUR_SECONDS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TemperatureReading, TemperatureStore};
+    use temp_core::Temperature;
+
+    const BERLIN: Tz = chrono_tz::Europe::Berlin;
+    /// 2024-03-31 00:00 in Berlin (CET), the day clocks go forward.
+    const SPRING_FORWARD_DAY: u64 = 1_711_839_600;
+    /// 2024-10-27 00:00 in Berlin (CEST), the day clocks go back.
+    const FALL_BACK_DAY: u64 = 1_729_980_000;
+
+    #[test]
+    fn day_boundaries_follow_dst() {
+        assert_eq!(local_day_start(SPRING_FORWARD_DAY + 12 * HOUR_SECONDS, BERLIN), SPRING_FORWARD_DAY);
+        assert_eq!(local_day_end(SPRING_FORWARD_DAY, BERLIN), SPRING_FORWARD_DAY + 23 * HOUR_SECONDS);
+        assert_eq!(hours_in_day(SPRING_FORWARD_DAY, BERLIN), 23);
+        assert_eq!(hours_in_day(FALL_BACK_DAY, BERLIN), 25);
+        assert_eq!(hours_in_day(FALL_BACK_DAY + 25 * HOUR_SECONDS, BERLIN), 24);
+
+        // UTC days are plain multiples of a day
+        assert_eq!(local_day_start(SPRING_FORWARD_DAY, Tz::UTC), SPRING_FORWARD_DAY - 23 * HOUR_SECONDS);
+    }
+
+    #[test]
+    fn daily_rollups_align_to_local_midnight() {
+        let store = TemperatureStore::new(10);
+        // Two local days around the spring transition, 23 + 24 hours
+        for hour in 0..47 {
+            let celsius = if hour < 23 { 10.0 } else { 20.0 };
+            store.add_reading(TemperatureReading::with_timestamp(
+                Temperature::new(celsius),
+                SPRING_FORWARD_DAY + hour * HOUR_SECONDS,
+            ));
+        }
+
+        let days = store.daily_rollups(SPRING_FORWARD_DAY, SPRING_FORWARD_DAY + 47 * HOUR_SECONDS, BERLIN);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].start, SPRING_FORWARD_DAY);
+        assert_eq!(days[0].count, 23);
+        assert_eq!(days[0].average.celsius, 10.0);
+        assert_eq!(days[1].start, SPRING_FORWARD_DAY + 23 * HOUR_SECONDS);
+        assert_eq!(days[1].max.celsius, 20.0);
+
+        // In UTC the same readings straddle three days
+        assert_eq!(store.daily_rollups(0, u64::MAX, Tz::UTC).len(), 3);
+    }
+}
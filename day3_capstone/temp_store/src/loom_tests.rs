@@ -0,0 +1,100 @@
+//! Model-checked tests of the store's locking.
+//!
+//! Only built with `--cfg loom`, which also replaces the store's locks, so
+//! run them on their own:
+//! `RUSTFLAGS="--cfg loom" cargo test -p temp_store --features loom --lib loom_tests`.
+
+use loom::thread;
+use temp_core::Temperature;
+
+use crate::{TemperatureReading, TemperatureStore};
+
+fn reading(celsius: f32, timestamp: u64) -> TemperatureReading {
+    TemperatureReading::with_timestamp(Temperature::new(celsius), timestamp)
+}
+
+#[test]
+fn concurrent_writers_lose_no_readings() {
+    loom::model(|| {
+        let store = TemperatureStore::new(10);
+        let writers: Vec<_> = (0..2)
+            .map(|i| {
+                let store = store.clone_handle();
+                thread::spawn(move || store.add_reading(reading(20.0 + i as f32, 60 * i)))
+            })
+            .collect();
+        for writer in writers {
+            assert!(writer.join().unwrap());
+        }
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.query_range_at(0, 1000, crate::Resolution::Minute).points.len(), 2);
+    });
+}
+
+#[test]
+fn stats_see_whole_readings() {
+    loom::model(|| {
+        let store = TemperatureStore::new(10);
+        store.add_reading(reading(10.0, 0));
+
+        let writer = {
+            let store = store.clone_handle();
+            thread::spawn(move || store.add_reading(reading(30.0, 1)))
+        };
+
+        // Either before or after the write, never in between
+        let stats = store.calculate_stats().unwrap();
+        match stats.count {
+            1 => assert_eq!(stats.average.celsius, 10.0),
+            2 => assert_eq!(stats.average.celsius, 20.0),
+            count => panic!("unexpected count {}", count),
+        }
+
+        writer.join().unwrap();
+        assert_eq!(store.calculate_stats().unwrap().count, 2);
+    });
+}
+
+#[test]
+fn eviction_under_contention_keeps_capacity() {
+    loom::model(|| {
+        let store = TemperatureStore::new(2);
+        store.add_reading(reading(1.0, 0));
+
+        let writers: Vec<_> = (1..3)
+            .map(|i| {
+                let store = store.clone_handle();
+                thread::spawn(move || store.add_reading(reading(i as f32, i)))
+            })
+            .collect();
+        let latest = store.get_latest();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert!(latest.is_some());
+        assert_eq!(store.len(), 2);
+        assert!(store.get_all().iter().all(|r| r.timestamp > 0));
+    });
+}
+
+#[test]
+fn subscribers_get_every_reading_after_subscribing() {
+    loom::model(|| {
+        let store = TemperatureStore::new(10);
+        let writer = {
+            let store = store.clone_handle();
+            thread::spawn(move || store.add_reading(reading(20.0, 1)))
+        };
+        let receiver = store.subscribe();
+        writer.join().unwrap();
+        store.add_reading(reading(21.0, 2));
+
+        // The racing reading arrives once or not at all, depending on who
+        // took the lock first; the one after subscribing always arrives
+        let delivered: Vec<u64> = receiver.try_iter().map(|reading| reading.timestamp).collect();
+        assert!(delivered == [1, 2] || delivered == [2], "{:?}", delivered);
+        assert_eq!(store.len(), 2);
+    });
+}
@@ -0,0 +1,142 @@
+//! Keeping glitched samples out of the stats.
+//!
+//! A single bad sample, such as a 400 °C spike from a loose wire, would
+//! otherwise become the window's max and drag its average. With an
+//! [`OutlierRejection`] set, stats leave out readings outside a physically
+//! plausible range, then readings further than `max_deviations` standard
+//! deviations from the mean of the rest. The readings themselves are kept,
+//! and [`TemperatureStats::excluded`] says how many were left out.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use temp_core::{PreciseTemperature, Temperature};
+
+use crate::{TemperatureReading, TemperatureStats};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct OutlierRejection {
+    /// Lowest and highest plausible temperature in °C.
+    #[serde(default)]
+    pub plausible_range: Option<(f32, f32)>,
+    /// Standard deviations from the mean beyond which a reading is left out.
+    #[serde(default)]
+    pub max_deviations: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvalidRejection {
+    EmptyRange,
+    NonPositiveDeviations,
+}
+
+impl fmt::Display for InvalidRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidRejection::EmptyRange => write!(f, "Plausible range must have min below max"),
+            InvalidRejection::NonPositiveDeviations => write!(f, "Max deviations must be a positive number"),
+        }
+    }
+}
+
+impl std::error::Error for InvalidRejection {}
+
+impl OutlierRejection {
+    /// Leaves out readings outside `min..=max`.
+    pub fn plausible(min: f32, max: f32) -> Self {
+        Self { plausible_range: Some((min, max)), max_deviations: None }
+    }
+
+    /// Leaves out readings more than `deviations` standard deviations from the mean.
+    pub fn sigma(deviations: f32) -> Self {
+        Self { plausible_range: None, max_deviations: Some(deviations) }
+    }
+
+    pub fn with_sigma(mut self, deviations: f32) -> Self {
+        self.max_deviations = Some(deviations);
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), InvalidRejection> {
+        if let Some((min, max)) = self.plausible_range {
+            if min.is_nan() || max.is_nan() || min >= max {
+                return Err(InvalidRejection::EmptyRange);
+            }
+        }
+        if let Some(deviations) = self.max_deviations {
+            if !(deviations > 0.0 && deviations.is_finite()) {
+                return Err(InvalidRejection::NonPositiveDeviations);
+            }
+        }
+        Ok(())
+    }
+
+    fn plausible_reading(&self, celsius: f32) -> bool {
+        self.plausible_range.is_none_or(|(min, max)| (min..=max).contains(&celsius))
+    }
+
+    /// Stats over `readings` without the outliers; `None` if none is left.
+    pub fn stats(&self, readings: &[TemperatureReading]) -> Option<TemperatureStats> {
+        let plausible: Vec<f32> = readings
+            .iter()
+            .map(|r| r.temperature.celsius)
+            .filter(|&c| self.plausible_reading(c))
+            .collect();
+
+        let kept: Vec<f32> = match self.max_deviations {
+            Some(deviations) if plausible.len() > 1 => {
+                let n = plausible.len() as f64;
+                let mean = plausible.iter().map(|&c| f64::from(c)).sum::<f64>() / n;
+                let variance = plausible.iter().map(|&c| (f64::from(c) - mean).powi(2)).sum::<f64>() / n;
+                let limit = f64::from(deviations) * variance.sqrt();
+                plausible.into_iter().filter(|&c| (f64::from(c) - mean).abs() <= limit).collect()
+            }
+            _ => plausible,
+        };
+
+        let min = kept.iter().copied().reduce(f32::min)?;
+        let max = kept.iter().copied().reduce(f32::max)?;
+        let average = kept.iter().map(|&c| f64::from(c)).sum::<f64>() / kept.len() as f64;
+        Some(TemperatureStats {
+            excluded: readings.len() - kept.len(),
+            ..TemperatureStats::new(Temperature::new(min), Temperature::new(max), PreciseTemperature::new(average), kept.len())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TemperatureStore;
+
+    #[test]
+    fn a_glitch_does_not_become_the_max() {
+        let store = TemperatureStore::new(100);
+        for (i, celsius) in [21.0, 21.5, 22.0, 400.0, 21.8, 22.2, 21.6, 21.9, 22.1, 21.7].into_iter().enumerate() {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(celsius), i as u64));
+        }
+        assert_eq!(store.get_stats().max.celsius, 400.0);
+
+        store.set_outlier_rejection(Some(OutlierRejection::plausible(-40.0, 125.0)));
+        let stats = store.get_stats();
+        assert_eq!((stats.max.celsius, stats.count, stats.excluded), (22.2, 9, 1));
+
+        store.set_outlier_rejection(Some(OutlierRejection::sigma(2.0)));
+        let stats = store.get_stats();
+        assert_eq!((stats.max.celsius, stats.excluded), (22.2, 1));
+        assert!((stats.average.celsius - 21.756).abs() < 0.01);
+
+        // Rejection only affects stats
+        assert_eq!(store.len(), 10);
+        store.set_outlier_rejection(None);
+        assert_eq!(store.get_stats().excluded, 0);
+    }
+
+    #[test]
+    fn validates_the_limits() {
+        assert_eq!(OutlierRejection::plausible(50.0, 0.0).validate(), Err(InvalidRejection::EmptyRange));
+        assert_eq!(OutlierRejection::sigma(0.0).validate(), Err(InvalidRejection::NonPositiveDeviations));
+        assert_eq!(OutlierRejection::sigma(f32::NAN).validate(), Err(InvalidRejection::NonPositiveDeviations));
+        assert!(OutlierRejection::plausible(-40.0, 125.0).with_sigma(3.0).validate().is_ok());
+    }
+}
@@ -0,0 +1,562 @@
+//! Append-only file backend for readings.
+//!
+//! Readings (or any other serializable records, such as alert history) are
+//! written as JSON lines. How often the data is pushed to the
+//! disk is governed by a [`FlushPolicy`]: flushing after every write is the
+//! most durable, batching writes spares SD cards and keeps ingestion fast.
+//!
+//! With the `encryption` feature each line can be sealed by a
+//! [`RecordCipher`](crate::encryption::RecordCipher) instead.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error_hook::{self, SwallowedKind};
+use crate::{CompactionReport, TemperatureReading};
+#[cfg(feature = "encryption")]
+use crate::encryption::RecordCipher;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Flush and sync after every reading.
+    EveryWrite,
+    /// Flush once this many readings are pending.
+    EveryN(u32),
+    /// Flush when the oldest pending reading is older than this.
+    Interval(Duration),
+}
+
+pub struct FileBackend<T = TemperatureReading> {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    policy: FlushPolicy,
+    pending: u32,
+    /// When the oldest record not yet flushed was appended.
+    oldest_pending: Option<Instant>,
+    /// Times [`FlushPolicy::Interval`]; `Instant::now` unless replaced.
+    clock: fn() -> Instant,
+    #[cfg(feature = "encryption")]
+    cipher: Option<RecordCipher>,
+    compacting: bool,
+    records: PhantomData<fn(&T)>,
+}
+
+/// The records in a log up to the moment the snapshot was taken.
+///
+/// Reading it does not touch the backend, so it can be done without holding
+/// the store's lock while new records keep being appended. The snapshot
+/// keeps the log file open, so a compaction replacing the log meanwhile
+/// does not change what it reads.
+pub struct LogSnapshot<T> {
+    file: File,
+    len: u64,
+    /// Records not yet written to the file when the snapshot was taken.
+    buffered: Vec<u8>,
+    #[cfg(feature = "encryption")]
+    cipher: Option<RecordCipher>,
+    records: PhantomData<fn(&T)>,
+}
+
+/// A compaction in progress.
+///
+/// Only the part of the log written before [`FileBackend::begin_compaction`]
+/// is rewritten, into a file next to the log, so the backend can keep
+/// appending meanwhile. [`FileBackend::finish_compaction`] then copies the
+/// records appended since and swaps the files.
+pub struct Compaction<T> {
+    snapshot: LogSnapshot<T>,
+    temp_path: PathBuf,
+    kept: usize,
+    dropped: usize,
+}
+
+impl FileBackend {
+    /// Reads every reading stored in the log at `path`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Vec<TemperatureReading>> {
+        Self::load_records(path)
+    }
+
+    /// Reads every reading stored in an encrypted log.
+    #[cfg(feature = "encryption")]
+    pub fn load_encrypted(path: impl AsRef<Path>, cipher: &RecordCipher) -> io::Result<Vec<TemperatureReading>> {
+        Self::load_records_encrypted(path, cipher)
+    }
+
+    /// Reads readings from JSON lines, e.g. a backup.
+    pub(crate) fn load_from(reader: impl BufRead) -> io::Result<Vec<TemperatureReading>> {
+        Self::read_lines(reader, |line| Ok(line.to_string()))
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> FileBackend<T> {
+    /// Opens (or creates) the log at `path` for appending.
+    pub fn open(path: impl AsRef<Path>, policy: FlushPolicy) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+            policy,
+            pending: 0,
+            oldest_pending: None,
+            clock: Instant::now,
+            #[cfg(feature = "encryption")]
+            cipher: None,
+            compacting: false,
+            records: PhantomData,
+        })
+    }
+
+    /// Opens the log at `path`, encrypting every record written from now on.
+    #[cfg(feature = "encryption")]
+    pub fn open_encrypted(path: impl AsRef<Path>, policy: FlushPolicy, cipher: RecordCipher) -> io::Result<Self> {
+        let mut backend = Self::open(path, policy)?;
+        backend.cipher = Some(cipher);
+        Ok(backend)
+    }
+
+    /// Reads every record stored in the log at `path`.
+    pub fn load_records(path: impl AsRef<Path>) -> io::Result<Vec<T>> {
+        Self::load_lines(path, |line| Ok(line.to_string()))
+    }
+
+    /// Reads every record stored in an encrypted log.
+    #[cfg(feature = "encryption")]
+    pub fn load_records_encrypted(path: impl AsRef<Path>, cipher: &RecordCipher) -> io::Result<Vec<T>> {
+        Self::load_lines(path, |line| {
+            cipher
+                .decrypt_record(line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+    }
+
+    fn load_lines(
+        path: impl AsRef<Path>,
+        decode: impl Fn(&str) -> io::Result<String>,
+    ) -> io::Result<Vec<T>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        Self::read_lines(BufReader::new(file), decode)
+    }
+
+    fn read_lines(reader: impl BufRead, decode: impl Fn(&str) -> io::Result<String>) -> io::Result<Vec<T>> {
+        let mut records = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str(&decode(&line)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    pub fn append(&mut self, record: &T) -> io::Result<()> {
+        let line = serde_json::to_string(record)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        #[cfg(feature = "encryption")]
+        let line = match &self.cipher {
+            Some(cipher) => cipher
+                .encrypt_record(&line)
+                .map_err(io::Error::other)?,
+            None => line,
+        };
+        writeln!(self.writer, "{}", line)?;
+        self.pending += 1;
+        self.oldest_pending.get_or_insert_with(self.clock);
+
+        if self.needs_flush() {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes buffered records out and captures the log as it is now. The
+    /// records are not synced, so they still count as pending for the
+    /// flush policy.
+    pub fn snapshot(&mut self) -> io::Result<LogSnapshot<T>> {
+        self.writer.flush()?;
+        self.peek()
+    }
+
+    /// Captures the log without writing to it: the part already written
+    /// out, plus a copy of the records the flush policy still holds back.
+    pub fn peek(&self) -> io::Result<LogSnapshot<T>> {
+        let file = File::open(&self.path)?;
+        Ok(LogSnapshot {
+            len: file.metadata()?.len(),
+            file,
+            buffered: self.writer.buffer().to_vec(),
+            #[cfg(feature = "encryption")]
+            cipher: self.cipher.clone(),
+            records: PhantomData,
+        })
+    }
+
+    /// Starts rewriting the log; see [`Compaction`]. Only one compaction
+    /// can run at a time.
+    pub fn begin_compaction(&mut self) -> io::Result<Compaction<T>> {
+        if self.compacting {
+            return Err(io::Error::other("a compaction is already running"));
+        }
+        let snapshot = self.snapshot()?;
+        self.compacting = true;
+
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".compact");
+        Ok(Compaction {
+            snapshot,
+            temp_path: temp_path.into(),
+            kept: 0,
+            dropped: 0,
+        })
+    }
+
+    /// Appends the records written since `compaction` began to the rewritten
+    /// log and replaces the log with it.
+    pub fn finish_compaction(&mut self, compaction: Compaction<T>) -> io::Result<CompactionReport> {
+        self.compacting = false;
+        self.flush()?;
+
+        let mut log = File::open(&self.path)?;
+        let bytes_before = log.metadata()?.len();
+        log.seek(SeekFrom::Start(compaction.snapshot.len))?;
+        let mut rewritten = OpenOptions::new().append(true).open(&compaction.temp_path)?;
+        io::copy(&mut log, &mut rewritten)?;
+        rewritten.sync_all()?;
+        let bytes_after = rewritten.metadata()?.len();
+
+        fs::rename(&compaction.temp_path, &self.path)?;
+        sync_dir_of(&self.path)?;
+        let file = OpenOptions::new().append(true).open(&self.path)?;
+        self.writer = BufWriter::new(file);
+
+        Ok(CompactionReport {
+            records_kept: compaction.kept,
+            records_dropped: compaction.dropped,
+            bytes_before,
+            bytes_after,
+        })
+    }
+
+    /// Gives up on `compaction`, leaving the log as it is.
+    pub fn cancel_compaction(&mut self, compaction: Compaction<T>) {
+        self.compacting = false;
+        match fs::remove_file(&compaction.temp_path) {
+            // NotFound: cancelled before the rewrite created it
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                let context = format!("Failed to remove {}", compaction.temp_path.display());
+                error_hook::report(SwallowedKind::Io, &context, &e);
+            }
+        }
+    }
+}
+
+/// Syncs the directory holding `path`, which a rename into it only
+/// survives a crash after.
+fn sync_dir_of(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    // Windows cannot open a directory as a file to sync it
+    if cfg!(unix) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+impl<T: DeserializeOwned> LogSnapshot<T> {
+    /// Calls `visit` with every record and the line it was stored as.
+    pub fn for_each(&self, mut visit: impl FnMut(&str, T) -> io::Result<()>) -> io::Result<()> {
+        let mut log = &self.file;
+        log.seek(SeekFrom::Start(0))?;
+        for line in BufReader::new(log.take(self.len).chain(self.buffered.as_slice())).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str(&self.decode(&line)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            visit(&line, record)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "encryption")]
+    fn decode(&self, line: &str) -> io::Result<String> {
+        match &self.cipher {
+            Some(cipher) => cipher
+                .decrypt_record(line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            None => Ok(line.to_string()),
+        }
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    fn decode(&self, line: &str) -> io::Result<String> {
+        Ok(line.to_string())
+    }
+}
+
+impl<T: DeserializeOwned> Compaction<T> {
+    /// Writes the records matching `keep` to the new log. Does not touch
+    /// the backend, so it runs without holding the store's lock.
+    pub fn rewrite(&mut self, keep: impl Fn(&T) -> bool) -> io::Result<()> {
+        let mut rewritten = BufWriter::new(File::create(&self.temp_path)?);
+        let (mut kept, mut dropped) = (0, 0);
+        self.snapshot.for_each(|line, record| {
+            if keep(&record) {
+                // Copied as written, so encrypted lines stay encrypted
+                writeln!(rewritten, "{}", line)?;
+                kept += 1;
+            } else {
+                dropped += 1;
+            }
+            Ok(())
+        })?;
+        self.kept = kept;
+        self.dropped = dropped;
+        rewritten.flush()
+    }
+}
+
+impl<T> FileBackend<T> {
+    /// True if the policy asks for pending writes to be flushed now.
+    pub fn needs_flush(&self) -> bool {
+        if self.pending == 0 {
+            return false;
+        }
+        match self.policy {
+            FlushPolicy::EveryWrite => true,
+            FlushPolicy::EveryN(n) => self.pending >= n,
+            FlushPolicy::Interval(interval) => self
+                .oldest_pending
+                .is_some_and(|since| (self.clock)().saturating_duration_since(since) >= interval),
+        }
+    }
+
+    /// Writes buffered records out and syncs them to the disk.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_buffered()?;
+        self.writer.get_ref().sync_data()
+    }
+
+    /// Writes buffered records out without waiting for the disk, returning
+    /// the log's file to sync, e.g. once the caller let go of a lock.
+    pub fn write_pending(&mut self) -> io::Result<File> {
+        self.write_buffered()?;
+        self.writer.get_ref().try_clone()
+    }
+
+    fn write_buffered(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.pending = 0;
+        self.oldest_pending = None;
+        Ok(())
+    }
+
+    pub fn pending_writes(&self) -> u32 {
+        self.pending
+    }
+
+    /// Replaces the clock the interval policy is timed with, e.g. by one
+    /// a test can pause. Records already pending count from the new clock's now.
+    pub fn set_clock(&mut self, clock: fn() -> Instant) {
+        self.clock = clock;
+        if self.oldest_pending.is_some() {
+            self.oldest_pending = Some(clock());
+        }
+    }
+
+    pub fn policy(&self) -> FlushPolicy {
+        self.policy
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<T> Drop for FileBackend<T> {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            let context = format!("Failed to flush {} on close", self.path.display());
+            error_hook::report(SwallowedKind::Io, &context, &e);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("temp_store_{}_{}.log", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temp_core::Temperature;
+
+    #[test]
+    fn backend_round_trip() {
+        let path = test_path("round_trip");
+        {
+            let mut backend = FileBackend::open(&path, FlushPolicy::EveryWrite).unwrap();
+            backend.append(&TemperatureReading::with_timestamp(Temperature::new(21.0), 10)).unwrap();
+            backend.append(&TemperatureReading::with_timestamp(Temperature::new(22.0), 20)).unwrap();
+            assert_eq!(backend.pending_writes(), 0);
+        }
+
+        let readings = FileBackend::load(&path).unwrap();
+        assert_eq!(readings.len(), 2);
+        assert_eq!(readings[1].temperature.celsius, 22.0);
+        assert_eq!(readings[1].timestamp, 20);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn every_n_policy_batches_writes() {
+        let path = test_path("every_n");
+        let mut backend = FileBackend::open(&path, FlushPolicy::EveryN(3)).unwrap();
+
+        for i in 0..2 {
+            backend.append(&TemperatureReading::with_timestamp(Temperature::new(20.0), i)).unwrap();
+        }
+        assert_eq!(backend.pending_writes(), 2);
+        assert!(FileBackend::load(&path).unwrap().is_empty());
+
+        backend.append(&TemperatureReading::with_timestamp(Temperature::new(20.0), 2)).unwrap();
+        assert_eq!(backend.pending_writes(), 0);
+        assert_eq!(FileBackend::load(&path).unwrap().len(), 3);
+
+        drop(backend);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn interval_policy_waits_for_explicit_flush() {
+        let path = test_path("interval");
+        let mut backend = FileBackend::open(&path, FlushPolicy::Interval(Duration::from_secs(3600))).unwrap();
+
+        backend.append(&TemperatureReading::with_timestamp(Temperature::new(20.0), 1)).unwrap();
+        assert!(!backend.needs_flush());
+        assert_eq!(backend.pending_writes(), 1);
+
+        backend.flush().unwrap();
+        assert_eq!(FileBackend::load(&path).unwrap().len(), 1);
+
+        drop(backend);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn interval_policy_counts_from_the_oldest_pending_record() {
+        let path = test_path("interval_oldest");
+        let interval = Duration::from_millis(50);
+        let mut backend = FileBackend::open(&path, FlushPolicy::Interval(interval)).unwrap();
+
+        // An idle log does not flush the first record after the pause at once
+        std::thread::sleep(interval * 2);
+        backend.append(&TemperatureReading::with_timestamp(Temperature::new(20.0), 1)).unwrap();
+        assert_eq!(backend.pending_writes(), 1);
+        assert!(!backend.needs_flush());
+
+        std::thread::sleep(interval * 2);
+        assert!(backend.needs_flush());
+        backend.append(&TemperatureReading::with_timestamp(Temperature::new(20.0), 2)).unwrap();
+        assert_eq!(backend.pending_writes(), 0);
+        assert!(!backend.needs_flush());
+
+        drop(backend);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compaction_keeps_records_appended_meanwhile() {
+        let path = test_path("compaction");
+        let mut backend = FileBackend::open(&path, FlushPolicy::EveryN(100)).unwrap();
+        for ts in 0..4 {
+            backend.append(&TemperatureReading::with_timestamp(Temperature::new(20.0), ts)).unwrap();
+        }
+
+        let mut compaction = backend.begin_compaction().unwrap();
+        assert!(backend.begin_compaction().is_err());
+        backend.append(&TemperatureReading::with_timestamp(Temperature::new(21.0), 0)).unwrap();
+        compaction.rewrite(|r: &TemperatureReading| r.timestamp >= 2).unwrap();
+        let report = backend.finish_compaction(compaction).unwrap();
+        assert_eq!((report.records_kept, report.records_dropped), (2, 2));
+
+        backend.append(&TemperatureReading::with_timestamp(Temperature::new(22.0), 9)).unwrap();
+        backend.flush().unwrap();
+        let timestamps: Vec<u64> = FileBackend::load(&path).unwrap().iter().map(|r| r.timestamp).collect();
+        assert_eq!(timestamps, vec![2, 3, 0, 9]);
+
+        drop(backend);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn snapshot_outlives_compaction() {
+        let path = test_path("snapshot_compaction");
+        let mut backend = FileBackend::open(&path, FlushPolicy::EveryWrite).unwrap();
+        for ts in 0..4 {
+            backend.append(&TemperatureReading::with_timestamp(Temperature::new(20.0), ts)).unwrap();
+        }
+
+        let snapshot = backend.peek().unwrap();
+        let mut compaction = backend.begin_compaction().unwrap();
+        compaction.rewrite(|r: &TemperatureReading| r.timestamp == 3).unwrap();
+        backend.finish_compaction(compaction).unwrap();
+        backend.append(&TemperatureReading::with_timestamp(Temperature::new(21.0), 9)).unwrap();
+
+        let mut timestamps = Vec::new();
+        snapshot
+            .for_each(|_, r: TemperatureReading| {
+                timestamps.push(r.timestamp);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(timestamps, vec![0, 1, 2, 3]);
+
+        drop(backend);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_backend_round_trip() {
+        let path = test_path("encrypted");
+        let cipher = RecordCipher::new(&[42; 32]);
+        {
+            let mut backend = FileBackend::open_encrypted(&path, FlushPolicy::EveryWrite, cipher.clone()).unwrap();
+            backend.append(&TemperatureReading::with_timestamp(Temperature::new(21.5), 99)).unwrap();
+        }
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("timestamp"));
+        assert!(FileBackend::load(&path).is_err());
+
+        let readings = FileBackend::load_encrypted(&path, &cipher).unwrap();
+        assert_eq!(readings[0].temperature.celsius, 21.5);
+        assert!(FileBackend::load_encrypted(&path, &RecordCipher::new(&[1; 32])).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+//! Reusable buffers for reading batches and encoded messages.
+//!
+//! At high sample and request rates, the same few kinds of `Vec` are
+//! allocated and freed over and over: batches of readings copied out of a
+//! store, buffers a response is encoded into. A [`VecPool`] keeps the
+//! buffers handed back to it and gives them out again cleared, with their
+//! capacity intact. Its [`PoolStats`] tell how often a buffer was reused; a
+//! low hit rate means buffers aren't coming back, or more are in flight
+//! than the pool keeps.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{fmt, mem};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error_hook;
+use crate::TemperatureReading;
+
+pub type ReadingPool = VecPool<TemperatureReading>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolStats {
+    /// Buffers given out that were reused.
+    pub hits: u64,
+    /// Buffers given out that had to be allocated.
+    pub misses: u64,
+    /// Buffers handed back but dropped, because the pool was full or they had
+    /// grown past its capacity limit.
+    pub discarded: u64,
+    /// Buffers waiting to be reused.
+    pub pooled: usize,
+}
+
+impl PoolStats {
+    /// Share of buffers given out that were reused, 0 before the first.
+    pub fn hit_rate(&self) -> f64 {
+        let taken = self.hits + self.misses;
+        if taken == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / taken as f64
+    }
+}
+
+struct PoolInner<T> {
+    free: Mutex<Vec<Vec<T>>>,
+    max_pooled: usize,
+    max_capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    discarded: AtomicU64,
+}
+
+/// A pool of `Vec<T>`. Clones share the pool.
+pub struct VecPool<T> {
+    inner: Arc<PoolInner<T>>,
+}
+
+impl<T> Clone for VecPool<T> {
+    fn clone(&self) -> Self {
+        Self { inner: Arc::clone(&self.inner) }
+    }
+}
+
+impl<T> fmt::Debug for VecPool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VecPool")
+            .field("max_pooled", &self.inner.max_pooled)
+            .field("max_capacity", &self.inner.max_capacity)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl<T> Default for VecPool<T> {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MAX_POOLED, Self::DEFAULT_MAX_CAPACITY)
+    }
+}
+
+impl<T> VecPool<T> {
+    pub const DEFAULT_MAX_POOLED: usize = 32;
+    pub const DEFAULT_MAX_CAPACITY: usize = 64 * 1024;
+
+    /// Keeps up to `max_pooled` buffers of at most `max_capacity` elements;
+    /// larger ones are freed when handed back, so one huge batch doesn't pin
+    /// its memory for good.
+    pub fn new(max_pooled: usize, max_capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                free: Mutex::new(Vec::new()),
+                max_pooled,
+                max_capacity,
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                discarded: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// An empty buffer, reused if one is pooled. Hand it back with
+    /// [`give`](Self::give) once done.
+    pub fn take(&self) -> Vec<T> {
+        let reused = error_hook::lock(&self.inner.free, "Recovered a buffer pool lock").pop();
+        match reused {
+            Some(buffer) => {
+                self.inner.hits.fetch_add(1, Ordering::Relaxed);
+                buffer
+            }
+            None => {
+                self.inner.misses.fetch_add(1, Ordering::Relaxed);
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn give(&self, mut buffer: Vec<T>) {
+        // Never allocated, e.g. taken out of a `Pooled`; nothing to reuse
+        if buffer.capacity() == 0 {
+            return;
+        }
+        if buffer.capacity() <= self.inner.max_capacity {
+            buffer.clear();
+            let mut free = error_hook::lock(&self.inner.free, "Recovered a buffer pool lock");
+            if free.len() < self.inner.max_pooled {
+                free.push(buffer);
+                return;
+            }
+        }
+        self.inner.discarded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Like [`take`](Self::take), handing the buffer back when dropped.
+    pub fn get(&self) -> Pooled<T> {
+        Pooled { buffer: self.take(), pool: self.clone() }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            discarded: self.inner.discarded.load(Ordering::Relaxed),
+            pooled: error_hook::lock(&self.inner.free, "Recovered a buffer pool lock").len(),
+        }
+    }
+}
+
+/// A buffer from a [`VecPool`] that goes back to it when dropped.
+pub struct Pooled<T> {
+    buffer: Vec<T>,
+    pool: VecPool<T>,
+}
+
+impl<T> Pooled<T> {
+    /// Keeps the buffer instead of handing it back.
+    pub fn into_inner(mut self) -> Vec<T> {
+        mem::take(&mut self.buffer)
+    }
+}
+
+impl<T> std::ops::Deref for Pooled<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.buffer
+    }
+}
+
+impl<T> std::ops::DerefMut for Pooled<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        &mut self.buffer
+    }
+}
+
+impl<T> Drop for Pooled<T> {
+    fn drop(&mut self) {
+        self.pool.give(mem::take(&mut self.buffer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_buffers_handed_back() {
+        let pool = VecPool::new(2, 100);
+        let mut buffer = pool.take();
+        buffer.extend([1u8, 2, 3]);
+        let capacity = buffer.capacity();
+        pool.give(buffer);
+
+        let reused = pool.take();
+        assert!(reused.is_empty());
+        assert_eq!(reused.capacity(), capacity);
+        pool.give(reused);
+
+        {
+            let mut pooled = pool.get();
+            pooled.push(4);
+        }
+        assert_eq!(pool.stats(), PoolStats { hits: 2, misses: 1, discarded: 0, pooled: 1 });
+        assert!((pool.stats().hit_rate() - 2.0 / 3.0).abs() < 1e-9);
+
+        let kept = pool.get().into_inner();
+        assert_eq!(kept.capacity(), capacity);
+        assert_eq!(pool.stats().pooled, 0);
+    }
+
+    #[test]
+    fn drops_oversized_buffers_and_those_over_the_limit() {
+        let pool = VecPool::new(1, 100);
+        pool.give(Vec::<u8>::with_capacity(1000));
+        pool.give(Vec::with_capacity(10));
+        pool.give(Vec::with_capacity(10));
+        assert_eq!(pool.stats(), PoolStats { hits: 0, misses: 0, discarded: 2, pooled: 1 });
+        assert_eq!(PoolStats::default().hit_rate(), 0.0);
+    }
+}
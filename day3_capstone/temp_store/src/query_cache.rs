@@ -0,0 +1,187 @@
+//! Cached results of range queries and daily rollups.
+//!
+//! Dashboards refresh the same windows over and over, and for a persistent
+//! store each refresh may page the older part in from the log. The store
+//! keeps the results of recent queries keyed by what was asked, and drops
+//! a result as soon as a reading lands in, or leaves, a bucket it covers.
+//! A query over yesterday thus stays cached while today's readings come in.
+//!
+//! The least recently used result makes room once the cache is full.
+
+use serde::{Deserialize, Serialize};
+
+use crate::local_time::Tz;
+use crate::rollup::{Resolution, RollupPoint};
+
+/// Results kept by a new store.
+pub const DEFAULT_QUERY_CACHE_ENTRIES: usize = 64;
+
+/// What a cached result answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum QueryKey {
+    Range { start: u64, end: u64, resolution: Resolution },
+    DailyRollups { start: u64, end: u64, tz: Tz },
+}
+
+impl QueryKey {
+    /// The tier the result is built from.
+    fn tier(&self) -> Resolution {
+        match *self {
+            QueryKey::Range { resolution, .. } => resolution,
+            QueryKey::DailyRollups { .. } => Resolution::Hour,
+        }
+    }
+
+    /// Whether a reading at `timestamp` changes the result: the bucket it
+    /// falls into is one the query returns.
+    fn covers(&self, timestamp: u64) -> bool {
+        let (QueryKey::Range { start, end, .. } | QueryKey::DailyRollups { start, end, .. }) = *self;
+        let bucket_seconds = self.tier().bucket_seconds();
+        timestamp >= start - start % bucket_seconds && timestamp - timestamp % bucket_seconds < end
+    }
+}
+
+/// Hit and miss counts, to tell whether the cache pays off.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QueryCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Results dropped because a reading changed them.
+    pub invalidations: u64,
+    pub entries: usize,
+}
+
+#[derive(Debug)]
+pub(crate) struct QueryCache {
+    capacity: usize,
+    /// Least recently used first.
+    entries: Vec<(QueryKey, Vec<RollupPoint>)>,
+    /// Bumped on every write, so a result computed while the store was
+    /// unlocked is not cached if a reading came in meanwhile.
+    generation: u64,
+    stats: QueryCacheStats,
+}
+
+impl QueryCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Vec::new(),
+            generation: 0,
+            stats: QueryCacheStats::default(),
+        }
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let surplus = self.entries.len().saturating_sub(capacity);
+        self.entries.drain(..surplus);
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub(crate) fn get(&mut self, key: &QueryKey) -> Option<Vec<RollupPoint>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        match self.entries.iter().position(|(cached, _)| cached == key) {
+            Some(index) => {
+                self.stats.hits += 1;
+                let entry = self.entries.remove(index);
+                let points = entry.1.clone();
+                self.entries.push(entry);
+                Some(points)
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Caches `points` unless the store was written to since `generation`.
+    pub(crate) fn insert(&mut self, key: QueryKey, generation: u64, points: Vec<RollupPoint>) {
+        if self.capacity == 0 || generation != self.generation {
+            return;
+        }
+        self.entries.retain(|(cached, _)| *cached != key);
+        if self.entries.len() >= self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push((key, points));
+    }
+
+    /// Drops the results a new reading at `timestamp` changes.
+    pub(crate) fn invalidate(&mut self, timestamp: u64) {
+        self.invalidate_where(|key| key.covers(timestamp));
+    }
+
+    /// Drops the results built from `tier` that covered the point at
+    /// `timestamp` it evicted.
+    pub(crate) fn invalidate_evicted(&mut self, tier: Resolution, timestamp: u64) {
+        self.invalidate_where(|key| key.tier() == tier && key.covers(timestamp));
+    }
+
+    fn invalidate_where(&mut self, stale: impl Fn(&QueryKey) -> bool) {
+        self.generation += 1;
+        let before = self.entries.len();
+        self.entries.retain(|(key, _)| !stale(key));
+        self.stats.invalidations += (before - self.entries.len()) as u64;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.generation += 1;
+        self.stats.invalidations += self.entries.len() as u64;
+        self.entries.clear();
+    }
+
+    pub(crate) fn stats(&self) -> QueryCacheStats {
+        QueryCacheStats {
+            entries: self.entries.len(),
+            ..self.stats
+        }
+    }
+
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.entries.capacity() * std::mem::size_of::<(QueryKey, Vec<RollupPoint>)>()
+            + self
+                .entries
+                .iter()
+                .map(|(_, points)| points.capacity() * std::mem::size_of::<RollupPoint>())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_cover_the_buckets_they_return() {
+        let raw = QueryKey::Range { start: 100, end: 200, resolution: Resolution::Raw };
+        assert!(raw.covers(100) && raw.covers(199));
+        assert!(!raw.covers(99) && !raw.covers(200));
+
+        // The minute bucket at 60 is returned for a start of 90, and the one at 180 for an end of 190
+        let minute = QueryKey::Range { start: 90, end: 190, resolution: Resolution::Minute };
+        assert!(minute.covers(60) && minute.covers(239));
+        assert!(!minute.covers(59) && !minute.covers(240));
+
+        let mut cache = QueryCache::new(2);
+        let generation = cache.generation();
+        cache.insert(raw, generation, Vec::new());
+        cache.insert(minute, generation, Vec::new());
+        cache.invalidate_evicted(Resolution::Hour, 150);
+        assert_eq!(cache.stats().entries, 2);
+        cache.invalidate(220);
+        assert_eq!(cache.get(&raw), Some(Vec::new()));
+        assert_eq!(cache.get(&minute), None);
+        assert_eq!(cache.stats(), QueryCacheStats { hits: 1, misses: 1, invalidations: 1, entries: 1 });
+
+        // Computed before the write, so not cached
+        cache.insert(minute, generation, Vec::new());
+        assert_eq!(cache.stats().entries, 1);
+    }
+}
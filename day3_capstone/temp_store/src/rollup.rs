@@ -0,0 +1,336 @@
+//! Rollup tiers and the range query planner.
+//!
+//! Besides the raw circular buffer, the store keeps per-minute and per-hour
+//! aggregates. Range queries are served from the coarsest tier that still gives
+//! a useful picture of the requested span, so long dashboard windows don't have
+//! to walk thousands of raw readings.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+use temp_core::{PreciseTemperature, Temperature};
+
+use crate::{precise_mean, Accumulator, TemperatureReading};
+
+pub const MINUTE_SECONDS: u64 = 60;
+pub const HOUR_SECONDS: u64 = 60 * MINUTE_SECONDS;
+pub const DAY_SECONDS: u64 = 24 * HOUR_SECONDS;
+
+/// One day of minute rollups.
+pub const MINUTE_TIER_CAPACITY: usize = 24 * 60;
+/// Roughly one month of hour rollups.
+pub const HOUR_TIER_CAPACITY: usize = 31 * 24;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Raw,
+    Minute,
+    Hour,
+}
+
+impl Resolution {
+    /// Raw below one hour, minute rollups below one day, hour rollups otherwise.
+    pub fn for_span(span_seconds: u64) -> Self {
+        if span_seconds < HOUR_SECONDS {
+            Resolution::Raw
+        } else if span_seconds < DAY_SECONDS {
+            Resolution::Minute
+        } else {
+            Resolution::Hour
+        }
+    }
+
+    pub fn bucket_seconds(&self) -> u64 {
+        match self {
+            Resolution::Raw => 1,
+            Resolution::Minute => MINUTE_SECONDS,
+            Resolution::Hour => HOUR_SECONDS,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(into = "WireRollupPoint", from = "WireRollupPoint")]
+pub struct RollupPoint {
+    pub start: u64,
+    pub min: Temperature,
+    pub max: Temperature,
+    /// `sum / count`, rounded to a reading.
+    pub average: Temperature,
+    pub count: usize,
+    /// Sum of the readings in the bucket, which `average` is derived from.
+    pub sum: Accumulator,
+}
+
+impl RollupPoint {
+    pub fn from_reading(reading: &TemperatureReading, bucket_seconds: u64) -> Self {
+        Self {
+            start: reading.timestamp - reading.timestamp % bucket_seconds,
+            min: reading.temperature,
+            max: reading.temperature,
+            average: reading.temperature,
+            count: 1,
+            sum: Accumulator::from(reading.temperature.celsius),
+        }
+    }
+
+    /// The average before rounding to `f32`.
+    pub fn precise_average(&self) -> PreciseTemperature {
+        precise_mean(self.sum, self.count)
+    }
+
+    /// Folds `count` readings summing to `sum` into the average.
+    pub(crate) fn add_sum(&mut self, sum: Accumulator, count: usize) {
+        self.sum += sum;
+        self.count += count;
+        self.average = self.precise_average().to_temperature();
+    }
+
+    fn merge(&mut self, temperature: Temperature) {
+        let celsius = temperature.celsius;
+        if celsius < self.min.celsius {
+            self.min = temperature;
+        }
+        if celsius > self.max.celsius {
+            self.max = temperature;
+        }
+        self.add_sum(Accumulator::from(celsius), 1);
+    }
+
+    /// Folds in another aggregate of the same bucket.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn combine(&mut self, other: &RollupPoint) {
+        if other.min.celsius < self.min.celsius {
+            self.min = other.min;
+        }
+        if other.max.celsius > self.max.celsius {
+            self.max = other.max;
+        }
+        self.add_sum(other.sum, other.count);
+    }
+}
+
+/// How a [`RollupPoint`] goes on the wire: the sum always as `f64`, so peers
+/// built with and without `high-precision` read each other's points, and
+/// rebuilt from the average when a peer that predates it left it out.
+#[derive(Serialize, Deserialize)]
+struct WireRollupPoint {
+    start: u64,
+    min: Temperature,
+    max: Temperature,
+    average: Temperature,
+    count: usize,
+    #[serde(default = "missing_sum")]
+    sum: f64,
+}
+
+fn missing_sum() -> f64 {
+    f64::NAN
+}
+
+impl From<RollupPoint> for WireRollupPoint {
+    #[allow(clippy::useless_conversion)] // f64 to f64 with `high-precision`
+    fn from(point: RollupPoint) -> Self {
+        let RollupPoint { start, min, max, average, count, sum } = point;
+        Self { start, min, max, average, count, sum: f64::from(sum) }
+    }
+}
+
+impl From<WireRollupPoint> for RollupPoint {
+    fn from(point: WireRollupPoint) -> Self {
+        let WireRollupPoint { start, min, max, average, count, sum } = point;
+        // Sums of validated readings are never NaN
+        let sum = if sum.is_nan() { f64::from(average.celsius) * count as f64 } else { sum };
+        Self { start, min, max, average, count, sum: sum as Accumulator }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RangeQueryResult {
+    pub resolution: Resolution,
+    pub points: Vec<RollupPoint>,
+}
+
+/// Fixed-capacity series of aggregates with a constant bucket width.
+#[derive(Debug, Clone)]
+pub struct RollupTier {
+    bucket_seconds: u64,
+    capacity: usize,
+    points: VecDeque<RollupPoint>,
+    /// Points at the front closed by [`seal`](Self::seal); readings no longer merge into them.
+    sealed: usize,
+}
+
+impl RollupTier {
+    pub fn new(bucket_seconds: u64, capacity: usize) -> Self {
+        Self {
+            bucket_seconds,
+            capacity,
+            points: VecDeque::new(),
+            sealed: 0,
+        }
+    }
+
+    pub fn add(&mut self, reading: &TemperatureReading) {
+        let bucket = reading.timestamp - reading.timestamp % self.bucket_seconds;
+
+        // Readings almost always land in the newest bucket, so search from the back
+        let position = self.points.range(self.sealed..).rposition(|p| p.start <= bucket).map(|i| i + self.sealed);
+        match position {
+            Some(index) if self.points[index].start == bucket => {
+                self.points[index].merge(reading.temperature);
+            }
+            _ => {
+                let index = position.map_or(self.sealed, |i| i + 1);
+                if self.points.len() >= self.capacity {
+                    if index == 0 {
+                        // Older than everything we still keep
+                        return;
+                    }
+                    self.points.pop_front();
+                    self.sealed = self.sealed.saturating_sub(1);
+                    self.points.insert(index - 1, RollupPoint::from_reading(reading, self.bucket_seconds));
+                } else {
+                    self.points.insert(index, RollupPoint::from_reading(reading, self.bucket_seconds));
+                }
+            }
+        }
+    }
+
+    /// Points whose bucket starts in `start..end`.
+    pub fn range(&self, start: u64, end: u64) -> Vec<RollupPoint> {
+        self.points
+            .iter()
+            .filter(|p| p.start >= start - start % self.bucket_seconds && p.start < end)
+            .copied()
+            .collect()
+    }
+
+    /// Closes every point kept so far. A later reading in the same bucket
+    /// as a closed point starts a second point for it, so the bucket an
+    /// epoch starts in is reported once for each epoch.
+    pub fn seal(&mut self) {
+        self.sealed = self.points.len();
+    }
+
+    /// Start of the oldest bucket still kept.
+    pub fn first_start(&self) -> Option<u64> {
+        self.points.front().map(|p| p.start)
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.sealed = 0;
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.points.capacity() * core::mem::size_of::<RollupPoint>()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn reading(celsius: f32, timestamp: u64) -> TemperatureReading {
+        TemperatureReading::with_timestamp(Temperature::new(celsius), timestamp)
+    }
+
+    #[test]
+    fn resolution_for_span() {
+        assert_eq!(Resolution::for_span(0), Resolution::Raw);
+        assert_eq!(Resolution::for_span(HOUR_SECONDS - 1), Resolution::Raw);
+        assert_eq!(Resolution::for_span(HOUR_SECONDS), Resolution::Minute);
+        assert_eq!(Resolution::for_span(DAY_SECONDS - 1), Resolution::Minute);
+        assert_eq!(Resolution::for_span(7 * DAY_SECONDS), Resolution::Hour);
+    }
+
+    #[test]
+    fn tier_aggregates_buckets() {
+        let mut tier = RollupTier::new(MINUTE_SECONDS, 10);
+        tier.add(&reading(10.0, 60));
+        tier.add(&reading(20.0, 90));
+        tier.add(&reading(30.0, 125));
+        // Late reading for the first bucket
+        tier.add(&reading(30.0, 119));
+
+        assert_eq!(tier.len(), 2);
+        let points = tier.range(0, 1000);
+        assert_eq!(points[0].start, 60);
+        assert_eq!(points[0].count, 3);
+        assert_eq!(points[0].min.celsius, 10.0);
+        assert_eq!(points[0].max.celsius, 30.0);
+        assert_eq!(points[0].average.celsius, 20.0);
+        assert_eq!(points[1].start, 120);
+    }
+
+    #[test]
+    fn tier_evicts_oldest_bucket() {
+        let mut tier = RollupTier::new(MINUTE_SECONDS, 2);
+        tier.add(&reading(1.0, 0));
+        tier.add(&reading(2.0, 60));
+        tier.add(&reading(3.0, 120));
+
+        let points = tier.range(0, 1000);
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].start, 60);
+
+        // Too old to fit anymore
+        tier.add(&reading(4.0, 5));
+        assert_eq!(tier.range(0, 1000)[0].start, 60);
+    }
+
+    #[test]
+    fn sealed_buckets_take_no_more_readings() {
+        let mut tier = RollupTier::new(MINUTE_SECONDS, 10);
+        tier.add(&reading(10.0, 60));
+        tier.seal();
+        tier.add(&reading(20.0, 90));
+        tier.add(&reading(30.0, 100));
+
+        let points = tier.range(0, 1000);
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].start, points[0].count), (60, 1));
+        assert_eq!((points[1].start, points[1].count), (60, 2));
+        assert_eq!(points[1].average.celsius, 25.0);
+    }
+
+    #[cfg(feature = "high-precision")]
+    #[test]
+    fn rollup_averages_do_not_drift() {
+        // Rebuilding the sum from an f32 average on every add drifts by whole
+        // hundredths long before a million readings
+        let mut point = RollupPoint::from_reading(&reading(20.1, 0), HOUR_SECONDS);
+        for i in 1..1_000_000 {
+            point.merge(Temperature::new(20.1 + (i % 10) as f32 * 0.01));
+        }
+
+        assert_eq!(point.count, 1_000_000);
+        assert!((point.precise_average().celsius - 20.145).abs() < 1e-6, "{}", point.precise_average());
+        assert!((point.average.celsius - 20.145).abs() < 1e-5, "{}", point.average.celsius);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn wire_points_rebuild_a_missing_sum() {
+        let mut point = RollupPoint::from_reading(&reading(20.0, 0), HOUR_SECONDS);
+        point.merge(Temperature::new(21.0));
+        let json = serde_json::to_value(point).unwrap();
+        assert_eq!(serde_json::from_value::<RollupPoint>(json.clone()).unwrap(), point);
+
+        let mut older = json;
+        older.as_object_mut().unwrap().remove("sum");
+        let rebuilt: RollupPoint = serde_json::from_value(older).unwrap();
+        assert_eq!(rebuilt.sum, 41.0);
+        assert_eq!(rebuilt.precise_average(), point.precise_average());
+    }
+}
@@ -0,0 +1,1110 @@
+//! The store itself: raw readings, rollups, the optional log and the
+//! queries over them, behind a handle that can be shared between threads.
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use crate::error_hook::{self, SwallowedKind};
+use crate::forecast::HoltWinters;
+use crate::query_cache::{QueryCache, QueryKey, DEFAULT_QUERY_CACHE_ENTRIES};
+use crate::rollup::{RollupTier, DAY_SECONDS, HOUR_SECONDS, HOUR_TIER_CAPACITY, MINUTE_SECONDS, MINUTE_TIER_CAPACITY};
+use crate::sync::{Arc, Mutex, MutexGuard};
+#[cfg(feature = "encryption")]
+use crate::encryption;
+use crate::{aggregate, annotation, backup, compare, local_time, window};
+use crate::{
+    Annotation, BackupFormat, CompactionReport, ComparisonPeriod, DegreeDayReport, Epoch, FileBackend, FlushPolicy,
+    Forecast, IngestGuard, LogSnapshot, OutlierRejection, QueryCacheStats, RangeQueryResult, Resolution, RollupPoint,
+    StorageInfo, TemperatureReading, TemperatureStats, Tz, WindowComparison,
+};
+use temp_core::{PreciseTemperature, Temperature};
+
+struct StoreInner {
+    readings: Vec<TemperatureReading>,
+    /// Min, max and sum of `readings`, kept up to date as they change.
+    window: window::WindowStats,
+    outlier_rejection: Option<OutlierRejection>,
+    ingest_guard: Option<IngestGuard>,
+    minute_rollups: RollupTier,
+    hour_rollups: RollupTier,
+    annotations: annotation::Annotations,
+    query_cache: QueryCache,
+    epoch: Epoch,
+    /// Readings at the front of `readings` from before the epoch, left out of `window`.
+    pre_epoch: usize,
+    backend: Option<FileBackend>,
+    /// No reading in the backend's log is older than this.
+    log_start: Option<u64>,
+    /// Receive every reading added from now on; dropped once their receiver is.
+    subscribers: Vec<mpsc::Sender<TemperatureReading>>,
+    /// Panics while the lock was held, recovered from.
+    lock_poisonings: u64,
+    /// Readings refused as NaN, infinite or below absolute zero.
+    rejected_readings: u64,
+}
+
+impl StoreInner {
+    /// Oldest time the in-memory tier for `resolution` still covers; older
+    /// readings only exist in the log.
+    fn hot_start(&self, resolution: Resolution) -> Option<u64> {
+        match resolution {
+            Resolution::Raw => self.readings.iter().map(|r| r.timestamp).min(),
+            Resolution::Minute => self.minute_rollups.first_start(),
+            Resolution::Hour => self.hour_rollups.first_start(),
+        }
+    }
+}
+
+pub struct TemperatureStore {
+    inner: Arc<Mutex<StoreInner>>,
+    capacity: usize,
+}
+
+impl TemperatureStore {
+    /// A store keeping the last `capacity` raw readings; zero is treated as one.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            inner: Arc::new(Mutex::new(StoreInner {
+                readings: Vec::with_capacity(capacity),
+                window: window::WindowStats::default(),
+                outlier_rejection: None,
+                ingest_guard: None,
+                minute_rollups: RollupTier::new(MINUTE_SECONDS, MINUTE_TIER_CAPACITY),
+                hour_rollups: RollupTier::new(HOUR_SECONDS, HOUR_TIER_CAPACITY),
+                annotations: annotation::Annotations::default(),
+                query_cache: QueryCache::new(DEFAULT_QUERY_CACHE_ENTRIES),
+                epoch: Epoch::default(),
+                pre_epoch: 0,
+                backend: None,
+                log_start: None,
+                subscribers: Vec::new(),
+                lock_poisonings: 0,
+                rejected_readings: 0,
+            })),
+            capacity,
+        }
+    }
+
+    pub fn with_ingest_guard(capacity: usize, guard: IngestGuard) -> Self {
+        let store = Self::new(capacity);
+        store.set_ingest_guard(Some(guard));
+        store
+    }
+
+    /// Opens a store backed by the log at `path`, replaying the readings already in it.
+    pub fn open(capacity: usize, path: impl AsRef<Path>, policy: FlushPolicy) -> io::Result<Self> {
+        let existing = FileBackend::load(&path)?;
+        let backend = FileBackend::open(&path, policy)?;
+        Ok(Self::with_backend(capacity, existing, backend))
+    }
+
+    /// Like [`open`](Self::open) for a log encrypted with `cipher`.
+    #[cfg(feature = "encryption")]
+    pub fn open_encrypted(
+        capacity: usize,
+        path: impl AsRef<Path>,
+        policy: FlushPolicy,
+        cipher: encryption::RecordCipher,
+    ) -> io::Result<Self> {
+        let existing = FileBackend::load_encrypted(&path, &cipher)?;
+        let backend = FileBackend::open_encrypted(&path, policy, cipher)?;
+        Ok(Self::with_backend(capacity, existing, backend))
+    }
+
+    fn with_backend(capacity: usize, mut existing: Vec<TemperatureReading>, backend: FileBackend) -> Self {
+        let store = Self::new(capacity);
+        {
+            let mut inner = store.lock();
+            let logged = existing.len();
+            existing.retain(|r| r.temperature.validate().is_ok());
+            inner.rejected_readings = (logged - existing.len()) as u64;
+            for reading in &existing {
+                inner.minute_rollups.add(reading);
+                inner.hour_rollups.add(reading);
+            }
+            let start_index = existing.len().saturating_sub(capacity);
+            inner.readings.extend_from_slice(&existing[start_index..]);
+            for reading in &existing[start_index..] {
+                inner.window.push(reading.temperature.celsius);
+            }
+            inner.backend = Some(backend);
+            inner.log_start = existing.iter().map(|r| r.timestamp).min();
+        }
+        store
+    }
+
+    /// Locks the store. A writer that panicked cannot leave a reading half
+    /// applied, and a reader cannot change anything, so a poisoned lock is
+    /// recovered and counted rather than propagated.
+    fn lock(&self) -> MutexGuard<'_, StoreInner> {
+        self.inner.lock().unwrap_or_else(|poisoned| {
+            let mut inner = poisoned.into_inner();
+            inner.lock_poisonings += 1;
+            error_hook::report(SwallowedKind::LockPoisoned, "Recovered the store lock", &"a holder panicked");
+            self.inner.clear_poison();
+            inner
+        })
+    }
+
+    /// How often a panic while holding the store's lock, e.g. in a
+    /// [`with_readings`](Self::with_readings) callback, poisoned it.
+    pub fn lock_poisonings(&self) -> u64 {
+        self.lock().lock_poisonings
+    }
+
+    /// Install (or remove) the guard used to shed readings under high write rates.
+    pub fn set_ingest_guard(&self, guard: Option<IngestGuard>) {
+        let mut inner = self.lock();
+        inner.ingest_guard = guard;
+    }
+
+    /// Leaves outliers out of [`calculate_stats`](Self::calculate_stats), or
+    /// includes everything again with `None`.
+    pub fn set_outlier_rejection(&self, rejection: Option<OutlierRejection>) {
+        self.lock().outlier_rejection = rejection;
+    }
+
+    /// Keeps the results of up to `entries` range queries and daily rollups,
+    /// see [`query_cache`]; zero turns caching off.
+    pub fn set_query_cache_capacity(&self, entries: usize) {
+        self.lock().query_cache.set_capacity(entries);
+    }
+
+    pub fn query_cache_stats(&self) -> QueryCacheStats {
+        self.lock().query_cache.stats()
+    }
+
+    /// A channel receiving every reading added from now on, for sinks that
+    /// follow the store. Readings shed by the ingest guard are not sent.
+    pub fn subscribe(&self) -> mpsc::Receiver<TemperatureReading> {
+        let (sender, receiver) = mpsc::channel();
+        self.lock().subscribers.push(sender);
+        receiver
+    }
+
+    /// Adds a reading, returning false if it was rejected as impossible or
+    /// the ingest guard shed it.
+    pub fn add_reading(&self, reading: TemperatureReading) -> bool {
+        let mut inner = self.lock();
+        if !Self::admit(&mut inner, &reading) {
+            return false;
+        }
+
+        self.insert(&mut inner, reading);
+        true
+    }
+
+    /// Adds a batch under one lock; returns how many were admitted.
+    pub fn add_readings(&self, readings: &[TemperatureReading]) -> usize {
+        let mut inner = self.lock();
+        let mut admitted = 0;
+        for &reading in readings {
+            if !Self::admit(&mut inner, &reading) {
+                continue;
+            }
+            self.insert(&mut inner, reading);
+            admitted += 1;
+        }
+        admitted
+    }
+
+    /// A NaN would poison the window's min, max and sum for as long as it is
+    /// kept, so impossible temperatures never get in.
+    fn admit(inner: &mut StoreInner, reading: &TemperatureReading) -> bool {
+        if let Err(e) = reading.temperature.validate() {
+            inner.rejected_readings += 1;
+            error_hook::report(SwallowedKind::Sensor, "Rejected a reading", &e);
+            return false;
+        }
+        inner.ingest_guard.as_mut().is_none_or(|guard| guard.admit(reading.timestamp))
+    }
+
+    fn insert(&self, inner: &mut StoreInner, reading: TemperatureReading) {
+        if inner.readings.len() >= self.capacity {
+            let evicted = inner.readings.remove(0);
+            if inner.pre_epoch > 0 {
+                inner.pre_epoch -= 1;
+            } else {
+                inner.window.evict(evicted.temperature.celsius);
+            }
+            inner.query_cache.invalidate_evicted(Resolution::Raw, evicted.timestamp);
+        }
+
+        inner.readings.push(reading);
+        inner.window.push(reading.temperature.celsius);
+        let tier_starts = [inner.minute_rollups.first_start(), inner.hour_rollups.first_start()];
+        inner.minute_rollups.add(&reading);
+        inner.hour_rollups.add(&reading);
+        inner.query_cache.invalidate(reading.timestamp);
+        // A full tier drops its oldest bucket
+        let tiers = [(Resolution::Minute, &inner.minute_rollups), (Resolution::Hour, &inner.hour_rollups)];
+        for (start, (resolution, tier)) in tier_starts.into_iter().zip(tiers) {
+            if let Some(start) = start.filter(|&start| tier.first_start() != Some(start)) {
+                inner.query_cache.invalidate_evicted(resolution, start);
+            }
+        }
+
+        if let Some(backend) = inner.backend.as_mut() {
+            if let Err(e) = backend.append(&reading) {
+                let context = format!("Failed to persist reading to {}", backend.path().display());
+                error_hook::report(SwallowedKind::Io, &context, &e);
+            }
+            inner.log_start = Some(inner.log_start.map_or(reading.timestamp, |start| start.min(reading.timestamp)));
+        }
+
+        inner.subscribers.retain(|subscriber| subscriber.send(reading).is_ok());
+    }
+
+    /// Streams every reading the store has to `out`: the whole log of a
+    /// persistent store, the raw window otherwise. The log is read without
+    /// holding the lock, so ingestion carries on; readings arriving meanwhile
+    /// are left for the next backup. Returns the number of readings written.
+    pub fn write_backup(&self, out: impl Write, format: BackupFormat) -> io::Result<u64> {
+        let (snapshot, window) = {
+            let mut inner = self.lock();
+            match inner.backend.as_mut() {
+                Some(backend) => (Some(backend.peek()?), Vec::new()),
+                None => (None, inner.readings.clone()),
+            }
+        };
+
+        let mut writer = backup::BackupWriter::new(out, format)?;
+        match snapshot {
+            Some(snapshot) => snapshot.for_each(|_, reading| writer.write(&reading))?,
+            None => window.iter().try_for_each(|reading| writer.write(reading))?,
+        }
+        writer.finish()
+    }
+
+    /// Adds every reading of a backup, bypassing the ingest guard, and
+    /// persists them if the store has a backend. Returns how many were
+    /// restored; impossible temperatures are skipped like in
+    /// [`add_reading`](Self::add_reading).
+    pub fn restore_backup(&self, input: impl Read) -> io::Result<usize> {
+        let readings = backup::read_backup(input)?;
+        let mut inner = self.lock();
+        let mut restored = 0;
+        for reading in readings {
+            if reading.temperature.validate().is_err() {
+                inner.rejected_readings += 1;
+                continue;
+            }
+            self.insert(&mut inner, reading);
+            restored += 1;
+        }
+        if let Some(backend) = inner.backend.as_mut() {
+            backend.flush()?;
+        }
+        Ok(restored)
+    }
+
+    /// Flushes and syncs pending writes of the persistent backend, if any.
+    ///
+    /// The records are written out under the lock, which keeps them in
+    /// order, but synced after it, so readings keep coming in meanwhile.
+    pub fn flush(&self) -> io::Result<()> {
+        let log = match self.lock().backend.as_mut() {
+            Some(backend) => backend.write_pending()?,
+            None => return Ok(()),
+        };
+        log.sync_data()
+    }
+
+    /// Times the backend's [`FlushPolicy::Interval`] with `clock` instead of
+    /// `Instant::now`, e.g. the clock of the runtime flushing it.
+    pub fn set_flush_clock(&self, clock: fn() -> Instant) {
+        if let Some(backend) = self.lock().backend.as_mut() {
+            backend.set_clock(clock);
+        }
+    }
+
+    /// Flushes only if the backend's policy says it is time; returns whether it did.
+    pub fn flush_if_due(&self) -> io::Result<bool> {
+        let log = match self.lock().backend.as_mut() {
+            Some(backend) if backend.needs_flush() => backend.write_pending()?,
+            _ => return Ok(false),
+        };
+        log.sync_data().map(|()| true)
+    }
+
+    /// Rewrites the persistent log without the readings no tier keeps any
+    /// more: those older than both the oldest hourly rollup and the raw
+    /// window, which reopening the store would replay only to discard.
+    ///
+    /// The log is rewritten without holding the lock, so readings keep
+    /// coming in; they are carried over when the files are swapped.
+    /// Returns `None` for a store without a backend.
+    pub fn compact(&self) -> io::Result<Option<CompactionReport>> {
+        let (mut compaction, cutoff) = {
+            let mut inner = self.lock();
+            let oldest_raw = inner.readings.iter().map(|r| r.timestamp).min();
+            let cutoff = match (inner.hour_rollups.first_start(), oldest_raw) {
+                (Some(hour), Some(raw)) => hour.min(raw),
+                (hour, raw) => hour.or(raw).unwrap_or(0),
+            };
+            let Some(backend) = inner.backend.as_mut() else {
+                return Ok(None);
+            };
+            (backend.begin_compaction()?, cutoff)
+        };
+
+        let rewritten = compaction.rewrite(|reading: &TemperatureReading| reading.timestamp >= cutoff);
+
+        let mut inner = self.lock();
+        let Some(backend) = inner.backend.as_mut() else {
+            return Ok(None);
+        };
+        match rewritten {
+            Ok(()) => {
+                let report = backend.finish_compaction(compaction)?;
+                inner.log_start = inner.log_start.map(|start| start.max(cutoff));
+                inner.query_cache.clear();
+                Ok(Some(report))
+            }
+            Err(e) => {
+                backend.cancel_compaction(compaction);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn pending_writes(&self) -> u32 {
+        let inner = self.lock();
+        inner.backend.as_ref().map_or(0, |backend| backend.pending_writes())
+    }
+
+    /// Readings in `start..end`, served from the tier matching the span.
+    pub fn query_range(&self, start: u64, end: u64) -> RangeQueryResult {
+        let resolution = Resolution::for_span(end.saturating_sub(start));
+        self.query_range_at(start, end, resolution)
+    }
+
+    /// Like [`query_range`](Self::query_range) at a fixed resolution.
+    ///
+    /// Memory holds the hot tier: the raw window and the rollups. For a
+    /// persistent store, the part of the range older than what the tier
+    /// still covers is paged in from the log, along with the records it
+    /// still buffers; nothing is flushed for it, so the flush policy alone
+    /// decides how often the disk is written. The log is read without
+    /// holding the lock, so ingestion and `get_latest` carry on meanwhile.
+    ///
+    /// Results are cached until a reading changes them, see [`query_cache`].
+    pub fn query_range_at(&self, start: u64, end: u64, resolution: Resolution) -> RangeQueryResult {
+        let key = QueryKey::Range { start, end, resolution };
+        let (hot, cold, generation) = {
+            let mut inner = self.lock();
+            if let Some(points) = inner.query_cache.get(&key) {
+                return RangeQueryResult { resolution, points };
+            }
+            let generation = inner.query_cache.generation();
+            let hot_start = inner.hot_start(resolution).unwrap_or(u64::MAX);
+            let hot = match resolution {
+                Resolution::Raw => inner
+                    .readings
+                    .iter()
+                    .filter(|r| r.timestamp >= start && r.timestamp < end)
+                    .map(|r| RollupPoint::from_reading(r, 1))
+                    .collect(),
+                Resolution::Minute => inner.minute_rollups.range(start, end),
+                Resolution::Hour => inner.hour_rollups.range(start, end),
+            };
+
+            let in_log = inner.log_start.is_some_and(|log_start| log_start < hot_start.min(end));
+            let cold = match inner.backend.as_mut() {
+                Some(backend) if in_log && start < hot_start => match backend.peek() {
+                    Ok(snapshot) => Some((snapshot, hot_start.min(end))),
+                    Err(e) => {
+                        let context = format!("Failed to page in {}", backend.path().display());
+                        error_hook::report(SwallowedKind::Io, &context, &e);
+                        return RangeQueryResult { resolution, points: hot };
+                    }
+                },
+                _ => None,
+            };
+            (hot, cold, generation)
+        };
+
+        let mut points = match cold {
+            Some((snapshot, cold_end)) => match page_in(&snapshot, start, cold_end, resolution) {
+                Ok(points) => points,
+                Err(e) => {
+                    error_hook::report(SwallowedKind::Io, "Failed to page in readings", &e);
+                    return RangeQueryResult { resolution, points: hot };
+                }
+            },
+            None => Vec::new(),
+        };
+        points.extend(hot);
+        self.lock().query_cache.insert(key, generation, points.clone());
+        RangeQueryResult { resolution, points }
+    }
+
+    /// Statistics for `start..end`, from the finest tier that still reaches back to `start`.
+    pub fn window_stats(&self, start: u64, end: u64) -> Option<TemperatureStats> {
+        let inner = self.lock();
+
+        if inner.readings.first().is_some_and(|r| r.timestamp <= start) {
+            let readings: Vec<_> = inner
+                .readings
+                .iter()
+                .filter(|r| r.timestamp >= start && r.timestamp < end)
+                .copied()
+                .collect();
+            return aggregate::stats(&readings);
+        }
+
+        let tier = if inner.minute_rollups.first_start().is_some_and(|first| first <= start) {
+            &inner.minute_rollups
+        } else {
+            &inner.hour_rollups
+        };
+        compare::stats_from_points(&tier.range(start, end))
+    }
+
+    /// Compares `start..end` with the same window `period` earlier.
+    pub fn compare_window(&self, start: u64, end: u64, period: ComparisonPeriod) -> Option<WindowComparison> {
+        let offset = period.offset_seconds();
+        let current = self.window_stats(start, end)?;
+        let previous = self.window_stats(start.checked_sub(offset)?, end.checked_sub(offset)?)?;
+        Some(WindowComparison::new(period, current, previous))
+    }
+
+    /// Heating and cooling degree-days per day in `start..end`, from the hour rollups.
+    pub fn degree_days(&self, start: u64, end: u64, base: Temperature) -> DegreeDayReport {
+        self.degree_days_in(start, end, base, Tz::UTC)
+    }
+
+    /// Degree-days with days starting at local midnight in `tz`.
+    pub fn degree_days_in(&self, start: u64, end: u64, base: Temperature, tz: Tz) -> DegreeDayReport {
+        let inner = self.lock();
+        DegreeDayReport::from_hourly_in(&inner.hour_rollups.range(start, end), base, tz)
+    }
+
+    /// One rollup per local day in `start..end`, built from the hour rollups.
+    pub fn daily_rollups(&self, start: u64, end: u64, tz: Tz) -> Vec<RollupPoint> {
+        let mut inner = self.lock();
+        let key = QueryKey::DailyRollups { start, end, tz };
+        if let Some(days) = inner.query_cache.get(&key) {
+            return days;
+        }
+        let days = local_time::daily_rollups(&inner.hour_rollups.range(start, end), tz);
+        let generation = inner.query_cache.generation();
+        inner.query_cache.insert(key, generation, days.clone());
+        days
+    }
+
+    /// Forecast for the next `horizon` seconds, or `None` without enough recent history.
+    ///
+    /// Below a day the minute rollups are used; longer horizons use the hour
+    /// rollups with a daily season.
+    pub fn forecast(&self, horizon: u64) -> Option<Forecast> {
+        let inner = self.lock();
+        let (resolution, tier, model) = if horizon < DAY_SECONDS {
+            (Resolution::Minute, &inner.minute_rollups, HoltWinters::default())
+        } else {
+            (Resolution::Hour, &inner.hour_rollups, HoltWinters::default().with_season(24, 0.1))
+        };
+
+        let steps = horizon.div_ceil(resolution.bucket_seconds()) as usize;
+        Forecast::from_points(&tier.range(0, u64::MAX), resolution, &model, steps)
+    }
+
+    /// Attaches a note to `start..end`; `None` if `end` is not after `start`.
+    pub fn annotate(&self, start: u64, end: u64, text: String, author: Option<String>) -> Option<Annotation> {
+        let mut inner = self.lock();
+        inner.annotations.add(start, end, text, author)
+    }
+
+    pub fn remove_annotation(&self, id: u64) -> Option<Annotation> {
+        let mut inner = self.lock();
+        inner.annotations.remove(id)
+    }
+
+    /// Annotations overlapping `start..end`, ordered by start.
+    pub fn annotations(&self, start: u64, end: u64) -> Vec<Annotation> {
+        let inner = self.lock();
+        inner.annotations.overlapping(start, end)
+    }
+
+    /// Number of readings refused as NaN, infinite or below absolute zero,
+    /// including any found in the log when the store was opened.
+    pub fn rejected_count(&self) -> u64 {
+        self.lock().rejected_readings
+    }
+
+    /// Number of readings dropped by the ingest guard so far.
+    pub fn shed_count(&self) -> u64 {
+        let inner = self.lock();
+        inner.ingest_guard.as_ref().map_or(0, |guard| guard.shed_count())
+    }
+
+    pub fn get_latest(&self) -> Option<TemperatureReading> {
+        let readings = &self.lock().readings;
+        readings.last().copied()
+    }
+
+    pub fn get_all(&self) -> Vec<TemperatureReading> {
+        self.with_readings(|readings| readings.to_vec())
+    }
+
+    /// Runs `f` on the stored readings without copying them.
+    ///
+    /// The store stays locked while `f` runs, so keep the closure short and
+    /// don't call back into the same store from it.
+    pub fn with_readings<R>(&self, f: impl FnOnce(&[TemperatureReading]) -> R) -> R {
+        let inner = self.lock();
+        f(&inner.readings)
+    }
+
+    /// Like [`with_readings`](Self::with_readings), limited to the newest `count` readings.
+    pub fn with_recent_readings<R>(&self, count: usize, f: impl FnOnce(&[TemperatureReading]) -> R) -> R {
+        self.with_readings(|readings| {
+            let start_index = readings.len().saturating_sub(count);
+            f(&readings[start_index..])
+        })
+    }
+
+    /// Visits every stored reading, oldest first.
+    pub fn for_each_reading(&self, mut visitor: impl FnMut(&TemperatureReading)) {
+        self.with_readings(|readings| readings.iter().for_each(&mut visitor));
+    }
+
+    /// Stats of the raw window since the epoch started, kept up to date on
+    /// every insert rather than computed by scanning it. With outlier
+    /// rejection set, the window is scanned to find the outliers.
+    pub fn calculate_stats(&self) -> Option<TemperatureStats> {
+        let inner = self.lock();
+        match &inner.outlier_rejection {
+            Some(rejection) => rejection.stats(&inner.readings[inner.pre_epoch..]),
+            None => inner.window.stats(),
+        }
+    }
+
+    pub fn get_stats(&self) -> TemperatureStats {
+        self.calculate_stats()
+            .unwrap_or(TemperatureStats::new(Temperature::new(0.0), Temperature::new(0.0), PreciseTemperature::default(), 0))
+    }
+
+    /// Starts a new epoch at `started_at`, see [`epoch`]: stats restart
+    /// empty and the rollup buckets in progress are closed.
+    pub fn start_epoch(&self, started_at: u64) -> Epoch {
+        let mut inner = self.lock();
+        inner.epoch = Epoch {
+            id: inner.epoch.id + 1,
+            started_at,
+        };
+        inner.pre_epoch = inner.readings.len();
+        inner.window.clear();
+        inner.minute_rollups.seal();
+        inner.hour_rollups.seal();
+        inner.query_cache.clear();
+        inner.epoch
+    }
+
+    pub fn epoch(&self) -> Epoch {
+        self.lock().epoch
+    }
+
+    pub fn reading_count(&self) -> usize {
+        self.len()
+    }
+
+    pub fn get_recent_readings(&self, count: usize) -> Vec<TemperatureReading> {
+        self.with_recent_readings(count, |readings| readings.to_vec())
+    }
+
+    /// Like [`get_recent_readings`](Self::get_recent_readings), appending to
+    /// `out`, e.g. a batch from a [`ReadingPool`].
+    pub fn recent_readings_into(&self, count: usize, out: &mut Vec<TemperatureReading>) {
+        self.with_recent_readings(count, |readings| out.extend_from_slice(readings));
+    }
+
+    /// Empties the in-memory tiers. The log keeps its readings, but range
+    /// queries no longer page them in.
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+        inner.log_start = None;
+        inner.readings.clear();
+        inner.pre_epoch = 0;
+        inner.window.clear();
+        inner.minute_rollups.clear();
+        inner.hour_rollups.clear();
+        inner.query_cache.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        let readings = &self.lock().readings;
+        readings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Approximate heap and inline bytes held by the store, including rollup tiers.
+    pub fn memory_usage(&self) -> usize {
+        self.storage_info().memory_bytes
+    }
+
+    pub fn storage_info(&self) -> StorageInfo {
+        let inner = self.lock();
+        let memory_bytes = std::mem::size_of::<Self>()
+            + std::mem::size_of::<StoreInner>()
+            + inner.readings.capacity() * std::mem::size_of::<TemperatureReading>()
+            + inner.window.memory_usage()
+            + inner.minute_rollups.memory_usage()
+            + inner.hour_rollups.memory_usage()
+            + inner.query_cache.memory_usage();
+
+        StorageInfo {
+            readings: inner.readings.len(),
+            capacity: self.capacity,
+            minute_rollups: inner.minute_rollups.len(),
+            hour_rollups: inner.hour_rollups.len(),
+            memory_bytes,
+        }
+    }
+
+    pub fn clone_handle(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            capacity: self.capacity,
+        }
+    }
+}
+
+/// Aggregates the logged readings in `start..end` at `resolution`.
+fn page_in(
+    snapshot: &LogSnapshot<TemperatureReading>,
+    start: u64,
+    end: u64,
+    resolution: Resolution,
+) -> io::Result<Vec<RollupPoint>> {
+    let mut readings = Vec::new();
+    snapshot.for_each(|_, reading: TemperatureReading| {
+        if reading.timestamp >= start && reading.timestamp < end {
+            readings.push(reading);
+        }
+        Ok(())
+    })?;
+    Ok(match resolution {
+        Resolution::Raw => readings.iter().map(|r| RollupPoint::from_reading(r, 1)).collect(),
+        Resolution::Minute | Resolution::Hour => aggregate::rollup(&readings, resolution.bucket_seconds()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    use crate::{persist, SheddingPolicy};
+
+    #[test]
+    fn store_basic_operations() {
+        let store = TemperatureStore::new(5);
+
+        assert!(store.is_empty());
+        assert_eq!(store.len(), 0);
+        assert!(store.get_latest().is_none());
+        assert!(store.calculate_stats().is_none());
+
+        let reading = TemperatureReading::new(Temperature::new(20.0));
+        store.add_reading(reading);
+
+        assert_eq!(store.len(), 1);
+        assert!(!store.is_empty());
+
+        let latest = store.get_latest().unwrap();
+        assert_eq!(latest.temperature.celsius, 20.0);
+    }
+
+    #[test]
+    fn store_circular_buffer() {
+        let store = TemperatureStore::new(3);
+
+        // Add more readings than capacity
+        for i in 0..5 {
+            let reading = TemperatureReading::new(Temperature::new(i as f32 * 10.0));
+            store.add_reading(reading);
+        }
+
+        assert_eq!(store.len(), 3);
+
+        let readings = store.get_all();
+        assert_eq!(readings.len(), 3);
+
+        // Should contain temperatures 20.0, 30.0, 40.0 (the last 3)
+        assert_eq!(readings[0].temperature.celsius, 20.0);
+        assert_eq!(readings[1].temperature.celsius, 30.0);
+        assert_eq!(readings[2].temperature.celsius, 40.0);
+    }
+
+    #[test]
+    fn store_statistics() {
+        let store = TemperatureStore::new(10);
+
+        let temps = vec![10.0, 20.0, 30.0, 40.0, 50.0];
+        for temp in temps {
+            let reading = TemperatureReading::new(Temperature::new(temp));
+            store.add_reading(reading);
+        }
+
+        let stats = store.calculate_stats().unwrap();
+        assert_eq!(stats.min.celsius, 10.0);
+        assert_eq!(stats.max.celsius, 50.0);
+        assert_eq!(stats.average.celsius, 30.0);
+        assert_eq!(stats.count, 5);
+    }
+
+    #[test]
+    fn store_thread_safety() {
+        let store = TemperatureStore::new(100);
+        let store1 = store.clone_handle();
+        let store2 = store.clone_handle();
+
+        let handle1 = thread::spawn(move || {
+            for i in 0..50 {
+                let reading = TemperatureReading::new(Temperature::new(i as f32));
+                store1.add_reading(reading);
+            }
+        });
+
+        let handle2 = thread::spawn(move || {
+            for i in 50..100 {
+                let reading = TemperatureReading::new(Temperature::new(i as f32));
+                store2.add_reading(reading);
+            }
+        });
+
+        handle1.join().unwrap();
+        handle2.join().unwrap();
+
+        assert_eq!(store.len(), 100);
+        let stats = store.calculate_stats().unwrap();
+        assert_eq!(stats.count, 100);
+        assert_eq!(stats.min.celsius, 0.0);
+        assert_eq!(stats.max.celsius, 99.0);
+    }
+
+    #[test]
+    fn store_rejects_impossible_temperatures() {
+        let store = TemperatureStore::new(10);
+        for celsius in [20.0, f32::NAN, -300.0, f32::INFINITY, 22.0] {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(celsius), 1000));
+        }
+        let batch = [TemperatureReading::with_timestamp(Temperature::new(f32::NAN), 1001)];
+        assert_eq!(store.add_readings(&batch), 0);
+
+        let stats = store.calculate_stats().unwrap();
+        assert_eq!((stats.min.celsius, stats.max.celsius, stats.average.celsius, stats.count), (20.0, 22.0, 21.0, 2));
+        assert_eq!(store.rejected_count(), 4);
+    }
+
+    #[test]
+    fn store_sheds_load_over_limit() {
+        let store = TemperatureStore::with_ingest_guard(100, IngestGuard::new(3, SheddingPolicy::EveryNth(2)));
+
+        let stored = (0..7)
+            .filter(|i| store.add_reading(TemperatureReading::with_timestamp(Temperature::new(*i as f32), 1000)))
+            .count();
+
+        // 3 under the limit plus every 2nd of the 4 excess readings
+        assert_eq!(stored, 5);
+        assert_eq!(store.len(), 5);
+        assert_eq!(store.shed_count(), 2);
+
+        // The same as one batch
+        let store = TemperatureStore::with_ingest_guard(100, IngestGuard::new(3, SheddingPolicy::EveryNth(2)));
+        let batch: Vec<_> = (0..7).map(|i| TemperatureReading::with_timestamp(Temperature::new(i as f32), 1000)).collect();
+        assert_eq!(store.add_readings(&batch), 5);
+        let mut recent = vec![batch[0]];
+        store.recent_readings_into(2, &mut recent);
+        assert_eq!(recent.iter().map(|r| r.temperature.celsius).collect::<Vec<_>>(), [0.0, 4.0, 6.0]);
+    }
+
+    #[test]
+    fn store_range_query_picks_resolution() {
+        let store = TemperatureStore::new(10);
+
+        // Two days of readings every 30 minutes; the raw buffer only keeps the last 10
+        for i in 0..96u64 {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(i as f32), i * 1800));
+        }
+
+        let recent = store.query_range(95 * 1800, 96 * 1800);
+        assert_eq!(recent.resolution, Resolution::Raw);
+        assert_eq!(recent.points.len(), 1);
+        assert_eq!(recent.points[0].average.celsius, 95.0);
+
+        let day = store.query_range(0, 12 * 3600);
+        assert_eq!(day.resolution, Resolution::Minute);
+        assert_eq!(day.points.len(), 24);
+
+        let all = store.query_range(0, 2 * 86400);
+        assert_eq!(all.resolution, Resolution::Hour);
+        assert_eq!(all.points.len(), 48);
+        assert_eq!(all.points[0].count, 2);
+        assert_eq!(all.points[0].average.celsius, 0.5);
+    }
+
+    #[test]
+    fn range_queries_are_cached_until_a_reading_changes_them() {
+        let store = TemperatureStore::new(10);
+        for i in 0..48u64 {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(20.0), i * 1800));
+        }
+
+        let yesterday = store.query_range_at(0, 12 * 3600, Resolution::Minute);
+        assert_eq!(store.query_range_at(0, 12 * 3600, Resolution::Minute), yesterday);
+        let days = store.daily_rollups(0, 86400, Tz::UTC);
+        assert_eq!(store.daily_rollups(0, 86400, Tz::UTC), days);
+        assert_eq!(store.query_cache_stats(), QueryCacheStats { hits: 2, misses: 2, invalidations: 0, entries: 2 });
+
+        // Later readings leave both alone
+        store.add_reading(TemperatureReading::with_timestamp(Temperature::new(30.0), 86400));
+        assert_eq!(store.query_cache_stats().entries, 2);
+
+        // A late reading in a covered bucket is seen by the next query
+        store.add_reading(TemperatureReading::with_timestamp(Temperature::new(30.0), 3600 + 90));
+        assert_eq!(store.query_cache_stats().entries, 0);
+        let updated = store.query_range_at(0, 12 * 3600, Resolution::Minute);
+        assert_eq!(updated.points.len(), yesterday.points.len() + 1);
+        assert_eq!(store.daily_rollups(0, 86400, Tz::UTC)[0].count, days[0].count + 1);
+
+        store.set_query_cache_capacity(0);
+        store.query_range_at(0, 12 * 3600, Resolution::Minute);
+        assert_eq!(store.query_cache_stats().entries, 0);
+    }
+
+    #[test]
+    fn store_memory_accounting() {
+        let store = TemperatureStore::new(50);
+        let empty = store.storage_info();
+        assert_eq!(empty.readings, 0);
+        assert_eq!(empty.capacity, 50);
+        assert!(empty.memory_bytes >= 50 * std::mem::size_of::<TemperatureReading>());
+
+        for i in 0..10u64 {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(20.0), i * 60));
+        }
+
+        let info = store.storage_info();
+        assert_eq!(info.readings, 10);
+        assert_eq!(info.minute_rollups, 10);
+        assert_eq!(info.hour_rollups, 1);
+        assert!(info.memory_bytes > empty.memory_bytes);
+        assert_eq!(store.memory_usage(), info.memory_bytes);
+    }
+
+    #[test]
+    fn store_borrowed_access() {
+        let store = TemperatureStore::new(10);
+        for i in 0..5 {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(i as f32), i));
+        }
+
+        let total = store.with_readings(|readings| readings.iter().map(|r| r.temperature.celsius).sum::<f32>());
+        assert_eq!(total, 10.0);
+
+        let recent = store.with_recent_readings(2, |readings| TemperatureStats::from_readings(readings).unwrap());
+        assert_eq!(recent.count, 2);
+        assert_eq!(recent.min.celsius, 3.0);
+
+        let mut visited = Vec::new();
+        store.for_each_reading(|r| visited.push(r.timestamp));
+        assert_eq!(visited, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn store_persists_and_reloads() {
+        let path = persist::test_path("store_reload");
+        {
+            let store = TemperatureStore::open(3, &path, FlushPolicy::EveryN(10)).unwrap();
+            for i in 0..5u64 {
+                store.add_reading(TemperatureReading::with_timestamp(Temperature::new(i as f32), i));
+            }
+            assert_eq!(store.pending_writes(), 5);
+            store.flush().unwrap();
+            assert_eq!(store.pending_writes(), 0);
+        }
+
+        let store = TemperatureStore::open(3, &path, FlushPolicy::EveryWrite).unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get_latest().unwrap().temperature.celsius, 4.0);
+        assert_eq!(store.query_range_at(0, 60, Resolution::Minute).points[0].count, 5);
+
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn range_queries_page_in_readings_older_than_memory() {
+        let path = persist::test_path("store_tiering");
+        let minutes = MINUTE_TIER_CAPACITY as u64 + 30;
+        let store = TemperatureStore::open(5, &path, FlushPolicy::EveryN(100)).unwrap();
+        for minute in 0..minutes {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(minute as f32), minute * 60));
+        }
+        assert_eq!(store.storage_info().minute_rollups, MINUTE_TIER_CAPACITY);
+
+        let pending = store.pending_writes();
+        assert!(pending > 0);
+        let raw = store.query_range_at(0, minutes * 60, Resolution::Raw).points;
+        assert_eq!(raw.len(), minutes as usize);
+        // Paged in along with what the log still buffers, without flushing it
+        assert_eq!(store.pending_writes(), pending);
+        assert!(raw.windows(2).all(|w| w[0].start < w[1].start));
+        let by_minute = store.query_range_at(0, 60 * 60, Resolution::Minute).points;
+        assert_eq!(by_minute.len(), 60);
+        assert_eq!(by_minute[0].average.celsius, 0.0);
+
+        // Memory still only holds the hot window
+        assert_eq!(store.len(), 5);
+        assert_eq!(store.calculate_stats().unwrap().min.celsius, (minutes - 5) as f32);
+        drop(store);
+
+        let store = TemperatureStore::open(5, &path, FlushPolicy::EveryWrite).unwrap();
+        assert_eq!(store.query_range_at(0, 300, Resolution::Raw).points.len(), 5);
+        store.clear();
+        assert!(store.query_range_at(0, 300, Resolution::Raw).points.is_empty());
+
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compaction_drops_readings_older_than_every_tier() {
+        let path = persist::test_path("store_compact");
+        let hours = HOUR_TIER_CAPACITY as u64 + 50;
+        let store = TemperatureStore::open(3, &path, FlushPolicy::EveryN(100)).unwrap();
+        for hour in 0..hours {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(20.0), hour * HOUR_SECONDS));
+        }
+        let report = store.compact().unwrap().unwrap();
+        assert_eq!(report.records_dropped, 50);
+        assert_eq!(report.records_kept, HOUR_TIER_CAPACITY);
+        assert!(report.reclaimed_bytes() > 0);
+
+        // Appends go to the compacted log
+        store.add_reading(TemperatureReading::with_timestamp(Temperature::new(25.0), hours * HOUR_SECONDS));
+        let before = store.query_range_at(0, (hours + 1) * HOUR_SECONDS, Resolution::Hour).points;
+        drop(store);
+
+        let store = TemperatureStore::open(3, &path, FlushPolicy::EveryWrite).unwrap();
+        assert_eq!(store.query_range_at(0, (hours + 1) * HOUR_SECONDS, Resolution::Hour).points, before);
+        assert_eq!(store.get_latest().unwrap().temperature.celsius, 25.0);
+        assert_eq!(TemperatureStore::new(3).compact().unwrap(), None);
+
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn backup_covers_the_whole_log_and_restores() {
+        let path = persist::test_path("store_backup");
+        let store = TemperatureStore::open(2, &path, FlushPolicy::EveryN(100)).unwrap();
+        for i in 0..10u64 {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(20.0 + i as f32 / 4.0), i * 60));
+        }
+
+        let mut backup = Vec::new();
+        assert_eq!(store.write_backup(&mut backup, BackupFormat::Compact).unwrap(), 10);
+
+        let restored = TemperatureStore::new(20);
+        assert_eq!(restored.restore_backup(backup.as_slice()).unwrap(), 10);
+        assert_eq!(restored.len(), 10);
+        assert_eq!(restored.get_latest(), store.get_latest());
+
+        // A JSON backup of a memory store is its raw window
+        let mut json = Vec::new();
+        assert_eq!(restored.write_backup(&mut json, BackupFormat::Json).unwrap(), 10);
+        assert_eq!(String::from_utf8(json).unwrap().lines().count(), 10);
+
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn epochs_restart_stats_but_keep_history() {
+        let store = TemperatureStore::new(4);
+        for (i, celsius) in [30.0, 32.0, 34.0].into_iter().enumerate() {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(celsius), i as u64 * 10));
+        }
+        assert_eq!(store.epoch(), Epoch::default());
+
+        let epoch = store.start_epoch(25);
+        assert_eq!(epoch, Epoch { id: 1, started_at: 25 });
+        assert_eq!(store.calculate_stats(), None);
+        assert_eq!(store.len(), 3);
+
+        store.add_reading(TemperatureReading::with_timestamp(Temperature::new(4.0), 30));
+        store.add_reading(TemperatureReading::with_timestamp(Temperature::new(6.0), 40));
+        let stats = store.get_stats();
+        assert_eq!((stats.count, stats.min.celsius, stats.max.celsius), (2, 4.0, 6.0));
+        // One minute bucket per epoch
+        let points = store.query_range_at(0, 60, Resolution::Minute).points;
+        assert_eq!(points.iter().map(|p| p.count).collect::<Vec<_>>(), [3, 2]);
+
+        // Evicting pre-epoch readings leaves the stats alone
+        for timestamp in [50, 60, 70] {
+            store.add_reading(TemperatureReading::with_timestamp(Temperature::new(8.0), timestamp));
+        }
+        assert_eq!(store.get_stats().count, 4);
+        assert_eq!(store.get_stats().min.celsius, 6.0);
+        assert_eq!(store.start_epoch(80).id, 2);
+    }
+
+    #[test]
+    fn survives_poisoned_lock_and_zero_capacity() {
+        let store = TemperatureStore::new(0);
+        let poisoner = store.clone_handle();
+        let result = thread::spawn(move || {
+            let _guard = poisoner.lock();
+            panic!("writer died while holding the lock");
+        })
+        .join();
+        assert!(result.is_err());
+
+        assert!(store.add_reading(TemperatureReading::with_timestamp(Temperature::new(20.0), 1)));
+        assert!(store.add_reading(TemperatureReading::with_timestamp(Temperature::new(21.0), 2)));
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.get_latest().unwrap().timestamp, 2);
+        assert_eq!(store.lock_poisonings(), 1);
+
+        // A reader panicking mid-iteration is recovered from just the same
+        let reader = store.clone_handle();
+        let result = thread::spawn(move || {
+            reader.for_each_reading(|_| panic!("reader died mid-iteration"));
+        })
+        .join();
+        assert!(result.is_err());
+        assert_eq!(store.get_stats().count, 1);
+        assert_eq!(store.lock_poisonings(), 2);
+    }
+
+    #[cfg(feature = "high-precision")]
+    #[test]
+    fn long_run_averages_do_not_drift() {
+        // An f32 sum passes 2^24 after some 800k of these and then drops every fraction added
+        let readings: Vec<_> = (0..2_000_000)
+            .map(|i| TemperatureReading::with_timestamp(Temperature::new(20.1 + (i % 10) as f32 * 0.01), i))
+            .collect();
+        let stats = TemperatureStats::from_readings(&readings).unwrap();
+        assert!((stats.average.celsius - 20.145).abs() < 1e-4, "{}", stats.average.celsius);
+        assert!((stats.precise_average.celsius - 20.145).abs() < 1e-6, "{}", stats.precise_average);
+
+        let mut hours = RollupTier::new(HOUR_SECONDS, 24);
+        readings[..86_400].iter().for_each(|reading| hours.add(reading));
+        let daily = local_time::daily_rollups(&hours.range(0, u64::MAX), local_time::Tz::UTC);
+        assert!((daily[0].average.celsius - 20.145).abs() < 1e-4, "{}", daily[0].average.celsius);
+        assert!((daily[0].precise_average().celsius - 20.145).abs() < 1e-6, "{}", daily[0].precise_average());
+    }
+}
@@ -0,0 +1,27 @@
+//! Locking primitives of the store. Built with `--cfg loom` (and the `loom`
+//! feature for the dependency), loom's versions are swapped in so the
+//! concurrency tests can explore every interleaving.
+
+#[cfg(loom)]
+pub(crate) use loom::sync::{Arc, MutexGuard};
+#[cfg(not(loom))]
+pub(crate) use std::sync::{Arc, Mutex, MutexGuard};
+
+/// loom's mutex with the part of `std`'s API the store uses.
+#[cfg(loom)]
+#[derive(Debug)]
+pub(crate) struct Mutex<T>(loom::sync::Mutex<T>);
+
+#[cfg(loom)]
+impl<T> Mutex<T> {
+    pub(crate) fn new(data: T) -> Self {
+        Self(loom::sync::Mutex::new(data))
+    }
+
+    pub(crate) fn lock(&self) -> std::sync::LockResult<MutexGuard<'_, T>> {
+        self.0.lock()
+    }
+
+    /// loom's locks are never poisoned, so there is nothing to clear.
+    pub(crate) fn clear_poison(&self) {}
+}
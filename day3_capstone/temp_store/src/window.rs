@@ -0,0 +1,108 @@
+//! Running statistics over the raw window.
+//!
+//! Readings leave the window oldest first, so the minimum and maximum are
+//! kept with monotonic deques: each holds the readings that can still become
+//! the extreme once older ones are evicted, which makes every update O(1)
+//! amortized. The sum is kept in `f64`, where adding and subtracting `f32`
+//! temperatures is exact, so it does not drift over millions of readings.
+
+use std::collections::VecDeque;
+
+use temp_core::{PreciseTemperature, Temperature};
+
+use crate::TemperatureStats;
+
+#[derive(Debug, Default)]
+pub(crate) struct WindowStats {
+    /// `(sequence, celsius)`, increasing from front to back.
+    min: VecDeque<(u64, f32)>,
+    /// `(sequence, celsius)`, decreasing from front to back.
+    max: VecDeque<(u64, f32)>,
+    sum: f64,
+    /// Sequence of the next reading pushed and of the oldest one still in the window.
+    next: u64,
+    oldest: u64,
+}
+
+impl WindowStats {
+    pub(crate) fn push(&mut self, celsius: f32) {
+        let sequence = self.next;
+        self.next += 1;
+        self.sum += f64::from(celsius);
+
+        while self.min.back().is_some_and(|&(_, back)| back >= celsius) {
+            self.min.pop_back();
+        }
+        self.min.push_back((sequence, celsius));
+        while self.max.back().is_some_and(|&(_, back)| back <= celsius) {
+            self.max.pop_back();
+        }
+        self.max.push_back((sequence, celsius));
+    }
+
+    /// Takes out the oldest reading, `celsius`, as it leaves the window.
+    pub(crate) fn evict(&mut self, celsius: f32) {
+        let sequence = self.oldest;
+        self.oldest += 1;
+        self.sum -= f64::from(celsius);
+
+        if self.min.front().is_some_and(|&(front, _)| front == sequence) {
+            self.min.pop_front();
+        }
+        if self.max.front().is_some_and(|&(front, _)| front == sequence) {
+            self.max.pop_front();
+        }
+    }
+
+    /// Heap bytes held by the deques.
+    pub(crate) fn memory_usage(&self) -> usize {
+        (self.min.capacity() + self.max.capacity()) * std::mem::size_of::<(u64, f32)>()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(crate) fn stats(&self) -> Option<TemperatureStats> {
+        let count = (self.next - self.oldest) as usize;
+        let (&(_, min), &(_, max)) = (self.min.front()?, self.max.front()?);
+        Some(TemperatureStats::new(
+            Temperature::new(min),
+            Temperature::new(max),
+            PreciseTemperature::new(self.sum / count as f64),
+            count,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TemperatureReading;
+
+    #[test]
+    fn matches_a_full_scan_as_the_window_slides() {
+        let values: Vec<f32> = (0..200).map(|i| ((i * 37) % 101) as f32 / 4.0 - 5.0).collect();
+        let window = 16;
+        let mut stats = WindowStats::default();
+
+        for (i, &celsius) in values.iter().enumerate() {
+            if i >= window {
+                stats.evict(values[i - window]);
+            }
+            stats.push(celsius);
+
+            let readings: Vec<_> = values[i.saturating_sub(window - 1)..=i]
+                .iter()
+                .map(|&c| TemperatureReading::with_timestamp(Temperature::new(c), 0))
+                .collect();
+            let expected = TemperatureStats::from_readings(&readings).unwrap();
+            let actual = stats.stats().unwrap();
+            assert_eq!((actual.min, actual.max, actual.count), (expected.min, expected.max, expected.count));
+            assert!((actual.average.celsius - expected.average.celsius).abs() < 1e-4);
+        }
+
+        stats.clear();
+        assert!(stats.stats().is_none());
+    }
+}